@@ -0,0 +1,371 @@
+//! Client of the chain signatures contract, for integrators that request signatures from their
+//! own services instead of through `near call`.
+//!
+//! ```no_run
+//! # async fn example(signer: near_crypto::InMemorySigner) -> Result<(), chain_signatures_client::Error> {
+//! use chain_signatures_client::ChainSignaturesClient;
+//!
+//! let client = ChainSignaturesClient::new("https://rpc.testnet.near.org", "v1.signer-prod.testnet".parse().unwrap());
+//! let _addresses = client.derived_addresses("ethereum/1", &signer.account_id).await?;
+//! let _signature = client.sign(&signer, [1; 32], "ethereum/1").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use crypto_shared::{
+    derive_epsilon, derive_key, kdf::check_ec_signature, near_public_key_to_affine_point,
+    DerivedAddresses, PublicKey, ScalarExt as _, SignMessage, SignatureResponse,
+};
+use k256::Scalar;
+use mpc_contract::errors::SignError;
+use mpc_contract::primitives::SignRequest;
+use near_account_id::AccountId;
+use near_crypto::InMemorySigner;
+use near_fetch::ops::AsyncTransactionStatus;
+use near_token::NearToken;
+use serde_json::json;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("rpc error: {0}")]
+    Rpc(#[from] near_fetch::Error),
+    #[error("malformed response: {0}")]
+    MalformedResponse(#[from] serde_json::Error),
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("sign request failed: {0}")]
+    SignFailed(String),
+    #[error("sign request timed out before it was signed")]
+    Timeout,
+    #[error("signature does not match the derived key: {0}")]
+    InvalidSignature(String),
+}
+
+/// How failed requests to the RPC node are retried. A sign request that made it on chain is
+/// never submitted again, only the polling of its outcome is retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryOptions {
+    pub retries: usize,
+    /// Delay before the first retry, doubled for every further retry.
+    pub base_delay: Duration,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            base_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+/// How a submitted sign request is waited on.
+#[derive(Debug, Clone, Copy)]
+pub struct PollOptions {
+    pub interval: Duration,
+    /// How long to wait for the signature. Requests that are not signed in time are resolved
+    /// by the contract with a timeout error after about 200 blocks.
+    pub timeout: Duration,
+}
+
+impl Default for PollOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ChainSignaturesClient {
+    rpc_client: near_fetch::Client,
+    contract_id: AccountId,
+    retry: RetryOptions,
+    poll: PollOptions,
+}
+
+impl ChainSignaturesClient {
+    pub fn new(rpc_url: &str, contract_id: AccountId) -> Self {
+        Self::from_client(near_fetch::Client::new(rpc_url), contract_id)
+    }
+
+    pub fn from_client(rpc_client: near_fetch::Client, contract_id: AccountId) -> Self {
+        Self {
+            rpc_client,
+            contract_id,
+            retry: RetryOptions::default(),
+            poll: PollOptions::default(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryOptions) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_poll(mut self, poll: PollOptions) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    pub fn contract_id(&self) -> &AccountId {
+        &self.contract_id
+    }
+
+    /// The root public key of the contract, which all the keys are derived from.
+    pub async fn public_key(&self) -> Result<PublicKey, Error> {
+        let public_key: near_sdk::PublicKey = self.view("public_key", json!({})).await?;
+        Ok(near_public_key_to_affine_point(public_key))
+    }
+
+    /// The key derived for `predecessor` from `path`, which signs the requests `predecessor`
+    /// submits with that path.
+    pub async fn derived_public_key(
+        &self,
+        path: &str,
+        predecessor: &AccountId,
+    ) -> Result<PublicKey, Error> {
+        let public_key: near_sdk::PublicKey = self
+            .view(
+                "derived_public_key",
+                json!({ "path": path, "predecessor": predecessor }),
+            )
+            .await?;
+        Ok(near_public_key_to_affine_point(public_key))
+    }
+
+    /// The keys derived for each pair of path and predecessor, in the same order, in a single
+    /// call. At most [`mpc_contract::MAX_DERIVED_KEYS`] keys can be derived at once.
+    pub async fn derived_public_keys(
+        &self,
+        keys: &[(&str, &AccountId)],
+    ) -> Result<Vec<PublicKey>, Error> {
+        let public_keys: Vec<near_sdk::PublicKey> = self
+            .view("derived_public_keys", json!({ "keys": keys }))
+            .await?;
+        Ok(public_keys
+            .into_iter()
+            .map(near_public_key_to_affine_point)
+            .collect())
+    }
+
+    /// The Bitcoin and Ethereum addresses controlled by the key derived for `predecessor` from
+    /// `path`.
+    pub async fn derived_addresses(
+        &self,
+        path: &str,
+        predecessor: &AccountId,
+    ) -> Result<DerivedAddresses, Error> {
+        self.view(
+            "derived_addresses",
+            json!({ "path": path, "predecessor": predecessor }),
+        )
+        .await
+    }
+
+    pub async fn latest_key_version(&self) -> Result<u32, Error> {
+        self.view("latest_key_version", json!({})).await
+    }
+
+    /// Deposit that a new sign request has to attach right now. It changes with the amount of
+    /// pending requests, so it is only a snapshot.
+    pub async fn signature_deposit(&self) -> Result<NearToken, Error> {
+        let deposit: near_sdk::json_types::U128 = self
+            .view("experimental_signature_deposit", json!({}))
+            .await?;
+        Ok(NearToken::from_yoctonear(deposit.0))
+    }
+
+    /// Signs `payload` with the key derived for `signer` from `path`, using the latest key
+    /// version. Waits for the signature and checks it against the derived key before returning.
+    pub async fn sign(
+        &self,
+        signer: &InMemorySigner,
+        payload: [u8; 32],
+        path: &str,
+    ) -> Result<SignatureResponse, Error> {
+        let key_version = self.latest_key_version().await?;
+        self.sign_request(
+            signer,
+            SignRequest {
+                payload,
+                path: path.to_string(),
+                key_version,
+                message: None,
+                max_wait_blocks: None,
+            },
+        )
+        .await
+    }
+
+    /// Signs the digest of `message`, which the contract hashes with the hash function of the
+    /// message, the same way as [`Self::sign`].
+    pub async fn sign_message(
+        &self,
+        signer: &InMemorySigner,
+        message: SignMessage,
+        path: &str,
+    ) -> Result<SignatureResponse, Error> {
+        let key_version = self.latest_key_version().await?;
+        self.sign_request(
+            signer,
+            SignRequest {
+                payload: [0; 32],
+                path: path.to_string(),
+                key_version,
+                message: Some(message),
+                max_wait_blocks: None,
+            },
+        )
+        .await
+    }
+
+    /// Submits `request` on behalf of `signer` with the currently required deposit, and waits
+    /// for the nodes to sign it.
+    pub async fn sign_request(
+        &self,
+        signer: &InMemorySigner,
+        request: SignRequest,
+    ) -> Result<SignatureResponse, Error> {
+        let payload = match &request.message {
+            Some(message) => message.digest(),
+            None => request.payload,
+        };
+        let payload = Scalar::from_bytes(payload)
+            .ok_or_else(|| Error::InvalidRequest("payload is not a valid scalar".to_string()))?;
+        crypto_shared::derivation_path::validate_path(&request.path)
+            .map_err(|err| Error::InvalidRequest(err.to_string()))?;
+        let expected_key = derive_key(
+            self.public_key().await?,
+            derive_epsilon(&signer.account_id, &request.path),
+        );
+        let deposit = self.signature_deposit().await?;
+
+        let status = self
+            .retry("sign", || async {
+                self.rpc_client
+                    .call(signer, &self.contract_id, "sign")
+                    .args_json(json!({ "request": &request }))
+                    .deposit(deposit)
+                    .max_gas()
+                    .transact_async()
+                    .await
+            })
+            .await?;
+        let signature = self.wait_for_signature(status).await?;
+
+        check_ec_signature(
+            &expected_key,
+            &signature.big_r.affine_point,
+            &signature.s.scalar,
+            payload,
+            signature.recovery_id,
+        )
+        .map_err(|err| Error::InvalidSignature(err.to_string()))?;
+        Ok(signature)
+    }
+
+    /// Polls the transaction of a sign request until the contract resumes it with a signature
+    /// or a failure.
+    async fn wait_for_signature(
+        &self,
+        status: AsyncTransactionStatus,
+    ) -> Result<SignatureResponse, Error> {
+        let started = Instant::now();
+        loop {
+            let outcome = match self.retry("status", || status.status()).await? {
+                Poll::Ready(outcome) => outcome,
+                Poll::Pending if started.elapsed() >= self.poll.timeout => {
+                    return Err(Error::Timeout)
+                }
+                Poll::Pending => {
+                    tokio::time::sleep(self.poll.interval).await;
+                    continue;
+                }
+            };
+            if outcome.is_failure() {
+                let failure = format!("{:?}", outcome.status());
+                if failure.contains(&SignError::Timeout.to_string()) {
+                    return Err(Error::Timeout);
+                }
+                return Err(Error::SignFailed(failure));
+            }
+            return Ok(outcome.json()?);
+        }
+    }
+
+    async fn view<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        args: serde_json::Value,
+    ) -> Result<T, Error> {
+        let result = self
+            .retry(method, || {
+                self.rpc_client
+                    .view(&self.contract_id, method)
+                    .args_json(args.clone())
+            })
+            .await?;
+        Ok(result.json()?)
+    }
+
+    /// Runs `request` until it reaches the RPC node, backing off exponentially in between.
+    async fn retry<T, F, Fut>(&self, method: &str, mut request: F) -> Result<T, near_fetch::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::IntoFuture<Output = Result<T, near_fetch::Error>>,
+    {
+        let mut delay = self.retry.base_delay;
+        let mut attempt = 0;
+        loop {
+            match request().await {
+                Err(err) if attempt < self.retry.retries => {
+                    tracing::debug!(method, attempt, %err, "rpc request failed, retrying");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sign_request_rejects_malformed_path() {
+        // the request is rejected before anything is sent to the unreachable RPC node
+        let client =
+            ChainSignaturesClient::new("http://127.0.0.1:1", "signer.near".parse().unwrap())
+                .with_retry(RetryOptions {
+                    retries: 0,
+                    base_delay: Duration::ZERO,
+                });
+        let signer = InMemorySigner::from_seed(
+            "alice.near".parse().unwrap(),
+            near_crypto::KeyType::ED25519,
+            "alice",
+        );
+        let err = client
+            .sign_request(
+                &signer,
+                SignRequest {
+                    payload: [1; 32],
+                    path: "/ethereum//1".to_string(),
+                    key_version: 0,
+                    message: None,
+                    max_wait_blocks: None,
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidRequest(_)), "{err}");
+    }
+}
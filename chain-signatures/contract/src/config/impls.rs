@@ -1,13 +1,29 @@
 use borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::{AccountId, NearToken};
 
 use super::{
-    Config, DynamicValue, PresignatureConfig, ProtocolConfig, SignatureConfig, TripleConfig,
+    BackoffConfig, BackpressureConfig, Config, DynamicValue, EvictionConfig, FeeConfig,
+    KeyVersionConfig, PayloadFormat, PresignatureConfig, PresignatureExpiryConfig,
+    ProactiveResharingConfig, ProtocolConfig, RequestGcConfig, SignAccessConfig, SignAccessMode,
+    SignLimitsConfig, SignRequestConfig, SignatureCacheConfig, SignatureConfig, TimeoutConfig,
+    TripleConfig,
 };
 
 /// This is maximum expected participants we aim to support right now. This can be different
 /// in the future as we scale the network further.
 const MAX_EXPECTED_PARTICIPANTS: u32 = 32;
 
+/// Amount of blocks after which the protocol resumes a yielded promise with a timeout.
+const YIELD_TIMEOUT_BLOCKS: u64 = 200;
+
+/// Amount of blocks in an epoch of mainnet and testnet.
+const EPOCH_LENGTH_BLOCKS: u64 = 43_200;
+
+/// Most blocks a cached signature is kept for, whatever the config says. The contract pays for
+/// the storage of the cache, so it is only meant to bridge a failed callback, not to archive
+/// signatures.
+const MAX_SIGNATURE_RETENTION_BLOCKS: u64 = 3_600;
+
 /// The network multiplier is used to calculate the maximum amount of protocols in totality
 /// that should be in the network.
 const NETWORK_MULTIPLIER: u32 = 128;
@@ -22,6 +38,294 @@ impl Config {
             }
         }
     }
+
+    /// Pricing of sign requests. Falls back to the default pricing if the `fee` entry is
+    /// missing or can not be parsed.
+    pub fn fee(&self) -> FeeConfig {
+        self.other
+            .get("fee")
+            .and_then(|value| serde_json::from_value(value.0.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Lifetime of sign requests. Falls back to the default lifetime if the `sign_request`
+    /// entry is missing or can not be parsed.
+    pub fn sign_request(&self) -> SignRequestConfig {
+        self.other
+            .get("sign_request")
+            .and_then(|value| serde_json::from_value(value.0.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Limits on what can be signed. Falls back to the default limits if the `sign_limits`
+    /// entry is missing or can not be parsed.
+    pub fn sign_limits(&self) -> SignLimitsConfig {
+        self.other
+            .get("sign_limits")
+            .and_then(|value| serde_json::from_value(value.0.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Cleanup of stale sign requests. Falls back to the default cleanup if the `request_gc`
+    /// entry is missing or can not be parsed.
+    pub fn request_gc(&self) -> RequestGcConfig {
+        self.other
+            .get("request_gc")
+            .and_then(|value| serde_json::from_value(value.0.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Retention of the signatures of completed requests. Falls back to the default retention
+    /// if the `signature_cache` entry is missing or can not be parsed.
+    pub fn signature_cache(&self) -> SignatureCacheConfig {
+        self.other
+            .get("signature_cache")
+            .and_then(|value| serde_json::from_value(value.0.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Proactive resharing of the key shares. Falls back to never resharing proactively if the
+    /// `proactive_resharing` entry is missing or can not be parsed.
+    pub fn proactive_resharing(&self) -> ProactiveResharingConfig {
+        self.other
+            .get("proactive_resharing")
+            .and_then(|value| serde_json::from_value(value.0.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// When sign requests get rejected for a lack of presignatures. Falls back to the default
+    /// if the `backpressure` entry is missing or can not be parsed.
+    pub fn backpressure(&self) -> BackpressureConfig {
+        self.other
+            .get("backpressure")
+            .and_then(|value| serde_json::from_value(value.0.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Timeouts of reaching the peers and the contract. Falls back to the default timeouts if
+    /// the `timeouts` entry is missing or can not be parsed.
+    pub fn timeouts(&self) -> TimeoutConfig {
+        self.other
+            .get("timeouts")
+            .and_then(|value| serde_json::from_value(value.0.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Lifetime of the presignatures kept by the nodes. Falls back to the default lifetime if
+    /// the `presignature_expiry` entry is missing or can not be parsed.
+    pub fn presignature_expiry(&self) -> PresignatureExpiryConfig {
+        self.other
+            .get("presignature_expiry")
+            .and_then(|value| serde_json::from_value(value.0.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Eviction of offline participants. Falls back to never evicting if the `eviction` entry
+    /// is missing or can not be parsed.
+    pub fn eviction(&self) -> EvictionConfig {
+        self.other
+            .get("eviction")
+            .and_then(|value| serde_json::from_value(value.0.clone()).ok())
+            .unwrap_or_default()
+    }
+}
+
+impl BackoffConfig {
+    /// Delay in milliseconds before the retry number `retry`, counting from zero.
+    pub fn delay(&self, retry: u32) -> u64 {
+        self.initial_delay
+            .saturating_mul(2u64.saturating_pow(retry))
+            .min(self.max_delay)
+    }
+}
+
+impl ProactiveResharingConfig {
+    /// Whether key shares that were handed out at `shares_epoch_height` are due for a refresh
+    /// by the epoch `epoch_height`.
+    pub fn is_due(&self, shares_epoch_height: u64, epoch_height: u64) -> bool {
+        self.interval_epochs > 0
+            && epoch_height >= shares_epoch_height.saturating_add(self.interval_epochs)
+    }
+}
+
+impl KeyVersionConfig {
+    /// Whether sign requests for `key_version` are still served at the block `block_height`.
+    pub fn is_served(&self, key_version: u32, block_height: u64) -> bool {
+        key_version <= self.latest
+            && self
+                .deprecated
+                .get(&key_version)
+                .map_or(true, |retired_at| block_height < *retired_at)
+    }
+
+    /// Makes the next version the latest one, and deprecates the current latest version so
+    /// that it stops being served at the block `retired_at`.
+    pub fn rotate(&mut self, retired_at: u64) {
+        self.deprecated.insert(self.latest, retired_at);
+        self.latest += 1;
+    }
+}
+
+impl RequestGcConfig {
+    /// Whether a request submitted at `submitted_at` can be cleaned by the block `block_height`.
+    /// Requests are kept at least until their yield timed out, so that requests that are still
+    /// waiting for a signature never get cleaned.
+    pub fn is_stale(&self, submitted_at: u64, block_height: u64) -> bool {
+        let retention_blocks = self
+            .retention_epochs
+            .saturating_mul(EPOCH_LENGTH_BLOCKS)
+            .max(2 * YIELD_TIMEOUT_BLOCKS);
+        block_height >= submitted_at.saturating_add(retention_blocks)
+    }
+
+    /// Bounty for cleaning a request that holds `deposit`. It is paid out of that deposit, so
+    /// it never exceeds it and cleaning can not drain the funds of the contract.
+    pub fn bounty_for(&self, deposit: u128) -> u128 {
+        self.bounty.0.min(deposit)
+    }
+}
+
+impl Default for RequestGcConfig {
+    fn default() -> Self {
+        Self {
+            retention_epochs: 2,
+            bounty: NearToken::from_millinear(1).as_yoctonear().into(),
+        }
+    }
+}
+
+impl SignatureCacheConfig {
+    /// Whether the signature of a request signed at `signed_at` is still kept at `block_height`.
+    /// The retention is capped at [`MAX_SIGNATURE_RETENTION_BLOCKS`].
+    pub fn is_retained(&self, signed_at: u64, block_height: u64) -> bool {
+        let retention_blocks = self.retention_blocks.min(MAX_SIGNATURE_RETENTION_BLOCKS);
+        block_height < signed_at.saturating_add(retention_blocks)
+    }
+}
+
+impl Default for SignatureCacheConfig {
+    fn default() -> Self {
+        Self {
+            retention_blocks: 600,
+        }
+    }
+}
+
+impl SignAccessConfig {
+    /// Whether `account_id` may submit sign requests.
+    pub fn is_allowed(&self, account_id: &AccountId) -> bool {
+        match self.mode {
+            SignAccessMode::Open => true,
+            SignAccessMode::Allowlist => self.accounts.contains(account_id),
+            SignAccessMode::Denylist => !self.accounts.contains(account_id),
+        }
+    }
+}
+
+impl SignLimitsConfig {
+    /// Whether payloads of `format` may be signed.
+    pub fn allows(&self, format: PayloadFormat) -> bool {
+        self.payload_formats.contains(&format)
+    }
+}
+
+impl Default for SignLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_path_len: super::default_max_path_len(),
+            payload_formats: super::default_payload_formats(),
+            max_message_len: super::default_max_message_len(),
+        }
+    }
+}
+
+impl SignRequestConfig {
+    /// Whether a request submitted at `submitted_at` has expired by the block `block_height`.
+    pub fn is_expired(&self, submitted_at: u64, block_height: u64) -> bool {
+        block_height >= submitted_at.saturating_add(self.ttl_blocks)
+    }
+}
+
+impl Default for SignRequestConfig {
+    fn default() -> Self {
+        Self {
+            ttl_blocks: YIELD_TIMEOUT_BLOCKS,
+            ordering: Default::default(),
+            max_concurrent_signatures: super::default_max_concurrent_signatures(),
+        }
+    }
+}
+
+impl FeeConfig {
+    /// Deposit in yoctoNEAR required for a new sign request while `pending_requests` are
+    /// waiting for a signature.
+    pub fn signature_deposit(&self, pending_requests: u32) -> u128 {
+        if pending_requests <= self.free_pending_requests {
+            return self.base_deposit.0;
+        }
+        let congestion = (pending_requests - self.free_pending_requests) as u128;
+        congestion.saturating_mul(self.deposit_per_pending_request.0)
+    }
+}
+
+impl BackpressureConfig {
+    /// Whether a capacity reported at `reported_at` is still taken into account at
+    /// `block_height`.
+    pub fn is_fresh(&self, reported_at: u64, block_height: u64) -> bool {
+        block_height.saturating_sub(reported_at) < self.stale_after_blocks
+    }
+}
+
+impl EvictionConfig {
+    /// Whether a participant whose last heartbeat is `blocks_since_last_seen` old gets evicted.
+    pub fn is_stale(&self, blocks_since_last_seen: u64) -> bool {
+        self.stale_after_blocks > 0 && blocks_since_last_seen >= self.stale_after_blocks
+    }
+}
+
+impl PresignatureExpiryConfig {
+    /// Unix timestamp in seconds before which presignatures have expired at `now`, or `None`
+    /// if presignatures never expire.
+    pub fn created_before(&self, now: u64) -> Option<u64> {
+        (self.max_age > 0).then(|| now.saturating_sub(self.max_age / 1000))
+    }
+}
+
+impl Default for PresignatureExpiryConfig {
+    fn default() -> Self {
+        Self {
+            max_age: hours_to_ms(24),
+        }
+    }
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            message_retry: super::default_message_retry(),
+            signature_publish_retry: super::default_signature_publish_retry(),
+        }
+    }
+}
+
+impl Default for BackpressureConfig {
+    fn default() -> Self {
+        Self {
+            stale_after_blocks: 100,
+            retry_after_blocks: 10,
+        }
+    }
+}
+
+impl Default for FeeConfig {
+    fn default() -> Self {
+        Self {
+            base_deposit: 1.into(),
+            free_pending_requests: 3,
+            deposit_per_pending_request: NearToken::from_millinear(50).as_yoctonear().into(),
+            duplicate_fee: super::default_duplicate_fee(),
+        }
+    }
 }
 
 impl Default for ProtocolConfig {
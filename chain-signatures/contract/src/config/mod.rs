@@ -2,10 +2,12 @@ mod impls;
 
 pub use impls::{min_to_ms, secs_to_ms};
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::AccountId;
 
 /// Dynamic value is used to store any kind of value in the contract state. These values
 /// can be deserialized on the fly to get the actual configurations, but the contract will
@@ -95,9 +97,271 @@ pub struct SignatureConfig {
     pub other: HashMap<String, DynamicValue>,
 }
 
+/// Pricing of sign requests, stored under the `fee` entry of [`Config`]. The required deposit
+/// stays at `base_deposit` while the queue is short and then grows linearly with every pending
+/// request, so that flooding the queue gets more expensive the more congested it is.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FeeConfig {
+    /// Deposit in yoctoNEAR required while there are at most `free_pending_requests` pending.
+    pub base_deposit: U128,
+    /// Amount of pending requests that do not make new requests more expensive.
+    pub free_pending_requests: u32,
+    /// Deposit in yoctoNEAR added for every pending request above `free_pending_requests`.
+    pub deposit_per_pending_request: U128,
+    /// Deposit in yoctoNEAR kept from a request that duplicates a pending one, which shares
+    /// the signature of the pending request. The rest of its deposit is refunded.
+    #[serde(default = "default_duplicate_fee")]
+    pub duplicate_fee: U128,
+}
+
+fn default_duplicate_fee() -> U128 {
+    1.into()
+}
+
+/// Lifetime of sign requests, stored under the `sign_request` entry of [`Config`].
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SignRequestConfig {
+    /// Amount of blocks after which a request that has not been signed yet expires. Expired
+    /// requests resolve with a timeout error and get their deposit refunded. Requests always
+    /// time out after the yield timeout of the protocol, even if this is set higher.
+    pub ttl_blocks: u64,
+    /// Order in which the nodes work through the sign requests they propose.
+    #[serde(default)]
+    pub ordering: SignRequestOrdering,
+    /// Most signatures a node generates at the same time for the requests it proposes. Once
+    /// this many are in flight, the remaining requests wait for a slot to free up, taking turns
+    /// between the accounts that submitted them.
+    #[serde(default = "default_max_concurrent_signatures")]
+    pub max_concurrent_signatures: u32,
+}
+
+fn default_max_concurrent_signatures() -> u32 {
+    16
+}
+
+/// Order in which a node works through the sign requests it proposes.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignRequestOrdering {
+    /// Oldest request first.
+    #[default]
+    Fifo,
+    /// Request with the highest attached deposit first, so that paying more gets a request
+    /// signed sooner when the network is congested. Requests with the same deposit are FIFO.
+    Deposit,
+}
+
+/// Limits on what can be signed, stored under the `sign_limits` entry of [`Config`], so that
+/// they can be changed through a config update instead of a redeploy of the contract.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SignLimitsConfig {
+    /// Longest derivation path in bytes.
+    #[serde(default = "default_max_path_len")]
+    pub max_path_len: u32,
+    /// Formats of the payloads that may be signed.
+    #[serde(default = "default_payload_formats")]
+    pub payload_formats: BTreeSet<PayloadFormat>,
+    /// Longest message in bytes that the contract hashes into the payload of a request.
+    #[serde(default = "default_max_message_len")]
+    pub max_message_len: u32,
+}
+
+fn default_max_path_len() -> u32 {
+    crypto_shared::derivation_path::MAX_PATH_LEN as u32
+}
+
+fn default_payload_formats() -> BTreeSet<PayloadFormat> {
+    [
+        PayloadFormat::Raw,
+        PayloadFormat::Eip712,
+        PayloadFormat::Message,
+    ]
+    .into()
+}
+
+fn default_max_message_len() -> u32 {
+    4096
+}
+
+/// Format of the payload of a sign request, which depends on the method it was submitted with.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadFormat {
+    /// A hash submitted as is through `sign` or `sign_batch`.
+    Raw,
+    /// The digest of EIP-712 typed data submitted through `sign_typed_data`.
+    Eip712,
+    /// A full message submitted through `sign` or `sign_batch`, hashed by the contract.
+    Message,
+}
+
+/// Cleanup of sign requests that never got cleared from the contract state, stored under the
+/// `request_gc` entry of [`Config`]. Requests normally get removed once they resolve, but a
+/// request whose callback never ran, e.g. because it ran out of gas, would otherwise stay around
+/// forever and keep counting towards the limit of pending requests.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RequestGcConfig {
+    /// Amount of epochs after which a request that is still in the contract state gets cleaned.
+    pub retention_epochs: u64,
+    /// Amount in yoctoNEAR paid to the caller of `clean_requests` for every request it cleans,
+    /// out of the deposit of that request and at most all of it.
+    pub bounty: U128,
+}
+
+/// Retention of the signatures of completed requests for the `get_signature` view, stored under
+/// the `signature_cache` entry of [`Config`]. Lets requesters whose callback failed, e.g. because
+/// it ran out of gas, fetch their signature instead of paying for the request again.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SignatureCacheConfig {
+    /// Amount of blocks a signature is kept for after its request got signed, at most 3600.
+    pub retention_blocks: u64,
+}
+
+/// Who may submit sign requests. Lets the protocol run in a permissioned mode, where only
+/// approved accounts can request signatures. Kept in the contract state next to [`Config`]
+/// rather than in it, so that replacing the config through `update_config` leaves it as is.
+#[derive(
+    Clone, Debug, Default, Serialize, Deserialize, BorshSerialize, BorshDeserialize, PartialEq, Eq,
+)]
+pub struct SignAccessConfig {
+    pub mode: SignAccessMode,
+    /// Accounts the `mode` applies to. Ignored while the mode is [`SignAccessMode::Open`].
+    #[serde(default)]
+    pub accounts: BTreeSet<AccountId>,
+}
+
+/// How the accounts of [`SignAccessConfig`] are checked against the caller of `sign`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Serialize,
+    Deserialize,
+    BorshSerialize,
+    BorshDeserialize,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SignAccessMode {
+    /// Any account may submit sign requests.
+    #[default]
+    Open,
+    /// Only the listed accounts may submit sign requests.
+    Allowlist,
+    /// All accounts except the listed ones may submit sign requests.
+    Denylist,
+}
+
+/// Versions of the root key that sign requests can use. Older versions keep being served after
+/// a rotation until their deprecation window ends, so that callers have time to move over to the
+/// latest version. Kept in the contract state next to [`Config`], like [`SignAccessConfig`].
+#[derive(
+    Clone, Debug, Default, Serialize, Deserialize, BorshSerialize, BorshDeserialize, PartialEq, Eq,
+)]
+pub struct KeyVersionConfig {
+    /// Version of the root key that new callers should use.
+    pub latest: u32,
+    /// Deprecated versions along with the block at which they stop being served.
+    #[serde(default)]
+    pub deprecated: BTreeMap<u32, u64>,
+}
+
+/// Proactive resharing of the key shares, stored under the `proactive_resharing` entry of
+/// [`Config`]. The participants reshare the same key among themselves every `interval_epochs`,
+/// so that a leaked share becomes useless once the shares got refreshed.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProactiveResharingConfig {
+    /// Amount of NEAR epochs after which the key shares get refreshed. `0` turns proactive
+    /// resharing off.
+    pub interval_epochs: u64,
+}
+
+/// Rejection of sign requests while the network is out of presignatures, stored under the
+/// `backpressure` entry of [`Config`]. The nodes report how many presignatures they have through
+/// `report_capacity`, and `sign` fails right away instead of accepting requests that would time
+/// out before a presignature frees up for them.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BackpressureConfig {
+    /// Amount of blocks after which the capacity reported by a node is no longer taken into
+    /// account. `0` turns backpressure off.
+    pub stale_after_blocks: u64,
+    /// Amount of blocks that callers are asked to wait before retrying a rejected request.
+    pub retry_after_blocks: u64,
+}
+
+/// Timeouts of the nodes that are not part of [`ProtocolConfig`], stored under the `timeouts`
+/// entry of [`Config`]. The generation timeouts of the protocols stay in [`ProtocolConfig`],
+/// while this covers how long the nodes keep retrying to reach their peers and the contract.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TimeoutConfig {
+    /// Retries of delivering a batch of protocol messages to a peer. Messages that could still
+    /// not be delivered are queued again, until the timeout of their protocol.
+    #[serde(default = "default_message_retry")]
+    pub message_retry: BackoffConfig,
+    /// Retries of publishing a signature to the contract, e.g. while the RPC is down.
+    #[serde(default = "default_signature_publish_retry")]
+    pub signature_publish_retry: BackoffConfig,
+}
+
+/// Exponential backoff between the attempts of an operation.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BackoffConfig {
+    /// Delay in milliseconds before the first retry. It doubles with every further retry.
+    pub initial_delay: u64,
+    /// Longest delay in milliseconds between two attempts.
+    pub max_delay: u64,
+    /// Amount of retries after the first attempt failed.
+    pub max_retries: u32,
+}
+
+fn default_message_retry() -> BackoffConfig {
+    BackoffConfig {
+        initial_delay: 10,
+        max_delay: 1000,
+        max_retries: 3,
+    }
+}
+
+fn default_signature_publish_retry() -> BackoffConfig {
+    BackoffConfig {
+        initial_delay: secs_to_ms(2),
+        max_delay: secs_to_ms(60),
+        max_retries: 9,
+    }
+}
+
+/// Lifetime of the presignatures kept by the nodes, stored under the `presignature_expiry` entry
+/// of [`Config`]. A presignature that sits unused for long stays exposed to whoever compromises
+/// the node later on, so the nodes discard the ones older than `max_age` and generate new ones
+/// in their place.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PresignatureExpiryConfig {
+    /// Age in milliseconds after which a presignature that was not used yet gets discarded.
+    /// `0` keeps presignatures until they are used.
+    pub max_age: u64,
+}
+
+/// Eviction of participants that went offline, stored under the `eviction` entry of [`Config`].
+/// The nodes vote to kick a participant out of the running protocol once its last heartbeat on
+/// the contract, see `participant_heartbeats`, is `stale_after_blocks` old. Going by the contract
+/// rather than by what each node reaches on its own lets all nodes agree on who is offline.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EvictionConfig {
+    /// Amount of blocks without a heartbeat after which a participant gets voted out. Nodes ping
+    /// every 5 minutes, so this has to be well above that. `0` turns eviction off.
+    pub stale_after_blocks: u64,
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::config::Config;
+    use crate::config::{
+        BackpressureConfig, Config, EvictionConfig, FeeConfig, KeyVersionConfig, PayloadFormat,
+        PresignatureExpiryConfig, ProactiveResharingConfig, RequestGcConfig, SignAccessConfig,
+        SignAccessMode, SignLimitsConfig, SignRequestConfig, SignRequestOrdering,
+        SignatureCacheConfig, TimeoutConfig,
+    };
 
     #[test]
     fn test_load_config() {
@@ -133,5 +397,259 @@ mod tests {
         assert_eq!(config.protocol.message_timeout, 10000);
         assert_eq!(config.get("integer").unwrap(), serde_json::json!(20));
         assert_eq!(config.get("string").unwrap(), serde_json::json!("value2"));
+        assert_eq!(config.fee(), FeeConfig::default());
+        assert_eq!(config.sign_request(), SignRequestConfig::default());
+        assert_eq!(config.request_gc(), RequestGcConfig::default());
+        assert_eq!(config.signature_cache(), SignatureCacheConfig::default());
+        assert_eq!(config.sign_limits(), SignLimitsConfig::default());
+        assert_eq!(
+            config.proactive_resharing(),
+            ProactiveResharingConfig::default()
+        );
+        assert_eq!(config.backpressure(), BackpressureConfig::default());
+        assert_eq!(config.timeouts(), TimeoutConfig::default());
+        assert_eq!(
+            config.presignature_expiry(),
+            PresignatureExpiryConfig::default()
+        );
+    }
+
+    #[test]
+    fn test_eviction_config() {
+        let mut config = Config::default();
+        assert_eq!(config.eviction(), EvictionConfig::default());
+        assert!(!config.eviction().is_stale(u64::MAX));
+
+        config.other.insert(
+            "eviction".to_string(),
+            serde_json::json!({ "stale_after_blocks": 100 }).into(),
+        );
+        assert!(!config.eviction().is_stale(99));
+        assert!(config.eviction().is_stale(100));
+    }
+
+    #[test]
+    fn test_presignature_expiry_config() {
+        let mut config = Config::default();
+        assert_eq!(
+            config.presignature_expiry().created_before(100_000),
+            Some(100_000 - 24 * 60 * 60)
+        );
+
+        config.other.insert(
+            "presignature_expiry".to_string(),
+            serde_json::json!({ "max_age": 60_000 }).into(),
+        );
+        assert_eq!(
+            config.presignature_expiry().created_before(1_000),
+            Some(940)
+        );
+        assert_eq!(config.presignature_expiry().created_before(10), Some(0));
+
+        config.other.insert(
+            "presignature_expiry".to_string(),
+            serde_json::json!({ "max_age": 0 }).into(),
+        );
+        assert_eq!(config.presignature_expiry().created_before(1_000), None);
+    }
+
+    #[test]
+    fn test_timeout_config() {
+        let mut config = Config::default();
+        config.other.insert(
+            "timeouts".to_string(),
+            serde_json::json!({
+                "message_retry": { "initial_delay": 100, "max_delay": 500, "max_retries": 5 },
+            })
+            .into(),
+        );
+        let timeouts = config.timeouts();
+        let delays = (0..timeouts.message_retry.max_retries)
+            .map(|retry| timeouts.message_retry.delay(retry))
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [100, 200, 400, 500, 500]);
+        // Entries that leave out a backoff keep its default.
+        assert_eq!(
+            timeouts.signature_publish_retry,
+            TimeoutConfig::default().signature_publish_retry
+        );
+        assert_eq!(timeouts.signature_publish_retry.delay(0), 2000);
+        assert_eq!(timeouts.signature_publish_retry.delay(100), 60_000);
+    }
+
+    #[test]
+    fn test_sign_request_config() {
+        let mut config = Config::default();
+        config.other.insert(
+            "sign_request".to_string(),
+            serde_json::json!({ "ttl_blocks": 20 }).into(),
+        );
+        assert_eq!(config.sign_request().ttl_blocks, 20);
+        assert_eq!(config.sign_request().ordering, SignRequestOrdering::Fifo);
+        assert!(!config.sign_request().is_expired(100, 119));
+        assert!(config.sign_request().is_expired(100, 120));
+
+        config.other.insert(
+            "sign_request".to_string(),
+            serde_json::json!({ "ttl_blocks": 20, "ordering": "deposit" }).into(),
+        );
+        assert_eq!(config.sign_request().ordering, SignRequestOrdering::Deposit);
+        assert_eq!(config.sign_request().max_concurrent_signatures, 16);
+
+        config.other.insert(
+            "sign_request".to_string(),
+            serde_json::json!({ "ttl_blocks": 20, "max_concurrent_signatures": 4 }).into(),
+        );
+        assert_eq!(config.sign_request().max_concurrent_signatures, 4);
+    }
+
+    #[test]
+    fn test_sign_limits_config() {
+        let mut config = Config::default();
+        assert_eq!(config.sign_limits().max_path_len, 256);
+        assert!(config.sign_limits().allows(PayloadFormat::Raw));
+        assert!(config.sign_limits().allows(PayloadFormat::Eip712));
+        assert!(config.sign_limits().allows(PayloadFormat::Message));
+        assert_eq!(config.sign_limits().max_message_len, 4096);
+
+        config.other.insert(
+            "sign_limits".to_string(),
+            serde_json::json!({ "max_path_len": 64, "payload_formats": ["eip712"] }).into(),
+        );
+        assert_eq!(config.sign_limits().max_path_len, 64);
+        assert!(!config.sign_limits().allows(PayloadFormat::Raw));
+        assert!(config.sign_limits().allows(PayloadFormat::Eip712));
+        assert!(!config.sign_limits().allows(PayloadFormat::Message));
+
+        config.other.insert(
+            "sign_limits".to_string(),
+            serde_json::json!({ "max_path_len": 512 }).into(),
+        );
+        assert_eq!(config.sign_limits().max_path_len, 512);
+        assert!(config.sign_limits().allows(PayloadFormat::Raw));
+    }
+
+    #[test]
+    fn test_fee_config() {
+        let mut config = Config::default();
+        config.other.insert(
+            "fee".to_string(),
+            serde_json::json!({
+                "base_deposit": "10",
+                "free_pending_requests": 1,
+                "deposit_per_pending_request": "100",
+            })
+            .into(),
+        );
+
+        let fee = config.fee();
+        assert_eq!(fee.signature_deposit(0), 10);
+        assert_eq!(fee.signature_deposit(1), 10);
+        assert_eq!(fee.signature_deposit(2), 100);
+        assert_eq!(fee.signature_deposit(5), 400);
+        // Entries written before duplicates shared signatures get the default duplicate fee.
+        assert_eq!(fee.duplicate_fee, FeeConfig::default().duplicate_fee);
+
+        // A malformed entry falls back to the default pricing instead of bricking `sign`.
+        config
+            .other
+            .insert("fee".to_string(), serde_json::json!("cheap").into());
+        assert_eq!(config.fee(), FeeConfig::default());
+    }
+
+    #[test]
+    fn test_sign_access_config() {
+        let alice = "alice.near".parse().unwrap();
+        let bob = "bob.near".parse().unwrap();
+
+        let mut sign_access = SignAccessConfig::default();
+        assert_eq!(sign_access.mode, SignAccessMode::Open);
+        assert!(sign_access.is_allowed(&alice));
+
+        sign_access.mode = SignAccessMode::Allowlist;
+        sign_access.accounts.insert(alice.clone());
+        assert!(sign_access.is_allowed(&alice));
+        assert!(!sign_access.is_allowed(&bob));
+
+        sign_access.mode = SignAccessMode::Denylist;
+        assert!(!sign_access.is_allowed(&alice));
+        assert!(sign_access.is_allowed(&bob));
+    }
+
+    #[test]
+    fn test_request_gc_config() {
+        let mut config = Config::default();
+        config.other.insert(
+            "request_gc".to_string(),
+            serde_json::json!({ "retention_epochs": 3, "bounty": "10" }).into(),
+        );
+        let request_gc = config.request_gc();
+        assert_eq!(request_gc.bounty.0, 10);
+        assert!(!request_gc.is_stale(100, 100 + 3 * 43_200 - 1));
+        assert!(request_gc.is_stale(100, 100 + 3 * 43_200));
+
+        // requests are never cleaned before their yield timed out
+        config.other.insert(
+            "request_gc".to_string(),
+            serde_json::json!({ "retention_epochs": 0, "bounty": "10" }).into(),
+        );
+        assert!(!config.request_gc().is_stale(100, 101));
+
+        // the bounty is capped at the deposit of the cleaned request
+        assert_eq!(config.request_gc().bounty_for(100), 10);
+        assert_eq!(config.request_gc().bounty_for(3), 3);
+        assert_eq!(config.request_gc().bounty_for(0), 0);
+    }
+
+    #[test]
+    fn test_signature_cache_config() {
+        let mut config = Config::default();
+        assert!(config.signature_cache().is_retained(100, 100 + 599));
+        assert!(!config.signature_cache().is_retained(100, 100 + 600));
+
+        config.other.insert(
+            "signature_cache".to_string(),
+            serde_json::json!({ "retention_blocks": 10 }).into(),
+        );
+        let signature_cache = config.signature_cache();
+        assert!(signature_cache.is_retained(100, 109));
+        assert!(!signature_cache.is_retained(100, 110));
+
+        // the retention is capped, whatever the config says
+        config.other.insert(
+            "signature_cache".to_string(),
+            serde_json::json!({ "retention_blocks": 43_200 }).into(),
+        );
+        let signature_cache = config.signature_cache();
+        assert!(signature_cache.is_retained(100, 100 + 3_599));
+        assert!(!signature_cache.is_retained(100, 100 + 3_600));
+    }
+
+    #[test]
+    fn test_key_version_config() {
+        let mut key_versions = KeyVersionConfig::default();
+        assert!(key_versions.is_served(0, 100));
+        assert!(!key_versions.is_served(1, 100));
+
+        key_versions.rotate(150);
+        assert_eq(key_versions.latest, 1);
+        assert_eq!(key_versions.deprecated.get(&0), Some(&150));
+        assert!(key_versions.is_served(0, 149));
+        assert!(!key_versions.is_served(0, 150));
+        assert!(key_versions.is_served(1, 150));
+        assert!(!key_versions.is_served(2, 150));
+    }
+
+    #[test]
+    fn test_proactive_resharing_config() {
+        let mut config = Config::default();
+        assert!(!config.proactive_resharing().is_due(0, 1_000));
+
+        config.other.insert(
+            "proactive_resharing".to_string(),
+            serde_json::json!({ "interval_epochs": 7 }).into(),
+        );
+        assert!(!config.proactive_resharing().is_due(10, 16));
+        assert!(config.proactive_resharing().is_due(10, 17));
     }
 }
@@ -54,6 +54,15 @@ impl From<SignError> for Error {
     }
 }
 
+impl SignError {
+    pub(crate) fn message<T>(self, msg: T) -> Error
+    where
+        T: Into<Cow<'static, str>>,
+    {
+        Error::message(ErrorKind::Sign(self), msg)
+    }
+}
+
 impl From<RespondError> for Error {
     fn from(code: RespondError) -> Self {
         Self::simple(ErrorKind::Respond(code))
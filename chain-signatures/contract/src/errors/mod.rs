@@ -13,12 +13,32 @@ pub enum SignError {
     UnsupportedKeyVersion,
     #[error("Too many pending requests. Please try again later.")]
     RequestLimitExceeded,
+    #[error("This account is not allowed to submit sign requests. Call sign_access() to get the access list.")]
+    CallerNotAllowed,
+    #[error(
+        "This key version is deprecated and no longer served. Call latest_key_version() to get the latest supported version."
+    )]
+    KeyVersionRetired,
+    #[error(
+        "This account is pinned to a different key version. Call pinned_key_version() to get the pinned version."
+    )]
+    KeyVersionMismatch,
+    #[error("The network is out of presignatures. Please try again later.")]
+    Overloaded,
+    #[error(
+        "Sign requests are paused by the participants. Call sign_pause() to get the pause status."
+    )]
+    Paused,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum RespondError {
     #[error("The provided signature is invalid.")]
     InvalidSignature,
+    #[error("The provided participants are not all participants of the protocol.")]
+    UnknownParticipant,
+    #[error("Fewer participants than the threshold were provided.")]
+    TooFewParticipants,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, thiserror::Error)]
@@ -49,6 +69,20 @@ pub enum VoteError {
     JoinNotCandidate,
     #[error("Number of participants cannot go below threshold.")]
     ParticipantsBelowThreshold,
+    #[error("Account in the new participant set is neither a participant nor a candidate.")]
+    NewParticipantNotCandidate,
+    #[error("New participant set is the same as the current one.")]
+    ParticipantSetUnchanged,
+    #[error("New participant set must keep at least threshold of the current participants.")]
+    TooFewRemainingParticipants,
+    #[error("Threshold must be at least 2 and at most the number of participants.")]
+    InvalidThreshold,
+    #[error("New threshold is the same as the current one.")]
+    ThresholdUnchanged,
+    #[error("Sign requests are already paused.")]
+    AlreadyPaused,
+    #[error("Sign requests are not paused.")]
+    NotPaused,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, thiserror::Error)]
@@ -63,6 +97,16 @@ pub enum InvalidParameters {
     RequestNotFound,
     #[error("Update not found.")]
     UpdateNotFound,
+    #[error("Batch is empty or contains more requests than allowed.")]
+    InvalidBatchSize,
+    #[error("Domain separator does not match the domain.")]
+    InvalidDomainSeparator,
+    #[error("Derivation path is malformed.")]
+    MalformedPath,
+    #[error("Payload format is not allowed.")]
+    PayloadFormatNotAllowed,
+    #[error("Maximum wait of the request is zero or above the request TTL of the contract.")]
+    InvalidMaxWaitBlocks,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, thiserror::Error)]
@@ -0,0 +1,193 @@
+//! Events of the contract in the [NEP-297](https://nomicon.io/Standards/EventsFormat) format.
+//! They are logged as `EVENT_JSON:{"standard":"chain-signatures","version":"1.0.0",...}` so
+//! that indexers and explorers can follow the protocol without parsing the other logs.
+
+use crypto_shared::SignatureResponse;
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, AccountId, CryptoHash};
+
+use crate::config::SignAccessMode;
+use crate::primitives::SignatureRequest;
+use crate::state::ResharingContractState;
+
+pub const EVENT_STANDARD: &str = "chain-signatures";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum Event {
+    /// Requests accepted by `sign` or `sign_batch`.
+    SignatureRequested(Vec<SignatureRequested>),
+    /// Requests that got their signature.
+    SignatureCompleted(Vec<SignatureCompleted>),
+    /// Requests that expired or ran into the yield timeout without a signature.
+    SignatureTimedOut(Vec<SignatureTimedOut>),
+    /// The participants or the threshold changed and the nodes started resharing their keys.
+    ResharingStarted(Vec<ResharingStarted>),
+    /// The accounts allowed to submit sign requests changed.
+    SignAccessUpdated(Vec<SignAccessUpdated>),
+    /// Stale requests removed from the contract state by `clean_requests`.
+    RequestCleaned(Vec<RequestCleaned>),
+    /// A new version of the root key became the latest one.
+    KeyVersionRotated(Vec<KeyVersionRotated>),
+    /// An account pinned its sign requests to a key version, or removed its pin.
+    KeyVersionPinned(Vec<KeyVersionPinned>),
+    /// A node claimed its share of the treasury.
+    RewardsClaimed(Vec<RewardsClaimed>),
+    /// A supermajority of the participants paused new sign requests, or resumed them.
+    SignPauseUpdated(Vec<SignPauseUpdated>),
+}
+
+#[derive(Serialize, Debug)]
+pub struct SignatureRequested {
+    /// Id of the request, which its signature can be fetched by through `get_signature`.
+    pub request_id: CryptoHash,
+    pub request: SignatureRequest,
+    pub requester: AccountId,
+    pub path: String,
+    pub key_version: u32,
+    /// Deposit in yoctoNEAR locked in for the request.
+    pub deposit: U128,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SignatureCompleted {
+    pub request: SignatureRequest,
+    pub requester: AccountId,
+    pub signature: SignatureResponse,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SignatureTimedOut {
+    pub request: SignatureRequest,
+    pub requester: AccountId,
+    /// Block at which the request expired, if it expired through the `ttl_blocks` of the
+    /// config rather than the yield timeout of the protocol.
+    pub expired_at_block: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResharingStarted {
+    pub old_epoch: u64,
+    pub old_participants: Vec<AccountId>,
+    pub new_participants: Vec<AccountId>,
+    pub threshold: usize,
+    /// Threshold of the old participants, when the resharing also changes the threshold.
+    pub old_threshold: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SignAccessUpdated {
+    pub mode: SignAccessMode,
+    /// Accounts added to the access list.
+    pub added: Vec<AccountId>,
+    /// Accounts removed from the access list.
+    pub removed: Vec<AccountId>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RequestCleaned {
+    pub request: SignatureRequest,
+    pub requester: AccountId,
+    /// Block the request was submitted in.
+    pub block_height: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct KeyVersionRotated {
+    pub latest: u32,
+    /// The previous latest version, which stops being served at `retired_at`.
+    pub deprecated: u32,
+    pub retired_at: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct KeyVersionPinned {
+    pub account_id: AccountId,
+    /// `None` if the account removed its pin.
+    pub key_version: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RewardsClaimed {
+    pub account_id: AccountId,
+    /// Signatures the node got paid for.
+    pub signatures: u64,
+    /// Amount in yoctoNEAR paid to the node.
+    pub amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SignPauseUpdated {
+    /// Whether `sign` rejects new requests from now on.
+    pub paused: bool,
+}
+
+impl From<&ResharingContractState> for ResharingStarted {
+    fn from(state: &ResharingContractState) -> Self {
+        Self {
+            old_epoch: state.old_epoch,
+            old_participants: state.old_participants.keys().cloned().collect(),
+            new_participants: state.new_participants.keys().cloned().collect(),
+            threshold: state.threshold,
+            old_threshold: state.old_threshold,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+impl Event {
+    pub fn to_json(&self) -> String {
+        let log = EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_VERSION,
+            event: self,
+        };
+        format!("EVENT_JSON:{}", serde_json::to_string(&log).unwrap())
+    }
+
+    pub fn emit(&self) {
+        env::log_str(&self.to_json());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_format() {
+        let event = Event::ResharingStarted(vec![ResharingStarted {
+            old_epoch: 1,
+            old_participants: vec!["a.near".parse().unwrap(), "b.near".parse().unwrap()],
+            new_participants: vec!["a.near".parse().unwrap()],
+            threshold: 1,
+            old_threshold: None,
+        }]);
+        let json = event.to_json();
+        let json = json.strip_prefix("EVENT_JSON:").unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(json).unwrap(),
+            serde_json::json!({
+                "standard": "chain-signatures",
+                "version": "1.0.0",
+                "event": "resharing_started",
+                "data": [{
+                    "old_epoch": 1,
+                    "old_participants": ["a.near", "b.near"],
+                    "new_participants": ["a.near"],
+                    "threshold": 1,
+                    "old_threshold": null,
+                }],
+            })
+        );
+    }
+}
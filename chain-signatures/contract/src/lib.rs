@@ -1,12 +1,14 @@
 pub mod config;
 pub mod errors;
+pub mod events;
+pub mod migration;
 pub mod primitives;
 pub mod state;
 pub mod update;
 
 use crypto_shared::{
-    derive_epsilon, derive_key, kdf::check_ec_signature, near_public_key_to_affine_point,
-    types::SignatureResponse, ScalarExt as _,
+    derive_epsilon, derive_key, eip712::typed_data_digest, kdf::check_ec_signature,
+    near_public_key_to_affine_point, types::SignatureResponse, DerivedAddresses, ScalarExt as _,
 };
 use errors::{
     ConversionError, InitError, InvalidParameters, InvalidState, JoinError, PublicKeyError,
@@ -15,24 +17,34 @@ use errors::{
 use k256::elliptic_curve::sec1::ToEncodedPoint;
 use k256::Scalar;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LookupMap;
+use near_sdk::collections::{LookupMap, UnorderedMap};
 use near_sdk::json_types::U128;
 use near_sdk::{
     env, log, near_bindgen, AccountId, CryptoHash, Gas, GasWeight, NearToken, Promise,
-    PromiseError, PublicKey,
+    PromiseError, PromiseResult, PublicKey,
 };
 use primitives::{
-    CandidateInfo, Candidates, ContractSignatureRequest, Participants, PkVotes, SignRequest,
-    SignaturePromiseError, SignatureRequest, SignatureResult, StorageKey, Votes, YieldIndex,
+    CandidateInfo, Candidates, ContractSignatureRequest, Heartbeat, NetworkCapacity, NodeCapacity,
+    NodeRewards, ParticipantHeartbeat, ParticipantSetVotes, Participants, PendingRequest, PkVotes,
+    SignEstimate, SignPause, SignPauseView, SignRequest, SignStats, SignTypedDataRequest,
+    SignatureCache, SignaturePromiseError, SignatureRequest, SignatureResult, SignatureResume,
+    StorageKey, ThresholdVotes, Treasury, TreasuryView, Votes, YieldIndex,
 };
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 
-use crate::config::Config;
+use crate::config::{
+    Config, KeyVersionConfig, PayloadFormat, SignAccessConfig, SignAccessMode, SignRequestOrdering,
+};
 use crate::errors::Error;
+use crate::events::{
+    Event, KeyVersionPinned, KeyVersionRotated, RequestCleaned, RewardsClaimed, SignAccessUpdated,
+    SignPauseUpdated, SignatureCompleted, SignatureRequested, SignatureTimedOut,
+};
 use crate::update::{ProposeUpdateArgs, ProposedUpdates, UpdateId};
 
 pub use state::{
     InitializingContractState, ProtocolContractState, ResharingContractState, RunningContractState,
+    StateDetails, VoteDetails,
 };
 
 const GAS_FOR_SIGN_CALL: Gas = Gas::from_tgas(50);
@@ -46,6 +58,42 @@ const CLEAR_STATE_ON_FINISH_CALL_GAS: Gas = Gas::from_tgas(20);
 // Prepaid gas for a `return_signature_on_finish` call
 const RETURN_SIGNATURE_ON_FINISH_CALL_GAS: Gas = Gas::from_tgas(10);
 
+// Prepaid gas for a `return_signatures_on_finish` call
+const RETURN_SIGNATURES_ON_FINISH_CALL_GAS: Gas = Gas::from_tgas(10);
+
+// Maximum amount of sign requests that can be submitted in a single `sign_batch` call
+pub const MAX_SIGN_BATCH_SIZE: usize = 4;
+
+// Maximum amount of keys that can be derived in a single `derived_public_keys` call
+pub const MAX_DERIVED_KEYS: usize = 16;
+
+// Maximum amount of pending sign requests before new requests are rejected
+const MAX_PENDING_REQUESTS: u32 = 16;
+
+// Maximum amount of duplicates sharing the signature of a single pending request
+const MAX_DUPLICATE_REQUESTS: usize = 4;
+
+// Maximum amount of pending requests checked in a single `clean_requests` call
+pub const MAX_CLEAN_REQUESTS: u32 = 16;
+
+// Amount of pending requests checked for staleness along the way by every `sign` and
+// `sign_batch` call
+const AUTO_CLEAN_REQUESTS: usize = 4;
+
+// Maximum amount of pending requests checked in a single `expire_requests` call
+pub const MAX_EXPIRE_REQUESTS: u32 = 16;
+
+// Amount of pending requests checked for expiry along the way by every `sign` and `sign_batch`
+// call
+const AUTO_EXPIRE_REQUESTS: usize = 4;
+
+// Amount of cached signatures past their retention dropped along the way by every `respond` call
+const AUTO_PRUNE_SIGNATURES: usize = 2;
+
+// Amount of blocks since its last heartbeat within which a node gets credited for the signatures
+// it helped produce. Nodes ping every 5 minutes, so this leaves room for a few missed pings.
+const CREDIT_HEARTBEAT_BLOCKS: u64 = 1_000;
+
 // Prepaid gas for a `update_config` call
 const UPDATE_CONFIG_GAS: Gas = Gas::from_tgas(5);
 
@@ -68,13 +116,64 @@ pub struct MpcContract {
     request_counter: u32,
     proposed_updates: ProposedUpdates,
     config: Config,
+    /// Same requests as in `pending_requests`, kept around to be able to list them.
+    pending_requests_index: UnorderedMap<SignatureRequest, PendingRequest>,
+    /// Key versions that accounts pinned their sign requests to.
+    key_version_pins: LookupMap<AccountId, u32>,
+    /// The most recently signed requests, to estimate how long new ones take.
+    sign_stats: SignStats,
+    /// The last ping of each node, to see which of them are offline.
+    heartbeats: LookupMap<AccountId, Heartbeat>,
+    /// Requests submitted while an identical one was pending, which get resolved along with it.
+    duplicate_requests: LookupMap<SignatureRequest, Vec<YieldIndex>>,
+    /// The presignatures each node last reported to have, to reject requests while there are
+    /// none left for them.
+    capacities: LookupMap<AccountId, NodeCapacity>,
+    /// Block height by which requests submitted with a `max_wait_blocks` expire.
+    request_deadlines: LookupMap<SignatureRequest, u64>,
+    /// Fees of the signed requests, until the nodes that produced the signatures claim them.
+    treasury: Treasury,
+    /// Whether new sign requests are paused by the participants, and their votes to flip that.
+    sign_pause: SignPause,
+    /// Signatures of the recently completed requests, for requesters whose callback failed.
+    signature_cache: SignatureCache,
+    /// Position in `pending_requests_index` at which the next `expire_requests` pass starts.
+    expire_cursor: u64,
+    /// Position in `pending_requests_index` at which the next `clean_requests` pass starts.
+    clean_cursor: u64,
+    /// Who may submit sign requests, managed by the contract account.
+    sign_access: SignAccessConfig,
+    /// Key versions that are served, rotated by the contract account.
+    key_versions: KeyVersionConfig,
 }
 
 impl MpcContract {
-    fn mark_request_received(&mut self, request: &SignatureRequest) {
+    fn mark_request_received(
+        &mut self,
+        request: &SignatureRequest,
+        requester: &AccountId,
+        deposit: u128,
+        max_wait_blocks: Option<u64>,
+    ) {
         if self.pending_requests.insert(request, &None).is_none() {
             self.request_counter += 1;
         }
+        if let Some(max_wait_blocks) = max_wait_blocks {
+            self.request_deadlines.insert(
+                request,
+                &env::block_height().saturating_add(max_wait_blocks),
+            );
+        }
+        self.pending_requests_index.insert(
+            request,
+            &PendingRequest {
+                request: request.clone(),
+                requester: requester.clone(),
+                timestamp: env::block_timestamp(),
+                block_height: env::block_height(),
+                deposit: deposit.into(),
+            },
+        );
     }
 
     fn add_request(&mut self, request: &SignatureRequest, data_id: CryptoHash) {
@@ -87,7 +186,77 @@ impl MpcContract {
         }
     }
 
+    fn add_duplicate_request(&mut self, request: &SignatureRequest, data_id: CryptoHash) {
+        let mut duplicates = self.duplicate_requests.get(request).unwrap_or_default();
+        duplicates.push(YieldIndex { data_id });
+        self.duplicate_requests.insert(request, &duplicates);
+    }
+
+    fn duplicate_requests(&self, request: &SignatureRequest) -> usize {
+        self.duplicate_requests
+            .get(request)
+            .map_or(0, |duplicates| duplicates.len())
+    }
+
+    /// Resolves the duplicates of `request` with the same outcome as the request itself.
+    fn resume_duplicate_requests(&mut self, request: &SignatureRequest, resume: &SignatureResume) {
+        let Some(duplicates) = self.duplicate_requests.remove(request) else {
+            return;
+        };
+        let resume = serde_json::to_vec(resume).unwrap();
+        for YieldIndex { data_id } in duplicates {
+            env::promise_yield_resume(&data_id, &resume);
+        }
+    }
+
+    /// Presignatures that the participants reported recently enough, compared to the requests
+    /// that are already waiting for one and `requests` more.
+    fn network_capacity(&self, requests: u32) -> NetworkCapacity {
+        let backpressure = self.config.backpressure();
+        let block_height = env::block_height();
+        let mut presignatures = 0u32;
+        let mut reporting = 0u32;
+        let mut threshold = None;
+        if let ProtocolContractState::Running(state) = &self.protocol_state {
+            for account_id in state.participants.keys() {
+                let Some(capacity) = self.capacities.get(account_id) else {
+                    continue;
+                };
+                if backpressure.is_fresh(capacity.block_height, block_height) {
+                    presignatures = presignatures.saturating_add(capacity.presignatures);
+                    reporting += 1;
+                }
+            }
+            threshold = Some(state.threshold);
+        }
+        // Without recent reports from at least the threshold of the participants, the capacity
+        // of the network is unknown and nothing gets rejected.
+        let known =
+            threshold.is_some_and(|threshold| reporting > 0 && reporting as usize >= threshold);
+        NetworkCapacity {
+            presignatures,
+            reporting,
+            pending_requests: self.request_counter,
+            overloaded: known && presignatures < self.request_counter.saturating_add(requests),
+            retry_after_blocks: backpressure.retry_after_blocks,
+        }
+    }
+
+    /// Fails if the network is out of presignatures for `requests` more requests.
+    fn check_capacity(&self, requests: u32) -> Result<(), Error> {
+        let capacity = self.network_capacity(requests);
+        if capacity.overloaded {
+            return Err(SignError::Overloaded.message(format!(
+                "system overloaded, retry after {} blocks",
+                capacity.retry_after_blocks
+            )));
+        }
+        Ok(())
+    }
+
     fn remove_request(&mut self, request: SignatureRequest) -> Result<(), Error> {
+        self.pending_requests_index.remove(&request);
+        self.request_deadlines.remove(&request);
         if self.pending_requests.remove(&request).is_some() {
             self.request_counter -= 1;
             Ok(())
@@ -96,6 +265,114 @@ impl MpcContract {
         }
     }
 
+    /// Credits the signature to the participants with the ids in `signers`, which produced it.
+    /// Which nodes took part in a signature can not be verified on chain, so only the ones that
+    /// pinged the contract within the last [`CREDIT_HEARTBEAT_BLOCKS`] get credited, which keeps
+    /// the responder from getting nodes that went offline paid.
+    fn record_signature(&mut self, signers: &[u32]) {
+        let ProtocolContractState::Running(state) = &self.protocol_state else {
+            return;
+        };
+        let block_height = env::block_height();
+        let credited: BTreeSet<AccountId> = signers
+            .iter()
+            .filter_map(|id| state.participants.account_id(*id))
+            .filter(|account_id| {
+                self.heartbeats.get(*account_id).is_some_and(|heartbeat| {
+                    block_height.saturating_sub(heartbeat.block_height) <= CREDIT_HEARTBEAT_BLOCKS
+                })
+            })
+            .cloned()
+            .collect();
+        for account_id in &credited {
+            self.treasury.record_signature(account_id);
+        }
+    }
+
+    /// Keeps the signature of `request` under its id for the `get_signature` view, dropping
+    /// cached signatures that are past their retention along the way.
+    fn cache_signature(&mut self, request: &SignatureRequest, signature: &SignatureResponse) {
+        let retention = self.config.signature_cache();
+        let block_height = env::block_height();
+        self.signature_cache
+            .prune(&retention, block_height, AUTO_PRUNE_SIGNATURES);
+        self.signature_cache
+            .insert(&request.id(), signature, block_height);
+    }
+
+    /// Checks the next `limit` pending requests, going around the index from where the last
+    /// call left off, and resumes the ones that have outlived the `ttl_blocks` of the config or
+    /// their own `max_wait_blocks`, so that they resolve with a timeout instead of waiting for
+    /// the yield timeout of the protocol.
+    fn expire_requests(&mut self, limit: usize) -> u32 {
+        let sign_request = self.config.sign_request();
+        let block_height = env::block_height();
+        let requests = self.pending_requests_index.values_as_vector();
+        let len = requests.len();
+        let mut expired = 0;
+        // Removals move the last request into the freed slot, so a request can be skipped by
+        // a pass, in which case it gets checked by the next one.
+        for _ in 0..(limit as u64).min(len) {
+            let index = self.expire_cursor % len;
+            self.expire_cursor = index + 1;
+            let Some(pending) = requests.get(index) else {
+                break;
+            };
+            let past_deadline = self
+                .request_deadlines
+                .get(&pending.request)
+                .is_some_and(|deadline| block_height >= deadline);
+            if !past_deadline && !sign_request.is_expired(pending.block_height, block_height) {
+                continue;
+            }
+            // Requests that already got resumed are skipped by `promise_yield_resume`, they
+            // stay around until their callback removes them.
+            if let Some(Some(YieldIndex { data_id })) = self.pending_requests.get(&pending.request)
+            {
+                let resume = SignatureResume::Expired {
+                    expired_at_block: block_height,
+                };
+                if env::promise_yield_resume(&data_id, &serde_json::to_vec(&resume).unwrap()) {
+                    expired += 1;
+                }
+            }
+        }
+        expired
+    }
+
+    /// Checks the next `limit` pending requests, going around the index from where the last
+    /// call left off, and removes the ones that outlived the `retention_epochs` of the config
+    /// without getting cleared by their callback, returning the removed requests.
+    fn clean_requests(&mut self, limit: usize) -> Vec<PendingRequest> {
+        let request_gc = self.config.request_gc();
+        let block_height = env::block_height();
+        let requests = self.pending_requests_index.values_as_vector();
+        let len = requests.len();
+        let mut stale = Vec::new();
+        // Requests are only removed once the pass is done, so the cursor moves over the same
+        // positions it checked. Those removals move other requests around, which then get
+        // checked by a later pass.
+        for _ in 0..(limit as u64).min(len) {
+            let index = self.clean_cursor % len;
+            self.clean_cursor = index + 1;
+            let Some(pending) = requests.get(index) else {
+                break;
+            };
+            if request_gc.is_stale(pending.block_height, block_height) {
+                stale.push(pending);
+            }
+        }
+        let expired = SignatureResume::Expired {
+            expired_at_block: block_height,
+        };
+        for pending in &stale {
+            // Requests that are only left in the index get removed from it all the same.
+            let _ = self.remove_request(pending.request.clone());
+            self.resume_duplicate_requests(&pending.request, &expired);
+        }
+        stale
+    }
+
     pub fn init(
         threshold: usize,
         candidates: BTreeMap<AccountId, CandidateInfo>,
@@ -111,6 +388,20 @@ impl MpcContract {
             request_counter: 0,
             proposed_updates: ProposedUpdates::default(),
             config: config.unwrap_or_default(),
+            pending_requests_index: UnorderedMap::new(StorageKey::PendingRequestsIndex),
+            key_version_pins: LookupMap::new(StorageKey::KeyVersionPins),
+            sign_stats: SignStats::default(),
+            heartbeats: LookupMap::new(StorageKey::Heartbeats),
+            duplicate_requests: LookupMap::new(StorageKey::DuplicateRequests),
+            capacities: LookupMap::new(StorageKey::Capacities),
+            request_deadlines: LookupMap::new(StorageKey::RequestDeadlines),
+            treasury: Treasury::new(),
+            sign_pause: SignPause::default(),
+            signature_cache: SignatureCache::new(),
+            expire_cursor: 0,
+            clean_cursor: 0,
+            sign_access: SignAccessConfig::default(),
+            key_versions: KeyVersionConfig::default(),
         }
     }
 }
@@ -125,20 +416,25 @@ impl VersionedMpcContract {
     #[handle_result]
     #[payable]
     pub fn sign(&mut self, request: SignRequest) -> Result<near_sdk::Promise, Error> {
+        self.sign_with_format(request, PayloadFormat::Raw)
+    }
+
+    /// Submits `request`, whose payload is of `format`, the same way for every sign method.
+    fn sign_with_format(
+        &mut self,
+        request: SignRequest,
+        format: PayloadFormat,
+    ) -> Result<near_sdk::Promise, Error> {
+        self.check_sign_access()?;
+        // It's important we fail here because the MPC nodes will fail in an identical way.
+        // This allows users to get the error message
+        let payload = self.validate_sign_request(&request, format)?;
         let SignRequest {
-            payload,
             path,
             key_version,
+            max_wait_blocks,
+            ..
         } = request;
-        // It's important we fail here because the MPC nodes will fail in an identical way.
-        // This allows users to get the error message
-        let payload = Scalar::from_bytes(payload).ok_or(
-            InvalidParameters::MalformedPayload
-                .message("Payload hash cannot be convereted to Scalar"),
-        )?;
-        if key_version > self.latest_key_version() {
-            return Err(SignError::UnsupportedKeyVersion.into());
-        }
         // Check deposit
         let deposit = env::attached_deposit();
         let required_deposit: u128 = self.experimental_signature_deposit().into();
@@ -158,31 +454,229 @@ impl VersionedMpcContract {
             )));
         }
 
+        let predecessor = env::predecessor_account_id();
+        let request = SignatureRequest::new(payload, &predecessor, &path);
+        let duplicate = self.request_already_exists(&request);
         match self {
             Self::V0(mpc_contract) => {
-                if mpc_contract.request_counter > 16 {
+                mpc_contract.expire_requests(AUTO_EXPIRE_REQUESTS);
+                // Stale requests get cleaned here without a bounty or an event, since nothing
+                // may be logged before the entropy.
+                mpc_contract.clean_requests(AUTO_CLEAN_REQUESTS);
+                // Duplicates do not need a signature of their own, so they do not count
+                // towards the pending requests.
+                if duplicate {
+                    if mpc_contract.duplicate_requests(&request) >= MAX_DUPLICATE_REQUESTS {
+                        return Err(SignError::RequestCollision.into());
+                    }
+                } else if mpc_contract.request_counter > MAX_PENDING_REQUESTS {
                     return Err(SignError::RequestLimitExceeded.into());
+                } else {
+                    mpc_contract.check_capacity(1)?;
                 }
             }
         }
-        let predecessor = env::predecessor_account_id();
-        let request = SignatureRequest::new(payload, &predecessor, &path);
-        if !self.request_already_exists(&request) {
+        // When requests are ordered by deposit, the whole deposit is the bid for being signed
+        // sooner, so none of it is refunded once the request is signed.
+        let fee = if duplicate {
+            NearToken::from_yoctonear(self.config().fee().duplicate_fee.0)
+        } else {
+            match self.sign_request_ordering() {
+                SignRequestOrdering::Fifo => NearToken::from_yoctonear(required_deposit),
+                SignRequestOrdering::Deposit => deposit,
+            }
+        };
+        if duplicate {
+            // Without the entropy the nodes do not pick up the duplicate as a new request.
+            log!(
+                "sign: duplicate of a pending request, predecessor={predecessor}, payload={payload:?}, path={path:?}",
+            );
+        } else {
             log!(
                 "sign: predecessor={predecessor}, payload={payload:?}, path={path:?}, key_version={key_version}",
             );
             env::log_str(&serde_json::to_string(&near_sdk::env::random_seed_array()).unwrap());
-            self.mark_request_received(&request);
+            self.mark_request_received(
+                &request,
+                &predecessor,
+                deposit.as_yoctonear(),
+                max_wait_blocks,
+            );
+        }
+        // Logged after the entropy, which the nodes expect to be the second log.
+        Event::SignatureRequested(vec![SignatureRequested {
+            request_id: request.id(),
+            request: request.clone(),
+            requester: predecessor.clone(),
+            path,
+            key_version,
+            deposit: deposit.as_yoctonear().into(),
+        }])
+        .emit();
+        let contract_signature_request = ContractSignatureRequest {
+            request,
+            requester: predecessor,
+            deposit,
+            required_deposit: fee,
+            duplicate,
+        };
+        Ok(Self::ext(env::current_account_id()).sign_helper(contract_signature_request))
+    }
+
+    /// Submit up to [`MAX_SIGN_BATCH_SIZE`] sign requests in a single call. Each request is
+    /// validated the same way as in [`Self::sign`] and the whole batch is rejected if any of
+    /// them is invalid. The attached deposit must cover the required deposit of every request,
+    /// and the signatures are returned in the same order as the requests once all of them resolve.
+    #[handle_result]
+    #[payable]
+    pub fn sign_batch(&mut self, requests: Vec<SignRequest>) -> Result<near_sdk::Promise, Error> {
+        self.check_sign_access()?;
+        if requests.is_empty() || requests.len() > MAX_SIGN_BATCH_SIZE {
+            return Err(InvalidParameters::InvalidBatchSize.message(format!(
+                "Provided {}, maximum {}",
+                requests.len(),
+                MAX_SIGN_BATCH_SIZE
+            )));
+        }
+        let payloads = requests
+            .iter()
+            .map(|request| self.validate_sign_request(request, PayloadFormat::Raw))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Check deposit
+        let batch_size = requests.len() as u128;
+        let deposit = env::attached_deposit();
+        let required_deposit: u128 = self.experimental_signature_deposit().into();
+        let total_required_deposit = required_deposit.saturating_mul(batch_size);
+        if deposit.as_yoctonear() < total_required_deposit {
+            return Err(InvalidParameters::InsufficientDeposit.message(format!(
+                "Attached {}, Required {}",
+                deposit.as_yoctonear(),
+                total_required_deposit,
+            )));
+        }
+        // Make sure every sign call in the batch will not run out of gas doing yield/resume logic
+        let required_gas = GAS_FOR_SIGN_CALL.saturating_mul(requests.len() as u64);
+        if env::prepaid_gas() < required_gas {
+            return Err(InvalidParameters::InsufficientGas.message(format!(
+                "Provided: {}, required: {}",
+                env::prepaid_gas(),
+                required_gas
+            )));
+        }
+
+        match self {
+            Self::V0(mpc_contract) => {
+                mpc_contract.expire_requests(AUTO_EXPIRE_REQUESTS);
+                // Stale requests get cleaned here without a bounty or an event, since nothing
+                // may be logged before the entropy.
+                mpc_contract.clean_requests(AUTO_CLEAN_REQUESTS);
+                if mpc_contract.request_counter + requests.len() as u32 > MAX_PENDING_REQUESTS {
+                    return Err(SignError::RequestLimitExceeded.into());
+                }
+                mpc_contract.check_capacity(requests.len() as u32)?;
+            }
+        }
+
+        // Same as in `sign`, except that the bid is split evenly between the requests.
+        let fee = match self.sign_request_ordering() {
+            SignRequestOrdering::Fifo => required_deposit,
+            SignRequestOrdering::Deposit => deposit.as_yoctonear() / batch_size,
+        };
+        let predecessor = env::predecessor_account_id();
+        log!(
+            "sign_batch: predecessor={predecessor}, requests={}",
+            requests.len()
+        );
+        env::log_str(&serde_json::to_string(&near_sdk::env::random_seed_array()).unwrap());
+
+        let mut batch: Option<Promise> = None;
+        let mut requested = Vec::with_capacity(requests.len());
+        for (request, payload) in requests.into_iter().zip(payloads) {
+            let SignRequest {
+                path,
+                key_version,
+                max_wait_blocks,
+                ..
+            } = request;
+            let request = SignatureRequest::new(payload, &predecessor, &path);
+            if self.request_already_exists(&request) {
+                return Err(SignError::RequestCollision.into());
+            }
+            log!(
+                "sign: predecessor={predecessor}, payload={payload:?}, path={path:?}, key_version={key_version}",
+            );
+            self.mark_request_received(&request, &predecessor, fee, max_wait_blocks);
+            requested.push(SignatureRequested {
+                request_id: request.id(),
+                request: request.clone(),
+                requester: predecessor.clone(),
+                path,
+                key_version,
+                deposit: fee.into(),
+            });
             let contract_signature_request = ContractSignatureRequest {
                 request,
-                requester: predecessor,
-                deposit,
-                required_deposit: NearToken::from_yoctonear(required_deposit),
+                requester: predecessor.clone(),
+                deposit: NearToken::from_yoctonear(fee),
+                required_deposit: NearToken::from_yoctonear(fee),
+                duplicate: false,
             };
-            Ok(Self::ext(env::current_account_id()).sign_helper(contract_signature_request))
-        } else {
-            Err(SignError::RequestCollision.into())
+            let promise =
+                Self::ext(env::current_account_id()).sign_helper(contract_signature_request);
+            batch = Some(match batch {
+                Some(batch) => batch.and(promise),
+                None => promise,
+            });
+        }
+
+        Event::SignatureRequested(requested).emit();
+
+        // Refund whatever was attached on top of the fees of the whole batch.
+        let total_fee = fee.saturating_mul(batch_size);
+        if let Some(diff) = deposit.checked_sub(NearToken::from_yoctonear(total_fee)) {
+            if diff > NearToken::from_yoctonear(0) {
+                log!("refund more than required deposit {diff} to {predecessor}");
+                Promise::new(predecessor).transfer(diff);
+            }
         }
+
+        // SAFETY: the batch is guaranteed to have at least one request from the size check above.
+        let batch = batch.unwrap();
+        Ok(batch.then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(RETURN_SIGNATURES_ON_FINISH_CALL_GAS)
+                .with_unused_gas_weight(0)
+                .return_signatures_on_finish(),
+        ))
+    }
+
+    /// Sign EIP-712 typed data. The domain separator of the request is checked against its
+    /// domain, so that wallets can not be tricked into signing for another domain, and the digest
+    /// of the typed data is then signed with secp256k1 the same way as in [`Self::sign`].
+    #[handle_result]
+    #[payable]
+    pub fn sign_typed_data(
+        &mut self,
+        request: SignTypedDataRequest,
+    ) -> Result<near_sdk::Promise, Error> {
+        let separator = request
+            .domain
+            .separator()
+            .map_err(|err| InvalidParameters::InvalidDomainSeparator.message(err.to_string()))?;
+        if separator != request.domain_separator {
+            return Err(InvalidParameters::InvalidDomainSeparator.into());
+        }
+        self.sign_with_format(
+            SignRequest {
+                payload: typed_data_digest(&separator, &request.struct_hash),
+                path: request.path,
+                key_version: request.key_version,
+                message: None,
+                max_wait_blocks: None,
+            },
+            PayloadFormat::Eip712,
+        )
     }
 
     /// This is the root public key combined from all the public keys of the participants.
@@ -203,39 +697,340 @@ impl VersionedMpcContract {
         path: String,
         predecessor: Option<AccountId>,
     ) -> Result<PublicKey, Error> {
-        let predecessor = predecessor.unwrap_or_else(env::predecessor_account_id);
-        let epsilon = derive_epsilon(&predecessor, &path);
-        let derived_public_key =
-            derive_key(near_public_key_to_affine_point(self.public_key()?), epsilon);
-        let encoded_point = derived_public_key.to_encoded_point(false);
-        let slice: &[u8] = &encoded_point.as_bytes()[1..65];
-        let mut data: Vec<u8> = vec![near_sdk::CurveType::SECP256K1 as u8];
-        data.extend(slice.to_vec());
-        PublicKey::try_from(data).map_err(|_| PublicKeyError::DerivedKeyConversionFailed.into())
+        to_near_public_key(&self.derive_public_key(path, predecessor)?)
+    }
+
+    /// The derived public keys of up to [`MAX_DERIVED_KEYS`] pairs of path and predecessor, in
+    /// the same order. A predecessor that is not provided is the caller of the contract, like in
+    /// `derived_public_key`.
+    #[handle_result]
+    pub fn derived_public_keys(
+        &self,
+        keys: Vec<(String, Option<AccountId>)>,
+    ) -> Result<Vec<PublicKey>, Error> {
+        if keys.is_empty() || keys.len() > MAX_DERIVED_KEYS {
+            return Err(InvalidParameters::InvalidBatchSize.message(format!(
+                "expected between 1 and {} keys, got {}",
+                MAX_DERIVED_KEYS,
+                keys.len()
+            )));
+        }
+        let root_public_key = near_public_key_to_affine_point(self.public_key()?);
+        keys.into_iter()
+            .map(|(path, predecessor)| {
+                let predecessor = predecessor.unwrap_or_else(env::predecessor_account_id);
+                let epsilon = derive_epsilon(&predecessor, &path);
+                to_near_public_key(&derive_key(root_public_key, epsilon))
+            })
+            .collect()
+    }
+
+    /// These are the Bitcoin and Ethereum addresses controlled by the derived public key of the
+    /// given path and predecessor. If predecessor is not provided, it will be the caller of the
+    /// contract.
+    #[handle_result]
+    pub fn derived_addresses(
+        &self,
+        path: String,
+        predecessor: Option<AccountId>,
+    ) -> Result<DerivedAddresses, Error> {
+        Ok(DerivedAddresses::new(
+            &self.derive_public_key(path, predecessor)?,
+        ))
+    }
+
+    /// Whether `signature` is a valid signature of `payload` by the key derived for `path` and
+    /// `predecessor`, so that contracts can check the signatures of the MPC service without
+    /// doing the secp256k1 math themselves.
+    #[handle_result]
+    pub fn verify(
+        &self,
+        payload: [u8; 32],
+        path: String,
+        predecessor: AccountId,
+        signature: SignatureResponse,
+    ) -> Result<bool, Error> {
+        let payload = Scalar::from_bytes(payload).ok_or(
+            InvalidParameters::MalformedPayload
+                .message("Payload hash cannot be convereted to Scalar"),
+        )?;
+        let expected_public_key = self.derive_public_key(path, Some(predecessor))?;
+        Ok(check_ec_signature(
+            &expected_public_key,
+            &signature.big_r.affine_point,
+            &signature.s.scalar,
+            payload,
+            signature.recovery_id,
+        )
+        .is_ok())
     }
 
     /// Key versions refer new versions of the root key that we may choose to generate on cohort changes
-    /// Older key versions will always work but newer key versions were never held by older signers
+    /// Older key versions keep working until their deprecation window ends, see `key_versions`
     /// Newer key versions may also add new security features, like only existing within a secure enclave
-    /// Currently only 0 is a valid key version
-    pub const fn latest_key_version(&self) -> u32 {
-        0
+    pub fn latest_key_version(&self) -> u32 {
+        self.key_versions().latest
+    }
+
+    /// The latest key version along with the deprecated versions and the block at which each
+    /// of them stops being served.
+    pub fn key_versions(&self) -> KeyVersionConfig {
+        match self {
+            Self::V0(mpc_contract) => mpc_contract.key_versions.clone(),
+        }
+    }
+
+    /// Key version that the sign requests of `account_id` are pinned to, if any.
+    pub fn pinned_key_version(&self, account_id: AccountId) -> Option<u32> {
+        match self {
+            Self::V0(mpc_contract) => mpc_contract.key_version_pins.get(&account_id),
+        }
+    }
+
+    /// Pins the sign requests of the caller to `key_version`, so that requests for any other
+    /// version get rejected instead of being signed with a key the caller does not expect,
+    /// e.g. after a rotation changed the latest version. `None` removes the pin.
+    /// The caller pays for the storage of the pin, and gets it refunded when removing it.
+    #[handle_result]
+    #[payable]
+    pub fn pin_key_version(&mut self, key_version: Option<u32>) -> Result<(), Error> {
+        let account_id = env::predecessor_account_id();
+        let deposit = env::attached_deposit().as_yoctonear();
+        if let Some(key_version) = key_version {
+            if !self
+                .key_versions()
+                .is_served(key_version, env::block_height())
+            {
+                return Err(SignError::UnsupportedKeyVersion.into());
+            }
+        }
+        let storage_before = env::storage_usage();
+        match self {
+            Self::V0(mpc_contract) => match key_version {
+                Some(key_version) => {
+                    mpc_contract
+                        .key_version_pins
+                        .insert(&account_id, &key_version);
+                }
+                None => {
+                    mpc_contract.key_version_pins.remove(&account_id);
+                }
+            },
+        }
+        let storage_after = env::storage_usage();
+        let storage_cost = env::storage_byte_cost().as_yoctonear();
+        let refund = if storage_after > storage_before {
+            let required = (storage_after - storage_before) as u128 * storage_cost;
+            if deposit < required {
+                return Err(InvalidParameters::InsufficientDeposit.message(format!(
+                    "Attached {deposit}, required {required} to store the pin"
+                )));
+            }
+            deposit - required
+        } else {
+            deposit + (storage_before - storage_after) as u128 * storage_cost
+        };
+        Event::KeyVersionPinned(vec![KeyVersionPinned {
+            account_id: account_id.clone(),
+            key_version,
+        }])
+        .emit();
+        if refund > 0 {
+            Promise::new(account_id).transfer(NearToken::from_yoctonear(refund));
+        }
+        Ok(())
+    }
+
+    /// Whether the key shares of the participants are due for a proactive resharing, which the
+    /// nodes then start through `start_proactive_resharing`.
+    pub fn proactive_resharing_due(&self) -> bool {
+        match self.state() {
+            ProtocolContractState::Running(state) => self
+                .config()
+                .proactive_resharing()
+                .is_due(state.shares_epoch_height, env::epoch_height()),
+            _ => false,
+        }
+    }
+
+    /// Who may submit sign requests. Anyone may, unless the contract runs in permissioned mode.
+    pub fn sign_access(&self) -> SignAccessConfig {
+        match self {
+            Self::V0(mpc_contract) => mpc_contract.sign_access.clone(),
+        }
     }
 
     /// This experimental function calculates the fee for a signature request.
     /// The fee is volatile and depends on the number of pending requests.
     /// If used on a client side, it can give outdate results.
     pub fn experimental_signature_deposit(&self) -> U128 {
-        const CHEAP_REQUESTS: u32 = 3;
-        let pending_requests = match self {
-            Self::V0(mpc_contract) => mpc_contract.request_counter,
-        };
-        match pending_requests {
-            0..=CHEAP_REQUESTS => U128::from(1),
-            _ => {
-                let expensive_requests = (pending_requests - CHEAP_REQUESTS) as u128;
-                let price = expensive_requests * NearToken::from_millinear(50).as_yoctonear();
-                U128::from(price)
+        match self {
+            Self::V0(mpc_contract) => mpc_contract
+                .config
+                .fee()
+                .signature_deposit(mpc_contract.request_counter)
+                .into(),
+        }
+    }
+
+    /// Deposit, queue depth and expected latency of a sign request submitted right now. The
+    /// latency is estimated from the most recently signed requests, so like the deposit it can
+    /// change by the time the request gets submitted.
+    pub fn estimate_sign(&self) -> SignEstimate {
+        match self {
+            Self::V0(mpc_contract) => {
+                let queue_depth = mpc_contract.request_counter;
+                SignEstimate {
+                    deposit: mpc_contract
+                        .config
+                        .fee()
+                        .signature_deposit(queue_depth)
+                        .into(),
+                    queue_depth,
+                    estimated_latency_ms: mpc_contract
+                        .sign_stats
+                        .estimate_latency(queue_depth)
+                        .map(|latency| latency / 1_000_000),
+                    samples: mpc_contract.sign_stats.samples(),
+                }
+            }
+        }
+    }
+
+    /// How recently each node of the protocol pinged the contract, so that operators that went
+    /// offline can be spotted before signatures stall.
+    pub fn participant_heartbeats(&self) -> Vec<ParticipantHeartbeat> {
+        match self {
+            Self::V0(mpc_contract) => mpc_contract
+                .protocol_state
+                .node_accounts()
+                .into_iter()
+                .map(|account_id| {
+                    let last_seen = mpc_contract.heartbeats.get(&account_id);
+                    ParticipantHeartbeat {
+                        blocks_since_last_seen: last_seen.map(|heartbeat| {
+                            env::block_height().saturating_sub(heartbeat.block_height)
+                        }),
+                        account_id,
+                        last_seen,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Presignatures the participants reported to have available, and whether `sign` rejects
+    /// new requests because of a lack of them.
+    pub fn network_capacity(&self) -> NetworkCapacity {
+        match self {
+            Self::V0(mpc_contract) => mpc_contract.network_capacity(1),
+        }
+    }
+
+    /// Fees of the signed requests that the nodes did not claim yet.
+    pub fn treasury(&self) -> TreasuryView {
+        match self {
+            Self::V0(mpc_contract) => mpc_contract.treasury.view(),
+        }
+    }
+
+    /// Signatures that `account_id` helped produce since it last claimed its rewards, and the
+    /// fees it would get for them by calling `claim_rewards` now.
+    pub fn rewards(&self, account_id: AccountId) -> NodeRewards {
+        match self {
+            Self::V0(mpc_contract) => mpc_contract.treasury.rewards(&account_id),
+        }
+    }
+
+    /// Whether `sign` rejects new requests because the participants paused them, along with
+    /// the votes of the participants to flip that.
+    pub fn sign_pause(&self) -> SignPauseView {
+        match self {
+            Self::V0(mpc_contract) => {
+                let no_participants = Participants::new();
+                let participants = mpc_contract
+                    .protocol_state
+                    .current_participants()
+                    .unwrap_or(&no_participants);
+                mpc_contract.sign_pause.view(participants)
+            }
+        }
+    }
+
+    /// Overview of the protocol state: the epoch, participants, candidates and ongoing votes.
+    pub fn state_details(&self) -> StateDetails {
+        self.state().into()
+    }
+
+    /// Sign requests that have not been responded to yet, along with when they were submitted.
+    /// A request is removed from here once it gets a signature or times out.
+    pub fn get_pending_requests(&self) -> Vec<PendingRequest> {
+        match self {
+            Self::V0(mpc_contract) => mpc_contract.pending_requests_index.values().collect(),
+        }
+    }
+
+    /// Signature of the request with `request_id` if it got signed within the retention of the
+    /// `signature_cache` config, so that requesters whose callback failed, e.g. because it ran
+    /// out of gas, can still fetch their signature instead of paying for a new one. `request_id`
+    /// is the one in the `signature_requested` event of the sign call.
+    pub fn get_signature(&self, request_id: CryptoHash) -> Option<SignatureResponse> {
+        match self {
+            Self::V0(mpc_contract) => mpc_contract.signature_cache.get(
+                &request_id,
+                &mpc_contract.config.signature_cache(),
+                env::block_height(),
+            ),
+        }
+    }
+
+    /// Checks the next [`MAX_EXPIRE_REQUESTS`] pending requests, picking up where the last check
+    /// left off, and resolves the ones that have not been signed within the `ttl_blocks` of the
+    /// config with a timeout error and refunds their deposit. Every new sign request checks a
+    /// few of them as well, so this only needs to be called while there are none.
+    ///
+    /// returns the amount of requests that expired.
+    pub fn expire_requests(&mut self) -> u32 {
+        match self {
+            Self::V0(mpc_contract) => mpc_contract.expire_requests(MAX_EXPIRE_REQUESTS as usize),
+        }
+    }
+
+    /// Checks the next `limit` (at most [`MAX_CLEAN_REQUESTS`]) pending requests, picking up
+    /// where the last check left off, and removes the ones that outlived the `retention_epochs`
+    /// of the config without getting cleared. The caller gets paid the `bounty` of the config for
+    /// every one of them out of the deposit of that request, which was never refunded or paid
+    /// out since the request did not resolve, and will not be once it is removed. It can be
+    /// called by anyone and returns the amount of requests that got cleaned.
+    pub fn clean_requests(&mut self, limit: Option<u32>) -> u32 {
+        let limit = limit.unwrap_or(MAX_CLEAN_REQUESTS).min(MAX_CLEAN_REQUESTS);
+        match self {
+            Self::V0(mpc_contract) => {
+                let cleaned = mpc_contract.clean_requests(limit as usize);
+                if cleaned.is_empty() {
+                    return 0;
+                }
+                Event::RequestCleaned(
+                    cleaned
+                        .iter()
+                        .map(|pending| RequestCleaned {
+                            request: pending.request.clone(),
+                            requester: pending.requester.clone(),
+                            block_height: pending.block_height,
+                        })
+                        .collect(),
+                )
+                .emit();
+                let request_gc = mpc_contract.config.request_gc();
+                let bounty = cleaned
+                    .iter()
+                    .map(|pending| request_gc.bounty_for(pending.deposit.0))
+                    .fold(0u128, u128::saturating_add);
+                if bounty > 0 {
+                    Promise::new(env::predecessor_account_id())
+                        .transfer(NearToken::from_yoctonear(bounty));
+                }
+                cleaned.len() as u32
             }
         }
     }
@@ -244,15 +1039,32 @@ impl VersionedMpcContract {
 // Node API
 #[near_bindgen]
 impl VersionedMpcContract {
+    /// Delivers the signature of a pending request. `participants` are the ids of the nodes
+    /// that produced the signature, which have to be at least a threshold of participants of the
+    /// protocol. The signature is credited in the treasury to the ones among them that recently
+    /// pinged the contract, and to nobody if no ids are given.
     #[handle_result]
     pub fn respond(
         &mut self,
         request: SignatureRequest,
         response: SignatureResponse,
+        participants: Option<Vec<u32>>,
     ) -> Result<(), Error> {
         let protocol_state = self.mutable_state();
 
-        if let ProtocolContractState::Running(_) = protocol_state {
+        if let ProtocolContractState::Running(state) = protocol_state {
+            if participants
+                .as_ref()
+                .is_some_and(|ids| !ids.iter().all(|id| state.participants.contains_id(*id)))
+            {
+                return Err(RespondError::UnknownParticipant.into());
+            }
+            if participants
+                .as_ref()
+                .is_some_and(|ids| ids.iter().collect::<BTreeSet<_>>().len() < state.threshold)
+            {
+                return Err(RespondError::TooFewParticipants.into());
+            }
             let signer = env::signer_account_id();
             log!(
                 "respond: signer={}, request={:?} big_r={:?} s={:?}",
@@ -285,10 +1097,16 @@ impl VersionedMpcContract {
                     if let Some(Some(YieldIndex { data_id })) =
                         mpc_contract.pending_requests.get(&request)
                     {
-                        env::promise_yield_resume(
+                        // Only the response that resolves the request gets credited.
+                        if env::promise_yield_resume(
                             &data_id,
                             &serde_json::to_vec(&response).unwrap(),
-                        );
+                        ) {
+                            mpc_contract.record_signature(&participants.unwrap_or_default());
+                            // Cached here rather than in the callback, so that the signature
+                            // is kept even if the callback fails.
+                            mpc_contract.cache_signature(&request, &response);
+                        }
                         Ok(())
                     } else {
                         Err(InvalidParameters::RequestNotFound.into())
@@ -367,14 +1185,17 @@ impl VersionedMpcContract {
                 if voted.len() >= *threshold {
                     let mut new_participants = participants.clone();
                     new_participants.insert(candidate, candidate_info.clone().into());
-                    *protocol_state = ProtocolContractState::Resharing(ResharingContractState {
+                    let resharing = ResharingContractState {
                         old_epoch: *epoch,
                         old_participants: participants.clone(),
                         new_participants,
                         threshold: *threshold,
                         public_key: public_key.clone(),
                         finished_votes: HashSet::new(),
-                    });
+                        old_threshold: None,
+                    };
+                    Event::ResharingStarted(vec![(&resharing).into()]).emit();
+                    *protocol_state = ProtocolContractState::Resharing(resharing);
                     Ok(true)
                 } else {
                     Ok(false)
@@ -413,14 +1234,17 @@ impl VersionedMpcContract {
                 if voted.len() >= *threshold {
                     let mut new_participants = participants.clone();
                     new_participants.remove(&kick);
-                    *protocol_state = ProtocolContractState::Resharing(ResharingContractState {
+                    let resharing = ResharingContractState {
                         old_epoch: *epoch,
                         old_participants: participants.clone(),
                         new_participants,
                         threshold: *threshold,
                         public_key: public_key.clone(),
                         finished_votes: HashSet::new(),
-                    });
+                        old_threshold: None,
+                    };
+                    Event::ResharingStarted(vec![(&resharing).into()]).emit();
+                    *protocol_state = ProtocolContractState::Resharing(resharing);
                     Ok(true)
                 } else {
                     Ok(false)
@@ -430,6 +1254,186 @@ impl VersionedMpcContract {
         }
     }
 
+    /// Vote for replacing the current participant set with `participants`. This allows several
+    /// nodes to join and leave at once with a single resharing. Every account in the new set
+    /// must either be a participant already or a candidate that has called `join`, and at least
+    /// the threshold of the current participants must stay on to reshare their key shares.
+    ///
+    /// returns true once the threshold of participants has voted for the same set and
+    /// the contract has moved into the resharing state.
+    #[handle_result]
+    pub fn vote_new_participants(
+        &mut self,
+        participants: BTreeSet<AccountId>,
+    ) -> Result<bool, Error> {
+        log!(
+            "vote_new_participants: signer={}, participants={:?}",
+            env::signer_account_id(),
+            participants
+        );
+        let voter = self.voter()?;
+        let protocol_state = self.mutable_state();
+        match protocol_state {
+            ProtocolContractState::Running(RunningContractState {
+                epoch,
+                participants: old_participants,
+                threshold,
+                public_key,
+                candidates,
+                new_participants_votes,
+                ..
+            }) => {
+                if participants.len() < *threshold {
+                    return Err(VoteError::ParticipantsBelowThreshold.into());
+                }
+                if participants.iter().eq(old_participants.keys()) {
+                    return Err(VoteError::ParticipantSetUnchanged.into());
+                }
+                // The new participants get their shares from the old ones, which takes at least
+                // the threshold of the old participants to stay on.
+                let remaining = participants
+                    .iter()
+                    .filter(|account_id| old_participants.contains_key(account_id))
+                    .count();
+                if remaining < *threshold {
+                    return Err(VoteError::TooFewRemainingParticipants.into());
+                }
+
+                let mut new_participants = old_participants.clone();
+                for account_id in old_participants.keys() {
+                    if !participants.contains(account_id) {
+                        new_participants.remove(account_id);
+                    }
+                }
+                for account_id in &participants {
+                    if old_participants.contains_key(account_id) {
+                        continue;
+                    }
+                    let candidate_info = candidates
+                        .get(account_id)
+                        .ok_or(VoteError::NewParticipantNotCandidate)?;
+                    new_participants.insert(account_id.clone(), candidate_info.clone().into());
+                }
+
+                if new_participants_votes.vote(voter, participants) >= *threshold {
+                    let resharing = ResharingContractState {
+                        old_epoch: *epoch,
+                        old_participants: old_participants.clone(),
+                        new_participants,
+                        threshold: *threshold,
+                        public_key: public_key.clone(),
+                        finished_votes: HashSet::new(),
+                        old_threshold: None,
+                    };
+                    Event::ResharingStarted(vec![(&resharing).into()]).emit();
+                    *protocol_state = ProtocolContractState::Resharing(resharing);
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
+            _ => Err(InvalidState::UnexpectedProtocolState.message(protocol_state.name())),
+        }
+    }
+
+    /// Vote for changing the signing threshold of the current participants. Once the threshold
+    /// of participants has voted for the same value, the participants reshare their keys to the
+    /// new threshold.
+    ///
+    /// returns true once the contract has moved into the resharing state.
+    #[handle_result]
+    pub fn vote_threshold(&mut self, threshold: usize) -> Result<bool, Error> {
+        log!(
+            "vote_threshold: signer={}, threshold={}",
+            env::signer_account_id(),
+            threshold
+        );
+        let voter = self.voter()?;
+        let protocol_state = self.mutable_state();
+        match protocol_state {
+            ProtocolContractState::Running(RunningContractState {
+                epoch,
+                participants,
+                threshold: old_threshold,
+                public_key,
+                threshold_votes,
+                ..
+            }) => {
+                if threshold < 2 || threshold > participants.len() {
+                    return Err(VoteError::InvalidThreshold.into());
+                }
+                if threshold == *old_threshold {
+                    return Err(VoteError::ThresholdUnchanged.into());
+                }
+
+                if threshold_votes.vote(voter, threshold) >= *old_threshold {
+                    let resharing = ResharingContractState {
+                        old_epoch: *epoch,
+                        old_participants: participants.clone(),
+                        new_participants: participants.clone(),
+                        threshold,
+                        public_key: public_key.clone(),
+                        finished_votes: HashSet::new(),
+                        old_threshold: Some(*old_threshold),
+                    };
+                    Event::ResharingStarted(vec![(&resharing).into()]).emit();
+                    *protocol_state = ProtocolContractState::Resharing(resharing);
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
+            _ => Err(InvalidState::UnexpectedProtocolState.message(protocol_state.name())),
+        }
+    }
+
+    /// Starts a proactive resharing once the key shares of the participants are older than the
+    /// `interval_epochs` of the `proactive_resharing` config. The participants reshare the same
+    /// key among themselves with the same threshold, which leaves the public key unchanged but
+    /// makes any share that leaked before useless. Any participant can start it, since it only
+    /// happens when the policy says so.
+    ///
+    /// returns true if the contract has moved into the resharing state, false if no resharing
+    /// is due or one is already underway.
+    #[handle_result]
+    pub fn start_proactive_resharing(&mut self) -> Result<bool, Error> {
+        log!(
+            "start_proactive_resharing: signer={}",
+            env::signer_account_id()
+        );
+        self.voter()?;
+        let proactive_resharing = self.config().proactive_resharing();
+        let protocol_state = self.mutable_state();
+        match protocol_state {
+            ProtocolContractState::Running(RunningContractState {
+                epoch,
+                participants,
+                threshold,
+                public_key,
+                shares_epoch_height,
+                ..
+            }) => {
+                if !proactive_resharing.is_due(*shares_epoch_height, env::epoch_height()) {
+                    return Ok(false);
+                }
+                let resharing = ResharingContractState {
+                    old_epoch: *epoch,
+                    old_participants: participants.clone(),
+                    new_participants: participants.clone(),
+                    threshold: *threshold,
+                    public_key: public_key.clone(),
+                    finished_votes: HashSet::new(),
+                    old_threshold: None,
+                };
+                Event::ResharingStarted(vec![(&resharing).into()]).emit();
+                *protocol_state = ProtocolContractState::Resharing(resharing);
+                Ok(true)
+            }
+            ProtocolContractState::Resharing(_) => Ok(false),
+            _ => Err(InvalidState::UnexpectedProtocolState.message(protocol_state.name())),
+        }
+    }
+
     #[handle_result]
     pub fn vote_pk(&mut self, public_key: PublicKey) -> Result<bool, Error> {
         log!(
@@ -456,6 +1460,9 @@ impl VersionedMpcContract {
                         candidates: Candidates::new(),
                         join_votes: Votes::new(),
                         leave_votes: Votes::new(),
+                        new_participants_votes: ParticipantSetVotes::new(),
+                        threshold_votes: ThresholdVotes::new(),
+                        shares_epoch_height: env::epoch_height(),
                     });
                     Ok(true)
                 } else {
@@ -485,6 +1492,7 @@ impl VersionedMpcContract {
                 threshold,
                 public_key,
                 finished_votes,
+                ..
             }) => {
                 if *old_epoch + 1 != epoch {
                     return Err(InvalidState::EpochMismatch.into());
@@ -499,6 +1507,9 @@ impl VersionedMpcContract {
                         candidates: Candidates::new(),
                         join_votes: Votes::new(),
                         leave_votes: Votes::new(),
+                        new_participants_votes: ParticipantSetVotes::new(),
+                        threshold_votes: ThresholdVotes::new(),
+                        shares_epoch_height: env::epoch_height(),
                     });
                     Ok(true)
                 } else {
@@ -516,6 +1527,95 @@ impl VersionedMpcContract {
         }
     }
 
+    /// Reports that the calling node is up. Nodes call this periodically, which is what the
+    /// `participant_heartbeats` view is based on.
+    #[handle_result]
+    pub fn ping(&mut self) -> Result<(), Error> {
+        let node = env::signer_account_id();
+        match self {
+            Self::V0(mpc_contract) => {
+                if !mpc_contract.protocol_state.node_accounts().contains(&node) {
+                    return Err(VoteError::VoterNotParticipant.into());
+                }
+                mpc_contract.heartbeats.insert(
+                    &node,
+                    &Heartbeat {
+                        block_height: env::block_height(),
+                        timestamp: env::block_timestamp(),
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports how many presignatures the calling node has available, which `sign` uses to
+    /// reject requests while the network is out of them. See the `backpressure` config entry.
+    #[handle_result]
+    pub fn report_capacity(&mut self, presignatures: u32) -> Result<(), Error> {
+        let node = env::signer_account_id();
+        match self {
+            Self::V0(mpc_contract) => {
+                if !mpc_contract.protocol_state.node_accounts().contains(&node) {
+                    return Err(VoteError::VoterNotParticipant.into());
+                }
+                mpc_contract.capacities.insert(
+                    &node,
+                    &NodeCapacity {
+                        presignatures,
+                        block_height: env::block_height(),
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Vote for pausing new sign requests in an emergency, such as a discovered vulnerability.
+    /// Requests submitted before the pause still get their signatures.
+    ///
+    /// returns true once a supermajority of the participants has voted for it and new sign
+    /// requests got paused.
+    #[handle_result]
+    pub fn vote_pause(&mut self) -> Result<bool, Error> {
+        log!("vote_pause: signer={}", env::signer_account_id());
+        self.vote_sign_pause(true)
+    }
+
+    /// Vote for resuming sign requests after they got paused with `vote_pause`.
+    ///
+    /// returns true once a supermajority of the participants has voted for it and new sign
+    /// requests are accepted again.
+    #[handle_result]
+    pub fn vote_unpause(&mut self) -> Result<bool, Error> {
+        log!("vote_unpause: signer={}", env::signer_account_id());
+        self.vote_sign_pause(false)
+    }
+
+    /// Pays the caller its share of the treasury, which is proportional to the signatures it
+    /// helped produce since it last claimed. Nodes that left the protocol can still claim the
+    /// rewards of the signatures they produced before. Nothing is paid while the share rounds
+    /// down to zero.
+    pub fn claim_rewards(&mut self) -> NodeRewards {
+        let account_id = env::predecessor_account_id();
+        match self {
+            Self::V0(mpc_contract) => {
+                let rewards = mpc_contract.treasury.claim(&account_id);
+                if rewards.amount.0 == 0 {
+                    return rewards;
+                }
+                Event::RewardsClaimed(vec![RewardsClaimed {
+                    account_id: account_id.clone(),
+                    signatures: rewards.signatures,
+                    amount: rewards.amount,
+                }])
+                .emit();
+                Promise::new(account_id).transfer(NearToken::from_yoctonear(rewards.amount.0));
+                rewards
+            }
+        }
+    }
+
     /// Propose an update to the contract. [`Update`] are all the possible updates that can be proposed.
     ///
     /// returns Some(id) if the proposal was successful, None otherwise
@@ -643,17 +1743,34 @@ impl VersionedMpcContract {
                 candidates: Candidates::new(),
                 join_votes: Votes::new(),
                 leave_votes: Votes::new(),
+                new_participants_votes: ParticipantSetVotes::new(),
+                threshold_votes: ThresholdVotes::new(),
+                shares_epoch_height: env::epoch_height(),
             }),
             pending_requests: LookupMap::new(StorageKey::PendingRequests),
             request_counter: 0,
             proposed_updates: ProposedUpdates::default(),
             config: config.unwrap_or_default(),
+            pending_requests_index: UnorderedMap::new(StorageKey::PendingRequestsIndex),
+            key_version_pins: LookupMap::new(StorageKey::KeyVersionPins),
+            sign_stats: SignStats::default(),
+            heartbeats: LookupMap::new(StorageKey::Heartbeats),
+            duplicate_requests: LookupMap::new(StorageKey::DuplicateRequests),
+            capacities: LookupMap::new(StorageKey::Capacities),
+            request_deadlines: LookupMap::new(StorageKey::RequestDeadlines),
+            treasury: Treasury::new(),
+            sign_pause: SignPause::default(),
+            signature_cache: SignatureCache::new(),
+            expire_cursor: 0,
+            clean_cursor: 0,
+            sign_access: SignAccessConfig::default(),
+            key_versions: KeyVersionConfig::default(),
         }))
     }
 
     /// This will be called internally by the contract to migrate the state when a new contract
-    /// is deployed. This function should be changed every time state is changed to do the proper
-    /// migrate flow.
+    /// is deployed. Every time the state layout changes, the previous layout should be added to
+    /// the [`migration`] module so that it can be upgraded here.
     ///
     /// If nothing is changed, then this function will just return the current state. If it fails
     /// to read the state, then it will return an error.
@@ -661,8 +1778,8 @@ impl VersionedMpcContract {
     #[init(ignore_state)]
     #[handle_result]
     pub fn migrate() -> Result<Self, Error> {
-        let old: MpcContract = env::state_read().ok_or(InvalidState::ContractStateIsMissing)?;
-        Ok(VersionedMpcContract::V0(old))
+        let state = env::storage_read(b"STATE").ok_or(InvalidState::ContractStateIsMissing)?;
+        migration::migrate_state(&state)
     }
 
     pub fn state(&self) -> &ProtocolContractState {
@@ -700,7 +1817,14 @@ impl VersionedMpcContract {
                     .try_into()
                     .expect("conversion to CryptoHash failed");
 
-                mpc_contract.add_request(&contract_signature_request.request, data_id);
+                if contract_signature_request.duplicate {
+                    // A duplicate whose request got resolved in the meantime is left to time
+                    // out, which refunds its whole deposit.
+                    mpc_contract
+                        .add_duplicate_request(&contract_signature_request.request, data_id);
+                } else {
+                    mpc_contract.add_request(&contract_signature_request.request, data_id);
+                }
 
                 // NOTE: there's another promise after the clear_state_on_finish to avoid any errors
                 // that would rollback the state.
@@ -736,6 +1860,25 @@ impl VersionedMpcContract {
         }
     }
 
+    /// Collects the results of every `sign_helper` promise created by [`Self::sign_batch`]. A
+    /// failed request does not fail the whole batch, but is returned as an error in its slot.
+    #[private]
+    pub fn return_signatures_on_finish(
+        &mut self,
+    ) -> Vec<SignatureResult<SignatureResponse, SignaturePromiseError>> {
+        (0..env::promise_results_count())
+            .map(|i| match env::promise_result(i) {
+                PromiseResult::Successful(data) => {
+                    match serde_json::from_slice::<SignatureResponse>(&data) {
+                        Ok(signature) => SignatureResult::Ok(signature),
+                        Err(_) => SignatureResult::Err(SignaturePromiseError::Failed),
+                    }
+                }
+                PromiseResult::Failed => SignatureResult::Err(SignaturePromiseError::Failed),
+            })
+            .collect()
+    }
+
     fn refund_on_fail(request: &ContractSignatureRequest) {
         let amount = request.deposit;
         let to = request.requester.clone();
@@ -760,27 +1903,80 @@ impl VersionedMpcContract {
     pub fn clear_state_on_finish(
         &mut self,
         contract_signature_request: ContractSignatureRequest,
-        #[callback_result] signature: Result<SignatureResponse, PromiseError>,
+        #[callback_result] signature: Result<SignatureResume, PromiseError>,
     ) -> Result<SignatureResult<SignatureResponse, SignaturePromiseError>, Error> {
         match self {
             Self::V0(mpc_contract) => {
-                // Clean up the local state
-                let result =
-                    mpc_contract.remove_request(contract_signature_request.request.clone());
-                if result.is_err() {
-                    // refund must happen in clear_state_on_finish, because regardless of this success or fail
-                    // the promise created by clear_state_on_finish is executed, because of callback_unwrap and
-                    // promise_then. but if return_signature_on_finish fail (returns error), the promise created
-                    // by it won't execute.
-                    Self::refund_on_fail(&contract_signature_request);
-                    result?;
+                let ContractSignatureRequest {
+                    request,
+                    requester,
+                    duplicate,
+                    ..
+                } = contract_signature_request.clone();
+                // Duplicates only get resolved by the request they duplicate, which owns the
+                // local state.
+                let mut submitted_at = None;
+                if !duplicate {
+                    submitted_at = mpc_contract
+                        .pending_requests_index
+                        .get(&request)
+                        .map(|pending| pending.timestamp);
+                    // Clean up the local state
+                    let result = mpc_contract.remove_request(request.clone());
+                    if result.is_err() {
+                        // The request is only gone already if `clean_requests` removed it, which
+                        // settled its deposit: part of it may have been paid out as the bounty,
+                        // so refunding it here would pay the deposit out twice.
+                        log!("request was cleaned before it resolved, skipping the refund");
+                        result?;
+                    }
+                    let resume = match &signature {
+                        Ok(resume) => resume.clone(),
+                        Err(_) => SignatureResume::Expired {
+                            expired_at_block: env::block_height(),
+                        },
+                    };
+                    mpc_contract.resume_duplicate_requests(&request, &resume);
                 }
                 match signature {
-                    Ok(signature) => {
+                    Ok(SignatureResume::Signature(signature)) => {
+                        if let Some(submitted_at) = submitted_at {
+                            mpc_contract
+                                .sign_stats
+                                .record(submitted_at, env::block_timestamp());
+                        }
+                        Event::SignatureCompleted(vec![SignatureCompleted {
+                            request,
+                            requester,
+                            signature: signature.clone(),
+                        }])
+                        .emit();
+                        // Whatever is not refunded is the fee, which goes to the nodes.
+                        let fee = contract_signature_request
+                            .deposit
+                            .min(contract_signature_request.required_deposit);
+                        mpc_contract.treasury.deposit(fee.as_yoctonear());
                         Self::refund_on_success(&contract_signature_request);
                         Ok(SignatureResult::Ok(signature))
                     }
+                    Ok(SignatureResume::Expired { expired_at_block }) => {
+                        log!("sign request expired at block {expired_at_block}");
+                        Event::SignatureTimedOut(vec![SignatureTimedOut {
+                            request,
+                            requester,
+                            expired_at_block: Some(expired_at_block),
+                        }])
+                        .emit();
+                        Self::refund_on_fail(&contract_signature_request);
+                        Ok(SignatureResult::Err(SignaturePromiseError::Expired))
+                    }
                     Err(_) => {
+                        Event::SignatureTimedOut(vec![SignatureTimedOut {
+                            request,
+                            requester,
+                            expired_at_block: None,
+                        }])
+                        .emit();
                         Self::refund_on_fail(&contract_signature_request);
                         Ok(SignatureResult::Err(SignaturePromiseError::Failed))
                     }
@@ -798,24 +1994,202 @@ impl VersionedMpcContract {
         }
     }
 
+    /// Makes the next key version the latest one, once the nodes hold it. The current latest
+    /// version keeps being served for `deprecation_blocks` more blocks, so that callers have
+    /// time to move over to the new version.
+    #[private]
+    pub fn rotate_key_version(&mut self, deprecation_blocks: u64) -> u32 {
+        match self {
+            Self::V0(mpc_contract) => {
+                let key_versions = &mut mpc_contract.key_versions;
+                let deprecated = key_versions.latest;
+                let retired_at = env::block_height().saturating_add(deprecation_blocks);
+                key_versions.rotate(retired_at);
+                let latest = key_versions.latest;
+                Event::KeyVersionRotated(vec![KeyVersionRotated {
+                    latest,
+                    deprecated,
+                    retired_at,
+                }])
+                .emit();
+                latest
+            }
+        }
+    }
+
+    /// Sets whether the access list is an allowlist or a denylist of sign callers, or turns
+    /// access control off with [`SignAccessMode::Open`]. The listed accounts are kept.
+    #[private]
+    pub fn set_sign_access_mode(&mut self, mode: SignAccessMode) {
+        self.update_sign_access(|sign_access| {
+            sign_access.mode = mode;
+            (Vec::new(), Vec::new())
+        });
+    }
+
+    #[private]
+    pub fn add_sign_access_accounts(&mut self, accounts: Vec<AccountId>) {
+        self.update_sign_access(|sign_access| {
+            let added = accounts
+                .into_iter()
+                .filter(|account_id| sign_access.accounts.insert(account_id.clone()))
+                .collect();
+            (added, Vec::new())
+        });
+    }
+
+    #[private]
+    pub fn remove_sign_access_accounts(&mut self, accounts: Vec<AccountId>) {
+        self.update_sign_access(|sign_access| {
+            let removed = accounts
+                .into_iter()
+                .filter(|account_id| sign_access.accounts.remove(account_id))
+                .collect();
+            (Vec::new(), removed)
+        });
+    }
+
+    /// Applies `update` to the access list and emits an event with the accounts it added
+    /// and removed.
+    fn update_sign_access(
+        &mut self,
+        update: impl FnOnce(&mut SignAccessConfig) -> (Vec<AccountId>, Vec<AccountId>),
+    ) {
+        match self {
+            Self::V0(mpc_contract) => {
+                let (added, removed) = update(&mut mpc_contract.sign_access);
+                Event::SignAccessUpdated(vec![SignAccessUpdated {
+                    mode: mpc_contract.sign_access.mode,
+                    added,
+                    removed,
+                }])
+                .emit();
+            }
+        }
+    }
+
+    fn check_sign_access(&self) -> Result<(), Error> {
+        let Self::V0(mpc_contract) = self;
+        if mpc_contract.sign_pause.is_paused() {
+            return Err(SignError::Paused.into());
+        }
+        if !mpc_contract
+            .sign_access
+            .is_allowed(&env::predecessor_account_id())
+        {
+            return Err(SignError::CallerNotAllowed.into());
+        }
+        Ok(())
+    }
+
     fn mutable_state(&mut self) -> &mut ProtocolContractState {
         match self {
             Self::V0(ref mut mpc_contract) => &mut mpc_contract.protocol_state,
         }
     }
 
+    /// Checks the parts of a [`SignRequest`] that do not depend on the attached deposit or gas,
+    /// returning the payload as a [`Scalar`] when valid, hashed from the message of the request
+    /// if it has one. The path, payload and message are checked against the limits of the
+    /// `sign_limits` config entry.
+    fn validate_sign_request(
+        &self,
+        request: &SignRequest,
+        format: PayloadFormat,
+    ) -> Result<Scalar, Error> {
+        let sign_limits = self.config().sign_limits();
+        // A full message is hashed into the payload, and then checked like any other payload.
+        let (format, payload) = match &request.message {
+            Some(message) => {
+                if request.payload != [0; 32] {
+                    return Err(InvalidParameters::MalformedPayload
+                        .message("Only one of payload and message can be set"));
+                }
+                if message.data.len() > sign_limits.max_message_len as usize {
+                    return Err(InvalidParameters::MalformedPayload.message(format!(
+                        "Message is {} bytes long, maximum {}",
+                        message.data.len(),
+                        sign_limits.max_message_len
+                    )));
+                }
+                (PayloadFormat::Message, message.digest())
+            }
+            None => (format, request.payload),
+        };
+        if !sign_limits.allows(format) {
+            return Err(InvalidParameters::PayloadFormatNotAllowed
+                .message(format!("{format:?} payloads are not allowed")));
+        }
+        let payload = Scalar::from_bytes(payload).ok_or(
+            InvalidParameters::MalformedPayload
+                .message("Payload hash cannot be convereted to Scalar"),
+        )?;
+        crypto_shared::derivation_path::validate_path_with_max_len(
+            &request.path,
+            sign_limits.max_path_len as usize,
+        )
+        .map_err(|err| InvalidParameters::MalformedPath.message(err.to_string()))?;
+        let key_versions = self.key_versions();
+        if request.key_version > key_versions.latest {
+            return Err(SignError::UnsupportedKeyVersion.into());
+        }
+        if !key_versions.is_served(request.key_version, env::block_height()) {
+            return Err(SignError::KeyVersionRetired.into());
+        }
+        let pinned = self.pinned_key_version(env::predecessor_account_id());
+        if pinned.map_or(false, |pinned| pinned != request.key_version) {
+            return Err(SignError::KeyVersionMismatch.into());
+        }
+        if let Some(max_wait_blocks) = request.max_wait_blocks {
+            let ttl_blocks = self.config().sign_request().ttl_blocks;
+            if max_wait_blocks == 0 || max_wait_blocks > ttl_blocks {
+                return Err(InvalidParameters::InvalidMaxWaitBlocks.message(format!(
+                    "Provided {max_wait_blocks}, must be between 1 and {ttl_blocks}"
+                )));
+            }
+        }
+        Ok(payload)
+    }
+
+    fn sign_request_ordering(&self) -> SignRequestOrdering {
+        match self {
+            Self::V0(mpc_contract) => mpc_contract.config.sign_request().ordering,
+        }
+    }
+
     fn request_already_exists(&self, request: &SignatureRequest) -> bool {
         match self {
             Self::V0(mpc_contract) => mpc_contract.pending_requests.contains_key(request),
         }
     }
 
-    fn mark_request_received(&mut self, request: &SignatureRequest) {
+    fn mark_request_received(
+        &mut self,
+        request: &SignatureRequest,
+        requester: &AccountId,
+        deposit: u128,
+        max_wait_blocks: Option<u64>,
+    ) {
         match self {
-            Self::V0(ref mut mpc_contract) => mpc_contract.mark_request_received(request),
+            Self::V0(ref mut mpc_contract) => {
+                mpc_contract.mark_request_received(request, requester, deposit, max_wait_blocks)
+            }
         }
     }
 
+    fn derive_public_key(
+        &self,
+        path: String,
+        predecessor: Option<AccountId>,
+    ) -> Result<crypto_shared::PublicKey, Error> {
+        let predecessor = predecessor.unwrap_or_else(env::predecessor_account_id);
+        let epsilon = derive_epsilon(&predecessor, &path);
+        Ok(derive_key(
+            near_public_key_to_affine_point(self.public_key()?),
+            epsilon,
+        ))
+    }
+
     fn threshold(&self) -> Result<usize, Error> {
         match self {
             Self::V0(contract) => match &contract.protocol_state {
@@ -836,6 +2210,32 @@ impl VersionedMpcContract {
         }
     }
 
+    fn vote_sign_pause(&mut self, pause: bool) -> Result<bool, Error> {
+        let voter = self.voter()?;
+        let Self::V0(mpc_contract) = self;
+        let Some(participants) = mpc_contract.protocol_state.current_participants() else {
+            return Err(
+                InvalidState::UnexpectedProtocolState.message(mpc_contract.protocol_state.name())
+            );
+        };
+        if mpc_contract.sign_pause.is_paused() == pause {
+            return Err(if pause {
+                VoteError::AlreadyPaused
+            } else {
+                VoteError::NotPaused
+            }
+            .into());
+        }
+        if !mpc_contract
+            .sign_pause
+            .vote(voter, participants, env::block_timestamp())
+        {
+            return Ok(false);
+        }
+        Event::SignPauseUpdated(vec![SignPauseUpdated { paused: pause }]).emit();
+        Ok(true)
+    }
+
     /// Get our own account id as a voter. Check to see if we are a participant in the protocol.
     /// If we are not a participant, return an error.
     fn voter(&self) -> Result<AccountId, Error> {
@@ -866,3 +2266,12 @@ impl VersionedMpcContract {
         Ok(voter)
     }
 }
+
+/// Encodes a derived key the way NEAR encodes secp256k1 public keys, which is without the SEC1
+/// tag byte of the uncompressed point.
+fn to_near_public_key(public_key: &crypto_shared::PublicKey) -> Result<PublicKey, Error> {
+    let encoded_point = public_key.to_encoded_point(false);
+    let mut data: Vec<u8> = vec![near_sdk::CurveType::SECP256K1 as u8];
+    data.extend_from_slice(&encoded_point.as_bytes()[1..65]);
+    PublicKey::try_from(data).map_err(|_| PublicKeyError::DerivedKeyConversionFailed.into())
+}
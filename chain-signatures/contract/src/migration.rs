@@ -0,0 +1,153 @@
+//! Upgrades the contract state written by the previous release to the current layout. The
+//! types the previous release stored are frozen in a module of their own, along with a
+//! conversion into the current types.
+//!
+//! Only released layouts need to be readable: when cutting a release that changes the layout
+//! of [`VersionedMpcContract`], replace the frozen types with the ones of the release being
+//! upgraded from, and convert them into the new layout directly.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+
+use crate::errors::{ConversionError, Error};
+use crate::VersionedMpcContract;
+
+/// Reads the contract state from its borsh representation, upgrading it from the layout of the
+/// previous release if needed.
+pub fn migrate_state(state: &[u8]) -> Result<VersionedMpcContract, Error> {
+    if let Ok(contract) = VersionedMpcContract::try_from_slice(state) {
+        return Ok(contract);
+    }
+    if let Ok(contract) = rc5::VersionedMpcContract::try_from_slice(state) {
+        return Ok(contract.into());
+    }
+    Err(ConversionError::DataConversion.into())
+}
+
+/// Layout of the 1.0.0-rc.5 release.
+pub mod rc5 {
+    use near_sdk::collections::{LookupMap, UnorderedMap};
+    use near_sdk::{env, AccountId, PublicKey};
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::config::{Config, KeyVersionConfig, SignAccessConfig};
+    use crate::primitives::{
+        Candidates, ParticipantSetVotes, Participants, SignPause, SignStats, SignatureCache,
+        SignatureRequest, StorageKey, ThresholdVotes, Treasury, Votes, YieldIndex,
+    };
+    use crate::state::{self, InitializingContractState};
+    use crate::update::ProposedUpdates;
+
+    #[derive(BorshDeserialize, BorshSerialize, Debug)]
+    pub struct RunningContractState {
+        pub epoch: u64,
+        pub participants: Participants,
+        pub threshold: usize,
+        pub public_key: PublicKey,
+        pub candidates: Candidates,
+        pub join_votes: Votes,
+        pub leave_votes: Votes,
+    }
+
+    #[derive(BorshDeserialize, BorshSerialize, Debug)]
+    pub struct ResharingContractState {
+        pub old_epoch: u64,
+        pub old_participants: Participants,
+        pub new_participants: Participants,
+        pub threshold: usize,
+        pub public_key: PublicKey,
+        pub finished_votes: HashSet<AccountId>,
+    }
+
+    #[derive(BorshDeserialize, BorshSerialize, Debug)]
+    pub enum ProtocolContractState {
+        NotInitialized,
+        Initializing(InitializingContractState),
+        Running(RunningContractState),
+        Resharing(ResharingContractState),
+    }
+
+    #[derive(BorshDeserialize, BorshSerialize, Debug)]
+    pub struct MpcContract {
+        pub protocol_state: ProtocolContractState,
+        pub pending_requests: LookupMap<SignatureRequest, Option<YieldIndex>>,
+        pub request_counter: u32,
+        pub proposed_updates: ProposedUpdates,
+        pub config: Config,
+    }
+
+    #[derive(BorshDeserialize, BorshSerialize, Debug)]
+    pub enum VersionedMpcContract {
+        V0(MpcContract),
+    }
+
+    impl From<ProtocolContractState> for state::ProtocolContractState {
+        fn from(state: ProtocolContractState) -> Self {
+            match state {
+                ProtocolContractState::NotInitialized => Self::NotInitialized,
+                ProtocolContractState::Initializing(state) => Self::Initializing(state),
+                // The key shares of the running participants are taken to be fresh as of the
+                // migration, so that proactive resharing does not kick in right after it.
+                ProtocolContractState::Running(state) => {
+                    Self::Running(state::RunningContractState {
+                        epoch: state.epoch,
+                        participants: state.participants,
+                        threshold: state.threshold,
+                        public_key: state.public_key,
+                        candidates: state.candidates,
+                        join_votes: state.join_votes,
+                        leave_votes: state.leave_votes,
+                        new_participants_votes: ParticipantSetVotes::new(),
+                        threshold_votes: ThresholdVotes::new(),
+                        shares_epoch_height: env::epoch_height(),
+                    })
+                }
+                ProtocolContractState::Resharing(state) => {
+                    Self::Resharing(state::ResharingContractState {
+                        old_epoch: state.old_epoch,
+                        old_participants: state.old_participants,
+                        new_participants: state.new_participants,
+                        threshold: state.threshold,
+                        public_key: state.public_key,
+                        finished_votes: state.finished_votes,
+                        old_threshold: None,
+                    })
+                }
+            }
+        }
+    }
+
+    impl From<VersionedMpcContract> for crate::VersionedMpcContract {
+        fn from(contract: VersionedMpcContract) -> Self {
+            let VersionedMpcContract::V0(old) = contract;
+            // Requests that are in flight during the migration keep their yield indices, so
+            // they can still be responded to. The previous release kept them in a `LookupMap`,
+            // which can not be iterated, so they can not be added to the index: they do not
+            // show up in `get_pending_requests` and are not expired by `expire_requests`, but
+            // still resolve through their yield callback, with a timeout at the latest.
+            // Signatures produced before the migration were not credited to any node, so the
+            // treasury starts out empty.
+            Self::V0(crate::MpcContract {
+                protocol_state: old.protocol_state.into(),
+                pending_requests: old.pending_requests,
+                request_counter: old.request_counter,
+                proposed_updates: old.proposed_updates,
+                config: old.config,
+                pending_requests_index: UnorderedMap::new(StorageKey::PendingRequestsIndex),
+                key_version_pins: LookupMap::new(StorageKey::KeyVersionPins),
+                sign_stats: SignStats::default(),
+                heartbeats: LookupMap::new(StorageKey::Heartbeats),
+                duplicate_requests: LookupMap::new(StorageKey::DuplicateRequests),
+                capacities: LookupMap::new(StorageKey::Capacities),
+                request_deadlines: LookupMap::new(StorageKey::RequestDeadlines),
+                treasury: Treasury::new(),
+                sign_pause: SignPause::default(),
+                signature_cache: SignatureCache::new(),
+                expire_cursor: 0,
+                clean_cursor: 0,
+                sign_access: SignAccessConfig::default(),
+                key_versions: KeyVersionConfig::default(),
+            })
+        }
+    }
+}
@@ -1,9 +1,14 @@
-use crypto_shared::{derive_epsilon, SerializableScalar};
+use crypto_shared::eip712::Eip712Domain;
+use crypto_shared::{derive_epsilon, SerializableScalar, SignMessage, SignatureResponse};
 use k256::Scalar;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{AccountId, BorshStorageKey, CryptoHash, NearToken, PublicKey};
-use std::collections::{BTreeMap, HashMap, HashSet};
+use near_sdk::{env, AccountId, BorshStorageKey, CryptoHash, NearToken, PublicKey};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use crate::config::SignatureCacheConfig;
 
 pub mod hpke {
     pub type PublicKey = [u8; 32];
@@ -14,6 +19,15 @@ pub mod hpke {
 pub enum StorageKey {
     PendingRequests,
     ProposedUpdatesEntries,
+    PendingRequestsIndex,
+    KeyVersionPins,
+    Heartbeats,
+    DuplicateRequests,
+    Capacities,
+    RequestDeadlines,
+    RewardSignatures,
+    CachedSignatures,
+    CachedSignatureOrder,
 }
 
 /// The index into calling the YieldResume feature of NEAR. This will allow to resume
@@ -38,6 +52,406 @@ pub struct ContractSignatureRequest {
     pub requester: AccountId,
     pub deposit: NearToken,
     pub required_deposit: NearToken,
+    /// Whether the request duplicates one that was already pending when it got submitted, in
+    /// which case it gets resolved along with that request instead of being signed on its own.
+    #[serde(default)]
+    pub duplicate: bool,
+}
+
+/// A sign request that is still waiting to be either responded to or timed out.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct PendingRequest {
+    pub request: SignatureRequest,
+    pub requester: AccountId,
+    /// Timestamp in nanoseconds of the block the request was submitted in.
+    pub timestamp: u64,
+    pub block_height: u64,
+    /// Deposit attached for the request, which the contract holds until the request resolves.
+    #[serde(default)]
+    pub deposit: U128,
+}
+
+/// A sign request that got its signature, kept to estimate how long new requests take.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct CompletedRequest {
+    /// Timestamp in nanoseconds of the block the signature was delivered in.
+    pub completed_at: u64,
+    /// Nanoseconds between the submission of the request and the delivery of its signature.
+    pub latency: u64,
+}
+
+/// The most recently signed requests, which the estimates of the `estimate_sign` view are
+/// based on.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, Default, PartialEq, Eq)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct SignStats {
+    recent: VecDeque<CompletedRequest>,
+}
+
+impl SignStats {
+    /// Amount of signed requests that the estimates are based on.
+    pub const WINDOW: usize = 32;
+
+    pub fn record(&mut self, submitted_at: u64, completed_at: u64) {
+        if self.recent.len() == Self::WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(CompletedRequest {
+            completed_at,
+            latency: completed_at.saturating_sub(submitted_at),
+        });
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.recent.len() as u32
+    }
+
+    /// Estimated nanoseconds until a request submitted now gets its signature, with
+    /// `queue_depth` requests already waiting. This is the mean latency of the recent requests,
+    /// or the time it takes to work through the queue at the recent throughput when that is
+    /// longer. There is no estimate before any request got signed.
+    pub fn estimate_latency(&self, queue_depth: u32) -> Option<u64> {
+        let (first, last) = (self.recent.front()?, self.recent.back()?);
+        let samples = self.recent.len() as u64;
+        let mean_latency = self.recent.iter().map(|c| c.latency).sum::<u64>() / samples;
+        let drain = if samples > 1 {
+            let interval = last.completed_at.saturating_sub(first.completed_at) / (samples - 1);
+            interval.saturating_mul(queue_depth as u64)
+        } else {
+            0
+        };
+        Some(mean_latency.max(drain))
+    }
+}
+
+/// The last time a node pinged the contract to report that it is up.
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq,
+)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct Heartbeat {
+    pub block_height: u64,
+    /// Timestamp in nanoseconds of the block the ping was made in.
+    pub timestamp: u64,
+}
+
+/// Presignatures a node reported to have available through `report_capacity`.
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq,
+)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct NodeCapacity {
+    pub presignatures: u32,
+    /// Block the capacity was reported in.
+    pub block_height: u64,
+}
+
+/// Fees of the signed requests, owed to the nodes that produced the signatures. A node is owed
+/// a share of the balance proportional to the signatures it helped produce since it last
+/// claimed, so the amount a node gets does not depend on the order in which the nodes claim.
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct Treasury {
+    /// Fees in yoctoNEAR that were not claimed yet.
+    balance: u128,
+    /// Signatures of all the nodes that were not claimed yet.
+    signatures: u64,
+    node_signatures: LookupMap<AccountId, u64>,
+}
+
+impl Default for Treasury {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Treasury {
+    pub fn new() -> Self {
+        Self {
+            balance: 0,
+            signatures: 0,
+            node_signatures: LookupMap::new(StorageKey::RewardSignatures),
+        }
+    }
+
+    pub fn deposit(&mut self, amount: u128) {
+        self.balance = self.balance.saturating_add(amount);
+    }
+
+    /// Counts a signature that `node` helped produce.
+    pub fn record_signature(&mut self, node: &AccountId) {
+        let signatures = self.node_signatures.get(node).unwrap_or_default();
+        self.node_signatures.insert(node, &(signatures + 1));
+        self.signatures += 1;
+    }
+
+    pub fn rewards(&self, node: &AccountId) -> NodeRewards {
+        let signatures = self.node_signatures.get(node).unwrap_or_default();
+        NodeRewards {
+            signatures,
+            amount: self.share(signatures).into(),
+        }
+    }
+
+    /// Takes the share of `node` out of the treasury, returning it along with the signatures it
+    /// was paid for. Signatures that would not pay anything yet are kept for a later claim.
+    pub fn claim(&mut self, node: &AccountId) -> NodeRewards {
+        let signatures = self.node_signatures.get(node).unwrap_or_default();
+        let amount = self.share(signatures);
+        if amount == 0 {
+            return NodeRewards::default();
+        }
+        self.node_signatures.remove(node);
+        self.balance -= amount;
+        self.signatures -= signatures;
+        NodeRewards {
+            signatures,
+            amount: amount.into(),
+        }
+    }
+
+    pub fn view(&self) -> TreasuryView {
+        TreasuryView {
+            balance: self.balance.into(),
+            signatures: self.signatures,
+        }
+    }
+
+    fn share(&self, signatures: u64) -> u128 {
+        if self.signatures == 0 {
+            return 0;
+        }
+        let (signatures, total) = (signatures as u128, self.signatures as u128);
+        // Rounded down, the remainder stays in the treasury for the next claims.
+        match self.balance.checked_mul(signatures) {
+            Some(product) => product / total,
+            None => self.balance / total * signatures,
+        }
+    }
+}
+
+/// The signatures a node helped produce since it last claimed its rewards, and the fees it is
+/// owed for them, returned by the `rewards` view and `claim_rewards`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeRewards {
+    pub signatures: u64,
+    /// Amount in yoctoNEAR.
+    pub amount: U128,
+}
+
+/// Fees held by the contract for the nodes, returned by the `treasury` view.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreasuryView {
+    /// Fees in yoctoNEAR that were not claimed yet.
+    pub balance: U128,
+    /// Signatures that the fees are split by.
+    pub signatures: u64,
+}
+
+/// Signatures of the completed requests by their request id, kept for the `get_signature` view
+/// until they are past the retention of the `signature_cache` config. Signatures are dropped in
+/// the order they got cached.
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct SignatureCache {
+    signatures: LookupMap<CryptoHash, CachedSignature>,
+    /// Ids of the requests in the order their signatures got cached.
+    order: LookupMap<u64, CryptoHash>,
+    /// Position in `order` of the oldest signature that was not dropped yet.
+    first: u64,
+    /// Position in `order` of the next signature to cache.
+    next: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+#[borsh(crate = "near_sdk::borsh")]
+struct CachedSignature {
+    signature: SignatureResponse,
+    /// Block the request got signed in.
+    block_height: u64,
+}
+
+impl Default for SignatureCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SignatureCache {
+    pub fn new() -> Self {
+        Self {
+            signatures: LookupMap::new(StorageKey::CachedSignatures),
+            order: LookupMap::new(StorageKey::CachedSignatureOrder),
+            first: 0,
+            next: 0,
+        }
+    }
+
+    pub fn insert(
+        &mut self,
+        request_id: &CryptoHash,
+        signature: &SignatureResponse,
+        block_height: u64,
+    ) {
+        self.signatures.insert(
+            request_id,
+            &CachedSignature {
+                signature: signature.clone(),
+                block_height,
+            },
+        );
+        self.order.insert(&self.next, request_id);
+        self.next += 1;
+    }
+
+    pub fn get(
+        &self,
+        request_id: &CryptoHash,
+        retention: &SignatureCacheConfig,
+        block_height: u64,
+    ) -> Option<SignatureResponse> {
+        self.signatures
+            .get(request_id)
+            .filter(|cached| retention.is_retained(cached.block_height, block_height))
+            .map(|cached| cached.signature)
+    }
+
+    /// Drops up to `limit` of the oldest signatures that are past their retention, returning
+    /// how many got dropped.
+    pub fn prune(
+        &mut self,
+        retention: &SignatureCacheConfig,
+        block_height: u64,
+        limit: usize,
+    ) -> usize {
+        let mut dropped = 0;
+        while self.first < self.next && dropped < limit {
+            if let Some(request_id) = self.order.get(&self.first) {
+                match self.signatures.get(&request_id) {
+                    // A request that got signed again since is kept until its latest signature
+                    // is past its retention as well.
+                    Some(cached) if retention.is_retained(cached.block_height, block_height) => {
+                        break
+                    }
+                    Some(_) => {
+                        self.signatures.remove(&request_id);
+                    }
+                    None => {}
+                }
+                self.order.remove(&self.first);
+            }
+            self.first += 1;
+            dropped += 1;
+        }
+        dropped
+    }
+}
+
+/// Emergency pause of new sign requests, voted by a supermajority of the participants. The same
+/// votes count towards pausing while running and towards resuming while paused, and are cleared
+/// each time the pause flips.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Default)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct SignPause {
+    /// Timestamp in nanoseconds of the block sign requests got paused in.
+    paused_since: Option<u64>,
+    votes: BTreeSet<AccountId>,
+}
+
+impl SignPause {
+    pub fn is_paused(&self) -> bool {
+        self.paused_since.is_some()
+    }
+
+    /// Votes needed out of `participants` to flip the pause, which is two thirds rounded up.
+    pub fn required_votes(participants: usize) -> usize {
+        (2 * participants).div_ceil(3)
+    }
+
+    /// Counts the vote of `voter` to flip the pause, returning true if it flipped. Votes of
+    /// accounts that are no longer in `participants` are not counted.
+    pub fn vote(&mut self, voter: AccountId, participants: &Participants, now: u64) -> bool {
+        self.votes.insert(voter);
+        self.votes
+            .retain(|account_id| participants.contains_key(account_id));
+        if self.votes.len() < Self::required_votes(participants.len()) {
+            return false;
+        }
+        self.votes.clear();
+        self.paused_since = match self.paused_since {
+            Some(_) => None,
+            None => Some(now),
+        };
+        true
+    }
+
+    pub fn view(&self, participants: &Participants) -> SignPauseView {
+        SignPauseView {
+            paused: self.is_paused(),
+            paused_since: self.paused_since,
+            votes: self
+                .votes
+                .iter()
+                .filter(|account_id| participants.contains_key(account_id))
+                .cloned()
+                .collect(),
+            required_votes: Self::required_votes(participants.len()),
+        }
+    }
+}
+
+/// Whether new sign requests are paused and the votes to flip that, returned by the
+/// `sign_pause` view.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignPauseView {
+    pub paused: bool,
+    /// Timestamp in nanoseconds of the block sign requests got paused in.
+    pub paused_since: Option<u64>,
+    /// Participants that voted to pause while running, or to resume while paused.
+    pub votes: BTreeSet<AccountId>,
+    pub required_votes: usize,
+}
+
+/// Whether the network can take more sign requests, returned by the `network_capacity` view.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NetworkCapacity {
+    /// Presignatures the participants with a recent report have available altogether.
+    pub presignatures: u32,
+    /// Participants whose report is recent enough to be taken into account.
+    pub reporting: u32,
+    /// Sign requests that are waiting for their signature.
+    pub pending_requests: u32,
+    /// Whether `sign` currently rejects new requests.
+    pub overloaded: bool,
+    /// Blocks to wait before retrying while the network is overloaded.
+    pub retry_after_blocks: u64,
+}
+
+/// How recently a node of the protocol was seen, returned by the `participant_heartbeats` view.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ParticipantHeartbeat {
+    pub account_id: AccountId,
+    /// The last ping of the node, or `null` if it never pinged.
+    pub last_seen: Option<Heartbeat>,
+    /// Blocks that passed since the last ping.
+    pub blocks_since_last_seen: Option<u64>,
+}
+
+/// What a sign request submitted right now costs and how long it is expected to take, so that
+/// wallets can show it to their users before they submit it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignEstimate {
+    /// Deposit that the request has to attach.
+    pub deposit: U128,
+    /// Sign requests that are waiting for their signature.
+    pub queue_depth: u32,
+    /// Expected milliseconds until the request gets its signature, or `null` when no request
+    /// got signed recently enough to base it on.
+    pub estimated_latency_ms: Option<u64>,
+    /// Amount of recently signed requests the estimate is based on.
+    pub samples: u32,
 }
 
 impl SignatureRequest {
@@ -52,6 +466,12 @@ impl SignatureRequest {
             payload_hash,
         }
     }
+
+    /// Identifier of the request: the sha256 of its 32 byte epsilon followed by its 32 byte
+    /// payload hash, which is logged as `request_id` in the `signature_requested` event.
+    pub fn id(&self) -> CryptoHash {
+        env::sha256_array(&borsh::to_vec(self).unwrap())
+    }
 }
 
 #[derive(
@@ -170,6 +590,19 @@ impl Participants {
         self.participants.keys()
     }
 
+    /// Whether `id` is the participant id of one of the participants.
+    pub fn contains_id(&self, id: u32) -> bool {
+        self.account_id(id).is_some()
+    }
+
+    /// Account of the participant with the participant id `id`.
+    pub fn account_id(&self, id: u32) -> Option<&AccountId> {
+        self.account_to_participant_id
+            .iter()
+            .find(|(account_id, known)| **known == id && self.contains_key(account_id))
+            .map(|(account_id, _)| account_id)
+    }
+
     pub fn len(&self) -> usize {
         self.participants.len()
     }
@@ -264,11 +697,84 @@ impl PkVotes {
     }
 }
 
+/// Votes for replacing the whole participant set at once. Every voter has a single active
+/// proposal, so voting again replaces the previous proposal of that voter.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Default)]
+pub struct ParticipantSetVotes {
+    pub votes: BTreeMap<AccountId, BTreeSet<AccountId>>,
+}
+
+impl ParticipantSetVotes {
+    pub fn new() -> Self {
+        ParticipantSetVotes {
+            votes: BTreeMap::new(),
+        }
+    }
+
+    /// Records the proposal of `voter` and returns how many voters have proposed the same set.
+    pub fn vote(&mut self, voter: AccountId, proposal: BTreeSet<AccountId>) -> usize {
+        self.votes.insert(voter, proposal.clone());
+        self.votes
+            .values()
+            .filter(|voted| **voted == proposal)
+            .count()
+    }
+}
+
+/// Votes for changing the signing threshold. Every voter has a single active proposal, so
+/// voting again replaces the previous proposal of that voter.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Default)]
+pub struct ThresholdVotes {
+    pub votes: BTreeMap<AccountId, usize>,
+}
+
+impl ThresholdVotes {
+    pub fn new() -> Self {
+        ThresholdVotes {
+            votes: BTreeMap::new(),
+        }
+    }
+
+    /// Records the proposal of `voter` and returns how many voters have proposed the same
+    /// threshold.
+    pub fn vote(&mut self, voter: AccountId, threshold: usize) -> usize {
+        self.votes.insert(voter, threshold);
+        self.votes
+            .values()
+            .filter(|voted| **voted == threshold)
+            .count()
+    }
+}
+
 #[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize, Debug)]
 pub struct SignRequest {
+    /// Hash to sign. Left out, or all zeroes, when `message` is set.
+    #[serde(default)]
     pub payload: [u8; 32],
     pub path: String,
     pub key_version: u32,
+    /// Full message to sign instead of `payload`, which the contract hashes into the payload.
+    #[serde(default)]
+    pub message: Option<SignMessage>,
+    /// Blocks after which the request fails with a timeout if it has not been signed, for
+    /// callers that would rather fail fast than wait. Can be at most the `ttl_blocks` of the
+    /// contract config, which applies when left out. Requests that duplicate a pending one
+    /// resolve along with it instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_wait_blocks: Option<u64>,
+}
+
+/// Request to sign EIP-712 typed data. The signed payload is the digest of the typed data, which
+/// is always signed with secp256k1.
+#[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize, Debug)]
+pub struct SignTypedDataRequest {
+    pub domain: Eip712Domain,
+    /// Domain separator computed by the wallet, which must match the one of `domain`.
+    pub domain_separator: [u8; 32],
+    /// `hashStruct` of the message.
+    pub struct_hash: [u8; 32],
+    pub path: String,
+    pub key_version: u32,
 }
 
 #[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
@@ -280,4 +786,125 @@ pub enum SignatureResult<T, E> {
 #[derive(Serialize, Deserialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub enum SignaturePromiseError {
     Failed,
+    /// The request was not signed within the `ttl_blocks` of the contract config.
+    Expired,
+}
+
+/// Data that a yielded sign request is resumed with. A response resumes it with the bare
+/// signature, so this stays compatible with requests resumed by older versions of the contract.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum SignatureResume {
+    Signature(SignatureResponse),
+    Expired { expired_at_block: u64 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NodeRewards, SignStats, SignatureCache, SignatureRequest, Treasury};
+    use crate::config::SignatureCacheConfig;
+    use crypto_shared::{SerializableAffinePoint, SerializableScalar, SignatureResponse};
+    use k256::{AffinePoint, Scalar};
+
+    #[test]
+    fn test_sign_stats_estimate() {
+        let mut stats = SignStats::default();
+        assert_eq!(stats.estimate_latency(0), None);
+
+        // Requests that took 4s to sign, with one signed every 2s.
+        for i in 0..3 {
+            let completed_at = 10_000_000_000 + i * 2_000_000_000;
+            stats.record(completed_at - 4_000_000_000, completed_at);
+        }
+        assert_eq!(stats.estimate_latency(0), Some(4_000_000_000));
+        assert_eq!(stats.estimate_latency(1), Some(4_000_000_000));
+        // Working through a long queue takes longer than the usual latency.
+        assert_eq!(stats.estimate_latency(5), Some(10_000_000_000));
+
+        for _ in 0..SignStats::WINDOW {
+            stats.record(0, 1_000_000_000);
+        }
+        assert_eq!(stats.samples(), SignStats::WINDOW as u32);
+    }
+
+    #[test]
+    fn test_treasury_shares() {
+        let (a, b) = ("a.near".parse().unwrap(), "b.near".parse().unwrap());
+        let mut treasury = Treasury::new();
+        for _ in 0..3 {
+            treasury.record_signature(&a);
+        }
+        treasury.record_signature(&b);
+        treasury.deposit(1_000);
+        assert_eq!(treasury.rewards(&a).amount.0, 750);
+        assert_eq!(treasury.rewards(&b).amount.0, 250);
+
+        // Claiming first does not change the share of the other node.
+        let claimed = treasury.claim(&b);
+        assert_eq!(
+            claimed,
+            NodeRewards {
+                signatures: 1,
+                amount: 250.into()
+            }
+        );
+        assert_eq!(treasury.rewards(&a).amount.0, 750);
+        assert_eq!(treasury.claim(&b), NodeRewards::default());
+        assert_eq!(treasury.claim(&a).amount.0, 750);
+        assert_eq!(treasury.view().balance.0, 0);
+        assert_eq!(treasury.view().signatures, 0);
+    }
+
+    #[test]
+    fn test_signature_cache_retention() {
+        let request = |i: u64| {
+            SignatureRequest {
+                epsilon: SerializableScalar {
+                    scalar: Scalar::from(i),
+                },
+                payload_hash: SerializableScalar {
+                    scalar: Scalar::from(i),
+                },
+            }
+            .id()
+        };
+        let signature = SignatureResponse {
+            big_r: SerializableAffinePoint {
+                affine_point: AffinePoint::GENERATOR,
+            },
+            s: SerializableScalar {
+                scalar: Scalar::ONE,
+            },
+            recovery_id: 0,
+        };
+        let retention = SignatureCacheConfig {
+            retention_blocks: 10,
+        };
+        let mut cache = SignatureCache::new();
+        cache.insert(&request(0), &signature, 100);
+        cache.insert(&request(1), &signature, 105);
+        assert_eq!(
+            cache.get(&request(0), &retention, 109),
+            Some(signature.clone())
+        );
+        assert_eq!(cache.get(&request(0), &retention, 110), None);
+        assert_eq!(cache.get(&request(2), &retention, 100), None);
+
+        // Only the signatures past their retention get dropped, oldest first.
+        assert_eq!(cache.prune(&retention, 110, 8), 1);
+        assert_eq!(
+            cache.get(&request(1), &retention, 110),
+            Some(signature.clone())
+        );
+
+        // A request signed again is kept along with its latest signature.
+        cache.insert(&request(1), &signature, 112);
+        assert_eq!(cache.prune(&retention, 116, 8), 0);
+        assert_eq!(
+            cache.get(&request(1), &retention, 116),
+            Some(signature.clone())
+        );
+        assert_eq!(cache.prune(&retention, 122, 8), 2);
+        assert_eq!(cache.get(&request(1), &retention, 121), None);
+    }
 }
@@ -1,10 +1,13 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 
 use borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{AccountId, PublicKey};
 
-use crate::primitives::{Candidates, Participants, PkVotes, Votes};
+use crate::primitives::{
+    CandidateInfo, Candidates, ParticipantInfo, ParticipantSetVotes, Participants, PkVotes,
+    ThresholdVotes, Votes,
+};
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug)]
 pub struct InitializingContractState {
@@ -22,6 +25,14 @@ pub struct RunningContractState {
     pub candidates: Candidates,
     pub join_votes: Votes,
     pub leave_votes: Votes,
+    #[serde(default)]
+    pub new_participants_votes: ParticipantSetVotes,
+    #[serde(default)]
+    pub threshold_votes: ThresholdVotes,
+    /// NEAR epoch height at which the participants got their current key shares, either from
+    /// key generation or from the last resharing.
+    #[serde(default)]
+    pub shares_epoch_height: u64,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug)]
@@ -32,6 +43,16 @@ pub struct ResharingContractState {
     pub threshold: usize,
     pub public_key: PublicKey,
     pub finished_votes: HashSet<AccountId>,
+    /// Threshold of the old participants, when the resharing also changes the threshold.
+    #[serde(default)]
+    pub old_threshold: Option<usize>,
+}
+
+impl ResharingContractState {
+    /// The threshold the old participants hold their key shares with.
+    pub fn old_threshold(&self) -> usize {
+        self.old_threshold.unwrap_or(self.threshold)
+    }
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug)]
@@ -51,4 +72,142 @@ impl ProtocolContractState {
             ProtocolContractState::Resharing(_) => "Resharing",
         }
     }
+
+    /// Accounts of the nodes taking part in the protocol: the candidates while initializing, the
+    /// participants while running, and both the old and new participants while resharing.
+    pub fn node_accounts(&self) -> BTreeSet<AccountId> {
+        match self {
+            ProtocolContractState::NotInitialized => BTreeSet::new(),
+            ProtocolContractState::Initializing(state) => state
+                .candidates
+                .iter()
+                .map(|(account_id, _)| account_id.clone())
+                .collect(),
+            ProtocolContractState::Running(state) => state.participants.keys().cloned().collect(),
+            ProtocolContractState::Resharing(state) => state
+                .old_participants
+                .keys()
+                .chain(state.new_participants.keys())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Participants that the protocol currently runs with, which are still the old ones while
+    /// resharing. There are none before the root key got generated.
+    pub fn current_participants(&self) -> Option<&Participants> {
+        match self {
+            ProtocolContractState::Running(state) => Some(&state.participants),
+            ProtocolContractState::Resharing(state) => Some(&state.old_participants),
+            _ => None,
+        }
+    }
+}
+
+/// Overview of the protocol state returned by the `state_details` view. It has the same shape in
+/// every protocol state, so that explorers and dashboards do not depend on how the contract
+/// stores its state. Fields that do not apply to the current state are `null` or empty.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StateDetails {
+    /// One of `NotInitialized`, `Initializing`, `Running` or `Resharing`.
+    pub state: String,
+    /// The current epoch. While resharing, this is the epoch being reshared from.
+    pub epoch: Option<u64>,
+    pub threshold: Option<usize>,
+    pub public_key: Option<PublicKey>,
+    /// The participants currently holding key shares.
+    pub participants: Vec<ParticipantInfo>,
+    /// The participants that will hold the key shares once resharing is finished.
+    pub new_participants: Vec<ParticipantInfo>,
+    pub candidates: Vec<CandidateInfo>,
+    pub votes: VoteDetails,
+}
+
+/// The votes that are currently being cast, keyed by what is being voted for.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct VoteDetails {
+    /// Voters for each candidate to join.
+    pub join: BTreeMap<AccountId, BTreeSet<AccountId>>,
+    /// Voters for each participant to be kicked.
+    pub leave: BTreeMap<AccountId, BTreeSet<AccountId>>,
+    /// Voters for each public key generated during initialization.
+    pub public_key: BTreeMap<PublicKey, BTreeSet<AccountId>>,
+    /// The participant set proposed by each voter.
+    pub new_participants: BTreeMap<AccountId, BTreeSet<AccountId>>,
+    /// The threshold proposed by each voter.
+    pub threshold: BTreeMap<AccountId, usize>,
+    /// Participants that have finished resharing.
+    pub reshared: BTreeSet<AccountId>,
+}
+
+fn voters<K: Ord + Clone>(
+    votes: &BTreeMap<K, HashSet<AccountId>>,
+) -> BTreeMap<K, BTreeSet<AccountId>> {
+    votes
+        .iter()
+        .map(|(key, voters)| (key.clone(), voters.iter().cloned().collect()))
+        .collect()
+}
+
+impl From<&ProtocolContractState> for StateDetails {
+    fn from(state: &ProtocolContractState) -> Self {
+        let mut details = StateDetails {
+            state: state.name().to_string(),
+            epoch: None,
+            threshold: None,
+            public_key: None,
+            participants: Vec::new(),
+            new_participants: Vec::new(),
+            candidates: Vec::new(),
+            votes: VoteDetails::default(),
+        };
+        match state {
+            ProtocolContractState::NotInitialized => {}
+            ProtocolContractState::Initializing(state) => {
+                details.threshold = Some(state.threshold);
+                details.candidates = state
+                    .candidates
+                    .iter()
+                    .map(|(_, info)| info.clone())
+                    .collect();
+                details.votes.public_key = voters(&state.pk_votes.votes);
+            }
+            ProtocolContractState::Running(state) => {
+                details.epoch = Some(state.epoch);
+                details.threshold = Some(state.threshold);
+                details.public_key = Some(state.public_key.clone());
+                details.participants = state
+                    .participants
+                    .iter()
+                    .map(|(_, info)| info.clone())
+                    .collect();
+                details.candidates = state
+                    .candidates
+                    .iter()
+                    .map(|(_, info)| info.clone())
+                    .collect();
+                details.votes.join = voters(&state.join_votes.votes);
+                details.votes.leave = voters(&state.leave_votes.votes);
+                details.votes.new_participants = state.new_participants_votes.votes.clone();
+                details.votes.threshold = state.threshold_votes.votes.clone();
+            }
+            ProtocolContractState::Resharing(state) => {
+                details.epoch = Some(state.old_epoch);
+                details.threshold = Some(state.threshold);
+                details.public_key = Some(state.public_key.clone());
+                details.participants = state
+                    .old_participants
+                    .iter()
+                    .map(|(_, info)| info.clone())
+                    .collect();
+                details.new_participants = state
+                    .new_participants
+                    .iter()
+                    .map(|(_, info)| info.clone())
+                    .collect();
+                details.votes.reshared = state.finished_votes.iter().cloned().collect();
+            }
+        }
+        details
+    }
 }
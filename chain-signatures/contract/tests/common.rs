@@ -24,6 +24,8 @@ use signature::DigestSigner;
 pub const CONTRACT_FILE_PATH: &str =
     "../../target/wasm32-unknown-unknown/release/mpc_contract.wasm";
 pub const INVALID_CONTRACT: &str = "../res/mpc_test_contract.wasm";
+/// Contract of the previous release, built by `res/build_previous_contract.sh`.
+pub const PREVIOUS_CONTRACT_FILE_PATH: &str = "../res/mpc_contract_previous.wasm";
 pub const PARTICIPANT_LEN: usize = 3;
 
 pub fn candidates(names: Option<Vec<AccountId>>) -> HashMap<AccountId, CandidateInfo> {
@@ -67,8 +69,12 @@ pub async fn accounts(
 }
 
 pub async fn init() -> (Worker<Sandbox>, Contract) {
+    init_from(CONTRACT_FILE_PATH).await
+}
+
+pub async fn init_from(contract_file_path: &str) -> (Worker<Sandbox>, Contract) {
     let worker = near_workspaces::sandbox().await.unwrap();
-    let wasm = std::fs::read(CONTRACT_FILE_PATH).unwrap();
+    let wasm = std::fs::read(contract_file_path).unwrap();
     let contract = worker.dev_deploy(&wasm).await.unwrap();
     (worker, contract)
 }
@@ -76,7 +82,14 @@ pub async fn init() -> (Worker<Sandbox>, Contract) {
 pub async fn init_with_candidates(
     pk: Option<near_crypto::PublicKey>,
 ) -> (Worker<Sandbox>, Contract, Vec<Account>) {
-    let (worker, contract) = init().await;
+    init_with_candidates_from(CONTRACT_FILE_PATH, pk).await
+}
+
+pub async fn init_with_candidates_from(
+    contract_file_path: &str,
+    pk: Option<near_crypto::PublicKey>,
+) -> (Worker<Sandbox>, Contract, Vec<Account>) {
+    let (worker, contract) = init_from(contract_file_path).await;
     let (accounts, candidates) = accounts(&worker).await;
 
     let init = if let Some(pk) = pk {
@@ -124,16 +137,24 @@ pub async fn init_with_candidates(
 }
 
 pub async fn init_env() -> (Worker<Sandbox>, Contract, Vec<Account>, k256::SecretKey) {
+    init_env_from(CONTRACT_FILE_PATH).await
+}
+
+pub async fn init_env_from(
+    contract_file_path: &str,
+) -> (Worker<Sandbox>, Contract, Vec<Account>, k256::SecretKey) {
     let sk = k256::SecretKey::random(&mut rand::thread_rng());
     let pk = sk.public_key();
-    let (worker, contract, accounts) =
-        init_with_candidates(Some(near_crypto::PublicKey::SECP256K1(
+    let (worker, contract, accounts) = init_with_candidates_from(
+        contract_file_path,
+        Some(near_crypto::PublicKey::SECP256K1(
             near_crypto::Secp256K1PublicKey::try_from(
                 &pk.as_affine().to_encoded_point(false).as_bytes()[1..65],
             )
             .unwrap(),
-        )))
-        .await;
+        )),
+    )
+    .await;
 
     (worker, contract, accounts, sk)
 }
@@ -235,6 +256,16 @@ pub async fn sign_and_validate(
     dbg!(&execution);
     let execution = execution.into_result()?;
 
+    let logs = execution.logs();
+    assert!(logs
+        .iter()
+        .any(|log| log.starts_with("EVENT_JSON:")
+            && log.contains(r#""event":"signature_requested""#)));
+    if respond.is_some() {
+        assert!(logs.iter().any(|log| log.starts_with("EVENT_JSON:")
+            && log.contains(r#""event":"signature_completed""#)));
+    }
+
     // Finally wait the result:
     let returned_resp: SignatureResponse = execution.json()?;
     if let Some((_, respond_resp)) = respond {
@@ -1,11 +1,17 @@
 pub mod common;
 use common::{candidates, create_response, init, init_env, sign_and_validate};
 
+use mpc_contract::config::{Config, SignAccessConfig, SignAccessMode};
 use mpc_contract::errors;
-use mpc_contract::primitives::{CandidateInfo, SignRequest};
+use mpc_contract::primitives::{
+    CandidateInfo, NetworkCapacity, NodeRewards, PendingRequest, SignEstimate, SignPauseView,
+    SignRequest, SignTypedDataRequest, SignatureResult, TreasuryView,
+};
 use near_workspaces::types::{AccountId, NearToken};
+use near_workspaces::Account;
 
-use crypto_shared::SignatureResponse;
+use crypto_shared::eip712::Eip712Domain;
+use crypto_shared::{HashAlgorithm, SignMessage, SignatureResponse};
 use std::collections::HashMap;
 
 #[tokio::test]
@@ -30,6 +36,8 @@ async fn test_contract_sign_request() -> anyhow::Result<()> {
             payload: payload_hash,
             path: path.into(),
             key_version: 0,
+            message: None,
+            max_wait_blocks: None,
         };
 
         sign_and_validate(&request, Some((&respond_req, &respond_resp)), &contract).await?;
@@ -43,6 +51,8 @@ async fn test_contract_sign_request() -> anyhow::Result<()> {
         payload: payload_hash,
         path: path.into(),
         key_version: 0,
+        message: None,
+        max_wait_blocks: None,
     };
     sign_and_validate(&request, Some((&respond_req, &respond_resp)), &contract).await?;
     sign_and_validate(&request, Some((&respond_req, &respond_resp)), &contract).await?;
@@ -74,6 +84,8 @@ async fn test_contract_sign_success_refund() -> anyhow::Result<()> {
         payload: payload_hash,
         path: path.into(),
         key_version: 0,
+        message: None,
+        max_wait_blocks: None,
     };
 
     let status = alice
@@ -133,6 +145,216 @@ async fn test_contract_sign_success_refund() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_contract_sign_deposit_ordering_keeps_bid() -> anyhow::Result<()> {
+    let (worker, contract, _, sk) = init_env().await;
+    let alice = worker.dev_create_account().await?;
+    let path = "test";
+
+    let mut config = Config::default();
+    config.other.insert(
+        "sign_request".to_string(),
+        serde_json::json!({ "ttl_blocks": 200, "ordering": "deposit" }).into(),
+    );
+    contract
+        .call("update_config")
+        .args_json(serde_json::json!({ "config": config }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(alice.id(), "bid", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+    let balance = alice.view_account().await?.balance;
+    let status = alice
+        .call(contract.id(), "sign")
+        .args_json(serde_json::json!({
+            "request": request,
+        }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
+        .transact_async()
+        .await?;
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    contract
+        .call("respond")
+        .args_json(serde_json::json!({
+            "request": respond_req,
+            "response": respond_resp
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let returned_resp: SignatureResponse = status.await?.into_result()?.json()?;
+    assert_eq!(returned_resp, respond_resp);
+
+    // The whole deposit is the bid, so nothing is refunded.
+    let new_balance = alice.view_account().await?.balance;
+    assert!(
+        balance.as_millinear() - new_balance.as_millinear() >= 1000,
+        "the bid should not be refunded"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_sign_rewards() -> anyhow::Result<()> {
+    let (worker, contract, accounts, sk) = init_env().await;
+    let alice = worker.dev_create_account().await?;
+    let path = "test";
+
+    // The whole bid is kept as the fee, so that it can be split between the nodes.
+    let mut config = Config::default();
+    config.other.insert(
+        "sign_request".to_string(),
+        serde_json::json!({ "ttl_blocks": 200, "ordering": "deposit" }).into(),
+    );
+    contract
+        .call("update_config")
+        .args_json(serde_json::json!({ "config": config }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(alice.id(), "rewards", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+    let status = alice
+        .call(contract.id(), "sign")
+        .args_json(serde_json::json!({
+            "request": request,
+        }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
+        .transact_async()
+        .await?;
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    // ids that are not participants are rejected
+    let execution = accounts[0]
+        .call(contract.id(), "respond")
+        .args_json(serde_json::json!({
+            "request": respond_req,
+            "response": respond_resp,
+            "participants": [0, 7],
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::RespondError::UnknownParticipant.to_string()));
+
+    // a responder can not claim the signature for itself alone
+    let execution = accounts[0]
+        .call(contract.id(), "respond")
+        .args_json(serde_json::json!({
+            "request": respond_req,
+            "response": respond_resp,
+            "participants": [0],
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::RespondError::TooFewParticipants.to_string()));
+
+    // Participant ids follow the order of the account ids. Only the nodes that pinged the
+    // contract recently get credited for the signature, not the one that went offline.
+    let mut nodes = accounts.iter().collect::<Vec<_>>();
+    nodes.sort_by(|a, b| a.id().cmp(b.id()));
+    let (online, offline) = nodes.split_at(2);
+    for node in online {
+        node.call(contract.id(), "ping")
+            .transact()
+            .await?
+            .into_result()?;
+    }
+    let signers = (0..nodes.len() as u32).collect::<Vec<_>>();
+    accounts[0]
+        .call(contract.id(), "respond")
+        .args_json(serde_json::json!({
+            "request": respond_req,
+            "response": respond_resp,
+            "participants": signers,
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    status.await?.into_result()?;
+
+    let fee = NearToken::from_near(1).as_yoctonear();
+    let share = fee / online.len() as u128;
+    let treasury: TreasuryView = contract.view("treasury").await?.json()?;
+    assert_eq!(treasury.balance.0, fee);
+    assert_eq!(treasury.signatures, online.len() as u64);
+
+    for (node, signatures) in online
+        .iter()
+        .map(|node| (node, 1))
+        .chain(offline.iter().map(|node| (node, 0)))
+    {
+        let rewards: NodeRewards = contract
+            .view("rewards")
+            .args_json(serde_json::json!({ "account_id": node.id() }))
+            .await?
+            .json()?;
+        assert_eq!(rewards.signatures, signatures);
+        assert_eq!(rewards.amount.0, share * signatures as u128);
+    }
+
+    let node = online[0];
+    let balance = node.view_account().await?.balance;
+    let claimed: NodeRewards = node
+        .call(contract.id(), "claim_rewards")
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    assert_eq!(claimed.amount.0, share);
+    let new_balance = node.view_account().await?.balance;
+    assert!(
+        new_balance.as_millinear() - balance.as_millinear() >= 320,
+        "the rewards should be paid out"
+    );
+
+    // Claiming again pays nothing, while the other nodes keep their shares.
+    let claimed: NodeRewards = node
+        .call(contract.id(), "claim_rewards")
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    assert_eq!(claimed, NodeRewards::default());
+    let treasury: TreasuryView = contract.view("treasury").await?.json()?;
+    assert_eq!(treasury.balance.0, fee - share);
+    assert_eq!(treasury.signatures, online.len() as u64 - 1);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_contract_sign_fail_refund() -> anyhow::Result<()> {
     let (worker, contract, _, sk) = init_env().await;
@@ -148,6 +370,8 @@ async fn test_contract_sign_fail_refund() -> anyhow::Result<()> {
         payload: payload_hash,
         path: path.into(),
         key_version: 0,
+        message: None,
+        max_wait_blocks: None,
     };
 
     let status = alice
@@ -208,6 +432,8 @@ async fn test_contract_sign_request_deposits() -> anyhow::Result<()> {
         payload: payload_hash,
         path: path.into(),
         key_version: 0,
+        message: None,
+        max_wait_blocks: None,
     };
 
     let status = contract
@@ -250,51 +476,1155 @@ async fn test_contract_sign_request_deposits() -> anyhow::Result<()> {
 }
 
 #[tokio::test]
-async fn test_contract_initialization() -> anyhow::Result<()> {
-    let (_, contract) = init().await;
-    let valid_candidates = candidates(None);
+async fn test_contract_sign_batch() -> anyhow::Result<()> {
+    let (_, contract, _, sk) = init_env().await;
+    let predecessor_id = contract.id();
+    let path = "test";
 
-    // Empty candidates should fail.
-    let candidates: HashMap<AccountId, CandidateInfo> = HashMap::new();
-    let result = contract
-        .call("init")
+    let mut requests = Vec::new();
+    let mut responses = Vec::new();
+    for msg in ["batch 0", "batch 1", "batch 2"] {
+        let (payload_hash, respond_req, respond_resp) =
+            create_response(predecessor_id, msg, path, &sk).await;
+        requests.push(SignRequest {
+            payload: payload_hash,
+            path: path.into(),
+            key_version: 0,
+            message: None,
+            max_wait_blocks: None,
+        });
+        responses.push((respond_req, respond_resp));
+    }
+
+    let status = contract
+        .call("sign_batch")
         .args_json(serde_json::json!({
-            "threshold": 2,
-            "candidates": candidates
+            "requests": requests,
+        }))
+        .deposit(NearToken::from_yoctonear(3))
+        .max_gas()
+        .transact_async()
+        .await?;
+    dbg!(&status);
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    // Only respond to the first two requests, the last one should time out on its own.
+    for (respond_req, respond_resp) in &responses[..2] {
+        let respond = contract
+            .call("respond")
+            .args_json(serde_json::json!({
+                "request": respond_req,
+                "response": respond_resp
+            }))
+            .max_gas()
+            .transact()
+            .await?;
+        dbg!(&respond);
+    }
+
+    let execution = status.await?;
+    dbg!(&execution);
+    let results: Vec<SignatureResult<SignatureResponse, serde_json::Value>> =
+        execution.into_result()?.json()?;
+    assert_eq!(results.len(), 3);
+    for (result, (_, respond_resp)) in results.iter().zip(&responses).take(2) {
+        match result {
+            SignatureResult::Ok(signature) => assert_eq!(signature, respond_resp),
+            SignatureResult::Err(err) => panic!("expected signature, got {err:?}"),
+        }
+    }
+    assert!(matches!(results[2], SignatureResult::Err(_)));
+
+    // A batch over the maximum size should be rejected outright.
+    let oversized = (0..=mpc_contract::MAX_SIGN_BATCH_SIZE)
+        .map(|i| SignRequest {
+            payload: [i as u8 + 1; 32],
+            path: path.into(),
+            key_version: 0,
+            message: None,
+            max_wait_blocks: None,
+        })
+        .collect::<Vec<_>>();
+    let execution = contract
+        .call("sign_batch")
+        .args_json(serde_json::json!({
+            "requests": oversized,
         }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
         .transact()
         .await?;
-    assert!(
-        result.is_failure(),
-        "initializing with zero candidates or less than threshold candidates should fail"
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::InvalidParameters::InvalidBatchSize.to_string()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_sign_malformed_path() -> anyhow::Result<()> {
+    let (_, contract, _, sk) = init_env().await;
+    let predecessor_id = contract.id();
+
+    for path in [
+        "/test",
+        "test//path",
+        "with space",
+        "ünïcode",
+        "a".repeat(257).as_str(),
+    ] {
+        let (payload_hash, _, _) = create_response(predecessor_id, "malformed", path, &sk).await;
+        let request = SignRequest {
+            payload: payload_hash,
+            path: path.into(),
+            key_version: 0,
+            message: None,
+            max_wait_blocks: None,
+        };
+
+        let execution = contract
+            .call("sign")
+            .args_json(serde_json::json!({
+                "request": request,
+            }))
+            .deposit(NearToken::from_near(1))
+            .max_gas()
+            .transact()
+            .await?;
+        assert!(execution
+            .into_result()
+            .unwrap_err()
+            .to_string()
+            .contains(&errors::InvalidParameters::MalformedPath.to_string()));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_sign_limits() -> anyhow::Result<()> {
+    let (_, contract, _, sk) = init_env().await;
+    let predecessor_id = contract.id();
+
+    let mut config = Config::default();
+    config.other.insert(
+        "sign_limits".to_string(),
+        serde_json::json!({ "max_path_len": 4, "payload_formats": ["eip712"] }).into(),
     );
+    contract
+        .call("update_config")
+        .args_json(serde_json::json!({ "config": config }))
+        .transact()
+        .await?
+        .into_result()?;
 
-    let result = contract
-        .call("init")
+    // raw payloads are not allowed anymore
+    let (payload_hash, _, _) = create_response(predecessor_id, "limits", "test", &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: "test".into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+    let execution = contract
+        .call("sign")
         .args_json(serde_json::json!({
-            "threshold": 2,
-            "candidates": valid_candidates,
+            "request": request,
         }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
         .transact()
         .await?;
-    assert!(
-        result.is_success(),
-        "initializing with valid candidates should succeed"
-    );
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::InvalidParameters::PayloadFormatNotAllowed.to_string()));
 
-    // Reinitializing after the first successful initialization should fail.
-    let result = contract
-        .call("init")
+    // typed data is, but only with paths of up to 4 bytes
+    let domain = Eip712Domain {
+        name: Some("Ether Mail".to_string()),
+        version: Some("1".to_string()),
+        chain_id: Some(1),
+        verifying_contract: None,
+        salt: None,
+    };
+    let request = SignTypedDataRequest {
+        domain_separator: domain.separator()?,
+        domain,
+        struct_hash: [1; 32],
+        path: "tests".into(),
+        key_version: 0,
+    };
+    let execution = contract
+        .call("sign_typed_data")
         .args_json(serde_json::json!({
-            "threshold": 2,
-            "candidates": valid_candidates,
+            "request": request,
         }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
         .transact()
         .await?;
-    assert!(
-        result.is_failure(),
-        "initializing with valid candidates again should fail"
-    );
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::InvalidParameters::MalformedPath.to_string()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_sign_typed_data_domain() -> anyhow::Result<()> {
+    let (_, contract, _, _) = init_env().await;
+    let domain = Eip712Domain {
+        name: Some("Ether Mail".to_string()),
+        version: Some("1".to_string()),
+        chain_id: Some(1),
+        verifying_contract: Some("0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC".to_string()),
+        salt: None,
+    };
+
+    // A domain separator of another domain, e.g. another chain, must be rejected.
+    let other_domain = Eip712Domain {
+        chain_id: Some(5),
+        ..domain.clone()
+    };
+    let request = SignTypedDataRequest {
+        domain: domain.clone(),
+        domain_separator: other_domain.separator()?,
+        struct_hash: [1; 32],
+        path: "test".into(),
+        key_version: 0,
+    };
+    let execution = contract
+        .call("sign_typed_data")
+        .args_json(serde_json::json!({
+            "request": request,
+        }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
+        .transact()
+        .await?;
+    dbg!(&execution);
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::InvalidParameters::InvalidDomainSeparator.to_string()));
+
+    // So must a domain that has no valid separator.
+    let request = SignTypedDataRequest {
+        domain: Eip712Domain {
+            verifying_contract: Some("0x1234".to_string()),
+            ..domain
+        },
+        domain_separator: [0; 32],
+        struct_hash: [1; 32],
+        path: "test".into(),
+        key_version: 0,
+    };
+    let execution = contract
+        .call("sign_typed_data")
+        .args_json(serde_json::json!({
+            "request": request,
+        }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::InvalidParameters::InvalidDomainSeparator.to_string()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_sign_message() -> anyhow::Result<()> {
+    let (_, contract, _, sk) = init_env().await;
+    let predecessor_id = contract.id();
+    let path = "test";
+
+    // The contract hashes the message into the same payload the nodes respond to.
+    let msg = "hello world";
+    let (_, respond_req, respond_resp) = create_response(predecessor_id, msg, path, &sk).await;
+    let request = SignRequest {
+        payload: [0; 32],
+        path: path.into(),
+        key_version: 0,
+        message: Some(SignMessage::new(msg, HashAlgorithm::Sha256)),
+        max_wait_blocks: None,
+    };
+    sign_and_validate(&request, Some((&respond_req, &respond_resp)), &contract).await?;
+
+    // A request can not have both a payload and a message.
+    let (payload_hash, _, _) = create_response(predecessor_id, "both", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        message: Some(SignMessage::new("both", HashAlgorithm::Keccak256)),
+        ..request
+    };
+    let execution = contract
+        .call("sign")
+        .args_json(serde_json::json!({
+            "request": request,
+        }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::InvalidParameters::MalformedPayload.to_string()));
+
+    // Nor a message longer than `max_message_len`.
+    let request = SignRequest {
+        payload: [0; 32],
+        message: Some(SignMessage::new(vec![1; 4097], HashAlgorithm::Keccak256)),
+        ..request
+    };
+    let execution = contract
+        .call("sign")
+        .args_json(serde_json::json!({
+            "request": request,
+        }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::InvalidParameters::MalformedPayload.to_string()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_sign_request_expires() -> anyhow::Result<()> {
+    let (worker, contract, _, sk) = init_env().await;
+    let alice = worker.dev_create_account().await?;
+    let path = "test";
+
+    let mut config = Config::default();
+    config.other.insert(
+        "sign_request".to_string(),
+        serde_json::json!({ "ttl_blocks": 5 }).into(),
+    );
+    contract
+        .call("update_config")
+        .args_json(serde_json::json!({ "config": config }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let (payload_hash, _, _) = create_response(alice.id(), "expiring", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+    let balance = alice.view_account().await?.balance;
+    let status = alice
+        .call(contract.id(), "sign")
+        .args_json(serde_json::json!({
+            "request": request,
+        }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
+        .transact_async()
+        .await?;
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    // nothing has expired before the ttl has passed
+    let expired: u32 = contract.call("expire_requests").transact().await?.json()?;
+    assert_eq!(expired, 0);
+
+    worker.fast_forward(10).await?;
+    let expired: u32 = contract.call("expire_requests").transact().await?.json()?;
+    assert_eq!(expired, 1);
+
+    // the request resolves right away instead of waiting for the yield timeout
+    let err = status
+        .await?
+        .into_result()
+        .expect_err("should have failed with timeout");
+    assert!(err
+        .to_string()
+        .contains(&errors::SignError::Timeout.to_string()));
+    let new_balance = alice.view_account().await?.balance;
+    assert!(
+        balance.as_millinear() - new_balance.as_millinear() < 10,
+        "refund should happen"
+    );
+    let pending: Vec<PendingRequest> = contract.view("get_pending_requests").await?.json()?;
+    assert!(pending.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_sign_request_max_wait_blocks() -> anyhow::Result<()> {
+    let (worker, contract, _, sk) = init_env().await;
+    let alice = worker.dev_create_account().await?;
+    let path = "test";
+
+    let mut config = Config::default();
+    config.other.insert(
+        "sign_request".to_string(),
+        serde_json::json!({ "ttl_blocks": 100 }).into(),
+    );
+    contract
+        .call("update_config")
+        .args_json(serde_json::json!({ "config": config }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    // waiting longer than the ttl of the config is rejected
+    let (payload_hash, _, _) = create_response(alice.id(), "fail fast", path, &sk).await;
+    let mut request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: Some(101),
+    };
+    let execution = alice
+        .call(contract.id(), "sign")
+        .args_json(serde_json::json!({
+            "request": request,
+        }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::InvalidParameters::InvalidMaxWaitBlocks.to_string()));
+
+    request.max_wait_blocks = Some(5);
+    let status = alice
+        .call(contract.id(), "sign")
+        .args_json(serde_json::json!({
+            "request": request,
+        }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
+        .transact_async()
+        .await?;
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    let expired: u32 = contract.call("expire_requests").transact().await?.json()?;
+    assert_eq!(expired, 0);
+
+    // the request expires long before the ttl of the config
+    worker.fast_forward(10).await?;
+    let expired: u32 = contract.call("expire_requests").transact().await?.json()?;
+    assert_eq!(expired, 1);
+    let err = status
+        .await?
+        .into_result()
+        .expect_err("should have failed with timeout");
+    assert!(err
+        .to_string()
+        .contains(&errors::SignError::Timeout.to_string()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_sign_duplicate_requests() -> anyhow::Result<()> {
+    let (worker, contract, _, sk) = init_env().await;
+    let alice = worker.dev_create_account().await?;
+    let path = "test";
+
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(alice.id(), "duplicate", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+    let sign = || {
+        alice
+            .call(contract.id(), "sign")
+            .args_json(serde_json::json!({
+                "request": request,
+            }))
+            .deposit(NearToken::from_near(1))
+            .max_gas()
+            .transact_async()
+    };
+    let balance = alice.view_account().await?.balance;
+    let first = sign().await?;
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    let duplicate = sign().await?;
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    // the duplicate does not count as a request of its own
+    let pending: Vec<PendingRequest> = contract.view("get_pending_requests").await?.json()?;
+    assert_eq!(pending.len(), 1);
+
+    contract
+        .call("respond")
+        .args_json(serde_json::json!({
+            "request": respond_req,
+            "response": respond_resp
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    // both requests resolve with the single response
+    let first = first.await?.into_result()?;
+    let duplicate = duplicate.await?.into_result()?;
+    assert_eq!(first.json::<SignatureResponse>()?, respond_resp);
+    assert_eq!(duplicate.json::<SignatureResponse>()?, respond_resp);
+    assert!(duplicate
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("sign: duplicate")));
+
+    let new_balance = alice.view_account().await?.balance;
+    assert!(
+        balance.as_millinear() - new_balance.as_millinear() < 20,
+        "both deposits should be refunded"
+    );
+    let pending: Vec<PendingRequest> = contract.view("get_pending_requests").await?.json()?;
+    assert!(pending.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_get_pending_requests() -> anyhow::Result<()> {
+    let (_, contract, _, sk) = init_env().await;
+    let predecessor_id = contract.id();
+    let path = "test";
+
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(predecessor_id, "pending", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+
+    let pending: Vec<PendingRequest> = contract.view("get_pending_requests").await?.json()?;
+    assert!(pending.is_empty());
+
+    let status = contract
+        .call("sign")
+        .args_json(serde_json::json!({
+            "request": request,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact_async()
+        .await?;
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    let pending: Vec<PendingRequest> = contract.view("get_pending_requests").await?.json()?;
+    assert_eq!(pending.len(), 1);
+    assert_eq!(&pending[0].requester, predecessor_id);
+    assert_eq!(
+        serde_json::to_value(&pending[0].request)?,
+        serde_json::to_value(&respond_req)?
+    );
+    assert!(pending[0].timestamp > 0);
+
+    contract
+        .call("respond")
+        .args_json(serde_json::json!({
+            "request": respond_req,
+            "response": respond_resp
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    status.await?.into_result()?;
+
+    let pending: Vec<PendingRequest> = contract.view("get_pending_requests").await?.json()?;
+    assert!(pending.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_get_signature() -> anyhow::Result<()> {
+    let (worker, contract, _, sk) = init_env().await;
+    let predecessor_id = contract.id();
+    let path = "test";
+
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(predecessor_id, "cached", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+
+    let signature: Option<SignatureResponse> = contract
+        .view("get_signature")
+        .args_json(serde_json::json!({ "request_id": respond_req.id() }))
+        .await?
+        .json()?;
+    assert!(signature.is_none());
+
+    sign_and_validate(&request, Some((&respond_req, &respond_resp)), &contract).await?;
+    let signature: Option<SignatureResponse> = contract
+        .view("get_signature")
+        .args_json(serde_json::json!({ "request_id": respond_req.id() }))
+        .await?
+        .json()?;
+    assert_eq!(signature, Some(respond_resp));
+
+    // the signature is dropped once it is older than the retention
+    let mut config = Config::default();
+    config.other.insert(
+        "signature_cache".to_string(),
+        serde_json::json!({ "retention_blocks": 5 }).into(),
+    );
+    contract
+        .call("update_config")
+        .args_json(serde_json::json!({ "config": config }))
+        .transact()
+        .await?
+        .into_result()?;
+    worker.fast_forward(10).await?;
+    let signature: Option<SignatureResponse> = contract
+        .view("get_signature")
+        .args_json(serde_json::json!({ "request_id": respond_req.id() }))
+        .await?
+        .json()?;
+    assert!(signature.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_estimate_sign() -> anyhow::Result<()> {
+    let (_, contract, _, sk) = init_env().await;
+    let predecessor_id = contract.id();
+    let path = "test";
+
+    let estimate: SignEstimate = contract.view("estimate_sign").await?.json()?;
+    let deposit: near_sdk::json_types::U128 = contract
+        .view("experimental_signature_deposit")
+        .await?
+        .json()?;
+    assert_eq!(estimate.deposit, deposit);
+    assert_eq!(estimate.queue_depth, 0);
+    assert_eq!(estimate.samples, 0);
+    assert_eq!(estimate.estimated_latency_ms, None);
+
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(predecessor_id, "estimate", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+    sign_and_validate(&request, Some((&respond_req, &respond_resp)), &contract).await?;
+
+    // The signed request is the basis of the estimate now.
+    let estimate: SignEstimate = contract.view("estimate_sign").await?.json()?;
+    assert_eq!(estimate.queue_depth, 0);
+    assert_eq!(estimate.samples, 1);
+    assert!(estimate.estimated_latency_ms.unwrap() > 0, "{estimate:?}");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_sign_backpressure() -> anyhow::Result<()> {
+    let (worker, contract, accounts, sk) = init_env().await;
+    let path = "test";
+
+    let report = |account: &Account, presignatures: u32| {
+        account
+            .call(contract.id(), "report_capacity")
+            .args_json(serde_json::json!({ "presignatures": presignatures }))
+            .transact()
+    };
+
+    // Nothing is known about the capacity of the network until the threshold of the nodes
+    // reported it.
+    let capacity: NetworkCapacity = contract.view("network_capacity").await?.json()?;
+    assert_eq!(capacity.reporting, 0);
+    assert!(!capacity.overloaded);
+    report(&accounts[0], 0).await?.into_result()?;
+    let capacity: NetworkCapacity = contract.view("network_capacity").await?.json()?;
+    assert_eq!(capacity.reporting, 1);
+    assert!(!capacity.overloaded);
+
+    report(&accounts[1], 0).await?.into_result()?;
+    let capacity: NetworkCapacity = contract.view("network_capacity").await?.json()?;
+    assert!(capacity.overloaded);
+
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(contract.id(), "backpressure", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+    let execution = contract
+        .call("sign")
+        .args_json(serde_json::json!({ "request": request }))
+        .deposit(NearToken::from_near(1))
+        .max_gas()
+        .transact()
+        .await?;
+    let err = execution.into_result().unwrap_err().to_string();
+    assert!(err.contains(&errors::SignError::Overloaded.to_string()));
+    assert!(err.contains("retry after 10 blocks"), "{err}");
+
+    report(&accounts[0], 1).await?.into_result()?;
+    let capacity: NetworkCapacity = contract.view("network_capacity").await?.json()?;
+    assert_eq!(capacity.presignatures, 1);
+    assert!(!capacity.overloaded);
+    sign_and_validate(&request, Some((&respond_req, &respond_resp)), &contract).await?;
+
+    // Only the nodes of the protocol can report their capacity.
+    let alice = worker.dev_create_account().await?;
+    assert!(report(&alice, 100).await?.is_failure());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_initialization() -> anyhow::Result<()> {
+    let (_, contract) = init().await;
+    let valid_candidates = candidates(None);
+
+    // Empty candidates should fail.
+    let candidates: HashMap<AccountId, CandidateInfo> = HashMap::new();
+    let result = contract
+        .call("init")
+        .args_json(serde_json::json!({
+            "threshold": 2,
+            "candidates": candidates
+        }))
+        .transact()
+        .await?;
+    assert!(
+        result.is_failure(),
+        "initializing with zero candidates or less than threshold candidates should fail"
+    );
+
+    let result = contract
+        .call("init")
+        .args_json(serde_json::json!({
+            "threshold": 2,
+            "candidates": valid_candidates,
+        }))
+        .transact()
+        .await?;
+    assert!(
+        result.is_success(),
+        "initializing with valid candidates should succeed"
+    );
+
+    // Reinitializing after the first successful initialization should fail.
+    let result = contract
+        .call("init")
+        .args_json(serde_json::json!({
+            "threshold": 2,
+            "candidates": valid_candidates,
+        }))
+        .transact()
+        .await?;
+    assert!(
+        result.is_failure(),
+        "initializing with valid candidates again should fail"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_sign_access() -> anyhow::Result<()> {
+    let (worker, contract, _, sk) = init_env().await;
+    let alice = worker.dev_create_account().await?;
+    let path = "test";
+
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(contract.id(), "permissioned", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+    let sign = |account: &Account| {
+        account
+            .call(contract.id(), "sign")
+            .args_json(serde_json::json!({ "request": request }))
+            .deposit(NearToken::from_near(1))
+            .max_gas()
+            .transact()
+    };
+
+    // only the contract admin can change the access list
+    let execution = alice
+        .call(contract.id(), "set_sign_access_mode")
+        .args_json(serde_json::json!({ "mode": "allowlist" }))
+        .transact()
+        .await?;
+    assert!(execution.into_result().is_err());
+
+    contract
+        .call("set_sign_access_mode")
+        .args_json(serde_json::json!({ "mode": "allowlist" }))
+        .transact()
+        .await?
+        .into_result()?;
+    let execution = sign(contract.as_account()).await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::SignError::CallerNotAllowed.to_string()));
+
+    let execution = contract
+        .call("add_sign_access_accounts")
+        .args_json(serde_json::json!({ "accounts": [contract.id()] }))
+        .transact()
+        .await?
+        .into_result()?;
+    assert!(execution
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("EVENT_JSON:")
+            && log.contains(r#""event":"sign_access_updated""#)));
+    let sign_access: SignAccessConfig = contract.view("sign_access").await?.json()?;
+    assert_eq!(sign_access.mode, SignAccessMode::Allowlist);
+    assert!(sign_access.accounts.contains(contract.id()));
+
+    // replacing the config leaves the access list as is
+    contract
+        .call("update_config")
+        .args_json(serde_json::json!({ "config": Config::default() }))
+        .transact()
+        .await?
+        .into_result()?;
+    let sign_access_after: SignAccessConfig = contract.view("sign_access").await?.json()?;
+    assert_eq!(sign_access_after, sign_access);
+
+    sign_and_validate(&request, Some((&respond_req, &respond_resp)), &contract).await?;
+    let execution = sign(&alice).await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::SignError::CallerNotAllowed.to_string()));
+
+    // in denylist mode the listed accounts are the ones that get rejected
+    contract
+        .call("set_sign_access_mode")
+        .args_json(serde_json::json!({ "mode": "denylist" }))
+        .transact()
+        .await?
+        .into_result()?;
+    let execution = sign(contract.as_account()).await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::SignError::CallerNotAllowed.to_string()));
+
+    contract
+        .call("remove_sign_access_accounts")
+        .args_json(serde_json::json!({ "accounts": [contract.id()] }))
+        .transact()
+        .await?
+        .into_result()?;
+    let sign_access: SignAccessConfig = contract.view("sign_access").await?.json()?;
+    assert!(sign_access.accounts.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_sign_pause() -> anyhow::Result<()> {
+    let (worker, contract, accounts, sk) = init_env().await;
+    let alice = worker.dev_create_account().await?;
+    let path = "test";
+
+    let vote = |account: &Account, method: &'static str| {
+        account.call(contract.id(), method).max_gas().transact()
+    };
+    let sign = |account: &Account, payload: [u8; 32]| {
+        let request = SignRequest {
+            payload,
+            path: path.into(),
+            key_version: 0,
+            message: None,
+            max_wait_blocks: None,
+        };
+        account
+            .call(contract.id(), "sign")
+            .args_json(serde_json::json!({ "request": request }))
+            .deposit(NearToken::from_near(1))
+            .max_gas()
+    };
+
+    let execution = vote(&alice, "vote_pause").await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::VoteError::VoterNotParticipant.to_string()));
+
+    let paused: bool = vote(&accounts[0], "vote_pause")
+        .await?
+        .into_result()?
+        .json()?;
+    assert!(!paused);
+    let pause: SignPauseView = contract.view("sign_pause").await?.json()?;
+    assert!(!pause.paused);
+    assert_eq!(pause.votes.len(), 1);
+    assert_eq!(pause.required_votes, 2);
+
+    // A request submitted before the pause still gets its signature.
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(alice.id(), "in flight", path, &sk).await;
+    let status = sign(&alice, payload_hash).transact_async().await?;
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    let execution = vote(&accounts[1], "vote_pause").await?.into_result()?;
+    assert!(execution.logs().iter().any(
+        |log| log.starts_with("EVENT_JSON:") && log.contains(r#""event":"sign_pause_updated""#)
+    ));
+    assert!(execution.json::<bool>()?);
+    let pause: SignPauseView = contract.view("sign_pause").await?.json()?;
+    assert!(pause.paused);
+    assert!(pause.paused_since.is_some());
+    assert!(pause.votes.is_empty());
+
+    let (payload_hash, _, _) = create_response(alice.id(), "paused", path, &sk).await;
+    let execution = sign(&alice, payload_hash).transact().await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::SignError::Paused.to_string()));
+
+    accounts[0]
+        .call(contract.id(), "respond")
+        .args_json(serde_json::json!({
+            "request": respond_req,
+            "response": respond_resp,
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let signature: SignatureResponse = status.await?.into_result()?.json()?;
+    assert_eq!(signature, respond_resp);
+
+    let execution = vote(&accounts[2], "vote_pause").await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::VoteError::AlreadyPaused.to_string()));
+
+    let resumed: bool = vote(&accounts[0], "vote_unpause")
+        .await?
+        .into_result()?
+        .json()?;
+    assert!(!resumed);
+    let resumed: bool = vote(&accounts[2], "vote_unpause")
+        .await?
+        .into_result()?
+        .json()?;
+    assert!(resumed);
+    let pause: SignPauseView = contract.view("sign_pause").await?.json()?;
+    assert!(!pause.paused);
+    assert_eq!(pause.paused_since, None);
+
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(contract.id(), "resumed", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+    sign_and_validate(&request, Some((&respond_req, &respond_resp)), &contract).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_clean_requests() -> anyhow::Result<()> {
+    let (worker, contract, _, sk) = init_env().await;
+    let alice = worker.dev_create_account().await?;
+    let path = "test";
+
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(contract.id(), "clean", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+    let status = contract
+        .call("sign")
+        .args_json(serde_json::json!({
+            "request": request,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact_async()
+        .await?;
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    // requests that are still waiting for their signature are never cleaned
+    let cleaned: u32 = alice
+        .call(contract.id(), "clean_requests")
+        .args_json(serde_json::json!({ "limit": null }))
+        .transact()
+        .await?
+        .json()?;
+    assert_eq!(cleaned, 0);
+    let pending: Vec<PendingRequest> = contract.view("get_pending_requests").await?.json()?;
+    assert_eq!(pending.len(), 1);
+    // the deposit is kept with the request, to pay the bounty out of once it goes stale
+    assert_eq!(pending[0].deposit.0, 1);
+
+    contract
+        .call("respond")
+        .args_json(serde_json::json!({
+            "request": respond_req,
+            "response": respond_resp
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    status.await?.into_result()?;
+
+    let pending: Vec<PendingRequest> = contract.view("get_pending_requests").await?.json()?;
+    assert!(pending.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_contract_key_version_rotation() -> anyhow::Result<()> {
+    let (_, contract, _, sk) = init_env().await;
+    let predecessor_id = contract.id();
+    let path = "test";
+
+    let sign = |key_version: u32, payload_hash| {
+        contract
+            .call("sign")
+            .args_json(serde_json::json!({
+                "request": SignRequest {
+                    payload: payload_hash,
+                    path: path.into(),
+                    key_version,
+                    message: None,
+                    max_wait_blocks: None,
+                },
+            }))
+            .deposit(NearToken::from_near(1))
+            .max_gas()
+            .transact()
+    };
+    let (payload_hash, _, _) = create_response(predecessor_id, "rotation", path, &sk).await;
+
+    // version 0 is retired right away, version 1 keeps being served after the next rotation
+    for deprecation_blocks in [0, 1_000] {
+        contract
+            .call("rotate_key_version")
+            .args_json(serde_json::json!({ "deprecation_blocks": deprecation_blocks }))
+            .transact()
+            .await?
+            .into_result()?;
+    }
+    let latest: u32 = contract.view("latest_key_version").await?.json()?;
+    assert_eq!(latest, 2);
+
+    // replacing the config leaves the key versions as they are
+    contract
+        .call("update_config")
+        .args_json(serde_json::json!({ "config": Config::default() }))
+        .transact()
+        .await?
+        .into_result()?;
+    let latest: u32 = contract.view("latest_key_version").await?.json()?;
+    assert_eq!(latest, 2);
+
+    let execution = sign(0, payload_hash).await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::SignError::KeyVersionRetired.to_string()));
+
+    // a pinned account can not sign with any other version
+    contract
+        .call("pin_key_version")
+        .args_json(serde_json::json!({ "key_version": 1 }))
+        .deposit(NearToken::from_millinear(10))
+        .transact()
+        .await?
+        .into_result()?;
+    let pinned: Option<u32> = contract
+        .view("pinned_key_version")
+        .args_json(serde_json::json!({ "account_id": predecessor_id }))
+        .await?
+        .json()?;
+    assert_eq!(pinned, Some(1));
+
+    let execution = sign(2, payload_hash).await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::SignError::KeyVersionMismatch.to_string()));
+
+    // retired versions can not be pinned
+    let execution = contract
+        .call("pin_key_version")
+        .args_json(serde_json::json!({ "key_version": 0 }))
+        .deposit(NearToken::from_millinear(10))
+        .transact()
+        .await?;
+    assert!(execution.into_result().is_err());
 
     Ok(())
 }
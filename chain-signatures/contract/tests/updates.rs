@@ -1,12 +1,18 @@
 pub mod common;
-use common::{init_env, vote_update_till_completion, CONTRACT_FILE_PATH, INVALID_CONTRACT};
+use common::{
+    create_response, init_env, init_env_from, sign_and_validate, vote_update_till_completion,
+    CONTRACT_FILE_PATH, INVALID_CONTRACT, PREVIOUS_CONTRACT_FILE_PATH,
+};
 
 use std::collections::HashMap;
 
 use mpc_contract::config::{Config, ProtocolConfig};
 use mpc_contract::errors;
+use mpc_contract::primitives::{NetworkCapacity, PendingRequest, SignRequest};
 use mpc_contract::update::{ProposeUpdateArgs, UpdateId};
+use mpc_contract::ProtocolContractState;
 
+use crypto_shared::SignatureResponse;
 use near_workspaces::types::NearToken;
 
 pub fn dummy_contract() -> ProposeUpdateArgs {
@@ -265,3 +271,177 @@ async fn test_propose_update_contract_many() {
         contract.view("state").await.unwrap().json().unwrap();
     dbg!(state);
 }
+
+#[tokio::test]
+async fn test_update_keeps_in_flight_sign_requests() -> anyhow::Result<()> {
+    let (_, contract, accounts, sk) = init_env().await;
+    let predecessor_id = contract.id();
+    let path = "test";
+
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(predecessor_id, "in flight", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+    let status = contract
+        .call("sign")
+        .args_json(serde_json::json!({
+            "request": request,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact_async()
+        .await?;
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    // Upgrade the contract while the sign request is still waiting for a response.
+    let execution = accounts[0]
+        .call(contract.id(), "propose_update")
+        .args_borsh((current_contract(),))
+        .max_gas()
+        .deposit(CURRENT_CONTRACT_DEPLOY_DEPOSIT)
+        .transact()
+        .await?;
+    assert!(execution.is_success(), "{execution:#?}");
+    let proposal_id: UpdateId = execution.json()?;
+    vote_update_till_completion(&contract, &accounts, &proposal_id).await;
+
+    let pending: Vec<PendingRequest> = contract.view("get_pending_requests").await?.json()?;
+    assert_eq!(pending.len(), 1);
+
+    // The request can still be responded to, and the caller gets the signature.
+    contract
+        .call("respond")
+        .args_json(serde_json::json!({
+            "request": respond_req,
+            "response": respond_resp
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let returned_resp: SignatureResponse = status.await?.into_result()?.json()?;
+    assert_eq!(returned_resp, respond_resp);
+
+    let pending: Vec<PendingRequest> = contract.view("get_pending_requests").await?.json()?;
+    assert!(pending.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_from_previous_release() -> anyhow::Result<()> {
+    let (_, contract, accounts, sk) = init_env_from(PREVIOUS_CONTRACT_FILE_PATH).await;
+    let ProtocolContractState::Running(running) = contract.view("state").await?.json()? else {
+        panic!("contract should be running");
+    };
+
+    // A sign request of the previous release that is still in flight during the upgrade.
+    let path = "test";
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(contract.id(), "in flight", path, &sk).await;
+    let status = contract
+        .call("sign")
+        .args_json(serde_json::json!({
+            "request": {
+                "payload": payload_hash,
+                "path": path,
+                "key_version": 0,
+            },
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact_async()
+        .await?;
+    // Another one that never gets a response.
+    let (unanswered_hash, unanswered_req, _) =
+        create_response(contract.id(), "unanswered", path, &sk).await;
+    let unanswered = contract
+        .call("sign")
+        .args_json(serde_json::json!({
+            "request": {
+                "payload": unanswered_hash,
+                "path": path,
+                "key_version": 0,
+            },
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact_async()
+        .await?;
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    // The previous release deploys the update and calls `migrate` on it.
+    let execution = accounts[0]
+        .call(contract.id(), "propose_update")
+        .args_borsh((current_contract(),))
+        .max_gas()
+        .deposit(CURRENT_CONTRACT_DEPLOY_DEPOSIT)
+        .transact()
+        .await?;
+    assert!(execution.is_success(), "{execution:#?}");
+    let proposal_id: UpdateId = execution.json()?;
+    vote_update_till_completion(&contract, &accounts, &proposal_id).await;
+
+    let ProtocolContractState::Running(migrated) = contract.view("state").await?.json()? else {
+        panic!("contract should still be running");
+    };
+    assert_eq!(migrated.epoch, running.epoch);
+    assert_eq!(migrated.participants.len(), running.participants.len());
+    assert_eq!(migrated.public_key, running.public_key);
+    assert!(migrated.new_participants_votes.votes.is_empty());
+
+    // The request from before the upgrade can still be responded to.
+    contract
+        .call("respond")
+        .args_json(serde_json::json!({
+            "request": respond_req,
+            "response": respond_resp
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let returned_resp: SignatureResponse = status.await?.into_result()?.json()?;
+    assert_eq!(returned_resp, respond_resp);
+
+    // The unanswered one is not in the index, but still times out through its yield callback
+    // and leaves the state along with it.
+    let err = unanswered
+        .await?
+        .into_result()
+        .expect_err("should have failed with timeout");
+    assert!(err
+        .to_string()
+        .contains(&errors::SignError::Timeout.to_string()));
+    let execution = contract
+        .call("respond")
+        .args_json(serde_json::json!({
+            "request": unanswered_req,
+            "response": respond_resp
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(execution.is_failure());
+    let capacity: NetworkCapacity = contract.view("network_capacity").await?.json()?;
+    assert_eq!(capacity.pending_requests, 0);
+
+    // The migrated contract keeps serving sign requests.
+    let (payload_hash, respond_req, respond_resp) =
+        create_response(contract.id(), "after migration", path, &sk).await;
+    let request = SignRequest {
+        payload: payload_hash,
+        path: path.into(),
+        key_version: 0,
+        message: None,
+        max_wait_blocks: None,
+    };
+    sign_and_validate(&request, Some((&respond_req, &respond_resp)), &contract).await?;
+
+    Ok(())
+}
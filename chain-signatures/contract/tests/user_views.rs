@@ -2,6 +2,9 @@ pub mod common;
 use common::{create_response, init_env};
 
 use mpc_contract::primitives::SignRequest;
+use mpc_contract::StateDetails;
+
+use crypto_shared::{near_public_key_to_affine_point, DerivedAddresses};
 
 use near_sdk::{CurveType, PublicKey};
 use near_workspaces::types::NearToken;
@@ -51,6 +54,65 @@ async fn test_derived_public_key() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_derived_public_keys() -> anyhow::Result<()> {
+    let (_, contract, _, _) = init_env().await;
+    let keys = [
+        ("test", "alice.near"),
+        ("other", "alice.near"),
+        ("test", "bob.near"),
+    ];
+
+    let batch: Vec<String> = contract
+        .view("derived_public_keys")
+        .args_json(json!({ "keys": keys }))
+        .await?
+        .json()?;
+    assert_eq!(batch.len(), keys.len());
+    for ((path, predecessor), key) in keys.iter().zip(&batch) {
+        let single: String = contract
+            .view("derived_public_key")
+            .args_json(json!({ "path": path, "predecessor": predecessor }))
+            .await?
+            .json()?;
+        assert_eq!(&single, key);
+    }
+
+    let too_many = vec![("test", "alice.near"); mpc_contract::MAX_DERIVED_KEYS + 1];
+    let result = contract
+        .view("derived_public_keys")
+        .args_json(json!({ "keys": too_many }))
+        .await;
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_derived_addresses() -> anyhow::Result<()> {
+    let (_, contract, _, _) = init_env().await;
+    let args = json!({
+        "path": "test",
+        "predecessor": "alice.near"
+    });
+
+    let addresses: DerivedAddresses = contract
+        .view("derived_addresses")
+        .args_json(args.clone())
+        .await?
+        .json()?;
+    let key: String = contract
+        .view("derived_public_key")
+        .args_json(args)
+        .await?
+        .json()?;
+    let pk = near_public_key_to_affine_point(PublicKey::from_str(&key)?);
+    assert_eq!(addresses, DerivedAddresses::new(&pk));
+    assert!(addresses.bitcoin.starts_with("bc1q"));
+    assert!(addresses.bitcoin_testnet.starts_with("tb1q"));
+    assert!(addresses.ethereum.starts_with("0x"));
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_experimental_signature_deposit() -> anyhow::Result<()> {
     let (worker, contract, _, sk) = init_env().await;
@@ -75,6 +137,8 @@ async fn test_experimental_signature_deposit() -> anyhow::Result<()> {
             payload: payload_hash,
             path: path.into(),
             key_version: 0,
+            message: None,
+            max_wait_blocks: None,
         };
         let _status = alice
             .call(contract.id(), "sign")
@@ -100,3 +164,87 @@ async fn test_experimental_signature_deposit() -> anyhow::Result<()> {
     assert_eq!(deposit, NearToken::from_millinear(50).as_yoctonear());
     Ok(())
 }
+
+#[tokio::test]
+async fn test_state_details() -> anyhow::Result<()> {
+    let (_, contract, accounts, _) = init_env().await;
+
+    let details: StateDetails = contract.view("state_details").await?.json()?;
+    assert_eq!(details.state, "Running");
+    assert_eq!(details.epoch, Some(0));
+    assert_eq!(details.threshold, Some(2));
+    assert!(details.public_key.is_some());
+    let mut participants = details
+        .participants
+        .iter()
+        .map(|info| info.account_id.to_string())
+        .collect::<Vec<_>>();
+    let mut expected = accounts
+        .iter()
+        .map(|account| account.id().to_string())
+        .collect::<Vec<_>>();
+    participants.sort();
+    expected.sort();
+    assert_eq!(participants, expected);
+    assert!(details.candidates.is_empty());
+    assert!(details.votes.leave.is_empty());
+
+    let kick = accounts[1].id();
+    accounts[0]
+        .call(contract.id(), "vote_leave")
+        .args_json(json!({ "kick": kick }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let details: StateDetails = contract.view("state_details").await?.json()?;
+    let voters = details
+        .votes
+        .leave
+        .iter()
+        .map(|(kick, voters)| {
+            (
+                kick.to_string(),
+                voters.iter().map(|voter| voter.to_string()).collect(),
+            )
+        })
+        .collect::<Vec<(String, Vec<String>)>>();
+    assert_eq!(
+        voters,
+        vec![(kick.to_string(), vec![accounts[0].id().to_string()])]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_verify() -> anyhow::Result<()> {
+    let (_, contract, _, sk) = init_env().await;
+    let predecessor = "alice.near".parse()?;
+    let (payload, _, signature) = create_response(&predecessor, "hello world", "test", &sk).await;
+
+    let verified: bool = contract
+        .view("verify")
+        .args_json(json!({
+            "payload": payload,
+            "path": "test",
+            "predecessor": predecessor,
+            "signature": signature,
+        }))
+        .await?
+        .json()?;
+    assert!(verified);
+
+    // the signature is not valid for the key of another path
+    let verified: bool = contract
+        .view("verify")
+        .args_json(json!({
+            "payload": payload,
+            "path": "other",
+            "predecessor": predecessor,
+            "signature": signature,
+        }))
+        .await?
+        .json()?;
+    assert!(!verified);
+    Ok(())
+}
@@ -1,6 +1,8 @@
 pub mod common;
 use common::init_env;
 
+use mpc_contract::errors;
+use mpc_contract::primitives::ParticipantHeartbeat;
 use serde_json::json;
 
 #[tokio::test]
@@ -349,3 +351,351 @@ async fn test_vote_reshare() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_vote_new_participants() -> anyhow::Result<()> {
+    let (worker, contract, accounts, _) = init_env().await;
+
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let execution = alice
+        .call(contract.id(), "join")
+        .args_json(json!({
+            "url": "127.0.0.1",
+            "cipher_pk": vec![1u8; 32],
+            "sign_pk": "ed25519:J75xXmF7WUPS3xCm3hy2tgwLCKdYM1iJd4BWF8sWVnae",
+        }))
+        .transact()
+        .await?;
+    assert!(execution.is_success());
+    // now alice is candidate, bob is just a random account
+
+    // bob is not a candidate, so he cannot be part of the new participant set
+    let execution = accounts[0]
+        .call(contract.id(), "vote_new_participants")
+        .args_json(json!({
+            "participants": [accounts[0].id(), accounts[1].id(), bob.id()],
+        }))
+        .transact()
+        .await?;
+    assert!(execution.is_failure());
+
+    // the new participant set cannot be the same as the current one
+    let execution = accounts[0]
+        .call(contract.id(), "vote_new_participants")
+        .args_json(json!({
+            "participants": [accounts[0].id(), accounts[1].id(), accounts[2].id()],
+        }))
+        .transact()
+        .await?;
+    assert!(execution.is_failure());
+
+    // alice replaces accounts[2] in a single resharing
+    let new_participants = json!({
+        "participants": [accounts[0].id(), accounts[1].id(), alice.id()],
+    });
+
+    // candidates should not have permission to vote
+    let execution = alice
+        .call(contract.id(), "vote_new_participants")
+        .args_json(new_participants.clone())
+        .transact()
+        .await?;
+    assert!(execution.is_failure());
+
+    let execution = accounts[0]
+        .call(contract.id(), "vote_new_participants")
+        .args_json(new_participants.clone())
+        .transact()
+        .await?;
+    assert!(execution.is_success());
+    let vote_pass: bool = execution.json().unwrap();
+    assert!(!vote_pass);
+
+    let execution = accounts[2]
+        .call(contract.id(), "vote_new_participants")
+        .args_json(new_participants)
+        .transact()
+        .await?;
+    assert!(execution.is_success());
+    let vote_pass: bool = execution.json().unwrap();
+    assert!(vote_pass);
+
+    let state: mpc_contract::ProtocolContractState =
+        contract.view("state").await.unwrap().json().unwrap();
+    match state {
+        mpc_contract::ProtocolContractState::Resharing(r) => {
+            assert!(r.new_participants.contains_key(alice.id()));
+            assert!(r.new_participants.contains_key(accounts[0].id()));
+            assert!(r.new_participants.contains_key(accounts[1].id()));
+            assert!(!r.new_participants.contains_key(accounts[2].id()));
+        }
+        _ => panic!("should be in resharing state"),
+    };
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_vote_new_participants_keeps_threshold() -> anyhow::Result<()> {
+    let (worker, contract, accounts, _) = init_env().await;
+
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    for candidate in [&alice, &bob] {
+        let execution = candidate
+            .call(contract.id(), "join")
+            .args_json(json!({
+                "url": "127.0.0.1",
+                "cipher_pk": vec![1u8; 32],
+                "sign_pk": "ed25519:J75xXmF7WUPS3xCm3hy2tgwLCKdYM1iJd4BWF8sWVnae",
+            }))
+            .transact()
+            .await?;
+        assert!(execution.is_success());
+    }
+
+    // only one of the current participants would stay on, below the threshold of 2, so the
+    // new participants could not get their shares
+    let execution = accounts[0]
+        .call(contract.id(), "vote_new_participants")
+        .args_json(json!({
+            "participants": [accounts[0].id(), alice.id(), bob.id()],
+        }))
+        .transact()
+        .await?;
+    assert!(execution
+        .into_result()
+        .unwrap_err()
+        .to_string()
+        .contains(&errors::VoteError::TooFewRemainingParticipants.to_string()));
+
+    // keeping two of them is enough, even when growing the set
+    let execution = accounts[0]
+        .call(contract.id(), "vote_new_participants")
+        .args_json(json!({
+            "participants": [accounts[0].id(), accounts[1].id(), alice.id(), bob.id()],
+        }))
+        .transact()
+        .await?;
+    assert!(execution.is_success());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_vote_threshold() -> anyhow::Result<()> {
+    let (worker, contract, accounts, _) = init_env().await;
+
+    // the threshold cannot be above the number of participants or below 2
+    for threshold in [1, 4] {
+        let execution = accounts[0]
+            .call(contract.id(), "vote_threshold")
+            .args_json(json!({
+                "threshold": threshold,
+            }))
+            .transact()
+            .await?;
+        assert!(execution.is_failure());
+    }
+
+    // the threshold cannot be voted to stay the same
+    let execution = accounts[0]
+        .call(contract.id(), "vote_threshold")
+        .args_json(json!({
+            "threshold": 2,
+        }))
+        .transact()
+        .await?;
+    assert!(execution.is_failure());
+
+    // non participants should not have permission to vote
+    let alice = worker.dev_create_account().await?;
+    let execution = alice
+        .call(contract.id(), "vote_threshold")
+        .args_json(json!({
+            "threshold": 3,
+        }))
+        .transact()
+        .await?;
+    assert!(execution.is_failure());
+
+    let execution = accounts[0]
+        .call(contract.id(), "vote_threshold")
+        .args_json(json!({
+            "threshold": 3,
+        }))
+        .transact()
+        .await?;
+    assert!(execution.is_success());
+    let vote_pass: bool = execution.json().unwrap();
+    assert!(!vote_pass);
+
+    let execution = accounts[1]
+        .call(contract.id(), "vote_threshold")
+        .args_json(json!({
+            "threshold": 3,
+        }))
+        .transact()
+        .await?;
+    assert!(execution.is_success());
+    let vote_pass: bool = execution.json().unwrap();
+    assert!(vote_pass);
+
+    let state: mpc_contract::ProtocolContractState =
+        contract.view("state").await.unwrap().json().unwrap();
+    match state {
+        mpc_contract::ProtocolContractState::Resharing(r) => {
+            assert_eq!(r.threshold, 3);
+            assert_eq!(r.old_threshold(), 2);
+            assert!(r.old_participants.keys().eq(r.new_participants.keys()));
+        }
+        _ => panic!("should be in resharing state"),
+    };
+
+    // every participant has to finish resharing now that the threshold is raised to 3
+    for (i, account) in accounts.iter().enumerate() {
+        let execution = account
+            .call(contract.id(), "vote_reshared")
+            .args_json(json!({
+                "epoch": 1
+            }))
+            .transact()
+            .await?;
+        assert!(execution.is_success());
+        let vote_pass: bool = execution.json().unwrap();
+        assert_eq!(vote_pass, i == accounts.len() - 1);
+    }
+
+    let state: mpc_contract::ProtocolContractState =
+        contract.view("state").await.unwrap().json().unwrap();
+    match state {
+        mpc_contract::ProtocolContractState::Running(r) => {
+            assert_eq!(r.epoch, 1);
+            assert_eq!(r.threshold, 3);
+            assert!(r.threshold_votes.votes.is_empty());
+        }
+        _ => panic!("should be in running state"),
+    };
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_proactive_resharing() -> anyhow::Result<()> {
+    let (worker, contract, accounts, _) = init_env().await;
+
+    // nothing happens while proactive resharing is turned off
+    let execution = accounts[0]
+        .call(contract.id(), "start_proactive_resharing")
+        .transact()
+        .await?;
+    assert!(execution.is_success());
+    let started: bool = execution.json().unwrap();
+    assert!(!started);
+
+    let mut config = mpc_contract::config::Config::default();
+    config.other.insert(
+        "proactive_resharing".to_string(),
+        json!({ "interval_epochs": 1 }).into(),
+    );
+    contract
+        .call("update_config")
+        .args_json(json!({ "config": config }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let mut due = false;
+    for _ in 0..20 {
+        due = contract.view("proactive_resharing_due").await?.json()?;
+        if due {
+            break;
+        }
+        worker.fast_forward(100).await?;
+    }
+    assert!(due, "key shares should be due for a refresh after an epoch");
+
+    // non participants can not start it
+    let alice = worker.dev_create_account().await?;
+    let execution = alice
+        .call(contract.id(), "start_proactive_resharing")
+        .transact()
+        .await?;
+    assert!(execution.is_failure());
+
+    let execution = accounts[0]
+        .call(contract.id(), "start_proactive_resharing")
+        .transact()
+        .await?;
+    assert!(execution.is_success());
+    let started: bool = execution.json().unwrap();
+    assert!(started);
+
+    // the same key is reshared among the same participants with the same threshold
+    let state: mpc_contract::ProtocolContractState =
+        contract.view("state").await.unwrap().json().unwrap();
+    match state {
+        mpc_contract::ProtocolContractState::Resharing(r) => {
+            assert_eq!(r.threshold, 2);
+            assert_eq!(r.old_threshold(), 2);
+            assert!(r.old_participants.keys().eq(r.new_participants.keys()));
+        }
+        _ => panic!("should be in resharing state"),
+    };
+
+    // the other participants see that it is already underway
+    let execution = accounts[1]
+        .call(contract.id(), "start_proactive_resharing")
+        .transact()
+        .await?;
+    let started: bool = execution.json().unwrap();
+    assert!(!started);
+
+    for account in &accounts {
+        account
+            .call(contract.id(), "vote_reshared")
+            .args_json(json!({
+                "epoch": 1
+            }))
+            .transact()
+            .await?
+            .into_result()?;
+    }
+    let due: bool = contract.view("proactive_resharing_due").await?.json()?;
+    assert!(!due);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ping() -> anyhow::Result<()> {
+    let (worker, contract, accounts, _) = init_env().await;
+
+    let heartbeats: Vec<ParticipantHeartbeat> =
+        contract.view("participant_heartbeats").await?.json()?;
+    assert_eq!(heartbeats.len(), accounts.len());
+    assert!(heartbeats.iter().all(|h| h.last_seen.is_none()));
+
+    let execution = accounts[0].call(contract.id(), "ping").transact().await?;
+    assert!(execution.is_success());
+
+    let heartbeats: Vec<ParticipantHeartbeat> =
+        contract.view("participant_heartbeats").await?.json()?;
+    let pinged = heartbeats
+        .iter()
+        .find(|h| &h.account_id == accounts[0].id())
+        .unwrap();
+    assert!(pinged.last_seen.is_some());
+    assert!(pinged.blocks_since_last_seen.is_some());
+    assert!(heartbeats
+        .iter()
+        .filter(|h| &h.account_id != accounts[0].id())
+        .all(|h| h.last_seen.is_none()));
+
+    // Only the nodes of the protocol can ping.
+    let alice = worker.dev_create_account().await?;
+    let execution = alice.call(contract.id(), "ping").transact().await?;
+    assert!(execution.is_failure());
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//! Addresses on other chains that are controlled by a derived key, so that integrators can find
+//! out where to send funds to without reimplementing the encodings themselves.
+
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use ripemd::Ripemd160;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+
+use crate::types::PublicKey;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BitcoinNetwork {
+    Mainnet,
+    Testnet,
+}
+
+impl BitcoinNetwork {
+    /// Human readable part of the bech32 encoded segwit addresses of the network.
+    pub fn hrp(&self) -> &'static str {
+        match self {
+            BitcoinNetwork::Mainnet => "bc",
+            BitcoinNetwork::Testnet => "tb",
+        }
+    }
+}
+
+/// Addresses controlled by a single derived key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DerivedAddresses {
+    pub bitcoin: String,
+    pub bitcoin_testnet: String,
+    pub ethereum: String,
+}
+
+impl DerivedAddresses {
+    pub fn new(public_key: &PublicKey) -> Self {
+        DerivedAddresses {
+            bitcoin: bitcoin_p2wpkh_address(public_key, BitcoinNetwork::Mainnet),
+            bitcoin_testnet: bitcoin_p2wpkh_address(public_key, BitcoinNetwork::Testnet),
+            ethereum: ethereum_address(public_key),
+        }
+    }
+}
+
+/// EIP-55 checksummed Ethereum address of the public key, i.e. the last 20 bytes of the keccak
+/// hash of its uncompressed encoding.
+pub fn ethereum_address(public_key: &PublicKey) -> String {
+    let encoded = public_key.to_encoded_point(false);
+    let hash = Keccak256::digest(&encoded.as_bytes()[1..]);
+    let address = hex(&hash[12..]);
+
+    let checksum = Keccak256::digest(address.as_bytes());
+    let checksummed: String = address
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (checksum[i / 2] >> (4 * (1 - i % 2))) & 0x0f;
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!("0x{checksummed}")
+}
+
+/// Native segwit (P2WPKH) address of the public key, i.e. the witness v0 program of the
+/// hash160 of its compressed encoding.
+pub fn bitcoin_p2wpkh_address(public_key: &PublicKey, network: BitcoinNetwork) -> String {
+    let encoded = public_key.to_encoded_point(true);
+    let hash = Ripemd160::digest(Sha256::digest(encoded.as_bytes()));
+
+    let mut data = vec![0u8];
+    data.extend(convert_bits(&hash, 8, 5));
+    bech32_encode(network.hrp(), &data)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Encodes 5 bit groups with the original bech32 checksum (BIP-173), which is the one used by
+/// witness v0 addresses.
+fn bech32_encode(hrp: &str, data: &[u8]) -> String {
+    let mut values: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|b| b & 0x1f));
+    values.extend(data);
+    values.extend([0; 6]);
+    let polymod = bech32_polymod(&values) ^ 1;
+
+    let mut address = format!("{hrp}1");
+    address.extend(data.iter().map(|&d| BECH32_CHARSET[d as usize] as char));
+    address.extend(
+        (0..6).map(|i| BECH32_CHARSET[((polymod >> (5 * (5 - i))) & 0x1f) as usize] as char),
+    );
+    address
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut chk = 1u32;
+    for &value in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+/// Regroups `data` from groups of `from` bits into groups of `to` bits, padding the last group
+/// with zeros.
+fn convert_bits(data: &[u8], from: u32, to: u32) -> Vec<u8> {
+    let mut acc = 0u32;
+    let mut bits = 0u32;
+    let mut out = Vec::new();
+    let max = (1 << to) - 1;
+    for &value in data {
+        acc = (acc << from) | value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            out.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if bits > 0 {
+        out.push(((acc << (to - bits)) & max) as u8);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::elliptic_curve::CurveArithmetic;
+    use k256::Secp256k1;
+
+    // The generator is the public key of the secret key 1, which has well known addresses.
+    fn generator() -> PublicKey {
+        <Secp256k1 as CurveArithmetic>::ProjectivePoint::GENERATOR.to_affine()
+    }
+
+    #[test]
+    fn test_ethereum_address() {
+        assert_eq!(
+            ethereum_address(&generator()),
+            "0x7E5F4552091A69125d5DfCd7b8C2659029395Bdf"
+        );
+    }
+
+    #[test]
+    fn test_bitcoin_p2wpkh_address() {
+        // Test vectors from BIP-173.
+        assert_eq!(
+            bitcoin_p2wpkh_address(&generator(), BitcoinNetwork::Mainnet),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+        assert_eq!(
+            bitcoin_p2wpkh_address(&generator(), BitcoinNetwork::Testnet),
+            "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"
+        );
+    }
+}
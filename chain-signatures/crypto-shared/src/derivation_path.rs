@@ -0,0 +1,108 @@
+//! Grammar of the derivation paths accepted by the contract, so that wallets derive their keys
+//! from the same paths that the contract signs for.
+//!
+//! A canonical path is at most [`MAX_PATH_LEN`] bytes of printable ASCII without whitespace,
+//! made up of `/` separated segments that are not empty, e.g. `ethereum/1`. The empty path is
+//! canonical as well. The contract can be configured with a different maximum length, which is
+//! checked with [`validate_path_with_max_len`]. Paths are not rewritten by the contract, since that would change the keys
+//! derived from them, so a path has to be canonicalized before it gets signed for.
+
+use k256::Scalar;
+use near_account_id::AccountId;
+
+/// Longest derivation path in bytes.
+pub const MAX_PATH_LEN: usize = 256;
+
+/// Separator of the segments of a derivation path.
+pub const PATH_SEPARATOR: char = '/';
+
+/// Canonical form of `path`, with the surrounding whitespace trimmed and the empty segments
+/// removed. Fails if the path is too long or contains characters that are not allowed.
+pub fn canonicalize_path(path: &str) -> anyhow::Result<String> {
+    canonicalize_path_with_max_len(path, MAX_PATH_LEN)
+}
+
+/// Same as [`canonicalize_path`], except that the canonical path may be at most `max_len`
+/// bytes long.
+pub fn canonicalize_path_with_max_len(path: &str, max_len: usize) -> anyhow::Result<String> {
+    let canonical = path
+        .trim()
+        .split(PATH_SEPARATOR)
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    if let Some(invalid) = canonical.chars().find(|c| !c.is_ascii_graphic()) {
+        anyhow::bail!("path contains the invalid character {invalid:?}");
+    }
+    anyhow::ensure!(
+        canonical.len() <= max_len,
+        "path is {} bytes long, the maximum is {max_len}",
+        canonical.len()
+    );
+    Ok(canonical)
+}
+
+/// Checks that `path` is canonical, which is what the contract requires of signed paths.
+pub fn validate_path(path: &str) -> anyhow::Result<()> {
+    validate_path_with_max_len(path, MAX_PATH_LEN)
+}
+
+/// Same as [`validate_path`], except that the path may be at most `max_len` bytes long.
+pub fn validate_path_with_max_len(path: &str, max_len: usize) -> anyhow::Result<()> {
+    let canonical = canonicalize_path_with_max_len(path, max_len)?;
+    anyhow::ensure!(
+        canonical == path,
+        "path is not canonical, use {canonical:?} instead"
+    );
+    Ok(())
+}
+
+/// Epsilon of the key derived for `predecessor_id` from the canonical form of `path`, which is
+/// the same epsilon the contract signs with once the canonical path is requested.
+pub fn derive_epsilon(path: &str, predecessor_id: &AccountId) -> anyhow::Result<Scalar> {
+    let canonical = canonicalize_path(path)?;
+    Ok(crate::kdf::derive_epsilon(predecessor_id, &canonical))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonicalize_path() {
+        assert_eq!(canonicalize_path("").unwrap(), "");
+        assert_eq!(canonicalize_path("test").unwrap(), "test");
+        assert_eq!(canonicalize_path(" /ethereum//1/ ").unwrap(), "ethereum/1");
+        assert_eq!(
+            canonicalize_path("m/44'/60'/0'/0/0").unwrap(),
+            "m/44'/60'/0'/0/0"
+        );
+        assert!(canonicalize_path("with space").is_err());
+        assert!(canonicalize_path("tab\there").is_err());
+        assert!(canonicalize_path("ünïcode").is_err());
+        assert!(canonicalize_path(&"a".repeat(MAX_PATH_LEN)).is_ok());
+        assert!(canonicalize_path(&"a".repeat(MAX_PATH_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_validate_path() {
+        assert!(validate_path("").is_ok());
+        assert!(validate_path("ethereum/1").is_ok());
+        assert!(validate_path("/ethereum/1").is_err());
+        assert!(validate_path("ethereum//1").is_err());
+        assert!(validate_path("ethereum/1 ").is_err());
+        assert!(validate_path_with_max_len("ethereum/1", 10).is_ok());
+        assert!(validate_path_with_max_len("ethereum/12", 10).is_err());
+        assert!(validate_path_with_max_len(&"a".repeat(MAX_PATH_LEN + 1), 512).is_ok());
+    }
+
+    #[test]
+    fn test_derive_epsilon_canonical() {
+        let predecessor_id: AccountId = "wallet.near".parse().unwrap();
+        assert_eq!(
+            derive_epsilon("/ethereum/1/", &predecessor_id).unwrap(),
+            crate::kdf::derive_epsilon(&predecessor_id, "ethereum/1")
+        );
+        assert!(derive_epsilon("ethereum 1", &predecessor_id).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! Hashing of [EIP-712](https://eips.ethereum.org/EIPS/eip-712) typed data, so that the contract
+//! and the nodes agree on the digest that gets signed for a typed data request.
+
+use anyhow::Context;
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+/// The `EIP712Domain` of typed data. Only the fields that are set are part of the domain type,
+/// in the order defined by the EIP.
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, BorshSerialize, BorshDeserialize,
+)]
+pub struct Eip712Domain {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub chain_id: Option<u64>,
+    /// Hex encoded address of the contract that verifies the signature, e.g. `0xCcCC..cccC`.
+    pub verifying_contract: Option<String>,
+    pub salt: Option<[u8; 32]>,
+}
+
+impl Eip712Domain {
+    /// Encoded type of the domain, e.g. `EIP712Domain(string name,uint256 chainId)`.
+    pub fn encode_type(&self) -> String {
+        let fields = [
+            self.name.as_ref().map(|_| "string name"),
+            self.version.as_ref().map(|_| "string version"),
+            self.chain_id.map(|_| "uint256 chainId"),
+            self.verifying_contract
+                .as_ref()
+                .map(|_| "address verifyingContract"),
+            self.salt.map(|_| "bytes32 salt"),
+        ];
+        let fields: Vec<_> = fields.into_iter().flatten().collect();
+        format!("EIP712Domain({})", fields.join(","))
+    }
+
+    /// `hashStruct` of the domain. Fails if the verifying contract is not a valid address.
+    pub fn separator(&self) -> anyhow::Result<[u8; 32]> {
+        let mut encoded = Vec::with_capacity(32 * 6);
+        encoded.extend(keccak256(self.encode_type().as_bytes()));
+        if let Some(name) = &self.name {
+            encoded.extend(keccak256(name.as_bytes()));
+        }
+        if let Some(version) = &self.version {
+            encoded.extend(keccak256(version.as_bytes()));
+        }
+        if let Some(chain_id) = self.chain_id {
+            encoded.extend([0; 24]);
+            encoded.extend(chain_id.to_be_bytes());
+        }
+        if let Some(verifying_contract) = &self.verifying_contract {
+            encoded.extend([0; 12]);
+            encoded.extend(parse_address(verifying_contract)?);
+        }
+        if let Some(salt) = self.salt {
+            encoded.extend(salt);
+        }
+        Ok(keccak256(&encoded))
+    }
+}
+
+/// Digest that gets signed for typed data, i.e. `keccak256("\x19\x01" ‖ domainSeparator ‖
+/// hashStruct(message))`.
+pub fn typed_data_digest(domain_separator: &[u8; 32], struct_hash: &[u8; 32]) -> [u8; 32] {
+    let mut encoded = Vec::with_capacity(2 + 32 + 32);
+    encoded.extend([0x19, 0x01]);
+    encoded.extend(domain_separator);
+    encoded.extend(struct_hash);
+    keccak256(&encoded)
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+fn parse_address(address: &str) -> anyhow::Result<[u8; 20]> {
+    let hex = address.strip_prefix("0x").unwrap_or(address);
+    anyhow::ensure!(hex.len() == 40, "address must be 20 bytes: {address}");
+    let mut bytes = [0; 20];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+            .with_context(|| format!("address is not hex encoded: {address}"))?;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    // The `Mail` example of the EIP.
+    #[test]
+    fn test_typed_data_digest() {
+        let domain = Eip712Domain {
+            name: Some("Ether Mail".to_string()),
+            version: Some("1".to_string()),
+            chain_id: Some(1),
+            verifying_contract: Some("0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC".to_string()),
+            salt: None,
+        };
+        assert_eq!(
+            domain.encode_type(),
+            "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)"
+        );
+        let separator = domain.separator().unwrap();
+        assert_eq!(
+            separator,
+            from_hex("f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f")
+        );
+
+        let struct_hash =
+            from_hex("c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e");
+        assert_eq!(
+            typed_data_digest(&separator, &struct_hash),
+            from_hex("be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2")
+        );
+    }
+
+    #[test]
+    fn test_invalid_verifying_contract() {
+        let domain = Eip712Domain {
+            verifying_contract: Some("0x1234".to_string()),
+            ..Default::default()
+        };
+        assert!(domain.separator().is_err());
+    }
+}
@@ -1,9 +1,15 @@
+pub mod address;
+pub mod derivation_path;
+pub mod eip712;
 pub mod kdf;
+pub mod message;
 pub mod types;
 
+pub use address::{bitcoin_p2wpkh_address, ethereum_address, BitcoinNetwork, DerivedAddresses};
 use k256::elliptic_curve::sec1::FromEncodedPoint;
 use k256::EncodedPoint;
 pub use kdf::{derive_epsilon, derive_key, x_coordinate};
+pub use message::{HashAlgorithm, SignMessage};
 pub use types::{
     PublicKey, ScalarExt, SerializableAffinePoint, SerializableScalar, SignatureResponse,
 };
@@ -0,0 +1,94 @@
+//! Hashing of full messages submitted for signing, so that the contract and the nodes agree on
+//! the digest that gets signed when a sign request carries a message instead of a payload.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+
+/// Hash function that turns a message into the 32 byte payload that gets signed.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    BorshSerialize,
+    BorshDeserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    /// SHA-256, e.g. the second round of the double SHA-256 of a Bitcoin sighash preimage.
+    Sha256,
+    /// Keccak-256 as used by Ethereum, e.g. for the RLP encoding of a transaction.
+    Keccak256,
+}
+
+impl HashAlgorithm {
+    pub fn digest(&self, message: &[u8]) -> [u8; 32] {
+        match self {
+            HashAlgorithm::Sha256 => Sha256::digest(message).into(),
+            HashAlgorithm::Keccak256 => Keccak256::digest(message).into(),
+        }
+    }
+}
+
+/// A message of any length that gets hashed with `hash` before being signed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
+pub struct SignMessage {
+    pub data: Vec<u8>,
+    pub hash: HashAlgorithm,
+}
+
+impl SignMessage {
+    pub fn new(data: impl Into<Vec<u8>>, hash: HashAlgorithm) -> Self {
+        Self {
+            data: data.into(),
+            hash,
+        }
+    }
+
+    /// The payload that gets signed for this message.
+    pub fn digest(&self) -> [u8; 32] {
+        self.hash.digest(&self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_message_digest() {
+        // Digests of the empty message.
+        assert_eq!(
+            SignMessage::new(Vec::new(), HashAlgorithm::Sha256).digest(),
+            from_hex("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+        assert_eq!(
+            SignMessage::new(Vec::new(), HashAlgorithm::Keccak256).digest(),
+            from_hex("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470")
+        );
+    }
+
+    #[test]
+    fn test_sign_message_json() {
+        let message: SignMessage =
+            serde_json::from_str(r#"{"data": [1, 2, 3], "hash": "keccak256"}"#).unwrap();
+        assert_eq!(
+            message,
+            SignMessage::new([1, 2, 3], HashAlgorithm::Keccak256)
+        );
+    }
+}
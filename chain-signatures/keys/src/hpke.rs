@@ -1,6 +1,7 @@
 use std::io;
 
 use borsh::{self, BorshDeserialize, BorshSerialize};
+use hkdf::Hkdf;
 use hpke::{
     aead::{AeadTag, ChaCha20Poly1305},
     kdf::HkdfSha384,
@@ -8,6 +9,7 @@ use hpke::{
     OpModeR,
 };
 use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Sha384};
 
 /// This can be used to customize the generated key. This will be used as a sort of
 /// versioning mechanism for the key. It's additional context about who is encrypting
@@ -80,6 +82,128 @@ impl PublicKey {
     }
 }
 
+impl Ciphered {
+    /// Decrypts the message given `dh`, the X25519 shared secret of the recipient's secret key
+    /// and the encapped key. This lets the Diffie-Hellman step happen where the secret key can not
+    /// be read from, such as an HSM, while the rest of the HPKE key schedule (RFC 9180) runs here.
+    pub fn decrypt_with_dh(
+        &self,
+        recipient: &PublicKey,
+        dh: &[u8],
+        associated_data: &[u8],
+    ) -> Result<Vec<u8>, hpke::HpkeError> {
+        use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+
+        // An all-zero shared secret means the encapped key was a low order point.
+        if dh.len() != 32 || dh.iter().all(|byte| *byte == 0) {
+            return Err(hpke::HpkeError::OpenError);
+        }
+
+        // DHKEM(X25519, HKDF-SHA256) decapsulation.
+        let kem_suite = [
+            b"KEM".as_slice(),
+            <Kem as hpke::Kem>::KEM_ID.to_be_bytes().as_slice(),
+        ]
+        .concat();
+        let kem_context = [
+            self.encapped_key.to_bytes().as_slice(),
+            recipient.to_bytes().as_slice(),
+        ]
+        .concat();
+        let (_, eae_prk) = Hkdf::<Sha256>::extract(None, &labeled_ikm(&kem_suite, b"eae_prk", dh));
+        let mut shared_secret = [0; 32];
+        eae_prk
+            .expand(
+                &labeled_info(32, &kem_suite, b"shared_secret", &kem_context),
+                &mut shared_secret,
+            )
+            .map_err(|_| hpke::HpkeError::KdfOutputTooLong)?;
+
+        // Key schedule of the base mode with the HKDF-SHA384 KDF.
+        let suite = [
+            b"HPKE".as_slice(),
+            <Kem as hpke::Kem>::KEM_ID.to_be_bytes().as_slice(),
+            <Kdf as hpke::kdf::Kdf>::KDF_ID.to_be_bytes().as_slice(),
+            <Aead as hpke::aead::Aead>::AEAD_ID.to_be_bytes().as_slice(),
+        ]
+        .concat();
+        let (psk_id_hash, _) =
+            Hkdf::<Sha384>::extract(None, &labeled_ikm(&suite, b"psk_id_hash", b""));
+        let (info_hash, _) =
+            Hkdf::<Sha384>::extract(None, &labeled_ikm(&suite, b"info_hash", INFO_ENTROPY));
+        let context = [
+            [0u8].as_slice(),
+            psk_id_hash.as_slice(),
+            info_hash.as_slice(),
+        ]
+        .concat();
+        let (_, secret) = Hkdf::<Sha384>::extract(
+            Some(shared_secret.as_slice()),
+            &labeled_ikm(&suite, b"secret", b""),
+        );
+        let mut key = [0; 32];
+        let mut nonce = [0; 12];
+        secret
+            .expand(&labeled_info(32, &suite, b"key", &context), &mut key)
+            .and_then(|_| {
+                secret.expand(
+                    &labeled_info(12, &suite, b"base_nonce", &context),
+                    &mut nonce,
+                )
+            })
+            .map_err(|_| hpke::HpkeError::KdfOutputTooLong)?;
+
+        // The message is the first and only one of its context, so the nonce is the base nonce.
+        let mut plaintext = self.text.to_vec();
+        chacha20poly1305::ChaCha20Poly1305::new(&key.into())
+            .decrypt_in_place_detached(
+                &nonce.into(),
+                associated_data,
+                &mut plaintext,
+                chacha20poly1305::Tag::from_slice(&self.tag.to_bytes()),
+            )
+            .map_err(|_| hpke::HpkeError::OpenError)?;
+        Ok(plaintext)
+    }
+}
+
+/// `LabeledExtract` input keying material of RFC 9180.
+fn labeled_ikm(suite_id: &[u8], label: &[u8], ikm: &[u8]) -> Vec<u8> {
+    [b"HPKE-v1".as_slice(), suite_id, label, ikm].concat()
+}
+
+/// `LabeledExpand` info of RFC 9180.
+fn labeled_info(len: u16, suite_id: &[u8], label: &[u8], info: &[u8]) -> Vec<u8> {
+    [
+        len.to_be_bytes().as_slice(),
+        b"HPKE-v1",
+        suite_id,
+        label,
+        info,
+    ]
+    .concat()
+}
+
+impl EncappedKey {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        hpke::Serializable::to_bytes(&self.0).to_vec()
+    }
+
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, hpke::HpkeError> {
+        Ok(Self(hpke::Deserializable::from_bytes(bytes)?))
+    }
+}
+
+impl Tag {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        hpke::Serializable::to_bytes(&self.0).to_vec()
+    }
+
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, hpke::HpkeError> {
+        Ok(Self(hpke::Deserializable::from_bytes(bytes)?))
+    }
+}
+
 impl BorshSerialize for PublicKey {
     fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
         BorshSerialize::serialize(&self.to_bytes(), writer)
@@ -150,6 +274,29 @@ mod tests {
         assert_eq!(msg, &decrypted[..]);
     }
 
+    #[test]
+    fn test_decrypt_with_dh() {
+        let (sk, pk) = super::generate();
+        let msg = b"hello world";
+        let associated_data = b"associated data";
+        let cipher = pk.encrypt(msg, associated_data).unwrap();
+
+        let encapped_key: [u8; 32] = cipher.encapped_key.to_bytes().try_into().unwrap();
+        let dh = x25519_dalek::StaticSecret::from(sk.to_bytes())
+            .diffie_hellman(&x25519_dalek::PublicKey::from(encapped_key));
+        let decrypted = cipher
+            .decrypt_with_dh(&pk, dh.as_bytes(), associated_data)
+            .unwrap();
+        assert_eq!(msg, &decrypted[..]);
+
+        assert!(cipher
+            .decrypt_with_dh(&pk, dh.as_bytes(), b"other associated data")
+            .is_err());
+        assert!(cipher
+            .decrypt_with_dh(&pk, &[0; 32], associated_data)
+            .is_err());
+    }
+
     #[test]
     fn test_serialization_format() {
         let sk_hex = "cf3df427dc1377914349b592cfff8deb4b9f8ab1cc4baa8e8e004b6502ac1ca0";
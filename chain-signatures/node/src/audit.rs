@@ -0,0 +1,285 @@
+//! Append-only audit log of the privileged operations of the node: reads and writes of its key
+//! share, resharings, the votes it casts on the contract and calls to its admin API. Every record
+//! carries the hash of the record before it, so that records removed from or edited in the log
+//! file can be told apart from the ones the node wrote.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use near_account_id::AccountId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+use crate::gcp::{GcpService, LoggingService, SecretResult};
+use crate::protocol::state::PersistentNodeData;
+use crate::storage::secret_storage::{SecretStorage, SecretStorageBox};
+
+/// Name of the log the records are written to in Cloud Logging.
+const CLOUD_LOG_ID: &str = "mpc-audit";
+
+static AUDIT_LOG: once_cell::sync::OnceCell<mpsc::UnboundedSender<AuditRecord>> =
+    once_cell::sync::OnceCell::new();
+
+#[derive(Debug, Clone, Default, clap::Parser)]
+#[group(id = "audit_options")]
+pub struct Options {
+    /// File to append the audit log to, one JSON record per line. Privileged operations are
+    /// only written to the regular logs when not provided.
+    #[clap(long, env("MPC_AUDIT_LOG_PATH"))]
+    pub audit_log_path: Option<PathBuf>,
+    /// Also write the audit log to Cloud Logging of `gcp_project_id`, as the `mpc-audit` log.
+    #[clap(long, env("MPC_AUDIT_LOG_GCP"))]
+    pub audit_log_gcp: bool,
+}
+
+impl Options {
+    pub fn into_str_args(self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(audit_log_path) = self.audit_log_path {
+            args.extend([
+                "--audit-log-path".to_string(),
+                audit_log_path.display().to_string(),
+            ]);
+        }
+        if self.audit_log_gcp {
+            args.push("--audit-log-gcp".to_string());
+        }
+        args
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    KeyShareRead {
+        /// Epoch of the key share that was read, if there was one.
+        epoch: Option<u64>,
+    },
+    KeyShareStored {
+        epoch: u64,
+    },
+    ResharingStarted {
+        old_epoch: u64,
+        old_participants: Vec<AccountId>,
+        new_participants: Vec<AccountId>,
+    },
+    ResharingCompleted {
+        epoch: u64,
+    },
+    /// A call of the node to a voting method of the contract, such as `vote_pk` or `vote_leave`.
+    VoteCast {
+        method: String,
+        args: serde_json::Value,
+        /// What the contract returned for the vote.
+        result: bool,
+    },
+    AdminCall {
+        endpoint: String,
+        remote_addr: SocketAddr,
+        authorized: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Unix timestamp in milliseconds of when the operation happened.
+    pub timestamp: i64,
+    /// Who performed the operation: the account of the node, or `admin` for calls to the
+    /// admin API.
+    pub actor: String,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+    /// Hex encoded SHA-256 of the previous line of the log file, or empty for the first one.
+    #[serde(default)]
+    pub prev_hash: String,
+}
+
+/// Starts writing the audit log configured in `options`. Has to be called from within the
+/// runtime of the node, before any record is made.
+pub async fn init(options: &Options, gcp_service: &GcpService) -> anyhow::Result<()> {
+    let file = match &options.audit_log_path {
+        Some(path) => Some(AuditFile::open(path).await?),
+        None => None,
+    };
+    let cloud = options.audit_log_gcp.then(|| gcp_service.logging.clone());
+    if file.is_none() && cloud.is_none() {
+        return Ok(());
+    }
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    if AUDIT_LOG.set(sender).is_err() {
+        anyhow::bail!("audit log was already initialized");
+    }
+    tokio::spawn(write_records(receiver, file, cloud));
+    tracing::info!(path = ?options.audit_log_path, cloud = options.audit_log_gcp, "audit log enabled");
+    Ok(())
+}
+
+/// Records that `actor` performed the operation `event`.
+pub fn record(actor: impl ToString, event: AuditEvent) {
+    let record = AuditRecord {
+        timestamp: Utc::now().timestamp_millis(),
+        actor: actor.to_string(),
+        event,
+        prev_hash: String::new(),
+    };
+    tracing::info!(target: "audit", actor = %record.actor, event = ?record.event, "audit");
+    if let Some(sender) = AUDIT_LOG.get() {
+        if sender.send(record).is_err() {
+            tracing::error!("audit log writer stopped, dropping audit record");
+        }
+    }
+}
+
+async fn write_records(
+    mut receiver: mpsc::UnboundedReceiver<AuditRecord>,
+    mut file: Option<AuditFile>,
+    cloud: Option<LoggingService>,
+) {
+    while let Some(mut record) = receiver.recv().await {
+        if let Some(file) = &mut file {
+            if let Err(err) = file.append(&mut record).await {
+                tracing::error!(?err, ?record, "failed to append to audit log file");
+            }
+        }
+        if let Some(cloud) = &cloud {
+            let payload = match serde_json::to_value(&record) {
+                Ok(payload) => payload,
+                Err(err) => {
+                    tracing::error!(?err, "failed to serialize audit record");
+                    continue;
+                }
+            };
+            if let Err(err) = cloud.write_entry(CLOUD_LOG_ID, payload).await {
+                tracing::error!(
+                    ?err,
+                    ?record,
+                    "failed to write audit record to cloud logging"
+                );
+            }
+        }
+    }
+}
+
+/// The log file, which is only ever appended to.
+struct AuditFile {
+    file: tokio::fs::File,
+    last_hash: String,
+}
+
+impl AuditFile {
+    async fn open(path: &Path) -> anyhow::Result<Self> {
+        // Continue the hash chain of the records written by earlier runs of the node.
+        let last_hash = match tokio::fs::read(path).await {
+            Ok(contents) => contents
+                .split(|byte| *byte == b'\n')
+                .rev()
+                .find(|line| !line.is_empty())
+                .map(|line| hex::encode(Sha256::digest(line)))
+                .unwrap_or_default(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        Ok(Self { file, last_hash })
+    }
+
+    async fn append(&mut self, record: &mut AuditRecord) -> anyhow::Result<()> {
+        record.prev_hash = self.last_hash.clone();
+        let line = serde_json::to_vec(record)?;
+        self.file.write_all(&line).await?;
+        self.file.write_all(b"\n").await?;
+        self.file.sync_data().await?;
+        self.last_hash = hex::encode(Sha256::digest(&line));
+        Ok(())
+    }
+}
+
+/// Records every read and write of the key share kept in the wrapped storage.
+pub struct AuditedSecretStorage {
+    inner: SecretStorageBox,
+    account_id: AccountId,
+}
+
+impl AuditedSecretStorage {
+    pub fn new(inner: SecretStorageBox, account_id: &AccountId) -> Self {
+        Self {
+            inner,
+            account_id: account_id.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretStorage for AuditedSecretStorage {
+    async fn store(&mut self, data: &PersistentNodeData) -> SecretResult<()> {
+        self.inner.store(data).await?;
+        record(
+            &self.account_id,
+            AuditEvent::KeyShareStored { epoch: data.epoch },
+        );
+        Ok(())
+    }
+
+    async fn load(&self) -> SecretResult<Option<PersistentNodeData>> {
+        let data = self.inner.load().await?;
+        record(
+            &self.account_id,
+            AuditEvent::KeyShareRead {
+                epoch: data.as_ref().map(|data| data.epoch),
+            },
+        );
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_audit_file_hash_chain() {
+        let path = std::env::temp_dir().join(format!("{}-audit.log", std::process::id()));
+        let event = AuditEvent::KeyShareStored { epoch: 1 };
+        let new_record = || AuditRecord {
+            timestamp: 0,
+            actor: "node.test".to_string(),
+            event: event.clone(),
+            prev_hash: String::new(),
+        };
+
+        let mut file = AuditFile::open(&path).await.unwrap();
+        file.append(&mut new_record()).await.unwrap();
+        file.append(&mut new_record()).await.unwrap();
+        drop(file);
+        // Reopening the file continues the chain where it left off.
+        let mut file = AuditFile::open(&path).await.unwrap();
+        file.append(&mut new_record()).await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        let records = lines
+            .iter()
+            .map(|line| serde_json::from_str::<AuditRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records[0].prev_hash, "");
+        for (record, prev_line) in records.iter().skip(1).zip(&lines) {
+            assert_eq!(
+                record.prev_hash,
+                hex::encode(Sha256::digest(prev_line.as_bytes()))
+            );
+            assert_eq!(record.event, event);
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+//! Moves the key share of a node from one secret storage backend to another, e.g. from a local
+//! JSON file to GCP Secret Manager or AWS Secrets Manager. The backends are configured with the
+//! same storage options as the node itself. The key share is checked against the public key of
+//! the contract before it is written, and read back from the destination afterwards.
+
+use clap::Parser;
+use mpc_node::gcp::GcpService;
+use mpc_node::storage;
+use mpc_node::storage::secret_storage::{migrate, SecretStorageKind};
+use near_account_id::AccountId;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser, Debug)]
+#[command(name = "migrate-keyshare")]
+struct Args {
+    /// This node's account id
+    #[arg(long, env("MPC_ACCOUNT_ID"))]
+    account_id: AccountId,
+    /// Backend to read the key share from.
+    #[arg(long, value_enum)]
+    from: SecretStorageKind,
+    /// Backend to write the key share to.
+    #[arg(long, value_enum)]
+    to: SecretStorageKind,
+    /// NEAR RPC address
+    #[arg(
+        long,
+        env("MPC_NEAR_RPC"),
+        default_value("https://rpc.testnet.near.org")
+    )]
+    near_rpc: String,
+    /// MPC contract id
+    #[arg(long, env("MPC_CONTRACT_ID"), default_value("v1.signer-dev.testnet"))]
+    mpc_contract_id: AccountId,
+    /// Skip checking the key share against the public key of the contract, for migrating
+    /// without access to a NEAR RPC.
+    #[arg(long)]
+    skip_contract_check: bool,
+    /// Overwrite a key share the destination already has.
+    #[arg(long)]
+    force: bool,
+    /// Storage options of both backends
+    #[clap(flatten)]
+    storage_options: storage::Options,
+}
+
+fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+    let args = Args::parse();
+    if args.from == args.to {
+        anyhow::bail!("the key share has to be migrated to another backend");
+    }
+    if [args.from, args.to].contains(&SecretStorageKind::Memory) {
+        anyhow::bail!("key shares can not be migrated to or from memory");
+    }
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    rt.block_on(async {
+        let gcp_service = if [args.from, args.to].contains(&SecretStorageKind::Gcp) {
+            Some(GcpService::init(&args.account_id, &args.storage_options).await?)
+        } else {
+            None
+        };
+        let open = |kind| {
+            storage::secret_storage::open(
+                kind,
+                gcp_service.as_ref(),
+                &args.storage_options,
+                &args.account_id,
+            )
+        };
+        let from = open(args.from)?;
+        let mut to = open(args.to)?;
+
+        let state = if args.skip_contract_check {
+            tracing::warn!("not checking the key share against the contract");
+            None
+        } else {
+            let rpc_client = near_fetch::Client::new(&args.near_rpc);
+            Some(
+                mpc_node::rpc_client::fetch_mpc_contract_state(&rpc_client, &args.mpc_contract_id)
+                    .await?,
+            )
+        };
+        let node_data = migrate::migrate(
+            &from,
+            &mut to,
+            |node_data| match &state {
+                Some(state) => migrate::verify_against_contract(node_data, state),
+                None => Ok(()),
+            },
+            args.force,
+        )
+        .await?;
+        tracing::info!(
+            epoch = node_data.epoch,
+            from = %args.from,
+            to = %args.to,
+            "migrated key share"
+        );
+        anyhow::Ok(())
+    })
+}
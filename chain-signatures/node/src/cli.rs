@@ -1,21 +1,48 @@
-use crate::config::{Config, LocalConfig, NetworkConfig, OverrideConfig};
+use crate::audit::AuditedSecretStorage;
+use crate::config::{Config, ConfigReloader, LocalConfig, NetworkConfig, OverrideConfig};
 use crate::gcp::GcpService;
+use crate::hsm::{AccountSigner, CipherKey, Hsm, MessageSigner};
 use crate::protocol::{MpcSignProtocol, SignQueue};
-use crate::{http_client, indexer, mesh, storage, web};
+use crate::storage::backup::BackupTarget;
+use crate::storage::secret_storage::SecretStorageBox;
+use crate::storage::StorageCipher;
+use crate::{audit, hsm, http_client, indexer, mesh, storage, telemetry, web};
+use anyhow::Context;
 use clap::Parser;
 use deadpool_redis::Runtime;
 use local_ip_address::local_ip;
 use near_account_id::AccountId;
 use near_crypto::{InMemorySigner, SecretKey};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::{mpsc, RwLock};
+use tokio::sync::{broadcast, mpsc, RwLock};
 use tracing_stackdriver::layer as stackdriver_layer;
-use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
+use tracing_subscriber::{layer::SubscriberExt, reload, EnvFilter, Registry};
 use url::Url;
 
 use mpc_keys::hpke;
 
+/// Format of the logs the node writes when it is not running on GCP, where the logs are always
+/// in the stackdriver format.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, including the fields of the spans the log was emitted in, such
+    /// as the `request_id` of the sign request being worked on.
+    Json,
+}
+
+impl std::fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 pub enum Cli {
     Start {
@@ -32,19 +59,21 @@ pub enum Cli {
         /// This node's account id
         #[arg(long, env("MPC_ACCOUNT_ID"))]
         account_id: AccountId,
-        /// This node's account ed25519 secret key
-        #[arg(long, env("MPC_ACCOUNT_SK"))]
-        account_sk: SecretKey,
+        /// This node's account ed25519 secret key. Not needed when the account key is kept in
+        /// an HSM, see `hsm_options`.
+        #[arg(long, env("MPC_ACCOUNT_SK"), required_unless_present = "hsm_module")]
+        account_sk: Option<SecretKey>,
         /// The web port for this server
         #[arg(long, env("MPC_WEB_PORT"))]
         web_port: u16,
         // TODO: need to add in CipherPK type for parsing.
-        /// The cipher public key used to encrypt messages between nodes.
-        #[arg(long, env("MPC_CIPHER_PK"))]
-        cipher_pk: String,
+        /// The cipher public key used to encrypt messages between nodes. Loaded from Vault
+        /// together with the secret key when not provided, see `vault_cipher_key_path`.
+        #[arg(long, env("MPC_CIPHER_PK"), requires = "cipher_sk")]
+        cipher_pk: Option<String>,
         /// The cipher secret key used to decrypt messages between nodes.
-        #[arg(long, env("MPC_CIPHER_SK"))]
-        cipher_sk: String,
+        #[arg(long, env("MPC_CIPHER_SK"), requires = "cipher_pk")]
+        cipher_sk: Option<String>,
         /// The secret key used to sign messages to be sent between nodes.
         #[arg(long, env("MPC_SIGN_SK"))]
         sign_sk: Option<SecretKey>,
@@ -60,13 +89,124 @@ pub enum Cli {
         /// The set of configurations that we will use to override contract configurations.
         #[arg(long, env("MPC_OVERRIDE_CONFIG"), value_parser = clap::value_parser!(OverrideConfig))]
         override_config: Option<OverrideConfig>,
+        /// Overrides of the timeouts of the contract config, such as
+        /// `{"message_retry":{"max_retries":5}}`. They keep applying to every config fetched
+        /// from the contract.
+        #[arg(long, env("MPC_TIMEOUTS_CONFIG"), value_parser = clap::value_parser!(OverrideConfig))]
+        timeouts_config: Option<OverrideConfig>,
+        /// Number of threads that poke the ongoing triple generation protocols in parallel.
+        /// Defaults to one per available core.
+        #[arg(long, env("MPC_TRIPLE_POKE_THREADS"))]
+        triple_poke_threads: Option<usize>,
+        /// JSON file with the settings that can be changed while the node is running, such as
+        /// the log filter, stockpile targets and peer timeouts. It is reloaded on `SIGHUP` and
+        /// through `POST /admin/reload`.
+        #[arg(long, env("MPC_CONFIG_FILE"))]
+        config_file: Option<PathBuf>,
         /// referer header for mainnet whitelist
         #[arg(long, env("MPC_CLIENT_HEADER_REFERER"), default_value(None))]
         client_header_referer: Option<String>,
+        /// Token that has to be presented to use the admin endpoints of the node. The admin
+        /// endpoints are disabled when not provided.
+        #[arg(long, env("MPC_ADMIN_TOKEN"))]
+        admin_token: Option<String>,
         #[clap(flatten)]
         mesh_options: mesh::Options,
         #[clap(flatten)]
         message_options: http_client::Options,
+        /// HSM options
+        #[clap(flatten)]
+        hsm_options: hsm::Options,
+        /// Format of the logs written by the node.
+        #[arg(long, env("MPC_LOG_FORMAT"), value_enum, default_value_t = LogFormat::Text)]
+        log_format: LogFormat,
+        /// OpenTelemetry trace export options
+        #[clap(flatten)]
+        telemetry_options: telemetry::Options,
+        /// Audit log options
+        #[clap(flatten)]
+        audit_options: audit::Options,
+    },
+    /// Writes the persistent state of the node (key share, epoch and public key) to a file
+    /// encrypted to the node's cipher key, so that it can be moved to new hardware with
+    /// `import-state` without having to go through resharing.
+    ExportState {
+        /// This node's account id
+        #[arg(long, env("MPC_ACCOUNT_ID"))]
+        account_id: AccountId,
+        /// The cipher secret key of the node, the state gets encrypted to its public key.
+        #[arg(long, env("MPC_CIPHER_SK"))]
+        cipher_sk: String,
+        /// File to write the encrypted state to.
+        #[arg(long)]
+        path: PathBuf,
+        /// Storage options
+        #[clap(flatten)]
+        storage_options: storage::Options,
+    },
+    /// Loads a state written by `export-state` into the node's storage. The node has to use
+    /// the same account and cipher key as the one that exported it.
+    ImportState {
+        /// This node's account id
+        #[arg(long, env("MPC_ACCOUNT_ID"))]
+        account_id: AccountId,
+        /// The cipher secret key of the node, used to decrypt the state.
+        #[arg(long, env("MPC_CIPHER_SK"))]
+        cipher_sk: String,
+        /// File to read the encrypted state from.
+        #[arg(long)]
+        path: PathBuf,
+        /// Overwrite the state the node already has stored.
+        #[arg(long)]
+        force: bool,
+        /// Storage options
+        #[clap(flatten)]
+        storage_options: storage::Options,
+    },
+    /// Rewrites the key share file at `sk_share_local_path` encrypted with the configured
+    /// passphrase or transit key. Upgrades plaintext files written by earlier versions of the
+    /// node, and re-encrypts files that are already encrypted with a new salt or data key.
+    EncryptKeyShare {
+        /// This node's account id
+        #[arg(long, env("MPC_ACCOUNT_ID"))]
+        account_id: AccountId,
+        /// Storage options
+        #[clap(flatten)]
+        storage_options: storage::Options,
+    },
+    /// Generates a cold backup key and prints its hex encoded public and secret keys. The
+    /// public key is given to the node as `sk_share_backup_pk`, the secret key has to be kept
+    /// offline until a key share needs to be restored.
+    GenerateBackupKey,
+    /// Escrows the key share the node already has to `sk_share_backup_path`, for nodes that
+    /// enable backups after they got their key share. Nodes started with backups enabled
+    /// escrow every new key share themselves.
+    BackupKeyShare {
+        /// This node's account id
+        #[arg(long, env("MPC_ACCOUNT_ID"))]
+        account_id: AccountId,
+        /// Storage options
+        #[clap(flatten)]
+        storage_options: storage::Options,
+    },
+    /// Restores a key share escrowed to the backup key into the node's storage, after the
+    /// node lost its storage. Works without the node's cipher key.
+    RestoreKeyShare {
+        /// This node's account id
+        #[arg(long, env("MPC_ACCOUNT_ID"))]
+        account_id: AccountId,
+        /// The hex encoded secret key of the backup key.
+        #[arg(long, env("MPC_SK_SHARE_BACKUP_SK"))]
+        backup_sk: String,
+        /// Backup file to restore.
+        #[arg(long)]
+        path: PathBuf,
+        /// Overwrite the state the node already has stored.
+        #[arg(long)]
+        force: bool,
+        /// Storage options
+        #[clap(flatten)]
+        storage_options: storage::Options,
     },
 }
 
@@ -86,9 +226,17 @@ impl Cli {
                 my_address,
                 storage_options,
                 override_config,
+                timeouts_config,
+                triple_poke_threads,
+                config_file,
                 client_header_referer,
+                admin_token,
                 mesh_options,
                 message_options,
+                hsm_options,
+                log_format,
+                telemetry_options,
+                audit_options,
             } => {
                 let mut args = vec![
                     "start".to_string(),
@@ -98,17 +246,24 @@ impl Cli {
                     mpc_contract_id.to_string(),
                     "--account-id".to_string(),
                     account_id.to_string(),
-                    "--account-sk".to_string(),
-                    account_sk.to_string(),
                     "--web-port".to_string(),
                     web_port.to_string(),
-                    "--cipher-pk".to_string(),
-                    cipher_pk,
-                    "--cipher-sk".to_string(),
-                    cipher_sk,
                     "--redis-url".to_string(),
                     storage_options.redis_url.to_string(),
+                    "--log-format".to_string(),
+                    log_format.to_string(),
                 ];
+                if let Some(account_sk) = account_sk {
+                    args.extend(["--account-sk".to_string(), account_sk.to_string()]);
+                }
+                if let (Some(cipher_pk), Some(cipher_sk)) = (cipher_pk, cipher_sk) {
+                    args.extend([
+                        "--cipher-pk".to_string(),
+                        cipher_pk,
+                        "--cipher-sk".to_string(),
+                        cipher_sk,
+                    ]);
+                }
                 if let Some(sign_sk) = sign_sk {
                     args.extend(["--sign-sk".to_string(), sign_sk.to_string()]);
                 }
@@ -121,15 +276,136 @@ impl Cli {
                         serde_json::to_string(&override_config).unwrap(),
                     ]);
                 }
+                if let Some(timeouts_config) = timeouts_config {
+                    args.extend([
+                        "--timeouts-config".to_string(),
+                        serde_json::to_string(&timeouts_config).unwrap(),
+                    ]);
+                }
+                if let Some(triple_poke_threads) = triple_poke_threads {
+                    args.extend([
+                        "--triple-poke-threads".to_string(),
+                        triple_poke_threads.to_string(),
+                    ]);
+                }
+                if let Some(config_file) = config_file {
+                    args.extend([
+                        "--config-file".to_string(),
+                        config_file.display().to_string(),
+                    ]);
+                }
 
                 if let Some(client_header_referer) = client_header_referer {
                     args.extend(["--client-header-referer".to_string(), client_header_referer]);
                 }
+                if let Some(admin_token) = admin_token {
+                    args.extend(["--admin-token".to_string(), admin_token]);
+                }
 
                 args.extend(indexer_options.into_str_args());
                 args.extend(storage_options.into_str_args());
                 args.extend(mesh_options.into_str_args());
                 args.extend(message_options.into_str_args());
+                args.extend(hsm_options.into_str_args());
+                args.extend(telemetry_options.into_str_args());
+                args.extend(audit_options.into_str_args());
+                args
+            }
+            Cli::ExportState {
+                account_id,
+                cipher_sk,
+                path,
+                storage_options,
+            } => {
+                let mut args = vec![
+                    "export-state".to_string(),
+                    "--account-id".to_string(),
+                    account_id.to_string(),
+                    "--cipher-sk".to_string(),
+                    cipher_sk,
+                    "--path".to_string(),
+                    path.display().to_string(),
+                    "--redis-url".to_string(),
+                    storage_options.redis_url.to_string(),
+                ];
+                args.extend(storage_options.into_str_args());
+                args
+            }
+            Cli::ImportState {
+                account_id,
+                cipher_sk,
+                path,
+                force,
+                storage_options,
+            } => {
+                let mut args = vec![
+                    "import-state".to_string(),
+                    "--account-id".to_string(),
+                    account_id.to_string(),
+                    "--cipher-sk".to_string(),
+                    cipher_sk,
+                    "--path".to_string(),
+                    path.display().to_string(),
+                    "--redis-url".to_string(),
+                    storage_options.redis_url.to_string(),
+                ];
+                if force {
+                    args.push("--force".to_string());
+                }
+                args.extend(storage_options.into_str_args());
+                args
+            }
+            Cli::EncryptKeyShare {
+                account_id,
+                storage_options,
+            } => {
+                let mut args = vec![
+                    "encrypt-key-share".to_string(),
+                    "--account-id".to_string(),
+                    account_id.to_string(),
+                    "--redis-url".to_string(),
+                    storage_options.redis_url.to_string(),
+                ];
+                args.extend(storage_options.into_str_args());
+                args
+            }
+            Cli::GenerateBackupKey => vec!["generate-backup-key".to_string()],
+            Cli::BackupKeyShare {
+                account_id,
+                storage_options,
+            } => {
+                let mut args = vec![
+                    "backup-key-share".to_string(),
+                    "--account-id".to_string(),
+                    account_id.to_string(),
+                    "--redis-url".to_string(),
+                    storage_options.redis_url.to_string(),
+                ];
+                args.extend(storage_options.into_str_args());
+                args
+            }
+            Cli::RestoreKeyShare {
+                account_id,
+                backup_sk,
+                path,
+                force,
+                storage_options,
+            } => {
+                let mut args = vec![
+                    "restore-key-share".to_string(),
+                    "--account-id".to_string(),
+                    account_id.to_string(),
+                    "--backup-sk".to_string(),
+                    backup_sk,
+                    "--path".to_string(),
+                    path.display().to_string(),
+                    "--redis-url".to_string(),
+                    storage_options.redis_url.to_string(),
+                ];
+                if force {
+                    args.push("--force".to_string());
+                }
+                args.extend(storage_options.into_str_args());
                 args
             }
         }
@@ -156,16 +432,51 @@ fn is_running_on_gcp() -> bool {
 }
 
 pub fn run(cmd: Cli) -> anyhow::Result<()> {
+    run_with_secret_storage(cmd, None)
+}
+
+/// Same as [`run`], except that the node keeps its secret data in `secret_storage` when given,
+/// instead of in the backend picked by the storage options. This lets operators plug in their
+/// own [`SecretStorage`](storage::secret_storage::SecretStorage) backend.
+pub fn run_with_secret_storage(
+    cmd: Cli,
+    secret_storage: Option<SecretStorageBox>,
+) -> anyhow::Result<()> {
     // Install global collector configured based on RUST_LOG env var.
-    let base_subscriber = Registry::default().with(EnvFilter::from_default_env());
+    let (log_filter, log_filter_handle) = reload::Layer::new(EnvFilter::from_default_env());
+    let base_subscriber = Registry::default().with(log_filter);
 
-    let subscriber = if is_running_on_gcp() {
+    let (log_format, otlp_layer) = match &cmd {
+        Cli::Start {
+            log_format,
+            telemetry_options,
+            account_id,
+            ..
+        } => (
+            *log_format,
+            telemetry::layer(telemetry_options, account_id)?,
+        ),
+        _ => (LogFormat::default(), None),
+    };
+    let (fmt_layer, json_layer, stackdriver) = if is_running_on_gcp() {
         let stackdriver = stackdriver_layer().with_writer(std::io::stderr);
-        base_subscriber.with(None).with(Some(stackdriver))
+        (None, None, Some(stackdriver))
+    } else if log_format == LogFormat::Json {
+        let json_layer = tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_thread_ids(true);
+        (None, Some(json_layer), None)
     } else {
         let fmt_layer = tracing_subscriber::fmt::layer().with_thread_ids(true);
-        base_subscriber.with(Some(fmt_layer)).with(None)
+        (Some(fmt_layer), None, None)
     };
+    let subscriber = base_subscriber
+        .with(otlp_layer)
+        .with(fmt_layer)
+        .with(json_layer)
+        .with(stackdriver);
 
     tracing::subscriber::set_global_default(subscriber).expect("Failed to set subscriber");
 
@@ -185,16 +496,57 @@ pub fn run(cmd: Cli) -> anyhow::Result<()> {
             my_address,
             storage_options,
             override_config,
+            timeouts_config,
+            triple_poke_threads,
+            config_file,
             client_header_referer,
+            admin_token,
             mesh_options,
             message_options,
+            hsm_options,
+            log_format: _,
+            telemetry_options: _,
+            audit_options,
         } => {
-            let sign_queue = Arc::new(RwLock::new(SignQueue::new()));
+            crate::rng::init(&account_id);
+            crate::failpoint::init();
+            let (sign_events, _) = broadcast::channel(1024);
+            let sign_queue = Arc::new(RwLock::new(SignQueue::new(sign_events.clone())));
             let rt = tokio::runtime::Builder::new_multi_thread()
                 .enable_all()
                 .build()?;
             let gcp_service =
                 rt.block_on(async { GcpService::init(&account_id, &storage_options).await })?;
+            rt.block_on(audit::init(&audit_options, &gcp_service))?;
+            let hsm = hsm_options
+                .hsm_module
+                .as_deref()
+                .map(|module| {
+                    tracing::info!(module, "using hsm for the keys of the node");
+                    Hsm::connect(module, &hsm_options)
+                })
+                .transpose()?;
+            let (cipher_pk, cipher_sk) = match &hsm {
+                Some(hsm) => {
+                    let cipher_key = hsm.cipher_key(&hsm_options.hsm_cipher_key_label)?;
+                    (
+                        cipher_key.public_key(),
+                        CipherKey::Hsm(Arc::new(cipher_key)),
+                    )
+                }
+                None => {
+                    let (cipher_pk, cipher_sk) = match (cipher_pk, cipher_sk) {
+                        (Some(cipher_pk), Some(cipher_sk)) => (cipher_pk, cipher_sk),
+                        _ => rt.block_on(storage::secret_storage::load_cipher_keys(
+                            &storage_options,
+                        ))?,
+                    };
+                    (
+                        hpke::PublicKey::try_from_bytes(&hex::decode(cipher_pk)?)?,
+                        hpke::SecretKey::try_from_bytes(&hex::decode(cipher_sk)?)?.into(),
+                    )
+                }
+            };
             let (indexer_handle, indexer) = indexer::run(
                 &indexer_options,
                 &mpc_contract_id,
@@ -204,18 +556,47 @@ pub fn run(cmd: Cli) -> anyhow::Result<()> {
                 &rt,
             )?;
 
-            let key_storage =
-                storage::secret_storage::init(Some(&gcp_service), &storage_options, &account_id);
+            let key_storage = match secret_storage {
+                Some(secret_storage) => secret_storage,
+                None => storage::secret_storage::init(
+                    Some(&gcp_service),
+                    &storage_options,
+                    &account_id,
+                )?,
+            };
+            let key_storage = storage::backup::wrap(key_storage, &storage_options, &account_id)?;
+
+            let storage_cipher = StorageCipher::new(cipher_sk.clone());
 
             let redis_url: Url = Url::parse(storage_options.redis_url.as_str())?;
 
             let redis_cfg = deadpool_redis::Config::from_url(redis_url);
             let redis_pool = redis_cfg.create_pool(Some(Runtime::Tokio1)).unwrap();
-            let triple_storage = storage::triple_storage::init(&redis_pool, &account_id);
+            let triple_storage =
+                storage::triple_storage::init(&redis_pool, &account_id, &storage_cipher);
             let presignature_storage =
-                storage::presignature_storage::init(&redis_pool, &account_id);
+                storage::presignature_storage::init(&redis_pool, &account_id, &storage_cipher);
+            let publish_storage = storage::publish_storage::init(&redis_pool, &account_id);
+            let message_session =
+                rt.block_on(storage::message_session::next(&redis_pool, &account_id))?;
+            tracing::info!(message_session, "starting a new message session");
 
-            let sign_sk = sign_sk.unwrap_or_else(|| account_sk.clone());
+            let sign_sk = match &hsm {
+                Some(hsm) => {
+                    MessageSigner::Hsm(Arc::new(hsm.signer(&hsm_options.hsm_sign_key_label)?))
+                }
+                None => sign_sk
+                    .or_else(|| account_sk.clone())
+                    .context("--account-sk is required without an hsm")?
+                    .into(),
+            };
+            if message_options.mesh_mtls {
+                anyhow::ensure!(
+                    message_options.transport == http_client::Transport::Quic,
+                    "--mesh-mtls requires the quic transport"
+                );
+                crate::quic::init_mtls(&account_id, &sign_sk)?;
+            }
             let my_address = my_address
                 .map(|mut addr| {
                     addr.set_port(Some(web_port)).unwrap();
@@ -227,6 +608,8 @@ pub fn run(cmd: Cli) -> anyhow::Result<()> {
                 });
 
             let (sender, receiver) = mpsc::channel(16384);
+            let (config_sender, config_receiver) = mpsc::channel(16);
+            let (admin_sender, admin_receiver) = mpsc::channel(16);
 
             tracing::info!(%my_address, "address detected");
             let mut rpc_client = near_fetch::Client::new(&near_rpc);
@@ -236,7 +619,19 @@ pub fn run(cmd: Cli) -> anyhow::Result<()> {
             }
 
             tracing::info!(rpc_addr = rpc_client.rpc_addr(), "rpc client initialized");
-            let signer = InMemorySigner::from_secret_key(account_id.clone(), account_sk);
+            let signer = match &hsm {
+                Some(hsm) => AccountSigner::hsm(
+                    account_id.clone(),
+                    hsm.signer(&hsm_options.hsm_account_key_label)?,
+                ),
+                None => InMemorySigner::from_secret_key(
+                    account_id.clone(),
+                    account_sk.context("--account-sk is required without an hsm")?,
+                )
+                .into(),
+            };
+            let config_reloader = config_file
+                .map(|path| ConfigReloader::new(path, log_filter_handle, admin_sender.clone()));
             let (protocol, protocol_state) = MpcSignProtocol::init(
                 my_address,
                 mpc_contract_id,
@@ -244,28 +639,47 @@ pub fn run(cmd: Cli) -> anyhow::Result<()> {
                 rpc_client,
                 signer,
                 receiver,
+                config_receiver,
+                admin_receiver,
                 sign_queue,
+                sign_events.clone(),
                 key_storage,
                 triple_storage,
                 presignature_storage,
+                publish_storage,
                 Config::new(LocalConfig {
                     over: override_config.unwrap_or_else(Default::default),
-                    network: NetworkConfig {
-                        cipher_pk: hpke::PublicKey::try_from_bytes(&hex::decode(cipher_pk)?)?,
-                        sign_sk,
-                    },
+                    timeouts: timeouts_config.unwrap_or_else(Default::default),
+                    triple_poke_threads,
+                    network: NetworkConfig { cipher_pk, sign_sk },
                 }),
                 mesh_options,
                 message_options,
+                http_client::SequenceNumbers::new(message_session),
             );
 
             rt.block_on(async {
                 tracing::info!("protocol initialized");
+                if let Some(config_reloader) = &config_reloader {
+                    config_reloader.reload().await?;
+                    tokio::spawn(config_reloader.clone().reload_on_hangup());
+                }
                 let protocol_handle = tokio::spawn(async move { protocol.run().await });
                 tracing::info!("protocol thread spawned");
-                let cipher_sk = hpke::SecretKey::try_from_bytes(&hex::decode(cipher_sk)?)?;
                 let web_handle = tokio::spawn(async move {
-                    web::run(web_port, sender, cipher_sk, protocol_state, indexer).await
+                    web::run(
+                        web_port,
+                        sender,
+                        config_sender,
+                        admin_sender,
+                        cipher_sk,
+                        protocol_state,
+                        indexer,
+                        sign_events,
+                        admin_token,
+                        config_reloader,
+                    )
+                    .await
                 });
                 tracing::info!("protocol http server spawned");
 
@@ -277,7 +691,123 @@ pub fn run(cmd: Cli) -> anyhow::Result<()> {
                 anyhow::Ok(())
             })?;
         }
+        Cli::ExportState {
+            account_id,
+            cipher_sk,
+            path,
+            storage_options,
+        } => {
+            let cipher_sk = hpke::SecretKey::try_from_bytes(&hex::decode(cipher_sk)?)?;
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            rt.block_on(async {
+                let gcp_service = GcpService::init(&account_id, &storage_options).await?;
+                let key_storage = match secret_storage {
+                    Some(secret_storage) => secret_storage,
+                    None => storage::secret_storage::init(
+                        Some(&gcp_service),
+                        &storage_options,
+                        &account_id,
+                    )?,
+                };
+                storage::snapshot::export(&key_storage, &account_id, &cipher_sk, &path).await
+            })?;
+        }
+        Cli::ImportState {
+            account_id,
+            cipher_sk,
+            path,
+            force,
+            storage_options,
+        } => {
+            let cipher_sk = hpke::SecretKey::try_from_bytes(&hex::decode(cipher_sk)?)?;
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            rt.block_on(async {
+                let gcp_service = GcpService::init(&account_id, &storage_options).await?;
+                let mut key_storage = match secret_storage {
+                    Some(secret_storage) => secret_storage,
+                    None => storage::secret_storage::init(
+                        Some(&gcp_service),
+                        &storage_options,
+                        &account_id,
+                    )?,
+                };
+                storage::snapshot::import(&mut key_storage, &account_id, &cipher_sk, &path, force)
+                    .await
+            })?;
+        }
+        Cli::EncryptKeyShare {
+            account_id,
+            storage_options,
+        } => {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            rt.block_on(storage::secret_storage::encrypt_key_share(
+                &storage_options,
+                &account_id,
+            ))?;
+        }
+        Cli::GenerateBackupKey => {
+            let (backup_sk, backup_pk) = hpke::generate();
+            println!("backup_pk: {}", hex::encode(backup_pk.to_bytes()));
+            println!("backup_sk: {}", hex::encode(backup_sk.to_bytes()));
+        }
+        Cli::BackupKeyShare {
+            account_id,
+            storage_options,
+        } => {
+            let Some(target) = BackupTarget::from_options(&storage_options, &account_id)? else {
+                anyhow::bail!(
+                    "backing up the key share requires `sk_share_backup_pk` and `sk_share_backup_path`"
+                );
+            };
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            rt.block_on(async {
+                let gcp_service = GcpService::init(&account_id, &storage_options).await?;
+                let key_storage = match secret_storage {
+                    Some(secret_storage) => secret_storage,
+                    None => storage::secret_storage::init(
+                        Some(&gcp_service),
+                        &storage_options,
+                        &account_id,
+                    )?,
+                };
+                storage::backup::backup(&key_storage, &target).await
+            })?;
+        }
+        Cli::RestoreKeyShare {
+            account_id,
+            backup_sk,
+            path,
+            force,
+            storage_options,
+        } => {
+            let backup_sk = hpke::SecretKey::try_from_bytes(&hex::decode(backup_sk)?)?;
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            rt.block_on(async {
+                let gcp_service = GcpService::init(&account_id, &storage_options).await?;
+                let mut key_storage = match secret_storage {
+                    Some(secret_storage) => secret_storage,
+                    None => storage::secret_storage::init(
+                        Some(&gcp_service),
+                        &storage_options,
+                        &account_id,
+                    )?,
+                };
+                storage::backup::restore(&mut key_storage, &account_id, &backup_sk, &path, force)
+                    .await
+            })?;
+        }
     }
 
+    telemetry::shutdown();
     Ok(())
 }
@@ -1,11 +1,20 @@
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-use mpc_contract::config::ProtocolConfig;
+use anyhow::Context;
+use mpc_contract::config::{
+    EvictionConfig, PresignatureExpiryConfig, ProtocolConfig, SignRequestConfig, TimeoutConfig,
+};
 use mpc_keys::hpke;
 use near_account_id::AccountId;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tokio::sync::mpsc;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::hsm::MessageSigner;
+use crate::protocol::AdminCommand;
 
 /// The contract's config is a dynamic representation of all configurations possible.
 pub type ContractConfig = HashMap<String, Value>;
@@ -13,6 +22,10 @@ pub type ContractConfig = HashMap<String, Value>;
 #[derive(Clone, Debug, Default)]
 pub struct Config {
     pub protocol: ProtocolConfig,
+    pub sign_request: SignRequestConfig,
+    pub timeouts: TimeoutConfig,
+    pub presignature_expiry: PresignatureExpiryConfig,
+    pub eviction: EvictionConfig,
     pub local: LocalConfig,
 }
 
@@ -29,7 +42,23 @@ impl Config {
             }
         }
 
-        Self { protocol, local }
+        let timeouts = with_timeouts_override(&TimeoutConfig::default(), &local.timeouts)
+            .unwrap_or_else(|err| {
+                tracing::warn!(
+                    ?err,
+                    "unable to apply timeouts override, using the defaults"
+                );
+                TimeoutConfig::default()
+            });
+
+        Self {
+            protocol,
+            sign_request: SignRequestConfig::default(),
+            timeouts,
+            presignature_expiry: PresignatureExpiryConfig::default(),
+            eviction: EvictionConfig::default(),
+            local,
+        }
     }
 
     pub fn try_from_contract(mut contract: ContractConfig, original: &Config) -> Option<Self> {
@@ -42,13 +71,54 @@ impl Config {
             tracing::warn!("unable to parse protocol in contract config");
             return None;
         };
+        // Same as the contract, a missing or malformed entry falls back to the default.
+        let sign_request = contract
+            .remove("sign_request")
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default();
+        let timeouts: TimeoutConfig = contract
+            .remove("timeouts")
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default();
+        let timeouts =
+            with_timeouts_override(&timeouts, &original.local.timeouts).unwrap_or(timeouts);
+        let presignature_expiry = contract
+            .remove("presignature_expiry")
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default();
+        let eviction = contract
+            .remove("eviction")
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default();
 
         Some(Self {
             protocol,
+            sign_request,
+            timeouts,
+            presignature_expiry,
+            eviction,
             local: original.local.clone(),
         })
     }
 
+    /// Applies `over` on top of the current config. The overrides are also kept around in the
+    /// local config, so they keep applying to every config fetched from the contract afterwards.
+    pub fn apply_override(&mut self, over: &OverrideConfig) -> anyhow::Result<()> {
+        let mut protocol = serde_json::to_value(&self.protocol)?;
+        merge(&mut protocol, &over.entries);
+        self.protocol = serde_json::from_value(protocol)?;
+        merge(&mut self.local.over.entries, &over.entries);
+        Ok(())
+    }
+
+    /// Applies `over` on top of the current timeouts, and keeps it around for the timeouts
+    /// fetched from the contract afterwards like [`Config::apply_override`].
+    pub fn apply_timeouts_override(&mut self, over: &OverrideConfig) -> anyhow::Result<()> {
+        self.timeouts = with_timeouts_override(&self.timeouts, over)?;
+        merge(&mut self.local.timeouts.entries, &over.entries);
+        Ok(())
+    }
+
     /// Fetches the latest config from the contract and set the config inplace. The old config
     /// is returned when swap is completed.
     pub async fn fetch_inplace(
@@ -67,11 +137,16 @@ impl Config {
 pub struct LocalConfig {
     pub network: NetworkConfig,
     pub over: OverrideConfig,
+    /// Overrides of the `timeouts` entry of the contract config.
+    pub timeouts: OverrideConfig,
+    /// Number of threads poking the ongoing triple protocols, one per available core when not
+    /// set.
+    pub triple_poke_threads: Option<usize>,
 }
 
 #[derive(Clone, Debug)]
 pub struct NetworkConfig {
-    pub sign_sk: near_crypto::SecretKey,
+    pub sign_sk: MessageSigner,
     pub cipher_pk: hpke::PublicKey,
 }
 
@@ -81,7 +156,8 @@ impl Default for NetworkConfig {
             sign_sk: near_crypto::SecretKey::from_seed(
                 near_crypto::KeyType::ED25519,
                 "test-entropy",
-            ),
+            )
+            .into(),
             cipher_pk: hpke::PublicKey::from_bytes(&[0; 32]),
         }
     }
@@ -122,6 +198,117 @@ impl FromStr for OverrideConfig {
     }
 }
 
+/// Settings of the node that can be changed while it is running, by editing its config file
+/// and then sending it a `SIGHUP` or calling `POST /admin/reload`. Settings that are left out
+/// keep their current value.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    /// Log filter, in the same syntax as `RUST_LOG`.
+    pub log: Option<String>,
+    /// Overrides of the protocol config, such as the triple and presignature stockpile targets.
+    /// They are applied like the `--override-config` ones.
+    pub protocol: Option<Value>,
+    /// Timeout in milliseconds of fetching the state of a peer.
+    pub fetch_participant_timeout: Option<u64>,
+    /// How long in milliseconds the peers that answered a ping are considered active.
+    pub refresh_active_timeout: Option<u64>,
+    /// Timeout in milliseconds of delivering messages to a peer.
+    pub message_timeout: Option<u64>,
+    /// Overrides of the timeouts config, such as the backoff of retrying to deliver messages.
+    /// They are applied like the `--timeouts-config` ones.
+    pub timeouts: Option<Value>,
+}
+
+impl FileConfig {
+    /// Reads the config file at `path`, checking that all of its settings can be applied.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        let config: Self = serde_json::from_str(&file)
+            .with_context(|| format!("failed to parse config file {}", path.display()))?;
+        if let Some(log) = &config.log {
+            EnvFilter::try_new(log).context("invalid log filter")?;
+        }
+        if let Some(protocol) = &config.protocol {
+            Config::default()
+                .apply_override(&OverrideConfig::new(protocol.clone()))
+                .context("invalid protocol config")?;
+        }
+        if let Some(timeouts) = &config.timeouts {
+            Config::default()
+                .apply_timeouts_override(&OverrideConfig::new(timeouts.clone()))
+                .context("invalid timeouts config")?;
+        }
+        Ok(config)
+    }
+}
+
+/// Handle to change the log filter of the node while it is running.
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Applies the config file of the node. The log filter gets changed right away, everything
+/// else is handed over to the protocol loop, which applies it between two iterations so that
+/// the protocols that are in progress carry on.
+#[derive(Clone)]
+pub struct ConfigReloader {
+    path: PathBuf,
+    log_filter: LogFilterHandle,
+    admin_sender: mpsc::Sender<AdminCommand>,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        path: PathBuf,
+        log_filter: LogFilterHandle,
+        admin_sender: mpsc::Sender<AdminCommand>,
+    ) -> Self {
+        Self {
+            path,
+            log_filter,
+            admin_sender,
+        }
+    }
+
+    /// Loads the config file and applies it. Nothing is changed if the file is invalid.
+    pub async fn reload(&self) -> anyhow::Result<FileConfig> {
+        let config = FileConfig::load(&self.path)?;
+        if let Some(log) = &config.log {
+            self.log_filter.reload(EnvFilter::try_new(log)?)?;
+        }
+        self.admin_sender
+            .send(AdminCommand::Reload(config.clone()))
+            .await
+            .context("protocol is not running")?;
+        tracing::info!(path = %self.path.display(), ?config, "reloaded config file");
+        Ok(config)
+    }
+
+    /// Reloads the config file every time the node receives a `SIGHUP`.
+    pub async fn reload_on_hangup(self) -> anyhow::Result<()> {
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        while hangup.recv().await.is_some() {
+            if let Err(err) = self.reload().await {
+                tracing::error!(
+                    ?err,
+                    "failed to reload config file, keeping the current config"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The timeouts with the entries of `over` applied on top of them.
+fn with_timeouts_override(
+    timeouts: &TimeoutConfig,
+    over: &OverrideConfig,
+) -> anyhow::Result<TimeoutConfig> {
+    let mut timeouts = serde_json::to_value(timeouts)?;
+    merge(&mut timeouts, &over.entries);
+    Ok(serde_json::from_value(timeouts)?)
+}
+
 pub fn merge(base: &mut Value, new: &Value) {
     match (base, new) {
         (base @ &mut Value::Object(_), Value::Object(new)) => {
@@ -139,7 +326,7 @@ pub fn merge(base: &mut Value, new: &Value) {
 mod tests {
     use serde::Deserialize;
 
-    use super::merge;
+    use super::{merge, Config, ContractConfig, FileConfig, OverrideConfig};
 
     #[test]
     fn test_merge() {
@@ -177,4 +364,106 @@ mod tests {
         let base: Base = serde_json::from_value(base).unwrap();
         dbg!(base);
     }
+
+    #[test]
+    fn test_apply_override() {
+        let mut config = Config::default();
+        let max_triples = config.protocol.triple.max_triples;
+        config
+            .apply_override(&OverrideConfig::new(serde_json::json!({
+                "triple": {
+                    "min_triples": 42,
+                },
+            })))
+            .unwrap();
+        config
+            .apply_override(&OverrideConfig::new(serde_json::json!({
+                "presignature": {
+                    "min_presignatures": 7,
+                },
+            })))
+            .unwrap();
+
+        assert_eq!(config.protocol.triple.min_triples, 42);
+        assert_eq!(config.protocol.triple.max_triples, max_triples);
+        assert_eq!(config.protocol.presignature.min_presignatures, 7);
+
+        // Overrides are accumulated so they can be reapplied on top of the contract's config.
+        assert_eq!(
+            config.local.over.entries,
+            serde_json::json!({
+                "triple": { "min_triples": 42 },
+                "presignature": { "min_presignatures": 7 },
+            })
+        );
+    }
+
+    #[test]
+    fn test_timeouts_override() {
+        let mut config = Config::default();
+        config
+            .apply_timeouts_override(&OverrideConfig::new(serde_json::json!({
+                "message_retry": { "max_retries": 5 },
+            })))
+            .unwrap();
+        assert_eq!(config.timeouts.message_retry.max_retries, 5);
+
+        // Local overrides keep applying on top of the timeouts voted in the contract.
+        let contract = ContractConfig::from([
+            (
+                "protocol".to_string(),
+                serde_json::to_value(&config.protocol).unwrap(),
+            ),
+            (
+                "timeouts".to_string(),
+                serde_json::json!({
+                    "message_retry": { "initial_delay": 50, "max_delay": 200, "max_retries": 1 },
+                    "signature_publish_retry": { "initial_delay": 10, "max_delay": 20, "max_retries": 2 },
+                }),
+            ),
+        ]);
+        let fetched = Config::try_from_contract(contract, &config).unwrap();
+        assert_eq!(fetched.timeouts.message_retry.initial_delay, 50);
+        assert_eq!(fetched.timeouts.message_retry.max_retries, 5);
+        assert_eq!(fetched.timeouts.signature_publish_retry.max_retries, 2);
+    }
+
+    #[test]
+    fn test_load_file_config() {
+        let dir = std::env::temp_dir().join(format!("mpc-node-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+
+        std::fs::write(
+            &path,
+            r#"{
+                "log": "info,mpc_node=debug",
+                "protocol": { "triple": { "min_triples": 42 } },
+                "message_timeout": 2000
+            }"#,
+        )
+        .unwrap();
+        let config = FileConfig::load(&path).unwrap();
+        assert_eq!(config.log.as_deref(), Some("info,mpc_node=debug"));
+        assert_eq!(config.message_timeout, Some(2000));
+        assert_eq!(config.fetch_participant_timeout, None);
+
+        // Files with settings that can not be applied are rejected as a whole.
+        std::fs::write(
+            &path,
+            r#"{ "protocol": { "triple": { "min_triples": "many" } } }"#,
+        )
+        .unwrap();
+        assert!(FileConfig::load(&path).is_err());
+        std::fs::write(&path, r#"{ "log_level": "debug" }"#).unwrap();
+        assert!(FileConfig::load(&path).is_err());
+        std::fs::write(
+            &path,
+            r#"{ "timeouts": { "message_retry": { "max_retries": -1 } } }"#,
+        )
+        .unwrap();
+        assert!(FileConfig::load(&path).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
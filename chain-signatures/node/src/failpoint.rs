@@ -0,0 +1,284 @@
+//! Failure injection for tests. With the `failpoints` feature, the failpoints placed throughout
+//! the protocol can be armed through `MPC_FAILPOINTS`, so that tests can reproduce the failures
+//! and races seen in production, such as a dropped triple message or a node crashing in the
+//! middle of a presignature. Without the feature every failpoint is a no-op.
+//!
+//! `MPC_FAILPOINTS` is a `;` separated list of `<name>[@<key>]=[<count>*]<action>` entries, e.g.
+//! `triple_drop_message@1234=return;signature_publish=2*sleep(5000)`, where the action is one of:
+//! - `off`: the failpoint does nothing.
+//! - `return[(<arg>)]`: the code at the failpoint bails out, see the failpoint for what that means.
+//! - `sleep(<ms>)`: the node gets delayed at the failpoint.
+//! - `panic[(<msg>)]`: the task running into the failpoint panics.
+//! - `exit`: the node process exits right away, as if it crashed.
+//!
+//! A `<key>` restricts the entry to a single instance, e.g. the id of a triple, and a `<count>`
+//! disarms the entry after it triggered that many times.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Context as _;
+
+/// Incoming messages of a triple generation, keyed by the triple id. `return` drops them.
+pub const TRIPLE_DROP_MESSAGE: &str = "triple_drop_message";
+/// Every step of a presignature generation, keyed by the presignature id. `return` fails the
+/// generation and `exit` crashes the node in the middle of it.
+pub const PRESIGNATURE_GENERATE: &str = "presignature_generate";
+/// Publishing a signature to the contract, keyed by the request id. `return` skips publishing
+/// the signature and `sleep` delays it.
+pub const SIGNATURE_PUBLISH: &str = "signature_publish";
+/// Outgoing messages of a triple generation, keyed by the triple id. `return` corrupts the share
+/// in the message. Only evaluated by nodes built with the `byzantine` feature.
+pub const BYZANTINE_CORRUPT_TRIPLE: &str = "byzantine_corrupt_triple";
+/// Outgoing messages of a signature generation, keyed by the presignature id. `return` corrupts
+/// the signature share in the message. Only evaluated by nodes built with the `byzantine` feature.
+pub const BYZANTINE_CORRUPT_SIGNATURE: &str = "byzantine_corrupt_signature";
+/// Outgoing triple, presignature and signature messages once the node is past its first epoch.
+/// `return` sends the message for the previous epoch. Only evaluated by nodes built with the
+/// `byzantine` feature.
+pub const BYZANTINE_STALE_EPOCH: &str = "byzantine_stale_epoch";
+
+static FAILPOINTS: once_cell::sync::Lazy<Mutex<HashMap<String, FailPoint>>> =
+    once_cell::sync::Lazy::new(Default::default);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Off,
+    Return(Option<String>),
+    Sleep(Duration),
+    Panic(Option<String>),
+    Exit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailPoint {
+    pub action: Action,
+    /// Times the failpoint triggers before it gets disarmed, or `None` to keep it armed.
+    pub remaining: Option<u32>,
+}
+
+impl FromStr for FailPoint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (remaining, action) = match s.split_once('*') {
+            Some((count, action)) => (
+                Some(count.trim().parse().context("invalid failpoint count")?),
+                action,
+            ),
+            None => (None, s),
+        };
+        let action = action.trim();
+        let (kind, arg) = match action.split_once('(') {
+            Some((kind, arg)) => {
+                let arg = arg
+                    .strip_suffix(')')
+                    .with_context(|| format!("unclosed argument of failpoint action: {action}"))?;
+                (kind, Some(arg.to_string()))
+            }
+            None => (action, None),
+        };
+        let action = match kind {
+            "off" => Action::Off,
+            "return" => Action::Return(arg),
+            "sleep" => {
+                let ms = arg
+                    .context("failpoint action `sleep` requires a duration in milliseconds")?
+                    .parse()
+                    .context("invalid duration of failpoint action `sleep`")?;
+                Action::Sleep(Duration::from_millis(ms))
+            }
+            "panic" => Action::Panic(arg),
+            "exit" => Action::Exit,
+            _ => anyhow::bail!("unknown failpoint action: {kind}"),
+        };
+        Ok(Self { action, remaining })
+    }
+}
+
+/// Arms the failpoints in `MPC_FAILPOINTS`, if set.
+#[cfg(feature = "failpoints")]
+pub fn init() {
+    let Some(failpoints) = std::env::var("MPC_FAILPOINTS")
+        .ok()
+        .filter(|failpoints| !failpoints.trim().is_empty())
+    else {
+        return;
+    };
+    match configure(&failpoints) {
+        Ok(()) => tracing::warn!(%failpoints, "armed failpoints"),
+        Err(err) => tracing::error!(?err, %failpoints, "failed to arm failpoints"),
+    }
+}
+
+#[cfg(not(feature = "failpoints"))]
+pub fn init() {}
+
+/// Arms the failpoints of a `MPC_FAILPOINTS` formatted list. Entries for a failpoint that is
+/// already armed replace it.
+pub fn configure(failpoints: &str) -> anyhow::Result<()> {
+    let mut parsed = Vec::new();
+    for entry in failpoints
+        .split(';')
+        .filter(|entry| !entry.trim().is_empty())
+    {
+        let (name, failpoint) = entry
+            .split_once('=')
+            .with_context(|| format!("failpoint entry is missing an action: {entry}"))?;
+        parsed.push((name.trim().to_string(), failpoint.parse()?));
+    }
+    FAILPOINTS.lock().unwrap().extend(parsed);
+    Ok(())
+}
+
+/// Disarms every failpoint.
+pub fn clear() {
+    FAILPOINTS.lock().unwrap().clear();
+}
+
+/// Next action of the failpoint `name`, preferring the entry scoped to `key` over the one that
+/// applies to every instance.
+fn next(name: &str, key: Option<&str>) -> Option<Action> {
+    if !cfg!(feature = "failpoints") {
+        return None;
+    }
+    let mut failpoints = FAILPOINTS.lock().unwrap();
+    let scoped = key
+        .map(|key| format!("{name}@{key}"))
+        .filter(|scoped| failpoints.contains_key(scoped));
+    let entry = scoped.unwrap_or_else(|| name.to_string());
+    let failpoint = failpoints.get_mut(&entry)?;
+    if failpoint.action == Action::Off {
+        return None;
+    }
+    match &mut failpoint.remaining {
+        Some(0) => return None,
+        Some(remaining) => *remaining -= 1,
+        None => {}
+    }
+    tracing::warn!(failpoint = entry, action = ?failpoint.action, "failpoint triggered");
+    Some(failpoint.action.clone())
+}
+
+impl Action {
+    fn trigger(self) -> Option<Option<String>> {
+        match self {
+            Action::Return(arg) => Some(arg),
+            Action::Panic(msg) => panic!("failpoint panicked: {}", msg.unwrap_or_default()),
+            Action::Exit => std::process::exit(1),
+            Action::Off | Action::Sleep(_) => None,
+        }
+    }
+}
+
+/// Tampers with an outgoing message as armed through the `byzantine_*` failpoints.
+#[cfg(feature = "byzantine")]
+pub fn byzantine(msg: &mut crate::protocol::message::MpcMessage) {
+    use crate::protocol::message::MpcMessage;
+
+    // flipping the last byte keeps the data decodable, so that the receivers feed the wrong
+    // share into the protocol instead of dropping the message.
+    fn corrupt(data: &mut [u8]) {
+        if let Some(byte) = data.last_mut() {
+            *byte ^= 0xff;
+        }
+    }
+
+    let epoch = match msg {
+        MpcMessage::Triple(msg) => {
+            if eval_blocking(BYZANTINE_CORRUPT_TRIPLE, Some(&msg.id.to_string())).is_some() {
+                corrupt(&mut msg.data);
+            }
+            &mut msg.epoch
+        }
+        MpcMessage::Presignature(msg) => &mut msg.epoch,
+        MpcMessage::Signature(msg) => {
+            let presignature_id = msg.presignature_id.to_string();
+            if eval_blocking(BYZANTINE_CORRUPT_SIGNATURE, Some(&presignature_id)).is_some() {
+                corrupt(&mut msg.data);
+            }
+            &mut msg.epoch
+        }
+        MpcMessage::Generating(_) | MpcMessage::Resharing(_) => return,
+    };
+    if *epoch > 0 && eval_blocking(BYZANTINE_STALE_EPOCH, None).is_some() {
+        *epoch -= 1;
+    }
+}
+
+#[cfg(not(feature = "byzantine"))]
+pub fn byzantine(_msg: &mut crate::protocol::message::MpcMessage) {}
+
+/// Evaluates the failpoint `name`. Returns the argument of a `return` action when the code at
+/// the failpoint should bail out, after having slept, panicked or exited for the other actions.
+pub async fn eval(name: &str, key: Option<&str>) -> Option<Option<String>> {
+    match next(name, key)? {
+        Action::Sleep(duration) => {
+            tokio::time::sleep(duration).await;
+            None
+        }
+        action => action.trigger(),
+    }
+}
+
+/// Same as [`eval`] for code that can not await, which blocks the thread on `sleep` actions.
+pub fn eval_blocking(name: &str, key: Option<&str>) -> Option<Option<String>> {
+    match next(name, key)? {
+        Action::Sleep(duration) => {
+            std::thread::sleep(duration);
+            None
+        }
+        action => action.trigger(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_failpoint() {
+        assert_eq!(
+            "return".parse::<FailPoint>().unwrap(),
+            FailPoint {
+                action: Action::Return(None),
+                remaining: None
+            }
+        );
+        assert_eq!(
+            "2*return(drop)".parse::<FailPoint>().unwrap(),
+            FailPoint {
+                action: Action::Return(Some("drop".to_string())),
+                remaining: Some(2)
+            }
+        );
+        assert_eq!(
+            "sleep(1500)".parse::<FailPoint>().unwrap().action,
+            Action::Sleep(Duration::from_millis(1500))
+        );
+        assert_eq!("exit".parse::<FailPoint>().unwrap().action, Action::Exit);
+        assert!("sleep".parse::<FailPoint>().is_err());
+        assert!("return(".parse::<FailPoint>().is_err());
+        assert!("crash".parse::<FailPoint>().is_err());
+        assert!(configure("triple_drop_message").is_err());
+    }
+
+    #[cfg(feature = "failpoints")]
+    #[tokio::test]
+    async fn test_eval_failpoint() {
+        configure("test_scoped=return(any);test_scoped@1=1*return(one)").unwrap();
+        assert_eq!(
+            eval("test_scoped", Some("1")).await,
+            Some(Some("one".to_string()))
+        );
+        // the scoped entry is used up, but stays the one that applies to its key
+        assert_eq!(eval("test_scoped", Some("1")).await, None);
+        assert_eq!(
+            eval("test_scoped", Some("2")).await,
+            Some(Some("any".to_string()))
+        );
+        assert_eq!(eval("test_unarmed", None).await, None);
+    }
+}
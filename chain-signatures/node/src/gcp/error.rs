@@ -14,6 +14,12 @@ pub enum ConvertError {
 pub enum SecretStorageError {
     #[error("GCP error: {0}")]
     GcpError(#[from] google_secretmanager1::Error),
+    #[error("AWS error: {0}")]
+    AwsError(String),
+    #[error("Vault error: {0}")]
+    VaultError(String),
+    #[error("cipher error: {0}")]
+    CipherError(String),
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
     #[error("(de)serialization error: {0}")]
@@ -12,6 +12,8 @@ use google_datastore1::api::{
 };
 use google_datastore1::oauth2::AccessTokenAuthenticator;
 use google_datastore1::Datastore;
+use google_logging2::api::{LogEntry, MonitoredResource, WriteLogEntriesRequest};
+use google_logging2::Logging;
 use google_secretmanager1::api::{AddSecretVersionRequest, SecretPayload};
 use google_secretmanager1::oauth2::authenticator::ApplicationDefaultCredentialsTypes;
 use google_secretmanager1::oauth2::{
@@ -79,6 +81,48 @@ impl SecretManagerService {
     }
 }
 
+#[derive(Clone)]
+pub struct LoggingService {
+    logging: Logging<HttpsConnector<HttpConnector>>,
+    project_id: String,
+}
+
+impl LoggingService {
+    /// Appends `payload` as a structured entry to the log `log_id` of the project.
+    pub async fn write_entry(
+        &self,
+        log_id: &str,
+        payload: serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let serde_json::Value::Object(payload) = payload else {
+            anyhow::bail!("log entry payload has to be a JSON object");
+        };
+        let request = WriteLogEntriesRequest {
+            entries: Some(vec![LogEntry {
+                log_name: Some(format!("projects/{}/logs/{log_id}", self.project_id)),
+                resource: Some(MonitoredResource {
+                    type_: Some("global".to_string()),
+                    labels: None,
+                }),
+                severity: Some("NOTICE".to_string()),
+                json_payload: Some(payload.into_iter().collect()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        self.logging
+            .entries()
+            .write(request)
+            .doit()
+            .await
+            .map_err(|e| {
+                tracing::error!(%e, "failed to write log entry");
+                e
+            })?;
+        Ok(())
+    }
+}
+
 #[derive(Clone)]
 pub struct DatastoreService {
     datastore: Datastore<HttpsConnector<HttpConnector>>,
@@ -360,6 +404,7 @@ pub struct GcpService {
     pub project_id: String,
     pub datastore: DatastoreService,
     pub secret_manager: SecretManagerService,
+    pub logging: LoggingService,
     pub account_id: AccountId,
 }
 
@@ -370,6 +415,7 @@ impl GcpService {
     ) -> anyhow::Result<Self> {
         let project_id = storage_options.gcp_project_id.clone();
         let secret_manager;
+        let logging;
         let datastore = if let Some(gcp_datastore_url) = storage_options.gcp_datastore_url.clone() {
             let client = hyper::Client::builder().build(
                 hyper_rustls::HttpsConnectorBuilder::new()
@@ -384,6 +430,7 @@ impl GcpService {
                 .build()
                 .await?;
             secret_manager = SecretManager::new(client.clone(), authenticator.clone());
+            logging = Logging::new(client.clone(), authenticator.clone());
             let mut datastore = Datastore::new(client, authenticator);
             datastore.base_url(gcp_datastore_url.clone());
             datastore.root_url(gcp_datastore_url);
@@ -406,6 +453,7 @@ impl GcpService {
                 ApplicationDefaultCredentialsTypes::ServiceAccount(auth) => auth.build().await?,
             };
             secret_manager = SecretManager::new(client.clone(), authenticator.clone());
+            logging = Logging::new(client.clone(), authenticator.clone());
             Datastore::new(client, authenticator)
         };
 
@@ -421,6 +469,10 @@ impl GcpService {
                 secret_manager,
                 project_id: project_id.clone(),
             },
+            logging: LoggingService {
+                logging,
+                project_id: project_id.clone(),
+            },
             project_id,
         })
     }
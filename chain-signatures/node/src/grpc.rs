@@ -0,0 +1,182 @@
+//! gRPC transport for node to node messages. This is an alternative to the JSON `/msg`
+//! endpoint: the same HPKE encrypted messages get streamed through the `mesh.Mesh/Send`
+//! method, which is served on the same port as the rest of the web API.
+
+use crate::hsm::CipherKey;
+use crate::http_client::SendError;
+use crate::protocol::message::{ReplayWindow, SignedMessage};
+use crate::protocol::{CryptographicError, MpcMessage, NodeState};
+use cait_sith::protocol::Participant;
+use mpc_keys::hpke;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex, RwLock};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Response, Status, Streaming};
+
+pub mod proto {
+    tonic::include_proto!("mesh");
+}
+
+use proto::mesh_client::MeshClient;
+use proto::mesh_server::{Mesh, MeshServer};
+
+impl From<hpke::Ciphered> for proto::Ciphered {
+    fn from(ciphered: hpke::Ciphered) -> Self {
+        Self {
+            encapped_key: ciphered.encapped_key.to_bytes(),
+            text: ciphered.text,
+            tag: ciphered.tag.to_bytes(),
+        }
+    }
+}
+
+impl TryFrom<proto::Ciphered> for hpke::Ciphered {
+    type Error = Status;
+
+    fn try_from(ciphered: proto::Ciphered) -> Result<Self, Self::Error> {
+        Ok(Self {
+            encapped_key: hpke::EncappedKey::try_from_bytes(&ciphered.encapped_key)
+                .map_err(|err| Status::invalid_argument(format!("invalid encapped key: {err}")))?,
+            text: ciphered.text,
+            tag: hpke::Tag::try_from_bytes(&ciphered.tag)
+                .map_err(|err| Status::invalid_argument(format!("invalid tag: {err}")))?,
+        })
+    }
+}
+
+pub struct MeshService {
+    sender: mpsc::Sender<MpcMessage>,
+    protocol_state: Arc<RwLock<NodeState>>,
+    cipher_sk: CipherKey,
+    replay_window: Arc<std::sync::Mutex<ReplayWindow>>,
+}
+
+impl MeshService {
+    pub fn new(
+        sender: mpsc::Sender<MpcMessage>,
+        protocol_state: Arc<RwLock<NodeState>>,
+        cipher_sk: CipherKey,
+        replay_window: Arc<std::sync::Mutex<ReplayWindow>>,
+    ) -> MeshServer<Self> {
+        MeshServer::new(Self {
+            sender,
+            protocol_state,
+            cipher_sk,
+            replay_window,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Mesh for MeshService {
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn send(
+        &self,
+        request: Request<Streaming<proto::Ciphered>>,
+    ) -> Result<Response<proto::SendResponse>, Status> {
+        if crate::quic::mtls_enabled() {
+            return Err(Status::permission_denied(
+                "messages are only accepted over the authenticated quic mesh",
+            ));
+        }
+        let mut stream = request.into_inner();
+        let mut received = 0;
+        // The next message is only read once the previous one has been accepted by the
+        // protocol channel, so a busy node naturally slows down the sender.
+        while let Some(encrypted) = stream.message().await? {
+            let message: MpcMessage = match SignedMessage::decrypt(
+                &self.cipher_sk,
+                &self.protocol_state,
+                &self.replay_window,
+                encrypted.try_into()?,
+            )
+            .await
+            {
+                Ok(msg) => msg,
+                Err(CryptographicError::ReplayedMessage { from, seq }) => {
+                    tracing::warn!(?from, seq, "dropping a replayed message");
+                    continue;
+                }
+                Err(err) => {
+                    tracing::error!(?err, "failed to decrypt or verify an encrypted message");
+                    return Err(Status::invalid_argument(err.to_string()));
+                }
+            };
+
+            if let Err(err) = self.sender.send(message).await {
+                tracing::error!(?err, "failed to forward an encrypted protocol message");
+                return Err(Status::unavailable(err.to_string()));
+            }
+            received += 1;
+        }
+
+        Ok(Response::new(proto::SendResponse { received }))
+    }
+}
+
+/// Client side of the mesh gRPC service. Channels are created lazily and reused for every
+/// subsequent message sent to the same node.
+#[derive(Clone, Default)]
+pub struct Client {
+    channels: Arc<Mutex<HashMap<String, Channel>>>,
+}
+
+impl Client {
+    async fn channel(&self, url: &str) -> Result<Channel, SendError> {
+        let mut channels = self.channels.lock().await;
+        if let Some(channel) = channels.get(url) {
+            return Ok(channel.clone());
+        }
+        let channel = Endpoint::from_shared(url.to_string())?.connect_lazy();
+        channels.insert(url.to_string(), channel.clone());
+        Ok(channel)
+    }
+
+    #[tracing::instrument(level = "debug", name = "message_request", skip_all, fields(?from, to = url))]
+    pub async fn send_encrypted(
+        &self,
+        from: Participant,
+        url: &str,
+        message: Vec<hpke::Ciphered>,
+        request_timeout: Duration,
+    ) -> Result<(), SendError> {
+        tracing::debug!(?from, to = %url, "making grpc request: sending encrypted message");
+        let expected = message.len() as u64;
+        let mut client = MeshClient::new(self.channel(url).await?);
+        let stream = tokio_stream::iter(message.into_iter().map(proto::Ciphered::from));
+        let response = tokio::time::timeout(request_timeout, client.send(stream))
+            .await
+            .map_err(|_| SendError::Timeout(format!("send encrypted from {from:?} to {url}")))?
+            .map_err(|status| {
+                tracing::warn!("failed to send a message to {url} with code {status}");
+                SendError::GrpcStatus(status)
+            })?;
+
+        let received = response.into_inner().received;
+        if received != expected {
+            return Err(SendError::Unsuccessful(format!(
+                "{url} only received {received} out of {expected} messages"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_ciphered_proto_roundtrip() {
+        let associated_data = b"";
+        let (sk, pk) = mpc_keys::hpke::generate();
+        let message = b"hello mesh";
+
+        let ciphered = pk.encrypt(message, associated_data).unwrap();
+        let proto = super::proto::Ciphered::from(ciphered);
+        let ciphered: mpc_keys::hpke::Ciphered = proto.try_into().unwrap();
+        let decrypted = sk.decrypt(&ciphered, associated_data).unwrap();
+
+        assert_eq!(message, &decrypted[..]);
+    }
+}
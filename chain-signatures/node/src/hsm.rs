@@ -0,0 +1,585 @@
+//! PKCS#11 support for keeping the node's keys inside of an HSM: the key signing messages to
+//! other nodes, the key of the node's NEAR account and the cipher key other nodes encrypt their
+//! messages to. When configured, the keys never leave the device and every signature and
+//! decryption goes through the HSM itself.
+
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::mechanism::elliptic_curve::{EcKdf, Ecdh1DeriveParams};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, AttributeType, KeyType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use mpc_keys::hpke;
+use near_account_id::AccountId;
+use near_crypto::{ED25519PublicKey, InMemorySigner, PublicKey, Signature, Signer};
+use std::sync::{Arc, Mutex};
+
+/// Configures the HSM that holds the node's keys.
+#[derive(Debug, Clone, clap::Parser)]
+#[group(id = "hsm_options")]
+pub struct Options {
+    /// Path to the PKCS#11 module of the HSM vendor. Keys are kept in memory when not provided.
+    #[clap(long, env("MPC_HSM_MODULE"))]
+    pub hsm_module: Option<String>,
+    /// Index of the slot, among the slots with a token present, that holds the keys.
+    #[clap(long, env("MPC_HSM_SLOT"), default_value = "0")]
+    pub hsm_slot: usize,
+    /// User PIN used to log into the token.
+    #[clap(long, env("MPC_HSM_PIN"), requires = "hsm_module")]
+    pub hsm_pin: Option<String>,
+    /// Label of the Ed25519 key pair used to sign messages sent between nodes.
+    #[clap(long, env("MPC_HSM_SIGN_KEY_LABEL"), default_value = "mpc-sign-key")]
+    pub hsm_sign_key_label: String,
+    /// Label of the Ed25519 key pair of the node's NEAR account, used to sign transactions.
+    #[clap(
+        long,
+        env("MPC_HSM_ACCOUNT_KEY_LABEL"),
+        default_value = "mpc-account-key"
+    )]
+    pub hsm_account_key_label: String,
+    /// Label of the X25519 key pair that other nodes encrypt their messages to.
+    #[clap(
+        long,
+        env("MPC_HSM_CIPHER_KEY_LABEL"),
+        default_value = "mpc-cipher-key"
+    )]
+    pub hsm_cipher_key_label: String,
+}
+
+impl Options {
+    pub fn into_str_args(self) -> Vec<String> {
+        let mut opts = vec![
+            "--hsm-slot".to_string(),
+            self.hsm_slot.to_string(),
+            "--hsm-sign-key-label".to_string(),
+            self.hsm_sign_key_label,
+            "--hsm-account-key-label".to_string(),
+            self.hsm_account_key_label,
+            "--hsm-cipher-key-label".to_string(),
+            self.hsm_cipher_key_label,
+        ];
+        if let Some(hsm_module) = self.hsm_module {
+            opts.extend(vec!["--hsm-module".to_string(), hsm_module]);
+        }
+        if let Some(hsm_pin) = self.hsm_pin {
+            opts.extend(vec!["--hsm-pin".to_string(), hsm_pin]);
+        }
+        opts
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HsmError {
+    #[error("pkcs11 error: {0}")]
+    Pkcs11(#[from] cryptoki::error::Error),
+    #[error("no token present in slot {0}")]
+    SlotNotFound(usize),
+    #[error("key not found on the token: {0}")]
+    KeyNotFound(String),
+    #[error("key on the token is not valid: {0}")]
+    InvalidKey(String),
+    #[error("failed to decrypt: {0}")]
+    Decryption(String),
+}
+
+/// Ed25519 signer backed by a PKCS#11 token, see [`Hsm::signer`].
+pub struct Pkcs11Signer {
+    // Sessions cannot be used concurrently, so every signing operation goes through the lock.
+    session: Arc<Mutex<Session>>,
+    private_key: ObjectHandle,
+    public_key: PublicKey,
+}
+
+impl std::fmt::Debug for Pkcs11Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pkcs11Signer")
+            .field("public_key", &self.public_key)
+            .finish()
+    }
+}
+
+impl Pkcs11Signer {
+    pub fn public_key(&self) -> PublicKey {
+        self.public_key.clone()
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Result<Signature, HsmError> {
+        let session = self.session.lock().unwrap();
+        let signature = session.sign(&Mechanism::Eddsa, self.private_key, data)?;
+        Signature::from_parts(near_crypto::KeyType::ED25519, &signature)
+            .map_err(|err| HsmError::InvalidKey(err.to_string()))
+    }
+}
+
+/// X25519 key pair on a PKCS#11 token that HPKE messages get encrypted to. Only the
+/// Diffie-Hellman step of the decryption happens on the token, the shared secret it produces is
+/// only good for the message it was derived for.
+pub struct Pkcs11CipherKey {
+    session: Arc<Mutex<Session>>,
+    private_key: ObjectHandle,
+    public_key: hpke::PublicKey,
+}
+
+impl std::fmt::Debug for Pkcs11CipherKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pkcs11CipherKey")
+            .field("public_key", &self.public_key)
+            .finish()
+    }
+}
+
+impl Pkcs11CipherKey {
+    pub fn public_key(&self) -> hpke::PublicKey {
+        self.public_key.clone()
+    }
+
+    pub fn decrypt(
+        &self,
+        cipher: &hpke::Ciphered,
+        associated_data: &[u8],
+    ) -> Result<Vec<u8>, HsmError> {
+        let dh = self.derive_shared_secret(&cipher.encapped_key.to_bytes())?;
+        cipher
+            .decrypt_with_dh(&self.public_key, &dh, associated_data)
+            .map_err(|err| HsmError::Decryption(err.to_string()))
+    }
+
+    fn derive_shared_secret(&self, peer: &[u8]) -> Result<Vec<u8>, HsmError> {
+        let session = self.session.lock().unwrap();
+        let mechanism = Mechanism::Ecdh1Derive(Ecdh1DeriveParams::new(EcKdf::null(), peer));
+        let secret = session.derive_key(
+            &mechanism,
+            self.private_key,
+            &[
+                Attribute::Class(ObjectClass::SECRET_KEY),
+                Attribute::KeyType(KeyType::GENERIC_SECRET),
+                Attribute::ValueLen(32.into()),
+                Attribute::Token(false),
+                Attribute::Sensitive(false),
+                Attribute::Extractable(true),
+            ],
+        )?;
+        let value = session.get_attributes(secret, &[AttributeType::Value]);
+        session.destroy_object(secret)?;
+        match value?.into_iter().next() {
+            Some(Attribute::Value(dh)) => Ok(dh),
+            _ => Err(HsmError::InvalidKey(
+                "missing CKA_VALUE of the derived secret".to_string(),
+            )),
+        }
+    }
+}
+
+/// Session with the HSM, shared by all the keys loaded from it. Tokens only allow a single
+/// login per application, so there is one session per process.
+pub struct Hsm {
+    session: Arc<Mutex<Session>>,
+}
+
+impl Hsm {
+    pub fn connect(module: &str, options: &Options) -> Result<Self, HsmError> {
+        let pkcs11 = Pkcs11::new(module)?;
+        pkcs11.initialize(CInitializeArgs::OsThreads)?;
+        let slot = *pkcs11
+            .get_slots_with_token()?
+            .get(options.hsm_slot)
+            .ok_or(HsmError::SlotNotFound(options.hsm_slot))?;
+        let session = pkcs11.open_ro_session(slot)?;
+        if let Some(pin) = &options.hsm_pin {
+            session.login(UserType::User, Some(&AuthPin::new(pin.clone())))?;
+        }
+        Ok(Self {
+            session: Arc::new(Mutex::new(session)),
+        })
+    }
+
+    /// Loads the Ed25519 key pair labeled `label`.
+    pub fn signer(&self, label: &str) -> Result<Pkcs11Signer, HsmError> {
+        let (private_key, public_key) = {
+            let session = self.session.lock().unwrap();
+            let private_key = find_key(&session, ObjectClass::PRIVATE_KEY, label)?;
+            let public_key = find_key(&session, ObjectClass::PUBLIC_KEY, label)?;
+            (private_key, read_point(&session, public_key)?)
+        };
+        let public_key = PublicKey::ED25519(ED25519PublicKey(public_key));
+        tracing::info!(%public_key, label, "loaded signing key from hsm");
+
+        Ok(Pkcs11Signer {
+            session: self.session.clone(),
+            private_key,
+            public_key,
+        })
+    }
+
+    /// Loads the X25519 key pair labeled `label`.
+    pub fn cipher_key(&self, label: &str) -> Result<Pkcs11CipherKey, HsmError> {
+        let (private_key, public_key) = {
+            let session = self.session.lock().unwrap();
+            let private_key = find_key(&session, ObjectClass::PRIVATE_KEY, label)?;
+            let public_key = find_key(&session, ObjectClass::PUBLIC_KEY, label)?;
+            (private_key, read_point(&session, public_key)?)
+        };
+        let public_key = hpke::PublicKey::try_from_bytes(&public_key)
+            .map_err(|err| HsmError::InvalidKey(err.to_string()))?;
+        tracing::info!(
+            public_key = hex::encode(public_key.to_bytes()),
+            label,
+            "loaded cipher key from hsm"
+        );
+
+        Ok(Pkcs11CipherKey {
+            session: self.session.clone(),
+            private_key,
+            public_key,
+        })
+    }
+}
+
+fn find_key(session: &Session, class: ObjectClass, label: &str) -> Result<ObjectHandle, HsmError> {
+    session
+        .find_objects(&[
+            Attribute::Class(class),
+            Attribute::Label(label.as_bytes().to_vec()),
+        ])?
+        .into_iter()
+        .next()
+        .ok_or_else(|| HsmError::KeyNotFound(format!("{class} with label {label}")))
+}
+
+/// Reads the 32 byte public key of an Ed25519 or X25519 key pair.
+fn read_point(session: &Session, key: ObjectHandle) -> Result<[u8; 32], HsmError> {
+    let attributes = session.get_attributes(key, &[AttributeType::EcPoint])?;
+    let Some(Attribute::EcPoint(point)) = attributes.into_iter().next() else {
+        return Err(HsmError::InvalidKey("missing CKA_EC_POINT".to_string()));
+    };
+    // CKA_EC_POINT is a DER encoded OCTET STRING wrapping the 32 byte public key.
+    let bytes: [u8; 32] = match point.as_slice() {
+        [0x04, 0x20, rest @ ..] => rest.try_into(),
+        raw => raw.try_into(),
+    }
+    .map_err(|_| HsmError::InvalidKey(format!("unexpected point length {}", point.len())))?;
+    Ok(bytes)
+}
+
+/// The key used to sign messages sent to other nodes. It is either held in memory or
+/// delegated to an HSM.
+#[derive(Clone, Debug)]
+pub enum MessageSigner {
+    InMemory(near_crypto::SecretKey),
+    Hsm(Arc<Pkcs11Signer>),
+}
+
+impl MessageSigner {
+    pub fn public_key(&self) -> PublicKey {
+        match self {
+            MessageSigner::InMemory(sk) => sk.public_key(),
+            MessageSigner::Hsm(signer) => signer.public_key(),
+        }
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Result<Signature, HsmError> {
+        match self {
+            MessageSigner::InMemory(sk) => Ok(sk.sign(data)),
+            MessageSigner::Hsm(signer) => signer.sign(data),
+        }
+    }
+}
+
+impl From<near_crypto::SecretKey> for MessageSigner {
+    fn from(sk: near_crypto::SecretKey) -> Self {
+        MessageSigner::InMemory(sk)
+    }
+}
+
+/// The key of the node's NEAR account, used to sign the transactions sent to the contract. It is
+/// either held in memory or delegated to an HSM.
+///
+/// Transactions get signed through the infallible [`Signer`] trait, so an HSM that fails to sign
+/// leaves the transaction with an empty signature, which the RPC node rejects before it reaches
+/// the chain. The failure is kept until the transaction path picks it up through
+/// [`AccountSigner::check_signed`], which fails the call with the error of the HSM instead.
+#[derive(Clone)]
+pub enum AccountSigner {
+    InMemory(InMemorySigner),
+    Hsm {
+        account_id: AccountId,
+        signer: Arc<Pkcs11Signer>,
+        /// Error of the last signing that failed and was not picked up yet.
+        failure: Arc<Mutex<Option<HsmError>>>,
+    },
+}
+
+impl AccountSigner {
+    pub fn hsm(account_id: AccountId, signer: Pkcs11Signer) -> Self {
+        AccountSigner::Hsm {
+            account_id,
+            signer: Arc::new(signer),
+            failure: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn account_id(&self) -> &AccountId {
+        match self {
+            AccountSigner::InMemory(signer) => &signer.account_id,
+            AccountSigner::Hsm { account_id, .. } => account_id,
+        }
+    }
+
+    pub fn try_sign(&self, data: &[u8]) -> Result<Signature, HsmError> {
+        match self {
+            AccountSigner::InMemory(signer) => Ok(signer.sign(data)),
+            AccountSigner::Hsm { signer, .. } => signer.sign(data),
+        }
+    }
+
+    /// Result of a transaction signed by this signer, failing with the error of the HSM if it
+    /// could not sign the transaction, rather than with the rejection of its empty signature.
+    pub fn check_signed<T, E>(&self, result: Result<T, E>) -> anyhow::Result<T>
+    where
+        E: Into<anyhow::Error>,
+    {
+        let failure = match self {
+            AccountSigner::InMemory(_) => None,
+            AccountSigner::Hsm { failure, .. } => failure.lock().unwrap().take(),
+        };
+        match (result, failure) {
+            (Ok(value), _) => Ok(value),
+            (Err(_), Some(err)) => {
+                Err(anyhow::Error::new(err).context("the hsm failed to sign the transaction"))
+            }
+            (Err(err), None) => Err(err.into()),
+        }
+    }
+}
+
+impl Signer for AccountSigner {
+    fn public_key(&self) -> PublicKey {
+        match self {
+            AccountSigner::InMemory(signer) => signer.public_key(),
+            AccountSigner::Hsm { signer, .. } => signer.public_key(),
+        }
+    }
+
+    fn sign(&self, data: &[u8]) -> Signature {
+        match self.try_sign(data) {
+            Ok(signature) => signature,
+            Err(err) => {
+                tracing::error!(?err, "failed to sign a transaction with the hsm");
+                if let AccountSigner::Hsm { failure, .. } = self {
+                    *failure.lock().unwrap() = Some(err);
+                }
+                Signature::empty(near_crypto::KeyType::ED25519)
+            }
+        }
+    }
+
+    /// VRFs are only computed by block producers, never to sign transactions, so the HSM does
+    /// not compute them and an all-zero value and proof are returned instead.
+    fn compute_vrf_with_proof(
+        &self,
+        data: &[u8],
+    ) -> (near_crypto::vrf::Value, near_crypto::vrf::Proof) {
+        match self {
+            AccountSigner::InMemory(signer) => signer.compute_vrf_with_proof(data),
+            AccountSigner::Hsm { .. } => (
+                near_crypto::vrf::Value([0; 32]),
+                near_crypto::vrf::Proof([0; 64]),
+            ),
+        }
+    }
+}
+
+impl near_fetch::signer::ExposeAccountId for AccountSigner {
+    fn account_id(&self) -> &AccountId {
+        AccountSigner::account_id(self)
+    }
+}
+
+impl From<InMemorySigner> for AccountSigner {
+    fn from(signer: InMemorySigner) -> Self {
+        AccountSigner::InMemory(signer)
+    }
+}
+
+/// The key other nodes encrypt their messages to, which also encrypts the stockpile kept in
+/// redis. It is either held in memory or delegated to an HSM.
+#[derive(Clone)]
+pub enum CipherKey {
+    InMemory(hpke::SecretKey),
+    Hsm(Arc<Pkcs11CipherKey>),
+}
+
+impl CipherKey {
+    pub fn public_key(&self) -> hpke::PublicKey {
+        match self {
+            CipherKey::InMemory(sk) => sk.public_key(),
+            CipherKey::Hsm(key) => key.public_key(),
+        }
+    }
+
+    pub fn decrypt(
+        &self,
+        cipher: &hpke::Ciphered,
+        associated_data: &[u8],
+    ) -> Result<Vec<u8>, HsmError> {
+        match self {
+            CipherKey::InMemory(sk) => sk
+                .decrypt(cipher, associated_data)
+                .map_err(|err| HsmError::Decryption(err.to_string())),
+            CipherKey::Hsm(key) => key.decrypt(cipher, associated_data),
+        }
+    }
+}
+
+impl From<hpke::SecretKey> for CipherKey {
+    fn from(sk: hpke::SecretKey) -> Self {
+        CipherKey::InMemory(sk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SO_PIN: &str = "1234";
+    const USER_PIN: &str = "5678";
+    const TOKEN_LABEL: &str = "mpc-test";
+    /// DER encoded object identifiers of the curves, as expected by CKA_EC_PARAMS.
+    const ED25519_OID: [u8; 5] = [0x06, 0x03, 0x2b, 0x65, 0x70];
+    const X25519_OID: [u8; 5] = [0x06, 0x03, 0x2b, 0x65, 0x6e];
+
+    /// Initializes a fresh SoftHSM token holding the keys of a node and returns the options to
+    /// connect to it.
+    fn init_token(module: &str) -> Options {
+        let dir = std::env::temp_dir().join(format!("mpc-node-softhsm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let conf = dir.join("softhsm2.conf");
+        std::fs::write(&conf, format!("directories.tokendir = {}\n", dir.display())).unwrap();
+        std::env::set_var("SOFTHSM2_CONF", &conf);
+
+        let pkcs11 = Pkcs11::new(module).unwrap();
+        pkcs11.initialize(CInitializeArgs::OsThreads).unwrap();
+        let slot = pkcs11.get_slots_with_token().unwrap()[0];
+        pkcs11
+            .init_token(slot, &AuthPin::new(SO_PIN.to_string()), TOKEN_LABEL)
+            .unwrap();
+        // The initialized token gets moved to a new slot.
+        let slots = pkcs11.get_slots_with_token().unwrap();
+        let hsm_slot = slots
+            .iter()
+            .position(|slot| pkcs11.get_token_info(*slot).unwrap().label() == TOKEN_LABEL)
+            .unwrap();
+        let session = pkcs11.open_rw_session(slots[hsm_slot]).unwrap();
+        session
+            .login(UserType::So, Some(&AuthPin::new(SO_PIN.to_string())))
+            .unwrap();
+        session
+            .init_pin(&AuthPin::new(USER_PIN.to_string()))
+            .unwrap();
+        session.logout().unwrap();
+        session
+            .login(UserType::User, Some(&AuthPin::new(USER_PIN.to_string())))
+            .unwrap();
+
+        let options = Options {
+            hsm_module: Some(module.to_string()),
+            hsm_slot,
+            hsm_pin: Some(USER_PIN.to_string()),
+            hsm_sign_key_label: "mpc-sign-key".to_string(),
+            hsm_account_key_label: "mpc-account-key".to_string(),
+            hsm_cipher_key_label: "mpc-cipher-key".to_string(),
+        };
+        for label in [&options.hsm_sign_key_label, &options.hsm_account_key_label] {
+            session
+                .generate_key_pair(
+                    &Mechanism::EccEdwardsKeyPairGen,
+                    &[
+                        Attribute::Token(true),
+                        Attribute::Verify(true),
+                        Attribute::EcParams(ED25519_OID.to_vec()),
+                        Attribute::Label(label.as_bytes().to_vec()),
+                    ],
+                    &[
+                        Attribute::Token(true),
+                        Attribute::Private(true),
+                        Attribute::Sensitive(true),
+                        Attribute::Sign(true),
+                        Attribute::Label(label.as_bytes().to_vec()),
+                    ],
+                )
+                .unwrap();
+        }
+        let label = &options.hsm_cipher_key_label;
+        session
+            .generate_key_pair(
+                &Mechanism::EccMontgomeryKeyPairGen,
+                &[
+                    Attribute::Token(true),
+                    Attribute::EcParams(X25519_OID.to_vec()),
+                    Attribute::Label(label.as_bytes().to_vec()),
+                ],
+                &[
+                    Attribute::Token(true),
+                    Attribute::Private(true),
+                    Attribute::Sensitive(true),
+                    Attribute::Derive(true),
+                    Attribute::Label(label.as_bytes().to_vec()),
+                ],
+            )
+            .unwrap();
+        // Finalizes the library, so that the node can initialize it again.
+        drop(session);
+        drop(pkcs11);
+
+        options
+    }
+
+    /// Runs against SoftHSM when `SOFTHSM2_MODULE` points to its PKCS#11 module, such as
+    /// `/usr/lib/softhsm/libsofthsm2.so`. All keys are checked in one test, since the module can
+    /// only be initialized once per process at a time.
+    #[test]
+    fn test_softhsm_keys() {
+        let Ok(module) = std::env::var("SOFTHSM2_MODULE") else {
+            eprintln!("SOFTHSM2_MODULE is not set, skipping");
+            return;
+        };
+        let options = init_token(&module);
+        let hsm = Hsm::connect(&module, &options).unwrap();
+
+        // Messages to other nodes.
+        let sign_key =
+            MessageSigner::Hsm(Arc::new(hsm.signer(&options.hsm_sign_key_label).unwrap()));
+        let signature = sign_key.sign(b"hello mesh").unwrap();
+        assert!(signature.verify(b"hello mesh", &sign_key.public_key()));
+        assert!(!signature.verify(b"hello other mesh", &sign_key.public_key()));
+
+        // Transactions of the account, which has a key of its own.
+        let account_key = AccountSigner::hsm(
+            "node.test".parse().unwrap(),
+            hsm.signer(&options.hsm_account_key_label).unwrap(),
+        );
+        assert_ne!(account_key.public_key(), sign_key.public_key());
+        let signature = account_key.try_sign(b"transaction").unwrap();
+        assert!(signature.verify(b"transaction", &account_key.public_key()));
+        assert!(account_key.check_signed(Ok::<_, anyhow::Error>(())).is_ok());
+
+        // Messages encrypted to the node.
+        let cipher_key = CipherKey::Hsm(Arc::new(
+            hsm.cipher_key(&options.hsm_cipher_key_label).unwrap(),
+        ));
+        let ciphered = cipher_key
+            .public_key()
+            .encrypt(b"hello node", b"associated data")
+            .unwrap();
+        assert_eq!(
+            cipher_key.decrypt(&ciphered, b"associated data").unwrap(),
+            b"hello node"
+        );
+        assert!(cipher_key.decrypt(&ciphered, b"other data").is_err());
+
+        assert!(matches!(
+            hsm.signer("missing-key"),
+            Err(HsmError::KeyNotFound(_))
+        ));
+    }
+}
@@ -1,14 +1,19 @@
+use crate::grpc;
+use crate::hsm::MessageSigner;
+use crate::mesh::reputation::PeerReputation;
 use crate::protocol::contract::primitives::{ParticipantInfo, Participants};
 use crate::protocol::message::SignedMessage;
 use crate::protocol::MpcMessage;
+use crate::quic;
 use cait_sith::protocol::Participant;
-use mpc_contract::config::ProtocolConfig;
+use mpc_contract::config::{BackoffConfig, ProtocolConfig, TimeoutConfig};
 use mpc_keys::hpke::Ciphered;
 use reqwest::{Client, IntoUrl};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::str::Utf8Error;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use tokio_retry::strategy::{jitter, ExponentialBackoff};
+use tokio_retry::strategy::jitter;
 use tokio_retry::Retry;
 
 #[derive(Debug, Clone, clap::Parser)]
@@ -16,11 +21,53 @@ use tokio_retry::Retry;
 pub struct Options {
     #[clap(long, env("MPC_MESSAGE_TIMEOUT"), default_value = "1000")]
     pub timeout: u64,
+    /// Transport used to deliver messages to other nodes. Every node serves all transports,
+    /// so this only changes how this node sends its own messages.
+    #[clap(long, env("MPC_MESSAGE_TRANSPORT"), value_enum, default_value_t = Transport::Http)]
+    pub transport: Transport,
+    /// Authenticate the nodes of the QUIC mesh to each other with certificates signed by their
+    /// sign keys, and only accept messages from participants over it. QUIC is then the only
+    /// transport messages are sent and accepted on: `POST /msg` and the gRPC mesh refuse them,
+    /// and peers only count as active once they take a message over QUIC. Requires the QUIC
+    /// transport, and every other participant to enable it as well.
+    #[clap(long, env("MPC_MESH_MTLS"))]
+    pub mesh_mtls: bool,
 }
 
 impl Options {
     pub fn into_str_args(self) -> Vec<String> {
-        vec!["--timeout".to_string(), self.timeout.to_string()]
+        let mut args = vec![
+            "--timeout".to_string(),
+            self.timeout.to_string(),
+            "--transport".to_string(),
+            self.transport.to_string(),
+        ];
+        if self.mesh_mtls {
+            args.push("--mesh-mtls".to_string());
+        }
+        args
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Transport {
+    /// JSON encoded messages sent through `POST /msg`.
+    #[default]
+    Http,
+    /// Messages streamed through the `mesh.Mesh/Send` gRPC method.
+    Grpc,
+    /// Batches of messages sent on their own stream of a QUIC connection kept open to every
+    /// node, served over UDP on the same port number as the web API.
+    Quic,
+}
+
+impl std::fmt::Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transport::Http => write!(f, "http"),
+            Transport::Grpc => write!(f, "grpc"),
+            Transport::Quic => write!(f, "quic"),
+        }
     }
 }
 
@@ -42,16 +89,31 @@ pub enum SendError {
     Timeout(String),
     #[error("participant is not alive: {0}")]
     ParticipantNotAlive(String),
+    #[error("grpc transport error: {0}")]
+    GrpcTransportError(#[from] tonic::transport::Error),
+    #[error("grpc request was unsuccessful: {0}")]
+    GrpcStatus(#[from] tonic::Status),
+    #[error("quic error: {0}")]
+    QuicError(String),
+    #[error("quic connect error: {0}")]
+    QuicConnectError(#[from] quinn::ConnectError),
+    #[error("quic connection error: {0}")]
+    QuicConnectionError(#[from] quinn::ConnectionError),
+    #[error("quic write error: {0}")]
+    QuicWriteError(#[from] quinn::WriteError),
+    #[error("quic read error: {0}")]
+    QuicReadError(#[from] quinn::ReadToEndError),
 }
 
+#[tracing::instrument(level = "debug", name = "message_request", skip_all, fields(?from))]
 pub async fn send_encrypted<U: IntoUrl>(
     from: Participant,
     client: &Client,
     url: U,
     message: Vec<Ciphered>,
     request_timeout: Duration,
+    retry: &BackoffConfig,
 ) -> Result<(), SendError> {
-    let _span = tracing::info_span!("message_request");
     let mut url = url.into_url()?;
     url.set_path("msg");
     tracing::debug!(?from, to = %url, "making http request: sending encrypted message");
@@ -88,8 +150,47 @@ pub async fn send_encrypted<U: IntoUrl>(
         }
     };
 
-    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
-    Retry::spawn(retry_strategy, action).await
+    Retry::spawn(retry_strategy(retry), action).await
+}
+
+/// Delays between the attempts of delivering messages to a peer.
+pub(crate) fn retry_strategy(retry: &BackoffConfig) -> impl Iterator<Item = Duration> {
+    let retry = *retry;
+    (0..retry.max_retries)
+        .map(move |n| Duration::from_millis(retry.delay(n)))
+        .map(jitter)
+}
+
+/// Sequence numbers of the messages sent to every peer, shared by the message queues of all the
+/// states the node goes through. Receivers drop messages whose sequence number they have already
+/// seen, or that is too far behind the highest one they have seen from the same sender, so every
+/// peer gets a sequence of its own without gaps for the messages sent to the other peers.
+///
+/// The upper 32 bits hold the session, which is persisted and bumped every time the node starts,
+/// so that sequence numbers keep increasing across restarts. The lower 32 bits count the messages
+/// sent to the peer within the session.
+#[derive(Clone, Default)]
+pub struct SequenceNumbers {
+    session: u64,
+    next: Arc<Mutex<HashMap<Participant, u64>>>,
+}
+
+impl SequenceNumbers {
+    pub fn new(session: u32) -> Self {
+        Self {
+            session: u64::from(session) << 32,
+            next: Arc::default(),
+        }
+    }
+
+    /// Takes the sequence number of the next message sent to `to`.
+    pub fn next(&self, to: Participant) -> u64 {
+        let mut next = self.next.lock().unwrap();
+        let counter = next.entry(to).or_default();
+        let seq = self.session | *counter;
+        *counter += 1;
+        seq
+    }
 }
 
 // TODO: add in retry logic either in struct or at call site.
@@ -98,14 +199,20 @@ pub struct MessageQueue {
     deque: VecDeque<(ParticipantInfo, MpcMessage, Instant)>,
     seen_counts: HashSet<String>,
     message_options: Options,
+    grpc_client: grpc::Client,
+    quic_client: quic::Client,
+    seqs: SequenceNumbers,
 }
 
 impl MessageQueue {
-    pub fn new(options: Options) -> Self {
+    pub fn new(options: Options, seqs: SequenceNumbers) -> Self {
         Self {
             deque: VecDeque::default(),
             seen_counts: HashSet::default(),
             message_options: options,
+            grpc_client: grpc::Client::default(),
+            quic_client: quic::Client::default(),
+            seqs,
         }
     }
 
@@ -117,17 +224,21 @@ impl MessageQueue {
         self.deque.is_empty()
     }
 
-    pub fn push(&mut self, info: ParticipantInfo, msg: MpcMessage) {
+    pub fn push(&mut self, info: ParticipantInfo, mut msg: MpcMessage) {
+        crate::failpoint::byzantine(&mut msg);
         self.deque.push_back((info, msg, Instant::now()));
     }
 
+    #[tracing::instrument(level = "debug", name = "mesh_send", skip_all, fields(?from, queued = self.deque.len()))]
     pub async fn send_encrypted(
         &mut self,
         from: Participant,
-        sign_sk: &near_crypto::SecretKey,
+        sign_sk: &MessageSigner,
         client: &Client,
         participants: &Participants,
         cfg: &ProtocolConfig,
+        timeouts: &TimeoutConfig,
+        reputation: &PeerReputation,
     ) -> Vec<SendError> {
         let mut failed = VecDeque::new();
         let mut errors = Vec::new();
@@ -151,13 +262,15 @@ impl MessageQueue {
                 failed.push_back((info, msg, instant));
                 continue;
             }
-            let encrypted_msg = match SignedMessage::encrypt(&msg, from, sign_sk, &info.cipher_pk) {
-                Ok(encrypted) => encrypted,
-                Err(err) => {
-                    errors.push(SendError::EncryptionError(err.to_string()));
-                    continue;
-                }
-            };
+            let seq = self.seqs.next(Participant::from(info.id));
+            let encrypted_msg =
+                match SignedMessage::encrypt(&msg, from, seq, sign_sk, &info.cipher_pk) {
+                    Ok(encrypted) => encrypted,
+                    Err(err) => {
+                        errors.push(SendError::EncryptionError(err.to_string()));
+                        continue;
+                    }
+                };
             let encrypted = encrypted.entry(info.id).or_insert_with(Vec::new);
             encrypted.push((encrypted_msg, (info, msg, instant)));
         }
@@ -174,15 +287,47 @@ impl MessageQueue {
                 crate::metrics::NUM_SEND_ENCRYPTED_TOTAL
                     .with_label_values(&[account_id.as_str()])
                     .inc();
-                if let Err(err) = send_encrypted(
-                    from,
-                    client,
-                    &info.url,
-                    encrypted_partition,
-                    Duration::from_millis(self.message_options.timeout),
-                )
-                .await
-                {
+                let request_timeout = Duration::from_millis(self.message_options.timeout);
+                let retry = &timeouts.message_retry;
+                let result = match self.message_options.transport {
+                    Transport::Http => {
+                        send_encrypted(
+                            from,
+                            client,
+                            &info.url,
+                            encrypted_partition,
+                            request_timeout,
+                            retry,
+                        )
+                        .await
+                    }
+                    Transport::Grpc => {
+                        let grpc_client = &self.grpc_client;
+                        Retry::spawn(retry_strategy(retry), || {
+                            grpc_client.send_encrypted(
+                                from,
+                                &info.url,
+                                encrypted_partition.clone(),
+                                request_timeout,
+                            )
+                        })
+                        .await
+                    }
+                    Transport::Quic => {
+                        let quic_client = &self.quic_client;
+                        Retry::spawn(retry_strategy(retry), || {
+                            quic_client.send_encrypted(
+                                from,
+                                &info.url,
+                                encrypted_partition.clone(),
+                                request_timeout,
+                            )
+                        })
+                        .await
+                    }
+                };
+                if let Err(err) = result {
+                    reputation.record_failure(Participant::from(id));
                     crate::metrics::NUM_SEND_ENCRYPTED_FAILURE
                         .with_label_values(&[account_id.as_str()])
                         .inc();
@@ -194,6 +339,7 @@ impl MessageQueue {
                     failed.extend(msgs);
                     errors.push(err);
                 } else {
+                    reputation.record_success(Participant::from(id), start.elapsed());
                     compacted += msgs.len();
                     crate::metrics::SEND_ENCRYPTED_LATENCY
                         .with_label_values(&[account_id.as_str()])
@@ -269,8 +415,12 @@ fn timeout(msg: &MpcMessage, cfg: &ProtocolConfig) -> Duration {
 
 #[cfg(test)]
 mod tests {
-    use crate::protocol::message::GeneratingMessage;
+    use super::SequenceNumbers;
+    use crate::hsm::MessageSigner;
+    use crate::protocol::message::{GeneratingMessage, SignedMessage};
     use crate::protocol::MpcMessage;
+    use cait_sith::protocol::Participant;
+    use mpc_keys::hpke::Ciphered;
 
     #[test]
     fn test_sending_encrypted_message() {
@@ -291,4 +441,69 @@ mod tests {
 
         assert_eq!(starting_message, message);
     }
+
+    #[test]
+    fn test_encrypted_message_only_readable_by_recipient() {
+        let (recipient_sk, recipient_pk) = mpc_keys::hpke::generate();
+        let (other_sk, _) = mpc_keys::hpke::generate();
+        let sign_sk = near_crypto::SecretKey::from_random(near_crypto::KeyType::ED25519);
+        let from = cait_sith::protocol::Participant::from(0);
+        let message = MpcMessage::Generating(GeneratingMessage {
+            from,
+            data: vec![1, 2, 3],
+        });
+
+        let ciphered = SignedMessage::encrypt(
+            &message,
+            from,
+            7,
+            &MessageSigner::from(sign_sk.clone()),
+            &recipient_pk,
+        )
+        .unwrap();
+        let associated_data = SignedMessage::<MpcMessage>::ASSOCIATED_DATA;
+
+        // Nobody but the recipient can read the message, e.g. a proxy relaying it.
+        assert!(other_sk.decrypt(&ciphered, associated_data).is_err());
+
+        // Nor can the ciphertext be altered on the way.
+        let mut tampered =
+            serde_json::from_slice::<Ciphered>(&serde_json::to_vec(&ciphered).unwrap()).unwrap();
+        tampered.text[0] ^= 1;
+        assert!(recipient_sk.decrypt(&tampered, associated_data).is_err());
+
+        let decrypted = recipient_sk.decrypt(&ciphered, associated_data).unwrap();
+        let SignedMessage::<Vec<u8>> {
+            msg,
+            sig,
+            from: sender,
+            seq,
+        } = serde_json::from_slice(&decrypted).unwrap();
+        assert_eq!(sender, from);
+        assert_eq!(seq, 7);
+        let mut signed = msg.clone();
+        signed.extend_from_slice(&seq.to_be_bytes());
+        assert!(sig.verify(&signed, &sign_sk.public_key()));
+        // The sequence number is signed too, so it can not be changed to replay the message.
+        assert!(!sig.verify(&msg, &sign_sk.public_key()));
+        assert_eq!(serde_json::from_slice::<MpcMessage>(&msg).unwrap(), message);
+    }
+
+    #[test]
+    fn test_sequence_numbers_per_peer() {
+        let (alice, bob) = (Participant::from(0), Participant::from(1));
+        let seqs = SequenceNumbers::new(2);
+        assert_eq!(seqs.next(alice), 2 << 32);
+        assert_eq!(seqs.next(alice), (2 << 32) + 1);
+        // Messages sent to alice leave no gap in the sequence of bob.
+        assert_eq!(seqs.next(bob), 2 << 32);
+
+        // The queues of the next state keep counting where the previous ones stopped.
+        let cloned = seqs.clone();
+        assert_eq!(cloned.next(bob), (2 << 32) + 1);
+
+        // After a restart, the node starts in a later session, above everything it sent before.
+        let restarted = SequenceNumbers::new(3);
+        assert!(restarted.next(alice) > seqs.next(alice));
+    }
 }
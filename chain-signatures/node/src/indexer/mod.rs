@@ -0,0 +1,757 @@
+use crate::gcp::error::DatastoreStorageError;
+use crate::gcp::GcpService;
+use crate::kdf::{DerivationCache, DerivationPath};
+use crate::protocol::signature::sign_request_span;
+use crate::protocol::{SignQueue, SignRequest};
+use crate::types::LatestBlockHeight;
+use anyhow::Context as _;
+use crypto_shared::eip712::typed_data_digest;
+use crypto_shared::{ScalarExt, SignMessage};
+use k256::Scalar;
+use mpc_contract::primitives::SignTypedDataRequest;
+use near_account_id::AccountId;
+use near_lake_framework::{LakeBuilder, LakeContext};
+use near_lake_primitives::actions::ActionMetaDataExt;
+use near_lake_primitives::receipts::ExecutionStatus;
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::BlockHeight;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::ops::Mul;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, RwLock};
+use tokio_retry::strategy::{jitter, ExponentialBackoff};
+use tokio_retry::Retry;
+
+mod rpc;
+
+/// Source of the blocks that the indexer goes through.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum IndexerKind {
+    /// Streams blocks from NEAR Lake, which is stored on AWS S3.
+    #[default]
+    Lake,
+    /// Polls blocks directly from a NEAR RPC node.
+    Rpc,
+}
+
+impl std::fmt::Display for IndexerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndexerKind::Lake => write!(f, "lake"),
+            IndexerKind::Rpc => write!(f, "rpc"),
+        }
+    }
+}
+
+/// Configures indexer.
+#[derive(Debug, Clone, clap::Parser)]
+#[group(id = "indexer_options")]
+pub struct Options {
+    /// Where the indexer gets its blocks from.
+    #[clap(long, env("MPC_INDEXER_KIND"), value_enum, default_value_t = IndexerKind::Lake)]
+    pub indexer_kind: IndexerKind,
+
+    /// NEAR RPC URL to poll blocks from when using the `rpc` indexer. Only requests made
+    /// directly through transactions to the contract are picked up by the `rpc` indexer.
+    #[clap(
+        long,
+        env("MPC_INDEXER_RPC_URL"),
+        required_if_eq("indexer_kind", "rpc")
+    )]
+    pub rpc_url: Option<String>,
+
+    /// How often in milliseconds the `rpc` indexer checks for new final blocks.
+    #[clap(long, env("MPC_INDEXER_RPC_POLL_INTERVAL"), default_value = "500")]
+    pub rpc_poll_interval: u64,
+
+    /// AWS S3 bucket name for NEAR Lake Indexer
+    #[clap(
+        long,
+        env("MPC_INDEXER_S3_BUCKET"),
+        default_value = "near-lake-data-testnet"
+    )]
+    pub s3_bucket: String,
+
+    /// AWS S3 region name for NEAR Lake Indexer
+    #[clap(long, env("MPC_INDEXER_S3_REGION"), default_value = "eu-central-1")]
+    pub s3_region: String,
+
+    /// AWS S3 URL for NEAR Lake Indexer (can be used to point to LocalStack)
+    #[clap(long, env("MPC_INDEXER_S3_URL"))]
+    pub s3_url: Option<String>,
+
+    /// The block height to start indexing from when the node has not indexed any block yet.
+    /// Otherwise the indexer resumes from the block after the last one it processed.
+    // Defaults to the latest block on 2023-11-14 07:40:22 AM UTC
+    #[clap(
+        long,
+        env("MPC_INDEXER_START_BLOCK_HEIGHT"),
+        default_value = "145964826"
+    )]
+    pub start_block_height: u64,
+
+    /// The block height to start indexing from, regardless of the last block the node
+    /// processed, e.g. to go back over blocks whose requests were missed.
+    #[clap(long, env("MPC_INDEXER_START_BLOCK"))]
+    pub indexer_start_block: Option<u64>,
+
+    /// The amount of time before we should that our indexer is behind.
+    #[clap(long, env("MPC_INDEXER_BEHIND_THRESHOLD"), default_value = "200")]
+    pub behind_threshold: u64,
+
+    /// The threshold in seconds to check if the indexer needs to be restarted due to it stalling.
+    #[clap(long, env("MPC_INDEXER_RUNNING_THRESHOLD"), default_value = "300")]
+    pub running_threshold: u64,
+
+    /// How many derivation paths to cache the epsilon of, evicting the least recently used
+    /// ones. Zero disables the cache.
+    #[clap(long, env("MPC_DERIVATION_CACHE_SIZE"), default_value = "0")]
+    pub derivation_cache_size: usize,
+
+    /// Comma separated hot derivation paths, e.g. of popular dApps, in the form of
+    /// `<predecessor_id>:<path>`. Their epsilon is derived on startup and never evicted.
+    #[clap(long, env("MPC_DERIVATION_HOT_PATHS"), value_delimiter = ',')]
+    pub derivation_hot_paths: Vec<DerivationPath>,
+}
+
+impl Options {
+    pub fn into_str_args(self) -> Vec<String> {
+        let mut opts = vec![
+            "--indexer-kind".to_string(),
+            self.indexer_kind.to_string(),
+            "--rpc-poll-interval".to_string(),
+            self.rpc_poll_interval.to_string(),
+            "--s3-bucket".to_string(),
+            self.s3_bucket,
+            "--s3-region".to_string(),
+            self.s3_region,
+            "--start-block-height".to_string(),
+            self.start_block_height.to_string(),
+            "--behind-threshold".to_string(),
+            self.behind_threshold.to_string(),
+            "--running-threshold".to_string(),
+            self.running_threshold.to_string(),
+            "--derivation-cache-size".to_string(),
+            self.derivation_cache_size.to_string(),
+        ];
+
+        if let Some(s3_url) = self.s3_url {
+            opts.extend(vec!["--s3-url".to_string(), s3_url]);
+        }
+        if let Some(rpc_url) = self.rpc_url {
+            opts.extend(vec!["--rpc-url".to_string(), rpc_url]);
+        }
+        if let Some(indexer_start_block) = self.indexer_start_block {
+            opts.extend(vec![
+                "--indexer-start-block".to_string(),
+                indexer_start_block.to_string(),
+            ]);
+        }
+        for path in self.derivation_hot_paths {
+            opts.extend(vec!["--derivation-hot-paths".to_string(), path.to_string()]);
+        }
+
+        opts
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+struct SignArguments {
+    request: UnvalidatedContractSignRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+struct SignBatchArguments {
+    requests: Vec<UnvalidatedContractSignRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SignTypedDataArguments {
+    request: SignTypedDataRequest,
+}
+
+/// What is recieved when sign is called
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+struct UnvalidatedContractSignRequest {
+    #[serde(default)]
+    pub payload: [u8; 32],
+    pub path: String,
+    pub key_version: u32,
+    /// Full message that the contract hashed into the payload instead.
+    #[serde(default)]
+    pub message: Option<SignMessage>,
+}
+
+/// A validated version of the sign request
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ContractSignRequest {
+    pub payload: Scalar,
+    pub path: String,
+    pub key_version: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Indexer {
+    latest_block_height: Arc<RwLock<LatestBlockHeight>>,
+    last_updated_timestamp: Arc<RwLock<Instant>>,
+    latest_block_timestamp_nanosec: Arc<RwLock<Option<u64>>>,
+    running_threshold: Duration,
+    behind_threshold: Duration,
+    /// Notified when the indexer has to start over from `latest_block_height`.
+    resync: Arc<Notify>,
+}
+
+impl Indexer {
+    fn new(latest_block_height: LatestBlockHeight, options: &Options) -> Self {
+        tracing::info!(
+            "creating new indexer, latest block height: {}",
+            latest_block_height.block_height
+        );
+        Self {
+            latest_block_height: Arc::new(RwLock::new(latest_block_height)),
+            last_updated_timestamp: Arc::new(RwLock::new(Instant::now())),
+            latest_block_timestamp_nanosec: Arc::new(RwLock::new(None)),
+            running_threshold: Duration::from_secs(options.running_threshold),
+            behind_threshold: Duration::from_secs(options.behind_threshold),
+            resync: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Makes the indexer start over from `block_height`, e.g. to pick up the requests of blocks
+    /// that it missed. The new height gets persisted once the first block after it is indexed.
+    pub async fn resync(&self, block_height: BlockHeight) {
+        tracing::info!(block_height, "resyncing indexer");
+        self.latest_block_height.write().await.set(block_height);
+        self.resync.notify_one();
+    }
+
+    /// Get the latest block height from the chain.
+    pub async fn latest_block_height(&self) -> BlockHeight {
+        self.latest_block_height.read().await.block_height
+    }
+
+    /// Check whether the indexer is on track with the latest block height from the chain.
+    pub async fn is_running(&self) -> bool {
+        self.last_updated_timestamp.read().await.elapsed() <= self.running_threshold
+    }
+
+    /// Check whether the indexer is behind with the latest block height from the chain.
+    pub async fn is_behind(&self) -> bool {
+        if let Some(latest_block_timestamp_nanosec) =
+            *self.latest_block_timestamp_nanosec.read().await
+        {
+            crate::util::is_elapsed_longer_than_timeout(
+                latest_block_timestamp_nanosec / 1_000_000_000,
+                self.behind_threshold.as_millis() as u64,
+            )
+        } else {
+            true
+        }
+    }
+
+    pub async fn is_stable(&self) -> bool {
+        !self.is_behind().await && self.is_running().await
+    }
+
+    async fn update_block_height_and_timestamp(
+        &self,
+        block_height: BlockHeight,
+        block_timestamp_nanosec: u64,
+        gcp: &GcpService,
+    ) -> Result<(), DatastoreStorageError> {
+        tracing::debug!(block_height, "update_block_height_and_timestamp");
+        *self.last_updated_timestamp.write().await = Instant::now();
+        *self.latest_block_timestamp_nanosec.write().await = Some(block_timestamp_nanosec);
+        self.latest_block_height
+            .write()
+            .await
+            .set(block_height)
+            .store(gcp)
+            .await
+    }
+}
+
+#[derive(Clone, LakeContext)]
+struct Context {
+    mpc_contract_id: AccountId,
+    node_account_id: AccountId,
+    gcp_service: GcpService,
+    queue: Arc<RwLock<SignQueue>>,
+    indexer: Indexer,
+    derivation_cache: Arc<DerivationCache>,
+}
+
+/// A successfully executed function call on the contract that might contain sign requests.
+struct ContractCall<'a> {
+    method_name: &'a str,
+    args: &'a [u8],
+    /// The receipt that the call yielded to, i.e. the one awaiting the signature.
+    receipt_id: [u8; 32],
+    predecessor_id: AccountId,
+    /// Deposit attached to the call, shared evenly by the requests of a `sign_batch`.
+    deposit: u128,
+    logs: &'a [String],
+}
+
+/// Extracts the sign requests out of a `sign`, `sign_batch` or `sign_typed_data` call. Any other
+/// call is ignored.
+fn sign_requests(
+    call: ContractCall<'_>,
+    node_account_id: &AccountId,
+    derivation_cache: &DerivationCache,
+) -> Vec<SignRequest> {
+    let ContractCall {
+        method_name,
+        args,
+        receipt_id,
+        predecessor_id,
+        deposit,
+        logs,
+    } = call;
+    let requests = match method_name {
+        "sign" => {
+            tracing::debug!("found `sign` function call");
+            match serde_json::from_slice::<'_, SignArguments>(args) {
+                Ok(arguments) => vec![(receipt_id, None, arguments.request)],
+                Err(err) => {
+                    tracing::warn!(%err, "failed to parse `sign` arguments");
+                    return Vec::new();
+                }
+            }
+        }
+        "sign_batch" => {
+            tracing::debug!("found `sign_batch` function call");
+            match serde_json::from_slice::<'_, SignBatchArguments>(args) {
+                Ok(arguments) => arguments
+                    .requests
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, request)| {
+                        (batch_request_id(receipt_id, index), Some(index), request)
+                    })
+                    .collect(),
+                Err(err) => {
+                    tracing::warn!(%err, "failed to parse `sign_batch` arguments");
+                    return Vec::new();
+                }
+            }
+        }
+        "sign_typed_data" => {
+            tracing::debug!("found `sign_typed_data` function call");
+            match serde_json::from_slice::<'_, SignTypedDataArguments>(args) {
+                // The call only succeeded if the contract accepted the domain separator.
+                Ok(SignTypedDataArguments { request }) => vec![(
+                    receipt_id,
+                    None,
+                    UnvalidatedContractSignRequest {
+                        payload: typed_data_digest(&request.domain_separator, &request.struct_hash),
+                        path: request.path,
+                        key_version: request.key_version,
+                        message: None,
+                    },
+                )],
+                Err(err) => {
+                    tracing::warn!(%err, "failed to parse `sign_typed_data` arguments");
+                    return Vec::new();
+                }
+            }
+        }
+        _ => return Vec::new(),
+    };
+
+    // The contract resolves duplicates of a pending request along with it, so they are not
+    // signed on their own.
+    if logs
+        .first()
+        .is_some_and(|log| log.starts_with("sign: duplicate"))
+    {
+        tracing::debug!("`{method_name}` duplicates a pending request");
+        return Vec::new();
+    }
+
+    if logs.is_empty() {
+        tracing::warn!("`{method_name}` did not produce entropy");
+        return Vec::new();
+    }
+
+    let entropy_log_index = 1;
+    let Some(Ok(entropy)) = logs
+        .get(entropy_log_index)
+        .map(|log| serde_json::from_str::<'_, [u8; 32]>(log))
+    else {
+        tracing::warn!(
+            "`{method_name}` did not produce entropy correctly: {:?}",
+            logs.get(entropy_log_index)
+        );
+        return Vec::new();
+    };
+
+    let deposit = deposit / requests.len().max(1) as u128;
+    let mut sign_requests = Vec::new();
+    for (request_id, batch_index, request) in requests {
+        let _span = sign_request_span(request_id).entered();
+        let entropy = match batch_index {
+            Some(index) => batch_entropy(entropy, index),
+            None => entropy,
+        };
+        // The call only succeeded if the contract hashed the message the same way.
+        let payload_bytes = match &request.message {
+            Some(message) => message.digest(),
+            None => request.payload,
+        };
+        let Some(payload) = Scalar::from_bytes(payload_bytes) else {
+            tracing::warn!(
+                "`{method_name}` did not produce payload correctly: {:?}",
+                payload_bytes,
+            );
+            continue;
+        };
+
+        let epsilon = derivation_cache.epsilon(&predecessor_id, &request.path);
+        tracing::info!(
+            receipt_id = %CryptoHash(receipt_id),
+            request_id = %CryptoHash(request_id),
+            caller_id = predecessor_id.to_string(),
+            our_account = node_account_id.to_string(),
+            payload = hex::encode(payload_bytes),
+            key_version = request.key_version,
+            deposit,
+            entropy = hex::encode(entropy),
+            "indexed new `{method_name}` function call"
+        );
+        let request = ContractSignRequest {
+            payload,
+            path: request.path,
+            key_version: request.key_version,
+        };
+        sign_requests.push(SignRequest {
+            request_id,
+            request,
+            epsilon,
+            entropy,
+            deposit,
+            requester: predecessor_id.clone(),
+            // TODO: use indexer timestamp instead.
+            time_added: Instant::now(),
+        });
+    }
+    sign_requests
+}
+
+#[tracing::instrument(level = "info", skip_all, fields(block_height = block.block_height()))]
+async fn handle_block(
+    mut block: near_lake_primitives::block::Block,
+    ctx: &Context,
+) -> anyhow::Result<()> {
+    tracing::debug!("handle_block");
+    let mut pending_requests = Vec::new();
+    for action in block.actions().cloned().collect::<Vec<_>>() {
+        if action.receiver_id() == ctx.mpc_contract_id {
+            tracing::debug!("got action targeting {}", ctx.mpc_contract_id);
+            let Some(receipt) = block.receipt_by_id(&action.receipt_id()) else {
+                let err = format!(
+                    "indexer unable to find block for receipt_id={}",
+                    action.receipt_id()
+                );
+                tracing::warn!("{err}");
+                anyhow::bail!(err);
+            };
+            let ExecutionStatus::SuccessReceiptId(receipt_id) = receipt.status() else {
+                continue;
+            };
+            let Some(function_call) = action.as_function_call() else {
+                continue;
+            };
+            pending_requests.extend(sign_requests(
+                ContractCall {
+                    method_name: function_call.method_name(),
+                    args: function_call.args(),
+                    receipt_id: receipt_id.0,
+                    predecessor_id: action.predecessor_id(),
+                    deposit: function_call.deposit(),
+                    logs: receipt.logs(),
+                },
+                &ctx.node_account_id,
+                &ctx.derivation_cache,
+            ));
+        }
+    }
+
+    finish_block(
+        ctx,
+        block.block_height(),
+        block.header().timestamp_nanosec(),
+        pending_requests,
+    )
+    .await
+}
+
+/// Records that a block has been fully indexed and hands its sign requests to the protocol.
+async fn finish_block(
+    ctx: &Context,
+    block_height: BlockHeight,
+    block_timestamp_nanosec: u64,
+    pending_requests: Vec<SignRequest>,
+) -> anyhow::Result<()> {
+    ctx.indexer
+        .update_block_height_and_timestamp(block_height, block_timestamp_nanosec, &ctx.gcp_service)
+        .await?;
+
+    crate::metrics::LATEST_BLOCK_HEIGHT
+        .with_label_values(&[ctx.gcp_service.account_id.as_str()])
+        .set(block_height as i64);
+    let delay_ms =
+        chrono::Utc::now().timestamp_millis() - (block_timestamp_nanosec / 1_000_000) as i64;
+    crate::metrics::INDEXER_DELAY_MS
+        .with_label_values(&[ctx.gcp_service.account_id.as_str()])
+        .set(delay_ms.max(0));
+
+    // Add the requests after going through the whole block to avoid partial processing if indexer fails somewhere.
+    // This way we can revisit the same block if we failed while not having added the requests partially.
+    let mut queue = ctx.queue.write().await;
+    for request in pending_requests {
+        queue.add(request);
+        crate::metrics::NUM_SIGN_REQUESTS
+            .with_label_values(&[ctx.gcp_service.account_id.as_str()])
+            .inc();
+    }
+    drop(queue);
+
+    let log_indexing_interval = 1000;
+    if block_height % log_indexing_interval == 0 {
+        tracing::info!(
+            "indexed another {} blocks, latest: {}",
+            log_indexing_interval,
+            block_height
+        );
+    }
+
+    Ok(())
+}
+
+pub fn run(
+    options: &Options,
+    mpc_contract_id: &AccountId,
+    node_account_id: &AccountId,
+    queue: &Arc<RwLock<SignQueue>>,
+    gcp_service: &crate::gcp::GcpService,
+    rt: &tokio::runtime::Runtime,
+) -> anyhow::Result<(JoinHandle<anyhow::Result<()>>, Indexer)> {
+    tracing::info!(
+        indexer_kind = %options.indexer_kind,
+        rpc_url = options.rpc_url,
+        s3_bucket = options.s3_bucket,
+        s3_region = options.s3_region,
+        s3_url = options.s3_url,
+        start_block_height = options.start_block_height,
+        indexer_start_block = options.indexer_start_block,
+        %mpc_contract_id,
+        "starting indexer"
+    );
+
+    let start_block_height = rt.block_on(start_block_height(options, gcp_service))?;
+    let latest_block_height = LatestBlockHeight {
+        account_id: node_account_id.clone(),
+        block_height: start_block_height,
+    };
+
+    let indexer = Indexer::new(latest_block_height, options);
+    let context = Context {
+        mpc_contract_id: mpc_contract_id.clone(),
+        node_account_id: node_account_id.clone(),
+        gcp_service: gcp_service.clone(),
+        queue: queue.clone(),
+        indexer: indexer.clone(),
+        derivation_cache: Arc::new(DerivationCache::new(
+            node_account_id,
+            options.derivation_cache_size,
+            &options.derivation_hot_paths,
+        )),
+    };
+
+    let options = options.clone();
+    let join_handle = std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+
+        // If indexer fails for whatever reason, let's spin it back up:
+        let mut i = 0;
+        loop {
+            if i > 0 {
+                tracing::warn!("restarting indexer after failure: restart count={i}");
+            }
+            i += 1;
+
+            if options.indexer_kind == IndexerKind::Rpc {
+                if let Err(err) = rt.block_on(rpc::run(&options, &context)) {
+                    tracing::warn!(%err, "rpc indexer failed");
+                }
+                backoff(i, 1, 120);
+                continue;
+            }
+
+            let Ok(lake) = rt.block_on(async {
+                let latest = context.indexer.latest_block_height().await;
+                if i > 0 {
+                    tracing::warn!("indexer latest height {latest}, restart count={i}");
+                }
+                let mut lake_builder = LakeBuilder::default()
+                    .s3_bucket_name(&options.s3_bucket)
+                    .s3_region_name(&options.s3_region)
+                    .start_block_height(latest);
+
+                if let Some(s3_url) = &options.s3_url {
+                    let aws_config = aws_config::from_env().load().await;
+                    let s3_config = aws_sdk_s3::config::Builder::from(&aws_config)
+                        .endpoint_url(s3_url)
+                        .build();
+                    lake_builder = lake_builder.s3_config(s3_config);
+                }
+                let lake = lake_builder.build()?;
+                anyhow::Ok(lake)
+            }) else {
+                tracing::error!(?options, "indexer failed to build");
+                backoff(i, 1, 120);
+                continue;
+            };
+
+            // TODO/NOTE: currently indexer does not have any interrupt handlers and will never yield back
+            // as successful. We can add interrupt handlers in the future but this is not important right
+            // now since we managing nodes through integration tests that can kill it or through docker.
+            let join_handle = {
+                let context = context.clone();
+                rt.spawn(async move { lake.run_with_context_async(handle_block, &context).await })
+            };
+            let (resynced, outcome) = rt.block_on(async {
+                if i > 0 {
+                    // give it some time to catch up
+                    tracing::debug!("giving indexer some time to catch up");
+                    backoff(i, 10, 300);
+                }
+                // while running, we will keep the task spinning, and check every so often if
+                // the indexer has errored out or has to resync.
+                let mut resynced = false;
+                while context.indexer.is_running().await {
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_secs(60)) => {}
+                        _ = context.indexer.resync.notified() => {
+                            resynced = true;
+                            break;
+                        }
+                    }
+                    if join_handle.is_finished() {
+                        break;
+                    }
+                }
+
+                // Abort the indexer task if it's still running.
+                if !join_handle.is_finished() {
+                    tracing::debug!("aborting indexer task");
+                    join_handle.abort();
+                }
+
+                (resynced, join_handle.await)
+            });
+
+            if resynced {
+                // Start over right away from the height that was resynced to.
+                i = 0;
+                continue;
+            }
+
+            match outcome {
+                Ok(Ok(())) => {
+                    tracing::warn!("indexer finished successfully? -- this should not happen");
+                    break;
+                }
+                Ok(Err(err)) => {
+                    tracing::warn!(%err, "indexer failed");
+                }
+                Err(err) => {
+                    tracing::warn!(%err, "indexer join handle failed");
+                }
+            }
+
+            backoff(i, 1, 120)
+        }
+        Ok(())
+    });
+
+    Ok((join_handle, indexer))
+}
+
+/// The block the indexer starts from: the one after the last block processed before the node
+/// got restarted, unless overridden by `--indexer-start-block`. Starting from the latest block
+/// would silently skip the requests made while the node was down, so the last processed block
+/// is retried for a while if the storage is unreachable instead of falling back to the start
+/// block height.
+async fn start_block_height(options: &Options, gcp: &GcpService) -> anyhow::Result<BlockHeight> {
+    if let Some(block_height) = options.indexer_start_block {
+        tracing::info!(
+            block_height,
+            "starting indexer from the block set by --indexer-start-block"
+        );
+        return Ok(block_height);
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(500).map(jitter).take(5);
+    let last_processed = Retry::spawn(retry_strategy, || async {
+        match LatestBlockHeight::fetch(gcp).await {
+            Ok(latest) => Ok(Some(latest.block_height)),
+            Err(DatastoreStorageError::EntityNotFound(_)) => Ok(None),
+            Err(err) => {
+                tracing::warn!(%err, "failed to fetch the last processed block height");
+                Err(err)
+            }
+        }
+    })
+    .await
+    .context("failed to fetch the last processed block height")?;
+
+    Ok(match last_processed {
+        Some(block_height) => {
+            tracing::info!(
+                block_height,
+                "resuming indexer after the last processed block"
+            );
+            block_height + 1
+        }
+        None => {
+            tracing::info!(
+                block_height = options.start_block_height,
+                "no block processed yet, starting indexer from --start-block-height"
+            );
+            options.start_block_height
+        }
+    })
+}
+
+/// All requests in a `sign_batch` call share the same receipt, so each one gets its own
+/// request id derived from the receipt id and its position in the batch.
+fn batch_request_id(receipt_id: [u8; 32], index: usize) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(receipt_id);
+    hasher.update((index as u64).to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// All requests in a `sign_batch` call share the entropy logged by the contract, so each one
+/// gets its own entropy derived from it and its position in the batch. Otherwise the signatures
+/// of a batch would be produced with the same randomness.
+fn batch_entropy(entropy: [u8; 32], index: usize) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(entropy);
+    hasher.update((index as u64).to_le_bytes());
+    hasher.finalize().into()
+}
+
+fn backoff(i: u32, multiplier: u32, max: u64) {
+    // Exponential backoff with max delay of max seconds
+    let delay: u64 = std::cmp::min(2u64.pow(i).mul(multiplier as u64), max);
+    std::thread::sleep(std::time::Duration::from_secs(delay));
+}
@@ -0,0 +1,187 @@
+//! Indexer that polls final blocks straight from a NEAR RPC node instead of going through
+//! NEAR Lake. Useful for networks that do not have a lake bucket, such as private networks.
+//!
+//! The RPC does not expose the receipts of a chunk, so this indexer only goes through the
+//! transactions of each chunk and looks up their outcomes. This means that only requests made
+//! by a transaction sent directly to the contract are picked up; requests coming from other
+//! contracts through cross-contract calls are not seen by this indexer.
+
+use super::{finish_block, sign_requests, Context, ContractCall, Options};
+
+use near_jsonrpc_client::errors::{JsonRpcError, JsonRpcServerError};
+use near_jsonrpc_client::{methods, JsonRpcClient};
+use near_jsonrpc_primitives::types::chunks::ChunkReference;
+use near_jsonrpc_primitives::types::transactions::TransactionInfo;
+use near_primitives::types::{BlockHeight, BlockId, BlockReference, Finality};
+use near_primitives::views::{
+    ActionView, BlockView, ExecutionStatusView, SignedTransactionView, TxExecutionStatus,
+};
+use std::time::Duration;
+
+pub(super) async fn run(options: &Options, ctx: &Context) -> anyhow::Result<()> {
+    let Some(rpc_url) = &options.rpc_url else {
+        anyhow::bail!("rpc indexer requires --rpc-url to be set");
+    };
+    let client = JsonRpcClient::connect(rpc_url);
+    let poll_interval = Duration::from_millis(options.rpc_poll_interval);
+
+    let mut next_height = ctx.indexer.latest_block_height().await;
+    tracing::info!(rpc_url, next_height, "starting rpc indexer");
+    loop {
+        let final_block = client
+            .call(methods::block::RpcBlockRequest {
+                block_reference: BlockReference::Finality(Finality::Final),
+            })
+            .await?;
+
+        while next_height <= final_block.header.height {
+            if let Some(block) = fetch_block(&client, next_height).await? {
+                handle_block(&client, block, ctx).await?;
+            }
+            next_height += 1;
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(poll_interval) => {}
+            _ = ctx.indexer.resync.notified() => {
+                next_height = ctx.indexer.latest_block_height().await;
+                tracing::info!(next_height, "resyncing rpc indexer");
+            }
+        }
+    }
+}
+
+/// Fetches the block at the given height. Returns `None` if no block was produced at it.
+async fn fetch_block(
+    client: &JsonRpcClient,
+    height: BlockHeight,
+) -> anyhow::Result<Option<BlockView>> {
+    let result = client
+        .call(methods::block::RpcBlockRequest {
+            block_reference: BlockReference::BlockId(BlockId::Height(height)),
+        })
+        .await;
+    match result {
+        Ok(block) => Ok(Some(block)),
+        Err(JsonRpcError::ServerError(JsonRpcServerError::HandlerError(
+            methods::block::RpcBlockError::UnknownBlock { .. },
+        ))) => {
+            tracing::debug!(height, "no block at height, skipping");
+            Ok(None)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+async fn handle_block(
+    client: &JsonRpcClient,
+    block: BlockView,
+    ctx: &Context,
+) -> anyhow::Result<()> {
+    let block_height = block.header.height;
+    tracing::debug!(block_height, "handle_block");
+    let mut pending_requests = Vec::new();
+    for chunk in block.chunks {
+        // Chunks that were not produced at this height are repeated from previous blocks.
+        if chunk.height_included != block_height {
+            continue;
+        }
+        let chunk = client
+            .call(methods::chunk::RpcChunkRequest {
+                chunk_reference: ChunkReference::ChunkHash {
+                    chunk_id: chunk.chunk_hash,
+                },
+            })
+            .await?;
+
+        for transaction in chunk.transactions {
+            if transaction.receiver_id != ctx.mpc_contract_id {
+                continue;
+            }
+            tracing::debug!("got transaction targeting {}", ctx.mpc_contract_id);
+            pending_requests.extend(handle_transaction(client, transaction, ctx).await?);
+        }
+    }
+
+    finish_block(
+        ctx,
+        block_height,
+        block.header.timestamp_nanosec,
+        pending_requests,
+    )
+    .await
+}
+
+async fn handle_transaction(
+    client: &JsonRpcClient,
+    transaction: SignedTransactionView,
+    ctx: &Context,
+) -> anyhow::Result<Vec<crate::protocol::SignRequest>> {
+    if !transaction
+        .actions
+        .iter()
+        .any(|action| matches!(action, ActionView::FunctionCall { .. }))
+    {
+        return Ok(Vec::new());
+    }
+
+    let response = client
+        .call(methods::tx::RpcTransactionStatusRequest {
+            transaction_info: TransactionInfo::TransactionId {
+                tx_hash: transaction.hash,
+                sender_account_id: transaction.signer_id.clone(),
+            },
+            wait_until: TxExecutionStatus::Final,
+        })
+        .await?;
+    let Some(outcome) = response.final_execution_outcome else {
+        anyhow::bail!(
+            "indexer unable to find outcome for transaction={}",
+            transaction.hash
+        );
+    };
+    let outcome = outcome.into_outcome();
+
+    // The transaction gets converted into a single receipt executed on the contract.
+    let Some(receipt_id) = outcome.transaction_outcome.outcome.receipt_ids.first() else {
+        return Ok(Vec::new());
+    };
+    let Some(receipt) = outcome
+        .receipts_outcome
+        .iter()
+        .find(|receipt| &receipt.id == receipt_id)
+    else {
+        let err = format!("indexer unable to find outcome for receipt_id={receipt_id}");
+        tracing::warn!("{err}");
+        anyhow::bail!(err);
+    };
+    let ExecutionStatusView::SuccessReceiptId(yield_receipt_id) = &receipt.outcome.status else {
+        return Ok(Vec::new());
+    };
+
+    let mut requests = Vec::new();
+    for action in &transaction.actions {
+        let ActionView::FunctionCall {
+            method_name,
+            args,
+            deposit,
+            ..
+        } = action
+        else {
+            continue;
+        };
+        requests.extend(sign_requests(
+            ContractCall {
+                method_name,
+                args,
+                receipt_id: yield_receipt_id.0,
+                predecessor_id: transaction.signer_id.clone(),
+                deposit: *deposit,
+                logs: &receipt.outcome.logs,
+            },
+            &ctx.node_account_id,
+            &ctx.derivation_cache,
+        ));
+    }
+    Ok(requests)
+}
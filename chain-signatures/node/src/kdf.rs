@@ -1,9 +1,14 @@
 use anyhow::Context;
-use crypto_shared::{kdf::recover, x_coordinate, ScalarExt, SignatureResponse};
+use crypto_shared::{derive_epsilon, kdf::recover, x_coordinate, ScalarExt, SignatureResponse};
 use hkdf::Hkdf;
 use k256::{ecdsa::RecoveryId, elliptic_curve::sec1::ToEncodedPoint, AffinePoint, Scalar};
+use lru::LruCache;
+use near_account_id::AccountId;
 use near_primitives::hash::CryptoHash;
 use sha3::Sha3_256;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
 
 // In case there are multiple requests in the same block (hence same entropy), we need to ensure
 // that we generate different random scalars as delta tweaks.
@@ -63,3 +68,137 @@ pub fn into_eth_sig(
 
     anyhow::bail!("cannot use either recovery id (0 or 1) to recover pubic key")
 }
+
+/// A derivation path of a predecessor, written as `<predecessor_id>:<path>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DerivationPath {
+    pub predecessor_id: AccountId,
+    pub path: String,
+}
+
+impl std::str::FromStr for DerivationPath {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Account ids can not contain `:`, so the first one separates it from the path.
+        let (predecessor_id, path) = s
+            .split_once(':')
+            .context("derivation path has to be in the form of `<predecessor_id>:<path>`")?;
+        Ok(Self {
+            predecessor_id: predecessor_id.parse()?,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.predecessor_id, self.path)
+    }
+}
+
+/// Caches the epsilon of derivation paths, so that it does not have to be derived again for
+/// every sign request of a hot path. The hot paths given upfront are derived right away and never
+/// evicted, any other path is kept in a LRU cache of `capacity` entries.
+pub struct DerivationCache {
+    node_account_id: AccountId,
+    hot: HashMap<DerivationPath, Scalar>,
+    recent: Option<Mutex<LruCache<DerivationPath, Scalar>>>,
+}
+
+impl DerivationCache {
+    pub fn new(node_account_id: &AccountId, capacity: usize, hot_paths: &[DerivationPath]) -> Self {
+        let hot = hot_paths
+            .iter()
+            .map(|path| {
+                let epsilon = derive_epsilon(&path.predecessor_id, &path.path);
+                (path.clone(), epsilon)
+            })
+            .collect::<HashMap<_, _>>();
+        crate::metrics::DERIVATION_CACHE_SIZE
+            .with_label_values(&[node_account_id.as_str()])
+            .set(hot.len() as i64);
+        Self {
+            node_account_id: node_account_id.clone(),
+            hot,
+            recent: NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    /// Returns the epsilon of `path` of `predecessor_id`, deriving it if it is not cached.
+    pub fn epsilon(&self, predecessor_id: &AccountId, path: &str) -> Scalar {
+        let key = DerivationPath {
+            predecessor_id: predecessor_id.clone(),
+            path: path.to_string(),
+        };
+        if let Some(epsilon) = self.hot.get(&key) {
+            self.record(&crate::metrics::DERIVATION_CACHE_HITS);
+            return *epsilon;
+        }
+        let Some(recent) = &self.recent else {
+            self.record(&crate::metrics::DERIVATION_CACHE_MISSES);
+            return derive_epsilon(predecessor_id, path);
+        };
+
+        let mut recent = recent.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(epsilon) = recent.get(&key) {
+            self.record(&crate::metrics::DERIVATION_CACHE_HITS);
+            return *epsilon;
+        }
+        self.record(&crate::metrics::DERIVATION_CACHE_MISSES);
+        let epsilon = derive_epsilon(predecessor_id, path);
+        // The key is not in the cache, so anything returned has been evicted to make space.
+        if recent.push(key, epsilon).is_some() {
+            self.record(&crate::metrics::DERIVATION_CACHE_EVICTIONS);
+        }
+        crate::metrics::DERIVATION_CACHE_SIZE
+            .with_label_values(&[self.node_account_id.as_str()])
+            .set((self.hot.len() + recent.len()) as i64);
+        epsilon
+    }
+
+    fn record(&self, counter: &crate::metrics::CounterVec) {
+        counter
+            .with_label_values(&[self.node_account_id.as_str()])
+            .inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derivation_cache() {
+        let node: AccountId = "node.near".parse().unwrap();
+        let dapp: DerivationPath = "dapp.near:ethereum-1".parse().unwrap();
+        assert_eq!(dapp.predecessor_id.as_str(), "dapp.near");
+        assert_eq!(dapp.path, "ethereum-1");
+        assert_eq!(dapp.to_string(), "dapp.near:ethereum-1");
+        assert!("ethereum-1".parse::<DerivationPath>().is_err());
+
+        let cache = DerivationCache::new(&node, 1, &[dapp.clone()]);
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        for (predecessor_id, path) in [
+            (&dapp.predecessor_id, dapp.path.as_str()),
+            (&alice, "path"),
+            (&bob, "path"),
+            (&alice, "path"),
+        ] {
+            assert_eq!(
+                cache.epsilon(predecessor_id, path),
+                derive_epsilon(predecessor_id, path)
+            );
+        }
+
+        // The hot path is never evicted, while bob evicted alice from the single other entry.
+        let recent = cache.recent.as_ref().unwrap().lock().unwrap();
+        assert!(cache.hot.contains_key(&dapp));
+        assert!(recent.contains(&DerivationPath {
+            predecessor_id: alice,
+            path: "path".to_string(),
+        }));
+        assert_eq!(recent.len(), 1);
+    }
+}
@@ -1,14 +1,21 @@
+pub mod audit;
 pub mod cli;
 pub mod config;
+pub mod failpoint;
 pub mod gcp;
+pub mod grpc;
+pub mod hsm;
 pub mod http_client;
 pub mod indexer;
 pub mod kdf;
 pub mod mesh;
 pub mod metrics;
 pub mod protocol;
+pub mod quic;
+pub mod rng;
 pub mod rpc_client;
 pub mod storage;
+pub mod telemetry;
 pub mod types;
 pub mod util;
 pub mod web;
@@ -3,20 +3,26 @@ use std::time::{Duration, Instant};
 
 use cait_sith::protocol::Participant;
 use tokio::sync::RwLock;
+use tokio_retry::Retry;
 use url::Url;
 
 use crate::protocol::contract::primitives::Participants;
 use crate::protocol::ParticipantInfo;
 use crate::protocol::ProtocolState;
 use crate::web::StateView;
+use mpc_contract::config::{BackoffConfig, TimeoutConfig};
 use mpc_keys::hpke::Ciphered;
+use near_account_id::AccountId;
+use near_primitives::types::BlockHeight;
+use serde::{Deserialize, Serialize};
 
 // TODO: this is a basic connection pool and does not do most of the work yet. This is
 //       mostly here just to facilitate offline node handling for now.
 // TODO/NOTE: we can use libp2p to facilitate most the of low level TCP connection work.
-#[derive(Default)]
 pub struct Pool {
     http: reqwest::Client,
+    /// Delivers the empty messages over the authenticated QUIC mesh when mutual TLS is on.
+    quic: crate::quic::Client,
     connections: RwLock<Participants>,
     potential_connections: RwLock<Participants>,
     status: RwLock<HashMap<Participant, StateView>>,
@@ -27,6 +33,26 @@ pub struct Pool {
     potential_active: RwLock<Option<(Participants, Instant)>>,
     fetch_participant_timeout: Duration,
     refresh_active_timeout: Duration,
+    /// Retries of the empty message that checks whether a participant takes messages.
+    message_retry: BackoffConfig,
+}
+
+/// How this node sees one of its peers. This is the row of this node in the connectivity matrix
+/// of the network, the full matrix is made up of the rows of every node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerStatus {
+    pub participant: Participant,
+    pub account_id: AccountId,
+    pub url: String,
+    /// Whether the peer answered the last ping.
+    pub active: bool,
+    /// Whether the peer reported its indexer to be on track with the chain.
+    pub stable: bool,
+    /// The latest block height the peer reported, if it reported its state.
+    pub latest_block_height: Option<BlockHeight>,
+    /// Whether messages get delivered to the peer without failures or high latency.
+    #[serde(default)]
+    pub healthy: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
@@ -50,6 +76,7 @@ impl Pool {
         );
         Self {
             http: reqwest::Client::new(),
+            quic: crate::quic::Client::default(),
             connections: RwLock::new(Participants::default()),
             potential_connections: RwLock::new(Participants::default()),
             status: RwLock::new(HashMap::default()),
@@ -57,8 +84,32 @@ impl Pool {
             potential_active: RwLock::new(Option::default()),
             fetch_participant_timeout,
             refresh_active_timeout,
+            message_retry: TimeoutConfig::default().message_retry,
+        }
+    }
+
+    pub fn set_message_retry(&mut self, message_retry: BackoffConfig) {
+        self.message_retry = message_retry;
+    }
+
+    pub fn set_timeouts(
+        &mut self,
+        fetch_participant_timeout: Option<Duration>,
+        refresh_active_timeout: Option<Duration>,
+    ) {
+        if let Some(timeout) = fetch_participant_timeout {
+            self.fetch_participant_timeout = timeout;
+        }
+        if let Some(timeout) = refresh_active_timeout {
+            self.refresh_active_timeout = timeout;
         }
+        tracing::info!(
+            fetch_participant_timeout = ?self.fetch_participant_timeout,
+            refresh_active_timeout = ?self.refresh_active_timeout,
+            "changed pool timeouts"
+        );
     }
+
     pub async fn ping(&self) -> Participants {
         if let Some((ref active, timestamp)) = *self.current_active.read().await {
             if timestamp.elapsed() < self.refresh_active_timeout {
@@ -165,6 +216,56 @@ impl Pool {
         self.potential_connections.read().await.clone()
     }
 
+    /// Drops the cached active participants, so that the next ping reaches out to every
+    /// participant again instead of waiting for `refresh_active_timeout` to pass.
+    pub async fn invalidate(&self) {
+        *self.current_active.write().await = None;
+        *self.potential_active.write().await = None;
+    }
+
+    /// The status of every participant this node connects to, as of the last ping. Participants
+    /// that are in `active` answered the last ping.
+    pub async fn peer_statuses(&self, active: &Participants) -> Vec<PeerStatus> {
+        let connections = self.connections.read().await;
+        let potential_connections = self.potential_connections.read().await;
+        let status = self.status.read().await;
+        let mut peers = Vec::new();
+        for (participant, info) in connections.iter().chain(
+            potential_connections
+                .iter()
+                .filter(|(participant, _)| !connections.contains_key(participant)),
+        ) {
+            let state = status.get(participant);
+            let (stable, latest_block_height) = match state {
+                Some(StateView::Running {
+                    is_stable,
+                    latest_block_height,
+                    ..
+                })
+                | Some(StateView::Resharing {
+                    is_stable,
+                    latest_block_height,
+                    ..
+                }) => (*is_stable, Some(*latest_block_height)),
+                Some(StateView::Joining {
+                    latest_block_height,
+                    ..
+                }) => (false, Some(*latest_block_height)),
+                _ => (false, None),
+            };
+            peers.push(PeerStatus {
+                participant: *participant,
+                account_id: info.account_id.clone(),
+                url: info.url.clone(),
+                active: active.contains_key(participant),
+                stable,
+                latest_block_height,
+                healthy: true,
+            });
+        }
+        peers
+    }
+
     pub async fn is_participant_stable(&self, participant: &Participant) -> bool {
         self.status
             .read()
@@ -198,18 +299,36 @@ impl Pool {
         }
     }
 
+    /// Checks that the participant takes messages. With mutual TLS on, only the QUIC mesh
+    /// takes them, and the handshake also proves that the participant is who it claims to be,
+    /// unlike its `/state` which anyone on the path could answer.
     async fn send_empty_msg(
         &self,
         participant: &Participant,
         participant_info: &ParticipantInfo,
     ) -> Result<(), crate::http_client::SendError> {
         let empty_msg: Vec<Ciphered> = Vec::new();
+        if crate::quic::mtls_enabled() {
+            return Retry::spawn(
+                crate::http_client::retry_strategy(&self.message_retry),
+                || {
+                    self.quic.send_encrypted(
+                        *participant,
+                        &participant_info.url,
+                        empty_msg.clone(),
+                        self.fetch_participant_timeout,
+                    )
+                },
+            )
+            .await;
+        }
         crate::http_client::send_encrypted(
             *participant,
             &self.http,
             participant_info.url.clone(),
             empty_msg,
             self.fetch_participant_timeout,
+            &self.message_retry,
         )
         .await
     }
@@ -1,9 +1,12 @@
 use std::time::Duration;
 
+use mpc_contract::config::BackoffConfig;
+
 use crate::protocol::contract::primitives::Participants;
 use crate::protocol::ProtocolState;
 
 pub mod connection;
+pub mod reputation;
 
 #[derive(Debug, Clone, clap::Parser)]
 #[group(id = "mesh_options")]
@@ -16,6 +19,19 @@ pub struct Options {
     pub fetch_participant_timeout: u64,
     #[clap(long, env("MPC_MESH_REFRESH_ACTIVE_TIMEOUT"), default_value = "1000")]
     pub refresh_active_timeout: u64,
+    /// Consecutive failures to deliver messages to a peer before backing off from it.
+    #[clap(
+        long,
+        env("MPC_MESH_PEER_FAILURES_BEFORE_BACKOFF"),
+        default_value = "3"
+    )]
+    pub peer_failures_before_backoff: u32,
+    /// Longest time in milliseconds to back off from an unhealthy peer.
+    #[clap(long, env("MPC_MESH_PEER_MAX_BACKOFF"), default_value = "60000")]
+    pub peer_max_backoff: u64,
+    /// Average latency in milliseconds of delivering messages above which a peer is slow.
+    #[clap(long, env("MPC_MESH_PEER_SLOW_LATENCY"), default_value = "2000")]
+    pub peer_slow_latency: u64,
 }
 
 impl Options {
@@ -25,6 +41,12 @@ impl Options {
             self.fetch_participant_timeout.to_string(),
             "--refresh-active-timeout".to_string(),
             self.refresh_active_timeout.to_string(),
+            "--peer-failures-before-backoff".to_string(),
+            self.peer_failures_before_backoff.to_string(),
+            "--peer-max-backoff".to_string(),
+            self.peer_max_backoff.to_string(),
+            "--peer-slow-latency".to_string(),
+            self.peer_slow_latency.to_string(),
         ]
     }
 }
@@ -39,6 +61,9 @@ pub struct Mesh {
     /// Potential participants that are active at the beginning of each protocol loop. This
     /// includes participants belonging to the next epoch.
     pub active_potential_participants: Participants,
+
+    /// Health of the peers, built from delivering messages to them.
+    pub reputation: reputation::PeerReputation,
 }
 
 impl Mesh {
@@ -50,9 +75,29 @@ impl Mesh {
             ),
             active_participants: Participants::default(),
             active_potential_participants: Participants::default(),
+            reputation: reputation::PeerReputation::new(reputation::ReputationConfig {
+                failures_before_backoff: options.peer_failures_before_backoff,
+                max_backoff: Duration::from_millis(options.peer_max_backoff),
+                slow_latency: Duration::from_millis(options.peer_slow_latency),
+            }),
         }
     }
 
+    /// Changes the timeouts of reaching the peers, keeping the current ones that are not given.
+    pub fn set_timeouts(
+        &mut self,
+        fetch_participant_timeout: Option<Duration>,
+        refresh_active_timeout: Option<Duration>,
+    ) {
+        self.connections
+            .set_timeouts(fetch_participant_timeout, refresh_active_timeout);
+    }
+
+    /// Changes the retries of checking whether the peers take messages.
+    pub fn set_message_retry(&mut self, message_retry: BackoffConfig) {
+        self.connections.set_message_retry(message_retry);
+    }
+
     /// Participants that are active at the beginning of each protocol loop.
     pub fn active_participants(&self) -> &Participants {
         &self.active_participants
@@ -64,6 +109,11 @@ impl Mesh {
         &self.active_potential_participants
     }
 
+    /// Health of the peers, used to leave unhealthy peers out of new protocols.
+    pub fn reputation(&self) -> &reputation::PeerReputation {
+        &self.reputation
+    }
+
     /// Get all pontential participants, but they may not necessarily be active.
     pub async fn potential_participants(&self) -> Participants {
         self.connections.potential_participants().await
@@ -100,6 +150,7 @@ impl Mesh {
         self.connections
             .establish_participants(contract_state)
             .await;
+        crate::quic::trust_participants(contract_state);
         self.ping().await;
 
         tracing::debug!(
@@ -109,6 +160,24 @@ impl Mesh {
         );
     }
 
+    /// Pings every participant right away, ignoring the active participants of the last ping.
+    pub async fn refresh(&mut self) {
+        self.connections.invalidate().await;
+        self.ping().await;
+    }
+
+    /// How this node sees each of its peers.
+    pub async fn peer_statuses(&self) -> Vec<connection::PeerStatus> {
+        let mut peers = self
+            .connections
+            .peer_statuses(&self.all_active_participants())
+            .await;
+        for peer in &mut peers {
+            peer.healthy = self.reputation.is_healthy(&peer.participant);
+        }
+        peers
+    }
+
     /// Ping the active participants such that we can see who is alive.
     pub async fn ping(&mut self) {
         self.active_participants = self.connections.ping().await;
@@ -0,0 +1,206 @@
+//! Reputation of the peers of this node, built from how sending messages to them went. Peers
+//! that keep failing get backed off from and peers that are slow to answer get deprioritized
+//! when picking the participants of new triples, so that the stockpile keeps filling up at the
+//! pace of the healthy peers while one of them is degraded.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use cait_sith::protocol::Participant;
+
+use crate::protocol::contract::primitives::Participants;
+
+/// Backoff after the first failure that exceeds `failures_before_backoff`. Every further
+/// failure doubles it, up to `max_backoff`.
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Weight of the latest sample in the moving average of the latency of a peer.
+const LATENCY_WEIGHT: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReputationConfig {
+    /// Consecutive failures after which a peer gets backed off from.
+    pub failures_before_backoff: u32,
+    /// Longest a peer gets backed off from.
+    pub max_backoff: Duration,
+    /// Average latency above which a peer is considered slow.
+    pub slow_latency: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+struct PeerRecord {
+    consecutive_failures: u32,
+    /// Moving average of the latency of successful sends.
+    latency: Option<Duration>,
+    backoff_until: Option<Instant>,
+}
+
+pub struct PeerReputation {
+    config: ReputationConfig,
+    peers: Mutex<HashMap<Participant, PeerRecord>>,
+}
+
+impl PeerReputation {
+    pub fn new(config: ReputationConfig) -> Self {
+        Self {
+            config,
+            peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records messages that got delivered to `participant` within `latency`.
+    pub fn record_success(&self, participant: Participant, latency: Duration) {
+        let mut peers = self.peers.lock().unwrap();
+        let record = peers.entry(participant).or_default();
+        if record.backoff_until.is_some() {
+            tracing::info!(?participant, "peer recovered, no longer backing off");
+        }
+        record.consecutive_failures = 0;
+        record.backoff_until = None;
+        record.latency = Some(match record.latency {
+            Some(average) => {
+                average.mul_f64(1.0 - LATENCY_WEIGHT) + latency.mul_f64(LATENCY_WEIGHT)
+            }
+            None => latency,
+        });
+    }
+
+    /// Records messages that could not be delivered to `participant`.
+    pub fn record_failure(&self, participant: Participant) {
+        let mut peers = self.peers.lock().unwrap();
+        let record = peers.entry(participant).or_default();
+        record.consecutive_failures += 1;
+        let Some(excess) = record
+            .consecutive_failures
+            .checked_sub(self.config.failures_before_backoff)
+        else {
+            return;
+        };
+        let backoff = BASE_BACKOFF
+            .saturating_mul(1 << excess.min(16))
+            .min(self.config.max_backoff);
+        tracing::warn!(
+            ?participant,
+            failures = record.consecutive_failures,
+            ?backoff,
+            "backing off from unhealthy peer"
+        );
+        record.backoff_until = Some(Instant::now() + backoff);
+    }
+
+    fn is_backed_off(record: &PeerRecord) -> bool {
+        record
+            .backoff_until
+            .map_or(false, |backoff_until| Instant::now() < backoff_until)
+    }
+
+    fn is_slow(&self, record: &PeerRecord) -> bool {
+        record
+            .latency
+            .map_or(false, |latency| latency > self.config.slow_latency)
+    }
+
+    /// Whether `participant` is neither backed off from nor slow.
+    pub fn is_healthy(&self, participant: &Participant) -> bool {
+        let peers = self.peers.lock().unwrap();
+        peers.get(participant).map_or(true, |record| {
+            !Self::is_backed_off(record) && !self.is_slow(record)
+        })
+    }
+
+    /// Participants of a new protocol out of `participants`. Unhealthy peers are left out as
+    /// long as there are at least `threshold` participants without them, where slow peers get
+    /// picked before the ones that are backed off from. `me` is always part of the result.
+    pub fn select(
+        &self,
+        me: Participant,
+        participants: &Participants,
+        threshold: usize,
+    ) -> Participants {
+        let peers = self.peers.lock().unwrap();
+        let mut selected = Participants::default();
+        let mut unhealthy = Vec::new();
+        for (participant, info) in participants.iter() {
+            let record = peers.get(participant).cloned().unwrap_or_default();
+            let backed_off = Self::is_backed_off(&record);
+            if *participant == me || !(backed_off || self.is_slow(&record)) {
+                selected.insert(participant, info.clone());
+            } else {
+                unhealthy.push((backed_off, record.latency, participant, info));
+            }
+        }
+        unhealthy.sort_by_key(|(backed_off, latency, ..)| (*backed_off, *latency));
+        for (_, _, participant, info) in unhealthy {
+            if selected.len() >= threshold {
+                break;
+            }
+            selected.insert(participant, info.clone());
+        }
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ParticipantInfo;
+
+    fn reputation() -> PeerReputation {
+        PeerReputation::new(ReputationConfig {
+            failures_before_backoff: 2,
+            max_backoff: Duration::from_secs(60),
+            slow_latency: Duration::from_millis(500),
+        })
+    }
+
+    fn participants(n: u32) -> Participants {
+        let mut participants = Participants::default();
+        for id in 0..n {
+            participants.insert(&Participant::from(id), ParticipantInfo::new(id));
+        }
+        participants
+    }
+
+    #[test]
+    fn test_backoff() {
+        let reputation = reputation();
+        let peer = Participant::from(1);
+        reputation.record_failure(peer);
+        assert!(reputation.is_healthy(&peer));
+        reputation.record_failure(peer);
+        assert!(!reputation.is_healthy(&peer));
+        reputation.record_success(peer, Duration::from_millis(10));
+        assert!(reputation.is_healthy(&peer));
+    }
+
+    #[test]
+    fn test_select() {
+        let reputation = reputation();
+        let me = Participant::from(0);
+        let participants = participants(4);
+        assert_eq!(reputation.select(me, &participants, 2).len(), 4);
+
+        // a slow peer and a backed off peer get left out while there are enough others
+        reputation.record_success(Participant::from(2), Duration::from_secs(2));
+        for _ in 0..2 {
+            reputation.record_failure(Participant::from(3));
+        }
+        let selected = reputation.select(me, &participants, 2);
+        assert_eq!(
+            selected.keys_vec(),
+            vec![Participant::from(0), Participant::from(1)]
+        );
+
+        // the slow peer is picked before the backed off one to reach the threshold
+        let selected = reputation.select(me, &participants, 3);
+        assert_eq!(
+            selected.keys_vec(),
+            vec![
+                Participant::from(0),
+                Participant::from(1),
+                Participant::from(2)
+            ]
+        );
+    }
+}
@@ -60,6 +60,15 @@ pub(crate) static LATEST_BLOCK_HEIGHT: Lazy<IntGaugeVec> = Lazy::new(|| {
     .unwrap()
 });
 
+pub(crate) static INDEXER_DELAY_MS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "multichain_indexer_delay_ms",
+        "Time between the latest indexed block being produced and the node finishing indexing it",
+        &["node_account_id"],
+    )
+    .unwrap()
+});
+
 pub(crate) static TRIPLE_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
     try_create_histogram_vec(
         "multichain_triple_latency_sec",
@@ -371,6 +380,15 @@ pub(crate) static PRESIGNATURE_GENERATOR_FAILURES: Lazy<CounterVec> = Lazy::new(
     .unwrap()
 });
 
+pub(crate) static PRESIGNATURES_EXPIRED: Lazy<CounterVec> = Lazy::new(|| {
+    try_create_counter_vec(
+        "multichain_presignatures_expired",
+        "total presignatures discarded for being older than their max age",
+        &["node_account_id"],
+    )
+    .unwrap()
+});
+
 pub(crate) static SIGNATURE_FAILURES: Lazy<CounterVec> = Lazy::new(|| {
     try_create_counter_vec(
         "multichain_signature_failures",
@@ -457,6 +475,42 @@ pub(crate) static PROTOCOL_ITER_CNT: Lazy<CounterVec> = Lazy::new(|| {
     .unwrap()
 });
 
+pub(crate) static DERIVATION_CACHE_HITS: Lazy<CounterVec> = Lazy::new(|| {
+    try_create_counter_vec(
+        "multichain_derivation_cache_hits",
+        "number of sign requests whose epsilon was found in the derivation cache",
+        &["node_account_id"],
+    )
+    .unwrap()
+});
+
+pub(crate) static DERIVATION_CACHE_MISSES: Lazy<CounterVec> = Lazy::new(|| {
+    try_create_counter_vec(
+        "multichain_derivation_cache_misses",
+        "number of sign requests whose epsilon had to be derived",
+        &["node_account_id"],
+    )
+    .unwrap()
+});
+
+pub(crate) static DERIVATION_CACHE_EVICTIONS: Lazy<CounterVec> = Lazy::new(|| {
+    try_create_counter_vec(
+        "multichain_derivation_cache_evictions",
+        "number of derivation paths evicted from the derivation cache",
+        &["node_account_id"],
+    )
+    .unwrap()
+});
+
+pub(crate) static DERIVATION_CACHE_SIZE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "multichain_derivation_cache_size",
+        "number of derivation paths held in the derivation cache",
+        &["node_account_id"],
+    )
+    .unwrap()
+});
+
 pub fn try_create_int_gauge_vec(name: &str, help: &str, labels: &[&str]) -> Result<IntGaugeVec> {
     check_metric_multichain_prefix(name)?;
     let opts = Opts::new(name, help);
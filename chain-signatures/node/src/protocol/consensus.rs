@@ -3,9 +3,11 @@ use super::state::{
     JoiningState, NodeState, PersistentNodeData, RunningState, StartedState,
     WaitingForConsensusState,
 };
-use super::{Config, SignQueue};
+use super::{Config, SignEventSender, SignQueue};
+use crate::audit::{self, AuditEvent};
 use crate::gcp::error::DatastoreStorageError;
 use crate::gcp::error::SecretStorageError;
+use crate::hsm::AccountSigner;
 use crate::http_client::MessageQueue;
 use crate::protocol::contract::primitives::Participants;
 use crate::protocol::presignature::PresignatureManager;
@@ -13,7 +15,8 @@ use crate::protocol::signature::SignatureManager;
 use crate::protocol::state::{GeneratingState, ResharingState};
 use crate::protocol::triple::TripleManager;
 use crate::storage::presignature_storage::PresignatureRedisStorage;
-use crate::storage::secret_storage::SecretNodeStorageBox;
+use crate::storage::publish_storage::PublishRedisStorage;
+use crate::storage::secret_storage::SecretStorageBox;
 use crate::storage::triple_storage::TripleRedisStorage;
 use crate::types::{KeygenProtocol, ReshareProtocol, SecretKeyShare};
 use crate::util::AffinePointExt;
@@ -29,21 +32,23 @@ use tokio::sync::RwLock;
 use url::Url;
 
 use near_account_id::AccountId;
-use near_crypto::InMemorySigner;
 
 pub trait ConsensusCtx {
     fn my_account_id(&self) -> &AccountId;
     fn http_client(&self) -> &reqwest::Client;
     fn rpc_client(&self) -> &near_fetch::Client;
-    fn signer(&self) -> &InMemorySigner;
+    fn signer(&self) -> &AccountSigner;
     fn mpc_contract_id(&self) -> &AccountId;
     fn my_address(&self) -> &Url;
     fn sign_queue(&self) -> Arc<RwLock<SignQueue>>;
-    fn secret_storage(&self) -> &SecretNodeStorageBox;
+    fn sign_events(&self) -> SignEventSender;
+    fn secret_storage(&self) -> &SecretStorageBox;
     fn triple_storage(&self) -> &TripleRedisStorage;
     fn presignature_storage(&self) -> &PresignatureRedisStorage;
+    fn publish_storage(&self) -> &PublishRedisStorage;
     fn cfg(&self) -> &Config;
     fn message_options(&self) -> http_client::Options;
+    fn message_seqs(&self) -> http_client::SequenceNumbers;
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -140,6 +145,7 @@ impl ConsensusProtocol for StartedState {
                                         epoch,
                                         ctx.my_account_id(),
                                         ctx.triple_storage(),
+                                        ctx.cfg().local.triple_poke_threads,
                                     )));
 
                                     let presignature_manager =
@@ -157,6 +163,8 @@ impl ConsensusProtocol for StartedState {
                                             public_key,
                                             epoch,
                                             ctx.my_account_id(),
+                                            ctx.sign_events(),
+                                            ctx.publish_storage(),
                                         )));
 
                                     Ok(NodeState::Running(RunningState {
@@ -171,6 +179,7 @@ impl ConsensusProtocol for StartedState {
                                         signature_manager,
                                         messages: Arc::new(RwLock::new(MessageQueue::new(
                                             ctx.message_options().clone(),
+                                            ctx.message_seqs(),
                                         ))),
                                     }))
                                 }
@@ -227,6 +236,7 @@ impl ConsensusProtocol for StartedState {
                                 protocol,
                                 messages: Arc::new(RwLock::new(MessageQueue::new(
                                     ctx.message_options().clone(),
+                                    ctx.message_seqs(),
                                 ))),
                             }))
                         }
@@ -381,6 +391,7 @@ impl ConsensusProtocol for WaitingForConsensusState {
                         self.epoch,
                         ctx.my_account_id(),
                         ctx.triple_storage(),
+                        ctx.cfg().local.triple_poke_threads,
                     )));
 
                     let presignature_manager = Arc::new(RwLock::new(PresignatureManager::new(
@@ -396,6 +407,8 @@ impl ConsensusProtocol for WaitingForConsensusState {
                         self.public_key,
                         self.epoch,
                         ctx.my_account_id(),
+                        ctx.sign_events(),
+                        ctx.publish_storage(),
                     )));
 
                     Ok(NodeState::Running(RunningState {
@@ -414,12 +427,13 @@ impl ConsensusProtocol for WaitingForConsensusState {
             },
             ProtocolState::Resharing(contract_state) => {
                 match (contract_state.old_epoch + 1).cmp(&self.epoch) {
-                    Ordering::Greater if contract_state.old_epoch + 2 == self.epoch => {
+                    // The contract already confirmed our epoch and went on to reshare it.
+                    Ordering::Greater if contract_state.old_epoch == self.epoch => {
                         tracing::info!("waiting(resharing): contract state is resharing, joining");
                         if contract_state.old_participants != self.participants {
                             return Err(ConsensusError::MismatchedParticipants);
                         }
-                        if contract_state.threshold != self.threshold {
+                        if contract_state.old_threshold != self.threshold {
                             return Err(ConsensusError::MismatchedThreshold);
                         }
                         if contract_state.public_key != self.public_key {
@@ -547,6 +561,9 @@ impl ConsensusProtocol for RunningState {
                         if !is_in_old_participant_set || !is_in_new_participant_set {
                             return Err(ConsensusError::HasBeenKicked);
                         }
+                        if contract_state.old_threshold != self.threshold {
+                            return Err(ConsensusError::MismatchedThreshold);
+                        }
                         if contract_state.public_key != self.public_key {
                             return Err(ConsensusError::MismatchedPublicKey);
                         }
@@ -672,7 +689,8 @@ impl ConsensusProtocol for JoiningState {
                         tracing::info!(
                             "joining(running): sending a transaction to join the participant set"
                         );
-                        ctx.rpc_client()
+                        let outcome = ctx
+                            .rpc_client()
                             .call(ctx.signer(), ctx.mpc_contract_id(), "join")
                             .args_json(json!({
                                 "url": ctx.my_address(),
@@ -682,11 +700,11 @@ impl ConsensusProtocol for JoiningState {
                             .max_gas()
                             .retry_exponential(10, 3)
                             .transact()
-                            .await
-                            .map_err(|err| {
-                                tracing::error!(?err, "failed to join the participant set");
-                                ConsensusError::CannotJoin(format!("{err:?}"))
-                            })?;
+                            .await;
+                        ctx.signer().check_signed(outcome).map_err(|err| {
+                            tracing::error!(?err, "failed to join the participant set");
+                            ConsensusError::CannotJoin(format!("{err:?}"))
+                        })?;
                         Ok(NodeState::Joining(self))
                     }
                 }
@@ -736,11 +754,21 @@ async fn start_resharing<C: ConsensusCtx>(
     ctx: C,
     contract_state: ResharingContractState,
 ) -> Result<NodeState, ConsensusError> {
-    let me = contract_state
+    let Some(me) = contract_state
         .new_participants
         .find_participant(ctx.my_account_id())
-        .unwrap();
+    else {
+        return Err(ConsensusError::HasBeenKicked);
+    };
     let protocol = ReshareProtocol::new(private_share, me, &contract_state)?;
+    audit::record(
+        ctx.my_account_id(),
+        AuditEvent::ResharingStarted {
+            old_epoch: contract_state.old_epoch,
+            old_participants: account_ids(&contract_state.old_participants),
+            new_participants: account_ids(&contract_state.new_participants),
+        },
+    );
     Ok(NodeState::Resharing(ResharingState {
         old_epoch: contract_state.old_epoch,
         old_participants: contract_state.old_participants,
@@ -750,6 +778,614 @@ async fn start_resharing<C: ConsensusCtx>(
         protocol,
         messages: Arc::new(RwLock::new(MessageQueue::new(
             ctx.message_options().clone(),
+            ctx.message_seqs(),
         ))),
     }))
 }
+
+fn account_ids(participants: &Participants) -> Vec<AccountId> {
+    participants.account_ids().into_iter().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LocalConfig;
+    use crate::protocol::contract::primitives::{
+        CandidateInfo, Candidates, ParticipantInfo, PkVotes, Votes,
+    };
+    use crate::protocol::contract::{InitializingContractState, RunningContractState};
+    use crate::storage::secret_storage::MemorySecretStorage;
+    use crate::storage::{presignature_storage, publish_storage, triple_storage, StorageCipher};
+
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+    use cait_sith::protocol::Participant;
+    use crypto_shared::PublicKey;
+    use deadpool_redis::Runtime;
+    use k256::Scalar;
+    use proptest::prelude::*;
+    use tokio::sync::broadcast;
+
+    const THRESHOLD: usize = 2;
+    /// Nodes that generate the key.
+    const CANDIDATES: [u32; 3] = [0, 1, 2];
+    /// Nodes that can join later on are the ones with ids below this.
+    const NODES: u32 = 6;
+    const PUBLIC_KEY: PublicKey = PublicKey::GENERATOR;
+
+    struct TestCtx {
+        account_id: AccountId,
+        http_client: reqwest::Client,
+        rpc_client: near_fetch::Client,
+        signer: AccountSigner,
+        mpc_contract_id: AccountId,
+        my_address: Url,
+        sign_queue: Arc<RwLock<SignQueue>>,
+        sign_events: SignEventSender,
+        secret_storage: SecretStorageBox,
+        triple_storage: TripleRedisStorage,
+        presignature_storage: PresignatureRedisStorage,
+        publish_storage: PublishRedisStorage,
+        cfg: Config,
+        message_options: http_client::Options,
+        message_seqs: http_client::SequenceNumbers,
+    }
+
+    impl TestCtx {
+        fn new(me: u32) -> Self {
+            let account_id = ParticipantInfo::new(me).account_id;
+            // Nothing listens on these. The model records the votes of the node in the contract
+            // state it hands over, so that the node never has to send them.
+            let redis_pool = deadpool_redis::Config::from_url("redis://127.0.0.1:1")
+                .create_pool(Some(Runtime::Tokio1))
+                .unwrap();
+            let (cipher_sk, _) = mpc_keys::hpke::generate();
+            let cipher = StorageCipher::new(cipher_sk.into());
+            let (sign_events, _) = broadcast::channel(16);
+            Self {
+                http_client: reqwest::Client::new(),
+                rpc_client: near_fetch::Client::new("http://127.0.0.1:1"),
+                signer: near_crypto::InMemorySigner::from_secret_key(
+                    account_id.clone(),
+                    near_crypto::SecretKey::from_random(near_crypto::KeyType::ED25519),
+                )
+                .into(),
+                mpc_contract_id: "mpc.test".parse().unwrap(),
+                my_address: "http://127.0.0.1:1".parse().unwrap(),
+                sign_queue: Arc::new(RwLock::new(SignQueue::new(sign_events.clone()))),
+                sign_events,
+                secret_storage: Box::<MemorySecretStorage>::default(),
+                triple_storage: triple_storage::init(&redis_pool, &account_id, &cipher),
+                presignature_storage: presignature_storage::init(&redis_pool, &account_id, &cipher),
+                publish_storage: publish_storage::init(&redis_pool, &account_id),
+                cfg: Config::new(LocalConfig::default()),
+                message_options: http_client::Options {
+                    timeout: 1000,
+                    transport: Default::default(),
+                    mesh_mtls: false,
+                },
+                message_seqs: Default::default(),
+                account_id,
+            }
+        }
+    }
+
+    impl ConsensusCtx for &TestCtx {
+        fn my_account_id(&self) -> &AccountId {
+            &self.account_id
+        }
+
+        fn http_client(&self) -> &reqwest::Client {
+            &self.http_client
+        }
+
+        fn rpc_client(&self) -> &near_fetch::Client {
+            &self.rpc_client
+        }
+
+        fn signer(&self) -> &AccountSigner {
+            &self.signer
+        }
+
+        fn mpc_contract_id(&self) -> &AccountId {
+            &self.mpc_contract_id
+        }
+
+        fn my_address(&self) -> &Url {
+            &self.my_address
+        }
+
+        fn sign_queue(&self) -> Arc<RwLock<SignQueue>> {
+            self.sign_queue.clone()
+        }
+
+        fn sign_events(&self) -> SignEventSender {
+            self.sign_events.clone()
+        }
+
+        fn secret_storage(&self) -> &SecretStorageBox {
+            &self.secret_storage
+        }
+
+        fn triple_storage(&self) -> &TripleRedisStorage {
+            &self.triple_storage
+        }
+
+        fn presignature_storage(&self) -> &PresignatureRedisStorage {
+            &self.presignature_storage
+        }
+
+        fn publish_storage(&self) -> &PublishRedisStorage {
+            &self.publish_storage
+        }
+
+        fn cfg(&self) -> &Config {
+            &self.cfg
+        }
+
+        fn message_options(&self) -> http_client::Options {
+            self.message_options.clone()
+        }
+
+        fn message_seqs(&self) -> http_client::SequenceNumbers {
+            self.message_seqs.clone()
+        }
+    }
+
+    /// Something that happens to the contract or to the node.
+    #[derive(Debug, Clone)]
+    enum Step {
+        /// The node with this id gets voted in, which starts a resharing.
+        Join(u32),
+        /// The participant with this id gets voted out, which starts a resharing.
+        Kick(u32),
+        /// Key generation or resharing completes. Only a threshold of votes is needed for that,
+        /// so the other nodes can complete it without this one.
+        Complete,
+        /// The node fetches the contract state and advances.
+        Observe,
+        /// The key generation or resharing of the node completes.
+        Finish,
+        /// The node restarts from the data it persisted.
+        Restart,
+    }
+
+    fn step() -> impl Strategy<Value = Step> {
+        prop_oneof![
+            1 => (0..NODES).prop_map(Step::Join),
+            1 => (0..NODES).prop_map(Step::Kick),
+            2 => Just(Step::Complete),
+            4 => Just(Step::Observe),
+            2 => Just(Step::Finish),
+            1 => Just(Step::Restart),
+        ]
+    }
+
+    /// The contract, as far as the consensus of the node is concerned.
+    #[derive(Debug, Clone)]
+    enum Contract {
+        Initializing {
+            candidates: BTreeSet<u32>,
+        },
+        Running {
+            epoch: u64,
+            participants: BTreeSet<u32>,
+        },
+        Resharing {
+            old_epoch: u64,
+            old_participants: BTreeSet<u32>,
+            new_participants: BTreeSet<u32>,
+        },
+    }
+
+    impl Contract {
+        /// Epoch that the contract is in, or heading to while it reshares.
+        fn epoch(&self) -> u64 {
+            match self {
+                Contract::Initializing { .. } => 0,
+                Contract::Running { epoch, .. } => *epoch,
+                Contract::Resharing { old_epoch, .. } => old_epoch + 1,
+            }
+        }
+
+        fn apply(&mut self, step: &Step, me: u32, node: &NodeState) {
+            // This node only gets voted in once it asked to join.
+            let asked_to_join = |id: u32| id != me || matches!(node, NodeState::Joining(_));
+            *self = match (self.clone(), step) {
+                (
+                    Contract::Running {
+                        epoch,
+                        participants,
+                    },
+                    Step::Join(id),
+                ) if !participants.contains(id) && asked_to_join(*id) => {
+                    let mut new_participants = participants.clone();
+                    new_participants.insert(*id);
+                    Contract::Resharing {
+                        old_epoch: epoch,
+                        old_participants: participants,
+                        new_participants,
+                    }
+                }
+                (
+                    Contract::Running {
+                        epoch,
+                        participants,
+                    },
+                    Step::Kick(id),
+                ) if participants.contains(id) && participants.len() > THRESHOLD => {
+                    let mut new_participants = participants.clone();
+                    new_participants.remove(id);
+                    Contract::Resharing {
+                        old_epoch: epoch,
+                        old_participants: participants,
+                        new_participants,
+                    }
+                }
+                (Contract::Initializing { candidates }, Step::Complete) => Contract::Running {
+                    epoch: 0,
+                    participants: candidates,
+                },
+                (
+                    Contract::Resharing {
+                        old_epoch,
+                        new_participants,
+                        ..
+                    },
+                    Step::Complete,
+                ) => Contract::Running {
+                    epoch: old_epoch + 1,
+                    participants: new_participants,
+                },
+                (contract, _) => contract,
+            };
+        }
+
+        /// The contract state that the node fetches. The votes and the join request that `node`
+        /// sends from its state are already in, as if they landed right before.
+        fn protocol_state(&self, me: u32, node: &NodeState) -> ProtocolState {
+            let my_account_id = ParticipantInfo::new(me).account_id;
+            match self {
+                Contract::Initializing { candidates } => {
+                    let mut pk_votes = BTreeMap::new();
+                    if let NodeState::WaitingForConsensus(state) = node {
+                        pk_votes.insert(
+                            state.public_key.into_near_public_key(),
+                            HashSet::from([my_account_id]),
+                        );
+                    }
+                    ProtocolState::Initializing(InitializingContractState {
+                        candidates: candidates_of(candidates.iter().copied()),
+                        threshold: THRESHOLD,
+                        pk_votes: PkVotes { pk_votes },
+                    })
+                }
+                Contract::Running {
+                    epoch,
+                    participants,
+                } => {
+                    let candidates = match node {
+                        NodeState::Joining(_) => candidates_of([me]),
+                        _ => candidates_of([]),
+                    };
+                    ProtocolState::Running(RunningContractState {
+                        epoch: *epoch,
+                        participants: participants_of(participants),
+                        threshold: THRESHOLD,
+                        public_key: PUBLIC_KEY,
+                        candidates,
+                        join_votes: Votes {
+                            votes: BTreeMap::new(),
+                        },
+                        leave_votes: Votes {
+                            votes: BTreeMap::new(),
+                        },
+                    })
+                }
+                Contract::Resharing {
+                    old_epoch,
+                    old_participants,
+                    new_participants,
+                } => {
+                    let mut finished_votes = HashSet::new();
+                    if let NodeState::WaitingForConsensus(state) = node {
+                        if state.epoch == old_epoch + 1 {
+                            finished_votes.insert(my_account_id);
+                        }
+                    }
+                    ProtocolState::Resharing(ResharingContractState {
+                        old_epoch: *old_epoch,
+                        old_participants: participants_of(old_participants),
+                        new_participants: participants_of(new_participants),
+                        threshold: THRESHOLD,
+                        old_threshold: THRESHOLD,
+                        public_key: PUBLIC_KEY,
+                        finished_votes,
+                    })
+                }
+            }
+        }
+
+        /// Whether `me` holds a share of the current epoch of the contract.
+        fn is_participant(&self, me: u32) -> bool {
+            match self {
+                Contract::Initializing { candidates } => candidates.contains(&me),
+                Contract::Running { participants, .. } => participants.contains(&me),
+                Contract::Resharing {
+                    old_participants, ..
+                } => old_participants.contains(&me),
+            }
+        }
+    }
+
+    fn participants_of(ids: &BTreeSet<u32>) -> Participants {
+        let mut participants = Participants::default();
+        for id in ids {
+            participants.insert(&Participant::from(*id), ParticipantInfo::new(*id));
+        }
+        participants
+    }
+
+    fn candidates_of(ids: impl IntoIterator<Item = u32>) -> Candidates {
+        Candidates {
+            candidates: ids
+                .into_iter()
+                .map(|id| {
+                    let info = ParticipantInfo::new(id);
+                    let candidate = CandidateInfo {
+                        account_id: info.account_id.clone(),
+                        url: info.url,
+                        cipher_pk: info.cipher_pk,
+                        sign_pk: info.sign_pk,
+                    };
+                    (info.account_id, candidate)
+                })
+                .collect(),
+        }
+    }
+
+    /// Epoch of the share that the node holds, if it holds one.
+    fn epoch_of(state: &NodeState) -> Option<u64> {
+        match state {
+            NodeState::Started(StartedState {
+                persistent_node_data,
+            }) => persistent_node_data.as_ref().map(|data| data.epoch),
+            NodeState::WaitingForConsensus(state) => Some(state.epoch),
+            NodeState::Running(state) => Some(state.epoch),
+            NodeState::Resharing(state) => Some(state.old_epoch),
+            NodeState::Starting | NodeState::Generating(_) | NodeState::Joining(_) => None,
+        }
+    }
+
+    /// Completes the key generation or resharing that the node is going through, the way
+    /// `CryptographicProtocol::progress` does once the cait-sith protocol returns.
+    fn finish(
+        state: NodeState,
+        ctx: &TestCtx,
+        persisted: &mut Option<PersistentNodeData>,
+    ) -> NodeState {
+        let (epoch, participants, threshold) = match &state {
+            NodeState::Generating(state) => (0, state.participants.clone(), state.threshold),
+            NodeState::Resharing(state) => (
+                state.old_epoch + 1,
+                state.new_participants.clone(),
+                state.threshold,
+            ),
+            _ => return state,
+        };
+        *persisted = Some(PersistentNodeData {
+            epoch,
+            private_share: Scalar::ONE,
+            public_key: PUBLIC_KEY,
+        });
+        NodeState::WaitingForConsensus(WaitingForConsensusState {
+            epoch,
+            participants,
+            threshold,
+            private_share: Scalar::ONE,
+            public_key: PUBLIC_KEY,
+            messages: Arc::new(RwLock::new(MessageQueue::new(
+                ctx.message_options(),
+                ctx.message_seqs(),
+            ))),
+        })
+    }
+
+    /// Advances the node like the protocol loop does, which keeps the state the node was in if
+    /// it is unable to advance.
+    async fn observe(
+        state: NodeState,
+        ctx: &TestCtx,
+        contract: &Contract,
+        me: u32,
+    ) -> Result<NodeState, TestCaseError> {
+        let before = epoch_of(&state);
+        let caught_up = matches!(
+            state,
+            NodeState::Started(_) | NodeState::WaitingForConsensus(_) | NodeState::Running(_)
+        ) && before == Some(contract_epoch(contract))
+            && contract.is_participant(me);
+
+        let contract_state = contract.protocol_state(me, &state);
+        let next = match state.clone().advance(ctx, contract_state).await {
+            Ok(next) => next,
+            Err(err) => {
+                prop_assert!(
+                    !matches!(
+                        err,
+                        ConsensusError::CannotVote(_) | ConsensusError::CannotJoin(_)
+                    ),
+                    "node had to send a vote that the model did not record: {err:?}"
+                );
+                state
+            }
+        };
+
+        let after = epoch_of(&next);
+        if let (Some(before), Some(after)) = (before, after) {
+            prop_assert!(
+                after <= before,
+                "advancing went from epoch {before} to {after} ({next})"
+            );
+        }
+        prop_assert!(
+            !(caught_up && matches!(next, NodeState::Joining(_))),
+            "node holding the share of epoch {before:?} fell back to joining against {contract:?}"
+        );
+        Ok(next)
+    }
+
+    /// Epoch whose shares the participants of `contract` hold.
+    fn contract_epoch(contract: &Contract) -> u64 {
+        match contract {
+            Contract::Initializing { .. } => 0,
+            Contract::Running { epoch, .. } => *epoch,
+            Contract::Resharing { old_epoch, .. } => *old_epoch,
+        }
+    }
+
+    async fn run_steps(me: u32, steps: Vec<Step>) -> Result<(), TestCaseError> {
+        let ctx = TestCtx::new(me);
+        let mut contract = Contract::Initializing {
+            candidates: CANDIDATES.into(),
+        };
+        let mut state = NodeState::Started(StartedState {
+            persistent_node_data: None,
+        });
+        let mut persisted = None;
+
+        for step in &steps {
+            match step {
+                Step::Observe => state = observe(state, &ctx, &contract, me).await?,
+                Step::Finish => state = finish(state, &ctx, &mut persisted),
+                Step::Restart => {
+                    state = NodeState::Started(StartedState {
+                        persistent_node_data: persisted.clone(),
+                    })
+                }
+                _ => contract.apply(step, me, &state),
+            }
+            if let Some(epoch) = epoch_of(&state) {
+                prop_assert!(
+                    epoch <= contract.epoch(),
+                    "node is at epoch {epoch} ahead of {contract:?}"
+                );
+            }
+        }
+
+        // Once the contract settles, the node has to catch up with it instead of getting stuck.
+        contract.apply(&Step::Complete, me, &state);
+        for _ in 0..4 {
+            state = observe(state, &ctx, &contract, me).await?;
+            state = finish(state, &ctx, &mut persisted);
+        }
+        let Contract::Running {
+            epoch,
+            participants,
+        } = &contract
+        else {
+            unreachable!("completed contract is running");
+        };
+        let holds_share = persisted.as_ref().map(|data| data.epoch) == Some(*epoch);
+        if participants.contains(&me) && holds_share {
+            prop_assert!(
+                matches!(&state, NodeState::Running(running) if running.epoch == *epoch),
+                "participant is {state} instead of running epoch {epoch}"
+            );
+        } else {
+            prop_assert!(
+                matches!(state, NodeState::Joining(_)),
+                "node without a share of epoch {epoch} is {state} instead of joining"
+            );
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_consensus_interleavings(
+            me in 0..NODES,
+            steps in prop::collection::vec(step(), 0..40),
+        ) {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(run_steps(me, steps))?;
+        }
+    }
+
+    fn waiting_state(ctx: &TestCtx, epoch: u64, participants: &BTreeSet<u32>) -> NodeState {
+        NodeState::WaitingForConsensus(WaitingForConsensusState {
+            epoch,
+            participants: participants_of(participants),
+            threshold: THRESHOLD,
+            private_share: Scalar::ONE,
+            public_key: PUBLIC_KEY,
+            messages: Arc::new(RwLock::new(MessageQueue::new(
+                ctx.message_options(),
+                ctx.message_seqs(),
+            ))),
+        })
+    }
+
+    fn resharing_state(
+        old_epoch: u64,
+        old_participants: &BTreeSet<u32>,
+        new_participants: &BTreeSet<u32>,
+    ) -> ProtocolState {
+        ProtocolState::Resharing(ResharingContractState {
+            old_epoch,
+            old_participants: participants_of(old_participants),
+            new_participants: participants_of(new_participants),
+            threshold: THRESHOLD,
+            old_threshold: THRESHOLD,
+            public_key: PUBLIC_KEY,
+            finished_votes: HashSet::new(),
+        })
+    }
+
+    /// The contract can confirm the epoch of a node and start resharing it before the node sees
+    /// it running. The node has to take part in that resharing with its share instead of
+    /// dropping the share to join again.
+    #[tokio::test]
+    async fn test_waiting_joins_resharing_of_its_epoch() {
+        let ctx = TestCtx::new(0);
+        let old_participants = BTreeSet::from([0, 1, 2]);
+        let new_participants = BTreeSet::from([0, 1, 2, 3]);
+        let next = waiting_state(&ctx, 1, &old_participants)
+            .advance(
+                &ctx,
+                resharing_state(1, &old_participants, &new_participants),
+            )
+            .await
+            .unwrap();
+        assert!(
+            matches!(&next, NodeState::Resharing(state) if state.old_epoch == 1),
+            "node is {next} instead of resharing epoch 1"
+        );
+    }
+
+    /// A node that is left out of that resharing is kicked, instead of panicking while looking
+    /// itself up in the new participants.
+    #[tokio::test]
+    async fn test_waiting_kicked_by_resharing_of_its_epoch() {
+        let ctx = TestCtx::new(2);
+        let old_participants = BTreeSet::from([0, 1, 2]);
+        let new_participants = BTreeSet::from([0, 1]);
+        let err = waiting_state(&ctx, 1, &old_participants)
+            .advance(
+                &ctx,
+                resharing_state(1, &old_participants, &new_participants),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, ConsensusError::HasBeenKicked),
+            "unexpected error {err:?}"
+        );
+    }
+}
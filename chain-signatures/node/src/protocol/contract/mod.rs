@@ -57,17 +57,22 @@ pub struct ResharingContractState {
     pub old_participants: Participants,
     pub new_participants: Participants,
     pub threshold: usize,
+    /// Threshold of the old participants, which differs from `threshold` when the resharing
+    /// changes the threshold.
+    pub old_threshold: usize,
     pub public_key: PublicKey,
     pub finished_votes: HashSet<AccountId>,
 }
 
 impl From<mpc_contract::ResharingContractState> for ResharingContractState {
     fn from(contract_state: mpc_contract::ResharingContractState) -> Self {
+        let old_threshold = contract_state.old_threshold();
         ResharingContractState {
             old_epoch: contract_state.old_epoch,
             old_participants: contract_state.old_participants.into(),
             new_participants: contract_state.new_participants.into(),
             threshold: contract_state.threshold,
+            old_threshold,
             public_key: contract_state.public_key.into_affine_point(),
             finished_votes: contract_state
                 .finished_votes
@@ -2,27 +2,28 @@ use std::sync::PoisonError;
 
 use super::state::{GeneratingState, NodeState, ResharingState, RunningState};
 use super::Config;
+use crate::audit::{self, AuditEvent};
 use crate::gcp::error::SecretStorageError;
+use crate::hsm::{AccountSigner, HsmError};
 use crate::http_client::SendError;
 use crate::mesh::Mesh;
 use crate::protocol::message::{GeneratingMessage, ResharingMessage};
 use crate::protocol::state::{PersistentNodeData, WaitingForConsensusState};
 use crate::protocol::MpcMessage;
-use crate::storage::secret_storage::SecretNodeStorageBox;
+use crate::storage::secret_storage::SecretStorageBox;
 use async_trait::async_trait;
 use cait_sith::protocol::{Action, InitializationError, Participant, ProtocolError};
 use k256::elliptic_curve::group::GroupEncoding;
 use near_account_id::AccountId;
-use near_crypto::InMemorySigner;
 
 #[async_trait::async_trait]
 pub trait CryptographicCtx {
     async fn me(&self) -> Participant;
     fn http_client(&self) -> &reqwest::Client;
     fn rpc_client(&self) -> &near_fetch::Client;
-    fn signer(&self) -> &InMemorySigner;
+    fn signer(&self) -> &AccountSigner;
     fn mpc_contract_id(&self) -> &AccountId;
-    fn secret_storage(&mut self) -> &mut SecretNodeStorageBox;
+    fn secret_storage(&mut self) -> &mut SecretStorageBox;
     fn cfg(&self) -> &Config;
 
     /// Active participants is the active participants at the beginning of each protocol loop.
@@ -51,6 +52,10 @@ pub enum CryptographicError {
     InvalidStateHandle(String),
     #[error("secret storage error: {0}")]
     SecretStorageError(#[from] SecretStorageError),
+    #[error("hsm error: {0}")]
+    HsmError(#[from] HsmError),
+    #[error("replayed message {seq} from {from:?}")]
+    ReplayedMessage { from: Participant, seq: u64 },
 }
 
 impl<T> From<PoisonError<T>> for CryptographicError {
@@ -101,6 +106,8 @@ impl CryptographicProtocol for GeneratingState {
                             ctx.http_client(),
                             ctx.mesh().active_participants(),
                             &ctx.cfg().protocol,
+                            &ctx.cfg().timeouts,
+                            ctx.mesh().reputation(),
                         )
                         .await;
                     if !failures.is_empty() {
@@ -163,6 +170,8 @@ impl CryptographicProtocol for GeneratingState {
                             ctx.http_client(),
                             ctx.mesh().active_participants(),
                             &ctx.cfg().protocol,
+                            &ctx.cfg().timeouts,
+                            ctx.mesh().reputation(),
                         )
                         .await;
                     if !failures.is_empty() {
@@ -201,6 +210,8 @@ impl CryptographicProtocol for WaitingForConsensusState {
                 ctx.http_client(),
                 ctx.mesh().active_participants(),
                 &ctx.cfg().protocol,
+                &ctx.cfg().timeouts,
+                ctx.mesh().reputation(),
             )
             .await;
         if !failures.is_empty() {
@@ -257,6 +268,8 @@ impl CryptographicProtocol for ResharingState {
                             ctx.http_client(),
                             &active,
                             &ctx.cfg().protocol,
+                            &ctx.cfg().timeouts,
+                            ctx.mesh().reputation(),
                         )
                         .await;
                     if !failures.is_empty() {
@@ -313,6 +326,12 @@ impl CryptographicProtocol for ResharingState {
                             public_key: self.public_key,
                         })
                         .await?;
+                    audit::record(
+                        ctx.signer().account_id(),
+                        AuditEvent::ResharingCompleted {
+                            epoch: self.old_epoch + 1,
+                        },
+                    );
 
                     // Send any leftover messages.
                     let failures = self
@@ -325,6 +344,8 @@ impl CryptographicProtocol for ResharingState {
                             ctx.http_client(),
                             &active,
                             &ctx.cfg().protocol,
+                            &ctx.cfg().timeouts,
+                            ctx.mesh().reputation(),
                         )
                         .await;
                     if !failures.is_empty() {
@@ -372,7 +393,16 @@ impl CryptographicProtocol for RunningState {
         crate::metrics::MESSAGE_QUEUE_SIZE
             .with_label_values(&[my_account_id.as_str()])
             .set(messages.len() as i64);
-        if let Err(err) = triple_manager.stockpile(active, protocol_cfg).await {
+        // leave unhealthy peers out of new triples while there are enough healthy ones, so that
+        // a degraded node does not slow down every triple of the stockpile.
+        let triple_participants =
+            ctx.mesh()
+                .reputation()
+                .select(ctx.me().await, active, self.threshold);
+        if let Err(err) = triple_manager
+            .stockpile(&triple_participants, protocol_cfg)
+            .await
+        {
             tracing::warn!(?err, "running: failed to stockpile triples");
         }
         for (p, msg) in triple_manager.poke(protocol_cfg).await {
@@ -437,7 +467,13 @@ impl CryptographicProtocol for RunningState {
             .with_label_values(&[my_account_id.as_str()])
             .set(sign_queue.len() as i64);
         let me = ctx.me().await;
-        sign_queue.organize(self.threshold, &stable, me, &my_account_id);
+        sign_queue.organize(
+            self.threshold,
+            &stable,
+            me,
+            &my_account_id,
+            ctx.cfg().sign_request.ordering,
+        );
 
         let my_requests = sign_queue.my_requests(me);
         crate::metrics::SIGN_QUEUE_MINE_SIZE
@@ -451,6 +487,7 @@ impl CryptographicProtocol for RunningState {
                 &stable,
                 my_requests,
                 &mut presignature_manager,
+                ctx.cfg().sign_request.max_concurrent_signatures as usize,
                 protocol_cfg,
             )
             .await;
@@ -462,7 +499,12 @@ impl CryptographicProtocol for RunningState {
             messages.push(info.clone(), MpcMessage::Signature(msg));
         }
         signature_manager
-            .publish(ctx.rpc_client(), ctx.signer(), ctx.mpc_contract_id())
+            .publish(
+                ctx.rpc_client(),
+                ctx.signer(),
+                ctx.mpc_contract_id(),
+                &ctx.cfg().timeouts.signature_publish_retry,
+            )
             .await;
         drop(signature_manager);
         let failures = messages
@@ -472,6 +514,8 @@ impl CryptographicProtocol for RunningState {
                 ctx.http_client(),
                 active,
                 protocol_cfg,
+                &ctx.cfg().timeouts,
+                ctx.mesh().reputation(),
             )
             .await;
         if !failures.is_empty() {
@@ -0,0 +1,231 @@
+//! Eviction of participants that went offline. A participant gets voted out of the running
+//! protocol with `vote_leave`, which starts a resharing without it once a threshold of nodes
+//! agree, when both of these hold:
+//! - its last heartbeat on the contract is older than the `eviction` entry of the contract config
+//!   allows. The heartbeats are the same for every node, so a node that got cut off from its
+//!   peers can not get the others evicted.
+//! - this node has not been able to reach it through the mesh for about as long. A node whose
+//!   pings to the contract fail, e.g. because its account ran out of gas, keeps taking part in the
+//!   protocol and does not get evicted for it.
+//!
+//! This way a node that went away for good does not need the operators to coordinate kicking it.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use mpc_contract::config::EvictionConfig;
+use mpc_contract::primitives::ParticipantHeartbeat;
+use near_primitives::types::AccountId;
+
+use crate::protocol::contract::primitives::Participants;
+
+/// Since when each participant of the running protocol has been unreachable through the mesh.
+#[derive(Default)]
+pub struct UnreachableTracker {
+    since: HashMap<AccountId, Instant>,
+}
+
+impl UnreachableTracker {
+    /// Records which of the `participants` are reachable according to `active`, the participants
+    /// that answered the last ping of the mesh.
+    pub fn observe(&mut self, participants: &Participants, active: &Participants, now: Instant) {
+        self.since
+            .retain(|account_id, _| participants.contains_account_id(account_id));
+        for (participant, info) in participants.iter() {
+            if active.contains_key(participant) {
+                if self.since.remove(&info.account_id).is_some() {
+                    tracing::info!(account_id = %info.account_id, "participant reachable again");
+                }
+            } else {
+                self.since
+                    .entry(info.account_id.clone())
+                    .or_insert_with(|| {
+                        tracing::info!(account_id = %info.account_id, "participant unreachable");
+                        now
+                    });
+            }
+        }
+    }
+
+    /// The `stale` participants that have also been unreachable for about as long as their
+    /// heartbeat has been stale according to `eviction`, at `now`.
+    pub fn offline(
+        &self,
+        eviction: &EvictionConfig,
+        stale: Vec<AccountId>,
+        now: Instant,
+    ) -> Vec<AccountId> {
+        // Blocks take at least about a second, so this is at most as long as the heartbeat of
+        // the participant has been stale.
+        let after = Duration::from_secs(eviction.stale_after_blocks);
+        stale
+            .into_iter()
+            .filter(|account_id| self.unreachable_for(account_id, after, now))
+            .collect()
+    }
+
+    /// Whether `account_id` has been unreachable for at least `after` at `now`.
+    fn unreachable_for(&self, account_id: &AccountId, after: Duration, now: Instant) -> bool {
+        self.since
+            .get(account_id)
+            .is_some_and(|since| now.saturating_duration_since(*since) >= after)
+    }
+}
+
+/// Participants of the running protocol whose last heartbeat is stale according to `eviction`,
+/// sorted by account id. Participants that never pinged are left alone, since the heartbeats only
+/// start with the nodes that run a version sending them. Empty if eviction is disabled.
+pub fn stale_participants(
+    eviction: &EvictionConfig,
+    participants: &Participants,
+    heartbeats: &[ParticipantHeartbeat],
+) -> Vec<AccountId> {
+    let mut stale = participants
+        .iter()
+        .filter(|(_, info)| {
+            heartbeats.iter().any(|heartbeat| {
+                heartbeat.account_id.as_str() == info.account_id.as_str()
+                    && heartbeat
+                        .blocks_since_last_seen
+                        .is_some_and(|blocks| eviction.is_stale(blocks))
+            })
+        })
+        .map(|(_, info)| info.account_id.clone())
+        .collect::<Vec<_>>();
+    stale.sort();
+    stale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ParticipantInfo;
+    use cait_sith::protocol::Participant;
+    use mpc_contract::primitives::Heartbeat;
+
+    fn participants(ids: &[u32]) -> Participants {
+        let mut participants = Participants::default();
+        for id in ids {
+            participants.insert(&Participant::from(*id), ParticipantInfo::new(*id));
+        }
+        participants
+    }
+
+    fn heartbeat(id: u32, blocks_since_last_seen: Option<u64>) -> ParticipantHeartbeat {
+        ParticipantHeartbeat {
+            account_id: format!("p-{id}").parse().unwrap(),
+            last_seen: blocks_since_last_seen.map(|blocks| Heartbeat {
+                block_height: 1_000 - blocks,
+                timestamp: 0,
+            }),
+            blocks_since_last_seen,
+        }
+    }
+
+    #[test]
+    fn test_evict_stale_heartbeats() {
+        let eviction = EvictionConfig {
+            stale_after_blocks: 100,
+        };
+        let heartbeats = [
+            heartbeat(0, Some(10)),
+            heartbeat(1, Some(99)),
+            heartbeat(2, Some(100)),
+            heartbeat(3, None),
+        ];
+        assert_eq!(
+            stale_participants(&eviction, &participants(&[0, 1, 2, 3]), &heartbeats),
+            vec!["p-2".parse::<AccountId>().unwrap()]
+        );
+
+        // Only participants of the running protocol are voted out, e.g. not candidates.
+        assert!(stale_participants(&eviction, &participants(&[0, 1]), &heartbeats).is_empty());
+    }
+
+    fn offline_participants(
+        eviction: &EvictionConfig,
+        participants: &Participants,
+        heartbeats: &[ParticipantHeartbeat],
+        unreachable: &UnreachableTracker,
+        now: Instant,
+    ) -> Vec<AccountId> {
+        let stale = stale_participants(eviction, participants, heartbeats);
+        unreachable.offline(eviction, stale, now)
+    }
+
+    #[test]
+    fn test_evict_stale_and_unreachable() {
+        let eviction = EvictionConfig {
+            stale_after_blocks: 100,
+        };
+        let all = participants(&[0, 1, 2]);
+        let heartbeats = [
+            heartbeat(0, Some(10)),
+            heartbeat(1, Some(100)),
+            heartbeat(2, Some(100)),
+        ];
+        let start = Instant::now();
+        let mut unreachable = UnreachableTracker::default();
+
+        // p-1 keeps taking part in the protocol, only its heartbeats stopped.
+        unreachable.observe(&all, &participants(&[0, 1]), start);
+        assert!(offline_participants(&eviction, &all, &heartbeats, &unreachable, start).is_empty());
+
+        let later = start + Duration::from_secs(100);
+        unreachable.observe(&all, &participants(&[0, 1]), later);
+        assert_eq!(
+            offline_participants(&eviction, &all, &heartbeats, &unreachable, later),
+            vec!["p-2".parse::<AccountId>().unwrap()]
+        );
+
+        // Unreachable participants with a fresh heartbeat are not evicted either.
+        unreachable.observe(&all, &participants(&[1, 2]), start);
+        assert!(offline_participants(&eviction, &all, &heartbeats, &unreachable, later).is_empty());
+    }
+
+    #[test]
+    fn test_reachable_again_resets() {
+        let all = participants(&[0, 1, 2]);
+        let start = Instant::now();
+        let mut unreachable = UnreachableTracker::default();
+        let after = Duration::from_secs(60);
+
+        unreachable.observe(&all, &participants(&[0, 1]), start);
+        unreachable.observe(&all, &all, start + Duration::from_secs(30));
+        unreachable.observe(
+            &all,
+            &participants(&[0, 1]),
+            start + Duration::from_secs(40),
+        );
+        let p2 = "p-2".parse::<AccountId>().unwrap();
+        assert!(!unreachable.unreachable_for(&p2, after, start + Duration::from_secs(70)));
+        assert!(unreachable.unreachable_for(&p2, after, start + Duration::from_secs(100)));
+
+        // Participants that left the protocol are no longer tracked.
+        unreachable.observe(&participants(&[0, 1]), &participants(&[0, 1]), start);
+        assert!(unreachable.since.is_empty());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let heartbeats = [heartbeat(0, Some(10)), heartbeat(1, Some(u64::MAX))];
+        assert!(stale_participants(
+            &EvictionConfig::default(),
+            &participants(&[0, 1]),
+            &heartbeats
+        )
+        .is_empty());
+
+        let mut unreachable = UnreachableTracker::default();
+        let start = Instant::now();
+        unreachable.observe(&participants(&[0, 1]), &participants(&[0]), start);
+        assert!(offline_participants(
+            &EvictionConfig::default(),
+            &participants(&[0, 1]),
+            &heartbeats,
+            &unreachable,
+            start + Duration::from_secs(3600)
+        )
+        .is_empty());
+    }
+}
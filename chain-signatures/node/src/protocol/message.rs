@@ -1,9 +1,10 @@
 use super::cryptography::CryptographicError;
 use super::presignature::{GenerationError, PresignatureId};
-use super::signature::SignRequestIdentifier;
+use super::signature::{sign_request_span, SignRequestIdentifier};
 use super::state::{GeneratingState, NodeState, ResharingState, RunningState};
 use super::triple::TripleId;
 use crate::gcp::error::SecretStorageError;
+use crate::hsm::{CipherKey, MessageSigner};
 use crate::http_client::SendError;
 use crate::indexer::ContractSignRequest;
 use crate::mesh::Mesh;
@@ -15,9 +16,10 @@ use k256::Scalar;
 use mpc_keys::hpke::{self, Ciphered};
 use near_crypto::Signature;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, VecDeque};
-use std::sync::Arc;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
 use tokio::sync::RwLock;
+use tracing::Instrument;
 
 #[async_trait::async_trait]
 pub trait MessageCtx {
@@ -47,6 +49,10 @@ pub struct TripleMessage {
     pub data: MessageData,
     // UNIX timestamp as seconds since the epoch
     pub timestamp: u64,
+    /// Participants the initiator picked for the triple. Older nodes do not send them, in which
+    /// case the triple is generated by the active participants.
+    #[serde(default)]
+    pub participants: Option<Vec<Participant>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -260,8 +266,22 @@ impl MessageHandler for RunningState {
             !triple_manager.refresh_gc(id)
         });
         for (id, queue) in triple_messages {
+            // the initiator may have left unhealthy peers out of the triple, so join it with the
+            // participants it picked rather than our own active participants.
+            let listed = queue.front().and_then(|msg| msg.participants.clone());
+            let picked = match &listed {
+                Some(listed) => {
+                    let picked = self.participants.intersection(&[listed]);
+                    if picked.len() != listed.len() {
+                        tracing::warn!(id, ?listed, "triple has unknown participants");
+                        continue;
+                    }
+                    picked
+                }
+                None => participants.clone(),
+            };
             let protocol = match triple_manager
-                .get_or_start_generation(*id, participants, protocol_cfg)
+                .get_or_start_generation(*id, &picked, protocol_cfg)
                 .await
             {
                 Ok(protocol) => protocol,
@@ -275,6 +295,16 @@ impl MessageHandler for RunningState {
 
             if let Some(protocol) = protocol {
                 while let Some(message) = queue.pop_front() {
+                    if crate::failpoint::eval(
+                        crate::failpoint::TRIPLE_DROP_MESSAGE,
+                        Some(&id.to_string()),
+                    )
+                    .await
+                    .is_some()
+                    {
+                        tracing::warn!(id, from = ?message.from, "dropping triple message");
+                        continue;
+                    }
                     protocol.message(message.from, message.data);
                 }
             }
@@ -389,6 +419,7 @@ impl MessageHandler for RunningState {
             !signature_manager.refresh_gc(sign_request_identifier)
         });
         for (sign_request_identifier, queue) in signature_messages {
+            let span = sign_request_span(sign_request_identifier.request_id);
             // SAFETY: this unwrap() is safe since we have already checked that the queue is not empty.
             let SignatureMessage {
                 proposer,
@@ -431,6 +462,7 @@ impl MessageHandler for RunningState {
                     &mut presignature_manager,
                     protocol_cfg,
                 )
+                .instrument(span.clone())
                 .await
             {
                 Ok(protocol) => protocol,
@@ -447,6 +479,7 @@ impl MessageHandler for RunningState {
                     // and have the other nodes timeout in the following cases:
                     // - If a presignature is in GC, then it was used already or failed to be produced.
                     // - If a presignature is missing, that means our system cannot process this signature.
+                    let _span = span.enter();
                     tracing::warn!(
                         ?sign_request_identifier,
                         ?err,
@@ -458,6 +491,7 @@ impl MessageHandler for RunningState {
                 Err(GenerationError::CaitSithInitializationError(error)) => {
                     // ignore the whole of the messages since the generation had bad parameters. Also have the other node who
                     // initiated the protocol resend the message or have it timeout on their side.
+                    let _span = span.enter();
                     tracing::warn!(
                         ?sign_request_identifier,
                         presignature_id,
@@ -468,6 +502,7 @@ impl MessageHandler for RunningState {
                     continue;
                 }
                 Err(err) => {
+                    let _span = span.enter();
                     tracing::warn!(
                         ?sign_request_identifier,
                         ?err,
@@ -485,10 +520,56 @@ impl MessageHandler for RunningState {
         triple_manager.garbage_collect(protocol_cfg);
         presignature_manager.garbage_collect(protocol_cfg);
         signature_manager.garbage_collect(protocol_cfg);
+        self.drop_stale_messages(queue);
         Ok(())
     }
 }
 
+impl RunningState {
+    /// Messages of an epoch before ours can never be processed anymore. Drop them and point out
+    /// their senders, which are either lagging behind or misbehaving.
+    fn drop_stale_messages(&self, queue: &mut MpcMessageQueue) {
+        fn drain<K, M>(
+            bins: &mut HashMap<u64, HashMap<K, VecDeque<M>>>,
+            epoch: u64,
+            from: fn(&M) -> Participant,
+            stale: &mut HashMap<(Participant, u64), usize>,
+        ) {
+            bins.retain(|bin_epoch, bins| {
+                if *bin_epoch >= epoch {
+                    return true;
+                }
+                for msg in bins.values().flatten() {
+                    *stale.entry((from(msg), *bin_epoch)).or_default() += 1;
+                }
+                false
+            });
+        }
+
+        let epoch = self.epoch;
+        let mut stale = HashMap::new();
+        drain(&mut queue.triple_bins, epoch, |msg| msg.from, &mut stale);
+        drain(
+            &mut queue.presignature_bins,
+            epoch,
+            |msg| msg.from,
+            &mut stale,
+        );
+        drain(&mut queue.signature_bins, epoch, |msg| msg.from, &mut stale);
+        for ((from, stale_epoch), count) in stale {
+            let account_id = self.participants.get(&from).map(|info| &info.account_id);
+            tracing::warn!(
+                ?from,
+                ?account_id,
+                stale_epoch,
+                epoch,
+                count,
+                "dropping messages of a stale epoch"
+            );
+        }
+    }
+}
+
 #[async_trait]
 impl MessageHandler for NodeState {
     async fn handle<C: MessageCtx + Send + Sync>(
@@ -510,6 +591,10 @@ impl MessageHandler for NodeState {
 
 /// A signed message that can be encrypted. Note that the message's signature is included
 /// in the encrypted message to avoid from it being tampered with without first decrypting.
+///
+/// Messages are encrypted to the `cipher_pk` that the receiving participant registered in the
+/// contract, so they stay confidential end to end: proxies and load balancers that terminate
+/// TLS in front of a node only ever see the ciphertext.
 #[derive(Serialize, Deserialize)]
 pub struct SignedMessage<T> {
     /// The message with all it's related info.
@@ -518,10 +603,20 @@ pub struct SignedMessage<T> {
     pub sig: Signature,
     /// From which particpant the message was sent.
     pub from: Participant,
+    /// Sequence number of the message, increasing with every message `from` sends to the same
+    /// receiver. It is covered by the signature and used to drop messages that are replayed.
+    pub seq: u64,
 }
 
 impl<T> SignedMessage<T> {
     pub const ASSOCIATED_DATA: &'static [u8] = b"";
+
+    /// The bytes that get signed for a message with the given sequence number.
+    fn signed_payload(msg: &[u8], seq: u64) -> Vec<u8> {
+        let mut payload = msg.to_vec();
+        payload.extend_from_slice(&seq.to_be_bytes());
+        payload
+    }
 }
 
 impl<T> SignedMessage<T>
@@ -531,12 +626,18 @@ where
     pub fn encrypt(
         msg: &T,
         from: Participant,
-        sign_sk: &near_crypto::SecretKey,
+        seq: u64,
+        sign_sk: &MessageSigner,
         cipher_pk: &hpke::PublicKey,
     ) -> Result<Ciphered, CryptographicError> {
         let msg = serde_json::to_vec(msg)?;
-        let sig = sign_sk.sign(&msg);
-        let msg = SignedMessage { msg, sig, from };
+        let sig = sign_sk.sign(&Self::signed_payload(&msg, seq))?;
+        let msg = SignedMessage {
+            msg,
+            sig,
+            from,
+            seq,
+        };
         let msg = serde_json::to_vec(&msg)?;
         let ciphered = cipher_pk
             .encrypt(&msg, SignedMessage::<T>::ASSOCIATED_DATA)
@@ -552,9 +653,13 @@ impl<T> SignedMessage<T>
 where
     T: for<'a> Deserialize<'a>,
 {
+    /// Decrypts a message and verifies that it was signed by the participant it claims to be
+    /// from. Messages that `replay_window` has already seen are rejected with
+    /// [`CryptographicError::ReplayedMessage`].
     pub async fn decrypt(
-        cipher_sk: &hpke::SecretKey,
+        cipher_sk: &CipherKey,
         protocol_state: &Arc<RwLock<NodeState>>,
+        replay_window: &Mutex<ReplayWindow>,
         encrypted: Ciphered,
     ) -> Result<T, CryptographicError> {
         let message = cipher_sk
@@ -563,9 +668,14 @@ where
                 tracing::error!(error = ?err, "failed to decrypt message");
                 CryptographicError::Encryption(err.to_string())
             })?;
-        let SignedMessage::<Vec<u8>> { msg, sig, from } = serde_json::from_slice(&message)?;
+        let SignedMessage::<Vec<u8>> {
+            msg,
+            sig,
+            from,
+            seq,
+        } = serde_json::from_slice(&message)?;
         if !sig.verify(
-            &msg,
+            &SignedMessage::<T>::signed_payload(&msg, seq),
             &protocol_state
                 .read()
                 .await
@@ -578,7 +688,79 @@ where
                     .to_string(),
             ));
         }
+        // Only authentic messages make it into the window, so forged sequence numbers can not
+        // be used to get legitimate messages dropped.
+        if !replay_window.lock()?.insert(from, seq) {
+            return Err(CryptographicError::ReplayedMessage { from, seq });
+        }
 
         Ok(serde_json::from_slice(&msg)?)
     }
 }
+
+/// Sequence numbers recently received from every participant. A message is accepted once,
+/// and only if its sequence number is within [`ReplayWindow::SIZE`] of the highest one seen
+/// from the same participant, so replayed messages can not be fed into the protocols again.
+#[derive(Default)]
+pub struct ReplayWindow {
+    peers: HashMap<Participant, PeerWindow>,
+}
+
+#[derive(Default)]
+struct PeerWindow {
+    highest: u64,
+    seen: BTreeSet<u64>,
+}
+
+impl ReplayWindow {
+    /// How far behind the highest sequence number a message can be and still be accepted,
+    /// to allow for messages arriving out of order.
+    pub const SIZE: u64 = 4096;
+
+    /// Records the sequence number `seq` of a message from `from`, returning false if the
+    /// message has been seen before or is too old to tell.
+    pub fn insert(&mut self, from: Participant, seq: u64) -> bool {
+        let window = self.peers.entry(from).or_default();
+        if seq > window.highest {
+            window.highest = seq;
+        } else if window.highest - seq >= Self::SIZE {
+            return false;
+        }
+        if !window.seen.insert(seq) {
+            return false;
+        }
+        let oldest = window.highest.saturating_sub(Self::SIZE - 1);
+        window.seen = window.seen.split_off(&oldest);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReplayWindow;
+    use cait_sith::protocol::Participant;
+
+    #[test]
+    fn test_replay_window() {
+        let alice = Participant::from(0);
+        let bob = Participant::from(1);
+        let mut window = ReplayWindow::default();
+
+        assert!(window.insert(alice, 100));
+        assert!(!window.insert(alice, 100));
+        // sequence numbers are tracked per participant
+        assert!(window.insert(bob, 100));
+
+        // messages can arrive out of order, but only once
+        assert!(window.insert(alice, 103));
+        assert!(window.insert(alice, 101));
+        assert!(!window.insert(alice, 101));
+
+        // messages older than the window are dropped
+        assert!(window.insert(alice, 100 + ReplayWindow::SIZE));
+        assert!(window.insert(alice, 102));
+        assert!(!window.insert(alice, 99));
+        assert!(window.insert(alice, 101 + 2 * ReplayWindow::SIZE));
+        assert!(!window.insert(alice, 103));
+    }
+}
@@ -2,6 +2,7 @@ mod cryptography;
 
 pub mod consensus;
 pub mod contract;
+pub mod eviction;
 pub mod message;
 pub mod presignature;
 pub mod signature;
@@ -13,15 +14,19 @@ pub use contract::primitives::ParticipantInfo;
 pub use contract::ProtocolState;
 pub use cryptography::CryptographicError;
 pub use message::MpcMessage;
+pub use signature::SignEvent;
+pub use signature::SignEventSender;
 pub use signature::SignQueue;
 pub use signature::SignRequest;
 pub use state::NodeState;
 pub use sysinfo::{Components, CpuRefreshKind, Disks, RefreshKind, System};
 
 use self::consensus::ConsensusCtx;
+use self::contract::RunningContractState;
 use self::cryptography::CryptographicCtx;
 use self::message::MessageCtx;
-use crate::config::Config;
+use crate::config::{Config, FileConfig, OverrideConfig};
+use crate::hsm::AccountSigner;
 use crate::http_client;
 use crate::mesh;
 use crate::mesh::Mesh;
@@ -30,34 +35,167 @@ use crate::protocol::cryptography::CryptographicProtocol;
 use crate::protocol::message::{MessageHandler, MpcMessageQueue};
 use crate::rpc_client;
 use crate::storage::presignature_storage::PresignatureRedisStorage;
-use crate::storage::secret_storage::SecretNodeStorageBox;
+use crate::storage::publish_storage::PublishRedisStorage;
+use crate::storage::secret_storage::SecretStorageBox;
 use crate::storage::triple_storage::TripleRedisStorage;
 
 use cait_sith::protocol::Participant;
+use chrono::Utc;
 use near_account_id::AccountId;
-use near_crypto::InMemorySigner;
 use reqwest::IntoUrl;
 use std::path::Path;
 use std::time::Instant;
 use std::{sync::Arc, time::Duration};
 use tokio::sync::mpsc::{self, error::TryRecvError};
-use tokio::sync::RwLock;
+use tokio::sync::{oneshot, RwLock};
+use tracing::Instrument;
 use url::Url;
 
+/// How often a running node checks whether the key shares are due for a proactive resharing.
+const PROACTIVE_RESHARING_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often a running node checks the heartbeats of the participants and votes to kick the
+/// ones that went offline.
+const EVICTION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often a node of the protocol pings the contract to report that it is up.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How often a running node checks its presignatures. Running out of them or having some again
+/// gets reported to the contract right away.
+const CAPACITY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a running node reports its presignatures to the contract when only their amount
+/// changed.
+const CAPACITY_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often a running node reports its presignatures to the contract when they did not change,
+/// so that its report does not go stale. Has to stay well below the `stale_after_blocks` of the
+/// `backpressure` entry of the contract config.
+const CAPACITY_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often a running node looks for presignatures that are older than the `max_age` of the
+/// `presignature_expiry` entry of the contract config.
+const PRESIGNATURE_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Requests of the admin API that have to be handled by the protocol loop.
+#[derive(Debug)]
+pub enum AdminCommand {
+    /// Fetch the contract state and config and ping every peer right away.
+    Refresh,
+    /// Report how this node sees each of its peers.
+    PeerStatuses(oneshot::Sender<Vec<mesh::connection::PeerStatus>>),
+    /// Apply the settings of the reloaded config file.
+    Reload(FileConfig),
+}
+
 struct Ctx {
     my_address: Url,
     account_id: AccountId,
     mpc_contract_id: AccountId,
-    signer: InMemorySigner,
+    signer: AccountSigner,
     rpc_client: near_fetch::Client,
     http_client: reqwest::Client,
     sign_queue: Arc<RwLock<SignQueue>>,
-    secret_storage: SecretNodeStorageBox,
+    sign_events: SignEventSender,
+    secret_storage: SecretStorageBox,
     triple_storage: TripleRedisStorage,
     presignature_storage: PresignatureRedisStorage,
+    publish_storage: PublishRedisStorage,
     cfg: Config,
     mesh: Mesh,
     message_options: http_client::Options,
+    message_seqs: http_client::SequenceNumbers,
+}
+
+impl Ctx {
+    /// Votes in the background to kick the `stale` participants, whose heartbeats on the
+    /// contract are too old and that stayed unreachable through the mesh, out of the running
+    /// protocol. Nothing is voted for if the heartbeat of this node is stale as well, since then
+    /// its own pings are not getting through, nor if this node can not reach a threshold of
+    /// participants, since then it is more likely to be the one that got cut off.
+    fn vote_to_evict(
+        &self,
+        running: &RunningContractState,
+        stale: Vec<AccountId>,
+        unreachable: &eviction::UnreachableTracker,
+    ) {
+        if stale.contains(&self.account_id) {
+            tracing::warn!(
+                ?stale,
+                "not voting to evict offline participants, the heartbeat of this node is stale too"
+            );
+            return;
+        }
+        let reachable = self.mesh.active_participants().len();
+        if reachable < running.threshold {
+            tracing::warn!(
+                reachable,
+                threshold = running.threshold,
+                "not voting to evict offline participants, this node reaches too few of them"
+            );
+            return;
+        }
+        let offline = unreachable.offline(&self.cfg.eviction, stale, Instant::now());
+        if offline.is_empty() {
+            return;
+        }
+        // The contract refuses to kick participants once it would leave less than a threshold.
+        if running.participants.len() <= running.threshold {
+            tracing::warn!(
+                ?offline,
+                "participants are offline but can not be evicted without going below the threshold"
+            );
+            return;
+        }
+
+        for kick in offline {
+            if kick == self.account_id
+                || running
+                    .leave_votes
+                    .get(&kick)
+                    .is_some_and(|voters| voters.contains(&self.account_id))
+            {
+                continue;
+            }
+            let rpc_client = self.rpc_client.clone();
+            let signer = self.signer.clone();
+            let mpc_contract_id = self.mpc_contract_id.clone();
+            tokio::spawn(async move {
+                if let Err(err) =
+                    rpc_client::vote_leave(&rpc_client, &signer, &mpc_contract_id, &kick).await
+                {
+                    tracing::warn!(?err, %kick, "could not vote to evict offline participant");
+                }
+            });
+        }
+    }
+
+    fn apply_file_config(&mut self, config: FileConfig) {
+        if let Some(protocol) = config.protocol {
+            if let Err(err) = self.cfg.apply_override(&OverrideConfig::new(protocol)) {
+                tracing::warn!(?err, "could not apply protocol config from the config file");
+            }
+        }
+        if let Some(timeouts) = config.timeouts {
+            if let Err(err) = self
+                .cfg
+                .apply_timeouts_override(&OverrideConfig::new(timeouts))
+            {
+                tracing::warn!(?err, "could not apply timeouts config from the config file");
+            }
+        }
+        if config.fetch_participant_timeout.is_some() || config.refresh_active_timeout.is_some() {
+            self.mesh.set_timeouts(
+                config.fetch_participant_timeout.map(Duration::from_millis),
+                config.refresh_active_timeout.map(Duration::from_millis),
+            );
+        }
+        if let Some(message_timeout) = config.message_timeout {
+            self.message_options.timeout = message_timeout;
+        }
+        tracing::info!(cfg = ?self.cfg, "applied config file");
+    }
 }
 
 impl ConsensusCtx for &mut MpcSignProtocol {
@@ -73,7 +211,7 @@ impl ConsensusCtx for &mut MpcSignProtocol {
         &self.ctx.rpc_client
     }
 
-    fn signer(&self) -> &InMemorySigner {
+    fn signer(&self) -> &AccountSigner {
         &self.ctx.signer
     }
 
@@ -89,7 +227,11 @@ impl ConsensusCtx for &mut MpcSignProtocol {
         self.ctx.sign_queue.clone()
     }
 
-    fn secret_storage(&self) -> &SecretNodeStorageBox {
+    fn sign_events(&self) -> SignEventSender {
+        self.ctx.sign_events.clone()
+    }
+
+    fn secret_storage(&self) -> &SecretStorageBox {
         &self.ctx.secret_storage
     }
 
@@ -105,9 +247,17 @@ impl ConsensusCtx for &mut MpcSignProtocol {
         &self.ctx.presignature_storage
     }
 
+    fn publish_storage(&self) -> &PublishRedisStorage {
+        &self.ctx.publish_storage
+    }
+
     fn message_options(&self) -> http_client::Options {
         self.ctx.message_options.clone()
     }
+
+    fn message_seqs(&self) -> http_client::SequenceNumbers {
+        self.ctx.message_seqs.clone()
+    }
 }
 
 #[async_trait::async_trait]
@@ -124,7 +274,7 @@ impl CryptographicCtx for &mut MpcSignProtocol {
         &self.ctx.rpc_client
     }
 
-    fn signer(&self) -> &InMemorySigner {
+    fn signer(&self) -> &AccountSigner {
         &self.ctx.signer
     }
 
@@ -132,7 +282,7 @@ impl CryptographicCtx for &mut MpcSignProtocol {
         &self.ctx.mpc_contract_id
     }
 
-    fn secret_storage(&mut self) -> &mut SecretNodeStorageBox {
+    fn secret_storage(&mut self) -> &mut SecretStorageBox {
         &mut self.ctx.secret_storage
     }
 
@@ -163,6 +313,8 @@ impl MessageCtx for &MpcSignProtocol {
 pub struct MpcSignProtocol {
     ctx: Ctx,
     receiver: mpsc::Receiver<MpcMessage>,
+    config_receiver: mpsc::Receiver<OverrideConfig>,
+    admin_receiver: mpsc::Receiver<AdminCommand>,
     state: Arc<RwLock<NodeState>>,
 }
 
@@ -173,19 +325,24 @@ impl MpcSignProtocol {
         mpc_contract_id: AccountId,
         account_id: AccountId,
         rpc_client: near_fetch::Client,
-        signer: InMemorySigner,
+        signer: AccountSigner,
         receiver: mpsc::Receiver<MpcMessage>,
+        config_receiver: mpsc::Receiver<OverrideConfig>,
+        admin_receiver: mpsc::Receiver<AdminCommand>,
         sign_queue: Arc<RwLock<SignQueue>>,
-        secret_storage: SecretNodeStorageBox,
+        sign_events: SignEventSender,
+        secret_storage: SecretStorageBox,
         triple_storage: TripleRedisStorage,
         presignature_storage: PresignatureRedisStorage,
+        publish_storage: PublishRedisStorage,
         cfg: Config,
         mesh_options: mesh::Options,
         message_options: http_client::Options,
+        message_seqs: http_client::SequenceNumbers,
     ) -> (Self, Arc<RwLock<NodeState>>) {
         let my_address = my_address.into_url().unwrap();
         let rpc_url = rpc_client.rpc_addr();
-        let signer_account_id: AccountId = signer.clone().account_id;
+        let signer_account_id = signer.account_id().clone();
         tracing::info!(
             ?my_address,
             ?mpc_contract_id,
@@ -203,17 +360,22 @@ impl MpcSignProtocol {
             rpc_client,
             http_client: reqwest::Client::new(),
             sign_queue,
+            sign_events,
             signer,
             secret_storage,
             triple_storage,
             presignature_storage,
+            publish_storage,
             cfg,
             mesh: Mesh::new(mesh_options),
             message_options,
+            message_seqs,
         };
         let protocol = MpcSignProtocol {
             ctx,
             receiver,
+            config_receiver,
+            admin_receiver,
             state: state.clone(),
         };
         (protocol, state)
@@ -233,6 +395,13 @@ impl MpcSignProtocol {
         let mut last_config_update = Instant::now();
         let mut last_hardware_pull = Instant::now();
         let mut last_pinged = Instant::now();
+        let mut last_resharing_check = Instant::now();
+        let mut last_eviction_check = Instant::now();
+        let mut unreachable = eviction::UnreachableTracker::default();
+        let mut last_heartbeat: Option<Instant> = None;
+        let mut last_capacity_check = Instant::now();
+        let mut last_capacity_report: Option<(Instant, usize)> = None;
+        let mut last_presignature_expiry = Instant::now();
 
         // Sets the latest configurations from the contract:
         if let Err(err) = self
@@ -274,7 +443,26 @@ impl MpcSignProtocol {
                 }
             }
 
-            let contract_state = if last_state_update.elapsed() > Duration::from_secs(1) {
+            let mut refresh = false;
+            while let Ok(command) = self.admin_receiver.try_recv() {
+                match command {
+                    AdminCommand::Refresh => {
+                        tracing::info!("refreshing state as requested through the admin API");
+                        refresh = true;
+                    }
+                    AdminCommand::PeerStatuses(reply) => {
+                        let _ = reply.send(self.ctx.mesh.peer_statuses().await);
+                    }
+                    AdminCommand::Reload(config) => self.ctx.apply_file_config(config),
+                }
+            }
+            if refresh {
+                self.ctx.mesh.refresh().await;
+                last_pinged = Instant::now();
+            }
+
+            let contract_state = if refresh || last_state_update.elapsed() > Duration::from_secs(1)
+            {
                 let contract_state = match rpc_client::fetch_mpc_contract_state(
                     &self.ctx.rpc_client,
                     &self.ctx.mpc_contract_id,
@@ -293,13 +481,50 @@ impl MpcSignProtocol {
                 // receiving messages.
                 self.ctx.mesh.establish_participants(&contract_state).await;
 
+                if let ProtocolState::Running(running) = &contract_state {
+                    unreachable.observe(
+                        &running.participants,
+                        self.ctx.mesh.active_participants(),
+                        Instant::now(),
+                    );
+                    let eviction_config = self.ctx.cfg.eviction;
+                    if eviction_config.stale_after_blocks > 0
+                        && last_eviction_check.elapsed() > EVICTION_CHECK_INTERVAL
+                    {
+                        last_eviction_check = Instant::now();
+                        match rpc_client::fetch_participant_heartbeats(
+                            &self.ctx.rpc_client,
+                            &self.ctx.mpc_contract_id,
+                        )
+                        .await
+                        {
+                            Ok(heartbeats) => {
+                                let stale = eviction::stale_participants(
+                                    &eviction_config,
+                                    &running.participants,
+                                    &heartbeats,
+                                );
+                                if !stale.is_empty() {
+                                    self.ctx.vote_to_evict(running, stale, &unreachable);
+                                }
+                            }
+                            Err(err) => {
+                                tracing::warn!(
+                                    ?err,
+                                    "could not check the participants for eviction"
+                                );
+                            }
+                        }
+                    }
+                }
+
                 last_state_update = Instant::now();
                 Some(contract_state)
             } else {
                 None
             };
 
-            if last_config_update.elapsed() > Duration::from_secs(5 * 60) {
+            if refresh || last_config_update.elapsed() > Duration::from_secs(5 * 60) {
                 // Sets the latest configurations from the contract:
                 if let Err(err) = self
                     .ctx
@@ -312,6 +537,19 @@ impl MpcSignProtocol {
                 last_config_update = Instant::now();
             }
 
+            // Apply overrides that were submitted through the admin API:
+            while let Ok(over) = self.config_receiver.try_recv() {
+                if let Err(err) = self.ctx.cfg.apply_override(&over) {
+                    tracing::warn!(?err, "could not apply config override");
+                } else {
+                    tracing::info!(?over, "applied config override");
+                }
+            }
+            // The peers get pinged with the same retries as the protocol messages.
+            self.ctx
+                .mesh
+                .set_message_retry(self.ctx.cfg.timeouts.message_retry);
+
             if last_pinged.elapsed() > Duration::from_millis(300) {
                 self.ctx.mesh.ping().await;
                 last_pinged = Instant::now();
@@ -323,7 +561,8 @@ impl MpcSignProtocol {
             };
 
             let crypto_time = Instant::now();
-            let mut state = match state.progress(&mut self).await {
+            let progress_span = tracing::debug_span!("progress", state = %state);
+            let mut state = match state.progress(&mut self).instrument(progress_span).await {
                 Ok(state) => {
                     tracing::debug!("progress ok: {state}");
                     state
@@ -341,7 +580,12 @@ impl MpcSignProtocol {
             let consensus_time = Instant::now();
             if let Some(contract_state) = contract_state {
                 let from_state = format!("{state}");
-                state = match state.advance(&mut self, contract_state).await {
+                let advance_span = tracing::debug_span!("advance", state = %from_state);
+                state = match state
+                    .advance(&mut self, contract_state)
+                    .instrument(advance_span)
+                    .await
+                {
                     Ok(state) => {
                         tracing::debug!("advance ok: {from_state} => {state}");
                         state
@@ -358,13 +602,125 @@ impl MpcSignProtocol {
                 .observe(consensus_time.elapsed().as_secs_f64());
 
             let message_time = Instant::now();
-            if let Err(err) = state.handle(&self, &mut queue).await {
+            let handle_span = tracing::debug_span!("handle_messages", state = %state);
+            if let Err(err) = state
+                .handle(&self, &mut queue)
+                .instrument(handle_span)
+                .await
+            {
                 tracing::warn!("protocol unable to handle messages: {err:?}");
             }
             crate::metrics::PROTOCOL_LATENCY_ITER_MESSAGE
                 .with_label_values(&[my_account_id.as_str()])
                 .observe(message_time.elapsed().as_secs_f64());
 
+            // Proactive resharing is checked for in the background, so that waiting on the
+            // transaction does not hold up the protocol.
+            if matches!(state, NodeState::Running(_))
+                && last_resharing_check.elapsed() > PROACTIVE_RESHARING_CHECK_INTERVAL
+            {
+                let rpc_client = self.ctx.rpc_client.clone();
+                let signer = self.ctx.signer.clone();
+                let mpc_contract_id = self.ctx.mpc_contract_id.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = rpc_client::start_proactive_resharing_if_due(
+                        &rpc_client,
+                        &signer,
+                        &mpc_contract_id,
+                    )
+                    .await
+                    {
+                        tracing::warn!(?err, "could not check for proactive resharing");
+                    }
+                });
+                last_resharing_check = Instant::now();
+            }
+
+            // Only the nodes of the protocol are accepted by `ping`, so joining nodes wait for
+            // being voted in first.
+            if matches!(
+                state,
+                NodeState::Generating(_)
+                    | NodeState::WaitingForConsensus(_)
+                    | NodeState::Running(_)
+                    | NodeState::Resharing(_)
+            ) && last_heartbeat.map_or(true, |last| last.elapsed() > HEARTBEAT_INTERVAL)
+            {
+                let rpc_client = self.ctx.rpc_client.clone();
+                let signer = self.ctx.signer.clone();
+                let mpc_contract_id = self.ctx.mpc_contract_id.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = rpc_client::ping(&rpc_client, &signer, &mpc_contract_id).await
+                    {
+                        tracing::warn!(?err, "could not report heartbeat to the contract");
+                    }
+                });
+                last_heartbeat = Some(Instant::now());
+            }
+
+            // The presignatures of this node tell the contract whether the network can take
+            // more sign requests, see `report_capacity`.
+            if let (NodeState::Running(running), true) = (
+                &state,
+                last_capacity_check.elapsed() > CAPACITY_CHECK_INTERVAL,
+            ) {
+                last_capacity_check = Instant::now();
+                let presignatures = running.presignature_manager.read().await.len_mine().await;
+                let due = last_capacity_report.map_or(true, |(last, reported)| {
+                    let elapsed = last.elapsed();
+                    (reported == 0) != (presignatures == 0)
+                        || (reported != presignatures && elapsed > CAPACITY_REPORT_INTERVAL)
+                        || elapsed > CAPACITY_REFRESH_INTERVAL
+                });
+                if due {
+                    let rpc_client = self.ctx.rpc_client.clone();
+                    let signer = self.ctx.signer.clone();
+                    let mpc_contract_id = self.ctx.mpc_contract_id.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = rpc_client::report_capacity(
+                            &rpc_client,
+                            &signer,
+                            &mpc_contract_id,
+                            presignatures as u32,
+                        )
+                        .await
+                        {
+                            tracing::warn!(?err, "could not report capacity to the contract");
+                        }
+                    });
+                    last_capacity_report = Some((Instant::now(), presignatures));
+                }
+            }
+
+            // Expired presignatures are discarded in the background, the stockpile replaces them
+            // like the ones that got used.
+            if let (NodeState::Running(running), true) = (
+                &state,
+                last_presignature_expiry.elapsed() > PRESIGNATURE_EXPIRY_CHECK_INTERVAL,
+            ) {
+                last_presignature_expiry = Instant::now();
+                if let Some(created_before) = self
+                    .ctx
+                    .cfg
+                    .presignature_expiry
+                    .created_before(Utc::now().timestamp() as u64)
+                {
+                    // Signatures started with a presignature right before it expired finish
+                    // within the total signature timeout.
+                    let grace = Duration::from_millis(
+                        self.ctx.cfg.protocol.signature.generation_timeout_total,
+                    );
+                    let presignature_manager = running.presignature_manager.clone();
+                    tokio::spawn(async move {
+                        presignature_manager
+                            .write()
+                            .await
+                            .expire(created_before, grace)
+                            .await;
+                    });
+                }
+            }
+
             let sleep_ms = match state {
                 NodeState::Generating(_) => 500,
                 NodeState::Resharing(_) => 500,
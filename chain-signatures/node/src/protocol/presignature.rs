@@ -30,6 +30,9 @@ pub struct Presignature {
     pub id: PresignatureId,
     pub output: PresignOutput<Secp256k1>,
     pub participants: Vec<Participant>,
+    /// Unix timestamp in seconds of when the presignature got generated, after which it
+    /// expires according to the `presignature_expiry` entry of the contract config.
+    pub created_at: u64,
 }
 
 impl Serialize for Presignature {
@@ -37,12 +40,13 @@ impl Serialize for Presignature {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("Presignature", 5)?;
+        let mut state = serializer.serialize_struct("Presignature", 6)?;
         state.serialize_field("id", &self.id)?;
         state.serialize_field("output_big_r", &self.output.big_r)?;
         state.serialize_field("output_k", &self.output.k)?;
         state.serialize_field("output_sigma", &self.output.sigma)?;
         state.serialize_field("participants", &self.participants)?;
+        state.serialize_field("created_at", &self.created_at)?;
         state.end()
     }
 }
@@ -59,6 +63,8 @@ impl<'de> Deserialize<'de> for Presignature {
             output_k: Scalar,
             output_sigma: Scalar,
             participants: Vec<Participant>,
+            #[serde(default)]
+            created_at: u64,
         }
 
         let fields = PresignatureFields::deserialize(deserializer)?;
@@ -71,6 +77,7 @@ impl<'de> Deserialize<'de> for Presignature {
                 sigma: fields.output_sigma,
             },
             participants: fields.participants,
+            created_at: fields.created_at,
         })
     }
 }
@@ -120,6 +127,16 @@ impl PresignatureGenerator {
                 anyhow::anyhow!("presignature protocol timed out").into(),
             ));
         }
+        if crate::failpoint::eval_blocking(
+            crate::failpoint::PRESIGNATURE_GENERATE,
+            Some(&hash_as_id(self.triple0, self.triple1).to_string()),
+        )
+        .is_some()
+        {
+            return Err(ProtocolError::Other(
+                anyhow::anyhow!("presignature protocol failed at a failpoint").into(),
+            ));
+        }
 
         self.protocol.poke()
     }
@@ -298,6 +315,39 @@ impl PresignatureManager {
         complete_presignatures + ongoing_generators
     }
 
+    /// Discards the presignatures of this node generated before `created_before`, a unix
+    /// timestamp in seconds, and returns how many presignatures were discarded. The ones of
+    /// other nodes are kept for `grace` longer, so that a signature their owner started right
+    /// before they expired can still finish. The stockpile generates new presignatures in place
+    /// of the discarded ones.
+    pub async fn expire(&mut self, created_before: u64, grace: Duration) -> usize {
+        let foreign_created_before = created_before.saturating_sub(grace.as_secs());
+        let mut expired = 0;
+        for (created_before, mine_only) in [(created_before, true), (foreign_created_before, false)]
+        {
+            match self
+                .presignature_storage
+                .remove_expired(created_before, mine_only)
+                .await
+            {
+                Ok(ids) => {
+                    expired += ids.len();
+                    for id in ids {
+                        self.gc.insert(id, Instant::now());
+                    }
+                }
+                Err(err) => tracing::error!(?err, "failed to expire presignatures"),
+            }
+        }
+        if expired > 0 {
+            tracing::info!(expired, created_before, "discarded expired presignatures");
+            crate::metrics::PRESIGNATURES_EXPIRED
+                .with_label_values(&[self.my_account_id.as_str()])
+                .inc_by(expired as f64);
+        }
+        expired
+    }
+
     pub fn garbage_collect(&mut self, cfg: &ProtocolConfig) {
         let before = self.gc.len();
         self.gc
@@ -615,6 +665,7 @@ impl PresignatureManager {
                             id: *id,
                             output,
                             participants: generator.participants.clone(),
+                            created_at: Utc::now().timestamp() as u64,
                         };
                         if generator.mine {
                             tracing::info!(id, "assigning presignature to myself");
@@ -693,6 +744,7 @@ mod tests {
                 sigma: <Secp256k1 as CurveArithmetic>::Scalar::ONE,
             },
             participants: vec![Participant::from(1), Participant::from(2)],
+            created_at: 1_700_000_000,
         };
 
         // Serialize Presignature to JSON
@@ -709,5 +761,6 @@ mod tests {
         assert_eq!(presignature.output.k, deserialized.output.k);
         assert_eq!(presignature.output.sigma, deserialized.output.sigma);
         assert_eq!(presignature.participants, deserialized.participants);
+        assert_eq!(presignature.created_at, deserialized.created_at);
     }
 }
@@ -1,8 +1,10 @@
 use super::contract::primitives::Participants;
 use super::message::SignatureMessage;
 use super::presignature::{GenerationError, Presignature, PresignatureId, PresignatureManager};
+use crate::hsm::AccountSigner;
 use crate::indexer::ContractSignRequest;
 use crate::kdf::{derive_delta, into_eth_sig};
+use crate::storage::publish_storage::{PendingPublish, PublishRedisStorage};
 use crate::types::SignatureProtocol;
 use crate::util::AffinePointExt;
 use near_primitives::hash::CryptoHash;
@@ -11,63 +13,158 @@ use cait_sith::protocol::{Action, InitializationError, Participant, ProtocolErro
 use cait_sith::{FullSignature, PresignOutput};
 use chrono::Utc;
 use crypto_shared::SerializableScalar;
-use crypto_shared::{derive_key, PublicKey};
+use crypto_shared::{derive_key, PublicKey, SignatureResponse};
 use k256::{Scalar, Secp256k1};
-use mpc_contract::config::ProtocolConfig;
+use mpc_contract::config::{BackoffConfig, ProtocolConfig, SignRequestOrdering};
 use mpc_contract::primitives::SignatureRequest;
 use rand::rngs::StdRng;
 use rand::seq::{IteratorRandom, SliceRandom};
 use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, VecDeque};
 use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::Instrument;
 
 use near_account_id::AccountId;
-use near_fetch::signer::SignerExt;
 
 pub type ReceiptId = near_primitives::hash::CryptoHash;
 
+/// Span to log the work on a sign request in. Its `request_id` is the receipt id of the `sign`
+/// call (or derived from it for `sign_batch`), and is the same on every node, so a request can
+/// be followed through the indexer, the protocol and the mesh messages of all the nodes.
+pub fn sign_request_span(request_id: [u8; 32]) -> tracing::Span {
+    tracing::info_span!("sign_request", request_id = %CryptoHash(request_id))
+}
+
+/// Lifecycle events of the sign requests seen by this node. These get streamed to the
+/// subscribers of the `/subscribe` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event")]
+#[serde(rename_all = "snake_case")]
+pub enum SignEvent {
+    /// The request was picked up by the indexer.
+    Received {
+        request_id: String,
+        payload: String,
+        path: String,
+        key_version: u32,
+    },
+    /// A presignature was assigned to the request and signature generation started.
+    PresignatureAssigned {
+        request_id: String,
+        presignature_id: PresignatureId,
+    },
+    /// The signature for the request was published to the contract by this node.
+    SignaturePublished {
+        request_id: String,
+        signature: SignatureResponse,
+    },
+}
+
+/// Sending side of the sign request lifecycle events. Events are dropped when nobody is
+/// subscribed.
+pub type SignEventSender = broadcast::Sender<SignEvent>;
+
 pub struct SignRequest {
     pub request_id: [u8; 32],
     pub request: ContractSignRequest,
     pub epsilon: Scalar,
     pub entropy: [u8; 32],
+    /// Deposit in yoctoNEAR attached to the request.
+    pub deposit: u128,
+    /// Account that submitted the request.
+    pub requester: AccountId,
     pub time_added: Instant,
 }
 
-/// Type that preserves the insertion order of requests.
+/// Requests this node proposes, waiting for a signature generation slot. Every requester has
+/// a queue of its own in the order of the [`SignRequestOrdering`] the requests got inserted
+/// with, and the requesters take turns so that one flooding the contract does not starve the
+/// others.
 #[derive(Default)]
 pub struct ParticipantRequests {
-    requests: VecDeque<SignRequest>,
+    queues: HashMap<AccountId, VecDeque<(u64, SignRequest)>>,
+    ordering: SignRequestOrdering,
+    /// Incremented with every inserted request, to keep the insertion order across queues.
+    inserted: u64,
+    len: usize,
 }
 
 impl ParticipantRequests {
-    fn insert(&mut self, request: SignRequest) {
-        self.requests.push_back(request);
+    fn insert(&mut self, request: SignRequest, ordering: SignRequestOrdering) {
+        self.ordering = ordering;
+        self.inserted += 1;
+        self.len += 1;
+        let queue = self.queues.entry(request.requester.clone()).or_default();
+        match ordering {
+            SignRequestOrdering::Fifo => queue.push_back((self.inserted, request)),
+            SignRequestOrdering::Deposit => {
+                // Goes behind every request with at least the same deposit, so requests with
+                // the same deposit stay in insertion order.
+                let index = queue.partition_point(|(_, queued)| queued.deposit >= request.deposit);
+                queue.insert(index, (self.inserted, request));
+            }
+        }
     }
 
     pub fn len(&self) -> usize {
-        self.requests.len()
+        self.len
     }
 
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
-    pub fn pop_front(&mut self) -> Option<SignRequest> {
-        self.requests.pop_front()
+    /// Takes the next request to generate a signature for. It is the one of the requester with
+    /// the fewest signatures `in_flight`, and between requesters with as many it is the one
+    /// that comes first according to the ordering.
+    pub fn pop_next(&mut self, in_flight: &HashMap<AccountId, usize>) -> Option<SignRequest> {
+        let ordering = self.ordering;
+        let requester = self
+            .queues
+            .iter()
+            .filter_map(|(requester, queue)| Some((requester, queue.front()?)))
+            .min_by(
+                |(a, (a_inserted, a_request)), (b, (b_inserted, b_request))| {
+                    let in_flight_a = in_flight.get(*a).copied().unwrap_or_default();
+                    let in_flight_b = in_flight.get(*b).copied().unwrap_or_default();
+                    let by_order = match ordering {
+                        SignRequestOrdering::Fifo => std::cmp::Ordering::Equal,
+                        SignRequestOrdering::Deposit => b_request.deposit.cmp(&a_request.deposit),
+                    };
+                    in_flight_a
+                        .cmp(&in_flight_b)
+                        .then(by_order)
+                        .then(a_inserted.cmp(b_inserted))
+                },
+            )
+            .map(|(requester, _)| requester.clone())?;
+
+        let queue = self.queues.get_mut(&requester)?;
+        let (_, request) = queue.pop_front()?;
+        if queue.is_empty() {
+            self.queues.remove(&requester);
+        }
+        self.len -= 1;
+        Some(request)
     }
 }
 
-#[derive(Default)]
 pub struct SignQueue {
     unorganized_requests: Vec<SignRequest>,
     requests: HashMap<Participant, ParticipantRequests>,
+    events: SignEventSender,
 }
 
 impl SignQueue {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn new(events: SignEventSender) -> Self {
+        Self {
+            unorganized_requests: Vec::new(),
+            requests: HashMap::new(),
+            events,
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -79,12 +176,19 @@ impl SignQueue {
     }
 
     pub fn add(&mut self, request: SignRequest) {
+        let _span = sign_request_span(request.request_id).entered();
         tracing::info!(
             request_id = ?CryptoHash(request.request_id),
             payload = hex::encode(request.request.payload.to_bytes()),
             entropy = hex::encode(request.entropy),
             "new sign request"
         );
+        let _ = self.events.send(SignEvent::Received {
+            request_id: CryptoHash(request.request_id).to_string(),
+            payload: hex::encode(request.request.payload.to_bytes()),
+            path: request.request.path.clone(),
+            key_version: request.request.key_version,
+        });
         self.unorganized_requests.push(request);
     }
 
@@ -94,6 +198,7 @@ impl SignQueue {
         stable: &Participants,
         me: Participant,
         my_account_id: &AccountId,
+        ordering: SignRequestOrdering,
     ) {
         if stable.len() < threshold {
             tracing::warn!(
@@ -105,6 +210,7 @@ impl SignQueue {
             return;
         }
         for request in self.unorganized_requests.drain(..) {
+            let _span = sign_request_span(request.request_id).entered();
             let mut rng = StdRng::from_seed(request.entropy);
             let subset = stable.keys().choose_multiple(&mut rng, threshold);
             let proposer = **subset.choose(&mut rng).unwrap();
@@ -118,7 +224,7 @@ impl SignQueue {
                     "saving sign request: node is in the signer subset"
                 );
                 let proposer_requests = self.requests.entry(proposer).or_default();
-                proposer_requests.insert(request);
+                proposer_requests.insert(request, ordering);
                 if is_mine {
                     crate::metrics::NUM_SIGN_REQUESTS_MINE
                         .with_label_values(&[my_account_id.as_str()])
@@ -155,6 +261,8 @@ pub struct SignatureGenerator {
     pub generator_timestamp: Instant,
     pub timeout: Duration,
     pub timeout_total: Duration,
+    /// Account that submitted the request. Only known to the proposer.
+    pub requester: Option<AccountId>,
 }
 
 impl SignatureGenerator {
@@ -169,6 +277,7 @@ impl SignatureGenerator {
         request_id: [u8; 32],
         entropy: [u8; 32],
         sign_request_timestamp: Instant,
+        requester: Option<AccountId>,
         cfg: &ProtocolConfig,
     ) -> Self {
         Self {
@@ -184,6 +293,7 @@ impl SignatureGenerator {
             generator_timestamp: Instant::now(),
             timeout: Duration::from_millis(cfg.signature.generation_timeout),
             timeout_total: Duration::from_millis(cfg.signature.generation_timeout_total),
+            requester,
         }
     }
 
@@ -214,6 +324,7 @@ pub struct GenerationRequest {
     pub request_id: [u8; 32],
     pub entropy: [u8; 32],
     pub sign_request_timestamp: Instant,
+    pub requester: Option<AccountId>,
 }
 
 #[derive(Debug, Clone, Eq, Hash, PartialEq)]
@@ -240,22 +351,33 @@ pub struct SignatureManager {
     failed: VecDeque<(SignRequestIdentifier, GenerationRequest)>,
     /// Set of completed signatures
     completed: HashMap<SignRequestIdentifier, Instant>,
-    /// Generated signatures assigned to the current node that are yet to be published.
-    /// Vec<(receipt_id, msg_hash, timestamp, output)>
+    /// Generated signatures assigned to the current node that are yet to be queued for
+    /// publishing.
     signatures: Vec<ToPublish>,
+    /// Signatures waiting to be published, mirrored in `publish_storage`.
+    to_publish: VecDeque<PendingPublish>,
+    /// When the signatures that failed to publish can be tried again, by request id.
+    publish_backoff: HashMap<[u8; 32], Instant>,
+    /// Sign requests of the signatures to publish, to measure their latency. Only known for the
+    /// signatures generated since the node started.
+    publish_requested_at: HashMap<[u8; 32], Instant>,
+    /// Whether the signatures queued before this manager was created have been loaded.
+    publish_loaded: bool,
+    publish_storage: PublishRedisStorage,
     me: Participant,
     public_key: PublicKey,
     epoch: u64,
     my_account_id: AccountId,
+    events: SignEventSender,
 }
 
-pub const MAX_RETRY: u8 = 10;
 pub struct ToPublish {
     request_id: [u8; 32],
     request: SignatureRequest,
     time_added: Instant,
     signature: FullSignature<Secp256k1>,
-    retry_count: u8,
+    /// Participants that produced the signature, which the contract credits for it.
+    participants: Vec<Participant>,
 }
 
 impl ToPublish {
@@ -264,13 +386,14 @@ impl ToPublish {
         request: SignatureRequest,
         time_added: Instant,
         signature: FullSignature<Secp256k1>,
+        participants: Vec<Participant>,
     ) -> ToPublish {
         ToPublish {
             request_id,
             request,
             time_added,
             signature,
-            retry_count: 0,
+            participants,
         }
     }
 }
@@ -281,19 +404,34 @@ impl SignatureManager {
         public_key: PublicKey,
         epoch: u64,
         my_account_id: &AccountId,
+        events: SignEventSender,
+        publish_storage: &PublishRedisStorage,
     ) -> Self {
         Self {
             generators: HashMap::new(),
             failed: VecDeque::new(),
             completed: HashMap::new(),
             signatures: Vec::new(),
+            to_publish: VecDeque::new(),
+            publish_backoff: HashMap::new(),
+            publish_requested_at: HashMap::new(),
+            publish_loaded: false,
+            publish_storage: publish_storage.clone(),
             me,
             public_key,
             epoch,
             my_account_id: my_account_id.clone(),
+            events,
         }
     }
 
+    fn presignature_assigned(&self, request_id: [u8; 32], presignature_id: PresignatureId) {
+        let _ = self.events.send(SignEvent::PresignatureAssigned {
+            request_id: CryptoHash(request_id).to_string(),
+            presignature_id,
+        });
+    }
+
     pub fn failed_len(&self) -> usize {
         self.failed.len()
     }
@@ -302,6 +440,25 @@ impl SignatureManager {
         self.me
     }
 
+    /// Signatures in flight for the requests this node proposes, including the failed ones
+    /// waiting to be retried, by the account that submitted them.
+    fn in_flight(&self) -> HashMap<AccountId, usize> {
+        let mut in_flight = HashMap::new();
+        let requesters = self
+            .generators
+            .values()
+            .filter_map(|generator| generator.requester.as_ref())
+            .chain(
+                self.failed
+                    .iter()
+                    .filter_map(|(_, req)| req.requester.as_ref()),
+            );
+        for requester in requesters {
+            *in_flight.entry(requester.clone()).or_default() += 1;
+        }
+        in_flight
+    }
+
     #[allow(clippy::too_many_arguments)]
     #[allow(clippy::result_large_err)]
     fn generate_internal(
@@ -320,6 +477,7 @@ impl SignatureManager {
             request_id,
             entropy,
             sign_request_timestamp,
+            requester,
         } = req;
         let PresignOutput { big_r, k, sigma } = presignature.output;
         let delta = derive_delta(request_id, entropy, big_r);
@@ -350,6 +508,7 @@ impl SignatureManager {
             request_id,
             entropy,
             sign_request_timestamp,
+            requester,
             cfg,
         ))
     }
@@ -364,6 +523,7 @@ impl SignatureManager {
         cfg: &ProtocolConfig,
     ) -> Result<(), (Presignature, InitializationError)> {
         tracing::info!(sign_request_identifier = ?sign_request_identifier, participants = ?participants.keys_vec(), "restarting failed protocol to generate signature");
+        let presignature_id = presignature.id;
         let generator = Self::generate_internal(
             participants,
             self.me,
@@ -375,6 +535,7 @@ impl SignatureManager {
         crate::metrics::NUM_TOTAL_HISTORICAL_SIGNATURE_GENERATORS
             .with_label_values(&[self.my_account_id.as_str()])
             .inc();
+        self.presignature_assigned(sign_request_identifier.request_id, presignature_id);
         self.generators.insert(sign_request_identifier, generator);
         Ok(())
     }
@@ -391,6 +552,7 @@ impl SignatureManager {
         epsilon: Scalar,
         entropy: [u8; 32],
         sign_request_timestamp: Instant,
+        requester: AccountId,
         cfg: &ProtocolConfig,
     ) -> Result<(), (Presignature, InitializationError)> {
         let sign_request_identifier =
@@ -402,6 +564,7 @@ impl SignatureManager {
             participants = ?participants.keys_vec(),
             "starting protocol to generate a new signature",
         );
+        let presignature_id = presignature.id;
         let generator = Self::generate_internal(
             participants,
             self.me,
@@ -414,12 +577,14 @@ impl SignatureManager {
                 request_id,
                 entropy,
                 sign_request_timestamp,
+                requester: Some(requester),
             },
             cfg,
         )?;
         crate::metrics::NUM_TOTAL_HISTORICAL_SIGNATURE_GENERATORS
             .with_label_values(&[self.my_account_id.as_str()])
             .inc();
+        self.presignature_assigned(request_id, presignature_id);
         self.generators.insert(sign_request_identifier, generator);
         Ok(())
     }
@@ -481,6 +646,7 @@ impl SignatureManager {
                         entropy,
                         request_id,
                         sign_request_timestamp: Instant::now(),
+                        requester: None,
                     },
                     cfg,
                 ) {
@@ -491,6 +657,10 @@ impl SignatureManager {
                         return Err(GenerationError::CaitSithInitializationError(err));
                     }
                 };
+                let _ = self.events.send(SignEvent::PresignatureAssigned {
+                    request_id: CryptoHash(request_id).to_string(),
+                    presignature_id,
+                });
                 let generator = entry.insert(generator);
                 crate::metrics::NUM_TOTAL_HISTORICAL_SIGNATURE_GENERATORS
                     .with_label_values(&[self.my_account_id.as_str()])
@@ -508,6 +678,7 @@ impl SignatureManager {
     pub fn poke(&mut self) -> Vec<(Participant, SignatureMessage)> {
         let mut messages = Vec::new();
         self.generators.retain(|sign_request_identifier, generator| {
+            let _span = sign_request_span(sign_request_identifier.request_id).entered();
             loop {
                 let action = match generator.poke() {
                     Ok(action) => action,
@@ -528,7 +699,8 @@ impl SignatureManager {
                                         epsilon: generator.epsilon,
                                         request_id: generator.request_id,
                                         entropy: generator.entropy,
-                                        sign_request_timestamp: generator.sign_request_timestamp
+                                        sign_request_timestamp: generator.sign_request_timestamp,
+                                        requester: generator.requester.clone(),
                                     },
                                 ));
                             } else {
@@ -598,7 +770,7 @@ impl SignatureManager {
                         };
                         if generator.proposer == self.me {
                             self.signatures
-                                .push(ToPublish::new(sign_request_identifier.request_id, request, generator.sign_request_timestamp, output));
+                                .push(ToPublish::new(sign_request_identifier.request_id, request, generator.sign_request_timestamp, output, generator.participants.clone()));
                         }
                         // Do not retain the protocol
                         return false;
@@ -615,6 +787,7 @@ impl SignatureManager {
         stable: &Participants,
         my_requests: &mut ParticipantRequests,
         presignature_manager: &mut PresignatureManager,
+        max_concurrent_signatures: usize,
         cfg: &ProtocolConfig,
     ) {
         if stable.len() < threshold {
@@ -626,9 +799,12 @@ impl SignatureManager {
             );
             return;
         }
+        let mut in_flight = self.in_flight();
+        let mut free_slots =
+            max_concurrent_signatures.saturating_sub(in_flight.values().sum::<usize>());
         let mut failed_presigs = Vec::new();
         while let Some(mut presignature) = {
-            if self.failed.is_empty() && my_requests.is_empty() {
+            if self.failed.is_empty() && (my_requests.is_empty() || free_slots == 0) {
                 None
             } else {
                 presignature_manager.take_mine().await
@@ -676,10 +852,15 @@ impl SignatureManager {
                 }
             }
 
-            let Some(my_request) = my_requests.pop_front() else {
+            let Some(my_request) = (free_slots > 0)
+                .then(|| my_requests.pop_next(&in_flight))
+                .flatten()
+            else {
                 failed_presigs.push(presignature);
                 continue;
             };
+            free_slots -= 1;
+            *in_flight.entry(my_request.requester.clone()).or_default() += 1;
 
             if let Err((presignature, InitializationError::BadParameters(err))) = self.generate(
                 &sig_participants,
@@ -689,6 +870,7 @@ impl SignatureManager {
                 my_request.epsilon,
                 my_request.entropy,
                 my_request.time_added,
+                my_request.requester,
                 cfg,
             ) {
                 failed_presigs.push(presignature);
@@ -704,65 +886,161 @@ impl SignatureManager {
         }
     }
 
-    pub async fn publish<T: SignerExt>(
-        &mut self,
-        rpc_client: &near_fetch::Client,
-        signer: &T,
-        mpc_contract_id: &AccountId,
-    ) {
-        let mut to_retry: Vec<ToPublish> = Vec::new();
-
-        for mut to_publish in self.signatures.drain(..) {
+    /// Moves the newly generated signatures into the durable publish queue, along with the ones
+    /// queued before this manager was created, e.g. before the node restarted.
+    async fn queue_signatures(&mut self) {
+        for to_publish in self.signatures.drain(..) {
             let ToPublish {
                 request_id,
                 request,
                 time_added,
                 signature,
-                ..
-            } = &to_publish;
+                participants,
+            } = to_publish;
             let expected_public_key = derive_key(self.public_key, request.epsilon.scalar);
             // We do this here, rather than on the client side, so we can use the ecrecover system function on NEAR to validate our signature
-            let Ok(signature) = into_eth_sig(
+            let Ok(response) = into_eth_sig(
                 &expected_public_key,
                 &signature.big_r,
                 &signature.s,
                 request.payload_hash.scalar,
             ) else {
-                tracing::error!(request_id = ?CryptoHash(*request_id), "Failed to generate a recovery ID");
+                tracing::error!(request_id = ?CryptoHash(request_id), "Failed to generate a recovery ID");
                 continue;
             };
-            let response = match rpc_client
+            let pending = PendingPublish {
+                request_id,
+                request,
+                response,
+                generated_at: Utc::now().timestamp() as u64,
+                attempts: 0,
+                participants: participants.into_iter().map(u32::from).collect(),
+            };
+            // The signature still gets published if it could not be stored, it is only lost if
+            // the node also restarts before that.
+            if let Err(err) = self.publish_storage.insert(&pending).await {
+                tracing::warn!(request_id = ?CryptoHash(request_id), ?err, "failed to store the signature to publish");
+            }
+            self.publish_requested_at.insert(request_id, time_added);
+            self.to_publish.push_back(pending);
+        }
+
+        if !self.publish_loaded {
+            match self.publish_storage.load().await {
+                Ok(stored) => {
+                    for pending in stored {
+                        if !self
+                            .to_publish
+                            .iter()
+                            .any(|queued| queued.request_id == pending.request_id)
+                        {
+                            tracing::info!(request_id = ?CryptoHash(pending.request_id), "resuming publishing of a stored signature");
+                            self.to_publish.push_back(pending);
+                        }
+                    }
+                    self.publish_loaded = true;
+                }
+                Err(err) => {
+                    tracing::warn!(?err, "failed to load the stored signatures to publish");
+                }
+            }
+        }
+    }
+
+    /// Publishes the queued signatures through `respond`, one transaction at a time so that the
+    /// nonces of the node's access key stay in order. A signature that could not be published,
+    /// e.g. because the RPC is down, stays queued and is retried with the backoff of `retry`,
+    /// until it ran out of retries or the contract rejected it.
+    pub async fn publish(
+        &mut self,
+        rpc_client: &near_fetch::Client,
+        signer: &AccountSigner,
+        mpc_contract_id: &AccountId,
+        retry: &BackoffConfig,
+    ) {
+        self.queue_signatures().await;
+
+        let mut to_retry = VecDeque::new();
+        while let Some(mut pending) = self.to_publish.pop_front() {
+            let request_id = pending.request_id;
+            if self
+                .publish_backoff
+                .get(&request_id)
+                .is_some_and(|retry_at| Instant::now() < *retry_at)
+            {
+                to_retry.push_back(pending);
+                continue;
+            }
+            if crate::failpoint::eval(
+                crate::failpoint::SIGNATURE_PUBLISH,
+                Some(&CryptoHash(request_id).to_string()),
+            )
+            .await
+            .is_some()
+            {
+                tracing::warn!(request_id = ?CryptoHash(request_id), "skipping publishing the signature");
+                self.forget_published(&request_id).await;
+                continue;
+            }
+            let PendingPublish {
+                request,
+                response: signature,
+                participants,
+                ..
+            } = &pending;
+            // The contract credits the signature to the participants that produced it.
+            let participants = (!participants.is_empty()).then_some(participants);
+            let outcome = rpc_client
                 .call(signer, mpc_contract_id, "respond")
                 .args_json(serde_json::json!({
                     "request": request,
                     "response": signature,
+                    "participants": participants,
                 }))
                 .max_gas()
                 .retry_exponential(10, 5)
                 .transact()
-                .await
-            {
+                .instrument(tracing::info_span!(
+                    parent: &sign_request_span(request_id),
+                    "publish_signature"
+                ))
+                .await;
+            let response = match signer.check_signed(outcome) {
                 Ok(response) => response,
                 Err(err) => {
-                    tracing::error!(request_id = ?CryptoHash(*request_id), request = ?request, error = ?err, "Failed to publish the signature");
+                    tracing::error!(request_id = ?CryptoHash(request_id), request = ?request, attempts = pending.attempts, error = ?err, "Failed to publish the signature");
                     crate::metrics::SIGNATURE_PUBLISH_FAILURES
                         .with_label_values(&[self.my_account_id.as_str()])
                         .inc();
-                    // Push the response to the back of the queue if it hasn't been retried the max number of times
-                    if to_publish.retry_count < MAX_RETRY {
-                        to_publish.retry_count += 1;
-                        to_retry.push(to_publish);
+                    pending.attempts = pending.attempts.saturating_add(1);
+                    // Every attempt after the first one that failed is a retry.
+                    let retries = u32::from(pending.attempts) - 1;
+                    if retries < retry.max_retries {
+                        let backoff = Duration::from_millis(retry.delay(retries));
+                        self.publish_backoff
+                            .insert(request_id, Instant::now() + backoff);
+                        if let Err(err) = self.publish_storage.insert(&pending).await {
+                            tracing::warn!(request_id = ?CryptoHash(request_id), ?err, "failed to store the signature to publish");
+                        }
+                        to_retry.push_back(pending);
+                    } else {
+                        tracing::error!(request_id = ?CryptoHash(request_id), "giving up on publishing the signature");
+                        self.forget_published(&request_id).await;
                     }
                     continue;
                 }
             };
 
+            // Either way the signature is done with: a rejection by the contract, e.g. because
+            // the request already timed out, does not go away by retrying.
+            let time_added = self.publish_requested_at.get(&request_id).copied();
+            self.forget_published(&request_id).await;
             match response.json() {
                 Ok(()) => {
-                    tracing::info!(request_id = ?CryptoHash(*request_id), request = ?request, bi_r = signature.big_r.affine_point.to_base58(), s = ?signature.s, "published signature sucessfully")
+                    tracing::info!(request_id = ?CryptoHash(request_id), request = ?request, bi_r = signature.big_r.affine_point.to_base58(), s = ?signature.s, "published signature sucessfully")
                 }
                 Err(err) => {
-                    tracing::error!(request_id = ?CryptoHash(*request_id), bi_r = signature.big_r.affine_point.to_base58(), s = ?signature.s, error = ?err, "smart contract threw error");
+                    tracing::error!(request_id = ?CryptoHash(request_id), bi_r = signature.big_r.affine_point.to_base58(), s = ?signature.s, error = ?err, "smart contract threw error");
                     crate::metrics::SIGNATURE_PUBLISH_RESPONSE_ERRORS
                         .with_label_values(&[self.my_account_id.as_str()])
                         .inc();
@@ -770,20 +1048,35 @@ impl SignatureManager {
                 }
             };
 
+            let _ = self.events.send(SignEvent::SignaturePublished {
+                request_id: CryptoHash(request_id).to_string(),
+                signature: pending.response,
+            });
             crate::metrics::NUM_SIGN_SUCCESS
                 .with_label_values(&[self.my_account_id.as_str()])
                 .inc();
-            crate::metrics::SIGN_LATENCY
-                .with_label_values(&[self.my_account_id.as_str()])
-                .observe(time_added.elapsed().as_secs_f64());
-            if time_added.elapsed().as_secs() <= 30 {
-                crate::metrics::NUM_SIGN_SUCCESS_30S
+            if let Some(time_added) = time_added {
+                crate::metrics::SIGN_LATENCY
                     .with_label_values(&[self.my_account_id.as_str()])
-                    .inc();
+                    .observe(time_added.elapsed().as_secs_f64());
+                if time_added.elapsed().as_secs() <= 30 {
+                    crate::metrics::NUM_SIGN_SUCCESS_30S
+                        .with_label_values(&[self.my_account_id.as_str()])
+                        .inc();
+                }
             }
         }
         // Put the failed requests at the back of the queue
-        self.signatures.extend(to_retry);
+        self.to_publish = to_retry;
+    }
+
+    /// Drops a signature that is done with from the publish queue.
+    async fn forget_published(&mut self, request_id: &[u8; 32]) {
+        self.publish_backoff.remove(request_id);
+        self.publish_requested_at.remove(request_id);
+        if let Err(err) = self.publish_storage.remove(request_id).await {
+            tracing::warn!(request_id = ?CryptoHash(*request_id), ?err, "failed to remove the published signature from storage");
+        }
     }
 
     /// Garbage collect all the completed signatures.
@@ -809,3 +1102,81 @@ impl SignatureManager {
         matches!(entry, Entry::Occupied(_))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: u8, deposit: u128, requester: &str) -> SignRequest {
+        SignRequest {
+            request_id: [id; 32],
+            request: ContractSignRequest {
+                payload: Scalar::ONE,
+                path: "test".to_string(),
+                key_version: 0,
+            },
+            epsilon: Scalar::ONE,
+            entropy: [0; 32],
+            deposit,
+            requester: requester.parse().unwrap(),
+            time_added: Instant::now(),
+        }
+    }
+
+    fn drain(requests: &mut ParticipantRequests) -> Vec<u8> {
+        std::iter::from_fn(|| requests.pop_next(&HashMap::new()))
+            .map(|request| request.request_id[0])
+            .collect()
+    }
+
+    #[test]
+    fn test_request_ordering() {
+        let deposits = [(0, 1), (1, 5), (2, 1), (3, 10), (4, 5)];
+
+        let mut requests = ParticipantRequests::default();
+        for (id, deposit) in deposits {
+            requests.insert(
+                request(id, deposit, "alice.near"),
+                SignRequestOrdering::Fifo,
+            );
+        }
+        assert_eq!(drain(&mut requests), vec![0, 1, 2, 3, 4]);
+
+        for (id, deposit) in deposits {
+            requests.insert(
+                request(id, deposit, "alice.near"),
+                SignRequestOrdering::Deposit,
+            );
+        }
+        assert_eq!(drain(&mut requests), vec![3, 1, 4, 0, 2]);
+        assert!(requests.is_empty());
+    }
+
+    #[test]
+    fn test_requester_fairness() {
+        let mut requests = ParticipantRequests::default();
+        for id in 0..10 {
+            requests.insert(request(id, 1, "flood.near"), SignRequestOrdering::Fifo);
+        }
+        requests.insert(request(10, 1, "alice.near"), SignRequestOrdering::Fifo);
+        requests.insert(request(11, 1, "bob.near"), SignRequestOrdering::Fifo);
+
+        // The requesters take turns, even though the flood came in first.
+        let mut in_flight = HashMap::new();
+        let mut order = Vec::new();
+        for _ in 0..4 {
+            let next = requests.pop_next(&in_flight).unwrap();
+            *in_flight.entry(next.requester).or_insert(0) += 1;
+            order.push(next.request_id[0]);
+        }
+        assert_eq!(order, vec![0, 10, 11, 1]);
+        assert_eq!(requests.len(), 8);
+
+        // A requester with signatures in flight waits for the others.
+        let mut requests = ParticipantRequests::default();
+        requests.insert(request(0, 1, "flood.near"), SignRequestOrdering::Fifo);
+        requests.insert(request(1, 1, "alice.near"), SignRequestOrdering::Fifo);
+        let in_flight = HashMap::from([("flood.near".parse().unwrap(), 4)]);
+        assert_eq!(requests.pop_next(&in_flight).unwrap().request_id[0], 1);
+    }
+}
@@ -6,7 +6,7 @@ use crate::storage::triple_storage::TripleRedisStorage;
 use crate::types::TripleProtocol;
 use crate::util::AffinePointExt;
 
-use cait_sith::protocol::{Action, InitializationError, Participant, ProtocolError};
+use cait_sith::protocol::{Action, InitializationError, MessageData, Participant, ProtocolError};
 use cait_sith::triples::{TripleGenerationOutput, TriplePub, TripleShare};
 use chrono::Utc;
 use highway::{HighwayHash, HighwayHasher};
@@ -17,9 +17,12 @@ use serde::{Deserialize, Serialize};
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use near_account_id::AccountId;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 
 /// Unique number used to identify a specific ongoing triple generation protocol.
 /// Without `TripleId` it would be unclear where to route incoming cait-sith triple generation
@@ -76,6 +79,65 @@ impl TripleGenerator {
     }
 }
 
+/// Where a triple protocol ended up after being poked until it could not progress anymore.
+enum PokeOutcome {
+    /// The protocol is waiting on messages from other participants.
+    Wait,
+    Failed(ProtocolError),
+    Completed(TripleGenerationOutput<Secp256k1>),
+}
+
+impl TripleGenerator {
+    /// Pokes the protocol until it has to wait, fails or completes, collecting the messages
+    /// it wants to send along the way.
+    fn poke_until_blocked(&mut self) -> (Vec<(Participant, MessageData)>, PokeOutcome) {
+        let mut sends = Vec::new();
+        loop {
+            let action = match self.poke() {
+                Ok(action) => action,
+                Err(e) => return (sends, PokeOutcome::Failed(e)),
+            };
+            match action {
+                Action::Wait => {
+                    tracing::debug!("triple: waiting");
+                    return (sends, PokeOutcome::Wait);
+                }
+                Action::SendMany(data) => {
+                    for p in &self.participants {
+                        sends.push((*p, data.clone()));
+                    }
+                }
+                Action::SendPrivate(p, data) => sends.push((p, data)),
+                Action::Return(output) => return (sends, PokeOutcome::Completed(output)),
+            }
+        }
+    }
+}
+
+type Poked = (
+    TripleId,
+    TripleGenerator,
+    Vec<(Participant, MessageData)>,
+    PokeOutcome,
+);
+
+/// Pokes `generators` on `pool`. Every generator can be picked up by whichever thread of the
+/// pool is free, so the work is spread evenly no matter how long the individual protocols take.
+fn poke_in_parallel(pool: &ThreadPool, generators: Vec<(TripleId, TripleGenerator)>) -> Vec<Poked> {
+    let span = tracing::Span::current();
+    pool.install(|| {
+        generators
+            .into_par_iter()
+            .with_max_len(1)
+            .map(|(id, mut generator)| {
+                let _span = span.enter();
+                let (sends, outcome) = generator.poke_until_blocked();
+                (id, generator, sends, outcome)
+            })
+            .collect()
+    })
+}
+
 /// Abstracts how triples are generated by providing a way to request a new triple that will be
 /// complete some time in the future and a way to take an already generated triple.
 pub struct TripleManager {
@@ -104,6 +166,9 @@ pub struct TripleManager {
     pub threshold: usize,
     pub epoch: u64,
     pub my_account_id: AccountId,
+
+    /// Threads that poke the ongoing protocols, see [`TripleManager::poke`].
+    poke_pool: Arc<ThreadPool>,
 }
 
 impl fmt::Debug for TripleManager {
@@ -123,13 +188,21 @@ impl fmt::Debug for TripleManager {
 }
 
 impl TripleManager {
+    /// `poke_threads` is the number of threads poking the ongoing protocols, one per available
+    /// core when not set.
     pub fn new(
         me: Participant,
         threshold: usize,
         epoch: u64,
         my_account_id: &AccountId,
         storage: &TripleRedisStorage,
+        poke_threads: Option<usize>,
     ) -> Self {
+        let poke_pool = ThreadPoolBuilder::new()
+            .num_threads(poke_threads.unwrap_or(0))
+            .thread_name(|i| format!("triple-poke-{i}"))
+            .build()
+            .expect("unable to start the triple poke threads");
         Self {
             generators: HashMap::new(),
             queued: VecDeque::new(),
@@ -141,6 +214,7 @@ impl TripleManager {
             epoch,
             triple_storage: storage.clone(),
             my_account_id: my_account_id.clone(),
+            poke_pool: Arc::new(poke_pool),
         }
     }
 
@@ -337,7 +411,7 @@ impl TripleManager {
         participants: &Participants,
         timeout: u64,
     ) -> Result<(), InitializationError> {
-        let id = rand::random();
+        let id = crate::rng::triple_id();
 
         // Check if the `id` is already in the system. Error out and have the next cycle try again.
         if self.generators.contains_key(&id)
@@ -448,6 +522,10 @@ impl TripleManager {
     /// Pokes all of the ongoing generation protocols and returns a vector of
     /// messages to be sent to the respective participant.
     ///
+    /// The protocols are poked in parallel on the poke threads of the manager, which pick up the
+    /// next protocol once they are done with the previous one, so that a few slow protocols do
+    /// not hold up the rest. The runtime is not blocked while they are at it.
+    ///
     /// An empty vector means we cannot progress until we receive a new message.
     pub async fn poke(&mut self, cfg: &ProtocolConfig) -> Vec<(Participant, TripleMessage)> {
         // Add more protocols to the ongoing pool if there is space.
@@ -458,130 +536,119 @@ impl TripleManager {
             }
         }
 
+        // Only the ongoing protocols get poked, the rest are retained for the next time they
+        // are in the ongoing pool.
+        let ongoing = self
+            .ongoing
+            .iter()
+            .filter_map(|id| self.generators.remove_entry(id))
+            .collect::<Vec<_>>();
+        let pool = self.poke_pool.clone();
+        let span = tracing::Span::current();
+        let poked = tokio::task::spawn_blocking(move || {
+            let _span = span.enter();
+            poke_in_parallel(&pool, ongoing)
+        })
+        .await
+        .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()));
+
         let mut messages = Vec::new();
         let mut errors = Vec::new();
         let mut new_triples = Vec::new();
         let mut new_mine_triples = Vec::new();
-        self.generators.retain(|id, generator| {
-            if !self.ongoing.contains(id) {
-                // If the protocol is not ongoing, we should retain it for the next time
-                // it is in the ongoing pool.
-                return true;
+        for (id, generator, sends, outcome) in poked {
+            for (p, data) in sends {
+                messages.push((
+                    p,
+                    TripleMessage {
+                        id,
+                        epoch: self.epoch,
+                        from: self.me,
+                        data,
+                        timestamp: Utc::now().timestamp() as u64,
+                        participants: Some(generator.participants.clone()),
+                    },
+                ));
             }
 
-            loop {
-                let action = match generator.poke() {
-                    Ok(action) => action,
-                    Err(e) => {
-                        errors.push(e);
-                        crate::metrics::TRIPLE_GENERATOR_FAILURES
+            match outcome {
+                PokeOutcome::Wait => {
+                    // Retain protocol until we are finished
+                    self.generators.insert(id, generator);
+                }
+                PokeOutcome::Failed(e) => {
+                    errors.push(e);
+                    crate::metrics::TRIPLE_GENERATOR_FAILURES
+                        .with_label_values(&[self.my_account_id.as_str()])
+                        .inc();
+                    self.gc.insert(id, Instant::now());
+                    self.ongoing.remove(&id);
+                    self.introduced.remove(&id);
+                    tracing::warn!(
+                        elapsed = ?generator.timestamp.unwrap().elapsed(),
+                        "added {id} to failed triples"
+                    );
+                }
+                PokeOutcome::Completed(output) => {
+                    tracing::info!(
+                        id,
+                        me = ?self.me,
+                        elapsed = ?generator.timestamp.unwrap().elapsed(),
+                        big_a = ?output.1.big_a.to_base58(),
+                        big_b = ?output.1.big_b.to_base58(),
+                        big_c = ?output.1.big_c.to_base58(),
+                        "completed triple generation"
+                    );
+
+                    if let Some(start_time) = generator.timestamp {
+                        crate::metrics::TRIPLE_LATENCY
                             .with_label_values(&[self.my_account_id.as_str()])
-                            .inc();
-                        self.gc.insert(*id, Instant::now());
-                        self.ongoing.remove(id);
-                        self.introduced.remove(id);
-                        tracing::warn!(
-                            elapsed = ?generator.timestamp.unwrap().elapsed(),
-                            "added {id} to failed triples"
-                        );
-                        break false;
+                            .observe(start_time.elapsed().as_secs_f64());
                     }
-                };
 
-                match action {
-                    Action::Wait => {
-                        tracing::debug!("triple: waiting");
-                        // Retain protocol until we are finished
-                        break true;
-                    }
-                    Action::SendMany(data) => {
-                        for p in &generator.participants {
-                            messages.push((
-                                *p,
-                                TripleMessage {
-                                    id: *id,
-                                    epoch: self.epoch,
-                                    from: self.me,
-                                    data: data.clone(),
-                                    timestamp: Utc::now().timestamp() as u64,
-                                },
-                            ))
-                        }
-                    }
-                    Action::SendPrivate(p, data) => messages.push((
-                        p,
-                        TripleMessage {
-                            id: *id,
-                            epoch: self.epoch,
-                            from: self.me,
-                            data,
-                            timestamp: Utc::now().timestamp() as u64,
-                        },
-                    )),
-                    Action::Return(output) => {
-                        tracing::info!(
-                            id,
-                            me = ?self.me,
-                            elapsed = ?generator.timestamp.unwrap().elapsed(),
-                            big_a = ?output.1.big_a.to_base58(),
-                            big_b = ?output.1.big_b.to_base58(),
-                            big_c = ?output.1.big_c.to_base58(),
-                            "completed triple generation"
-                        );
-
-                        if let Some(start_time) = generator.timestamp {
-                            crate::metrics::TRIPLE_LATENCY
-                                .with_label_values(&[self.my_account_id.as_str()])
-                                .observe(start_time.elapsed().as_secs_f64());
-                        }
-
-                        crate::metrics::NUM_TOTAL_HISTORICAL_TRIPLE_GENERATORS_SUCCESS
+                    crate::metrics::NUM_TOTAL_HISTORICAL_TRIPLE_GENERATORS_SUCCESS
+                        .with_label_values(&[self.my_account_id.as_str()])
+                        .inc();
+
+                    let triple = Triple {
+                        id,
+                        share: output.0,
+                        public: output.1,
+                    };
+
+                    // After creation the triple is assigned to a random node, which is NOT necessarily the one that initiated it's creation
+                    let triple_is_mine = {
+                        // This is an entirely unpredictable value to all participants because it's a combination of big_c_i
+                        // It is the same value across all participants
+                        let big_c = triple.public.big_c;
+
+                        // We turn this into a u64 in a way not biased to the structure of the byte serialisation so we hash it
+                        // We use Highway Hash because the DefaultHasher doesn't guarantee a consistent output across versions
+                        let entropy = HighwayHasher::default().hash64(&big_c.to_bytes()) as usize;
+
+                        let num_participants = generator.participants.len();
+                        // This has a *tiny* bias towards lower indexed participants, they're up to (1 + num_participants / u64::MAX)^2 times more likely to be selected
+                        // This is acceptably small that it will likely never result in a biased selection happening
+                        let triple_owner = generator.participants[entropy % num_participants];
+
+                        triple_owner == self.me
+                    };
+
+                    if triple_is_mine {
+                        new_mine_triples.push(triple);
+                        crate::metrics::NUM_TOTAL_HISTORICAL_TRIPLE_GENERATIONS_MINE_SUCCESS
                             .with_label_values(&[self.my_account_id.as_str()])
                             .inc();
-
-                        let triple = Triple {
-                            id: *id,
-                            share: output.0,
-                            public: output.1,
-                        };
-
-                        // After creation the triple is assigned to a random node, which is NOT necessarily the one that initiated it's creation
-                        let triple_is_mine = {
-                            // This is an entirely unpredictable value to all participants because it's a combination of big_c_i
-                            // It is the same value across all participants
-                            let big_c = triple.public.big_c;
-
-                            // We turn this into a u64 in a way not biased to the structure of the byte serialisation so we hash it
-                            // We use Highway Hash because the DefaultHasher doesn't guarantee a consistent output across versions
-                            let entropy =
-                                HighwayHasher::default().hash64(&big_c.to_bytes()) as usize;
-
-                            let num_participants = generator.participants.len();
-                            // This has a *tiny* bias towards lower indexed participants, they're up to (1 + num_participants / u64::MAX)^2 times more likely to be selected
-                            // This is acceptably small that it will likely never result in a biased selection happening
-                            let triple_owner = generator.participants[entropy % num_participants];
-
-                            triple_owner == self.me
-                        };
-
-                        if triple_is_mine {
-                            new_mine_triples.push(triple.clone());
-                            crate::metrics::NUM_TOTAL_HISTORICAL_TRIPLE_GENERATIONS_MINE_SUCCESS
-                                .with_label_values(&[self.my_account_id.as_str()])
-                                .inc();
-                        } else {
-                            new_triples.push(triple.clone());
-                        }
-
-                        // Protocol done, remove it from the ongoing pool.
-                        self.ongoing.remove(id);
-                        self.introduced.remove(id);
-                        // Do not retain the protocol
-                        break false;
+                    } else {
+                        new_triples.push(triple);
                     }
+
+                    // Protocol done, remove it from the ongoing pool.
+                    self.ongoing.remove(&id);
+                    self.introduced.remove(&id);
                 }
             }
-        });
+        }
 
         for triple in new_triples {
             self.insert(triple).await;
@@ -598,3 +665,58 @@ impl TripleManager {
         messages
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poke_in_parallel() {
+        let participants = (0..3u32).map(Participant::from).collect::<Vec<_>>();
+        let mut generators = participants
+            .iter()
+            .enumerate()
+            .map(|(id, me)| {
+                let protocol =
+                    cait_sith::triples::generate_triple::<Secp256k1>(&participants, *me, 2)
+                        .unwrap();
+                let id = id as TripleId;
+                let generator =
+                    TripleGenerator::new(id, participants.clone(), Box::new(protocol), 60_000);
+                (id, generator)
+            })
+            .collect::<Vec<_>>();
+
+        let pool = ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+
+        // Route the messages between the generators of every participant until all complete.
+        let mut completed = Vec::new();
+        while !generators.is_empty() {
+            let mut waiting = HashMap::new();
+            let mut sent = Vec::new();
+            for (id, generator, sends, outcome) in poke_in_parallel(&pool, generators) {
+                let from = participants[id as usize];
+                sent.extend(sends.into_iter().map(|(to, data)| (from, to, data)));
+                match outcome {
+                    PokeOutcome::Wait => {
+                        waiting.insert(from, (id, generator));
+                    }
+                    PokeOutcome::Completed(output) => completed.push(output),
+                    PokeOutcome::Failed(err) => panic!("triple generation failed: {err:?}"),
+                }
+            }
+            for (from, to, data) in sent {
+                if let Some((_, generator)) = waiting.get_mut(&to).filter(|_| from != to) {
+                    generator.protocol.message(from, data);
+                }
+            }
+            generators = waiting.into_values().collect();
+        }
+
+        assert_eq!(completed.len(), participants.len());
+        let public = &completed[0].1;
+        assert!(completed
+            .iter()
+            .all(|(_, other)| other.big_c == public.big_c));
+    }
+}
@@ -0,0 +1,646 @@
+//! QUIC transport for node to node messages. The same HPKE encrypted messages as with the
+//! other transports are sent as a JSON batch on their own stream, and every node serves QUIC
+//! over UDP on the same port number as the web API.
+//!
+//! A single connection is kept open to every node and multiplexes all the batches sent to it,
+//! so a slow batch does not hold back the others like it does over a TCP connection. When a
+//! connection has to be reopened, the batch is sent as 0-RTT data along with the handshake.
+//!
+//! By default the certificate of every node is self-signed and not verified by the other nodes:
+//! the messages are already encrypted for their recipient and signed by their sender, so TLS
+//! only has to provide the transport. The same holds for 0-RTT data, which can be replayed by
+//! anyone on the path, as replayed messages get dropped by the receiver.
+//!
+//! With mutual TLS enabled through `--mesh-mtls`, every node presents a certificate whose key is
+//! signed by its message signing key, the `sign_pk` it registered in the contract. Both sides of
+//! a connection only accept the certificate of a current participant, so that nodes which are
+//! not part of the network can not even open a stream to deliver messages on.
+//!
+//! The QUIC mesh is then the authoritative transport: the `POST /msg` endpoint and the gRPC mesh
+//! service refuse messages, and a peer only counts as active once an empty batch got delivered
+//! to it over QUIC. The `/state` endpoint stays open over plain HTTP, since peers only read it
+//! for the progress of the others and it decides nothing on its own.
+
+use crate::hsm::{CipherKey, MessageSigner};
+use crate::http_client::SendError;
+use crate::protocol::contract::primitives::Participants;
+use crate::protocol::message::{ReplayWindow, SignedMessage};
+use crate::protocol::{CryptographicError, MpcMessage, NodeState, ProtocolState};
+use cait_sith::protocol::Participant;
+use mpc_keys::hpke;
+use near_primitives::types::AccountId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{mpsc, Mutex, RwLock};
+
+/// Name the certificate of every node is issued for, since nodes are only known by their URL.
+const SERVER_NAME: &str = "mpc-node";
+const ALPN_PROTOCOL: &[u8] = b"mpc-mesh/1";
+/// Largest batch of messages accepted on a single stream. Batches get partitioned to 256kb
+/// before being sent, so this leaves plenty of room.
+const MAX_BATCH_SIZE: usize = 4 * 1024 * 1024;
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Extension of the certificate that binds it to a participant. The OID is not registered
+/// anywhere, only other nodes ever read it.
+const IDENTITY_OID: &[u64] = &[1, 3, 6, 1, 4, 1, 62251, 1, 1];
+/// Prefix of the payload signed into a certificate, so that the signature can not be passed
+/// off as the one of a protocol message.
+const IDENTITY_DOMAIN: &[u8] = b"mpc-mesh-cert:";
+
+static MESH_TLS: once_cell::sync::OnceCell<MeshTls> = once_cell::sync::OnceCell::new();
+
+/// Enables mutual TLS on the QUIC mesh of this node. Has to be called before the mesh is
+/// served or any message is sent over it.
+pub fn init_mtls(account_id: &AccountId, sign_sk: &MessageSigner) -> anyhow::Result<()> {
+    if MESH_TLS.set(MeshTls::new(account_id, sign_sk)?).is_err() {
+        anyhow::bail!("mesh mtls was already initialized");
+    }
+    tracing::info!(%account_id, "mesh mtls enabled");
+    Ok(())
+}
+
+/// Whether the QUIC mesh authenticates its peers, in which case it is the only transport that
+/// messages are accepted on.
+pub fn mtls_enabled() -> bool {
+    MESH_TLS.get().is_some()
+}
+
+/// Trusts the certificates of the participants of `contract_state`, and of no one else. Both
+/// the current and the next participants are trusted while resharing.
+pub fn trust_participants(contract_state: &ProtocolState) {
+    let Some(tls) = MESH_TLS.get() else {
+        return;
+    };
+    match contract_state {
+        ProtocolState::Initializing(state) => {
+            tls.trust(&Participants::from(state.candidates.clone()))
+        }
+        ProtocolState::Running(state) => tls.trust(&state.participants),
+        ProtocolState::Resharing(state) => {
+            let mut participants = state.old_participants.clone();
+            for (participant, info) in state.new_participants.iter() {
+                participants.insert(participant, info.clone());
+            }
+            tls.trust(&participants)
+        }
+    }
+}
+
+/// Identity of a participant carried in the extension of its certificate.
+#[derive(Serialize, Deserialize)]
+struct CertIdentity {
+    account_id: AccountId,
+    /// Signature of the sign key of the participant over its account and the certificate key.
+    signature: near_crypto::Signature,
+}
+
+impl CertIdentity {
+    fn payload(account_id: &AccountId, public_key: &[u8]) -> Vec<u8> {
+        // Account ids never contain a nul byte, so it separates the two unambiguously.
+        [IDENTITY_DOMAIN, account_id.as_bytes(), &[0], public_key].concat()
+    }
+}
+
+/// Certificate of this node and the participants whose certificates it accepts.
+struct MeshTls {
+    cert_chain: Vec<rustls::Certificate>,
+    key: rustls::PrivateKey,
+    verifier: Arc<ParticipantVerifier>,
+}
+
+impl MeshTls {
+    /// Issues a certificate for a fresh key, signed into it by `sign_sk` of `account_id`.
+    fn new(account_id: &AccountId, sign_sk: &MessageSigner) -> anyhow::Result<Self> {
+        let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256)?;
+        let identity = CertIdentity {
+            account_id: account_id.clone(),
+            signature: sign_sk.sign(&CertIdentity::payload(
+                account_id,
+                key_pair.public_key_raw(),
+            ))?,
+        };
+
+        let mut params = rcgen::CertificateParams::new(vec![SERVER_NAME.to_string()]);
+        params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+        params
+            .custom_extensions
+            .push(rcgen::CustomExtension::from_oid_content(
+                IDENTITY_OID,
+                serde_json::to_vec(&identity)?,
+            ));
+        params.key_pair = Some(key_pair);
+        let cert = rcgen::Certificate::from_params(params)?;
+        Ok(Self {
+            cert_chain: vec![rustls::Certificate(cert.serialize_der()?)],
+            key: rustls::PrivateKey(cert.serialize_private_key_der()),
+            verifier: Arc::new(ParticipantVerifier::default()),
+        })
+    }
+
+    fn trust(&self, participants: &Participants) {
+        let trusted = participants
+            .iter()
+            .map(|(_, info)| (info.account_id.clone(), info.sign_pk.clone()))
+            .collect();
+        *self
+            .verifier
+            .trusted
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = trusted;
+    }
+}
+
+/// Accepts the certificates of the participants it trusts, on both sides of a connection.
+#[derive(Default)]
+struct ParticipantVerifier {
+    trusted: std::sync::RwLock<HashMap<AccountId, near_crypto::PublicKey>>,
+}
+
+impl ParticipantVerifier {
+    /// Returns the participant that `cert` was issued to, if it is trusted.
+    fn verify(&self, cert: &rustls::Certificate) -> Result<AccountId, rustls::Error> {
+        let (_, parsed) = x509_parser::parse_x509_certificate(&cert.0).map_err(|_| {
+            rustls::Error::InvalidCertificate(rustls::CertificateError::BadEncoding)
+        })?;
+        let identity = parsed
+            .extensions()
+            .iter()
+            .find(|ext| {
+                ext.oid
+                    .iter()
+                    .is_some_and(|arcs| arcs.eq(IDENTITY_OID.iter().copied()))
+            })
+            .and_then(|ext| serde_json::from_slice::<CertIdentity>(ext.value).ok())
+            .ok_or_else(|| {
+                rustls::Error::General("certificate does not identify a participant".to_string())
+            })?;
+
+        let trusted = self.trusted.read().unwrap_or_else(|err| err.into_inner());
+        let Some(sign_pk) = trusted.get(&identity.account_id) else {
+            return Err(rustls::Error::General(format!(
+                "{} is not a participant",
+                identity.account_id
+            )));
+        };
+        let payload = CertIdentity::payload(
+            &identity.account_id,
+            &parsed.public_key().subject_public_key.data,
+        );
+        if !identity.signature.verify(&payload, sign_pk) {
+            return Err(rustls::Error::General(format!(
+                "certificate of {} is not signed by its sign key",
+                identity.account_id
+            )));
+        }
+        Ok(identity.account_id)
+    }
+
+    /// Checks that the peer of `connection` is still trusted. Resumed sessions skip the
+    /// verification of certificates, and a connection outlives the participant set it was
+    /// opened with, so this is checked again for every stream.
+    fn verify_connection(&self, connection: &quinn::Connection) -> anyhow::Result<AccountId> {
+        let certs = connection
+            .peer_identity()
+            .and_then(|identity| identity.downcast::<Vec<rustls::Certificate>>().ok())
+            .ok_or_else(|| anyhow::anyhow!("peer did not present a certificate"))?;
+        let cert = certs
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("peer presented an empty certificate chain"))?;
+        Ok(self.verify(cert)?)
+    }
+}
+
+impl rustls::client::ServerCertVerifier for ParticipantVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        self.verify(end_entity)?;
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+impl rustls::server::ClientCertVerifier for ParticipantVerifier {
+    fn client_auth_root_subjects(&self) -> &[rustls::DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _now: SystemTime,
+    ) -> Result<rustls::server::ClientCertVerified, rustls::Error> {
+        self.verify(end_entity)?;
+        Ok(rustls::server::ClientCertVerified::assertion())
+    }
+}
+
+fn transport_config() -> anyhow::Result<quinn::TransportConfig> {
+    let mut transport = quinn::TransportConfig::default();
+    transport
+        .keep_alive_interval(Some(KEEP_ALIVE_INTERVAL))
+        .max_idle_timeout(Some(MAX_IDLE_TIMEOUT.try_into()?));
+    Ok(transport)
+}
+
+fn server_config(tls: Option<&MeshTls>) -> anyhow::Result<quinn::ServerConfig> {
+    let builder = rustls::ServerConfig::builder().with_safe_defaults();
+    let mut crypto = match tls {
+        Some(tls) => builder
+            .with_client_cert_verifier(tls.verifier.clone())
+            .with_single_cert(tls.cert_chain.clone(), tls.key.clone())?,
+        None => {
+            let cert = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])?;
+            builder.with_no_client_auth().with_single_cert(
+                vec![rustls::Certificate(cert.serialize_der()?)],
+                rustls::PrivateKey(cert.serialize_private_key_der()),
+            )?
+        }
+    };
+    crypto.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+    crypto.max_early_data_size = u32::MAX;
+
+    let mut config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    config.transport_config(Arc::new(transport_config()?));
+    Ok(config)
+}
+
+fn client_config(tls: Option<&MeshTls>) -> anyhow::Result<quinn::ClientConfig> {
+    let builder = rustls::ClientConfig::builder().with_safe_defaults();
+    let mut crypto = match tls {
+        Some(tls) => builder
+            .with_custom_certificate_verifier(tls.verifier.clone())
+            .with_client_auth_cert(tls.cert_chain.clone(), tls.key.clone())?,
+        None => builder
+            .with_custom_certificate_verifier(Arc::new(SkipServerVerification))
+            .with_no_client_auth(),
+    };
+    crypto.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+    crypto.enable_early_data = true;
+
+    let mut config = quinn::ClientConfig::new(Arc::new(crypto));
+    config.transport_config(Arc::new(transport_config()?));
+    Ok(config)
+}
+
+/// Accepts any certificate when mutual TLS is disabled, see the module docs for why this is fine.
+struct SkipServerVerification;
+
+impl rustls::client::ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+#[derive(Clone)]
+pub struct MeshService {
+    sender: mpsc::Sender<MpcMessage>,
+    protocol_state: Arc<RwLock<NodeState>>,
+    cipher_sk: CipherKey,
+    replay_window: Arc<std::sync::Mutex<ReplayWindow>>,
+}
+
+impl MeshService {
+    pub fn new(
+        sender: mpsc::Sender<MpcMessage>,
+        protocol_state: Arc<RwLock<NodeState>>,
+        cipher_sk: CipherKey,
+        replay_window: Arc<std::sync::Mutex<ReplayWindow>>,
+    ) -> Self {
+        Self {
+            sender,
+            protocol_state,
+            cipher_sk,
+            replay_window,
+        }
+    }
+
+    /// Serves the mesh over UDP on `port` until the endpoint gets closed.
+    pub async fn run(self, port: u16) -> anyhow::Result<()> {
+        let addr = SocketAddr::from(([0, 0, 0, 0], port));
+        let endpoint = quinn::Endpoint::server(server_config(MESH_TLS.get())?, addr)?;
+        tracing::info!(?addr, "starting quic server");
+        while let Some(connecting) = endpoint.accept().await {
+            let service = self.clone();
+            tokio::spawn(async move {
+                let remote = connecting.remote_address();
+                if let Err(err) = service.serve_connection(connecting).await {
+                    tracing::debug!(?remote, ?err, "quic connection closed");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn serve_connection(&self, connecting: quinn::Connecting) -> anyhow::Result<()> {
+        // 0-RTT streams can be accepted before the handshake completes.
+        let connection = match connecting.into_0rtt() {
+            Ok((connection, _)) => connection,
+            Err(connecting) => connecting.await?,
+        };
+        loop {
+            let (send, recv) = connection.accept_bi().await?;
+            if let Some(tls) = MESH_TLS.get() {
+                if let Err(err) = tls.verifier.verify_connection(&connection) {
+                    connection.close(0u32.into(), b"not a participant");
+                    return Err(err);
+                }
+            }
+            let service = self.clone();
+            tokio::spawn(async move {
+                if let Err(err) = service.serve_stream(send, recv).await {
+                    tracing::warn!(?err, "failed to serve a quic stream");
+                }
+            });
+        }
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn serve_stream(
+        &self,
+        mut send: quinn::SendStream,
+        mut recv: quinn::RecvStream,
+    ) -> anyhow::Result<()> {
+        let batch = recv.read_to_end(MAX_BATCH_SIZE).await?;
+        let encrypted: Vec<hpke::Ciphered> = serde_json::from_slice(&batch)?;
+        let mut received: u64 = 0;
+        for encrypted in encrypted {
+            let message: MpcMessage = match SignedMessage::decrypt(
+                &self.cipher_sk,
+                &self.protocol_state,
+                &self.replay_window,
+                encrypted,
+            )
+            .await
+            {
+                Ok(msg) => msg,
+                Err(CryptographicError::ReplayedMessage { from, seq }) => {
+                    tracing::warn!(?from, seq, "dropping a replayed message");
+                    received += 1;
+                    continue;
+                }
+                Err(err) => {
+                    tracing::error!(?err, "failed to decrypt or verify an encrypted message");
+                    break;
+                }
+            };
+
+            if let Err(err) = self.sender.send(message).await {
+                tracing::error!(?err, "failed to forward an encrypted protocol message");
+                break;
+            }
+            received += 1;
+        }
+
+        // The sender compares this to the size of its batch to know whether it went through.
+        send.write_all(&received.to_be_bytes()).await?;
+        send.finish().await?;
+        Ok(())
+    }
+}
+
+/// Client side of the QUIC mesh. The endpoint and the connections to every node are created
+/// lazily and reused for every subsequent message.
+#[derive(Clone, Default)]
+pub struct Client {
+    endpoint: Arc<Mutex<Option<quinn::Endpoint>>>,
+    connections: Arc<Mutex<HashMap<String, quinn::Connection>>>,
+}
+
+impl Client {
+    async fn endpoint(&self) -> Result<quinn::Endpoint, SendError> {
+        let mut endpoint = self.endpoint.lock().await;
+        if let Some(endpoint) = endpoint.as_ref() {
+            return Ok(endpoint.clone());
+        }
+        let mut created = quinn::Endpoint::client(SocketAddr::from(([0, 0, 0, 0], 0)))
+            .map_err(|err| SendError::QuicError(format!("failed to bind endpoint: {err}")))?;
+        created.set_default_client_config(
+            client_config(MESH_TLS.get()).map_err(|err| SendError::QuicError(err.to_string()))?,
+        );
+        *endpoint = Some(created.clone());
+        Ok(created)
+    }
+
+    /// Returns the open connection to `url`, or opens a new one. A new connection resumes the
+    /// previous session with the node through 0-RTT when possible, so the batch does not have
+    /// to wait for the handshake.
+    async fn connection(&self, url: &str) -> Result<quinn::Connection, SendError> {
+        if let Some(connection) = self.connections.lock().await.get(url) {
+            if connection.close_reason().is_none() {
+                return Ok(connection.clone());
+            }
+        }
+
+        let addr = resolve(url).await?;
+        let connecting = self.endpoint().await?.connect(addr, SERVER_NAME)?;
+        let connection = match connecting.into_0rtt() {
+            Ok((connection, _)) => connection,
+            Err(connecting) => connecting.await?,
+        };
+        self.connections
+            .lock()
+            .await
+            .insert(url.to_string(), connection.clone());
+        Ok(connection)
+    }
+
+    #[tracing::instrument(level = "debug", name = "message_request", skip_all, fields(?from, to = url))]
+    pub async fn send_encrypted(
+        &self,
+        from: Participant,
+        url: &str,
+        message: Vec<hpke::Ciphered>,
+        request_timeout: Duration,
+    ) -> Result<(), SendError> {
+        tracing::debug!(?from, to = %url, "making quic request: sending encrypted message");
+        let expected = message.len() as u64;
+        let batch = serde_json::to_vec(&message).map_err(SendError::DataConversionError)?;
+        let action = async {
+            let (mut send, mut recv) = self.connection(url).await?.open_bi().await?;
+            send.write_all(&batch).await?;
+            send.finish().await?;
+            let response = recv.read_to_end(std::mem::size_of::<u64>()).await?;
+            let received = <[u8; 8]>::try_from(response.as_slice())
+                .map(u64::from_be_bytes)
+                .map_err(|_| SendError::Unsuccessful(format!("{url} sent a malformed response")))?;
+            Ok::<_, SendError>(received)
+        };
+        let received = tokio::time::timeout(request_timeout, action)
+            .await
+            .map_err(|_| SendError::Timeout(format!("send encrypted from {from:?} to {url}")))?
+            .map_err(|err| {
+                tracing::warn!("failed to send a message to {url}: {err}");
+                err
+            })?;
+
+        if received != expected {
+            return Err(SendError::Unsuccessful(format!(
+                "{url} only received {received} out of {expected} messages"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// QUIC is served on the port of the web API, so the address comes straight from the URL.
+async fn resolve(url: &str) -> Result<SocketAddr, SendError> {
+    let parsed = url::Url::parse(url)
+        .map_err(|err| SendError::QuicError(format!("invalid url {url}: {err}")))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| SendError::QuicError(format!("url {url} has no host")))?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| SendError::QuicError(format!("url {url} has no port")))?;
+    tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|err| SendError::QuicError(format!("failed to resolve {url}: {err}")))?
+        .next()
+        .ok_or_else(|| SendError::QuicError(format!("{url} did not resolve to any address")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MeshTls;
+    use crate::hsm::MessageSigner;
+    use near_crypto::{KeyType, SecretKey};
+    use near_primitives::types::AccountId;
+    use std::net::SocketAddr;
+
+    async fn echo(endpoint: quinn::Endpoint) {
+        while let Some(connecting) = endpoint.accept().await {
+            tokio::spawn(async move {
+                let connection = match connecting.into_0rtt() {
+                    Ok((connection, _)) => connection,
+                    // Peers that fail the handshake are expected in some tests.
+                    Err(connecting) => match connecting.await {
+                        Ok(connection) => connection,
+                        Err(_) => return,
+                    },
+                };
+                while let Ok((mut send, mut recv)) = connection.accept_bi().await {
+                    let data = recv.read_to_end(1024).await.unwrap();
+                    send.write_all(&data).await.unwrap();
+                    send.finish().await.unwrap();
+                }
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quic_reconnect_with_0rtt() {
+        let server = quinn::Endpoint::server(
+            super::server_config(None).unwrap(),
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+        )
+        .unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(echo(server));
+
+        let mut client = quinn::Endpoint::client(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        client.set_default_client_config(super::client_config(None).unwrap());
+
+        for attempt in 0..2 {
+            let connecting = client.connect(addr, super::SERVER_NAME).unwrap();
+            let connection = match connecting.into_0rtt() {
+                Ok((connection, _)) => {
+                    assert_eq!(attempt, 1, "only a resumed session can use 0-RTT");
+                    connection
+                }
+                Err(connecting) => {
+                    assert_eq!(attempt, 0, "the resumed session did not use 0-RTT");
+                    connecting.await.unwrap()
+                }
+            };
+
+            let (mut send, mut recv) = connection.open_bi().await.unwrap();
+            send.write_all(b"hello mesh").await.unwrap();
+            send.finish().await.unwrap();
+            assert_eq!(recv.read_to_end(1024).await.unwrap(), b"hello mesh");
+            connection.close(0u32.into(), b"done");
+        }
+    }
+
+    fn identity(account_id: &str) -> (AccountId, SecretKey) {
+        (
+            account_id.parse().unwrap(),
+            SecretKey::from_random(KeyType::ED25519),
+        )
+    }
+
+    fn mesh_tls(identity: &(AccountId, SecretKey), trusted: &[&(AccountId, SecretKey)]) -> MeshTls {
+        let (account_id, sign_sk) = identity;
+        let tls = MeshTls::new(account_id, &MessageSigner::from(sign_sk.clone())).unwrap();
+        *tls.verifier.trusted.write().unwrap() = trusted
+            .iter()
+            .map(|(account_id, sign_sk)| (account_id.clone(), sign_sk.public_key()))
+            .collect();
+        tls
+    }
+
+    async fn round_trip(addr: SocketAddr, tls: &MeshTls) -> anyhow::Result<Vec<u8>> {
+        let mut client = quinn::Endpoint::client(SocketAddr::from(([127, 0, 0, 1], 0)))?;
+        client.set_default_client_config(super::client_config(Some(tls))?);
+        let connection = client.connect(addr, super::SERVER_NAME)?.await?;
+        let (mut send, mut recv) = connection.open_bi().await?;
+        send.write_all(b"hello mesh").await?;
+        send.finish().await?;
+        Ok(recv.read_to_end(1024).await?)
+    }
+
+    #[tokio::test]
+    async fn test_quic_mtls_only_accepts_participants() {
+        let server = identity("server.test");
+        let participant = identity("participant.test");
+        let outsider = identity("outsider.test");
+        // Claims the account of the participant, but its certificate is not signed by the sign
+        // key of the participant.
+        let impostor = (
+            participant.0.clone(),
+            SecretKey::from_random(KeyType::ED25519),
+        );
+        let cautious = identity("cautious.test");
+
+        let endpoint = quinn::Endpoint::server(
+            super::server_config(Some(&mesh_tls(&server, &[&participant, &cautious]))).unwrap(),
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+        )
+        .unwrap();
+        let addr = endpoint.local_addr().unwrap();
+        tokio::spawn(echo(endpoint));
+
+        assert_eq!(
+            round_trip(addr, &mesh_tls(&participant, &[&server]))
+                .await
+                .unwrap(),
+            b"hello mesh"
+        );
+        assert!(round_trip(addr, &mesh_tls(&outsider, &[&server]))
+            .await
+            .is_err());
+        assert!(round_trip(addr, &mesh_tls(&impostor, &[&server]))
+            .await
+            .is_err());
+        // The server is not a participant as far as this client knows.
+        assert!(round_trip(addr, &mesh_tls(&cautious, &[])).await.is_err());
+    }
+}
@@ -0,0 +1,45 @@
+//! Randomness of the ids of the triples the node introduces. With the `seeded-triple-ids`
+//! feature, these ids are drawn from the seed in `MPC_TEST_SEED` instead, so that a test run
+//! introduces the same triples when replayed with the seed it printed. Nothing else is seeded:
+//! the randomness within the cait-sith protocols, the timing of the messages and the order in
+//! which the nodes pick up work all stay as they are, so a replayed run is not deterministic.
+
+use near_account_id::AccountId;
+
+use crate::protocol::triple::TripleId;
+
+#[cfg(feature = "seeded-triple-ids")]
+static RNG: once_cell::sync::OnceCell<std::sync::Mutex<rand::rngs::StdRng>> =
+    once_cell::sync::OnceCell::new();
+
+/// Seeds the triple ids of this node from `MPC_TEST_SEED`, if set. The account id of the node
+/// is mixed into the seed so that nodes sharing a seed do not all make the same choices.
+#[cfg(feature = "seeded-triple-ids")]
+pub fn init(node_account_id: &AccountId) {
+    use rand::SeedableRng;
+
+    let Ok(seed) = std::env::var("MPC_TEST_SEED") else {
+        tracing::warn!("built with `seeded-triple-ids` but MPC_TEST_SEED is not set");
+        return;
+    };
+    let node_seed = near_primitives::hash::hash(format!("{seed}:{node_account_id}").as_bytes());
+    let rng = rand::rngs::StdRng::from_seed(node_seed.0);
+    if RNG.set(std::sync::Mutex::new(rng)).is_err() {
+        tracing::warn!("triple ids were already seeded");
+        return;
+    }
+    tracing::info!(%seed, "seeded triple ids");
+}
+
+#[cfg(not(feature = "seeded-triple-ids"))]
+pub fn init(_node_account_id: &AccountId) {}
+
+/// A random triple id, drawn from the seed of the node if there is one.
+pub fn triple_id() -> TripleId {
+    #[cfg(feature = "seeded-triple-ids")]
+    if let Some(rng) = RNG.get() {
+        use rand::Rng;
+        return rng.lock().unwrap().gen();
+    }
+    rand::random()
+}
@@ -1,8 +1,9 @@
+use crate::audit::{self, AuditEvent};
 use crate::config::{Config, ContractConfig};
+use crate::hsm::AccountSigner;
 use crate::protocol::ProtocolState;
 
 use near_account_id::AccountId;
-use near_crypto::InMemorySigner;
 
 use serde_json::json;
 
@@ -50,52 +51,221 @@ pub async fn fetch_mpc_config(
     })
 }
 
+#[tracing::instrument(level = "info", skip_all)]
 pub async fn vote_for_public_key(
     rpc_client: &near_fetch::Client,
-    signer: &InMemorySigner,
+    signer: &AccountSigner,
     mpc_contract_id: &AccountId,
     public_key: &near_crypto::PublicKey,
 ) -> anyhow::Result<bool> {
-    tracing::info!(%public_key, %signer.account_id, "voting for public key");
-    let result = rpc_client
+    tracing::info!(%public_key, account_id = %signer.account_id(), "voting for public key");
+    let args = json!({
+        "public_key": public_key
+    });
+    let outcome = rpc_client
         .call(signer, mpc_contract_id, "vote_pk")
-        .args_json(json!({
-            "public_key": public_key
-        }))
+        .args_json(&args)
         .max_gas()
         .retry_exponential(10, 5)
         .transact()
-        .await
+        .await;
+    let result = signer
+        .check_signed(outcome)
         .map_err(|e| {
             tracing::warn!(%e, "failed to vote for public key");
             e
         })?
         .json()?;
+    audit::record(
+        signer.account_id(),
+        AuditEvent::VoteCast {
+            method: "vote_pk".to_string(),
+            args,
+            result,
+        },
+    );
 
     Ok(result)
 }
 
+#[tracing::instrument(level = "info", skip_all, fields(epoch = epoch))]
 pub async fn vote_reshared(
     rpc_client: &near_fetch::Client,
-    signer: &InMemorySigner,
+    signer: &AccountSigner,
     mpc_contract_id: &AccountId,
     epoch: u64,
 ) -> anyhow::Result<bool> {
-    tracing::info!(%epoch, %signer.account_id, "voting for reshared");
-    let result = rpc_client
+    tracing::info!(%epoch, account_id = %signer.account_id(), "voting for reshared");
+    let args = json!({
+        "epoch": epoch
+    });
+    let outcome = rpc_client
         .call(signer, mpc_contract_id, "vote_reshared")
+        .args_json(&args)
+        .max_gas()
+        .retry_exponential(10, 5)
+        .transact()
+        .await;
+    let result = signer
+        .check_signed(outcome)
+        .map_err(|e| {
+            tracing::warn!(%e, "failed to vote for reshared");
+            e
+        })?
+        .json()?;
+    audit::record(
+        signer.account_id(),
+        AuditEvent::VoteCast {
+            method: "vote_reshared".to_string(),
+            args,
+            result,
+        },
+    );
+
+    Ok(result)
+}
+
+/// Reports to the contract that this node is up.
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn ping(
+    rpc_client: &near_fetch::Client,
+    signer: &AccountSigner,
+    mpc_contract_id: &AccountId,
+) -> anyhow::Result<()> {
+    let outcome = rpc_client
+        .call(signer, mpc_contract_id, "ping")
+        .transact()
+        .await;
+    signer
+        .check_signed(outcome)
+        .map_err(|e| {
+            tracing::warn!(%e, "failed to ping the contract");
+            e
+        })?
+        .json()?;
+
+    Ok(())
+}
+
+/// Fetches how recently each node of the protocol pinged the contract.
+pub async fn fetch_participant_heartbeats(
+    rpc_client: &near_fetch::Client,
+    mpc_contract_id: &AccountId,
+) -> anyhow::Result<Vec<mpc_contract::primitives::ParticipantHeartbeat>> {
+    let heartbeats = rpc_client
+        .view(mpc_contract_id, "participant_heartbeats")
+        .await
+        .map_err(|e| {
+            tracing::warn!(%e, "failed to fetch participant heartbeats");
+            e
+        })?
+        .json()?;
+
+    Ok(heartbeats)
+}
+
+/// Reports to the contract how many presignatures this node has available.
+#[tracing::instrument(level = "debug", skip_all, fields(presignatures))]
+pub async fn report_capacity(
+    rpc_client: &near_fetch::Client,
+    signer: &AccountSigner,
+    mpc_contract_id: &AccountId,
+    presignatures: u32,
+) -> anyhow::Result<()> {
+    let outcome = rpc_client
+        .call(signer, mpc_contract_id, "report_capacity")
         .args_json(json!({
-            "epoch": epoch
+            "presignatures": presignatures,
         }))
+        .transact()
+        .await;
+    signer
+        .check_signed(outcome)
+        .map_err(|e| {
+            tracing::warn!(%e, "failed to report capacity");
+            e
+        })?
+        .json()?;
+
+    Ok(())
+}
+
+/// Votes to kick `kick` out of the running protocol. Returns whether this vote started the
+/// resharing without it.
+#[tracing::instrument(level = "info", skip_all, fields(kick = %kick))]
+pub async fn vote_leave(
+    rpc_client: &near_fetch::Client,
+    signer: &AccountSigner,
+    mpc_contract_id: &AccountId,
+    kick: &AccountId,
+) -> anyhow::Result<bool> {
+    tracing::info!(%kick, account_id = %signer.account_id(), "voting to kick participant");
+    let args = json!({
+        "kick": kick
+    });
+    let outcome = rpc_client
+        .call(signer, mpc_contract_id, "vote_leave")
+        .args_json(&args)
         .max_gas()
         .retry_exponential(10, 5)
         .transact()
-        .await
+        .await;
+    let result = signer
+        .check_signed(outcome)
         .map_err(|e| {
-            tracing::warn!(%e, "failed to vote for reshared");
+            tracing::warn!(%e, "failed to vote to kick participant");
             e
         })?
         .json()?;
+    audit::record(
+        signer.account_id(),
+        AuditEvent::VoteCast {
+            method: "vote_leave".to_string(),
+            args,
+            result,
+        },
+    );
 
     Ok(result)
 }
+
+/// Starts a proactive resharing of the key shares if the contract says one is due. Every node
+/// checks on its own, so the call of all but the first node to get through is a no-op.
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn start_proactive_resharing_if_due(
+    rpc_client: &near_fetch::Client,
+    signer: &AccountSigner,
+    mpc_contract_id: &AccountId,
+) -> anyhow::Result<bool> {
+    let due: bool = rpc_client
+        .view(mpc_contract_id, "proactive_resharing_due")
+        .await?
+        .json()?;
+    if !due {
+        return Ok(false);
+    }
+
+    tracing::info!(account_id = %signer.account_id(), "key shares are due for a refresh, starting proactive resharing");
+    let outcome = rpc_client
+        .call(signer, mpc_contract_id, "start_proactive_resharing")
+        .max_gas()
+        .transact()
+        .await;
+    let started = signer
+        .check_signed(outcome)
+        .map_err(|e| {
+            tracing::warn!(%e, "failed to start proactive resharing");
+            e
+        })?
+        .json()?;
+    audit::record(
+        signer.account_id(),
+        AuditEvent::VoteCast {
+            method: "start_proactive_resharing".to_string(),
+            args: json!({}),
+            result: started,
+        },
+    );
+
+    Ok(started)
+}
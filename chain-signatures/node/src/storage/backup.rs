@@ -0,0 +1,256 @@
+//! Escrow of the key share to a cold backup key kept by the operator. Unlike snapshots, which are
+//! encrypted to the node's own cipher key, backups can be restored after the node lost all of its
+//! secrets, so that losing the storage of one operator does not force the whole network through
+//! resharing.
+
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use mpc_keys::hpke;
+use near_account_id::AccountId;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use super::secret_storage::{SecretStorage, SecretStorageBox};
+use super::Options;
+use crate::gcp::error::SecretStorageError;
+use crate::gcp::SecretResult;
+use crate::protocol::state::PersistentNodeData;
+
+const ASSOCIATED_DATA: &[u8] = b"mpc-key-share-backup";
+
+#[derive(Serialize, Deserialize)]
+pub struct KeyShareBackup {
+    pub account_id: AccountId,
+    pub backup_pk: hpke::PublicKey,
+    /// Unix timestamp in seconds of when the backup was written.
+    pub created_at: i64,
+    pub node_data: PersistentNodeData,
+}
+
+/// Where and to which key the key share of a node gets escrowed.
+#[derive(Clone)]
+pub struct BackupTarget {
+    pub account_id: AccountId,
+    pub backup_pk: hpke::PublicKey,
+    pub path: PathBuf,
+}
+
+impl BackupTarget {
+    /// The target configured in `opts`, if backups are enabled.
+    pub fn from_options(opts: &Options, account_id: &AccountId) -> anyhow::Result<Option<Self>> {
+        let (Some(backup_pk), Some(backup_path)) =
+            (&opts.sk_share_backup_pk, &opts.sk_share_backup_path)
+        else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            account_id: account_id.clone(),
+            backup_pk: hpke::PublicKey::try_from_bytes(&hex::decode(backup_pk)?)?,
+            path: PathBuf::from(format!("{backup_path}-{account_id}")),
+        }))
+    }
+
+    /// Writes `node_data` to the backup file encrypted to the backup key, replacing the
+    /// previous backup.
+    pub async fn write(&self, node_data: &PersistentNodeData) -> SecretResult<()> {
+        let backup = KeyShareBackup {
+            account_id: self.account_id.clone(),
+            backup_pk: self.backup_pk.clone(),
+            created_at: Utc::now().timestamp(),
+            node_data: node_data.clone(),
+        };
+        let ciphered = self
+            .backup_pk
+            .encrypt(&serde_json::to_vec(&backup)?, ASSOCIATED_DATA)
+            .map_err(|err| SecretStorageError::CipherError(err.to_string()))?;
+        // Write to a temporary file first, so that a crash does not leave a torn backup.
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut file = tokio::fs::File::create(&tmp_path).await?;
+        file.write_all(&serde_json::to_vec(&ciphered)?).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+        Ok(())
+    }
+}
+
+/// Escrows every key share stored into the wrapped storage. A failed backup is logged but does
+/// not fail the store, since the key share itself is already stored by then.
+pub struct BackupSecretStorage {
+    inner: SecretStorageBox,
+    target: BackupTarget,
+}
+
+impl BackupSecretStorage {
+    pub fn new(inner: SecretStorageBox, target: BackupTarget) -> Self {
+        Self { inner, target }
+    }
+}
+
+#[async_trait]
+impl SecretStorage for BackupSecretStorage {
+    async fn store(&mut self, data: &PersistentNodeData) -> SecretResult<()> {
+        self.inner.store(data).await?;
+        match self.target.write(data).await {
+            Ok(()) => tracing::info!(
+                epoch = data.epoch,
+                path = %self.target.path.display(),
+                "key share backed up"
+            ),
+            Err(err) => tracing::error!(
+                epoch = data.epoch,
+                path = %self.target.path.display(),
+                ?err,
+                "failed to back up key share"
+            ),
+        }
+        Ok(())
+    }
+
+    async fn load(&self) -> SecretResult<Option<PersistentNodeData>> {
+        self.inner.load().await
+    }
+}
+
+/// Wraps `storage` so that it escrows the key shares it stores, if backups are configured in
+/// `opts`.
+pub fn wrap(
+    storage: SecretStorageBox,
+    opts: &Options,
+    account_id: &AccountId,
+) -> anyhow::Result<SecretStorageBox> {
+    match BackupTarget::from_options(opts, account_id)? {
+        Some(target) => {
+            tracing::info!(path = %target.path.display(), "backing up key shares");
+            Ok(Box::new(BackupSecretStorage::new(storage, target)))
+        }
+        None => Ok(storage),
+    }
+}
+
+/// Escrows the key share already kept in `key_storage`, for nodes that enable backups after
+/// they got their key share.
+pub async fn backup(key_storage: &SecretStorageBox, target: &BackupTarget) -> anyhow::Result<()> {
+    let node_data = key_storage
+        .load()
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("node has no key share to back up"))?;
+    target.write(&node_data).await?;
+    tracing::info!(
+        epoch = node_data.epoch,
+        path = %target.path.display(),
+        "key share backed up"
+    );
+    Ok(())
+}
+
+/// Reads the backup at `path`, decrypts it with the backup secret key and stores it into
+/// `key_storage`. Existing state is only overwritten when `force` is set.
+pub async fn restore(
+    key_storage: &mut SecretStorageBox,
+    account_id: &AccountId,
+    backup_sk: &hpke::SecretKey,
+    path: &Path,
+    force: bool,
+) -> anyhow::Result<()> {
+    let ciphered: hpke::Ciphered = serde_json::from_slice(&tokio::fs::read(path).await?)?;
+    let plaintext = backup_sk
+        .decrypt(&ciphered, ASSOCIATED_DATA)
+        .map_err(|err| anyhow::anyhow!("failed to decrypt key share backup: {err}"))?;
+    let backup: KeyShareBackup = serde_json::from_slice(&plaintext)?;
+
+    if backup.account_id != *account_id {
+        anyhow::bail!(
+            "key share was backed up by {}, not by {account_id}",
+            backup.account_id
+        );
+    }
+    if let Some(existing) = key_storage.load().await? {
+        if !force {
+            anyhow::bail!(
+                "node already has state for epoch {}, refusing to overwrite it without --force",
+                existing.epoch
+            );
+        }
+        tracing::warn!(epoch = existing.epoch, "overwriting existing node state");
+    }
+
+    tracing::info!(
+        epoch = backup.node_data.epoch,
+        created_at = backup.created_at,
+        "restoring key share"
+    );
+    key_storage.store(&backup.node_data).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::secret_storage::MemorySecretStorage;
+    use k256::elliptic_curve::Field;
+    use k256::{ProjectivePoint, Scalar};
+
+    #[tokio::test]
+    async fn test_backup_restore_roundtrip() {
+        let account_id: AccountId = "node.test".parse().unwrap();
+        let (backup_sk, backup_pk) = hpke::generate();
+        let target = BackupTarget {
+            account_id: account_id.clone(),
+            backup_pk,
+            path: std::env::temp_dir().join(format!("{}-backup-{account_id}", std::process::id())),
+        };
+
+        // Storing a key share escrows it.
+        let mut storage: SecretStorageBox = Box::new(BackupSecretStorage::new(
+            Box::<MemorySecretStorage>::default(),
+            target.clone(),
+        ));
+        let private_share = Scalar::random(&mut rand::thread_rng());
+        storage
+            .store(&PersistentNodeData {
+                epoch: 2,
+                private_share,
+                public_key: (ProjectivePoint::GENERATOR * private_share).to_affine(),
+            })
+            .await
+            .unwrap();
+
+        // The backup can only be restored with the backup key, into the same account.
+        let mut restored: SecretStorageBox = Box::<MemorySecretStorage>::default();
+        let (other_sk, _) = hpke::generate();
+        assert!(
+            restore(&mut restored, &account_id, &other_sk, &target.path, false)
+                .await
+                .is_err()
+        );
+        let other_account_id: AccountId = "other.test".parse().unwrap();
+        assert!(restore(
+            &mut restored,
+            &other_account_id,
+            &backup_sk,
+            &target.path,
+            false
+        )
+        .await
+        .is_err());
+
+        restore(&mut restored, &account_id, &backup_sk, &target.path, false)
+            .await
+            .unwrap();
+        let node_data = restored.load().await.unwrap().unwrap();
+        assert_eq!(node_data.epoch, 2);
+        assert_eq!(node_data.private_share, private_share);
+
+        // Existing state is only replaced when forced.
+        assert!(
+            restore(&mut restored, &account_id, &backup_sk, &target.path, false)
+                .await
+                .is_err()
+        );
+
+        std::fs::remove_file(&target.path).unwrap();
+    }
+}
@@ -0,0 +1,21 @@
+//! Session of the messages this node sends to other nodes, which makes up the upper half of
+//! their sequence numbers. See [`crate::http_client::SequenceNumbers`].
+
+use deadpool_redis::Pool;
+use near_sdk::AccountId;
+use redis::AsyncCommands;
+
+// Can be used to "clear" redis storage in case of a breaking change
+const MESSAGE_SESSION_VERSION: &str = "v1";
+
+/// Bumps the message session persisted for `node_account_id` and returns the new one.
+pub async fn next(pool: &Pool, node_account_id: &AccountId) -> anyhow::Result<u32> {
+    let mut connection = pool.get().await?;
+    let session: u32 = connection
+        .incr(
+            format!("message_session:{MESSAGE_SESSION_VERSION}:{node_account_id}"),
+            1,
+        )
+        .await?;
+    Ok(session)
+}
@@ -1,7 +1,17 @@
+pub mod backup;
+pub mod message_session;
 pub mod presignature_storage;
+pub mod publish_storage;
 pub mod secret_storage;
+pub mod snapshot;
 pub mod triple_storage;
 
+use self::secret_storage::SecretStorageKind;
+use crate::hsm::CipherKey;
+use mpc_keys::hpke;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
 /// Configures storage.
 #[derive(Debug, Clone, clap::Parser)]
 #[group(id = "storage_options")]
@@ -15,12 +25,66 @@ pub struct Options {
     /// GCP Secret Manager ID that will be used to load/store the node's secret key share.
     #[clap(long, env("MPC_SK_SHARE_SECRET_ID"), requires_all=["gcp_project_id"])]
     pub sk_share_secret_id: Option<String>,
+    /// AWS Secrets Manager ID that will be used to load/store the node's secret key share.
+    /// AWS credentials and region are picked up from the standard AWS environment.
+    #[arg(
+        long,
+        env("MPC_AWS_SK_SHARE_SECRET_ID"),
+        conflicts_with = "sk_share_secret_id"
+    )]
+    pub aws_sk_share_secret_id: Option<String>,
     /// Mostly for integration tests.
     /// GCP Datastore URL that will be used to load/store the node's triples and presignatures.
     #[arg(long, env("MPC_GCP_DATASTORE_URL"))]
     pub gcp_datastore_url: Option<String>,
     #[arg(long, env("MPC_SK_SHARE_LOCAL_PATH"))]
     pub sk_share_local_path: Option<String>,
+    /// Passphrase to encrypt the key share file at `sk_share_local_path` with. The file is
+    /// plaintext if neither this nor `sk_share_local_transit_key` is set.
+    #[arg(long, env("MPC_SK_SHARE_LOCAL_PASSPHRASE"))]
+    pub sk_share_local_passphrase: Option<String>,
+    /// Key of a Vault transit engine, e.g. `transit/mpc-sk-share`, that wraps the random key
+    /// the key share file at `sk_share_local_path` is encrypted with.
+    #[arg(
+        long,
+        env("MPC_SK_SHARE_LOCAL_TRANSIT_KEY"),
+        requires = "vault_addr",
+        conflicts_with = "sk_share_local_passphrase"
+    )]
+    pub sk_share_local_transit_key: Option<String>,
+    /// Backend that stores the node's secret key share. Picked from the other options that are
+    /// set when not provided.
+    #[arg(long, env("MPC_SECRET_STORAGE"), value_enum)]
+    pub secret_storage: Option<SecretStorageKind>,
+    /// Address of the HashiCorp Vault server that stores the node's secret key share.
+    #[arg(long, env("VAULT_ADDR"))]
+    pub vault_addr: Option<String>,
+    /// Token to authenticate to the Vault server with. Takes precedence over AppRole auth.
+    #[arg(long, env("VAULT_TOKEN"))]
+    pub vault_token: Option<String>,
+    /// Role ID to authenticate to the Vault server with through AppRole.
+    #[arg(long, env("VAULT_ROLE_ID"), requires = "vault_secret_id")]
+    pub vault_role_id: Option<String>,
+    /// Secret ID to authenticate to the Vault server with through AppRole.
+    #[arg(long, env("VAULT_SECRET_ID"), requires = "vault_role_id")]
+    pub vault_secret_id: Option<String>,
+    /// Path of the secret key share in a KV version 2 engine of Vault, starting with the mount
+    /// of the engine, e.g. `secret/mpc-sk-share`.
+    #[arg(long, env("MPC_VAULT_SK_SHARE_PATH"), requires = "vault_addr")]
+    pub vault_sk_share_path: Option<String>,
+    /// Path of the cipher keys of the node in a KV version 2 engine of Vault, stored as the hex
+    /// encoded `cipher_pk` and `cipher_sk` fields. Used when the cipher keys are not provided.
+    #[arg(long, env("MPC_VAULT_CIPHER_KEY_PATH"), requires = "vault_addr")]
+    pub vault_cipher_key_path: Option<String>,
+    /// Hex encoded cipher public key of a cold backup key, usually kept offline by the
+    /// operator. Every key share the node stores is also escrowed to `sk_share_backup_path`
+    /// encrypted to this key, and can be restored with `restore-key-share`.
+    #[arg(long, env("MPC_SK_SHARE_BACKUP_PK"), requires = "sk_share_backup_path")]
+    pub sk_share_backup_pk: Option<String>,
+    /// Path prefix of the escrowed key share, suffixed with the account id of the node like
+    /// `sk_share_local_path`. Should be on other storage than the key share itself.
+    #[arg(long, env("MPC_SK_SHARE_BACKUP_PATH"), requires = "sk_share_backup_pk")]
+    pub sk_share_backup_path: Option<String>,
     #[arg(long, env("MPC_REDIS_URL"))]
     pub redis_url: String,
 }
@@ -36,6 +100,12 @@ impl Options {
         if let Some(sk_share_secret_id) = self.sk_share_secret_id {
             opts.extend(vec!["--sk-share-secret-id".to_string(), sk_share_secret_id]);
         }
+        if let Some(aws_sk_share_secret_id) = self.aws_sk_share_secret_id {
+            opts.extend(vec![
+                "--aws-sk-share-secret-id".to_string(),
+                aws_sk_share_secret_id,
+            ]);
+        }
         if let Some(gcp_datastore_url) = self.gcp_datastore_url {
             opts.extend(vec!["--gcp-datastore-url".to_string(), gcp_datastore_url]);
         }
@@ -45,7 +115,96 @@ impl Options {
                 sk_share_local_path,
             ]);
         }
+        if let Some(sk_share_local_passphrase) = self.sk_share_local_passphrase {
+            opts.extend(vec![
+                "--sk-share-local-passphrase".to_string(),
+                sk_share_local_passphrase,
+            ]);
+        }
+        if let Some(sk_share_local_transit_key) = self.sk_share_local_transit_key {
+            opts.extend(vec![
+                "--sk-share-local-transit-key".to_string(),
+                sk_share_local_transit_key,
+            ]);
+        }
+        if let Some(secret_storage) = self.secret_storage {
+            opts.extend(vec![
+                "--secret-storage".to_string(),
+                secret_storage.to_string(),
+            ]);
+        }
+        if let Some(vault_addr) = self.vault_addr {
+            opts.extend(vec!["--vault-addr".to_string(), vault_addr]);
+        }
+        if let Some(vault_token) = self.vault_token {
+            opts.extend(vec!["--vault-token".to_string(), vault_token]);
+        }
+        if let Some(vault_role_id) = self.vault_role_id {
+            opts.extend(vec!["--vault-role-id".to_string(), vault_role_id]);
+        }
+        if let Some(vault_secret_id) = self.vault_secret_id {
+            opts.extend(vec!["--vault-secret-id".to_string(), vault_secret_id]);
+        }
+        if let Some(vault_sk_share_path) = self.vault_sk_share_path {
+            opts.extend(vec![
+                "--vault-sk-share-path".to_string(),
+                vault_sk_share_path,
+            ]);
+        }
+        if let Some(vault_cipher_key_path) = self.vault_cipher_key_path {
+            opts.extend(vec![
+                "--vault-cipher-key-path".to_string(),
+                vault_cipher_key_path,
+            ]);
+        }
+        if let Some(sk_share_backup_pk) = self.sk_share_backup_pk {
+            opts.extend(vec!["--sk-share-backup-pk".to_string(), sk_share_backup_pk]);
+        }
+        if let Some(sk_share_backup_path) = self.sk_share_backup_path {
+            opts.extend(vec![
+                "--sk-share-backup-path".to_string(),
+                sk_share_backup_path,
+            ]);
+        }
 
         opts
     }
 }
+
+/// Encrypts triples and presignatures before they get written to redis, so the stockpile
+/// can survive a restart without our secret shares ever being stored in plaintext.
+/// Values are encrypted to the node's own cipher key.
+#[derive(Clone)]
+pub struct StorageCipher {
+    cipher_sk: CipherKey,
+    cipher_pk: hpke::PublicKey,
+}
+
+impl StorageCipher {
+    const ASSOCIATED_DATA: &'static [u8] = b"mpc-stockpile";
+
+    pub fn new(cipher_sk: CipherKey) -> Self {
+        Self {
+            cipher_pk: cipher_sk.public_key(),
+            cipher_sk,
+        }
+    }
+
+    pub fn encrypt<T: Serialize>(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        let plaintext = serde_json::to_vec(value)?;
+        let ciphered = self
+            .cipher_pk
+            .encrypt(&plaintext, Self::ASSOCIATED_DATA)
+            .map_err(|err| anyhow::anyhow!("failed to encrypt stored value: {err}"))?;
+        Ok(serde_json::to_vec(&ciphered)?)
+    }
+
+    pub fn decrypt<T: DeserializeOwned>(&self, bytes: &[u8]) -> anyhow::Result<T> {
+        let ciphered: hpke::Ciphered = serde_json::from_slice(bytes)?;
+        let plaintext = self
+            .cipher_sk
+            .decrypt(&ciphered, Self::ASSOCIATED_DATA)
+            .map_err(|err| anyhow::anyhow!("failed to decrypt stored value: {err}"))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
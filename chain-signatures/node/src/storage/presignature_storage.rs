@@ -1,19 +1,28 @@
+use std::collections::HashSet;
+
 use anyhow::Ok;
+use chrono::Utc;
 use deadpool_redis::Pool;
 use near_sdk::AccountId;
-use redis::{AsyncCommands, FromRedisValue, RedisWrite, ToRedisArgs};
+use redis::AsyncCommands;
 
 use crate::protocol::presignature::{Presignature, PresignatureId};
+use crate::storage::StorageCipher;
 
 type PresigResult<T> = std::result::Result<T, anyhow::Error>;
 
 // Can be used to "clear" redis storage in case of a breaking change
-const PRESIGNATURE_STORAGE_VERSION: &str = "v1";
+const PRESIGNATURE_STORAGE_VERSION: &str = "v2";
 
-pub fn init(pool: &Pool, node_account_id: &AccountId) -> PresignatureRedisStorage {
+pub fn init(
+    pool: &Pool,
+    node_account_id: &AccountId,
+    cipher: &StorageCipher,
+) -> PresignatureRedisStorage {
     PresignatureRedisStorage {
         redis_pool: pool.clone(),
         node_account_id: node_account_id.clone(),
+        cipher: cipher.clone(),
     }
 }
 
@@ -21,16 +30,25 @@ pub fn init(pool: &Pool, node_account_id: &AccountId) -> PresignatureRedisStorag
 pub struct PresignatureRedisStorage {
     redis_pool: Pool,
     node_account_id: AccountId,
+    cipher: StorageCipher,
 }
 
 impl PresignatureRedisStorage {
     pub async fn insert(&self, presignature: Presignature) -> PresigResult<()> {
+        let encrypted = self.cipher.encrypt(&presignature)?;
         let mut connection = self.redis_pool.get().await?;
         connection
-            .hset::<&str, PresignatureId, Presignature, ()>(
+            .hset::<&str, PresignatureId, Vec<u8>, ()>(
                 &self.presig_key(),
                 presignature.id,
-                presignature,
+                encrypted,
+            )
+            .await?;
+        connection
+            .zadd::<&str, u64, PresignatureId, ()>(
+                &self.created_key(),
+                presignature.id,
+                presignature.created_at,
             )
             .await?;
         Ok(())
@@ -63,13 +81,16 @@ impl PresignatureRedisStorage {
             tracing::error!("Can not take mine presignature as foreign: {:?}", id);
             return Ok(None);
         }
-        let result: Option<Presignature> = connection.hget(self.presig_key(), id).await?;
+        let result: Option<Vec<u8>> = connection.hget(self.presig_key(), id).await?;
         match result {
-            Some(presignature) => {
+            Some(encrypted) => {
                 connection
                     .hdel::<&str, PresignatureId, ()>(&self.presig_key(), *id)
                     .await?;
-                Ok(Some(presignature))
+                connection
+                    .zrem::<&str, PresignatureId, ()>(&self.created_key(), *id)
+                    .await?;
+                Ok(Some(self.cipher.decrypt(&encrypted)?))
             }
             None => Ok(None),
         }
@@ -96,10 +117,61 @@ impl PresignatureRedisStorage {
         Ok(result)
     }
 
+    /// Removes the presignatures generated before `created_before`, a unix timestamp in
+    /// seconds, and returns their ids. With `mine_only`, the presignatures of other nodes are
+    /// kept.
+    pub async fn remove_expired(
+        &self,
+        created_before: u64,
+        mine_only: bool,
+    ) -> PresigResult<Vec<PresignatureId>> {
+        let mut connection = self.redis_pool.get().await?;
+
+        // Presignatures stored before their creation was kept count as generated now.
+        let ids: Vec<PresignatureId> = connection.hkeys(self.presig_key()).await?;
+        let indexed: HashSet<PresignatureId> = connection.zrange(self.created_key(), 0, -1).await?;
+        let now = Utc::now().timestamp() as u64;
+        let unindexed = ids
+            .into_iter()
+            .filter(|id| !indexed.contains(id))
+            .map(|id| (now, id))
+            .collect::<Vec<_>>();
+        if !unindexed.is_empty() {
+            connection
+                .zadd_multiple::<&str, u64, PresignatureId, ()>(&self.created_key(), &unindexed)
+                .await?;
+        }
+
+        let candidates: Vec<PresignatureId> = connection
+            .zrangebyscore(self.created_key(), "-inf", format!("({created_before}"))
+            .await?;
+        let mut expired = Vec::new();
+        for id in candidates {
+            let mine: bool = connection.sismember(self.mine_key(), id).await?;
+            if mine_only && !mine {
+                continue;
+            }
+            if mine {
+                connection
+                    .srem::<&str, PresignatureId, ()>(&self.mine_key(), id)
+                    .await?;
+            }
+            connection
+                .hdel::<&str, PresignatureId, ()>(&self.presig_key(), id)
+                .await?;
+            connection
+                .zrem::<&str, PresignatureId, ()>(&self.created_key(), id)
+                .await?;
+            expired.push(id);
+        }
+        Ok(expired)
+    }
+
     pub async fn clear(&self) -> PresigResult<()> {
         let mut connection = self.redis_pool.get().await?;
         connection.del::<&str, ()>(&self.presig_key()).await?;
         connection.del::<&str, ()>(&self.mine_key()).await?;
+        connection.del::<&str, ()>(&self.created_key()).await?;
         Ok(())
     }
 
@@ -116,33 +188,12 @@ impl PresignatureRedisStorage {
             PRESIGNATURE_STORAGE_VERSION, self.node_account_id
         )
     }
-}
-
-impl ToRedisArgs for Presignature {
-    fn write_redis_args<W>(&self, out: &mut W)
-    where
-        W: ?Sized + RedisWrite,
-    {
-        match serde_json::to_string(self) {
-            std::result::Result::Ok(json) => out.write_arg(json.as_bytes()),
-            Err(e) => {
-                tracing::error!("Failed to serialize Presignature: {}", e);
-                out.write_arg("failed_to_serialize".as_bytes())
-            }
-        }
-    }
-}
 
-impl FromRedisValue for Presignature {
-    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
-        let json: String = String::from_redis_value(v)?;
-
-        serde_json::from_str(&json).map_err(|e| {
-            redis::RedisError::from((
-                redis::ErrorKind::TypeError,
-                "Failed to deserialize Presignature",
-                e.to_string(),
-            ))
-        })
+    /// Sorted set of the presignature ids by when they were generated.
+    fn created_key(&self) -> String {
+        format!(
+            "presignatures_created:{}:{}",
+            PRESIGNATURE_STORAGE_VERSION, self.node_account_id
+        )
     }
 }
@@ -0,0 +1,98 @@
+use anyhow::Ok;
+use crypto_shared::SignatureResponse;
+use deadpool_redis::Pool;
+use mpc_contract::primitives::SignatureRequest;
+use near_sdk::AccountId;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+
+type PublishResult<T> = std::result::Result<T, anyhow::Error>;
+
+// Can be used to "clear" redis storage in case of a breaking change
+const PUBLISH_STORAGE_VERSION: &str = "v1";
+
+pub fn init(pool: &Pool, node_account_id: &AccountId) -> PublishRedisStorage {
+    PublishRedisStorage {
+        redis_pool: pool.clone(),
+        node_account_id: node_account_id.clone(),
+    }
+}
+
+/// A signature of this node that still has to be published to the contract through `respond`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingPublish {
+    pub request_id: [u8; 32],
+    pub request: SignatureRequest,
+    pub response: SignatureResponse,
+    /// Unix timestamp in seconds of when the signature got generated.
+    pub generated_at: u64,
+    /// Attempts at publishing the signature that failed so far.
+    pub attempts: u8,
+    /// Ids of the participants that produced the signature. Empty for the signatures stored
+    /// before the participants were kept.
+    #[serde(default)]
+    pub participants: Vec<u32>,
+}
+
+/// Durable queue of the signatures waiting to be published, so that they survive an RPC outage
+/// or a restart of the node instead of wasting the presignatures they were made with. The
+/// signatures are not secret, so unlike triples and presignatures they are stored unencrypted.
+#[derive(Clone)]
+pub struct PublishRedisStorage {
+    redis_pool: Pool,
+    node_account_id: AccountId,
+}
+
+impl PublishRedisStorage {
+    /// Adds `pending` to the queue, or updates it if it is already queued.
+    pub async fn insert(&self, pending: &PendingPublish) -> PublishResult<()> {
+        let mut connection = self.redis_pool.get().await?;
+        connection
+            .hset::<&str, String, Vec<u8>, ()>(
+                &self.publish_key(),
+                hex::encode(pending.request_id),
+                serde_json::to_vec(pending)?,
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove(&self, request_id: &[u8; 32]) -> PublishResult<()> {
+        let mut connection = self.redis_pool.get().await?;
+        connection
+            .hdel::<&str, String, ()>(&self.publish_key(), hex::encode(request_id))
+            .await?;
+        Ok(())
+    }
+
+    /// Every queued signature, oldest first.
+    pub async fn load(&self) -> PublishResult<Vec<PendingPublish>> {
+        let mut connection = self.redis_pool.get().await?;
+        let entries: Vec<Vec<u8>> = connection.hvals(self.publish_key()).await?;
+        let mut pending = entries
+            .iter()
+            .map(|entry| serde_json::from_slice::<PendingPublish>(entry))
+            .collect::<Result<Vec<_>, _>>()?;
+        pending.sort_by_key(|pending| pending.generated_at);
+        Ok(pending)
+    }
+
+    pub async fn len_queued(&self) -> PublishResult<usize> {
+        let mut connection = self.redis_pool.get().await?;
+        let result: usize = connection.hlen(self.publish_key()).await?;
+        Ok(result)
+    }
+
+    pub async fn clear(&self) -> PublishResult<()> {
+        let mut connection = self.redis_pool.get().await?;
+        connection.del::<&str, ()>(&self.publish_key()).await?;
+        Ok(())
+    }
+
+    fn publish_key(&self) -> String {
+        format!(
+            "publish_queue:{}:{}",
+            PUBLISH_STORAGE_VERSION, self.node_account_id
+        )
+    }
+}
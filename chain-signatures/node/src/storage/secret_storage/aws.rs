@@ -0,0 +1,84 @@
+use async_trait::async_trait;
+use aws_sdk_secretsmanager::primitives::Blob;
+use tokio::sync::OnceCell;
+
+use super::SecretStorage;
+use crate::gcp::error::SecretStorageError;
+use crate::gcp::SecretResult;
+use crate::protocol::state::PersistentNodeData;
+
+/// Stores the node data in AWS Secrets Manager. AWS credentials and region are picked up from the
+/// standard AWS environment.
+pub struct AwsSecretStorage {
+    client: OnceCell<aws_sdk_secretsmanager::Client>,
+    secret_id: String,
+}
+
+impl AwsSecretStorage {
+    pub fn new(secret_id: String) -> Self {
+        Self {
+            client: OnceCell::new(),
+            secret_id,
+        }
+    }
+
+    async fn client(&self) -> &aws_sdk_secretsmanager::Client {
+        self.client
+            .get_or_init(|| async {
+                let aws_config = aws_config::from_env().load().await;
+                aws_sdk_secretsmanager::Client::new(&aws_config)
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl SecretStorage for AwsSecretStorage {
+    async fn store(&mut self, data: &PersistentNodeData) -> SecretResult<()> {
+        tracing::info!("storing PersistentNodeData using AwsSecretStorage");
+        self.client()
+            .await
+            .put_secret_value()
+            .secret_id(&self.secret_id)
+            .secret_binary(Blob::new(serde_json::to_vec(data)?))
+            .send()
+            .await
+            .map_err(|err| SecretStorageError::AwsError(err.into_service_error().to_string()))?;
+        Ok(())
+    }
+
+    async fn load(&self) -> SecretResult<Option<PersistentNodeData>> {
+        tracing::info!("loading PersistentNodeData using AwsSecretStorage");
+        let response = match self
+            .client()
+            .await
+            .get_secret_value()
+            .secret_id(&self.secret_id)
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(err) => {
+                let err = err.into_service_error();
+                if err.is_resource_not_found_exception() {
+                    tracing::info!("no key share stored yet, presuming it is missing");
+                    return Ok(None);
+                }
+                return Err(SecretStorageError::AwsError(err.to_string()));
+            }
+        };
+        match response.secret_binary {
+            Some(data) => match serde_json::from_slice(data.as_ref()) {
+                Ok(persistent_node_data) => Ok(Some(persistent_node_data)),
+                Err(err) => {
+                    tracing::error!(%err, data_len = data.as_ref().len(), "failed to convert stored data to key share, presuming it is missing");
+                    Ok(None)
+                }
+            },
+            None => {
+                tracing::error!("failed to load existing key share, presuming it is missing");
+                Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use std::path::PathBuf;
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use async_trait::async_trait;
+use hmac::Hmac;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::SecretStorage;
+#[cfg(feature = "vault-secret-storage")]
+use super::VaultClient;
+use crate::gcp::error::SecretStorageError;
+use crate::gcp::SecretResult;
+use crate::protocol::state::PersistentNodeData;
+use crate::storage::Options;
+
+/// Version of the encrypted file format, bumped whenever it changes.
+const FORMAT_VERSION: u32 = 1;
+
+/// Iterations of PBKDF2-HMAC-SHA256 deriving the file key from a passphrase.
+const PBKDF2_ROUNDS: u32 = 600_000;
+
+const ASSOCIATED_DATA: &[u8] = b"mpc-sk-share";
+
+/// How the key share file is protected.
+pub enum FileEncryption {
+    /// Written as plaintext JSON. Mostly for integration tests.
+    None,
+    /// Encrypted with a key derived from a passphrase.
+    Passphrase(String),
+    /// Encrypted with a random data key, which is stored in the file wrapped by a key of the
+    /// transit secrets engine of Vault. The key share can only be read while Vault is reachable.
+    #[cfg(feature = "vault-secret-storage")]
+    VaultTransit { client: VaultClient, key: String },
+}
+
+impl FileEncryption {
+    pub fn from_options(opts: &Options) -> anyhow::Result<Self> {
+        match (
+            &opts.sk_share_local_passphrase,
+            &opts.sk_share_local_transit_key,
+        ) {
+            (Some(_), Some(_)) => anyhow::bail!(
+                "only one of `sk_share_local_passphrase` and `sk_share_local_transit_key` can be set"
+            ),
+            (Some(passphrase), None) => Ok(Self::Passphrase(passphrase.clone())),
+            #[cfg(feature = "vault-secret-storage")]
+            (None, Some(key)) => Ok(Self::VaultTransit {
+                client: VaultClient::from_options(opts)?,
+                key: key.clone(),
+            }),
+            #[cfg(not(feature = "vault-secret-storage"))]
+            (None, Some(_)) => anyhow::bail!(
+                "`sk_share_local_transit_key` requires the `vault-secret-storage` feature"
+            ),
+            (None, None) => Ok(Self::None),
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        matches!(self, Self::None)
+    }
+
+    async fn seal(&self, plaintext: &[u8]) -> SecretResult<Vec<u8>> {
+        let file = match self {
+            Self::None => return Ok(plaintext.to_vec()),
+            Self::Passphrase(passphrase) => {
+                EncryptedFile::with_passphrase(passphrase, PBKDF2_ROUNDS, plaintext)?
+            }
+            #[cfg(feature = "vault-secret-storage")]
+            Self::VaultTransit { client, key } => {
+                let data_key = random_bytes::<32>();
+                let wrapped = client.transit_encrypt(key, &data_key).await?;
+                EncryptedFile::seal(
+                    WrappedKey::VaultTransit {
+                        key: key.clone(),
+                        wrapped,
+                    },
+                    &data_key,
+                    plaintext,
+                )?
+            }
+        };
+        Ok(serde_json::to_vec(&file)?)
+    }
+
+    async fn open(&self, file: &EncryptedFile) -> SecretResult<Vec<u8>> {
+        let data_key = match (self, &file.key) {
+            (Self::Passphrase(passphrase), WrappedKey::Passphrase { salt, rounds }) => {
+                derive_key(passphrase, &decode_hex(salt)?, *rounds)
+            }
+            #[cfg(feature = "vault-secret-storage")]
+            (Self::VaultTransit { client, .. }, WrappedKey::VaultTransit { key, wrapped }) => {
+                let data_key = client.transit_decrypt(key, wrapped).await?;
+                data_key
+                    .try_into()
+                    .map_err(|_| cipher_error("unwrapped data key is not 32 bytes"))?
+            }
+            (Self::None, _) => {
+                return Err(cipher_error(
+                    "key share file is encrypted, but no key is configured",
+                ))
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                return Err(cipher_error(
+                    "key share file is encrypted with another kind of key",
+                ))
+            }
+        };
+        file.open(&data_key)
+    }
+}
+
+/// Key of an encrypted key share file, along with what is needed to recover it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WrappedKey {
+    /// The key is derived from the passphrase with PBKDF2-HMAC-SHA256.
+    Passphrase { salt: String, rounds: u32 },
+    /// The key is wrapped by the Vault transit key `key`.
+    VaultTransit { key: String, wrapped: String },
+}
+
+/// Key share file encrypted with AES-256-GCM. Binary fields are hex encoded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct EncryptedFile {
+    version: u32,
+    key: WrappedKey,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Contents of a key share file, which may still be plaintext if it was written before the
+/// file got encrypted.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredFile {
+    Encrypted(EncryptedFile),
+    Plaintext(PersistentNodeData),
+}
+
+impl EncryptedFile {
+    fn with_passphrase(passphrase: &str, rounds: u32, plaintext: &[u8]) -> SecretResult<Self> {
+        let salt = random_bytes::<16>();
+        let data_key = derive_key(passphrase, &salt, rounds);
+        Self::seal(
+            WrappedKey::Passphrase {
+                salt: hex::encode(salt),
+                rounds,
+            },
+            &data_key,
+            plaintext,
+        )
+    }
+
+    fn seal(key: WrappedKey, data_key: &[u8; 32], plaintext: &[u8]) -> SecretResult<Self> {
+        let nonce = random_bytes::<12>();
+        let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(data_key))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: ASSOCIATED_DATA,
+                },
+            )
+            .map_err(|_| cipher_error("failed to encrypt the key share"))?;
+        Ok(Self {
+            version: FORMAT_VERSION,
+            key,
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    fn open(&self, data_key: &[u8; 32]) -> SecretResult<Vec<u8>> {
+        if self.version != FORMAT_VERSION {
+            return Err(cipher_error(format!(
+                "unsupported key share file version {}",
+                self.version
+            )));
+        }
+        let nonce = decode_hex(&self.nonce)?;
+        if nonce.len() != 12 {
+            return Err(cipher_error("nonce is not 12 bytes"));
+        }
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(data_key))
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &decode_hex(&self.ciphertext)?,
+                    aad: ASSOCIATED_DATA,
+                },
+            )
+            .map_err(|_| cipher_error("failed to decrypt the key share, is the key right?"))
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], rounds: u32) -> [u8; 32] {
+    let mut key = [0; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, rounds, &mut key);
+    key
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn decode_hex(value: &str) -> SecretResult<Vec<u8>> {
+    hex::decode(value).map_err(|err| cipher_error(format!("malformed key share file: {err}")))
+}
+
+fn cipher_error(err: impl ToString) -> SecretStorageError {
+    SecretStorageError::CipherError(err.to_string())
+}
+
+/// Stores the node data in a local file, encrypted unless no [`FileEncryption`] is configured.
+pub struct FileSecretStorage {
+    path: PathBuf,
+    encryption: FileEncryption,
+}
+
+impl FileSecretStorage {
+    pub fn new(path: &str, encryption: FileEncryption) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            encryption,
+        }
+    }
+
+    /// Rewrites the key share file with the configured encryption, which upgrades a plaintext
+    /// file or one encrypted with the same kind of key. Returns whether there was a file.
+    pub async fn reencrypt(&mut self) -> anyhow::Result<bool> {
+        anyhow::ensure!(
+            !self.encryption.is_none(),
+            "a passphrase or transit key is required to encrypt the key share"
+        );
+        let Some(data) = self.load().await? else {
+            return Ok(false);
+        };
+        self.store(&data).await?;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl SecretStorage for FileSecretStorage {
+    async fn store(&mut self, data: &PersistentNodeData) -> SecretResult<()> {
+        tracing::info!("storing PersistentNodeData using FileSecretStorage");
+        let contents = self.encryption.seal(&serde_json::to_vec(data)?).await?;
+        // Write to a temporary file first, so that a crash does not leave a torn key share.
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut file = File::create(&tmp_path).await?;
+        file.write_all(&contents).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+
+        Ok(())
+    }
+
+    async fn load(&self) -> SecretResult<Option<PersistentNodeData>> {
+        tracing::info!("loading PersistentNodeData using FileSecretStorage");
+        // Open the file asynchronously
+        let file_res = File::open(self.path.as_os_str()).await;
+
+        match file_res {
+            Ok(mut file) => {
+                let mut contents = Vec::new();
+                // Read the contents of the file into the vector
+                tracing::info!("loading PersistentNodeData using FileSecretStorage: reading");
+                file.read_to_end(&mut contents).await?;
+
+                tracing::info!("loading PersistentNodeData using FileSecretStorage: read done");
+                let data = match serde_json::from_slice(&contents)? {
+                    StoredFile::Encrypted(file) => {
+                        serde_json::from_slice(&self.encryption.open(&file).await?)?
+                    }
+                    StoredFile::Plaintext(data) => {
+                        if !self.encryption.is_none() {
+                            tracing::warn!(
+                                "key share file is plaintext, upgrade it with `mpc-node encrypt-key-share`"
+                            );
+                        }
+                        data
+                    }
+                };
+
+                Ok(Some(data))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passphrase_roundtrip() {
+        let plaintext = br#"{"epoch":1}"#;
+        let file = EncryptedFile::with_passphrase("correct horse", 1_000, plaintext).unwrap();
+        let WrappedKey::Passphrase { salt, rounds } = &file.key else {
+            panic!("expected a passphrase key");
+        };
+        let salt = hex::decode(salt).unwrap();
+
+        let data_key = derive_key("correct horse", &salt, *rounds);
+        assert_eq!(file.open(&data_key).unwrap(), plaintext);
+
+        let wrong_key = derive_key("battery staple", &salt, *rounds);
+        assert!(file.open(&wrong_key).is_err());
+    }
+
+    #[test]
+    fn test_tampered_file() {
+        let data_key = random_bytes::<32>();
+        let mut file = EncryptedFile::seal(
+            WrappedKey::Passphrase {
+                salt: String::new(),
+                rounds: 1,
+            },
+            &data_key,
+            b"share",
+        )
+        .unwrap();
+        let mut ciphertext = hex::decode(&file.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        file.ciphertext = hex::encode(ciphertext);
+        assert!(file.open(&data_key).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_file_needs_key() {
+        let file = EncryptedFile::with_passphrase("passphrase", 1_000, b"share").unwrap();
+        assert!(FileEncryption::None.open(&file).await.is_err());
+    }
+}
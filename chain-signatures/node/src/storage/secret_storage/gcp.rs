@@ -0,0 +1,52 @@
+use async_trait::async_trait;
+
+use super::SecretStorage;
+use crate::gcp::{SecretManagerService, SecretResult};
+use crate::protocol::state::PersistentNodeData;
+
+/// Stores the node data in GCP Secret Manager.
+pub struct GcpSecretStorage {
+    secret_manager: SecretManagerService,
+    sk_share_secret_id: String,
+}
+
+impl GcpSecretStorage {
+    pub fn new(secret_manager: &SecretManagerService, sk_share_secret_id: String) -> Self {
+        Self {
+            secret_manager: secret_manager.clone(),
+            sk_share_secret_id,
+        }
+    }
+}
+
+#[async_trait]
+impl SecretStorage for GcpSecretStorage {
+    async fn store(&mut self, data: &PersistentNodeData) -> SecretResult<()> {
+        tracing::info!("storing PersistentNodeData using GcpSecretStorage");
+        self.secret_manager
+            .store_secret(&serde_json::to_vec(data)?, &self.sk_share_secret_id)
+            .await?;
+        Ok(())
+    }
+
+    async fn load(&self) -> SecretResult<Option<PersistentNodeData>> {
+        tracing::info!("loading PersistentNodeData using GcpSecretStorage");
+        let raw_data = self
+            .secret_manager
+            .load_secret(&self.sk_share_secret_id)
+            .await?;
+        match raw_data {
+            Some(data) if data.len() > 1 => match serde_json::from_slice(&data) {
+                Ok(persistent_node_data) => Ok(Some(persistent_node_data)),
+                Err(err) => {
+                    tracing::error!(%err, data_len = data.len(), "failed to convert stored data to key share, presuming it is missing");
+                    Ok(None)
+                }
+            },
+            _ => {
+                tracing::error!("failed to load existing key share, presuming it is missing");
+                Ok(None)
+            }
+        }
+    }
+}
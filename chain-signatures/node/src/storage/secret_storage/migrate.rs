@@ -0,0 +1,119 @@
+//! Moving the key share of a node from one secret storage backend to another, for operators
+//! that migrate a node between local files, GCP, AWS or Vault. Used by the `migrate-keyshare`
+//! binary.
+
+use super::SecretStorageBox;
+use crate::protocol::state::PersistentNodeData;
+use crate::protocol::ProtocolState;
+
+/// Checks that `node_data` is a key share of the root key of the contract in `state`, for an
+/// epoch that the network still signs with. While resharing, key shares of both the old and the
+/// new epoch are accepted.
+pub fn verify_against_contract(
+    node_data: &PersistentNodeData,
+    state: &ProtocolState,
+) -> anyhow::Result<()> {
+    let (public_key, epochs) = match state {
+        ProtocolState::Initializing(_) => {
+            anyhow::bail!("the contract has not generated its public key yet")
+        }
+        ProtocolState::Running(state) => (&state.public_key, state.epoch..=state.epoch),
+        ProtocolState::Resharing(state) => {
+            (&state.public_key, state.old_epoch..=state.old_epoch + 1)
+        }
+    };
+    if node_data.public_key != *public_key {
+        anyhow::bail!(
+            "key share is for public key {:?}, but the contract has {public_key:?}",
+            node_data.public_key
+        );
+    }
+    if !epochs.contains(&node_data.epoch) {
+        anyhow::bail!(
+            "key share is for epoch {}, but the contract is at epoch {}",
+            node_data.epoch,
+            epochs.end()
+        );
+    }
+    Ok(())
+}
+
+/// Copies the key share in `from` to `to`, checking it with `verify` first, and reads it back
+/// from `to` to make sure it arrived intact. Refuses to replace a key share that `to` already
+/// has unless `force` is set. The key share is left in `from`, for the operator to remove once
+/// the node runs off `to`.
+pub async fn migrate(
+    from: &SecretStorageBox,
+    to: &mut SecretStorageBox,
+    verify: impl FnOnce(&PersistentNodeData) -> anyhow::Result<()>,
+    force: bool,
+) -> anyhow::Result<PersistentNodeData> {
+    let Some(node_data) = from.load().await? else {
+        anyhow::bail!("no key share stored in the source backend");
+    };
+    verify(&node_data)?;
+
+    if let Some(existing) = to.load().await? {
+        if !force {
+            anyhow::bail!(
+                "destination already has a key share for epoch {}, refusing to overwrite it without --force",
+                existing.epoch
+            );
+        }
+        tracing::warn!(epoch = existing.epoch, "overwriting existing key share");
+    }
+    to.store(&node_data).await?;
+
+    let Some(stored) = to.load().await? else {
+        anyhow::bail!("key share could not be read back from the destination backend");
+    };
+    if stored.epoch != node_data.epoch
+        || stored.private_share != node_data.private_share
+        || stored.public_key != node_data.public_key
+    {
+        anyhow::bail!("key share read back from the destination backend differs from the source");
+    }
+    Ok(node_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::secret_storage::MemorySecretStorage;
+    use k256::elliptic_curve::Field;
+    use k256::{ProjectivePoint, Scalar};
+
+    #[tokio::test]
+    async fn test_migrate_key_share() {
+        let mut from: SecretStorageBox = Box::<MemorySecretStorage>::default();
+        let mut to: SecretStorageBox = Box::<MemorySecretStorage>::default();
+        assert!(migrate(&from, &mut to, |_| Ok(()), false).await.is_err());
+
+        let private_share = Scalar::random(&mut rand::thread_rng());
+        from.store(&PersistentNodeData {
+            epoch: 3,
+            private_share,
+            public_key: (ProjectivePoint::GENERATOR * private_share).to_affine(),
+        })
+        .await
+        .unwrap();
+
+        // Nothing is written when the key share fails the check.
+        assert!(
+            migrate(&from, &mut to, |_| Err(anyhow::anyhow!("wrong key")), false)
+                .await
+                .is_err()
+        );
+        assert!(to.load().await.unwrap().is_none());
+
+        let node_data = migrate(&from, &mut to, |_| Ok(()), false).await.unwrap();
+        assert_eq!(node_data.epoch, 3);
+        let stored = to.load().await.unwrap().unwrap();
+        assert_eq!(stored.private_share, private_share);
+        assert!(from.load().await.unwrap().is_some());
+
+        // An existing key share is only replaced when forced.
+        assert!(migrate(&from, &mut to, |_| Ok(()), false).await.is_err());
+        migrate(&from, &mut to, |_| Ok(()), true).await.unwrap();
+    }
+}
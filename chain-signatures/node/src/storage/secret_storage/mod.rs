@@ -0,0 +1,221 @@
+//! Storage of the secret data of the node, i.e. its key share. Backends are picked through the
+//! storage [`Options`] and each one sits behind its own feature, so that a node can be built with
+//! only the backends it needs. Operators with a backend of their own can implement
+//! [`SecretStorage`] for it and hand it to the node as a [`SecretStorageBox`].
+
+#[cfg(feature = "aws-secret-storage")]
+mod aws;
+#[cfg(feature = "file-secret-storage")]
+mod file;
+#[cfg(feature = "gcp-secret-storage")]
+mod gcp;
+#[cfg(feature = "vault-secret-storage")]
+mod vault;
+
+pub mod migrate;
+
+#[cfg(feature = "aws-secret-storage")]
+pub use aws::AwsSecretStorage;
+#[cfg(feature = "file-secret-storage")]
+pub use file::{FileEncryption, FileSecretStorage};
+#[cfg(feature = "gcp-secret-storage")]
+pub use gcp::GcpSecretStorage;
+#[cfg(feature = "vault-secret-storage")]
+pub use vault::{VaultAuth, VaultCipherKeys, VaultClient, VaultSecretStorage};
+
+use crate::gcp::{GcpService, SecretResult};
+use crate::protocol::state::PersistentNodeData;
+use crate::storage::Options;
+use async_trait::async_trait;
+
+use near_account_id::AccountId;
+
+#[async_trait]
+pub trait SecretStorage {
+    async fn store(&mut self, data: &PersistentNodeData) -> SecretResult<()>;
+    async fn load(&self) -> SecretResult<Option<PersistentNodeData>>;
+}
+
+pub type SecretStorageBox = Box<dyn SecretStorage + Send + Sync>;
+
+/// Keeps the node data in memory only, so it is lost once the node stops. Used when no other
+/// backend is configured.
+#[derive(Default)]
+pub struct MemorySecretStorage {
+    node_data: Option<PersistentNodeData>,
+}
+
+#[async_trait]
+impl SecretStorage for MemorySecretStorage {
+    async fn store(&mut self, data: &PersistentNodeData) -> SecretResult<()> {
+        tracing::info!("storing PersistentNodeData using MemorySecretStorage");
+        self.node_data = Some(data.clone());
+        Ok(())
+    }
+
+    async fn load(&self) -> SecretResult<Option<PersistentNodeData>> {
+        tracing::info!("loading PersistentNodeData using MemorySecretStorage");
+        Ok(self.node_data.clone())
+    }
+}
+
+/// Backend of the secret storage, picked with `--secret-storage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SecretStorageKind {
+    Gcp,
+    Aws,
+    Vault,
+    File,
+    Memory,
+}
+
+impl SecretStorageKind {
+    /// Backend picked from the options that are set, for when `--secret-storage` is not given.
+    fn infer(opts: &Options, has_gcp: bool) -> Self {
+        if opts.sk_share_secret_id.is_some() && has_gcp {
+            Self::Gcp
+        } else if opts.aws_sk_share_secret_id.is_some() {
+            Self::Aws
+        } else if opts.vault_sk_share_path.is_some() {
+            Self::Vault
+        } else if opts.sk_share_local_path.is_some() {
+            Self::File
+        } else {
+            Self::Memory
+        }
+    }
+}
+
+impl std::fmt::Display for SecretStorageKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Gcp => write!(f, "gcp"),
+            Self::Aws => write!(f, "aws"),
+            Self::Vault => write!(f, "vault"),
+            Self::File => write!(f, "file"),
+            Self::Memory => write!(f, "memory"),
+        }
+    }
+}
+
+/// Picks the backend configured in `opts`. Fails if it is configured to use a backend that the
+/// node was built without, rather than falling back to keeping the key share in memory.
+pub fn init(
+    gcp_service: Option<&GcpService>,
+    opts: &Options,
+    account_id: &AccountId,
+) -> anyhow::Result<SecretStorageBox> {
+    let kind = opts
+        .secret_storage
+        .unwrap_or_else(|| SecretStorageKind::infer(opts, gcp_service.is_some()));
+    open(kind, gcp_service, opts, account_id)
+}
+
+/// Opens the backend `kind` with its settings in `opts`, regardless of the backend that `opts`
+/// picks for the node itself.
+#[allow(unused_variables)]
+pub fn open(
+    kind: SecretStorageKind,
+    gcp_service: Option<&GcpService>,
+    opts: &Options,
+    account_id: &AccountId,
+) -> anyhow::Result<SecretStorageBox> {
+    match kind {
+        #[cfg(feature = "gcp-secret-storage")]
+        SecretStorageKind::Gcp => {
+            let (Some(gcp), Some(sk_share_secret_id)) = (gcp_service, &opts.sk_share_secret_id)
+            else {
+                anyhow::bail!("gcp secret storage requires `sk_share_secret_id`");
+            };
+            tracing::info!("using GcpSecretStorage");
+            Ok(Box::new(GcpSecretStorage::new(
+                &gcp.secret_manager,
+                sk_share_secret_id.clone(),
+            )))
+        }
+        #[cfg(feature = "aws-secret-storage")]
+        SecretStorageKind::Aws => {
+            let Some(aws_sk_share_secret_id) = &opts.aws_sk_share_secret_id else {
+                anyhow::bail!("aws secret storage requires `aws_sk_share_secret_id`");
+            };
+            tracing::info!("using AwsSecretStorage");
+            Ok(Box::new(AwsSecretStorage::new(
+                aws_sk_share_secret_id.clone(),
+            )))
+        }
+        #[cfg(feature = "vault-secret-storage")]
+        SecretStorageKind::Vault => {
+            let Some(vault_sk_share_path) = &opts.vault_sk_share_path else {
+                anyhow::bail!("vault secret storage requires `vault_sk_share_path`");
+            };
+            tracing::info!("using VaultSecretStorage");
+            Ok(Box::new(VaultSecretStorage::new(
+                VaultClient::from_options(opts)?,
+                vault_sk_share_path.clone(),
+            )))
+        }
+        #[cfg(feature = "file-secret-storage")]
+        SecretStorageKind::File => {
+            let Some(sk_share_local_path) = &opts.sk_share_local_path else {
+                anyhow::bail!("file secret storage requires `sk_share_local_path`");
+            };
+            let path = format!("{sk_share_local_path}-{account_id}");
+            tracing::info!("using FileSecretStorage with path: {}", path);
+            Ok(Box::new(FileSecretStorage::new(
+                &path,
+                FileEncryption::from_options(opts)?,
+            )))
+        }
+        SecretStorageKind::Memory => {
+            tracing::info!("using MemorySecretStorage");
+            Ok(Box::<MemorySecretStorage>::default())
+        }
+        #[allow(unreachable_patterns)]
+        kind => anyhow::bail!("the node was built without the `{kind}-secret-storage` feature"),
+    }
+}
+
+/// Upgrades the key share file of the file backend to the encryption configured in `opts`.
+#[allow(unused_variables)]
+pub async fn encrypt_key_share(opts: &Options, account_id: &AccountId) -> anyhow::Result<()> {
+    #[cfg(feature = "file-secret-storage")]
+    {
+        let Some(sk_share_local_path) = &opts.sk_share_local_path else {
+            anyhow::bail!("encrypting the key share requires `sk_share_local_path`");
+        };
+        let path = format!("{sk_share_local_path}-{account_id}");
+        let mut storage = FileSecretStorage::new(&path, FileEncryption::from_options(opts)?);
+        if storage.reencrypt().await? {
+            tracing::info!(path, "encrypted key share file");
+        } else {
+            tracing::warn!(path, "no key share file to encrypt");
+        }
+        Ok(())
+    }
+    #[cfg(not(feature = "file-secret-storage"))]
+    anyhow::bail!("the node was built without the `file-secret-storage` feature")
+}
+
+/// Loads the hex encoded cipher public and secret keys of the node from the secret at
+/// `vault_cipher_key_path`, for nodes that do not get them on the command line.
+#[allow(unused_variables)]
+pub async fn load_cipher_keys(opts: &Options) -> anyhow::Result<(String, String)> {
+    #[cfg(feature = "vault-secret-storage")]
+    {
+        let Some(vault_cipher_key_path) = &opts.vault_cipher_key_path else {
+            anyhow::bail!("the cipher keys are required unless `vault_cipher_key_path` is set");
+        };
+        let client = VaultClient::from_options(opts)?;
+        let Some(keys) = client
+            .read::<VaultCipherKeys>(vault_cipher_key_path)
+            .await?
+        else {
+            anyhow::bail!("no cipher keys stored in vault at {vault_cipher_key_path}");
+        };
+        Ok((keys.cipher_pk, keys.cipher_sk))
+    }
+    #[cfg(not(feature = "vault-secret-storage"))]
+    anyhow::bail!(
+        "the cipher keys are required when built without the `vault-secret-storage` feature"
+    )
+}
@@ -0,0 +1,400 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use super::SecretStorage;
+use crate::gcp::error::SecretStorageError;
+use crate::gcp::SecretResult;
+use crate::protocol::state::PersistentNodeData;
+use crate::storage::Options;
+
+/// Tokens are renewed once they are this close to expiring.
+const RENEW_BEFORE_EXPIRY: Duration = Duration::from_secs(60);
+
+/// How the node authenticates to Vault.
+#[derive(Clone)]
+pub enum VaultAuth {
+    /// A token issued out of band, e.g. by the Vault agent.
+    Token(String),
+    /// The AppRole auth method, which issues a fresh token whenever the current one can no
+    /// longer be renewed.
+    AppRole { role_id: String, secret_id: String },
+}
+
+struct VaultToken {
+    token: String,
+    renewable: bool,
+    /// `None` for tokens that never expire, such as root tokens.
+    expires_at: Option<Instant>,
+}
+
+impl VaultToken {
+    fn new(token: String, renewable: bool, ttl_secs: u64) -> Self {
+        Self {
+            token,
+            renewable,
+            expires_at: (ttl_secs > 0).then(|| Instant::now() + Duration::from_secs(ttl_secs)),
+        }
+    }
+
+    fn is_fresh(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => Instant::now() + RENEW_BEFORE_EXPIRY < expires_at,
+            None => true,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AuthResponse {
+    auth: AuthInfo,
+}
+
+#[derive(Deserialize)]
+struct AuthInfo {
+    client_token: String,
+    lease_duration: u64,
+    renewable: bool,
+}
+
+#[derive(Deserialize)]
+struct LookupResponse {
+    data: LookupInfo,
+}
+
+#[derive(Deserialize)]
+struct LookupInfo {
+    ttl: u64,
+    renewable: bool,
+}
+
+/// Envelope of the secrets read from and written to a KV version 2 engine.
+#[derive(Serialize, Deserialize)]
+struct KvData<T> {
+    data: T,
+}
+
+/// Client of the KV version 2 secrets engine of HashiCorp Vault, which keeps its token renewed.
+pub struct VaultClient {
+    client: reqwest::Client,
+    addr: String,
+    auth: VaultAuth,
+    token: RwLock<Option<VaultToken>>,
+}
+
+fn vault_error(err: impl std::fmt::Display) -> SecretStorageError {
+    SecretStorageError::VaultError(err.to_string())
+}
+
+impl VaultClient {
+    pub fn new(addr: &str, auth: VaultAuth) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            addr: addr.trim_end_matches('/').to_string(),
+            auth,
+            token: RwLock::new(None),
+        }
+    }
+
+    pub fn from_options(opts: &Options) -> anyhow::Result<Self> {
+        let Some(addr) = &opts.vault_addr else {
+            anyhow::bail!("vault storage requires `vault_addr`");
+        };
+        let auth = match (&opts.vault_token, &opts.vault_role_id, &opts.vault_secret_id) {
+            (Some(token), _, _) => VaultAuth::Token(token.clone()),
+            (None, Some(role_id), Some(secret_id)) => VaultAuth::AppRole {
+                role_id: role_id.clone(),
+                secret_id: secret_id.clone(),
+            },
+            _ => anyhow::bail!(
+                "vault storage requires either `vault_token` or `vault_role_id` and `vault_secret_id`"
+            ),
+        };
+        Ok(Self::new(addr, auth))
+    }
+
+    /// URL of the secret at `path`, which starts with the mount of the KV engine, e.g.
+    /// `secret/mpc-sk-share`. Paths without a mount are looked up in the `secret` mount.
+    fn kv_url(&self, path: &str) -> String {
+        let path = path.trim_matches('/');
+        let (mount, path) = path.split_once('/').unwrap_or(("secret", path));
+        format!("{}/v1/{mount}/data/{path}", self.addr)
+    }
+
+    async fn token(&self) -> SecretResult<String> {
+        if let Some(token) = self.token.read().await.as_ref() {
+            if token.is_fresh() {
+                return Ok(token.token.clone());
+            }
+        }
+
+        let mut current = self.token.write().await;
+        let renewed = match current.as_ref() {
+            Some(token) if token.is_fresh() => return Ok(token.token.clone()),
+            Some(token) if token.renewable => match self.renew(&token.token).await {
+                Ok(renewed) => Some(renewed),
+                Err(err) => {
+                    tracing::warn!(%err, "failed to renew vault token, logging in again");
+                    None
+                }
+            },
+            _ => None,
+        };
+        let token = match renewed {
+            Some(token) => token,
+            None => self.login().await?,
+        };
+        let value = token.token.clone();
+        *current = Some(token);
+        Ok(value)
+    }
+
+    async fn login(&self) -> SecretResult<VaultToken> {
+        match &self.auth {
+            VaultAuth::Token(token) => {
+                let lookup: LookupResponse = self
+                    .client
+                    .get(format!("{}/v1/auth/token/lookup-self", self.addr))
+                    .header("X-Vault-Token", token)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(vault_error)?
+                    .json()
+                    .await
+                    .map_err(vault_error)?;
+                Ok(VaultToken::new(
+                    token.clone(),
+                    lookup.data.renewable,
+                    lookup.data.ttl,
+                ))
+            }
+            VaultAuth::AppRole { role_id, secret_id } => {
+                tracing::info!("logging in to vault with approle");
+                let response: AuthResponse = self
+                    .client
+                    .post(format!("{}/v1/auth/approle/login", self.addr))
+                    .json(&serde_json::json!({
+                        "role_id": role_id,
+                        "secret_id": secret_id,
+                    }))
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(vault_error)?
+                    .json()
+                    .await
+                    .map_err(vault_error)?;
+                Ok(VaultToken::new(
+                    response.auth.client_token,
+                    response.auth.renewable,
+                    response.auth.lease_duration,
+                ))
+            }
+        }
+    }
+
+    async fn renew(&self, token: &str) -> SecretResult<VaultToken> {
+        tracing::info!("renewing vault token");
+        let response: AuthResponse = self
+            .client
+            .post(format!("{}/v1/auth/token/renew-self", self.addr))
+            .header("X-Vault-Token", token)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(vault_error)?
+            .json()
+            .await
+            .map_err(vault_error)?;
+        Ok(VaultToken::new(
+            response.auth.client_token,
+            response.auth.renewable,
+            response.auth.lease_duration,
+        ))
+    }
+
+    /// Reads the latest version of the secret at `path`, if there is one.
+    pub async fn read<T: DeserializeOwned>(&self, path: &str) -> SecretResult<Option<T>> {
+        let response = self
+            .client
+            .get(self.kv_url(path))
+            .header("X-Vault-Token", self.token().await?)
+            .send()
+            .await
+            .map_err(vault_error)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let secret: KvData<KvData<T>> = response
+            .error_for_status()
+            .map_err(vault_error)?
+            .json()
+            .await
+            .map_err(vault_error)?;
+        Ok(Some(secret.data.data))
+    }
+
+    /// Writes a new version of the secret at `path`.
+    pub async fn write<T: Serialize + Sync>(&self, path: &str, data: &T) -> SecretResult<()> {
+        self.client
+            .post(self.kv_url(path))
+            .header("X-Vault-Token", self.token().await?)
+            .json(&KvData { data })
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(vault_error)?;
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct TransitEncrypted {
+    ciphertext: String,
+}
+
+#[derive(Deserialize)]
+struct TransitDecrypted {
+    plaintext: String,
+}
+
+impl VaultClient {
+    /// URL of the `operation` on the key at `path` of a transit engine, where `path` starts with
+    /// the mount of the engine, e.g. `transit/mpc-sk-share`. Paths without a mount are looked up
+    /// in the `transit` mount.
+    fn transit_url(&self, operation: &str, path: &str) -> String {
+        let path = path.trim_matches('/');
+        let (mount, key) = path.rsplit_once('/').unwrap_or(("transit", path));
+        format!("{}/v1/{mount}/{operation}/{key}", self.addr)
+    }
+
+    /// Encrypts `plaintext` with the transit key at `path`, returning Vault's ciphertext.
+    pub async fn transit_encrypt(&self, path: &str, plaintext: &[u8]) -> SecretResult<String> {
+        let encrypted: KvData<TransitEncrypted> = self
+            .client
+            .post(self.transit_url("encrypt", path))
+            .header("X-Vault-Token", self.token().await?)
+            .json(&serde_json::json!({ "plaintext": STANDARD.encode(plaintext) }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(vault_error)?
+            .json()
+            .await
+            .map_err(vault_error)?;
+        Ok(encrypted.data.ciphertext)
+    }
+
+    /// Decrypts a `ciphertext` returned by [`Self::transit_encrypt`] for the same key.
+    pub async fn transit_decrypt(&self, path: &str, ciphertext: &str) -> SecretResult<Vec<u8>> {
+        let decrypted: KvData<TransitDecrypted> = self
+            .client
+            .post(self.transit_url("decrypt", path))
+            .header("X-Vault-Token", self.token().await?)
+            .json(&serde_json::json!({ "ciphertext": ciphertext }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(vault_error)?
+            .json()
+            .await
+            .map_err(vault_error)?;
+        STANDARD
+            .decode(decrypted.data.plaintext)
+            .map_err(vault_error)
+    }
+}
+
+/// Secret as stored in the KV engine. The node data is kept as a JSON string so that it shows
+/// up as a single field in Vault.
+#[derive(Serialize, Deserialize)]
+struct VaultSecret {
+    node_data: String,
+}
+
+/// Cipher keys of the node as stored in the KV engine, hex encoded like on the command line.
+#[derive(Serialize, Deserialize)]
+pub struct VaultCipherKeys {
+    pub cipher_pk: String,
+    pub cipher_sk: String,
+}
+
+/// Stores the node data in a KV version 2 secrets engine of HashiCorp Vault.
+pub struct VaultSecretStorage {
+    client: VaultClient,
+    sk_share_path: String,
+}
+
+impl VaultSecretStorage {
+    /// `sk_share_path` is the path of the secret including the mount of the KV engine, e.g.
+    /// `secret/mpc-sk-share`.
+    pub fn new(client: VaultClient, sk_share_path: String) -> Self {
+        Self {
+            client,
+            sk_share_path,
+        }
+    }
+}
+
+#[async_trait]
+impl SecretStorage for VaultSecretStorage {
+    async fn store(&mut self, data: &PersistentNodeData) -> SecretResult<()> {
+        tracing::info!("storing PersistentNodeData using VaultSecretStorage");
+        let secret = VaultSecret {
+            node_data: serde_json::to_string(data)?,
+        };
+        self.client.write(&self.sk_share_path, &secret).await
+    }
+
+    async fn load(&self) -> SecretResult<Option<PersistentNodeData>> {
+        tracing::info!("loading PersistentNodeData using VaultSecretStorage");
+        let Some(secret) = self.client.read::<VaultSecret>(&self.sk_share_path).await? else {
+            tracing::info!("no key share stored yet, presuming it is missing");
+            return Ok(None);
+        };
+        match serde_json::from_str(&secret.node_data) {
+            Ok(persistent_node_data) => Ok(Some(persistent_node_data)),
+            Err(err) => {
+                tracing::error!(%err, "failed to convert stored data to key share, presuming it is missing");
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kv_url() {
+        let client = VaultClient::new("http://vault:8200/", VaultAuth::Token("t".into()));
+        assert_eq!(
+            client.kv_url("kv/mpc/sk-share"),
+            "http://vault:8200/v1/kv/data/mpc/sk-share"
+        );
+        assert_eq!(
+            client.kv_url("sk-share"),
+            "http://vault:8200/v1/secret/data/sk-share"
+        );
+    }
+
+    #[test]
+    fn test_transit_url() {
+        let client = VaultClient::new("http://vault:8200", VaultAuth::Token("t".into()));
+        assert_eq!(
+            client.transit_url("encrypt", "kms/mpc/sk-share"),
+            "http://vault:8200/v1/kms/mpc/encrypt/sk-share"
+        );
+        assert_eq!(
+            client.transit_url("decrypt", "sk-share"),
+            "http://vault:8200/v1/transit/decrypt/sk-share"
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Snapshots of the persistent state of a node, so that it can be moved to new hardware while
+//! keeping its key share. Snapshots are encrypted to the node's own cipher key, so only a node
+//! started with the same cipher secret key is able to import them.
+
+use std::path::Path;
+
+use chrono::Utc;
+use mpc_keys::hpke;
+use near_account_id::AccountId;
+use serde::{Deserialize, Serialize};
+
+use super::secret_storage::SecretStorageBox;
+use crate::protocol::state::PersistentNodeData;
+
+const ASSOCIATED_DATA: &[u8] = b"mpc-node-state-snapshot";
+
+#[derive(Serialize, Deserialize)]
+pub struct NodeStateSnapshot {
+    pub account_id: AccountId,
+    pub cipher_pk: hpke::PublicKey,
+    /// Unix timestamp in seconds of when the snapshot was taken.
+    pub exported_at: i64,
+    pub node_data: PersistentNodeData,
+}
+
+/// Writes the state kept in `key_storage` to `path`, encrypted to `cipher_sk`.
+pub async fn export(
+    key_storage: &SecretStorageBox,
+    account_id: &AccountId,
+    cipher_sk: &hpke::SecretKey,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let node_data = key_storage
+        .load()
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("node has no stored state to export"))?;
+    tracing::info!(epoch = node_data.epoch, "exporting node state");
+
+    let snapshot = NodeStateSnapshot {
+        account_id: account_id.clone(),
+        cipher_pk: cipher_sk.public_key(),
+        exported_at: Utc::now().timestamp(),
+        node_data,
+    };
+    let ciphered = cipher_sk
+        .public_key()
+        .encrypt(&serde_json::to_vec(&snapshot)?, ASSOCIATED_DATA)
+        .map_err(|err| anyhow::anyhow!("failed to encrypt node state: {err}"))?;
+    tokio::fs::write(path, serde_json::to_vec(&ciphered)?).await?;
+
+    tracing::info!(path = %path.display(), "node state exported");
+    Ok(())
+}
+
+/// Reads a snapshot from `path` and stores it into `key_storage`. The snapshot must have been
+/// exported by the same account with the same cipher key. Existing state is only overwritten
+/// when `force` is set, to not accidentally replace a key share that is still in use.
+pub async fn import(
+    key_storage: &mut SecretStorageBox,
+    account_id: &AccountId,
+    cipher_sk: &hpke::SecretKey,
+    path: &Path,
+    force: bool,
+) -> anyhow::Result<()> {
+    let ciphered: hpke::Ciphered = serde_json::from_slice(&tokio::fs::read(path).await?)?;
+    let plaintext = cipher_sk
+        .decrypt(&ciphered, ASSOCIATED_DATA)
+        .map_err(|err| anyhow::anyhow!("failed to decrypt node state: {err}"))?;
+    let snapshot: NodeStateSnapshot = serde_json::from_slice(&plaintext)?;
+
+    if snapshot.account_id != *account_id {
+        anyhow::bail!(
+            "node state was exported by {}, not by {account_id}",
+            snapshot.account_id
+        );
+    }
+    if snapshot.cipher_pk != cipher_sk.public_key() {
+        anyhow::bail!("node state was exported with a different cipher key");
+    }
+    if let Some(existing) = key_storage.load().await? {
+        if !force {
+            anyhow::bail!(
+                "node already has state for epoch {}, refusing to overwrite it without --force",
+                existing.epoch
+            );
+        }
+        tracing::warn!(epoch = existing.epoch, "overwriting existing node state");
+    }
+
+    tracing::info!(
+        epoch = snapshot.node_data.epoch,
+        exported_at = snapshot.exported_at,
+        "importing node state"
+    );
+    key_storage.store(&snapshot.node_data).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{secret_storage, Options};
+    use k256::elliptic_curve::Field;
+    use k256::{ProjectivePoint, Scalar};
+
+    fn memory_storage(account_id: &AccountId) -> SecretStorageBox {
+        let opts = Options {
+            env: "test".to_string(),
+            gcp_project_id: "test".to_string(),
+            sk_share_secret_id: None,
+            aws_sk_share_secret_id: None,
+            gcp_datastore_url: None,
+            sk_share_local_path: None,
+            sk_share_local_passphrase: None,
+            sk_share_local_transit_key: None,
+            secret_storage: None,
+            vault_addr: None,
+            vault_token: None,
+            vault_role_id: None,
+            vault_secret_id: None,
+            vault_sk_share_path: None,
+            vault_cipher_key_path: None,
+            sk_share_backup_pk: None,
+            sk_share_backup_path: None,
+            redis_url: "redis://localhost".to_string(),
+        };
+        secret_storage::init(None, &opts, account_id).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_export_import_roundtrip() {
+        let account_id: AccountId = "node.test".parse().unwrap();
+        let (cipher_sk, _) = hpke::generate();
+        let path = std::env::temp_dir().join(format!("{}-{account_id}", std::process::id()));
+
+        let mut old_storage = memory_storage(&account_id);
+        let private_share = Scalar::random(&mut rand::thread_rng());
+        old_storage
+            .store(&PersistentNodeData {
+                epoch: 3,
+                private_share,
+                public_key: (ProjectivePoint::GENERATOR * private_share).to_affine(),
+            })
+            .await
+            .unwrap();
+        export(&old_storage, &account_id, &cipher_sk, &path)
+            .await
+            .unwrap();
+
+        // Only the same node can import the state.
+        let mut new_storage = memory_storage(&account_id);
+        let other_account_id: AccountId = "other.test".parse().unwrap();
+        assert!(import(
+            &mut new_storage,
+            &other_account_id,
+            &cipher_sk,
+            &path,
+            false
+        )
+        .await
+        .is_err());
+        let (other_cipher_sk, _) = hpke::generate();
+        assert!(import(
+            &mut new_storage,
+            &account_id,
+            &other_cipher_sk,
+            &path,
+            false
+        )
+        .await
+        .is_err());
+
+        import(&mut new_storage, &account_id, &cipher_sk, &path, false)
+            .await
+            .unwrap();
+        let imported = new_storage.load().await.unwrap().unwrap();
+        assert_eq!(imported.epoch, 3);
+        assert_eq!(imported.private_share, private_share);
+
+        // Existing state is only replaced when forced.
+        assert!(
+            import(&mut new_storage, &account_id, &cipher_sk, &path, false)
+                .await
+                .is_err()
+        );
+        import(&mut new_storage, &account_id, &cipher_sk, &path, true)
+            .await
+            .unwrap();
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,19 +1,21 @@
 use crate::protocol::triple::{Triple, TripleId};
+use crate::storage::StorageCipher;
 
 use deadpool_redis::Pool;
-use redis::{AsyncCommands, FromRedisValue, RedisWrite, ToRedisArgs};
+use redis::AsyncCommands;
 
 use near_account_id::AccountId;
 
 type TripleResult<T> = std::result::Result<T, anyhow::Error>;
 
 // Can be used to "clear" redis storage in case of a breaking change
-const TRIPLE_STORAGE_VERSION: &str = "v1";
+const TRIPLE_STORAGE_VERSION: &str = "v2";
 
-pub fn init(pool: &Pool, account_id: &AccountId) -> TripleRedisStorage {
+pub fn init(pool: &Pool, account_id: &AccountId, cipher: &StorageCipher) -> TripleRedisStorage {
     TripleRedisStorage {
         redis_pool: pool.clone(),
         node_account_id: account_id.clone(),
+        cipher: cipher.clone(),
     }
 }
 
@@ -21,12 +23,14 @@ pub fn init(pool: &Pool, account_id: &AccountId) -> TripleRedisStorage {
 pub struct TripleRedisStorage {
     redis_pool: Pool,
     node_account_id: AccountId,
+    cipher: StorageCipher,
 }
 
 impl TripleRedisStorage {
     pub async fn insert(&self, triple: Triple) -> TripleResult<()> {
+        let encrypted = self.cipher.encrypt(&triple)?;
         let mut conn = self.redis_pool.get().await?;
-        conn.hset::<&str, TripleId, Triple, ()>(&self.triple_key(), triple.id, triple)
+        conn.hset::<&str, TripleId, Vec<u8>, ()>(&self.triple_key(), triple.id, encrypted)
             .await?;
         Ok(())
     }
@@ -57,12 +61,12 @@ impl TripleRedisStorage {
             tracing::error!("Can not take mine triple as foreign: {:?}", id);
             return Ok(None);
         }
-        let result: Option<Triple> = conn.hget(self.triple_key(), id).await?;
+        let result: Option<Vec<u8>> = conn.hget(self.triple_key(), id).await?;
         match result {
-            Some(triple) => {
+            Some(encrypted) => {
                 conn.hdel::<&str, TripleId, ()>(&self.triple_key(), *id)
                     .await?;
-                Ok(Some(triple))
+                Ok(Some(self.cipher.decrypt(&encrypted)?))
             }
             None => Ok(None),
         }
@@ -110,32 +114,3 @@ impl TripleRedisStorage {
         )
     }
 }
-
-impl ToRedisArgs for Triple {
-    fn write_redis_args<W>(&self, out: &mut W)
-    where
-        W: ?Sized + RedisWrite,
-    {
-        match serde_json::to_string(self) {
-            std::result::Result::Ok(json) => out.write_arg(json.as_bytes()),
-            Err(e) => {
-                tracing::error!("Failed to serialize Triple: {}", e);
-                out.write_arg("failed_to_serialize".as_bytes())
-            }
-        }
-    }
-}
-
-impl FromRedisValue for Triple {
-    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
-        let json: String = String::from_redis_value(v)?;
-
-        serde_json::from_str(&json).map_err(|e| {
-            redis::RedisError::from((
-                redis::ErrorKind::TypeError,
-                "Failed to deserialize Triple",
-                e.to_string(),
-            ))
-        })
-    }
-}
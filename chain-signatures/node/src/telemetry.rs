@@ -0,0 +1,93 @@
+//! Export of the spans of the node to an OpenTelemetry collector over OTLP, so that the work of
+//! the indexer, the protocol and the mesh can be viewed as traces in e.g. Jaeger or Tempo.
+
+use near_account_id::AccountId;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::trace::{self, RandomIdGenerator, Sampler, Tracer};
+use opentelemetry::sdk::Resource;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_semantic_conventions::resource::{SERVICE_NAME, SERVICE_VERSION};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+#[derive(Debug, Clone, clap::Parser)]
+#[group(id = "telemetry_options")]
+pub struct Options {
+    /// OTLP/HTTP endpoint of the OpenTelemetry collector to export spans to, such as
+    /// `http://localhost:4318/v1/traces`. Spans are not exported when not provided. Only the
+    /// spans enabled by `RUST_LOG` are exported.
+    #[clap(long, env("MPC_OTLP_ENDPOINT"))]
+    pub otlp_endpoint: Option<String>,
+    /// Fraction of the traces to export, from 0 to 1.
+    #[clap(long, env("MPC_OTLP_SAMPLE_RATIO"), default_value = "1.0")]
+    pub otlp_sample_ratio: f64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            otlp_sample_ratio: 1.0,
+        }
+    }
+}
+
+impl Options {
+    pub fn into_str_args(self) -> Vec<String> {
+        let mut args = vec![
+            "--otlp-sample-ratio".to_string(),
+            self.otlp_sample_ratio.to_string(),
+        ];
+        if let Some(otlp_endpoint) = self.otlp_endpoint {
+            args.extend(["--otlp-endpoint".to_string(), otlp_endpoint]);
+        }
+        args
+    }
+}
+
+/// Layer that exports the spans of the node, or `None` when no collector is configured.
+pub fn layer<S>(
+    options: &Options,
+    account_id: &AccountId,
+) -> anyhow::Result<Option<OpenTelemetryLayer<S, Tracer>>>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    let Some(otlp_endpoint) = &options.otlp_endpoint else {
+        return Ok(None);
+    };
+
+    let resource = Resource::new(vec![
+        KeyValue::new(SERVICE_NAME, "mpc-node"),
+        KeyValue::new(SERVICE_VERSION, env!("CARGO_PKG_VERSION")),
+        KeyValue::new("account_id", account_id.to_string()),
+    ]);
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+        options.otlp_sample_ratio,
+    )));
+    // The layer is set up before the runtime of the node is started, so the spans are exported
+    // from a runtime of their own.
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(otlp_endpoint),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_sampler(sampler)
+                .with_id_generator(RandomIdGenerator::default())
+                .with_resource(resource),
+        )
+        .install_batch(opentelemetry::runtime::TokioCurrentThread)?;
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Exports the spans that have not been exported yet.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
@@ -69,6 +69,7 @@ pub struct ReshareProtocol {
     old_participants: Vec<Participant>,
     new_participants: Vec<Participant>,
     me: Participant,
+    old_threshold: usize,
     threshold: usize,
     private_share: Option<SecretKeyShare>,
     protocol: Arc<RwLock<Box<dyn Protocol<Output = SecretKeyShare> + Send + Sync>>>,
@@ -84,15 +85,17 @@ impl ReshareProtocol {
         let old_participants = contract_state.old_participants.keys_vec();
         let new_participants = contract_state.new_participants.keys_vec();
         tracing::debug!(
-            "ReshareProtocol::new old participants {:?} new participants {:?} me {:?}",
+            "ReshareProtocol::new old participants {:?} new participants {:?} me {:?} old threshold {} new threshold {}",
             old_participants,
             new_participants,
-            me
+            me,
+            contract_state.old_threshold,
+            contract_state.threshold,
         );
         Ok(Self {
             protocol: Arc::new(RwLock::new(Box::new(cait_sith::reshare::<Secp256k1>(
                 &old_participants,
-                contract_state.threshold,
+                contract_state.old_threshold,
                 &new_participants,
                 contract_state.threshold,
                 me,
@@ -101,6 +104,7 @@ impl ReshareProtocol {
             )?))),
             private_share,
             me,
+            old_threshold: contract_state.old_threshold,
             threshold: contract_state.threshold,
             old_participants,
             new_participants,
@@ -117,7 +121,7 @@ impl ReshareProtocol {
         );
         *self.write().await = Box::new(cait_sith::reshare::<Secp256k1>(
             &self.old_participants,
-            self.threshold,
+            self.old_threshold,
             &self.new_participants,
             self.threshold,
             self.me,
@@ -0,0 +1,268 @@
+//! Operator facing endpoints. These are only served when the node is started with an admin
+//! token, and every request must carry that token as a bearer `Authorization` header.
+
+use super::{state_view, AxumState, StateView};
+use crate::audit::{self, AuditEvent};
+use crate::config::{FileConfig, OverrideConfig};
+use crate::mesh::connection::PeerStatus;
+use crate::protocol::AdminCommand;
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use near_primitives::types::BlockHeight;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tokio::sync::oneshot;
+
+/// How long to wait for the protocol loop to answer a request of the admin API.
+const PROTOCOL_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/admin/state", get(state))
+        .route("/admin/refresh", post(refresh))
+        .route("/admin/resync", post(resync))
+        .route("/admin/stockpile", post(stockpile))
+        .route("/admin/reload", post(reload))
+}
+
+/// Compares the tokens in constant time, so that the time it takes does not tell how much of the
+/// token was guessed right. Hashing first makes that hold for the length of the token as well.
+fn tokens_match(provided: &str, token: &str) -> bool {
+    Sha256::digest(provided.as_bytes())
+        .ct_eq(&Sha256::digest(token.as_bytes()))
+        .into()
+}
+
+/// Checks the admin token of a call to `endpoint`, and records the call in the audit log
+/// whether it is authorized or not.
+fn authorize(
+    state: &AxumState,
+    headers: &HeaderMap,
+    endpoint: &str,
+    remote_addr: SocketAddr,
+) -> Result<(), StatusCode> {
+    let Some(token) = &state.admin_token else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let provided = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let authorized = provided.is_some_and(|provided| tokens_match(provided, token));
+    audit::record(
+        "admin",
+        AuditEvent::AdminCall {
+            endpoint: endpoint.to_string(),
+            remote_addr,
+            authorized,
+        },
+    );
+    if !authorized {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(())
+}
+
+/// New stockpile targets of the node. Only the provided values get changed.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct StockpileRequest {
+    pub min_triples: Option<u32>,
+    pub max_triples: Option<u32>,
+    pub min_presignatures: Option<u32>,
+    pub max_presignatures: Option<u32>,
+}
+
+impl StockpileRequest {
+    fn is_valid(&self) -> bool {
+        let ordered = |min: Option<u32>, max: Option<u32>| match (min, max) {
+            (Some(min), Some(max)) => min <= max,
+            _ => true,
+        };
+        ordered(self.min_triples, self.max_triples)
+            && ordered(self.min_presignatures, self.max_presignatures)
+    }
+
+    fn into_override(self) -> OverrideConfig {
+        let mut triple = serde_json::Map::new();
+        if let Some(min_triples) = self.min_triples {
+            triple.insert("min_triples".into(), min_triples.into());
+        }
+        if let Some(max_triples) = self.max_triples {
+            triple.insert("max_triples".into(), max_triples.into());
+        }
+
+        let mut presignature = serde_json::Map::new();
+        if let Some(min_presignatures) = self.min_presignatures {
+            presignature.insert("min_presignatures".into(), min_presignatures.into());
+        }
+        if let Some(max_presignatures) = self.max_presignatures {
+            presignature.insert("max_presignatures".into(), max_presignatures.into());
+        }
+
+        let mut entries = serde_json::Map::new();
+        if !triple.is_empty() {
+            entries.insert("triple".into(), triple.into());
+        }
+        if !presignature.is_empty() {
+            entries.insert("presignature".into(), presignature.into());
+        }
+        OverrideConfig::new(entries.into())
+    }
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn stockpile(
+    Extension(state): Extension<Arc<AxumState>>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<StockpileRequest>,
+) -> Result<Json<StockpileRequest>, StatusCode> {
+    authorize(&state, &headers, "/admin/stockpile", remote_addr)?;
+    if !request.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    tracing::info!(?request, "changing stockpile targets");
+    if let Err(err) = state
+        .config_sender
+        .send(request.clone().into_override())
+        .await
+    {
+        tracing::error!(?err, "failed to forward stockpile targets to the protocol");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    Ok(Json(request))
+}
+
+/// Everything an operator needs to see where a node is at.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminStateView {
+    /// The state of the protocol state machine of the node, e.g. `Running`.
+    pub protocol_state: String,
+    /// The same view of the node that its peers get, including its triple and presignature
+    /// counts.
+    pub state: StateView,
+    /// How the node sees each of its peers.
+    pub peers: Vec<PeerStatus>,
+    pub indexer: IndexerView,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexerView {
+    /// The last block the indexer has processed.
+    pub latest_block_height: BlockHeight,
+    pub is_running: bool,
+    pub is_behind: bool,
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn state(
+    Extension(state): Extension<Arc<AxumState>>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Json<AdminStateView>, StatusCode> {
+    authorize(&state, &headers, "/admin/state", remote_addr)?;
+    let (reply, peers) = oneshot::channel();
+    send_command(&state, AdminCommand::PeerStatuses(reply)).await?;
+    let peers = match tokio::time::timeout(PROTOCOL_TIMEOUT, peers).await {
+        Ok(Ok(peers)) => peers,
+        _ => {
+            tracing::warn!("protocol did not report the status of its peers in time");
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+    };
+
+    Ok(Json(AdminStateView {
+        protocol_state: state.protocol_state.read().await.to_string(),
+        state: state_view(&state).await,
+        peers,
+        indexer: IndexerView {
+            latest_block_height: state.indexer.latest_block_height().await,
+            is_running: state.indexer.is_running().await,
+            is_behind: state.indexer.is_behind().await,
+        },
+    }))
+}
+
+/// Makes the node fetch the contract state and config and ping its peers right away, instead
+/// of waiting for the next periodic refresh.
+#[tracing::instrument(level = "debug", skip_all)]
+async fn refresh(
+    Extension(state): Extension<Arc<AxumState>>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<StatusCode, StatusCode> {
+    authorize(&state, &headers, "/admin/refresh", remote_addr)?;
+    send_command(&state, AdminCommand::Refresh).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Reloads the config file of the node, the same as sending it a `SIGHUP`. Responds with the
+/// settings that got applied.
+#[tracing::instrument(level = "debug", skip_all)]
+async fn reload(
+    Extension(state): Extension<Arc<AxumState>>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Json<FileConfig>, StatusCode> {
+    authorize(&state, &headers, "/admin/reload", remote_addr)?;
+    let Some(config_reloader) = &state.config_reloader else {
+        tracing::warn!("node was started without a config file to reload");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    match config_reloader.reload().await {
+        Ok(config) => Ok(Json(config)),
+        Err(err) => {
+            tracing::error!(
+                ?err,
+                "failed to reload config file, keeping the current config"
+            );
+            Err(StatusCode::UNPROCESSABLE_ENTITY)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResyncRequest {
+    /// The block height the indexer starts over from.
+    pub block_height: BlockHeight,
+}
+
+/// Makes the indexer start over from the given block height, e.g. to pick up sign requests of
+/// blocks that it has missed.
+#[tracing::instrument(level = "debug", skip_all)]
+async fn resync(
+    Extension(state): Extension<Arc<AxumState>>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<ResyncRequest>,
+) -> Result<StatusCode, StatusCode> {
+    authorize(&state, &headers, "/admin/resync", remote_addr)?;
+    state.indexer.resync(request.block_height).await;
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn send_command(state: &AxumState, command: AdminCommand) -> Result<(), StatusCode> {
+    state.admin_sender.send(command).await.map_err(|err| {
+        tracing::error!(?err, "failed to forward admin command to the protocol");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokens_match;
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("secret-token", "secret-token"));
+        assert!(!tokens_match("secret-tokem", "secret-token"));
+        assert!(!tokens_match("secret", "secret-token"));
+        assert!(!tokens_match("", "secret-token"));
+    }
+}
@@ -20,6 +20,8 @@ pub enum Error {
     Message(#[from] SendError<MpcMessage>),
     #[error(transparent)]
     Rpc(#[from] near_fetch::Error),
+    #[error("messages are only accepted over the authenticated quic mesh")]
+    MeshAuthRequired,
 }
 
 impl Error {
@@ -30,6 +32,7 @@ impl Error {
             Error::Cryptography(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::Message(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::Rpc(_) => StatusCode::BAD_REQUEST,
+            Error::MeshAuthRequired => StatusCode::FORBIDDEN,
         }
     }
 }
@@ -0,0 +1,113 @@
+//! Probes for orchestrators such as Kubernetes. `/healthz` tells whether the node is alive at
+//! all, while `/readyz` tells whether it can take part in signing, so that nodes which are still
+//! waiting for consensus or lost their peers do not get routed to.
+
+use super::AxumState;
+use crate::protocol::{AdminCommand, NodeState};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Extension, Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How long a probe waits on the protocol before it considers the node stuck. Probes are
+/// expected to answer quickly, so this is much shorter than the timeout of the admin API.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+}
+
+/// Why a node is or is not ready to take part in signing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Readiness {
+    pub ready: bool,
+    /// The state of the protocol state machine of the node, e.g. `Running`.
+    pub protocol_state: String,
+    /// Whether the node holds a key share, which is only the case while running or resharing.
+    pub has_keyshare: bool,
+    /// Peers that answered the last ping, including this node.
+    pub reachable_peers: usize,
+    /// Peers needed to sign, or `None` if the node has no key share yet.
+    pub threshold: Option<usize>,
+    /// Whether the indexer is running and on track with the chain.
+    pub indexer_caught_up: bool,
+}
+
+/// The node is alive as long as its protocol state can be read and its indexer keeps going.
+#[tracing::instrument(level = "debug", skip_all)]
+async fn healthz(Extension(state): Extension<Arc<AxumState>>) -> StatusCode {
+    if tokio::time::timeout(PROBE_TIMEOUT, state.protocol_state.read())
+        .await
+        .is_err()
+    {
+        tracing::warn!("healthz: protocol state is locked up");
+        return StatusCode::SERVICE_UNAVAILABLE;
+    }
+    if !state.indexer.is_running().await {
+        tracing::warn!("healthz: indexer is not running");
+        return StatusCode::SERVICE_UNAVAILABLE;
+    }
+    StatusCode::OK
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn readyz(Extension(state): Extension<Arc<AxumState>>) -> (StatusCode, Json<Readiness>) {
+    let (protocol_state, threshold) =
+        match tokio::time::timeout(PROBE_TIMEOUT, state.protocol_state.read()).await {
+            Ok(protocol_state) => {
+                let threshold = match &*protocol_state {
+                    NodeState::Running(running) => Some(running.threshold),
+                    NodeState::Resharing(resharing) => Some(resharing.threshold),
+                    _ => None,
+                };
+                (protocol_state.to_string(), threshold)
+            }
+            Err(_) => ("Unknown".to_string(), None),
+        };
+    let reachable_peers = reachable_peers(&state).await;
+    let indexer_caught_up = state.indexer.is_stable().await;
+
+    let has_keyshare = threshold.is_some();
+    let ready =
+        indexer_caught_up && threshold.map_or(false, |threshold| reachable_peers >= threshold);
+    let readiness = Readiness {
+        ready,
+        protocol_state,
+        has_keyshare,
+        reachable_peers,
+        threshold,
+        indexer_caught_up,
+    };
+    if ready {
+        (StatusCode::OK, Json(readiness))
+    } else {
+        tracing::debug!(?readiness, "readyz: node is not ready");
+        (StatusCode::SERVICE_UNAVAILABLE, Json(readiness))
+    }
+}
+
+/// Peers that answered the last ping, as seen by the protocol loop. Zero if the protocol loop
+/// does not answer in time. Probes do not wait for room in the command queue, so that a stuck
+/// protocol loop does not pile them up.
+async fn reachable_peers(state: &AxumState) -> usize {
+    let (reply, peers) = oneshot::channel();
+    if let Err(err) = state
+        .admin_sender
+        .try_send(AdminCommand::PeerStatuses(reply))
+    {
+        tracing::warn!(?err, "readyz: failed to ask the protocol for its peers");
+        return 0;
+    }
+    match tokio::time::timeout(PROBE_TIMEOUT, peers).await {
+        Ok(Ok(peers)) => peers.iter().filter(|peer| peer.active).count(),
+        _ => {
+            tracing::warn!("readyz: protocol did not report the status of its peers in time");
+            0
+        }
+    }
+}
@@ -1,9 +1,18 @@
+mod admin;
 mod error;
+mod health;
+mod subscribe;
+
+pub use admin::{AdminStateView, IndexerView};
+pub use health::Readiness;
 
 use self::error::Error;
+use crate::config::{ConfigReloader, OverrideConfig};
+use crate::grpc::MeshService;
+use crate::hsm::CipherKey;
 use crate::indexer::Indexer;
-use crate::protocol::message::SignedMessage;
-use crate::protocol::{MpcMessage, NodeState};
+use crate::protocol::message::{ReplayWindow, SignedMessage};
+use crate::protocol::{AdminCommand, CryptographicError, MpcMessage, NodeState, SignEventSender};
 use crate::web::error::Result;
 use anyhow::Context;
 use axum::http::StatusCode;
@@ -11,33 +20,75 @@ use axum::routing::{get, post};
 use axum::{Extension, Json, Router};
 use axum_extra::extract::WithRejection;
 use cait_sith::protocol::Participant;
-use mpc_keys::hpke::{self, Ciphered};
+use mpc_keys::hpke::Ciphered;
 use near_primitives::types::BlockHeight;
 use prometheus::{Encoder, TextEncoder};
 use serde::{Deserialize, Serialize};
-use std::{net::SocketAddr, sync::Arc};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 use tokio::sync::{mpsc::Sender, RwLock};
 
 struct AxumState {
     sender: Sender<MpcMessage>,
+    config_sender: Sender<OverrideConfig>,
+    admin_sender: Sender<AdminCommand>,
     protocol_state: Arc<RwLock<NodeState>>,
-    cipher_sk: hpke::SecretKey,
+    cipher_sk: CipherKey,
+    replay_window: Arc<Mutex<ReplayWindow>>,
     indexer: Indexer,
+    sign_events: SignEventSender,
+    admin_token: Option<String>,
+    config_reloader: Option<ConfigReloader>,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     port: u16,
     sender: Sender<MpcMessage>,
-    cipher_sk: hpke::SecretKey,
+    config_sender: Sender<OverrideConfig>,
+    admin_sender: Sender<AdminCommand>,
+    cipher_sk: CipherKey,
     protocol_state: Arc<RwLock<NodeState>>,
     indexer: Indexer,
+    sign_events: SignEventSender,
+    admin_token: Option<String>,
+    config_reloader: Option<ConfigReloader>,
 ) -> anyhow::Result<()> {
     tracing::info!("running a node");
+    // All transports share the replay window, so a message can not be replayed over another.
+    let replay_window = Arc::new(Mutex::new(ReplayWindow::default()));
+    // gRPC requests are served on the same port and get routed by their `/mesh.Mesh/*` path.
+    let grpc = tonic::transport::server::Routes::new(MeshService::new(
+        sender.clone(),
+        protocol_state.clone(),
+        cipher_sk.clone(),
+        replay_window.clone(),
+    ))
+    .into_router();
+    // QUIC is served over UDP, so it can use the same port number as the rest.
+    let quic = crate::quic::MeshService::new(
+        sender.clone(),
+        protocol_state.clone(),
+        cipher_sk.clone(),
+        replay_window.clone(),
+    );
+    tokio::spawn(async move {
+        if let Err(err) = quic.run(port).await {
+            tracing::error!(?err, "quic server stopped");
+        }
+    });
+
     let axum_state = AxumState {
         sender,
+        config_sender,
+        admin_sender,
         protocol_state,
         cipher_sk,
+        replay_window,
         indexer,
+        sign_events,
+        admin_token,
+        config_reloader,
     };
 
     let app = Router::new()
@@ -52,12 +103,16 @@ pub async fn run(
         .route("/msg", post(msg))
         .route("/state", get(state))
         .route("/metrics", get(metrics))
-        .layer(Extension(Arc::new(axum_state)));
+        .merge(admin::router())
+        .merge(health::router())
+        .merge(subscribe::router())
+        .layer(Extension(Arc::new(axum_state)))
+        .merge(grpc);
 
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     tracing::info!(?addr, "starting http server");
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 
@@ -75,15 +130,24 @@ async fn msg(
     Extension(state): Extension<Arc<AxumState>>,
     WithRejection(Json(encrypted), _): WithRejection<Json<Vec<Ciphered>>, Error>,
 ) -> Result<()> {
+    if crate::quic::mtls_enabled() {
+        return Err(Error::MeshAuthRequired);
+    }
     for encrypted in encrypted.into_iter() {
         let message = match SignedMessage::decrypt(
             &state.cipher_sk,
             &state.protocol_state,
+            &state.replay_window,
             encrypted,
         )
         .await
         {
             Ok(msg) => msg,
+            // Retried requests deliver the same messages again, which is not an error.
+            Err(CryptographicError::ReplayedMessage { from, seq }) => {
+                tracing::warn!(?from, seq, "dropping a replayed message");
+                continue;
+            }
             Err(err) => {
                 tracing::error!(?err, "failed to decrypt or verify an encrypted message");
                 return Err(err.into());
@@ -130,6 +194,10 @@ pub enum StateView {
 #[tracing::instrument(level = "debug", skip_all)]
 async fn state(Extension(state): Extension<Arc<AxumState>>) -> Result<Json<StateView>> {
     tracing::debug!("fetching state");
+    Ok(Json(state_view(&state).await))
+}
+
+async fn state_view(state: &AxumState) -> StateView {
     let latest_block_height = state.indexer.latest_block_height().await;
     let is_stable = state.indexer.is_stable().await;
     let protocol_state = state.protocol_state.read().await;
@@ -146,7 +214,7 @@ async fn state(Extension(state): Extension<Arc<AxumState>>) -> Result<Json<State
             let presignature_potential_count = presignature_read.len_potential().await;
             let participants = state.participants.keys_vec();
 
-            Ok(Json(StateView::Running {
+            StateView::Running {
                 participants,
                 triple_count,
                 triple_mine_count,
@@ -156,28 +224,28 @@ async fn state(Extension(state): Extension<Arc<AxumState>>) -> Result<Json<State
                 presignature_potential_count,
                 latest_block_height,
                 is_stable,
-            }))
+            }
         }
         NodeState::Resharing(state) => {
             let old_participants = state.old_participants.keys_vec();
             let new_participants = state.new_participants.keys_vec();
-            Ok(Json(StateView::Resharing {
+            StateView::Resharing {
                 old_participants,
                 new_participants,
                 latest_block_height,
                 is_stable,
-            }))
+            }
         }
         NodeState::Joining(state) => {
             let participants = state.participants.keys_vec();
-            Ok(Json(StateView::Joining {
+            StateView::Joining {
                 participants,
                 latest_block_height,
-            }))
+            }
         }
         _ => {
             tracing::debug!("not running, state unavailable");
-            Ok(Json(StateView::NotRunning))
+            StateView::NotRunning
         }
     }
 }
@@ -0,0 +1,55 @@
+//! Streams the lifecycle events of sign requests over a WebSocket, so that clients do not
+//! have to poll the contract to find out where their request is at.
+
+use super::AxumState;
+use crate::protocol::SignEvent;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::response::Response;
+use axum::routing::get;
+use axum::{Extension, Router};
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+pub fn router() -> Router {
+    Router::new().route("/subscribe", get(subscribe))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn subscribe(Extension(state): Extension<Arc<AxumState>>, ws: WebSocketUpgrade) -> Response {
+    let events = state.sign_events.subscribe();
+    ws.on_upgrade(move |socket| stream_events(socket, events))
+}
+
+async fn stream_events(mut socket: WebSocket, mut events: broadcast::Receiver<SignEvent>) {
+    tracing::debug!("new sign event subscriber");
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "sign event subscriber is lagging behind");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let text = match serde_json::to_string(&event) {
+                    Ok(text) => text,
+                    Err(err) => {
+                        tracing::error!(?err, "failed to serialize sign event");
+                        continue;
+                    }
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            // Messages from the client are ignored; we only care about when it goes away.
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    tracing::debug!("sign event subscriber disconnected");
+}
@@ -0,0 +1,15 @@
+#!/bin/sh
+# Builds the contract of the previous release, which the migration tests upgrade from.
+set -e
+
+# Release tag of the contract the current one migrates from, see `migration.rs`. The tag has to
+# be fetched, e.g. with `git fetch --tags`.
+PREVIOUS_REV="${PREVIOUS_REV:-1.0.0-rc.5}"
+RES="$(cd "$(dirname "$0")" && pwd)"
+WORKTREE="$(mktemp -d)"
+
+git worktree add --detach "$WORKTREE" "refs/tags/$PREVIOUS_REV"
+trap 'git worktree remove --force "$WORKTREE"' EXIT
+
+(cd "$WORKTREE/chain-signatures" && CARGO_TARGET_DIR="$WORKTREE/target" cargo build -p mpc-contract --target wasm32-unknown-unknown --release)
+cp "$WORKTREE/target/wasm32-unknown-unknown/release/mpc_contract.wasm" "$RES/mpc_contract_previous.wasm"
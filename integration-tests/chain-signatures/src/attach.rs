@@ -0,0 +1,128 @@
+//! Environments set up by `setup-env --persist <FILE>` are described in a JSON file, so that test
+//! runs can attach to them through `MPC_TEST_ATTACH=<FILE>` instead of paying for setting up their
+//! own. The containers and nodes stay owned by the `setup-env` process.
+
+use std::path::Path;
+
+use anyhow::Context as _;
+use near_workspaces::network::ValidatorKey;
+use near_workspaces::{Account, AccountId, Contract};
+use serde::{Deserialize, Serialize};
+
+use crate::Nodes;
+
+/// Environment variable pointing test runs to the file of an environment to attach to.
+pub const ATTACH_ENV_VAR: &str = "MPC_TEST_ATTACH";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PersistedNode {
+    pub account_id: AccountId,
+    pub secret_key: String,
+    pub url: String,
+    /// Hex encoded cipher secret key of the node, for taking snapshots of the environment.
+    #[serde(default)]
+    pub cipher_sk: Option<String>,
+    #[serde(default)]
+    pub sign_sk: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PersistedEnv {
+    pub docker_network: String,
+    pub release: bool,
+    pub threshold: usize,
+    pub near_rpc: String,
+    pub validator_account_id: AccountId,
+    pub validator_secret_key: String,
+    pub contract_id: AccountId,
+    pub contract_secret_key: String,
+    pub datastore_url: String,
+    pub redis_url: String,
+    pub nodes: Vec<PersistedNode>,
+}
+
+impl PersistedEnv {
+    pub fn new(nodes: &Nodes, threshold: usize) -> anyhow::Result<Self> {
+        let ctx = nodes.ctx();
+        let validator = ctx.worker.root_account()?;
+        Ok(Self {
+            docker_network: ctx.docker_network.clone(),
+            release: ctx.release,
+            threshold,
+            near_rpc: ctx.lake_indexer.rpc_host_address.clone(),
+            validator_account_id: validator.id().clone(),
+            validator_secret_key: validator.secret_key().to_string(),
+            contract_id: ctx.mpc_contract.id().clone(),
+            contract_secret_key: ctx.mpc_contract.as_account().secret_key().to_string(),
+            datastore_url: ctx.datastore.local_address.clone(),
+            redis_url: ctx.redis.internal_address.clone(),
+            nodes: nodes
+                .near_accounts()
+                .into_iter()
+                .enumerate()
+                .map(|(i, account)| {
+                    let (cipher_sk, sign_sk) = nodes.node_keys(i)?;
+                    Ok(PersistedNode {
+                        account_id: account.id().clone(),
+                        secret_key: account.secret_key().to_string(),
+                        url: nodes.url(i).to_string(),
+                        cipher_sk: Some(hex::encode(cipher_sk.to_bytes())),
+                        sign_sk: Some(sign_sk.to_string()),
+                    })
+                })
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::read(path)
+            .with_context(|| format!("could not read environment file {}", path.display()))?;
+        Ok(serde_json::from_slice(&file)?)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("could not write environment file {}", path.display()))
+    }
+
+    /// Connects to the sandbox of the environment and to the accounts of the contract and nodes.
+    pub async fn attach<'a>(self) -> anyhow::Result<Nodes<'a>> {
+        let worker = near_workspaces::sandbox()
+            .rpc_addr(&self.near_rpc)
+            .validator_key(ValidatorKey::Known(
+                self.validator_account_id.clone(),
+                self.validator_secret_key.parse()?,
+            ))
+            .await?;
+        let contract = Contract::from_secret_key(
+            self.contract_id.clone(),
+            self.contract_secret_key.parse()?,
+            &worker,
+        );
+        let accounts = self
+            .nodes
+            .iter()
+            .map(|node| {
+                Ok(Account::from_secret_key(
+                    node.account_id.clone(),
+                    node.secret_key.parse()?,
+                    &worker,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Nodes::Attached {
+            env: self,
+            worker,
+            contract,
+            accounts,
+        })
+    }
+}
+
+/// File of the environment to attach to, if the test run was asked to attach to one.
+pub fn attach_path() -> Option<String> {
+    std::env::var(ATTACH_ENV_VAR).ok()
+}
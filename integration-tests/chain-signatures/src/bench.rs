@@ -0,0 +1,294 @@
+//! Benchmarks of the protocols that the nodes run in the background to fill up their triple and
+//! presignature stockpiles, to compare their throughput across releases.
+//!
+//! The protocols of all the participants run in this process and exchange their messages in
+//! rounds. A round takes as long as its slowest participant plus the network latency, which is
+//! added rather than slept through, so that runs are repeatable and do not depend on the network
+//! of the machine they run on.
+
+use std::time::{Duration, Instant};
+
+use cait_sith::protocol::{Action, MessageData, Participant, Protocol};
+use cait_sith::triples::TripleGenerationOutput;
+use cait_sith::{KeygenOutput, PresignArguments};
+use k256::Secp256k1;
+use serde::Serialize;
+
+type Protocols<T> = Vec<(Participant, Box<dyn Protocol<Output = T>>)>;
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Amounts of participants to benchmark with.
+    pub participants: Vec<usize>,
+    /// Network latencies to benchmark with.
+    pub latencies: Vec<Duration>,
+    /// Runs of each protocol for every combination of participants and latency.
+    pub samples: usize,
+    /// Label of the report, such as the release being benchmarked.
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BenchProtocol {
+    Triple,
+    Presignature,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchResult {
+    pub protocol: BenchProtocol,
+    pub participants: usize,
+    pub threshold: usize,
+    pub latency_ms: u64,
+    pub samples: usize,
+    /// Rounds of messages that a run of the protocol takes.
+    pub rounds: usize,
+    /// Mean time spent computing in a run, without the network latency.
+    pub compute_ms: f64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    /// Runs completed per second by a single generator. Nodes run several generators at once,
+    /// up to the `max_concurrent_generation` of the contract config.
+    pub per_sec: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub label: Option<String>,
+    pub results: Vec<BenchResult>,
+}
+
+/// Threshold the benchmarks run with for `participants`, which is two thirds of them.
+pub fn threshold(participants: usize) -> usize {
+    ((2 * participants + 2) / 3).max(2)
+}
+
+pub fn run(config: &BenchConfig) -> anyhow::Result<BenchReport> {
+    anyhow::ensure!(config.samples > 0, "at least one sample is required");
+    let mut results = Vec::new();
+    for &participants in &config.participants {
+        anyhow::ensure!(
+            participants >= 2,
+            "at least 2 participants are required, got {participants}"
+        );
+        let threshold = threshold(participants);
+        let participants = (0..participants as u32)
+            .map(Participant::from)
+            .collect::<Vec<_>>();
+        for &latency in &config.latencies {
+            tracing::info!(
+                participants = participants.len(),
+                threshold,
+                ?latency,
+                "benchmarking"
+            );
+            results.push(bench_triples(
+                &participants,
+                threshold,
+                latency,
+                config.samples,
+            )?);
+            results.push(bench_presignatures(
+                &participants,
+                threshold,
+                latency,
+                config.samples,
+            )?);
+        }
+    }
+    Ok(BenchReport {
+        label: config.label.clone(),
+        results,
+    })
+}
+
+fn bench_triples(
+    participants: &[Participant],
+    threshold: usize,
+    latency: Duration,
+    samples: usize,
+) -> anyhow::Result<BenchResult> {
+    let mut runs = Vec::with_capacity(samples);
+    for _ in 0..samples {
+        let (_, run) = run_protocols(triple_protocols(participants, threshold)?, latency)?;
+        runs.push(run);
+    }
+    Ok(BenchResult::new(
+        BenchProtocol::Triple,
+        participants.len(),
+        threshold,
+        latency,
+        &runs,
+    ))
+}
+
+fn bench_presignatures(
+    participants: &[Participant],
+    threshold: usize,
+    latency: Duration,
+    samples: usize,
+) -> anyhow::Result<BenchResult> {
+    let mut keygen = Protocols::<KeygenOutput<Secp256k1>>::new();
+    for &me in participants {
+        let protocol = cait_sith::keygen::<Secp256k1>(participants, me, threshold)?;
+        keygen.push((me, Box::new(protocol) as Box<dyn Protocol<Output = _>>));
+    }
+    let (keygen_out, _) = run_protocols(keygen, Duration::ZERO)?;
+
+    let mut runs = Vec::with_capacity(samples);
+    for _ in 0..samples {
+        // The triples are not part of the measurement, they are benchmarked on their own.
+        let (triples0, _) = run_protocols(triple_protocols(participants, threshold)?, latency)?;
+        let (triples1, _) = run_protocols(triple_protocols(participants, threshold)?, latency)?;
+
+        let mut presign = Protocols::new();
+        for (((me, keygen_out), (_, triple0)), (_, triple1)) in
+            keygen_out.iter().zip(triples0).zip(triples1)
+        {
+            let protocol = cait_sith::presign(
+                participants,
+                *me,
+                participants,
+                *me,
+                PresignArguments {
+                    triple0,
+                    triple1,
+                    keygen_out: KeygenOutput {
+                        private_share: keygen_out.private_share,
+                        public_key: keygen_out.public_key,
+                    },
+                    threshold,
+                },
+            )?;
+            presign.push((*me, Box::new(protocol) as Box<dyn Protocol<Output = _>>));
+        }
+        let (_, run) = run_protocols(presign, latency)?;
+        runs.push(run);
+    }
+    Ok(BenchResult::new(
+        BenchProtocol::Presignature,
+        participants.len(),
+        threshold,
+        latency,
+        &runs,
+    ))
+}
+
+fn triple_protocols(
+    participants: &[Participant],
+    threshold: usize,
+) -> anyhow::Result<Protocols<TripleGenerationOutput<Secp256k1>>> {
+    let mut protocols = Protocols::new();
+    for &me in participants {
+        let protocol =
+            cait_sith::triples::generate_triple::<Secp256k1>(participants, me, threshold)?;
+        protocols.push((me, Box::new(protocol) as Box<dyn Protocol<Output = _>>));
+    }
+    Ok(protocols)
+}
+
+/// How a single run of a protocol went.
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    rounds: usize,
+    compute: Duration,
+    latency: Duration,
+}
+
+impl Run {
+    fn duration(&self) -> Duration {
+        self.compute + self.latency * self.rounds as u32
+    }
+}
+
+/// Runs `protocols` to completion, returning their outputs in the order of the participants.
+fn run_protocols<T>(
+    mut protocols: Protocols<T>,
+    latency: Duration,
+) -> anyhow::Result<(Vec<(Participant, T)>, Run)> {
+    let mut outputs = Vec::new();
+    let mut run = Run {
+        rounds: 0,
+        compute: Duration::ZERO,
+        latency,
+    };
+    while !protocols.is_empty() {
+        let mut waiting = Vec::new();
+        let mut messages: Vec<(Participant, Option<Participant>, MessageData)> = Vec::new();
+        let mut slowest = Duration::ZERO;
+        for (me, mut protocol) in protocols {
+            let started = Instant::now();
+            loop {
+                match protocol.poke()? {
+                    Action::Wait => {
+                        waiting.push((me, protocol));
+                        break;
+                    }
+                    Action::SendMany(data) => messages.push((me, None, data)),
+                    Action::SendPrivate(to, data) => messages.push((me, Some(to), data)),
+                    Action::Return(output) => {
+                        outputs.push((me, output));
+                        break;
+                    }
+                }
+            }
+            slowest = slowest.max(started.elapsed());
+        }
+        // The participants compute in parallel, so a round takes as long as the slowest one.
+        run.compute += slowest;
+
+        if messages.is_empty() {
+            anyhow::ensure!(
+                waiting.is_empty(),
+                "protocol is stuck waiting for messages that were never sent"
+            );
+            break;
+        }
+        run.rounds += 1;
+        for (from, to, data) in messages {
+            for (me, protocol) in &mut waiting {
+                if *me != from && to.map_or(true, |to| to == *me) {
+                    protocol.message(from, data.clone());
+                }
+            }
+        }
+        protocols = waiting;
+    }
+    outputs.sort_by_key(|(me, _)| *me);
+    Ok((outputs, run))
+}
+
+impl BenchResult {
+    fn new(
+        protocol: BenchProtocol,
+        participants: usize,
+        threshold: usize,
+        latency: Duration,
+        runs: &[Run],
+    ) -> Self {
+        let mut durations = runs.iter().map(Run::duration).collect::<Vec<_>>();
+        durations.sort();
+        let percentile = |p: usize| as_ms(durations[(durations.len() - 1) * p / 100]);
+        let mean = durations.iter().sum::<Duration>() / durations.len() as u32;
+        let compute = runs.iter().map(|run| run.compute).sum::<Duration>() / runs.len() as u32;
+        Self {
+            protocol,
+            participants,
+            threshold,
+            latency_ms: latency.as_millis() as u64,
+            samples: runs.len(),
+            rounds: runs.iter().map(|run| run.rounds).max().unwrap_or_default(),
+            compute_ms: as_ms(compute),
+            mean_ms: as_ms(mean),
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            per_sec: 1.0 / mean.as_secs_f64(),
+        }
+    }
+}
+
+fn as_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,197 @@
+//! Fault injection for nodes running in docker containers. Network faults are applied by running
+//! a short-lived sidecar container in the network namespace of the targeted node, so the node
+//! image itself does not need `iptables`/`tc` or any extra capabilities.
+
+use crate::containers::DockerClient;
+use crate::Nodes;
+use anyhow::Context;
+use bollard::container::{Config, RemoveContainerOptions, WaitContainerOptions};
+use bollard::image::CreateImageOptions;
+use bollard::service::HostConfig;
+use futures::TryStreamExt;
+
+const NET_TOOLS_IMAGE: &str = "docker.io/nicolaka/netshoot";
+const NET_TOOLS_TAG: &str = "v0.13";
+
+/// The container of a node along with its address on the docker network.
+#[derive(Debug, Clone)]
+struct Target {
+    container_id: String,
+    ip_address: String,
+}
+
+/// Injects faults into the nodes of a docker based test run. Nodes are referred to by the same
+/// index as in [`Nodes`]. All the faults are undone when the containers get dropped at the end
+/// of the test, but can also be undone earlier through the corresponding methods.
+pub struct ChaosController<'a> {
+    docker_client: &'a DockerClient,
+    targets: Vec<Target>,
+}
+
+impl<'a> ChaosController<'a> {
+    pub async fn new(nodes: &Nodes<'a>) -> anyhow::Result<ChaosController<'a>> {
+        let Nodes::Docker { ctx, nodes } = nodes else {
+            anyhow::bail!("chaos testing is only supported for nodes running in docker");
+        };
+        let mut targets = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            targets.push(Target {
+                container_id: node.container.id().to_string(),
+                ip_address: ctx
+                    .docker_client
+                    .get_network_ip_address(&node.container, &ctx.docker_network)
+                    .await?,
+            });
+        }
+
+        let controller = ChaosController {
+            docker_client: ctx.docker_client,
+            targets,
+        };
+        controller.pull_net_tools().await?;
+        Ok(controller)
+    }
+
+    fn target(&self, node: usize) -> anyhow::Result<&Target> {
+        self.targets
+            .get(node)
+            .with_context(|| format!("no node with index {node}"))
+    }
+
+    /// Freezes all the processes of the node, as if the machine stopped responding.
+    pub async fn pause(&self, node: usize) -> anyhow::Result<()> {
+        let target = self.target(node)?;
+        tracing::info!(
+            node,
+            container_id = target.container_id,
+            "chaos: pausing node"
+        );
+        self.docker_client
+            .docker
+            .pause_container(&target.container_id)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn unpause(&self, node: usize) -> anyhow::Result<()> {
+        let target = self.target(node)?;
+        tracing::info!(
+            node,
+            container_id = target.container_id,
+            "chaos: unpausing node"
+        );
+        self.docker_client
+            .docker
+            .unpause_container(&target.container_id)
+            .await?;
+        Ok(())
+    }
+
+    /// Drops all the traffic between the nodes in `side_a` and the nodes in `side_b`, in both
+    /// directions. Traffic to anything else, such as the NEAR sandbox, is left untouched.
+    pub async fn partition(&self, side_a: &[usize], side_b: &[usize]) -> anyhow::Result<()> {
+        tracing::info!(?side_a, ?side_b, "chaos: partitioning nodes");
+        for &node in side_a {
+            let mut script = String::new();
+            for &peer in side_b {
+                let peer_ip = &self.target(peer)?.ip_address;
+                script.push_str(&format!(
+                    "iptables -A INPUT -s {peer_ip} -j DROP && iptables -A OUTPUT -d {peer_ip} -j DROP && "
+                ));
+            }
+            script.push_str("true");
+            self.run_net_tools(node, &script).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes every partition that involves the given nodes.
+    pub async fn heal(&self, nodes: &[usize]) -> anyhow::Result<()> {
+        tracing::info!(?nodes, "chaos: healing partitions");
+        for &node in nodes {
+            self.run_net_tools(node, "iptables -F INPUT && iptables -F OUTPUT")
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Delays every packet leaving the node by `latency_ms`, give or take `jitter_ms`.
+    pub async fn add_latency(
+        &self,
+        node: usize,
+        latency_ms: u32,
+        jitter_ms: u32,
+    ) -> anyhow::Result<()> {
+        tracing::info!(node, latency_ms, jitter_ms, "chaos: adding latency");
+        self.run_net_tools(
+            node,
+            &format!("tc qdisc replace dev eth0 root netem delay {latency_ms}ms {jitter_ms}ms"),
+        )
+        .await
+    }
+
+    pub async fn clear_latency(&self, node: usize) -> anyhow::Result<()> {
+        tracing::info!(node, "chaos: clearing latency");
+        self.run_net_tools(node, "tc qdisc del dev eth0 root || true")
+            .await
+    }
+
+    async fn pull_net_tools(&self) -> anyhow::Result<()> {
+        self.docker_client
+            .docker
+            .create_image(
+                Some(CreateImageOptions {
+                    from_image: NET_TOOLS_IMAGE,
+                    tag: NET_TOOLS_TAG,
+                    ..Default::default()
+                }),
+                None,
+                None,
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .with_context(|| format!("failed to pull {NET_TOOLS_IMAGE}:{NET_TOOLS_TAG}"))?;
+        Ok(())
+    }
+
+    /// Runs `script` in the network namespace of the node and waits for it to succeed.
+    async fn run_net_tools(&self, node: usize, script: &str) -> anyhow::Result<()> {
+        let target = self.target(node)?;
+        let docker = &self.docker_client.docker;
+        let image = format!("{NET_TOOLS_IMAGE}:{NET_TOOLS_TAG}");
+        let container = docker
+            .create_container::<String, String>(
+                None,
+                Config {
+                    image: Some(image),
+                    cmd: Some(vec!["sh".into(), "-c".into(), script.into()]),
+                    host_config: Some(HostConfig {
+                        network_mode: Some(format!("container:{}", target.container_id)),
+                        cap_add: Some(vec!["NET_ADMIN".into()]),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        docker
+            .start_container::<String>(&container.id, None)
+            .await?;
+        let outcome = docker
+            .wait_container(&container.id, None::<WaitContainerOptions<String>>)
+            .try_collect::<Vec<_>>()
+            .await;
+        docker
+            .remove_container(
+                &container.id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await?;
+        outcome.with_context(|| format!("chaos command failed on node {node}: {script}"))?;
+        Ok(())
+    }
+}
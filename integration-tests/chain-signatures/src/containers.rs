@@ -32,6 +32,7 @@ pub struct Node<'a> {
     pub cipher_sk: hpke::SecretKey,
     pub sign_sk: near_crypto::SecretKey,
     cfg: MultichainConfig,
+    failpoints: Option<String>,
     // near rpc address, after proxy
     near_rpc: String,
 }
@@ -44,6 +45,7 @@ impl<'a> Node<'a> {
         ctx: &super::Context<'a>,
         cfg: &MultichainConfig,
         account: &Account,
+        failpoints: Option<String>,
     ) -> anyhow::Result<Self> {
         tracing::info!(id = %account.id(), "running node container");
         let (cipher_sk, cipher_pk) = hpke::generate();
@@ -72,6 +74,7 @@ impl<'a> Node<'a> {
                 cipher_sk,
                 sign_sk,
                 cfg: cfg.clone(),
+                failpoints,
                 near_rpc: rpc_address_proxied,
             },
         )
@@ -87,17 +90,24 @@ impl<'a> Node<'a> {
             cipher_sk: self.cipher_sk,
             sign_sk: self.sign_sk,
             cfg: self.cfg,
+            failpoints: self.failpoints,
             near_rpc: self.near_rpc,
         }
     }
 
     pub async fn spawn(ctx: &super::Context<'a>, config: NodeConfig) -> anyhow::Result<Self> {
         let indexer_options = mpc_node::indexer::Options {
+            indexer_kind: mpc_node::indexer::IndexerKind::Lake,
+            rpc_url: None,
+            rpc_poll_interval: 500,
             s3_bucket: ctx.localstack.s3_bucket.clone(),
             s3_region: ctx.localstack.s3_region.clone(),
             s3_url: Some(ctx.localstack.s3_host_address.clone()),
             start_block_height: 0,
+            indexer_start_block: None,
             running_threshold: 120,
+            derivation_cache_size: 0,
+            derivation_hot_paths: Vec::new(),
             behind_threshold: 120,
         };
 
@@ -105,10 +115,10 @@ impl<'a> Node<'a> {
             near_rpc: config.near_rpc.clone(),
             mpc_contract_id: ctx.mpc_contract.id().clone(),
             account_id: config.account.id().clone(),
-            account_sk: config.account.secret_key().to_string().parse()?,
+            account_sk: Some(config.account.secret_key().to_string().parse()?),
             web_port: Self::CONTAINER_PORT,
-            cipher_pk: hex::encode(config.cipher_pk.to_bytes()),
-            cipher_sk: hex::encode(config.cipher_sk.to_bytes()),
+            cipher_pk: Some(hex::encode(config.cipher_pk.to_bytes())),
+            cipher_sk: Some(hex::encode(config.cipher_sk.to_bytes())),
             indexer_options: indexer_options.clone(),
             my_address: None,
             storage_options: ctx.storage_options.clone(),
@@ -116,16 +126,32 @@ impl<'a> Node<'a> {
             override_config: Some(OverrideConfig::new(serde_json::to_value(
                 config.cfg.protocol.clone(),
             )?)),
+            timeouts_config: None,
+            triple_poke_threads: None,
+            config_file: None,
             client_header_referer: None,
+            admin_token: None,
             mesh_options: ctx.mesh_options.clone(),
             message_options: ctx.message_options.clone(),
+            hsm_options: ctx.hsm_options.clone(),
+            log_format: mpc_node::cli::LogFormat::default(),
+            telemetry_options: mpc_node::telemetry::Options::default(),
+            audit_options: mpc_node::audit::Options::default(),
         }
         .into_str_args();
         let image: GenericImage = GenericImage::new("near/mpc-node", "latest")
             .with_wait_for(WaitFor::Nothing)
             .with_exposed_port(Self::CONTAINER_PORT)
             .with_env_var("RUST_LOG", "mpc_node=DEBUG")
-            .with_env_var("RUST_BACKTRACE", "1");
+            .with_env_var("RUST_BACKTRACE", "1")
+            .with_env_var(
+                "MPC_TEST_SEED",
+                std::env::var("MPC_TEST_SEED").unwrap_or_default(),
+            )
+            .with_env_var(
+                "MPC_FAILPOINTS",
+                config.failpoints.clone().unwrap_or_default(),
+            );
         let image: RunnableImage<GenericImage> = (image, args).into();
         let image = image.with_network(&ctx.docker_network);
         let container = ctx.docker_client.cli.run(image);
@@ -155,6 +181,7 @@ impl<'a> Node<'a> {
             cipher_sk: config.cipher_sk,
             sign_sk: config.sign_sk,
             cfg: config.cfg,
+            failpoints: config.failpoints,
             near_rpc: config.near_rpc,
         })
     }
@@ -179,7 +206,7 @@ impl<'a> LocalStack<'a> {
         s3_region: &str,
     ) -> anyhow::Result<LocalStack<'a>> {
         tracing::info!("running LocalStack container...");
-        let image = GenericImage::new("localstack/localstack", "3.5.0")
+        let image = GenericImage::new("docker.io/localstack/localstack", "3.5.0")
             .with_wait_for(WaitFor::message_on_stdout("Ready."));
         let image: RunnableImage<GenericImage> = image.into();
         let image = image.with_network(network);
@@ -426,12 +453,85 @@ impl<'a> LakeIndexer<'a> {
     }
 }
 
+/// Engine that the containers of the tests are run with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ContainerBackend {
+    #[default]
+    Docker,
+    /// Podman, rootless or not. Its API socket has to be running, e.g. through
+    /// `systemctl --user start podman.socket` for rootless Podman.
+    Podman,
+}
+
+impl ContainerBackend {
+    /// The backend picked through the `MPC_TEST_BACKEND` environment variable, Docker if unset.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("MPC_TEST_BACKEND") {
+            Ok(backend) => <Self as clap::ValueEnum>::from_str(&backend, true)
+                .map_err(|err| anyhow!("invalid MPC_TEST_BACKEND: {err}")),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Socket of the API of the engine. A unix socket in `DOCKER_HOST` takes precedence, since
+    /// that is where both engines point their clients to when the socket is not the default one.
+    fn socket(&self) -> String {
+        if let Ok(host) = std::env::var("DOCKER_HOST") {
+            if host.starts_with("unix://") {
+                return host;
+            }
+        }
+        match self {
+            ContainerBackend::Docker => "unix:///var/run/docker.sock".to_string(),
+            ContainerBackend::Podman => {
+                // Rootless Podman serves its API from the runtime directory of the user.
+                if let Ok(runtime_dir) = std::env::var("XDG_RUNTIME_DIR") {
+                    let socket = format!("{runtime_dir}/podman/podman.sock");
+                    if std::path::Path::new(&socket).exists() {
+                        return format!("unix://{socket}");
+                    }
+                }
+                "unix:///run/podman/podman.sock".to_string()
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ContainerBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            ContainerBackend::Docker => "docker",
+            ContainerBackend::Podman => "podman",
+        };
+        write!(f, "{}", str)
+    }
+}
+
 pub struct DockerClient {
     pub docker: Docker,
     pub cli: Cli,
+    pub backend: ContainerBackend,
 }
 
 impl DockerClient {
+    pub fn new(backend: ContainerBackend) -> anyhow::Result<Self> {
+        let socket = backend.socket();
+        tracing::info!(%backend, socket, "connecting to container engine");
+        Ok(Self {
+            docker: Docker::connect_with_local(
+                &socket,
+                // 10 minutes timeout for all requests in case a lot of tests are being ran in parallel.
+                600,
+                bollard::API_DEFAULT_VERSION,
+            )?,
+            cli: match backend {
+                ContainerBackend::Docker => Cli::docker(),
+                ContainerBackend::Podman => Cli::podman(),
+            },
+            backend,
+        })
+    }
+
     pub async fn get_network_ip_address<I: Image>(
         &self,
         container: &Container<'_, I>,
@@ -549,17 +649,9 @@ impl DockerClient {
 }
 
 impl Default for DockerClient {
+    /// Client of the engine picked through `MPC_TEST_BACKEND`.
     fn default() -> Self {
-        Self {
-            docker: Docker::connect_with_local(
-                "unix:///var/run/docker.sock",
-                // 10 minutes timeout for all requests in case a lot of tests are being ran in parallel.
-                600,
-                bollard::API_DEFAULT_VERSION,
-            )
-            .unwrap(),
-            cli: Default::default(),
-        }
+        Self::new(ContainerBackend::from_env().unwrap()).unwrap()
     }
 }
 
@@ -634,7 +726,7 @@ impl<'a> Redis<'a> {
 
     pub async fn run(docker_client: &'a DockerClient, network: &str) -> anyhow::Result<Redis<'a>> {
         tracing::info!("Running Redis container...");
-        let image = GenericImage::new("redis", "7.0.15")
+        let image = GenericImage::new("docker.io/library/redis", "7.0.15")
             .with_exposed_port(Self::DEFAULT_REDIS_PORT)
             .with_wait_for(WaitFor::message_on_stdout("Ready to accept connections"));
         let image: RunnableImage<GenericImage> = image.into();
@@ -0,0 +1,405 @@
+//! Terminal dashboard of a running environment, so that following what the nodes are up to does
+//! not take tailing the logs of every one of them. The state of each node is polled from its
+//! `/state` endpoint, or from `/admin/state` when the nodes were started with an admin token,
+//! and the signatures are followed through the `/subscribe` stream of every node.
+
+use std::collections::VecDeque;
+use std::io::Stdout;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{Event, EventStream, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use futures::StreamExt;
+use mpc_node::protocol::SignEvent;
+use mpc_node::web::{AdminStateView, StateView};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+use tokio::sync::Mutex;
+use tokio_tungstenite::tungstenite::Message;
+
+/// How often the dashboard gets redrawn, independently of how often the nodes are polled.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone)]
+pub struct DashboardConfig {
+    pub nodes: Vec<DashboardNode>,
+    /// Admin token of the nodes, which adds their peers and indexer to the dashboard.
+    pub admin_token: Option<String>,
+    /// How often the state of every node is polled.
+    pub interval: Duration,
+    /// Amount of the most recent signatures that are shown.
+    pub recent_signatures: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct DashboardNode {
+    pub name: String,
+    pub url: String,
+}
+
+/// The last state polled from a node.
+#[derive(Debug, Default)]
+struct NodeStatus {
+    protocol_state: Option<String>,
+    state: Option<StateView>,
+    indexer_height: Option<u64>,
+    indexer_behind: Option<bool>,
+    /// Active peers out of all the peers of the node, only known with the admin token.
+    peers: Option<(usize, usize)>,
+    error: Option<String>,
+    polled_at: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct RecentSignature {
+    node: String,
+    request_id: String,
+    published_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Dashboard {
+    nodes: Vec<NodeStatus>,
+    signatures: VecDeque<RecentSignature>,
+    published: usize,
+}
+
+/// Shows the dashboard until `q`, `Esc` or `Ctrl-C` is pressed.
+pub async fn run(config: DashboardConfig) -> anyhow::Result<()> {
+    let dashboard = Arc::new(Mutex::new(Dashboard {
+        nodes: config.nodes.iter().map(|_| NodeStatus::default()).collect(),
+        ..Default::default()
+    }));
+    let http_client = reqwest::Client::builder()
+        .timeout(config.interval.max(Duration::from_secs(1)))
+        .build()?;
+
+    let mut tasks = Vec::new();
+    for (id, node) in config.nodes.iter().enumerate() {
+        tasks.push(tokio::spawn(poll_node(
+            id,
+            node.clone(),
+            config.clone(),
+            http_client.clone(),
+            dashboard.clone(),
+        )));
+        tasks.push(tokio::spawn(follow_signatures(
+            node.clone(),
+            config.clone(),
+            dashboard.clone(),
+        )));
+    }
+
+    let mut terminal = enter_terminal()?;
+    let result = draw_until_quit(&mut terminal, &config, &dashboard).await;
+    leave_terminal(&mut terminal)?;
+    for task in tasks {
+        task.abort();
+    }
+    result
+}
+
+async fn draw_until_quit(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    config: &DashboardConfig,
+    dashboard: &Mutex<Dashboard>,
+) -> anyhow::Result<()> {
+    let mut events = EventStream::new();
+    let mut redraw = tokio::time::interval(REDRAW_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = redraw.tick() => {
+                let dashboard = dashboard.lock().await;
+                terminal.draw(|frame| render(frame, config, &dashboard))?;
+            }
+            event = events.next() => match event {
+                Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                    let ctrl_c = key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL);
+                    if ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                        return Ok(());
+                    }
+                }
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(err.into()),
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+fn enter_terminal() -> anyhow::Result<Terminal<CrosstermBackend<Stdout>>> {
+    terminal::enable_raw_mode()?;
+    let mut stdout = std::io::stdout();
+    crossterm::execute!(stdout, EnterAlternateScreen)?;
+    Ok(Terminal::new(CrosstermBackend::new(stdout))?)
+}
+
+fn leave_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> anyhow::Result<()> {
+    terminal::disable_raw_mode()?;
+    crossterm::execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    Ok(())
+}
+
+async fn poll_node(
+    id: usize,
+    node: DashboardNode,
+    config: DashboardConfig,
+    http_client: reqwest::Client,
+    dashboard: Arc<Mutex<Dashboard>>,
+) {
+    let mut interval = tokio::time::interval(config.interval);
+    loop {
+        interval.tick().await;
+        let status = fetch_status(&http_client, &node, config.admin_token.as_deref())
+            .await
+            .unwrap_or_else(|err| NodeStatus {
+                error: Some(err.to_string()),
+                ..Default::default()
+            });
+        let mut dashboard = dashboard.lock().await;
+        let last = &mut dashboard.nodes[id];
+        // A node that stops answering keeps showing its last known state next to the error.
+        if status.error.is_some() {
+            last.error = status.error;
+        } else {
+            *last = status;
+        }
+    }
+}
+
+async fn fetch_status(
+    http_client: &reqwest::Client,
+    node: &DashboardNode,
+    admin_token: Option<&str>,
+) -> anyhow::Result<NodeStatus> {
+    let url = url::Url::parse(&node.url)?;
+    let Some(admin_token) = admin_token else {
+        let state: StateView = http_client
+            .get(url.join("/state")?)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        return Ok(NodeStatus {
+            protocol_state: Some(state_name(&state).to_string()),
+            indexer_height: latest_block_height(&state),
+            state: Some(state),
+            polled_at: Some(Instant::now()),
+            ..Default::default()
+        });
+    };
+
+    let view: AdminStateView = http_client
+        .get(url.join("/admin/state")?)
+        .bearer_auth(admin_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let active = view.peers.iter().filter(|peer| peer.active).count();
+    Ok(NodeStatus {
+        protocol_state: Some(view.protocol_state),
+        state: Some(view.state),
+        indexer_height: Some(view.indexer.latest_block_height),
+        indexer_behind: Some(view.indexer.is_behind),
+        peers: Some((active, view.peers.len())),
+        error: None,
+        polled_at: Some(Instant::now()),
+    })
+}
+
+/// Follows the sign events of a node, and reconnects whenever its stream ends.
+async fn follow_signatures(
+    node: DashboardNode,
+    config: DashboardConfig,
+    dashboard: Arc<Mutex<Dashboard>>,
+) {
+    let url = node
+        .url
+        .replacen("http", "ws", 1)
+        .trim_end_matches('/')
+        .to_string()
+        + "/subscribe";
+    loop {
+        match tokio_tungstenite::connect_async(url.as_str()).await {
+            Ok((mut stream, _)) => {
+                while let Some(Ok(message)) = stream.next().await {
+                    let Message::Text(text) = message else {
+                        continue;
+                    };
+                    let Ok(SignEvent::SignaturePublished { request_id, .. }) =
+                        serde_json::from_str(&text)
+                    else {
+                        continue;
+                    };
+                    let mut dashboard = dashboard.lock().await;
+                    dashboard.published += 1;
+                    dashboard.signatures.push_front(RecentSignature {
+                        node: node.name.clone(),
+                        request_id,
+                        published_at: Instant::now(),
+                    });
+                    dashboard.signatures.truncate(config.recent_signatures);
+                }
+            }
+            Err(err) => tracing::debug!(?err, node = %node.name, "could not subscribe to node"),
+        }
+        tokio::time::sleep(config.interval).await;
+    }
+}
+
+fn render(frame: &mut Frame, config: &DashboardConfig, dashboard: &Dashboard) {
+    let [nodes_area, signatures_area, help_area] = Layout::vertical([
+        Constraint::Length(config.nodes.len() as u16 + 3),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let header = Row::new([
+        "node",
+        "state",
+        "triples (mine/all)",
+        "presignatures (mine/all)",
+        "block height",
+        "peers",
+        "updated",
+        "error",
+    ])
+    .style(Style::default().add_modifier(Modifier::BOLD));
+    let rows = config
+        .nodes
+        .iter()
+        .zip(&dashboard.nodes)
+        .map(|(node, status)| node_row(node, status));
+    let nodes = Table::new(
+        rows,
+        [
+            Constraint::Length(24),
+            Constraint::Length(22),
+            Constraint::Length(19),
+            Constraint::Length(25),
+            Constraint::Length(14),
+            Constraint::Length(7),
+            Constraint::Length(8),
+            Constraint::Min(10),
+        ],
+    )
+    .header(header)
+    .block(Block::bordered().title(" Nodes "));
+    frame.render_widget(nodes, nodes_area);
+
+    let rows = dashboard.signatures.iter().map(|signature| {
+        Row::new([
+            format!("{}s ago", signature.published_at.elapsed().as_secs()),
+            signature.node.clone(),
+            signature.request_id.clone(),
+        ])
+    });
+    let signatures = Table::new(
+        rows,
+        [
+            Constraint::Length(10),
+            Constraint::Length(24),
+            Constraint::Min(64),
+        ],
+    )
+    .header(
+        Row::new(["published", "node", "request id"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(Block::bordered().title(format!(
+        " Recent signatures ({} published) ",
+        dashboard.published
+    )));
+    frame.render_widget(signatures, signatures_area);
+
+    let help = Paragraph::new(format!(
+        "q: quit | polling every {}ms",
+        config.interval.as_millis()
+    ))
+    .style(Style::default().fg(Color::DarkGray));
+    frame.render_widget(help, help_area);
+}
+
+fn node_row<'a>(node: &'a DashboardNode, status: &'a NodeStatus) -> Row<'a> {
+    let unknown = || "-".to_string();
+    let (triples, presignatures) = match &status.state {
+        Some(StateView::Running {
+            triple_count,
+            triple_mine_count,
+            presignature_count,
+            presignature_mine_count,
+            ..
+        }) => (
+            format!("{triple_mine_count}/{triple_count}"),
+            format!("{presignature_mine_count}/{presignature_count}"),
+        ),
+        _ => (unknown(), unknown()),
+    };
+    let block_height = match (status.indexer_height, status.indexer_behind) {
+        (Some(height), Some(true)) => format!("{height} (behind)"),
+        (Some(height), _) => height.to_string(),
+        (None, _) => unknown(),
+    };
+    let state_style = match (&status.error, &status.state) {
+        (Some(_), _) => Style::default().fg(Color::Red),
+        (None, Some(StateView::Running { .. })) => Style::default().fg(Color::Green),
+        (None, _) => Style::default().fg(Color::Yellow),
+    };
+    Row::new([
+        Cell::from(node.name.as_str()),
+        Cell::from(status.protocol_state.clone().unwrap_or_else(unknown)).style(state_style),
+        Cell::from(triples),
+        Cell::from(presignatures),
+        Cell::from(block_height),
+        Cell::from(
+            status
+                .peers
+                .map(|(active, all)| format!("{active}/{all}"))
+                .unwrap_or_else(unknown),
+        ),
+        Cell::from(
+            status
+                .polled_at
+                .map(|at| format!("{}s ago", at.elapsed().as_secs()))
+                .unwrap_or_else(unknown),
+        ),
+        Cell::from(status.error.clone().unwrap_or_default()).style(Style::default().fg(Color::Red)),
+    ])
+}
+
+fn state_name(state: &StateView) -> &'static str {
+    match state {
+        StateView::Running { .. } => "Running",
+        StateView::Resharing { .. } => "Resharing",
+        StateView::Joining { .. } => "Joining",
+        StateView::NotRunning => "NotRunning",
+    }
+}
+
+fn latest_block_height(state: &StateView) -> Option<u64> {
+    match state {
+        StateView::Running {
+            latest_block_height,
+            ..
+        }
+        | StateView::Resharing {
+            latest_block_height,
+            ..
+        }
+        | StateView::Joining {
+            latest_block_height,
+            ..
+        } => Some(*latest_block_height),
+        StateView::NotRunning => None,
+    }
+}
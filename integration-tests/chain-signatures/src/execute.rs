@@ -24,21 +24,44 @@ pub fn executable(release: bool, executable: &str) -> Option<std::path::PathBuf>
     Some(executable)
 }
 
+/// Where the output of `node` gets written to when it is spawned as a local process.
+pub fn log_path(node: &str) -> Option<std::path::PathBuf> {
+    Some(target_dir()?.join("logs").join(format!("{node}.log")))
+}
+
+/// Spawns `node` as a child process, with its output captured into the file at [`log_path`]
+/// so that the logs of every node can be read separately. `failpoints` get armed on the node
+/// through `MPC_FAILPOINTS`.
 pub fn spawn_multichain(
     release: bool,
     node: &str,
     cli: mpc_node::cli::Cli,
-) -> anyhow::Result<Child> {
+    failpoints: Option<&str>,
+) -> anyhow::Result<(Child, std::path::PathBuf)> {
     let executable = executable(release, PACKAGE_MULTICHAIN)
         .with_context(|| format!("could not find target dir while starting {node} node"))?;
+    let log_path = log_path(node)
+        .with_context(|| format!("could not find target dir while starting {node} node"))?;
+    if let Some(log_dir) = log_path.parent() {
+        std::fs::create_dir_all(log_dir)
+            .with_context(|| format!("failed to create log dir {}", log_dir.display()))?;
+    }
+    let log = std::fs::File::create(&log_path)
+        .with_context(|| format!("failed to create log file {}", log_path.display()))?;
 
-    async_process::Command::new(&executable)
+    let mut command = async_process::Command::new(&executable);
+    command
         .args(cli.into_str_args())
         .env("RUST_LOG", "mpc_node=INFO")
-        .envs(std::env::vars())
-        .stdout(async_process::Stdio::inherit())
-        .stderr(async_process::Stdio::inherit())
+        .envs(std::env::vars());
+    if let Some(failpoints) = failpoints {
+        command.env("MPC_FAILPOINTS", failpoints);
+    }
+    let child = command
+        .stdout(log.try_clone()?)
+        .stderr(log)
         .kill_on_drop(true)
         .spawn()
-        .with_context(|| format!("failed to run {node} node: {}", executable.display()))
+        .with_context(|| format!("failed to run {node} node: {}", executable.display()))?;
+    Ok((child, log_path))
 }
@@ -0,0 +1,246 @@
+//! Kubernetes manifests for a chain-signatures cluster, mirroring what the docker harness sets
+//! up locally: every node gets its own StatefulSet, Secret and Service, and the nodes share a
+//! Redis instance.
+//!
+//! The manifests are rendered as a `v1/List` in JSON, which `kubectl apply -f` accepts just like
+//! YAML. The NEAR accounts of the nodes and the contract are not created here. The cipher and
+//! sign keys of the nodes are generated on every render, so the contract has to be initialized
+//! with the candidates of the same render, which are part of the manifests as the
+//! `mpc-candidates` ConfigMap.
+
+use std::collections::HashMap;
+
+use mpc_contract::primitives::CandidateInfo;
+use mpc_keys::hpke;
+use mpc_node::config::OverrideConfig;
+use near_crypto::{KeyType, SecretKey};
+use near_workspaces::AccountId;
+use serde_json::{json, Value};
+
+use crate::MultichainConfig;
+
+const APP_LABEL: &str = "mpc-node";
+const WEB_PORT: u16 = 3000;
+const REDIS_PORT: u16 = 6379;
+const REDIS_IMAGE: &str = "redis:7.0.15";
+/// Mount path of the volume that keeps the key share of a node across restarts.
+const DATA_PATH: &str = "/data";
+
+#[derive(Debug, Clone)]
+pub struct K8sConfig {
+    pub namespace: String,
+    /// Image of the node, e.g. `near/mpc-node:latest`.
+    pub image: String,
+    pub near_rpc: String,
+    pub mpc_contract_id: AccountId,
+    /// NEAR account of each node, by index, along with its secret key.
+    pub accounts: Vec<(AccountId, SecretKey)>,
+    /// Suffixes the datastore tables of the nodes, like `MPC_ENV`.
+    pub env: String,
+    pub gcp_project_id: String,
+    /// Datastore emulator to use instead of the datastore of `gcp_project_id`.
+    pub gcp_datastore_url: Option<String>,
+    /// Size of the volume claimed by every node for its key share.
+    pub storage_size: String,
+}
+
+/// Renders the manifests of a cluster with `cfg.nodes` nodes. The protocol config and the
+/// failpoints of `cfg` are passed to the nodes the same way the docker harness does.
+pub fn render(cfg: &MultichainConfig, k8s: &K8sConfig) -> anyhow::Result<Value> {
+    if k8s.accounts.len() != cfg.nodes {
+        anyhow::bail!(
+            "{} nodes need as many accounts, but {} were given",
+            cfg.nodes,
+            k8s.accounts.len()
+        );
+    }
+    if cfg.threshold > cfg.nodes {
+        anyhow::bail!(
+            "threshold {} is higher than the amount of nodes {}",
+            cfg.threshold,
+            cfg.nodes
+        );
+    }
+
+    let override_config = serde_json::to_string(&OverrideConfig::new(serde_json::to_value(
+        cfg.protocol.clone(),
+    )?))?;
+    let mut items = redis(k8s);
+    let mut candidates = HashMap::new();
+    for (i, (account_id, account_sk)) in k8s.accounts.iter().enumerate() {
+        let name = format!("{APP_LABEL}-{i}");
+        let (cipher_sk, cipher_pk) = hpke::generate();
+        let sign_sk = SecretKey::from_random(KeyType::ED25519);
+        let url = format!(
+            "http://{name}.{}.svc.cluster.local:{WEB_PORT}",
+            k8s.namespace
+        );
+
+        candidates.insert(
+            account_id.clone(),
+            CandidateInfo {
+                account_id: account_id.as_str().parse()?,
+                url: url.clone(),
+                cipher_pk: cipher_pk.to_bytes(),
+                sign_pk: sign_sk.public_key().to_string().parse()?,
+            },
+        );
+        items.push(json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": metadata(k8s, &name),
+            "type": "Opaque",
+            "stringData": {
+                "MPC_ACCOUNT_SK": account_sk.to_string(),
+                "MPC_CIPHER_PK": hex::encode(cipher_pk.to_bytes()),
+                "MPC_CIPHER_SK": hex::encode(cipher_sk.to_bytes()),
+                "MPC_SIGN_SK": sign_sk.to_string(),
+            },
+        }));
+        let mut node_service = service(k8s, &name, WEB_PORT);
+        // The nodes also serve the QUIC transport over UDP on the web port.
+        node_service["spec"]["ports"] = json!([
+            { "name": "web", "port": WEB_PORT, "targetPort": WEB_PORT },
+            { "name": "quic", "port": WEB_PORT, "targetPort": WEB_PORT, "protocol": "UDP" },
+        ]);
+        items.push(node_service);
+
+        let env = [
+            ("MPC_NEAR_RPC", k8s.near_rpc.clone()),
+            ("MPC_CONTRACT_ID", k8s.mpc_contract_id.to_string()),
+            ("MPC_ACCOUNT_ID", account_id.to_string()),
+            ("MPC_WEB_PORT", WEB_PORT.to_string()),
+            ("MPC_LOCAL_ADDRESS", url),
+            ("MPC_INDEXER_KIND", "rpc".to_string()),
+            ("MPC_INDEXER_RPC_URL", k8s.near_rpc.clone()),
+            ("MPC_ENV", k8s.env.clone()),
+            ("MPC_GCP_PROJECT_ID", k8s.gcp_project_id.clone()),
+            ("MPC_SK_SHARE_LOCAL_PATH", format!("{DATA_PATH}/sk-share")),
+            (
+                "MPC_REDIS_URL",
+                format!(
+                    "redis://mpc-redis.{}.svc.cluster.local:{REDIS_PORT}",
+                    k8s.namespace
+                ),
+            ),
+            ("MPC_OVERRIDE_CONFIG", override_config.clone()),
+            (
+                "MPC_FAILPOINTS",
+                cfg.failpoints.get(i).cloned().unwrap_or_default(),
+            ),
+            ("RUST_LOG", "mpc_node=DEBUG".to_string()),
+        ]
+        .into_iter()
+        .chain(
+            k8s.gcp_datastore_url
+                .clone()
+                .map(|url| ("MPC_GCP_DATASTORE_URL", url)),
+        )
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect::<Vec<_>>();
+
+        items.push(json!({
+            "apiVersion": "apps/v1",
+            "kind": "StatefulSet",
+            "metadata": metadata(k8s, &name),
+            "spec": {
+                "serviceName": name,
+                "replicas": 1,
+                "selector": { "matchLabels": { "app": APP_LABEL, "node": name } },
+                "template": {
+                    "metadata": { "labels": { "app": APP_LABEL, "node": name } },
+                    "spec": {
+                        "containers": [{
+                            "name": APP_LABEL,
+                            "image": k8s.image,
+                            "args": ["start"],
+                            "ports": [
+                                { "name": "web", "containerPort": WEB_PORT },
+                                { "name": "quic", "containerPort": WEB_PORT, "protocol": "UDP" },
+                            ],
+                            "env": env,
+                            "envFrom": [{ "secretRef": { "name": name } }],
+                            "readinessProbe": {
+                                "httpGet": { "path": "/", "port": WEB_PORT },
+                                "periodSeconds": 5,
+                            },
+                            "volumeMounts": [{ "name": "data", "mountPath": DATA_PATH }],
+                        }],
+                    },
+                },
+                "volumeClaimTemplates": [{
+                    "metadata": { "name": "data" },
+                    "spec": {
+                        "accessModes": ["ReadWriteOnce"],
+                        "resources": { "requests": { "storage": k8s.storage_size } },
+                    },
+                }],
+            },
+        }));
+    }
+
+    items.push(json!({
+        "apiVersion": "v1",
+        "kind": "ConfigMap",
+        "metadata": metadata(k8s, "mpc-candidates"),
+        "data": {
+            "init.json": serde_json::to_string_pretty(&json!({
+                "threshold": cfg.threshold,
+                "candidates": candidates,
+            }))?,
+        },
+    }));
+
+    Ok(json!({
+        "apiVersion": "v1",
+        "kind": "List",
+        "items": items,
+    }))
+}
+
+fn metadata(k8s: &K8sConfig, name: &str) -> Value {
+    json!({
+        "name": name,
+        "namespace": k8s.namespace,
+        "labels": { "app": APP_LABEL },
+    })
+}
+
+fn service(k8s: &K8sConfig, name: &str, port: u16) -> Value {
+    json!({
+        "apiVersion": "v1",
+        "kind": "Service",
+        "metadata": metadata(k8s, name),
+        "spec": {
+            "selector": { "app": APP_LABEL, "node": name },
+            "ports": [{ "port": port, "targetPort": port }],
+        },
+    })
+}
+
+fn redis(k8s: &K8sConfig) -> Vec<Value> {
+    let name = "mpc-redis";
+    vec![
+        service(k8s, name, REDIS_PORT),
+        json!({
+            "apiVersion": "apps/v1",
+            "kind": "StatefulSet",
+            "metadata": metadata(k8s, name),
+            "spec": {
+                "serviceName": name,
+                "replicas": 1,
+                "selector": { "matchLabels": { "app": APP_LABEL, "node": name } },
+                "template": {
+                    "metadata": { "labels": { "app": APP_LABEL, "node": name } },
+                    "spec": {
+                        "containers": [{
+                            "name": "redis",
+                            "image": REDIS_IMAGE,
+                            "ports": [{ "containerPort": REDIS_PORT }],
+                        }],
+                    },
+                },
+            },
+        }),
+    ]
+}
@@ -1,12 +1,24 @@
+pub mod attach;
+pub mod bench;
+pub mod chaos;
 pub mod containers;
+pub mod dashboard;
 pub mod execute;
+pub mod k8s;
+pub mod load_test;
 pub mod local;
+pub mod request_sign;
+pub mod simulate;
+pub mod snapshot;
 pub mod utils;
 
 use deadpool_redis::Pool;
 use std::collections::HashMap;
+use std::path::PathBuf;
 
+use self::attach::PersistedEnv;
 use self::local::NodeConfig;
+use crate::chaos::ChaosController;
 use crate::containers::DockerClient;
 use crate::containers::LocalStack;
 
@@ -15,11 +27,14 @@ use bollard::exec::{CreateExecOptions, StartExecResults};
 use futures::StreamExt;
 use mpc_contract::config::{PresignatureConfig, ProtocolConfig, TripleConfig};
 use mpc_contract::primitives::CandidateInfo;
+use mpc_keys::hpke;
 use mpc_node::gcp::GcpService;
+use mpc_node::hsm;
 use mpc_node::http_client;
 use mpc_node::mesh;
 use mpc_node::storage;
 use mpc_node::storage::triple_storage::TripleRedisStorage;
+use mpc_node::storage::StorageCipher;
 use near_crypto::KeyFile;
 use near_workspaces::network::{Sandbox, ValidatorKey};
 use near_workspaces::types::{KeyType, SecretKey};
@@ -28,12 +43,42 @@ use serde_json::json;
 use testcontainers::{Container, GenericImage};
 
 const NETWORK: &str = "mpc_it_network";
+const GCP_PROJECT_ID: &str = "multichain-integration";
+const SK_SHARE_LOCAL_PATH: &str = "multichain-integration-secret-manager";
+
+/// Artificial network conditions of a node, used to simulate nodes deployed across regions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyProfile {
+    /// Delay added to every packet sent by the node.
+    pub latency_ms: u32,
+    pub jitter_ms: u32,
+}
+
+impl LatencyProfile {
+    /// Profile that results in a round trip time of `rtt_ms` between two nodes using it.
+    pub fn from_rtt(rtt_ms: u32) -> Self {
+        Self {
+            latency_ms: rtt_ms / 2,
+            jitter_ms: 0,
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct MultichainConfig {
     pub nodes: usize,
     pub threshold: usize,
     pub protocol: ProtocolConfig,
+    /// Latency profile of each of the initial nodes, by index. Nodes without a profile do not
+    /// get any extra latency. Only supported for nodes running in docker.
+    pub latency_profiles: Vec<LatencyProfile>,
+    /// Failpoints armed on each of the initial nodes, by index, in the `MPC_FAILPOINTS` format.
+    /// Only used by nodes built with the `failpoints` feature.
+    pub failpoints: Vec<String>,
+    /// Transport the nodes send their messages to each other with.
+    pub transport: http_client::Transport,
+    /// Whether the nodes authenticate each other on the QUIC mesh. Requires the QUIC transport.
+    pub mesh_mtls: bool,
 }
 
 impl Default for MultichainConfig {
@@ -54,6 +99,10 @@ impl Default for MultichainConfig {
                 },
                 ..Default::default()
             },
+            latency_profiles: Vec::new(),
+            failpoints: Vec::new(),
+            transport: http_client::Transport::Http,
+            mesh_mtls: false,
         }
     }
 }
@@ -67,6 +116,13 @@ pub enum Nodes<'a> {
         ctx: Context<'a>,
         nodes: Vec<containers::Node<'a>>,
     },
+    /// Environment set up by another process, see [`attach`].
+    Attached {
+        env: PersistedEnv,
+        worker: Worker<Sandbox>,
+        contract: Contract,
+        accounts: Vec<Account>,
+    },
 }
 
 impl Nodes<'_> {
@@ -74,6 +130,7 @@ impl Nodes<'_> {
         match self {
             Nodes::Local { nodes, .. } => nodes.len(),
             Nodes::Docker { nodes, .. } => nodes.len(),
+            Nodes::Attached { accounts, .. } => accounts.len(),
         }
     }
 
@@ -85,6 +142,32 @@ impl Nodes<'_> {
         match self {
             Nodes::Local { ctx, .. } => ctx,
             Nodes::Docker { ctx, .. } => ctx,
+            Nodes::Attached { .. } => {
+                panic!("the context of an attached environment is owned by its setup-env process")
+            }
+        }
+    }
+
+    pub fn worker(&self) -> &Worker<Sandbox> {
+        match self {
+            Nodes::Attached { worker, .. } => worker,
+            _ => &self.ctx().worker,
+        }
+    }
+
+    /// Address of the RPC of the sandbox, reachable from the host.
+    pub fn near_rpc(&self) -> &str {
+        match self {
+            Nodes::Attached { env, .. } => &env.near_rpc,
+            _ => &self.ctx().lake_indexer.rpc_host_address,
+        }
+    }
+
+    /// Address of the datastore emulator, reachable from the host.
+    pub fn datastore_url(&self) -> &str {
+        match self {
+            Nodes::Attached { env, .. } => &env.datastore_url,
+            _ => &self.ctx().datastore.local_address,
         }
     }
 
@@ -92,6 +175,44 @@ impl Nodes<'_> {
         match self {
             Nodes::Local { nodes, .. } => &nodes[id].address,
             Nodes::Docker { nodes, .. } => &nodes[id].address,
+            Nodes::Attached { env, .. } => &env.nodes[id].url,
+        }
+    }
+
+    /// Output of the node `id` so far. Only nodes running as local processes write it to a file.
+    pub fn logs(&self, id: usize) -> anyhow::Result<String> {
+        match self {
+            Nodes::Local { nodes, .. } => {
+                let log_path = &nodes[id].log_path;
+                std::fs::read_to_string(log_path)
+                    .with_context(|| format!("failed to read {}", log_path.display()))
+            }
+            _ => anyhow::bail!("logs are only available for nodes running as local processes"),
+        }
+    }
+
+    /// Cipher and sign secret keys of the node `id`. Attached environments only know them if they
+    /// were persisted along with the environment.
+    pub fn node_keys(
+        &self,
+        id: usize,
+    ) -> anyhow::Result<(hpke::SecretKey, near_crypto::SecretKey)> {
+        match self {
+            Nodes::Local { nodes, .. } => {
+                Ok((nodes[id].cipher_sk.clone(), nodes[id].sign_sk.clone()))
+            }
+            Nodes::Docker { nodes, .. } => {
+                Ok((nodes[id].cipher_sk.clone(), nodes[id].sign_sk.clone()))
+            }
+            Nodes::Attached { env, .. } => {
+                let node = &env.nodes[id];
+                let (Some(cipher_sk), Some(sign_sk)) = (&node.cipher_sk, &node.sign_sk) else {
+                    anyhow::bail!("the keys of node {} were not persisted", node.account_id);
+                };
+                let cipher_sk = hpke::SecretKey::try_from_bytes(&hex::decode(cipher_sk)?)
+                    .map_err(|err| anyhow::anyhow!("invalid cipher key: {err}"))?;
+                Ok((cipher_sk, sign_sk.parse()?))
+            }
         }
     }
 
@@ -99,6 +220,7 @@ impl Nodes<'_> {
         match self {
             Nodes::Local { nodes, .. } => nodes.iter().map(|node| &node.account).collect(),
             Nodes::Docker { nodes, .. } => nodes.iter().map(|node| &node.account).collect(),
+            Nodes::Attached { accounts, .. } => accounts.iter().collect(),
         }
     }
 
@@ -110,10 +232,13 @@ impl Nodes<'_> {
         tracing::info!(id = %new_account.id(), "adding one more node");
         match self {
             Nodes::Local { ctx, nodes } => {
-                nodes.push(local::Node::run(ctx, cfg, new_account).await?)
+                nodes.push(local::Node::run(ctx, cfg, new_account, None).await?)
             }
             Nodes::Docker { ctx, nodes } => {
-                nodes.push(containers::Node::run(ctx, cfg, new_account).await?)
+                nodes.push(containers::Node::run(ctx, cfg, new_account, None).await?)
+            }
+            Nodes::Attached { .. } => {
+                anyhow::bail!("nodes can not be started in an attached environment")
             }
         }
 
@@ -136,6 +261,7 @@ impl Nodes<'_> {
                     .unwrap();
                 nodes.remove(index).kill()
             }
+            Nodes::Attached { .. } => panic!("nodes can not be killed in an attached environment"),
         };
 
         // wait for the node to be removed from the network
@@ -149,6 +275,9 @@ impl Nodes<'_> {
         match self {
             Nodes::Local { ctx, nodes } => nodes.push(local::Node::spawn(ctx, config).await?),
             Nodes::Docker { ctx, nodes } => nodes.push(containers::Node::spawn(ctx, config).await?),
+            Nodes::Attached { .. } => {
+                anyhow::bail!("nodes can not be restarted in an attached environment")
+            }
         }
         // wait for the node to be added to the network
         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
@@ -160,8 +289,9 @@ impl Nodes<'_> {
         &self,
         redis_pool: &Pool,
         account_id: &AccountId,
+        cipher: &StorageCipher,
     ) -> TripleRedisStorage {
-        storage::triple_storage::init(redis_pool, account_id)
+        storage::triple_storage::init(redis_pool, account_id, cipher)
     }
 
     pub async fn gcp_services(&self) -> anyhow::Result<Vec<GcpService>> {
@@ -181,6 +311,9 @@ impl Nodes<'_> {
                     );
                 }
             }
+            Nodes::Attached { .. } => {
+                anyhow::bail!("gcp services are not available in an attached environment")
+            }
         }
         Ok(gcp_services)
     }
@@ -191,7 +324,10 @@ impl Nodes<'_> {
     }
 
     pub fn contract(&self) -> &Contract {
-        &self.ctx().mpc_contract
+        match self {
+            Nodes::Attached { contract, .. } => contract,
+            _ => &self.ctx().mpc_contract,
+        }
     }
 }
 
@@ -209,8 +345,13 @@ pub struct Context<'a> {
     pub storage_options: storage::Options,
     pub mesh_options: mesh::Options,
     pub message_options: http_client::Options,
+    pub hsm_options: hsm::Options,
 }
 
+/// Contract that [`setup`] deploys instead of the one built from this tree, such as the wasm of
+/// the previous release to test upgrading from it.
+pub const CONTRACT_PATH_ENV_VAR: &str = "MPC_TEST_CONTRACT";
+
 pub async fn setup(docker_client: &DockerClient) -> anyhow::Result<Context<'_>> {
     let release = true;
     let docker_network = NETWORK;
@@ -222,38 +363,69 @@ pub async fn setup(docker_client: &DockerClient) -> anyhow::Result<Context<'_>>
         worker,
     } = initialize_lake_indexer(docker_client, docker_network).await?;
 
-    let mpc_contract = worker
-        .dev_deploy(&std::fs::read(
-            execute::target_dir()
-                .context("could not find target dir")?
-                .join("wasm32-unknown-unknown/release/mpc_contract.wasm"),
-        )?)
-        .await?;
+    let contract_path = match std::env::var(CONTRACT_PATH_ENV_VAR) {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => execute::target_dir()
+            .context("could not find target dir")?
+            .join("wasm32-unknown-unknown/release/mpc_contract.wasm"),
+    };
+    let mpc_contract =
+        worker
+            .dev_deploy(&std::fs::read(&contract_path).with_context(|| {
+                format!("could not read contract from {}", contract_path.display())
+            })?)
+            .await?;
     tracing::info!(contract_id = %mpc_contract.id(), "deployed mpc contract");
 
-    let gcp_project_id = "multichain-integration";
     let datastore =
-        crate::containers::Datastore::run(docker_client, docker_network, gcp_project_id).await?;
+        crate::containers::Datastore::run(docker_client, docker_network, GCP_PROJECT_ID).await?;
 
     let redis = crate::containers::Redis::run(docker_client, docker_network).await?;
     let redis_url = redis.internal_address.clone();
 
-    let sk_share_local_path = "multichain-integration-secret-manager".to_string();
     let storage_options = mpc_node::storage::Options {
         env: "local-test".to_string(),
-        gcp_project_id: "multichain-integration".to_string(),
+        gcp_project_id: GCP_PROJECT_ID.to_string(),
         sk_share_secret_id: None,
+        aws_sk_share_secret_id: None,
         gcp_datastore_url: Some(datastore.local_address.clone()),
-        sk_share_local_path: Some(sk_share_local_path),
+        sk_share_local_path: Some(SK_SHARE_LOCAL_PATH.to_string()),
+        sk_share_local_passphrase: None,
+        sk_share_local_transit_key: None,
+        secret_storage: None,
+        vault_addr: None,
+        vault_token: None,
+        vault_role_id: None,
+        vault_secret_id: None,
+        vault_sk_share_path: None,
+        vault_cipher_key_path: None,
+        sk_share_backup_pk: None,
+        sk_share_backup_path: None,
         redis_url,
     };
 
     let mesh_options = mpc_node::mesh::Options {
         fetch_participant_timeout: 1000,
         refresh_active_timeout: 1000,
+        peer_failures_before_backoff: 3,
+        peer_max_backoff: 60000,
+        peer_slow_latency: 2000,
     };
 
-    let message_options = http_client::Options { timeout: 1000 };
+    let message_options = http_client::Options {
+        timeout: 1000,
+        transport: http_client::Transport::Http,
+        mesh_mtls: false,
+    };
+
+    let hsm_options = hsm::Options {
+        hsm_module: None,
+        hsm_slot: 0,
+        hsm_pin: None,
+        hsm_sign_key_label: "mpc-sign-key".to_string(),
+        hsm_account_key_label: "mpc-account-key".to_string(),
+        hsm_cipher_key_label: "mpc-cipher-key".to_string(),
+    };
 
     Ok(Context {
         docker_client,
@@ -268,11 +440,14 @@ pub async fn setup(docker_client: &DockerClient) -> anyhow::Result<Context<'_>>
         storage_options,
         mesh_options,
         message_options,
+        hsm_options,
     })
 }
 
 pub async fn docker(cfg: MultichainConfig, docker_client: &DockerClient) -> anyhow::Result<Nodes> {
-    let ctx = setup(docker_client).await?;
+    let mut ctx = setup(docker_client).await?;
+    ctx.message_options.transport = cfg.transport;
+    ctx.message_options.mesh_mtls = cfg.mesh_mtls;
 
     let accounts =
         futures::future::join_all((0..cfg.nodes).map(|_| ctx.worker.dev_create_account()))
@@ -280,8 +455,8 @@ pub async fn docker(cfg: MultichainConfig, docker_client: &DockerClient) -> anyh
             .into_iter()
             .collect::<Result<Vec<_>, _>>()?;
     let mut node_futures = Vec::new();
-    for account in &accounts {
-        let node = containers::Node::run(&ctx, &cfg, account);
+    for (i, account) in accounts.iter().enumerate() {
+        let node = containers::Node::run(&ctx, &cfg, account, cfg.failpoints.get(i).cloned());
         node_futures.push(node);
     }
     let nodes = futures::future::join_all(node_futures)
@@ -314,14 +489,26 @@ pub async fn docker(cfg: MultichainConfig, docker_client: &DockerClient) -> anyh
         .await?
         .into_result()?;
 
-    Ok(Nodes::Docker { ctx, nodes })
+    let nodes = Nodes::Docker { ctx, nodes };
+    if !cfg.latency_profiles.is_empty() {
+        let chaos = ChaosController::new(&nodes).await?;
+        for (node, profile) in cfg.latency_profiles.iter().enumerate() {
+            chaos
+                .add_latency(node, profile.latency_ms, profile.jitter_ms)
+                .await?;
+        }
+    }
+
+    Ok(nodes)
 }
 
 pub async fn dry_host(
     cfg: MultichainConfig,
     docker_client: &DockerClient,
 ) -> anyhow::Result<Context> {
-    let ctx = setup(docker_client).await?;
+    let mut ctx = setup(docker_client).await?;
+    ctx.message_options.transport = cfg.transport;
+    ctx.message_options.mesh_mtls = cfg.mesh_mtls;
 
     let accounts =
         futures::future::join_all((0..cfg.nodes).map(|_| ctx.worker.dev_create_account()))
@@ -374,7 +561,12 @@ pub async fn dry_host(
 }
 
 pub async fn host(cfg: MultichainConfig, docker_client: &DockerClient) -> anyhow::Result<Nodes> {
-    let ctx = setup(docker_client).await?;
+    if !cfg.latency_profiles.is_empty() {
+        anyhow::bail!("latency profiles are only supported for nodes running in docker");
+    }
+    let mut ctx = setup(docker_client).await?;
+    ctx.message_options.transport = cfg.transport;
+    ctx.message_options.mesh_mtls = cfg.mesh_mtls;
 
     let accounts =
         futures::future::join_all((0..cfg.nodes).map(|_| ctx.worker.dev_create_account()))
@@ -382,8 +574,13 @@ pub async fn host(cfg: MultichainConfig, docker_client: &DockerClient) -> anyhow
             .into_iter()
             .collect::<Result<Vec<_>, _>>()?;
     let mut node_futures = Vec::with_capacity(cfg.nodes);
-    for account in &accounts {
-        node_futures.push(local::Node::run(&ctx, &cfg, account));
+    for (i, account) in accounts.iter().enumerate() {
+        node_futures.push(local::Node::run(
+            &ctx,
+            &cfg,
+            account,
+            cfg.failpoints.get(i).cloned(),
+        ));
     }
     let nodes = futures::future::join_all(node_futures)
         .await
@@ -418,7 +615,22 @@ pub async fn host(cfg: MultichainConfig, docker_client: &DockerClient) -> anyhow
     Ok(Nodes::Local { ctx, nodes })
 }
 
+/// Seed of the ids of the triples the nodes introduce, which they only use when built with the
+/// `seeded-triple-ids` feature. Taken from `MPC_TEST_SEED` so that a failed run can be replayed,
+/// and otherwise picked at random and exported for the nodes to pick up.
+pub fn test_seed() -> String {
+    let seed = std::env::var("MPC_TEST_SEED").unwrap_or_else(|_| {
+        let seed = rand::random::<u64>().to_string();
+        std::env::set_var("MPC_TEST_SEED", &seed);
+        seed
+    });
+    tracing::info!("running nodes with MPC_TEST_SEED={seed}");
+    seed
+}
+
 pub async fn run(cfg: MultichainConfig, docker_client: &DockerClient) -> anyhow::Result<Nodes> {
+    test_seed();
+
     #[cfg(feature = "docker-test")]
     return docker(cfg, docker_client).await;
 
@@ -0,0 +1,252 @@
+//! Load test of a running environment, local or on testnet. Sign requests are submitted at a
+//! fixed rate through `chain-signatures-client`, regardless of how many are still waiting on
+//! their signature, so that the rate the network keeps up with can be told from the latencies
+//! and errors of the requests.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chain_signatures_client::{ChainSignaturesClient, Error, PollOptions};
+use near_account_id::AccountId;
+use near_crypto::{InMemorySigner, SecretKey};
+use serde::Serialize;
+
+#[derive(Debug, Clone)]
+pub struct LoadTestConfig {
+    pub near_rpc: String,
+    pub mpc_contract_id: AccountId,
+    /// Accounts that submit the requests in turns. Requests of the same account compete for
+    /// the nonce of its access key, so higher rates need more accounts.
+    pub accounts: Vec<(AccountId, SecretKey)>,
+    /// Sign requests submitted per second.
+    pub rps: f64,
+    /// How long to keep submitting requests for.
+    pub duration: Duration,
+    pub path: String,
+    /// How long to wait for the signature of every request.
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    /// The summary along with every request.
+    Json,
+    /// One line per request.
+    Csv,
+}
+
+/// Outcome of a single sign request.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadTestSample {
+    pub account_id: AccountId,
+    /// Milliseconds since the start of the load test at which the request was submitted.
+    pub submitted_at_ms: u128,
+    pub latency_ms: u128,
+    /// Kind of the error the request failed with, such as `timeout` or `sign_failed`.
+    pub error_kind: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadTestReport {
+    pub target_rps: f64,
+    /// Rate at which the requests were actually submitted, which falls behind the target when
+    /// the machine running the load test can not keep up.
+    pub achieved_rps: f64,
+    pub duration_secs: f64,
+    pub requests: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub error_rate: f64,
+    /// Amount of failed requests by the kind of their error.
+    pub errors: BTreeMap<String, usize>,
+    /// Latencies of the requests that succeeded.
+    pub latency: LatencySummary,
+    pub samples: Vec<LoadTestSample>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencySummary {
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Submits sign requests at the rate of `cfg` for its duration, then waits for all of them to
+/// be signed or fail and reports how they went.
+pub async fn run(cfg: LoadTestConfig) -> anyhow::Result<LoadTestReport> {
+    anyhow::ensure!(!cfg.accounts.is_empty(), "at least one account is required");
+    anyhow::ensure!(
+        cfg.rps > 0.0,
+        "the rate has to be positive, got {}",
+        cfg.rps
+    );
+    let client = ChainSignaturesClient::new(&cfg.near_rpc, cfg.mpc_contract_id.clone()).with_poll(
+        PollOptions {
+            timeout: cfg.timeout,
+            ..Default::default()
+        },
+    );
+    let signers = cfg
+        .accounts
+        .iter()
+        .map(|(account_id, sk)| InMemorySigner::from_secret_key(account_id.clone(), sk.clone()))
+        .collect::<Vec<_>>();
+    let requests = (cfg.rps * cfg.duration.as_secs_f64()).ceil() as usize;
+    tracing::info!(
+        rps = cfg.rps,
+        duration = ?cfg.duration,
+        requests,
+        accounts = signers.len(),
+        "starting load test"
+    );
+
+    let started = Instant::now();
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / cfg.rps));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Burst);
+    let mut tasks = Vec::with_capacity(requests);
+    for i in 0..requests {
+        interval.tick().await;
+        let client = client.clone();
+        let signer = signers[i % signers.len()].clone();
+        let path = cfg.path.clone();
+        let submitted_at = started.elapsed();
+        tasks.push(tokio::spawn(async move {
+            let request_started = Instant::now();
+            let result = client.sign(&signer, random_payload(), &path).await;
+            let (error_kind, error) = match result {
+                Ok(_) => (None, None),
+                Err(err) => (Some(error_kind(&err).to_string()), Some(err.to_string())),
+            };
+            LoadTestSample {
+                account_id: signer.account_id,
+                submitted_at_ms: submitted_at.as_millis(),
+                latency_ms: request_started.elapsed().as_millis(),
+                error_kind,
+                error,
+            }
+        }));
+    }
+    let submitting = started.elapsed();
+    tracing::info!(
+        ?submitting,
+        "submitted all requests, waiting for their signatures"
+    );
+
+    let mut samples = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        samples.push(task.await?);
+    }
+    Ok(LoadTestReport::new(&cfg, submitting, samples))
+}
+
+impl LoadTestReport {
+    fn new(cfg: &LoadTestConfig, submitting: Duration, samples: Vec<LoadTestSample>) -> Self {
+        let mut errors = BTreeMap::new();
+        let mut latencies = Vec::new();
+        for sample in &samples {
+            match &sample.error_kind {
+                Some(kind) => *errors.entry(kind.clone()).or_default() += 1,
+                None => latencies.push(sample.latency_ms),
+            }
+        }
+        let failed = samples.len() - latencies.len();
+        Self {
+            target_rps: cfg.rps,
+            achieved_rps: samples.len() as f64 / submitting.as_secs_f64().max(f64::EPSILON),
+            duration_secs: cfg.duration.as_secs_f64(),
+            requests: samples.len(),
+            succeeded: latencies.len(),
+            failed,
+            error_rate: failed as f64 / samples.len().max(1) as f64,
+            errors,
+            latency: LatencySummary::new(latencies),
+            samples,
+        }
+    }
+
+    /// Writes the report in `format` to `out`.
+    pub fn write(&self, format: ReportFormat, out: &mut impl Write) -> anyhow::Result<()> {
+        match format {
+            ReportFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+            }
+            ReportFormat::Csv => {
+                writeln!(
+                    out,
+                    "account_id,submitted_at_ms,latency_ms,error_kind,error"
+                )?;
+                for sample in &self.samples {
+                    writeln!(
+                        out,
+                        "{},{},{},{},{}",
+                        sample.account_id,
+                        sample.submitted_at_ms,
+                        sample.latency_ms,
+                        sample.error_kind.as_deref().unwrap_or_default(),
+                        csv_field(sample.error.as_deref().unwrap_or_default()),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_to(&self, format: ReportFormat, path: &Path) -> anyhow::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(format, &mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl LatencySummary {
+    fn new(mut latencies: Vec<u128>) -> Self {
+        if latencies.is_empty() {
+            return Self::default();
+        }
+        latencies.sort_unstable();
+        let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100] as f64;
+        Self {
+            mean_ms: latencies.iter().sum::<u128>() as f64 / latencies.len() as f64,
+            p50_ms: percentile(50),
+            p90_ms: percentile(90),
+            p95_ms: percentile(95),
+            p99_ms: percentile(99),
+            max_ms: percentile(100),
+        }
+    }
+}
+
+fn error_kind(err: &Error) -> &'static str {
+    match err {
+        Error::Rpc(_) => "rpc",
+        Error::MalformedResponse(_) => "malformed_response",
+        Error::InvalidRequest(_) => "invalid_request",
+        Error::SignFailed(_) => "sign_failed",
+        Error::Timeout => "timeout",
+        Error::InvalidSignature(_) => "invalid_signature",
+    }
+}
+
+/// A random payload that is always a valid scalar, since its top byte is cleared.
+fn random_payload() -> [u8; 32] {
+    let mut payload = rand::random::<[u8; 32]>();
+    payload[0] = 0;
+    payload
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
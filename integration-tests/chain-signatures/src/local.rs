@@ -6,20 +6,24 @@ use anyhow::Context;
 use async_process::Child;
 use mpc_keys::hpke;
 use mpc_node::config::OverrideConfig;
-use near_workspaces::Account;
+use near_workspaces::{Account, AccountId};
 use shell_escape::escape;
+use std::path::PathBuf;
 
 pub struct Node {
     pub address: String,
     pub account: Account,
     pub sign_sk: near_crypto::SecretKey,
     pub cipher_pk: hpke::PublicKey,
-    cipher_sk: hpke::SecretKey,
+    pub cipher_sk: hpke::SecretKey,
     cfg: MultichainConfig,
+    failpoints: Option<String>,
     web_port: u16,
 
     // process held so it's not dropped. Once dropped, process will be killed.
     process: Child,
+    /// File the output of the node process is written to.
+    pub log_path: PathBuf,
     // near rpc address, after proxy
     pub near_rpc: String,
 }
@@ -31,6 +35,8 @@ pub struct NodeConfig {
     pub cipher_sk: hpke::SecretKey,
     pub sign_sk: near_crypto::SecretKey,
     pub cfg: MultichainConfig,
+    /// Failpoints armed on the node, in the `MPC_FAILPOINTS` format.
+    pub failpoints: Option<String>,
     // near rpc address, after proxy
     pub near_rpc: String,
 }
@@ -49,11 +55,17 @@ impl Node {
             near_crypto::SecretKey::from_seed(near_crypto::KeyType::ED25519, "integration-test");
 
         let indexer_options = mpc_node::indexer::Options {
+            indexer_kind: mpc_node::indexer::IndexerKind::Lake,
+            rpc_url: None,
+            rpc_poll_interval: 500,
             s3_bucket: ctx.localstack.s3_bucket.clone(),
             s3_region: ctx.localstack.s3_region.clone(),
             s3_url: Some(ctx.localstack.s3_host_address.clone()),
             start_block_height: 0,
+            indexer_start_block: None,
             running_threshold: 120,
+            derivation_cache_size: 0,
+            derivation_hot_paths: Vec::new(),
             behind_threshold: 120,
         };
         let near_rpc = ctx.lake_indexer.rpc_host_address.clone();
@@ -62,10 +74,10 @@ impl Node {
             near_rpc: near_rpc.clone(),
             mpc_contract_id: mpc_contract_id.clone(),
             account_id: account_id.clone(),
-            account_sk: account_sk.to_string().parse()?,
+            account_sk: Some(account_sk.to_string().parse()?),
             web_port,
-            cipher_pk: hex::encode(cipher_pk.to_bytes()),
-            cipher_sk: hex::encode(cipher_sk.to_bytes()),
+            cipher_pk: Some(hex::encode(cipher_pk.to_bytes())),
+            cipher_sk: Some(hex::encode(cipher_sk.to_bytes())),
             sign_sk: Some(sign_sk.clone()),
             indexer_options,
             my_address: None,
@@ -73,9 +85,17 @@ impl Node {
             override_config: Some(OverrideConfig::new(serde_json::to_value(
                 cfg.protocol.clone(),
             )?)),
+            timeouts_config: None,
+            triple_poke_threads: None,
+            config_file: None,
             client_header_referer: None,
+            admin_token: None,
             mesh_options: ctx.mesh_options.clone(),
             message_options: ctx.message_options.clone(),
+            hsm_options: ctx.hsm_options.clone(),
+            log_format: mpc_node::cli::LogFormat::default(),
+            telemetry_options: mpc_node::telemetry::Options::default(),
+            audit_options: mpc_node::audit::Options::default(),
         };
 
         let cmd = executable(ctx.release, crate::execute::PACKAGE_MULTICHAIN)
@@ -98,6 +118,7 @@ impl Node {
             cipher_sk,
             sign_sk,
             cfg: cfg.clone(),
+            failpoints: None,
             near_rpc,
         };
         Ok(node_config)
@@ -107,24 +128,13 @@ impl Node {
         ctx: &super::Context<'_>,
         cfg: &MultichainConfig,
         account: &Account,
+        failpoints: Option<String>,
     ) -> anyhow::Result<Self> {
         let web_port = utils::pick_unused_port().await?;
         let (cipher_sk, cipher_pk) = hpke::generate();
         let sign_sk =
             near_crypto::SecretKey::from_seed(near_crypto::KeyType::ED25519, "integration-test");
-        let near_rpc = ctx.lake_indexer.rpc_host_address.clone();
-
-        let proxy_name = format!("rpc_from_node_{}", account.id());
-        let rpc_port_proxied = utils::pick_unused_port().await?;
-        let rpc_address_proxied = format!("http://127.0.0.1:{}", rpc_port_proxied);
-        let address = format!("http://127.0.0.1:{web_port}");
-        tracing::info!(
-            "Proxy RPC address {} accessed by node@{} to {}",
-            near_rpc,
-            address,
-            rpc_address_proxied
-        );
-        LakeIndexer::populate_proxy(&proxy_name, true, &rpc_address_proxied, &near_rpc).await?;
+        let rpc_address_proxied = Self::proxy_rpc(ctx, account.id(), web_port).await?;
 
         Self::spawn(
             ctx,
@@ -135,30 +145,59 @@ impl Node {
                 cipher_sk,
                 sign_sk,
                 cfg: cfg.clone(),
+                failpoints,
                 near_rpc: rpc_address_proxied,
             },
         )
         .await
     }
 
+    /// Proxies the RPC of the sandbox for the node of `account_id`, so that tests can slow down or
+    /// cut off its connection to it. Returns the address the node reaches the RPC at.
+    pub async fn proxy_rpc(
+        ctx: &super::Context<'_>,
+        account_id: &AccountId,
+        web_port: u16,
+    ) -> anyhow::Result<String> {
+        let near_rpc = ctx.lake_indexer.rpc_host_address.clone();
+        let proxy_name = format!("rpc_from_node_{}", account_id);
+        let rpc_port_proxied = utils::pick_unused_port().await?;
+        let rpc_address_proxied = format!("http://127.0.0.1:{}", rpc_port_proxied);
+        let address = format!("http://127.0.0.1:{web_port}");
+        tracing::info!(
+            "Proxy RPC address {} accessed by node@{} to {}",
+            near_rpc,
+            address,
+            rpc_address_proxied
+        );
+        LakeIndexer::populate_proxy(&proxy_name, true, &rpc_address_proxied, &near_rpc).await?;
+        Ok(rpc_address_proxied)
+    }
+
     pub async fn spawn(ctx: &super::Context<'_>, config: NodeConfig) -> anyhow::Result<Self> {
         let web_port = config.web_port;
         let indexer_options = mpc_node::indexer::Options {
+            indexer_kind: mpc_node::indexer::IndexerKind::Lake,
+            rpc_url: None,
+            rpc_poll_interval: 500,
             s3_bucket: ctx.localstack.s3_bucket.clone(),
             s3_region: ctx.localstack.s3_region.clone(),
             s3_url: Some(ctx.localstack.s3_host_address.clone()),
             start_block_height: 0,
+            indexer_start_block: None,
             running_threshold: 120,
+            derivation_cache_size: 0,
+            derivation_hot_paths: Vec::new(),
             behind_threshold: 120,
         };
         let cli = mpc_node::cli::Cli::Start {
             near_rpc: config.near_rpc.clone(),
             mpc_contract_id: ctx.mpc_contract.id().clone(),
             account_id: config.account.id().clone(),
-            account_sk: config.account.secret_key().to_string().parse()?,
+            account_sk: Some(config.account.secret_key().to_string().parse()?),
             web_port,
-            cipher_pk: hex::encode(config.cipher_pk.to_bytes()),
-            cipher_sk: hex::encode(config.cipher_sk.to_bytes()),
+            cipher_pk: Some(hex::encode(config.cipher_pk.to_bytes())),
+            cipher_sk: Some(hex::encode(config.cipher_sk.to_bytes())),
             sign_sk: Some(config.sign_sk.clone()),
             indexer_options,
             my_address: None,
@@ -166,16 +205,36 @@ impl Node {
             override_config: Some(OverrideConfig::new(serde_json::to_value(
                 config.cfg.protocol.clone(),
             )?)),
+            timeouts_config: None,
+            triple_poke_threads: None,
+            config_file: None,
             client_header_referer: None,
+            admin_token: None,
             mesh_options: ctx.mesh_options.clone(),
             message_options: ctx.message_options.clone(),
+            hsm_options: ctx.hsm_options.clone(),
+            log_format: mpc_node::cli::LogFormat::default(),
+            telemetry_options: mpc_node::telemetry::Options::default(),
+            audit_options: mpc_node::audit::Options::default(),
         };
 
         let mpc_node_id = format!("multichain/{}", config.account.id());
-        let process = execute::spawn_multichain(ctx.release, &mpc_node_id, cli)?;
+        let (process, log_path) = execute::spawn_multichain(
+            ctx.release,
+            &mpc_node_id,
+            cli,
+            config.failpoints.as_deref(),
+        )?;
         let address = format!("http://127.0.0.1:{web_port}");
-        tracing::info!("node is starting at {address}");
-        utils::ping_until_ok(&address, 60).await?;
+        // The pid allows attaching a debugger to the node, e.g. with `rust-lldb -p <pid>`.
+        tracing::info!(
+            pid = process.id(),
+            log = %log_path.display(),
+            "node is starting at {address}"
+        );
+        utils::ping_until_ok(&address, 60)
+            .await
+            .with_context(|| format!("node did not start, see {}", log_path.display()))?;
         tracing::info!(node_account_id = %config.account.id(), ?address, "node started");
 
         Ok(Self {
@@ -186,8 +245,10 @@ impl Node {
             cipher_sk: config.cipher_sk,
             near_rpc: config.near_rpc,
             cfg: config.cfg,
+            failpoints: config.failpoints,
             web_port,
             process,
+            log_path,
         })
     }
 
@@ -202,6 +263,7 @@ impl Node {
             cipher_sk: self.cipher_sk.clone(),
             sign_sk: self.sign_sk.clone(),
             cfg: self.cfg.clone(),
+            failpoints: self.failpoints.clone(),
             near_rpc: self.near_rpc.clone(),
         }
     }
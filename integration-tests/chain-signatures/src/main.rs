@@ -1,10 +1,41 @@
-use clap::Parser;
-use integration_tests_chain_signatures::containers::DockerClient;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use crypto_shared::{HashAlgorithm, SignMessage};
+use integration_tests_chain_signatures::attach::PersistedEnv;
+use integration_tests_chain_signatures::bench::{self, BenchConfig};
+use integration_tests_chain_signatures::containers::{ContainerBackend, DockerClient};
+use integration_tests_chain_signatures::dashboard::{self, DashboardConfig, DashboardNode};
+use integration_tests_chain_signatures::k8s::{self, K8sConfig};
+use integration_tests_chain_signatures::load_test::{self, LoadTestConfig, ReportFormat};
+use integration_tests_chain_signatures::request_sign::{
+    self, RequestSignConfig, RequestSignPayload,
+};
+use integration_tests_chain_signatures::simulate::{self, SimulateConfig};
+use integration_tests_chain_signatures::snapshot::Snapshot;
 use integration_tests_chain_signatures::{dry_run, run, utils, MultichainConfig};
+use near_workspaces::types::SecretKey;
+use near_workspaces::AccountId;
 use tokio::signal;
 use tracing_subscriber::EnvFilter;
 
 #[derive(Parser, Debug)]
+struct Args {
+    /// Engine to run the containers with
+    #[arg(
+        long,
+        global = true,
+        env("MPC_TEST_BACKEND"),
+        value_enum,
+        default_value_t
+    )]
+    backend: ContainerBackend,
+    #[command(subcommand)]
+    command: Cli,
+}
+
+#[derive(Subcommand, Debug)]
 enum Cli {
     /// Spin up dependent services and mpc nodes
     SetupEnv {
@@ -12,9 +43,188 @@ enum Cli {
         nodes: usize,
         #[arg(short, long, default_value_t = 2)]
         threshold: usize,
+        /// Write the environment to this file, so that test runs can attach to it by pointing
+        /// `MPC_TEST_ATTACH` to the file. The file is removed once the environment is stopped.
+        #[arg(long)]
+        persist: Option<PathBuf>,
+        /// Attach to the environment persisted in this file instead of setting up a new one, to
+        /// check that it is still up and print it.
+        #[arg(long, conflicts_with = "persist")]
+        attach: Option<PathBuf>,
     },
     /// Spin up dependent services but not mpc nodes
     DepServices,
+    /// Save the contract and node accounts, the datastore and the key shares of an environment to
+    /// a file, to be restored with `load-snapshot` or by pointing `MPC_TEST_SNAPSHOT` to the file
+    SaveSnapshot {
+        /// Environment persisted by `setup-env --persist` to take the snapshot of. Has to be run
+        /// from the directory of `setup-env` to find the key shares of the nodes.
+        #[arg(long)]
+        attach: PathBuf,
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Spin up dependent services and mpc nodes with the state saved by `save-snapshot`
+    LoadSnapshot {
+        snapshot: PathBuf,
+        /// Write the environment to this file, so that test runs can attach to it by pointing
+        /// `MPC_TEST_ATTACH` to the file. The file is removed once the environment is stopped.
+        #[arg(long)]
+        persist: Option<PathBuf>,
+    },
+    /// Measure how fast triples and presignatures get generated, and print the results as JSON
+    Bench {
+        /// Amounts of participants to benchmark with
+        #[arg(long, value_delimiter = ',', default_values_t = [3, 5, 8])]
+        participants: Vec<usize>,
+        /// Network latencies in milliseconds added to every round of messages
+        #[arg(long, value_delimiter = ',', default_values_t = [0, 50, 200])]
+        latency_ms: Vec<u64>,
+        /// Runs of each protocol for every combination of participants and latency
+        #[arg(long, default_value_t = 5)]
+        samples: usize,
+        /// Label of the report, such as the release being benchmarked
+        #[arg(long)]
+        label: Option<String>,
+        /// Write the report to this file instead of printing it
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Run nodes in-process against a mock contract through key generation, signing and
+    /// resharing, without docker or a NEAR sandbox, and print the run as JSON
+    Simulate {
+        #[arg(short, long, default_value_t = 3)]
+        nodes: usize,
+        #[arg(short, long, default_value_t = 2)]
+        threshold: usize,
+        /// Signatures requested in every epoch
+        #[arg(long, default_value_t = 3)]
+        signatures: usize,
+        /// Resharings to go through, each of which has a node join, leave, or both
+        #[arg(long, default_value_t = 2)]
+        reshares: usize,
+        /// Seed of the scenario, to replay a previous run
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Render the Kubernetes manifests of a cluster of mpc nodes as JSON, which can be applied
+    /// with `kubectl apply -f`
+    GenerateK8s {
+        #[arg(short, long, default_value_t = 3)]
+        nodes: usize,
+        #[arg(short, long, default_value_t = 2)]
+        threshold: usize,
+        /// Account of each node as `<account id>=<secret key>`, one per node
+        #[arg(long = "account", value_parser = parse_account)]
+        accounts: Vec<(AccountId, near_crypto::SecretKey)>,
+        #[arg(long, default_value = "mpc")]
+        namespace: String,
+        #[arg(long, default_value = "near/mpc-node:latest")]
+        image: String,
+        #[arg(long, default_value = "https://rpc.testnet.near.org")]
+        near_rpc: String,
+        #[arg(long, default_value = "v1.signer-dev.testnet")]
+        mpc_contract_id: AccountId,
+        #[arg(long, default_value = "k8s")]
+        env: String,
+        #[arg(long, default_value = "multichain-k8s")]
+        gcp_project_id: String,
+        /// Datastore emulator to use instead of the datastore of the GCP project
+        #[arg(long)]
+        gcp_datastore_url: Option<String>,
+        /// Size of the volume of every node, which keeps its key share
+        #[arg(long, default_value = "1Gi")]
+        storage_size: String,
+        /// Write the manifests to this file instead of printing them
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Submit a sign request to a deployed contract, wait for its signature and verify it
+    /// against the derived key, then print the signature and how long it took as JSON
+    RequestSign {
+        #[arg(long, default_value = "https://rpc.testnet.near.org")]
+        near_rpc: String,
+        #[arg(long, default_value = "v1.signer-dev.testnet")]
+        mpc_contract_id: AccountId,
+        /// Account that submits the request as `<account id>=<secret key>`
+        #[arg(long, value_parser = parse_account)]
+        account: (AccountId, near_crypto::SecretKey),
+        /// Hex encoded 32 byte payload to sign
+        #[arg(long, value_parser = request_sign::parse_payload, required_unless_present = "message")]
+        payload: Option<[u8; 32]>,
+        /// Hex encoded message that the contract hashes with `--hash` into the payload
+        #[arg(long, conflicts_with = "payload")]
+        message: Option<String>,
+        /// Hash function of `--message`, either `sha256` or `keccak256`
+        #[arg(long, value_parser = parse_hash, default_value = "keccak256", requires = "message")]
+        hash: HashAlgorithm,
+        #[arg(long, default_value = "test")]
+        path: String,
+        /// Seconds to wait for the signature
+        #[arg(long, default_value_t = 300)]
+        timeout: u64,
+    },
+    /// Submit sign requests to a deployed contract at a fixed rate, and print the latencies and
+    /// error rates as JSON
+    LoadTest {
+        #[arg(long, default_value = "https://rpc.testnet.near.org")]
+        near_rpc: String,
+        #[arg(long, default_value = "v1.signer-dev.testnet")]
+        mpc_contract_id: AccountId,
+        /// Account that submits requests as `<account id>=<secret key>`. Can be repeated to
+        /// spread the requests over several accounts.
+        #[arg(long = "account", value_parser = parse_account, required = true)]
+        accounts: Vec<(AccountId, near_crypto::SecretKey)>,
+        /// Sign requests submitted per second
+        #[arg(long, default_value_t = 1.0)]
+        rps: f64,
+        /// Seconds to keep submitting requests for
+        #[arg(long, default_value_t = 60)]
+        duration: u64,
+        #[arg(long, default_value = "test")]
+        path: String,
+        /// Seconds to wait for the signature of every request
+        #[arg(long, default_value_t = 300)]
+        timeout: u64,
+        /// Write the report to this file instead of printing it
+        #[arg(long)]
+        output: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = ReportFormat::Json)]
+        format: ReportFormat,
+    },
+    /// Show the state, stockpile and indexer of every node along with the signatures they
+    /// publish, live in the terminal
+    Dashboard {
+        /// Environment persisted by `setup-env --persist` to watch the nodes of
+        #[arg(long, required_unless_present = "urls")]
+        attach: Option<PathBuf>,
+        /// Address of a node to watch, on top of the nodes of `--attach`. Can be repeated.
+        #[arg(long = "url")]
+        urls: Vec<String>,
+        /// Admin token of the nodes, to also show their peers and whether their indexer is
+        /// behind
+        #[arg(long, env("MPC_ADMIN_TOKEN"))]
+        admin_token: Option<String>,
+        /// Milliseconds between two polls of every node
+        #[arg(long, default_value_t = 1000)]
+        interval_ms: u64,
+        /// Amount of the most recent signatures to show
+        #[arg(long, default_value_t = 20)]
+        recent_signatures: usize,
+    },
+}
+
+fn parse_account(arg: &str) -> anyhow::Result<(AccountId, near_crypto::SecretKey)> {
+    let (account_id, sk) = arg
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("expected <account id>=<secret key>"))?;
+    Ok((account_id.parse()?, sk.parse()?))
+}
+
+fn parse_hash(arg: &str) -> anyhow::Result<HashAlgorithm> {
+    Ok(serde_json::from_value(serde_json::Value::String(
+        arg.to_string(),
+    ))?)
 }
 
 #[tokio::main]
@@ -23,10 +233,28 @@ async fn main() -> anyhow::Result<()> {
         .with_thread_ids(true)
         .with_env_filter(EnvFilter::from_default_env());
     subscriber.init();
-    let docker_client = DockerClient::default();
+    let args = Args::parse();
+    let docker_client = DockerClient::new(args.backend)?;
 
-    match Cli::parse() {
-        Cli::SetupEnv { nodes, threshold } => {
+    match args.command {
+        Cli::SetupEnv {
+            attach: Some(path), ..
+        } => {
+            let env = PersistedEnv::read(&path)?;
+            let nodes = env.clone().attach().await?;
+            nodes
+                .contract()
+                .view("state")
+                .await
+                .map_err(|err| anyhow::anyhow!("environment is not reachable: {err}"))?;
+            print_env(&env)?;
+        }
+        Cli::SetupEnv {
+            nodes,
+            threshold,
+            persist,
+            ..
+        } => {
             println!(
                 "Setting up an environment with {} nodes, {} threshold ...",
                 nodes, threshold
@@ -38,34 +266,19 @@ async fn main() -> anyhow::Result<()> {
             };
             println!("Full config: {:?}", config);
             let nodes = run(config.clone(), &docker_client).await?;
-            let ctx = nodes.ctx();
-            let urls: Vec<_> = (0..config.nodes).map(|i| nodes.url(i)).collect();
-            let near_accounts = nodes.near_accounts();
             let sk_local_path = nodes.ctx().storage_options.sk_share_local_path.clone();
-
-            println!("\nEnvironment is ready:");
-            println!("  docker-network: {}", ctx.docker_network);
-            println!("  release:        {}", ctx.release);
-
-            println!("\nExternal services:");
-            println!("  datastore:     {}", ctx.datastore.local_address);
-            println!("  lake_indexer:  {}", ctx.lake_indexer.rpc_host_address);
-            println!("  redis:  {}", ctx.redis.internal_address);
-
-            println!("\nNodes:");
-            for i in 0..urls.len() {
-                println!("  Node {}", i);
-                println!("    Url: {}", urls[i]);
-                let account_id = near_accounts[i].id();
-                println!("    Account: {}", account_id);
-                let sk = near_accounts[i].secret_key();
-                println!("    Secret Key: {}", sk);
-                let pk = sk.public_key();
-                println!("    Public Key: {}", pk);
+            let env = PersistedEnv::new(&nodes, config.threshold)?;
+            print_env(&env)?;
+            if let Some(path) = &persist {
+                env.write(path)?;
+                println!("\nEnvironment persisted to {}", path.display());
             }
 
             signal::ctrl_c().await.expect("Failed to listen for event");
             println!("Received Ctrl-C");
+            if let Some(path) = &persist {
+                std::fs::remove_file(path)?;
+            }
             utils::clear_local_sk_shares(sk_local_path).await?;
             println!("Clean up finished");
         }
@@ -79,7 +292,227 @@ async fn main() -> anyhow::Result<()> {
             println!("Received Ctrl-C");
             println!("Stopped dependency services");
         }
+        Cli::SaveSnapshot { attach, output } => {
+            let env = PersistedEnv::read(&attach)?;
+            let threshold = env.threshold;
+            let nodes = env.attach().await?;
+            Snapshot::take(&nodes, threshold).await?.write(&output)?;
+            println!("Snapshot saved to {}", output.display());
+        }
+        Cli::LoadSnapshot { snapshot, persist } => {
+            let snapshot = Snapshot::read(&snapshot)?;
+            let threshold = snapshot.threshold;
+            println!(
+                "Loading a snapshot of {} nodes, {} threshold ...",
+                snapshot.nodes.len(),
+                threshold
+            );
+            let nodes = snapshot.load(&docker_client).await?;
+            let sk_local_path = nodes.ctx().storage_options.sk_share_local_path.clone();
+            let env = PersistedEnv::new(&nodes, threshold)?;
+            print_env(&env)?;
+            if let Some(path) = &persist {
+                env.write(path)?;
+                println!("\nEnvironment persisted to {}", path.display());
+            }
+
+            signal::ctrl_c().await.expect("Failed to listen for event");
+            println!("Received Ctrl-C");
+            if let Some(path) = &persist {
+                std::fs::remove_file(path)?;
+            }
+            utils::clear_local_sk_shares(sk_local_path).await?;
+            println!("Clean up finished");
+        }
+        Cli::Bench {
+            participants,
+            latency_ms,
+            samples,
+            label,
+            output,
+        } => {
+            let config = BenchConfig {
+                participants,
+                latencies: latency_ms.into_iter().map(Duration::from_millis).collect(),
+                samples,
+                label,
+            };
+            let report = tokio::task::spawn_blocking(move || bench::run(&config)).await??;
+            let report = serde_json::to_string_pretty(&report)?;
+            match output {
+                Some(path) => std::fs::write(path, report)?,
+                None => println!("{report}"),
+            }
+        }
+        Cli::Simulate {
+            nodes,
+            threshold,
+            signatures,
+            reshares,
+            seed,
+        } => {
+            let report = simulate::run(&SimulateConfig {
+                nodes,
+                threshold,
+                signatures,
+                reshares,
+                seed,
+            })
+            .await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Cli::GenerateK8s {
+            nodes,
+            threshold,
+            accounts,
+            namespace,
+            image,
+            near_rpc,
+            mpc_contract_id,
+            env,
+            gcp_project_id,
+            gcp_datastore_url,
+            storage_size,
+            output,
+        } => {
+            let config = MultichainConfig {
+                nodes,
+                threshold,
+                ..Default::default()
+            };
+            let manifests = k8s::render(
+                &config,
+                &K8sConfig {
+                    namespace,
+                    image,
+                    near_rpc,
+                    mpc_contract_id,
+                    accounts,
+                    env,
+                    gcp_project_id,
+                    gcp_datastore_url,
+                    storage_size,
+                },
+            )?;
+            let manifests = serde_json::to_string_pretty(&manifests)?;
+            match output {
+                Some(path) => std::fs::write(path, manifests)?,
+                None => println!("{manifests}"),
+            }
+        }
+        Cli::RequestSign {
+            near_rpc,
+            mpc_contract_id,
+            account: (account_id, account_sk),
+            payload,
+            message,
+            hash,
+            path,
+            timeout,
+        } => {
+            let payload = match (payload, message) {
+                (_, Some(message)) => RequestSignPayload::Message(SignMessage::new(
+                    hex::decode(message.trim_start_matches("0x"))?,
+                    hash,
+                )),
+                (Some(payload), None) => RequestSignPayload::Raw(payload),
+                (None, None) => anyhow::bail!("either --payload or --message is required"),
+            };
+            let report = request_sign::run(RequestSignConfig {
+                near_rpc,
+                mpc_contract_id,
+                account_id,
+                account_sk,
+                payload,
+                path,
+                timeout: Duration::from_secs(timeout),
+            })
+            .await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Cli::LoadTest {
+            near_rpc,
+            mpc_contract_id,
+            accounts,
+            rps,
+            duration,
+            path,
+            timeout,
+            output,
+            format,
+        } => {
+            let report = load_test::run(LoadTestConfig {
+                near_rpc,
+                mpc_contract_id,
+                accounts,
+                rps,
+                duration: Duration::from_secs(duration),
+                path,
+                timeout: Duration::from_secs(timeout),
+            })
+            .await?;
+            match output {
+                Some(output) => report.write_to(format, &output)?,
+                None => report.write(format, &mut std::io::stdout().lock())?,
+            }
+            tracing::info!(
+                requests = report.requests,
+                error_rate = report.error_rate,
+                p50_ms = report.latency.p50_ms,
+                p99_ms = report.latency.p99_ms,
+                "load test finished"
+            );
+        }
+        Cli::Dashboard {
+            attach,
+            urls,
+            admin_token,
+            interval_ms,
+            recent_signatures,
+        } => {
+            let mut nodes = Vec::new();
+            if let Some(path) = attach {
+                let env = PersistedEnv::read(&path)?;
+                nodes.extend(env.nodes.into_iter().map(|node| DashboardNode {
+                    name: node.account_id.to_string(),
+                    url: node.url,
+                }));
+            }
+            nodes.extend(urls.into_iter().map(|url| DashboardNode {
+                name: url.clone(),
+                url,
+            }));
+            dashboard::run(DashboardConfig {
+                nodes,
+                admin_token,
+                interval: Duration::from_millis(interval_ms),
+                recent_signatures,
+            })
+            .await?;
+        }
     }
 
     Ok(())
 }
+
+fn print_env(env: &PersistedEnv) -> anyhow::Result<()> {
+    println!("\nEnvironment is ready:");
+    println!("  docker-network: {}", env.docker_network);
+    println!("  release:        {}", env.release);
+
+    println!("\nExternal services:");
+    println!("  datastore:     {}", env.datastore_url);
+    println!("  lake_indexer:  {}", env.near_rpc);
+    println!("  redis:  {}", env.redis_url);
+
+    println!("\nNodes:");
+    for (i, node) in env.nodes.iter().enumerate() {
+        println!("  Node {}", i);
+        println!("    Url: {}", node.url);
+        println!("    Account: {}", node.account_id);
+        println!("    Secret Key: {}", node.secret_key);
+        let sk: SecretKey = node.secret_key.parse()?;
+        println!("    Public Key: {}", sk.public_key());
+    }
+    Ok(())
+}
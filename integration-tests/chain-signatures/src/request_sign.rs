@@ -0,0 +1,99 @@
+//! Submits a single `sign` request the way an integrator would, through
+//! `chain-signatures-client`, and reports how long it took to get a verified signature.
+
+use std::time::{Duration, Instant};
+
+use chain_signatures_client::{ChainSignaturesClient, PollOptions};
+use crypto_shared::{DerivedAddresses, SignMessage, SignatureResponse};
+use mpc_contract::primitives::SignRequest;
+use near_account_id::AccountId;
+use near_crypto::{InMemorySigner, SecretKey};
+use serde::Serialize;
+
+#[derive(Debug, Clone)]
+pub struct RequestSignConfig {
+    pub near_rpc: String,
+    pub mpc_contract_id: AccountId,
+    /// Account that submits the request and pays its deposit.
+    pub account_id: AccountId,
+    pub account_sk: SecretKey,
+    pub payload: RequestSignPayload,
+    pub path: String,
+    /// How long to wait for the signature once the request is on chain.
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub enum RequestSignPayload {
+    /// A hash that is signed as is.
+    Raw([u8; 32]),
+    /// A message that the contract hashes into the payload.
+    Message(SignMessage),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestSignReport {
+    pub account_id: AccountId,
+    pub path: String,
+    pub key_version: u32,
+    /// Hex encoded payload that got signed.
+    pub payload: String,
+    /// Addresses controlled by the key that signed the payload.
+    pub addresses: DerivedAddresses,
+    pub signature: SignatureResponse,
+    /// Time from submitting the request until its signature was verified.
+    pub latency_ms: u128,
+}
+
+/// Submits the request of `cfg`, waits for its signature and checks it against the key
+/// derived for the account and path. Fails if the request is rejected, times out or comes
+/// back with a signature of another key.
+pub async fn run(cfg: RequestSignConfig) -> anyhow::Result<RequestSignReport> {
+    let client = ChainSignaturesClient::new(&cfg.near_rpc, cfg.mpc_contract_id.clone()).with_poll(
+        PollOptions {
+            timeout: cfg.timeout,
+            ..Default::default()
+        },
+    );
+    let signer = InMemorySigner::from_secret_key(cfg.account_id.clone(), cfg.account_sk.clone());
+    let key_version = client.latest_key_version().await?;
+    let addresses = client.derived_addresses(&cfg.path, &cfg.account_id).await?;
+
+    let (payload, message) = match cfg.payload {
+        RequestSignPayload::Raw(payload) => (payload, None),
+        RequestSignPayload::Message(message) => (message.digest(), Some(message)),
+    };
+    let request = SignRequest {
+        payload: if message.is_some() { [0; 32] } else { payload },
+        path: cfg.path.clone(),
+        key_version,
+        message,
+        max_wait_blocks: None,
+    };
+    tracing::info!(
+        account_id = %cfg.account_id,
+        path = cfg.path,
+        payload = hex::encode(payload),
+        "submitting sign request"
+    );
+
+    let started = Instant::now();
+    let signature = client.sign_request(&signer, request).await?;
+    Ok(RequestSignReport {
+        account_id: cfg.account_id,
+        path: cfg.path,
+        key_version,
+        payload: hex::encode(payload),
+        addresses,
+        signature,
+        latency_ms: started.elapsed().as_millis(),
+    })
+}
+
+/// Parses a hex encoded payload of 32 bytes, with or without a `0x` prefix.
+pub fn parse_payload(arg: &str) -> anyhow::Result<[u8; 32]> {
+    let bytes = hex::decode(arg.trim_start_matches("0x"))?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| anyhow::anyhow!("payload is {} bytes, not 32", bytes.len()))
+}
@@ -0,0 +1,592 @@
+//! Simulation of a whole MPC network within this process, without Docker, NEAR or any other
+//! external service.
+//!
+//! Virtual nodes run the same cait-sith protocols as the real nodes, exchanging their messages
+//! over channels instead of the mesh, and follow an in-memory mock of the contract through key
+//! generation, signing and resharing. A run takes seconds, so that many scenarios can be checked
+//! in a single test run. The seed picks the scenario: the payloads, which participants produce
+//! each signature, who joins and leaves on every resharing, and the order in which a node handles
+//! the messages waiting for it. When the nodes get scheduled is still up to tokio.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+use cait_sith::protocol::{Action, MessageData, Participant, Protocol};
+use cait_sith::triples::TripleGenerationOutput;
+use cait_sith::{FullSignature, KeygenOutput, PresignArguments, PresignOutput};
+use crypto_shared::{derive_epsilon, derive_key, ScalarExt, SignatureResponse};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::{AffinePoint, Scalar, Secp256k1};
+use mpc_node::kdf::{derive_delta, into_eth_sig};
+use near_account_id::AccountId;
+use rand::rngs::StdRng;
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+/// How long a virtual node waits for a message before its protocol is considered stuck.
+const STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+type Protocols<T> = Vec<(Participant, Box<dyn Protocol<Output = T> + Send>)>;
+
+#[derive(Debug, Clone)]
+pub struct SimulateConfig {
+    /// Nodes the network starts with.
+    pub nodes: usize,
+    pub threshold: usize,
+    /// Signatures requested in every epoch.
+    pub signatures: usize,
+    /// Resharings to go through, each of which has a node join, leave, or both.
+    pub reshares: usize,
+    /// Seed of the scenario, picked at random if not set.
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationReport {
+    /// Seed of the scenario, to replay it with.
+    pub seed: u64,
+    /// Root public key in compressed SEC1 form, hex encoded. It stays the same in every epoch.
+    pub public_key: String,
+    pub epochs: Vec<EpochReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EpochReport {
+    pub epoch: u64,
+    pub participants: Vec<u32>,
+    pub threshold: usize,
+    /// Signatures that the mock contract accepted in this epoch.
+    pub signatures: usize,
+    /// Time spent on the key generation or resharing that started the epoch.
+    pub setup_ms: f64,
+    /// Time spent producing the signatures of the epoch.
+    pub signing_ms: f64,
+}
+
+/// State of the [`MockContract`], following the one of the real contract with the nodes
+/// identified by their participant ids.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockState {
+    Initializing {
+        candidates: BTreeSet<u32>,
+        threshold: usize,
+        pk_votes: BTreeMap<u32, AffinePoint>,
+    },
+    Running {
+        epoch: u64,
+        participants: BTreeSet<u32>,
+        threshold: usize,
+        public_key: AffinePoint,
+    },
+    Resharing {
+        old_epoch: u64,
+        old_participants: BTreeSet<u32>,
+        new_participants: BTreeSet<u32>,
+        threshold: usize,
+        public_key: AffinePoint,
+        finished_votes: BTreeSet<u32>,
+    },
+}
+
+/// In-memory stand-in of the contract, checking the votes of the nodes and the signatures they
+/// respond with the way the real one does.
+#[derive(Debug)]
+pub struct MockContract {
+    state: MockState,
+    request_counter: u64,
+    /// Payload and epsilon of the requests that were not responded to yet.
+    pending_requests: HashMap<u64, (Scalar, Scalar)>,
+}
+
+impl MockContract {
+    pub fn new(candidates: BTreeSet<u32>, threshold: usize) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            threshold >= 2 && threshold <= candidates.len(),
+            "threshold {threshold} is out of range for {} candidates",
+            candidates.len()
+        );
+        Ok(Self {
+            state: MockState::Initializing {
+                candidates,
+                threshold,
+                pk_votes: BTreeMap::new(),
+            },
+            request_counter: 0,
+            pending_requests: HashMap::new(),
+        })
+    }
+
+    pub fn state(&self) -> &MockState {
+        &self.state
+    }
+
+    /// Returns whether the vote completed key generation.
+    pub fn vote_pk(&mut self, voter: u32, public_key: AffinePoint) -> anyhow::Result<bool> {
+        let MockState::Initializing {
+            candidates,
+            threshold,
+            pk_votes,
+        } = &mut self.state
+        else {
+            anyhow::bail!("protocol is not initializing");
+        };
+        anyhow::ensure!(candidates.contains(&voter), "{voter} is not a candidate");
+        if let Some((other, _)) = pk_votes.iter().find(|(_, pk)| **pk != public_key) {
+            anyhow::bail!("{voter} voted for another public key than {other}");
+        }
+        pk_votes.insert(voter, public_key);
+        if pk_votes.len() < candidates.len() {
+            return Ok(false);
+        }
+        self.state = MockState::Running {
+            epoch: 0,
+            participants: candidates.clone(),
+            threshold: *threshold,
+            public_key,
+        };
+        Ok(true)
+    }
+
+    /// Starts a resharing to `new_participants`, which the real contract does once enough
+    /// participants voted to have a candidate join or a participant leave.
+    pub fn propose_reshare(&mut self, new_participants: BTreeSet<u32>) -> anyhow::Result<()> {
+        let MockState::Running {
+            epoch,
+            participants,
+            threshold,
+            public_key,
+        } = &self.state
+        else {
+            anyhow::bail!("protocol is not running");
+        };
+        anyhow::ensure!(
+            new_participants.len() >= *threshold,
+            "{} participants are not enough for a threshold of {threshold}",
+            new_participants.len()
+        );
+        anyhow::ensure!(
+            participants.intersection(&new_participants).count() >= *threshold,
+            "not enough participants stay to reshare the key"
+        );
+        self.state = MockState::Resharing {
+            old_epoch: *epoch,
+            old_participants: participants.clone(),
+            new_participants,
+            threshold: *threshold,
+            public_key: *public_key,
+            finished_votes: BTreeSet::new(),
+        };
+        Ok(())
+    }
+
+    /// Returns whether the vote completed the resharing.
+    pub fn vote_reshared(&mut self, voter: u32, epoch: u64) -> anyhow::Result<bool> {
+        let MockState::Resharing {
+            old_epoch,
+            new_participants,
+            threshold,
+            public_key,
+            finished_votes,
+            ..
+        } = &mut self.state
+        else {
+            anyhow::bail!("protocol is not resharing");
+        };
+        anyhow::ensure!(
+            *old_epoch + 1 == epoch,
+            "voted for epoch {epoch}, expected {}",
+            *old_epoch + 1
+        );
+        anyhow::ensure!(
+            new_participants.contains(&voter),
+            "{voter} is not a new participant"
+        );
+        finished_votes.insert(voter);
+        if finished_votes.len() < new_participants.len() {
+            return Ok(false);
+        }
+        self.state = MockState::Running {
+            epoch,
+            participants: new_participants.clone(),
+            threshold: *threshold,
+            public_key: *public_key,
+        };
+        Ok(true)
+    }
+
+    /// Returns the id of the request.
+    pub fn sign(&mut self, payload: Scalar, epsilon: Scalar) -> anyhow::Result<u64> {
+        anyhow::ensure!(
+            matches!(self.state, MockState::Running { .. }),
+            "protocol is not running"
+        );
+        self.request_counter += 1;
+        self.pending_requests
+            .insert(self.request_counter, (payload, epsilon));
+        Ok(self.request_counter)
+    }
+
+    pub fn respond(&mut self, request_id: u64, response: &SignatureResponse) -> anyhow::Result<()> {
+        let (MockState::Running { public_key, .. } | MockState::Resharing { public_key, .. }) =
+            &self.state
+        else {
+            anyhow::bail!("protocol is not running");
+        };
+        let Some((payload, epsilon)) = self.pending_requests.get(&request_id) else {
+            anyhow::bail!("request {request_id} is not pending");
+        };
+        crypto_shared::kdf::check_ec_signature(
+            &derive_key(*public_key, *epsilon),
+            &response.big_r.affine_point,
+            &response.s.scalar,
+            *payload,
+            response.recovery_id,
+        )?;
+        self.pending_requests.remove(&request_id);
+        Ok(())
+    }
+}
+
+pub async fn run(config: &SimulateConfig) -> anyhow::Result<SimulationReport> {
+    let seed = config.seed.unwrap_or_else(rand::random);
+    tracing::info!(seed, "simulating");
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut contract = MockContract::new((0..config.nodes as u32).collect(), config.threshold)?;
+    let mut epochs = Vec::new();
+
+    let started = Instant::now();
+    let mut shares = keygen(&mut contract, &mut rng).await?;
+    let setup = started.elapsed();
+
+    for reshare in 0..=config.reshares {
+        let setup = if reshare == 0 {
+            setup
+        } else {
+            let started = Instant::now();
+            let MockState::Running { participants, .. } = contract.state().clone() else {
+                anyhow::bail!("protocol is not running");
+            };
+            contract.propose_reshare(next_participants(
+                &participants,
+                config.threshold,
+                &mut rng,
+            ))?;
+            shares = reshare_key(&mut contract, shares, &mut rng).await?;
+            started.elapsed()
+        };
+
+        let started = Instant::now();
+        for _ in 0..config.signatures {
+            sign(&mut contract, &shares, &mut rng).await?;
+        }
+        let MockState::Running {
+            epoch,
+            participants,
+            threshold,
+            ..
+        } = contract.state()
+        else {
+            anyhow::bail!("protocol is not running");
+        };
+        epochs.push(EpochReport {
+            epoch: *epoch,
+            participants: participants.iter().copied().collect(),
+            threshold: *threshold,
+            signatures: config.signatures,
+            setup_ms: as_ms(setup),
+            signing_ms: as_ms(started.elapsed()),
+        });
+    }
+
+    let MockState::Running { public_key, .. } = contract.state() else {
+        anyhow::bail!("protocol is not running");
+    };
+    Ok(SimulationReport {
+        seed,
+        public_key: hex::encode(public_key.to_encoded_point(true).as_bytes()),
+        epochs,
+    })
+}
+
+/// Participants after the next resharing, which has a new node join, one of the participants
+/// leave, or both, as long as enough of them stay.
+fn next_participants(
+    participants: &BTreeSet<u32>,
+    threshold: usize,
+    rng: &mut StdRng,
+) -> BTreeSet<u32> {
+    let mut next = participants.clone();
+    let (join, leave) = match rng.gen_range(0, 3) {
+        0 => (true, false),
+        1 => (false, true),
+        _ => (true, true),
+    };
+    if leave && participants.len() > threshold {
+        let left = *participants.iter().choose(rng).unwrap();
+        next.remove(&left);
+    }
+    if join || next.len() == participants.len() {
+        next.insert(participants.iter().max().map_or(0, |max| max + 1));
+    }
+    next
+}
+
+async fn keygen(
+    contract: &mut MockContract,
+    rng: &mut StdRng,
+) -> anyhow::Result<BTreeMap<u32, Scalar>> {
+    let MockState::Initializing {
+        candidates,
+        threshold,
+        ..
+    } = contract.state().clone()
+    else {
+        anyhow::bail!("protocol is not initializing");
+    };
+    let participants = as_participants(&candidates);
+    let mut protocols = Protocols::<KeygenOutput<Secp256k1>>::new();
+    for &me in &participants {
+        let protocol = cait_sith::keygen::<Secp256k1>(&participants, me, threshold)?;
+        protocols.push((me, Box::new(protocol)));
+    }
+
+    let mut shares = BTreeMap::new();
+    for (id, output) in candidates.iter().zip(run_protocols(protocols, rng).await?) {
+        contract.vote_pk(*id, output.public_key)?;
+        shares.insert(*id, output.private_share);
+    }
+    Ok(shares)
+}
+
+async fn reshare_key(
+    contract: &mut MockContract,
+    mut shares: BTreeMap<u32, Scalar>,
+    rng: &mut StdRng,
+) -> anyhow::Result<BTreeMap<u32, Scalar>> {
+    let MockState::Resharing {
+        old_epoch,
+        old_participants,
+        new_participants,
+        threshold,
+        public_key,
+        ..
+    } = contract.state().clone()
+    else {
+        anyhow::bail!("protocol is not resharing");
+    };
+    let old = as_participants(&old_participants);
+    let new = as_participants(&new_participants);
+    // Participants that leave are not part of the resharing, like the nodes that got kicked.
+    let mut protocols = Protocols::<Scalar>::new();
+    for (id, me) in new_participants.iter().zip(&new) {
+        let protocol = cait_sith::reshare::<Secp256k1>(
+            &old,
+            threshold,
+            &new,
+            threshold,
+            *me,
+            shares.remove(id),
+            public_key,
+        )?;
+        protocols.push((*me, Box::new(protocol)));
+    }
+
+    let mut new_shares = BTreeMap::new();
+    for (id, share) in new_participants
+        .iter()
+        .zip(run_protocols(protocols, rng).await?)
+    {
+        contract.vote_reshared(*id, old_epoch + 1)?;
+        new_shares.insert(*id, share);
+    }
+    anyhow::ensure!(
+        matches!(contract.state(), MockState::Running { .. }),
+        "resharing did not complete"
+    );
+    Ok(new_shares)
+}
+
+/// Requests a signature from the contract and has `threshold` of the participants, picked at
+/// random, produce it.
+async fn sign(
+    contract: &mut MockContract,
+    shares: &BTreeMap<u32, Scalar>,
+    rng: &mut StdRng,
+) -> anyhow::Result<()> {
+    let MockState::Running {
+        participants,
+        threshold,
+        public_key,
+        ..
+    } = contract.state().clone()
+    else {
+        anyhow::bail!("protocol is not running");
+    };
+    let requester: AccountId = "simulation.test".parse()?;
+    let path = format!("path-{}", rng.gen::<u32>());
+    let payload = Scalar::from_non_biased(rng.gen());
+    let epsilon = derive_epsilon(&requester, &path);
+    let request_id = contract.sign(payload, epsilon)?;
+
+    let mut signers = participants.iter().copied().collect::<Vec<_>>();
+    signers.shuffle(rng);
+    signers.truncate(threshold);
+    let signers = signers.into_iter().collect::<BTreeSet<_>>();
+    let participants = as_participants(&signers);
+
+    let triples0 = run_protocols(triple_protocols(&participants, threshold)?, rng).await?;
+    let triples1 = run_protocols(triple_protocols(&participants, threshold)?, rng).await?;
+    let mut presign = Protocols::<PresignOutput<Secp256k1>>::new();
+    for (((id, me), triple0), triple1) in signers
+        .iter()
+        .zip(&participants)
+        .zip(triples0)
+        .zip(triples1)
+    {
+        let protocol = cait_sith::presign(
+            &participants,
+            *me,
+            &participants,
+            *me,
+            PresignArguments {
+                triple0,
+                triple1,
+                keygen_out: KeygenOutput {
+                    private_share: shares[id],
+                    public_key,
+                },
+                threshold,
+            },
+        )?;
+        presign.push((*me, Box::new(protocol)));
+    }
+    let presignatures = run_protocols(presign, rng).await?;
+
+    // Tweak the presignatures the way the nodes do before signing.
+    let delta = derive_delta(rng.gen(), rng.gen(), presignatures[0].big_r);
+    let derived_key = derive_key(public_key, epsilon);
+    let mut protocols = Protocols::<FullSignature<Secp256k1>>::new();
+    for (me, PresignOutput { big_r, k, sigma }) in participants.iter().zip(presignatures) {
+        let output = PresignOutput {
+            big_r: (big_r * delta).to_affine(),
+            k: k * delta.invert().unwrap(),
+            sigma: (sigma + epsilon * k) * delta.invert().unwrap(),
+        };
+        let protocol = cait_sith::sign(&participants, *me, derived_key, output, payload)?;
+        protocols.push((*me, Box::new(protocol)));
+    }
+    let signatures = run_protocols(protocols, rng).await?;
+
+    for signature in &signatures {
+        anyhow::ensure!(
+            signature.big_r == signatures[0].big_r && signature.s == signatures[0].s,
+            "participants produced different signatures"
+        );
+    }
+    let response = into_eth_sig(
+        &derived_key,
+        &signatures[0].big_r,
+        &signatures[0].s,
+        payload,
+    )?;
+    contract.respond(request_id, &response)
+}
+
+fn triple_protocols(
+    participants: &[Participant],
+    threshold: usize,
+) -> anyhow::Result<Protocols<TripleGenerationOutput<Secp256k1>>> {
+    let mut protocols = Protocols::new();
+    for &me in participants {
+        let protocol =
+            cait_sith::triples::generate_triple::<Secp256k1>(participants, me, threshold)?;
+        protocols.push((me, Box::new(protocol)));
+    }
+    Ok(protocols)
+}
+
+fn as_participants(ids: &BTreeSet<u32>) -> Vec<Participant> {
+    ids.iter().copied().map(Participant::from).collect()
+}
+
+/// Inbox of every virtual node, through which the others send it messages.
+type ChannelMesh = HashMap<Participant, mpsc::UnboundedSender<(Participant, MessageData)>>;
+
+/// Runs `protocols` to completion, each in a virtual node of its own, and returns their outputs
+/// in the order of the participants.
+async fn run_protocols<T: Send + 'static>(
+    protocols: Protocols<T>,
+    rng: &mut StdRng,
+) -> anyhow::Result<Vec<T>> {
+    let mut inboxes = HashMap::new();
+    let mut mesh = ChannelMesh::new();
+    for (me, _) in &protocols {
+        let (tx, rx) = mpsc::unbounded_channel();
+        mesh.insert(*me, tx);
+        inboxes.insert(*me, rx);
+    }
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for (me, protocol) in protocols {
+        let inbox = inboxes.remove(&me).unwrap();
+        let mesh = mesh.clone();
+        let rng = StdRng::seed_from_u64(rng.gen());
+        tasks.spawn(async move { (me, run_node(me, protocol, inbox, mesh, rng).await) });
+    }
+    drop(mesh);
+
+    let mut outputs = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        let (me, output) = result?;
+        outputs.push((me, output?));
+    }
+    outputs.sort_by_key(|(me, _)| *me);
+    Ok(outputs.into_iter().map(|(_, output)| output).collect())
+}
+
+async fn run_node<T>(
+    me: Participant,
+    mut protocol: Box<dyn Protocol<Output = T> + Send>,
+    mut inbox: mpsc::UnboundedReceiver<(Participant, MessageData)>,
+    mesh: ChannelMesh,
+    mut rng: StdRng,
+) -> anyhow::Result<T> {
+    loop {
+        match protocol.poke()? {
+            Action::Wait => {
+                let Ok(Some(message)) = tokio::time::timeout(STALL_TIMEOUT, inbox.recv()).await
+                else {
+                    anyhow::bail!("{me:?} is stuck waiting for messages");
+                };
+                let mut messages = vec![message];
+                while let Ok(message) = inbox.try_recv() {
+                    messages.push(message);
+                }
+                messages.shuffle(&mut rng);
+                for (from, data) in messages {
+                    protocol.message(from, data);
+                }
+            }
+            Action::SendMany(data) => {
+                for (to, inbox) in &mesh {
+                    if *to != me {
+                        // Nodes that are already done do not need the message anymore.
+                        let _ = inbox.send((me, data.clone()));
+                    }
+                }
+            }
+            Action::SendPrivate(to, data) => {
+                if let Some(inbox) = mesh.get(&to) {
+                    let _ = inbox.send((me, data));
+                }
+            }
+            Action::Return(output) => return Ok(output),
+        }
+    }
+}
+
+fn as_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,321 @@
+//! Snapshots of an environment, so that tests of long running scenarios such as the states after
+//! a resharing can start from them instead of going through the scenario again. A snapshot is
+//! taken with `save-snapshot` or [`Snapshot::take`], and keeps the contract and node accounts of
+//! the sandbox, the entities of the datastore emulator and the key shares of the nodes. Loading it
+//! with `load-snapshot` or `MPC_TEST_SNAPSHOT=<FILE>` sets up new containers, patches the
+//! accounts into the new sandbox and starts the nodes again with the keys and ports they had.
+//!
+//! Only the accounts of the environment are kept, not the blocks of the sandbox, so sign requests
+//! that were pending when the snapshot was taken are not picked up again by the nodes. The
+//! triples and presignatures in redis are not kept either and get generated again.
+
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::Context as _;
+use mpc_contract::config::Config;
+use near_workspaces::network::Sandbox;
+use near_workspaces::types::{AccessKey, AccountDetailsPatch, CryptoHash, NearToken, SecretKey};
+use near_workspaces::{Account, AccountId, Contract, Worker};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::containers::DockerClient;
+use crate::local::{self, NodeConfig};
+use crate::{MultichainConfig, Nodes, GCP_PROJECT_ID, SK_SHARE_LOCAL_PATH};
+
+/// Environment variable pointing test runs to a snapshot to start from.
+pub const SNAPSHOT_ENV_VAR: &str = "MPC_TEST_SNAPSHOT";
+
+/// Mutations the datastore accepts in a single commit.
+const DATASTORE_COMMIT_LIMIT: usize = 500;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SnapshotAccount {
+    pub account_id: AccountId,
+    pub secret_key: String,
+    pub balance: NearToken,
+    pub storage_usage: u64,
+    /// Hex encoded wasm deployed to the account, if any.
+    pub code: Option<String>,
+    /// Hex encoded keys and values of the storage of the contract of the account.
+    pub state: Vec<(String, String)>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SnapshotNode {
+    pub account: SnapshotAccount,
+    pub url: String,
+    /// Hex encoded cipher secret key of the node.
+    pub cipher_sk: String,
+    pub sign_sk: String,
+    /// Hex encoded key share file of the node, if it had a key share.
+    pub key_share: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub threshold: usize,
+    pub contract: SnapshotAccount,
+    pub nodes: Vec<SnapshotNode>,
+    /// Entities of the datastore emulator, as returned by its REST API.
+    pub datastore: Vec<serde_json::Value>,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of `nodes`, which have to run as local processes for their key shares to
+    /// be read. Attached environments have to be set up by `setup-env` in the same directory.
+    pub async fn take(nodes: &Nodes<'_>, threshold: usize) -> anyhow::Result<Self> {
+        if let Nodes::Docker { .. } = nodes {
+            anyhow::bail!("snapshots are only supported for nodes running as local processes");
+        }
+        let worker = nodes.worker();
+        let contract = take_account(worker, nodes.contract().as_account()).await?;
+
+        let mut snapshot_nodes = Vec::with_capacity(nodes.len());
+        for (i, account) in nodes.near_accounts().into_iter().enumerate() {
+            let (cipher_sk, sign_sk) = nodes.node_keys(i)?;
+            let path = format!("{SK_SHARE_LOCAL_PATH}-{}", account.id());
+            let key_share = match std::fs::read(&path) {
+                Ok(key_share) => Some(hex::encode(key_share)),
+                Err(err) if err.kind() == ErrorKind::NotFound => None,
+                Err(err) => {
+                    return Err(err).with_context(|| format!("could not read key share {path}"))
+                }
+            };
+            snapshot_nodes.push(SnapshotNode {
+                account: take_account(worker, account).await?,
+                url: nodes.url(i).to_string(),
+                cipher_sk: hex::encode(cipher_sk.to_bytes()),
+                sign_sk: sign_sk.to_string(),
+                key_share,
+            });
+        }
+
+        let datastore = dump_datastore(nodes.datastore_url()).await?;
+        tracing::info!(
+            contract_id = %contract.account_id,
+            nodes = snapshot_nodes.len(),
+            entities = datastore.len(),
+            "took snapshot of the environment"
+        );
+        Ok(Self {
+            threshold,
+            contract,
+            nodes: snapshot_nodes,
+            datastore,
+        })
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::read(path)
+            .with_context(|| format!("could not read snapshot {}", path.display()))?;
+        Ok(serde_json::from_slice(&file)?)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, serde_json::to_vec(self)?)
+            .with_context(|| format!("could not write snapshot {}", path.display()))
+    }
+
+    /// Sets up new containers with the state of the snapshot and starts its nodes as local
+    /// processes. The nodes listen on the same ports as when the snapshot was taken, since the
+    /// contract keeps their urls.
+    pub async fn load(self, docker_client: &DockerClient) -> anyhow::Result<Nodes<'_>> {
+        crate::test_seed();
+        let mut ctx = crate::setup(docker_client).await?;
+
+        // The nodes look the contract up by its id, so the contract of the snapshot takes the
+        // place of the one that `setup` deployed.
+        let contract = restore_account(&ctx.worker, &self.contract).await?;
+        ctx.mpc_contract = Contract::from_secret_key(
+            contract.id().clone(),
+            contract.secret_key().clone(),
+            &ctx.worker,
+        );
+        restore_datastore(&ctx.datastore.local_address, &self.datastore).await?;
+
+        let config: Config = ctx.mpc_contract.view("config").await?.json()?;
+        let cfg = MultichainConfig {
+            nodes: self.nodes.len(),
+            threshold: self.threshold,
+            protocol: config.protocol,
+            ..Default::default()
+        };
+
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let account = restore_account(&ctx.worker, &node.account).await?;
+            if let Some(key_share) = &node.key_share {
+                let path = format!("{SK_SHARE_LOCAL_PATH}-{}", account.id());
+                std::fs::write(&path, hex::decode(key_share)?)
+                    .with_context(|| format!("could not write key share {path}"))?;
+            }
+            let web_port = url::Url::parse(&node.url)?
+                .port()
+                .with_context(|| format!("url of node {} has no port", account.id()))?;
+            let cipher_sk =
+                mpc_keys::hpke::SecretKey::try_from_bytes(&hex::decode(&node.cipher_sk)?)
+                    .map_err(|err| anyhow::anyhow!("invalid cipher key: {err}"))?;
+            let near_rpc = local::Node::proxy_rpc(&ctx, account.id(), web_port).await?;
+            let config = NodeConfig {
+                web_port,
+                account,
+                cipher_pk: cipher_sk.public_key(),
+                cipher_sk,
+                sign_sk: node.sign_sk.parse()?,
+                cfg: cfg.clone(),
+                failpoints: None,
+                near_rpc,
+            };
+            nodes.push(local::Node::spawn(&ctx, config).await?);
+        }
+        tracing::info!(contract_id = %ctx.mpc_contract.id(), "loaded snapshot of the environment");
+
+        Ok(Nodes::Local { ctx, nodes })
+    }
+}
+
+/// File of the snapshot to start from, if the test run was asked to start from one.
+pub fn snapshot_path() -> Option<String> {
+    std::env::var(SNAPSHOT_ENV_VAR).ok()
+}
+
+async fn take_account(
+    worker: &Worker<Sandbox>,
+    account: &Account,
+) -> anyhow::Result<SnapshotAccount> {
+    let details = worker.view_account(account.id()).await?;
+    let (code, state) = if details.code_hash == CryptoHash::default() {
+        (None, Vec::new())
+    } else {
+        let code = worker.view_code(account.id()).await?;
+        let state = worker
+            .view_state(account.id())
+            .await?
+            .into_iter()
+            .map(|(key, value)| (hex::encode(key), hex::encode(value)))
+            .collect();
+        (Some(hex::encode(code)), state)
+    };
+    Ok(SnapshotAccount {
+        account_id: account.id().clone(),
+        secret_key: account.secret_key().to_string(),
+        balance: details.balance,
+        storage_usage: details.storage_usage,
+        code,
+        state,
+    })
+}
+
+/// Creates the account of the snapshot in the sandbox, or overwrites it if it already exists.
+async fn restore_account(
+    worker: &Worker<Sandbox>,
+    account: &SnapshotAccount,
+) -> anyhow::Result<Account> {
+    let secret_key: SecretKey = account.secret_key.parse()?;
+    let mut patch = worker
+        .patch(&account.account_id)
+        .account(
+            AccountDetailsPatch::default()
+                .balance(account.balance)
+                .storage_usage(account.storage_usage),
+        )
+        .access_key(secret_key.public_key(), AccessKey::full_access());
+    if let Some(code) = &account.code {
+        patch = patch.code(&hex::decode(code)?);
+    }
+    let state = account
+        .state
+        .iter()
+        .map(|(key, value)| Ok((hex::decode(key)?, hex::decode(value)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    patch
+        .states(
+            state
+                .iter()
+                .map(|(key, value)| (key.as_slice(), value.as_slice())),
+        )
+        .transact()
+        .await?;
+
+    Ok(Account::from_secret_key(
+        account.account_id.clone(),
+        secret_key,
+        worker,
+    ))
+}
+
+/// Fetches all entities of the datastore emulator at `url` with a kindless query.
+async fn dump_datastore(url: &str) -> anyhow::Result<Vec<serde_json::Value>> {
+    let http_client = reqwest::Client::new();
+    let url = format!(
+        "{}/v1/projects/{GCP_PROJECT_ID}:runQuery",
+        url.trim_end_matches('/')
+    );
+    let mut entities = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut query = json!({});
+        if let Some(cursor) = &cursor {
+            query["startCursor"] = json!(cursor);
+        }
+        let response: serde_json::Value = http_client
+            .post(&url)
+            .json(&json!({ "query": query }))
+            .send()
+            .await?
+            .error_for_status()
+            .context("could not query the datastore")?
+            .json()
+            .await?;
+        let batch = &response["batch"];
+        if let Some(results) = batch["entityResults"].as_array() {
+            // Kinds starting with `__` hold the statistics of the datastore itself.
+            entities.extend(
+                results
+                    .iter()
+                    .map(|result| result["entity"].clone())
+                    .filter(|entity| {
+                        !entity["key"]["path"][0]["kind"]
+                            .as_str()
+                            .is_some_and(|kind| kind.starts_with("__"))
+                    }),
+            );
+        }
+        match (batch["moreResults"].as_str(), batch["endCursor"].as_str()) {
+            (Some("NOT_FINISHED" | "MORE_RESULTS_AFTER_LIMIT"), Some(end_cursor)) => {
+                cursor = Some(end_cursor.to_string())
+            }
+            _ => break,
+        }
+    }
+    Ok(entities)
+}
+
+async fn restore_datastore(url: &str, entities: &[serde_json::Value]) -> anyhow::Result<()> {
+    let http_client = reqwest::Client::new();
+    let url = format!(
+        "{}/v1/projects/{GCP_PROJECT_ID}:commit",
+        url.trim_end_matches('/')
+    );
+    for entities in entities.chunks(DATASTORE_COMMIT_LIMIT) {
+        let mutations = entities
+            .iter()
+            .map(|entity| json!({ "upsert": entity }))
+            .collect::<Vec<_>>();
+        http_client
+            .post(&url)
+            .json(&json!({
+                "mode": "NON_TRANSACTIONAL",
+                "mutations": mutations,
+            }))
+            .send()
+            .await?
+            .error_for_status()
+            .context("could not restore the datastore")?;
+    }
+    Ok(())
+}
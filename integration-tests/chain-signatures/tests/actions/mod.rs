@@ -43,7 +43,7 @@ use serde_json::json;
 pub async fn request_sign(
     ctx: &MultichainTestContext<'_>,
 ) -> anyhow::Result<([u8; 32], [u8; 32], Account, AsyncTransactionStatus)> {
-    let worker = &ctx.nodes.ctx().worker;
+    let worker = ctx.nodes.worker();
     let account = worker.dev_create_account().await?;
     let payload: [u8; 32] = rand::thread_rng().gen();
     let payload_hashed = web3::signing::keccak256(&payload);
@@ -58,6 +58,8 @@ pub async fn request_sign(
         payload: payload_hashed,
         path: "test".to_string(),
         key_version: 0,
+        message: None,
+        max_wait_blocks: None,
     };
     let status = ctx
         .rpc_client
@@ -76,7 +78,7 @@ pub async fn request_sign(
 pub async fn request_batch_random_sign(
     ctx: &MultichainTestContext<'_>,
 ) -> anyhow::Result<(Vec<([u8; 32], [u8; 32])>, Account, AsyncTransactionStatus)> {
-    let worker = &ctx.nodes.ctx().worker;
+    let worker = ctx.nodes.worker();
     let account = worker.dev_create_account().await?;
     let signer = InMemorySigner {
         account_id: account.id().clone(),
@@ -94,6 +96,8 @@ pub async fn request_batch_random_sign(
             payload: payload_hashed,
             path: "test".to_string(),
             key_version: 0,
+            message: None,
+            max_wait_blocks: None,
         };
         let function = Function::new("sign")
             .args_json(serde_json::json!({
@@ -112,7 +116,7 @@ pub async fn request_batch_random_sign(
 pub async fn request_batch_duplicate_sign(
     ctx: &MultichainTestContext<'_>,
 ) -> anyhow::Result<([u8; 32], u32, Account, AsyncTransactionStatus)> {
-    let worker = &ctx.nodes.ctx().worker;
+    let worker = ctx.nodes.worker();
     let account = worker.dev_create_account().await?;
     let signer = InMemorySigner {
         account_id: account.id().clone(),
@@ -129,6 +133,8 @@ pub async fn request_batch_duplicate_sign(
             payload: payload_hashed,
             path: "test".to_string(),
             key_version: 0,
+            message: None,
+            max_wait_blocks: None,
         };
         let function = Function::new("sign")
             .args_json(serde_json::json!({
@@ -208,7 +214,7 @@ pub async fn rogue_respond(
     predecessor: &near_workspaces::AccountId,
     path: &str,
 ) -> anyhow::Result<AsyncTransactionStatus> {
-    let worker = &ctx.nodes.ctx().worker;
+    let worker = ctx.nodes.worker();
     let account = worker.dev_create_account().await?;
 
     let signer = InMemorySigner {
@@ -278,6 +284,8 @@ pub async fn request_sign_non_random(
         payload: payload_hashed,
         path: "test".to_string(),
         key_version: 0,
+        message: None,
+        max_wait_blocks: None,
     };
 
     let status = ctx
@@ -10,9 +10,11 @@ use backon::{ConstantBuilder, ExponentialBuilder};
 use cait_sith::FullSignature;
 use crypto_shared::SignatureResponse;
 use k256::Secp256k1;
+use mpc_contract::primitives::NetworkCapacity;
 use mpc_contract::ProtocolContractState;
+use mpc_contract::ResharingContractState;
 use mpc_contract::RunningContractState;
-use mpc_node::web::StateView;
+use mpc_node::web::{Readiness, StateView};
 use near_fetch::ops::AsyncTransactionStatus;
 use near_lake_primitives::CryptoHash;
 use near_primitives::errors::ActionErrorKind;
@@ -60,6 +62,66 @@ pub async fn running_mpc<'a>(
         .with_context(|| err_msg)
 }
 
+pub async fn resharing_mpc<'a>(
+    ctx: &MultichainTestContext<'a>,
+) -> anyhow::Result<ResharingContractState> {
+    let is_resharing = || async {
+        let state: ProtocolContractState = ctx
+            .rpc_client
+            .view(ctx.contract().id(), "state")
+            .await
+            .map_err(|err| anyhow::anyhow!("could not view state {err:?}"))?
+            .json()?;
+
+        match state {
+            ProtocolContractState::Resharing(resharing) => Ok(resharing),
+            _ => anyhow::bail!("not resharing"),
+        }
+    };
+    is_resharing
+        .retry(
+            &ConstantBuilder::default()
+                .with_delay(Duration::from_millis(500))
+                .with_max_times(60),
+        )
+        .await
+        .with_context(|| "mpc did not start resharing in time")
+}
+
+pub async fn nodes_ready<'a>(ctx: &MultichainTestContext<'a>) -> anyhow::Result<Vec<Readiness>> {
+    let is_ready = |id| {
+        move || async move {
+            let readiness: Readiness = ctx
+                .http_client
+                .get(
+                    Url::parse(ctx.nodes.url(id))
+                        .unwrap()
+                        .join("/readyz")
+                        .unwrap(),
+                )
+                .send()
+                .await?
+                .json()
+                .await?;
+
+            if !readiness.ready {
+                anyhow::bail!("node is not ready yet: {readiness:?}");
+            }
+            Ok(readiness)
+        }
+    };
+
+    let mut readiness = Vec::new();
+    for id in 0..ctx.nodes.len() {
+        let ready = is_ready(id)
+            .retry(&ExponentialBuilder::default().with_max_times(6))
+            .await
+            .with_context(|| format!("mpc node '{id}' did not become ready before deadline"))?;
+        readiness.push(ready);
+    }
+    Ok(readiness)
+}
+
 pub async fn has_at_least_triples<'a>(
     ctx: &MultichainTestContext<'a>,
     expected_triple_count: usize,
@@ -189,6 +251,24 @@ pub async fn has_at_least_presignatures<'a>(
             .with_context(|| format!("mpc node '{id}' failed to generate '{expected_presignature_count}' presignatures before deadline"))?;
         state_views.push(state_view);
     }
+
+    // Sign requests get rejected until the contract learns about the presignatures too.
+    let has_capacity = || async {
+        let capacity: NetworkCapacity = ctx
+            .rpc_client
+            .view(ctx.contract().id(), "network_capacity")
+            .await
+            .map_err(|err| anyhow::anyhow!("could not view network capacity {err:?}"))?
+            .json()?;
+        if capacity.overloaded {
+            anyhow::bail!("contract does not know about the presignatures yet: {capacity:?}");
+        }
+        Ok(())
+    };
+    has_capacity
+        .retry(&ExponentialBuilder::default().with_max_times(6))
+        .await
+        .context("contract did not learn about the presignatures of the nodes before deadline")?;
     Ok(state_views)
 }
 
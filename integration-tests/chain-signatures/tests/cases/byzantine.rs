@@ -0,0 +1,80 @@
+//! Tests where a single node misbehaves through the `byzantine_*` failpoints while the honest
+//! majority has to keep producing signatures. The nodes have to be built with the `byzantine`
+//! feature of `mpc-node` for these to have any effect.
+
+use crate::actions::{self, wait_for};
+use crate::with_multichain_nodes;
+
+use integration_tests_chain_signatures::MultichainConfig;
+use test_log::test;
+
+#[test(tokio::test)]
+async fn test_signature_with_corrupted_triple_shares() -> anyhow::Result<()> {
+    let config = MultichainConfig {
+        failpoints: vec!["byzantine_corrupt_triple=10*return".to_string()],
+        ..Default::default()
+    };
+    with_multichain_nodes(config, |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 2).await?;
+            wait_for::has_at_least_presignatures(&ctx, 2).await?;
+            actions::single_signature_production(&ctx, &state_0).await
+        })
+    })
+    .await
+}
+
+#[test(tokio::test)]
+async fn test_signature_with_corrupted_signature_shares() -> anyhow::Result<()> {
+    let config = MultichainConfig {
+        failpoints: vec!["byzantine_corrupt_signature=3*return".to_string()],
+        ..Default::default()
+    };
+    with_multichain_nodes(config, |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 2).await?;
+            wait_for::has_at_least_presignatures(&ctx, 2).await?;
+            actions::single_signature_production(&ctx, &state_0).await?;
+            actions::single_signature_production(&ctx, &state_0).await
+        })
+    })
+    .await
+}
+
+#[test(tokio::test)]
+async fn test_signature_with_stale_epoch_messages() -> anyhow::Result<()> {
+    let config = MultichainConfig {
+        failpoints: vec!["byzantine_stale_epoch=10*return".to_string()],
+        ..Default::default()
+    };
+    with_multichain_nodes(config, |mut ctx| {
+        Box::pin(async move {
+            wait_for::running_mpc(&ctx, Some(0)).await?;
+            // the byzantine node can only send stale messages once there is an epoch before ours.
+            ctx.add_participant(None).await?;
+            let state_1 = wait_for::running_mpc(&ctx, Some(1)).await?;
+            assert_eq!(state_1.participants.len(), 4);
+            wait_for::has_at_least_triples(&ctx, 2).await?;
+            wait_for::has_at_least_presignatures(&ctx, 2).await?;
+            actions::single_signature_production(&ctx, &state_1).await?;
+
+            let byzantine = ctx.nodes.near_accounts()[0].id().to_string();
+            let mut identified = false;
+            for id in 1..ctx.nodes.len() {
+                identified |= ctx.nodes.logs(id)?.lines().any(|line| {
+                    line.contains("dropping messages of a stale epoch") && line.contains(&byzantine)
+                });
+            }
+            assert!(
+                identified,
+                "no honest node identified {byzantine} as sending stale messages"
+            );
+            Ok(())
+        })
+    })
+    .await
+}
@@ -0,0 +1,85 @@
+use crate::actions::{self, wait_for};
+use crate::with_multichain_nodes;
+
+use integration_tests_chain_signatures::chaos::ChaosController;
+use integration_tests_chain_signatures::MultichainConfig;
+use test_log::test;
+
+#[test(tokio::test)]
+async fn test_signature_paused_node_recovers() -> anyhow::Result<()> {
+    with_multichain_nodes(MultichainConfig::default(), |ctx| {
+        Box::pin(async move {
+            let chaos = ChaosController::new(&ctx.nodes).await?;
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 6).await?;
+            wait_for::has_at_least_mine_presignatures(&ctx, 1).await?;
+
+            chaos.pause(2).await?;
+            // The first request might land on a presignature shared with the paused node, so
+            // it is fine for it to time out as long as the follow up one goes through.
+            if actions::single_signature_production(&ctx, &state_0)
+                .await
+                .is_err()
+            {
+                actions::single_signature_production(&ctx, &state_0).await?;
+            }
+
+            chaos.unpause(2).await?;
+            wait_for::has_at_least_mine_presignatures(&ctx, 1).await?;
+            actions::single_payload_signature_production(&ctx, &state_0).await
+        })
+    })
+    .await
+}
+
+#[test(tokio::test)]
+async fn test_signature_partitioned_node_recovers() -> anyhow::Result<()> {
+    with_multichain_nodes(MultichainConfig::default(), |ctx| {
+        Box::pin(async move {
+            let chaos = ChaosController::new(&ctx.nodes).await?;
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 6).await?;
+            wait_for::has_at_least_mine_presignatures(&ctx, 1).await?;
+
+            // Node 0 can still reach the chain but none of the other nodes.
+            chaos.partition(&[0], &[1, 2]).await?;
+            if actions::single_signature_production(&ctx, &state_0)
+                .await
+                .is_err()
+            {
+                actions::single_signature_production(&ctx, &state_0).await?;
+            }
+
+            chaos.heal(&[0]).await?;
+            wait_for::has_at_least_mine_presignatures(&ctx, 1).await?;
+            actions::single_payload_signature_production(&ctx, &state_0).await
+        })
+    })
+    .await
+}
+
+#[test(tokio::test)]
+async fn test_signature_with_node_latency() -> anyhow::Result<()> {
+    with_multichain_nodes(MultichainConfig::default(), |ctx| {
+        Box::pin(async move {
+            let chaos = ChaosController::new(&ctx.nodes).await?;
+            for node in 0..ctx.nodes.len() {
+                chaos.add_latency(node, 200, 50).await?;
+            }
+
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 2).await?;
+            wait_for::has_at_least_presignatures(&ctx, 2).await?;
+            actions::single_signature_production(&ctx, &state_0).await?;
+
+            for node in 0..ctx.nodes.len() {
+                chaos.clear_latency(node).await?;
+            }
+            actions::single_signature_production(&ctx, &state_0).await
+        })
+    })
+    .await
+}
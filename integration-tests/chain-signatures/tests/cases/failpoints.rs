@@ -0,0 +1,66 @@
+//! Regression tests that inject failures into the nodes through their failpoints. The nodes have
+//! to be built with the `failpoints` feature of `mpc-node` for these to have any effect.
+
+use crate::actions::{self, wait_for};
+use crate::with_multichain_nodes;
+
+use integration_tests_chain_signatures::MultichainConfig;
+use test_log::test;
+
+#[test(tokio::test)]
+async fn test_signature_with_dropped_triple_messages() -> anyhow::Result<()> {
+    let config = MultichainConfig {
+        failpoints: vec!["triple_drop_message=5*return".to_string()],
+        ..Default::default()
+    };
+    with_multichain_nodes(config, |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 2).await?;
+            wait_for::has_at_least_presignatures(&ctx, 2).await?;
+            actions::single_signature_production(&ctx, &state_0).await
+        })
+    })
+    .await
+}
+
+#[test(tokio::test)]
+async fn test_signature_with_failed_presignatures() -> anyhow::Result<()> {
+    let config = MultichainConfig {
+        failpoints: vec![
+            String::new(),
+            String::new(),
+            "presignature_generate=3*return".to_string(),
+        ],
+        ..Default::default()
+    };
+    with_multichain_nodes(config, |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 2).await?;
+            wait_for::has_at_least_mine_presignatures(&ctx, 1).await?;
+            actions::single_signature_production(&ctx, &state_0).await
+        })
+    })
+    .await
+}
+
+#[test(tokio::test)]
+async fn test_signature_with_delayed_publish() -> anyhow::Result<()> {
+    let config = MultichainConfig {
+        failpoints: vec!["signature_publish=1*sleep(15000)".to_string(); 3],
+        ..Default::default()
+    };
+    with_multichain_nodes(config, |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 2).await?;
+            wait_for::has_at_least_presignatures(&ctx, 2).await?;
+            actions::single_signature_production(&ctx, &state_0).await
+        })
+    })
+    .await
+}
@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 use crate::actions::{self, add_latency, wait_for};
-use crate::with_multichain_nodes;
+use crate::{with_multichain_nodes, with_snapshot};
 
 use cait_sith::protocol::Participant;
 use cait_sith::triples::{TriplePub, TripleShare};
@@ -15,17 +15,30 @@ use k256::elliptic_curve::point::AffineCoordinates;
 use k256::Secp256k1;
 use mpc_contract::config::Config;
 use mpc_contract::update::ProposeUpdateArgs;
+use mpc_keys::hpke;
+use mpc_node::http_client;
 use mpc_node::kdf::into_eth_sig;
 use mpc_node::protocol::presignature::{Presignature, PresignatureId, PresignatureManager};
 use mpc_node::protocol::triple::{Triple, TripleManager};
-use mpc_node::storage;
+use mpc_node::storage::{self, StorageCipher};
 use mpc_node::types::LatestBlockHeight;
 use mpc_node::util::NearPublicKeyExt;
 use near_account_id::AccountId;
 use test_log::test;
 use url::Url;
 
+#[cfg(feature = "byzantine")]
+pub mod byzantine;
+#[cfg(feature = "docker-test")]
+pub mod chaos;
+#[cfg(feature = "failpoints")]
+pub mod failpoints;
+#[cfg(feature = "docker-test")]
+pub mod multi_region;
 pub mod nightly;
+pub mod recovery;
+pub mod sla;
+pub mod upgrade;
 
 #[test(tokio::test)]
 async fn test_multichain_reshare() -> anyhow::Result<()> {
@@ -83,6 +96,35 @@ async fn test_multichain_reshare() -> anyhow::Result<()> {
     .await
 }
 
+#[test(tokio::test)]
+async fn test_snapshot_after_reshare() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("mpc-snapshot-{}.json", rand::random::<u64>()));
+    let snapshot_path = path.clone();
+    with_multichain_nodes(MultichainConfig::default(), |mut ctx| {
+        Box::pin(async move {
+            wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert!(ctx.add_participant(None).await.is_ok());
+            wait_for::running_mpc(&ctx, Some(1)).await?;
+            ctx.save_snapshot(&snapshot_path).await
+        })
+    })
+    .await?;
+
+    // The nodes pick up from the epoch after the resharing, with the key shares it gave them.
+    let result = with_snapshot(&path, |ctx| {
+        Box::pin(async move {
+            let state = wait_for::running_mpc(&ctx, Some(1)).await?;
+            assert_eq!(state.participants.len(), 4);
+            wait_for::has_at_least_triples(&ctx, 2).await?;
+            wait_for::has_at_least_presignatures(&ctx, 2).await?;
+            actions::single_signature_production(&ctx, &state).await
+        })
+    })
+    .await;
+    std::fs::remove_file(&path)?;
+    result
+}
+
 #[test(tokio::test)]
 async fn test_triples_and_presignatures() -> anyhow::Result<()> {
     with_multichain_nodes(MultichainConfig::default(), |ctx| {
@@ -111,6 +153,67 @@ async fn test_signature_basic() -> anyhow::Result<()> {
     .await
 }
 
+#[test(tokio::test)]
+async fn test_signature_quic_transport() -> anyhow::Result<()> {
+    let config = MultichainConfig {
+        transport: http_client::Transport::Quic,
+        ..Default::default()
+    };
+    with_multichain_nodes(config, |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 2).await?;
+            wait_for::has_at_least_presignatures(&ctx, 2).await?;
+            actions::single_signature_production(&ctx, &state_0).await
+        })
+    })
+    .await
+}
+
+#[test(tokio::test)]
+async fn test_signature_quic_mtls() -> anyhow::Result<()> {
+    let config = MultichainConfig {
+        transport: http_client::Transport::Quic,
+        mesh_mtls: true,
+        ..Default::default()
+    };
+    with_multichain_nodes(config, |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 2).await?;
+            wait_for::has_at_least_presignatures(&ctx, 2).await?;
+            actions::single_signature_production(&ctx, &state_0).await
+        })
+    })
+    .await
+}
+
+#[test(tokio::test)]
+async fn test_health_probes() -> anyhow::Result<()> {
+    with_multichain_nodes(MultichainConfig::default(), |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            for readiness in wait_for::nodes_ready(&ctx).await? {
+                assert!(readiness.has_keyshare);
+                assert_eq!(readiness.threshold, Some(state_0.threshold));
+                assert!(readiness.reachable_peers >= state_0.threshold);
+            }
+            for id in 0..ctx.nodes.len() {
+                let healthz = ctx
+                    .http_client
+                    .get(Url::parse(ctx.nodes.url(id))?.join("/healthz")?)
+                    .send()
+                    .await?;
+                assert_eq!(healthz.status(), reqwest::StatusCode::OK);
+            }
+            Ok(())
+        })
+    })
+    .await
+}
+
 #[test(tokio::test)]
 async fn test_signature_offline_node() -> anyhow::Result<()> {
     with_multichain_nodes(MultichainConfig::default(), |mut ctx| {
@@ -217,8 +320,12 @@ async fn test_triple_persistence() -> anyhow::Result<()> {
     let redis_url = Url::parse(redis.internal_address.as_str())?;
     let redis_cfg = deadpool_redis::Config::from_url(redis_url);
     let redis_pool = redis_cfg.create_pool(Some(Runtime::Tokio1)).unwrap();
-    let triple_storage =
-        storage::triple_storage::init(&redis_pool, &AccountId::from_str("test.near").unwrap());
+    let (cipher_sk, _) = hpke::generate();
+    let triple_storage = storage::triple_storage::init(
+        &redis_pool,
+        &AccountId::from_str("test.near").unwrap(),
+        &StorageCipher::new(&cipher_sk),
+    );
 
     let mut triple_manager = TripleManager::new(
         Participant::from(0),
@@ -226,6 +333,7 @@ async fn test_triple_persistence() -> anyhow::Result<()> {
         123,
         &AccountId::from_str("test.near").unwrap(),
         &triple_storage,
+        None,
     );
 
     let triple_id_1: u64 = 1;
@@ -305,9 +413,11 @@ async fn test_presignature_persistence() -> anyhow::Result<()> {
     let redis_url = Url::parse(redis.internal_address.as_str())?;
     let redis_cfg = deadpool_redis::Config::from_url(redis_url);
     let redis_pool = redis_cfg.create_pool(Some(Runtime::Tokio1)).unwrap();
+    let (cipher_sk, _) = hpke::generate();
     let presignature_storage = storage::presignature_storage::init(
         &redis_pool,
         &AccountId::from_str("test.near").unwrap(),
+        &StorageCipher::new(&cipher_sk),
     );
     let mut presignature_manager = PresignatureManager::new(
         Participant::from(0),
@@ -365,6 +475,94 @@ async fn test_presignature_persistence() -> anyhow::Result<()> {
     assert!(presignature_manager.is_empty().await);
     assert_eq!(presignature_manager.len_potential().await, 0);
 
+    // Expired presignatures of this node are discarded first, the ones of other nodes only
+    // after the grace period.
+    let grace = std::time::Duration::from_secs(60);
+    let mut old_mine = dummy_presignature();
+    old_mine.created_at = 100;
+    presignature_manager.insert_mine(old_mine).await;
+    let mut old_foreign = dummy_presignature();
+    old_foreign.id = 2;
+    old_foreign.created_at = 100;
+    presignature_manager.insert(old_foreign).await;
+    let mut fresh = dummy_presignature();
+    fresh.id = 3;
+    presignature_manager.insert_mine(fresh).await;
+
+    assert_eq!(presignature_manager.expire(150, grace).await, 1);
+    assert!(!presignature_manager.contains(&1).await);
+    assert!(presignature_manager.contains(&2).await);
+    assert_eq!(presignature_manager.len_mine().await, 1);
+
+    assert_eq!(presignature_manager.expire(200, grace).await, 1);
+    assert!(!presignature_manager.contains(&2).await);
+    assert!(presignature_manager.contains_mine(&3).await);
+    assert_eq!(presignature_manager.len_generated().await, 1);
+
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_publish_persistence() -> anyhow::Result<()> {
+    use crypto_shared::{SerializableScalar, SignatureResponse};
+    use mpc_contract::primitives::SignatureRequest;
+    use mpc_node::storage::publish_storage::PendingPublish;
+
+    let docker_client = DockerClient::default();
+    let docker_network = "test-publish-persistence";
+    docker_client.create_network(docker_network).await?;
+    let redis = containers::Redis::run(&docker_client, docker_network).await?;
+    let redis_url = Url::parse(redis.internal_address.as_str())?;
+    let redis_cfg = deadpool_redis::Config::from_url(redis_url);
+    let redis_pool = redis_cfg.create_pool(Some(Runtime::Tokio1)).unwrap();
+    let publish_storage =
+        storage::publish_storage::init(&redis_pool, &AccountId::from_str("test.near").unwrap());
+
+    let pending = |id: u8, generated_at: u64| PendingPublish {
+        request_id: [id; 32],
+        request: SignatureRequest {
+            epsilon: SerializableScalar {
+                scalar: k256::Scalar::ONE,
+            },
+            payload_hash: SerializableScalar {
+                scalar: k256::Scalar::from(id as u64),
+            },
+        },
+        response: SignatureResponse::new(
+            <Secp256k1 as CurveArithmetic>::AffinePoint::GENERATOR,
+            k256::Scalar::ONE,
+            0,
+        ),
+        generated_at,
+        attempts: 0,
+        participants: vec![0, 1],
+    };
+
+    assert_eq!(publish_storage.len_queued().await?, 0);
+    publish_storage.insert(&pending(2, 200)).await?;
+    publish_storage.insert(&pending(1, 100)).await?;
+    assert_eq!(publish_storage.len_queued().await?, 2);
+
+    // Signatures are loaded oldest first, as they would be after a restart.
+    let loaded = publish_storage.load().await?;
+    assert_eq!(loaded[0].request_id, [1; 32]);
+    assert_eq!(loaded[1].request_id, [2; 32]);
+    assert_eq!(loaded[0].response, pending(1, 100).response);
+
+    // A failed attempt overwrites the queued signature instead of adding another one.
+    let mut retried = pending(1, 100);
+    retried.attempts = 3;
+    publish_storage.insert(&retried).await?;
+    assert_eq!(publish_storage.len_queued().await?, 2);
+    assert_eq!(publish_storage.load().await?[0].attempts, 3);
+
+    publish_storage.remove(&[1; 32]).await?;
+    let loaded = publish_storage.load().await?;
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].request_id, [2; 32]);
+
+    publish_storage.clear().await?;
+    assert_eq!(publish_storage.len_queued().await?, 0);
     Ok(())
 }
 
@@ -377,6 +575,7 @@ fn dummy_presignature() -> Presignature {
             sigma: <Secp256k1 as CurveArithmetic>::Scalar::ONE,
         },
         participants: vec![Participant::from(1), Participant::from(2)],
+        created_at: 1_000,
     }
 }
 
@@ -590,3 +789,138 @@ async fn test_batch_duplicate_signature() -> anyhow::Result<()> {
     })
     .await
 }
+
+#[test]
+fn test_bench_report() -> anyhow::Result<()> {
+    use integration_tests_chain_signatures::bench::{self, BenchConfig};
+    use std::time::Duration;
+
+    let report = bench::run(&BenchConfig {
+        participants: vec![3],
+        latencies: vec![Duration::from_millis(100)],
+        samples: 1,
+        label: Some("test".to_string()),
+    })?;
+
+    assert_eq!(report.results.len(), 2);
+    for result in &report.results {
+        assert_eq!(result.threshold, 2);
+        assert!(result.rounds > 0);
+        // every round of messages adds the latency on top of the compute time
+        let latency_ms = (result.rounds * 100) as f64;
+        assert!((result.mean_ms - result.compute_ms - latency_ms).abs() < 1e-3);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_simulation() -> anyhow::Result<()> {
+    use integration_tests_chain_signatures::simulate::{self, SimulateConfig};
+
+    for seed in 0..3 {
+        let report = simulate::run(&SimulateConfig {
+            nodes: 4,
+            threshold: 3,
+            signatures: 2,
+            reshares: 2,
+            seed: Some(seed),
+        })
+        .await?;
+
+        assert_eq!(report.seed, seed);
+        assert_eq!(report.epochs.len(), 3);
+        for (epoch, report) in report.epochs.iter().enumerate() {
+            assert_eq!(report.epoch, epoch as u64);
+            assert_eq!(report.signatures, 2);
+            assert!(report.participants.len() >= report.threshold);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_generate_k8s() -> anyhow::Result<()> {
+    use integration_tests_chain_signatures::k8s::{self, K8sConfig};
+    use near_crypto::{KeyType, SecretKey};
+
+    let config = MultichainConfig {
+        nodes: 3,
+        threshold: 2,
+        ..Default::default()
+    };
+    let accounts = (0..3)
+        .map(|i| {
+            Ok((
+                format!("node-{i}.test.near").parse()?,
+                SecretKey::from_random(KeyType::ED25519),
+            ))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let k8s_config = K8sConfig {
+        namespace: "mpc".to_string(),
+        image: "near/mpc-node:latest".to_string(),
+        near_rpc: "http://sandbox:3030".to_string(),
+        mpc_contract_id: "signer.test.near".parse()?,
+        accounts,
+        env: "k8s".to_string(),
+        gcp_project_id: "multichain-k8s".to_string(),
+        gcp_datastore_url: None,
+        storage_size: "1Gi".to_string(),
+    };
+    let manifests = k8s::render(&config, &k8s_config)?;
+
+    let items = manifests["items"].as_array().unwrap();
+    let count = |kind: &str| items.iter().filter(|item| item["kind"] == kind).count();
+    // one of each for every node, plus the StatefulSet and Service of redis.
+    assert_eq!(count("StatefulSet"), 4);
+    assert_eq!(count("Service"), 4);
+    assert_eq!(count("Secret"), 3);
+    let candidates = items
+        .iter()
+        .find(|item| item["metadata"]["name"] == "mpc-candidates")
+        .unwrap();
+    let init: serde_json::Value =
+        serde_json::from_str(candidates["data"]["init.json"].as_str().unwrap())?;
+    assert_eq!(init["threshold"], 2);
+    assert_eq!(init["candidates"].as_object().unwrap().len(), 3);
+
+    let missing_account = K8sConfig {
+        accounts: Vec::new(),
+        ..k8s_config
+    };
+    assert!(k8s::render(&config, &missing_account).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_mock_contract_rejects_mismatched_votes() -> anyhow::Result<()> {
+    use integration_tests_chain_signatures::simulate::{MockContract, MockState};
+
+    let mut contract = MockContract::new([0, 1, 2].into(), 2)?;
+    let public_key = k256::AffinePoint::GENERATOR;
+    let other_key = (k256::ProjectivePoint::GENERATOR * k256::Scalar::from(2u64)).to_affine();
+    assert!(!contract.vote_pk(0, public_key)?);
+    assert!(contract.vote_pk(1, other_key).is_err());
+    assert!(contract.vote_pk(3, public_key).is_err());
+    assert!(!contract.vote_pk(1, public_key)?);
+    assert!(contract.vote_pk(2, public_key)?);
+    assert!(matches!(
+        contract.state(),
+        MockState::Running { epoch: 0, .. }
+    ));
+
+    // Not enough participants stay for the key to be reshared.
+    assert!(contract.propose_reshare([0, 3, 4].into()).is_err());
+    contract.propose_reshare([0, 1, 3].into())?;
+    assert!(contract.vote_reshared(2, 1).is_err());
+    assert!(contract.vote_reshared(0, 2).is_err());
+    for voter in [0, 1] {
+        assert!(!contract.vote_reshared(voter, 1)?);
+    }
+    assert!(contract.vote_reshared(3, 1)?);
+    assert!(matches!(
+        contract.state(),
+        MockState::Running { epoch: 1, .. }
+    ));
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use crate::actions::{self, wait_for};
+use crate::with_multichain_nodes;
+
+use integration_tests_chain_signatures::{LatencyProfile, MultichainConfig};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use test_log::test;
+
+/// Round trip time between every pair of nodes, roughly what is seen between continents.
+const CROSS_REGION_RTT_MS: u32 = 150;
+
+/// Time a sign request is expected to take from being submitted until the signature is
+/// returned by the contract.
+const SIGNATURE_LATENCY_SLO: Duration = Duration::from_secs(30);
+
+#[test(tokio::test)]
+async fn test_signature_latency_cross_region() -> anyhow::Result<()> {
+    const SIGNATURES: usize = 3;
+
+    let config = MultichainConfig::default();
+    let config = MultichainConfig {
+        latency_profiles: vec![LatencyProfile::from_rtt(CROSS_REGION_RTT_MS); config.nodes],
+        ..config
+    };
+
+    with_multichain_nodes(config, |ctx| {
+        Box::pin(async move {
+            let state_0 = wait_for::running_mpc(&ctx, Some(0)).await?;
+            assert_eq!(state_0.participants.len(), 3);
+            wait_for::has_at_least_triples(&ctx, 6).await?;
+            wait_for::has_at_least_presignatures(&ctx, SIGNATURES).await?;
+
+            for i in 0..SIGNATURES {
+                let start = Instant::now();
+                let (_, _, _, status) = actions::request_sign(&ctx).await?;
+                let outcome = loop {
+                    if let Poll::Ready(outcome) = status.status().await? {
+                        break outcome;
+                    }
+                    if start.elapsed() > SIGNATURE_LATENCY_SLO {
+                        anyhow::bail!(
+                            "signature {i} was not produced within {SIGNATURE_LATENCY_SLO:?}"
+                        );
+                    }
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                };
+                let latency = start.elapsed();
+                tracing::info!(i, ?latency, "cross region signature produced");
+                assert!(outcome.is_success(), "signature {i} failed: {outcome:?}");
+                assert!(
+                    latency <= SIGNATURE_LATENCY_SLO,
+                    "signature {i} took {latency:?}, above the {SIGNATURE_LATENCY_SLO:?} slo"
+                );
+            }
+
+            Ok(())
+        })
+    })
+    .await
+}
@@ -25,6 +25,8 @@ async fn test_nightly_signature_production() -> anyhow::Result<()> {
             },
             ..Default::default()
         },
+        latency_profiles: Vec::new(),
+        failpoints: Vec::new(),
     };
 
     with_multichain_nodes(config, |ctx| {
@@ -0,0 +1,195 @@
+//! Signature latency SLA: submits a sustained stream of sign requests and checks the latency
+//! percentiles of their responses. The load and thresholds are configured through:
+//! - `SLA_RPS`: sign requests submitted per second, defaults to 1.
+//! - `SLA_DURATION_SECS`: for how long requests are submitted, defaults to 60.
+//! - `SLA_P50_MS` and `SLA_P95_MS`: latency thresholds, defaulting to 15 and 30 seconds.
+
+use std::task::Poll;
+use std::time::Duration;
+
+use crypto_shared::SignatureResponse;
+use integration_tests_chain_signatures::MultichainConfig;
+use k256::Secp256k1;
+use mpc_contract::primitives::SignRequest;
+use near_crypto::InMemorySigner;
+use near_workspaces::types::{Gas, NearToken};
+use rand::Rng;
+use test_log::test;
+use tokio::time::Instant;
+
+use crate::actions::{self, wait_for};
+use crate::with_multichain_nodes;
+
+/// How often the status of a sign request is checked. Bounds the precision of the latencies.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// After which time a sign request is considered failed.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+struct Sla {
+    rps: f64,
+    duration: Duration,
+    p50: Duration,
+    p95: Duration,
+}
+
+impl Sla {
+    fn from_env() -> anyhow::Result<Self> {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> anyhow::Result<T> {
+            match std::env::var(name) {
+                Ok(value) => value
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("invalid value for {name}: {value}")),
+                Err(_) => Ok(default),
+            }
+        }
+
+        let sla = Self {
+            rps: var("SLA_RPS", 1.0)?,
+            duration: Duration::from_secs(var("SLA_DURATION_SECS", 60)?),
+            p50: Duration::from_millis(var("SLA_P50_MS", 15_000)?),
+            p95: Duration::from_millis(var("SLA_P95_MS", 30_000)?),
+        };
+        anyhow::ensure!(sla.rps > 0.0, "SLA_RPS has to be positive");
+        Ok(sla)
+    }
+
+    fn requests(&self) -> usize {
+        (self.rps * self.duration.as_secs_f64()).ceil() as usize
+    }
+}
+
+/// Latency at `percentile` (between 0 and 1) of the sorted `latencies`.
+fn percentile(latencies: &[Duration], percentile: f64) -> Duration {
+    let index = ((latencies.len() - 1) as f64 * percentile).round() as usize;
+    latencies[index]
+}
+
+/// Renders the sorted `latencies` as a histogram with one second wide buckets.
+fn histogram(latencies: &[Duration]) -> String {
+    let Some(max) = latencies.last() else {
+        return String::new();
+    };
+    let mut buckets = vec![0; max.as_secs() as usize + 1];
+    for latency in latencies {
+        buckets[latency.as_secs() as usize] += 1;
+    }
+    let mut out = String::new();
+    for (secs, count) in buckets.into_iter().enumerate() {
+        out += &format!(
+            "{:>4}s..{:<4} | {:<40} {count}\n",
+            secs,
+            format!("{}s", secs + 1),
+            "#".repeat(count.min(40))
+        );
+    }
+    out
+}
+
+#[test(tokio::test)]
+#[ignore = "This is triggered by the nightly Github Actions pipeline"]
+async fn test_signature_latency_sla() -> anyhow::Result<()> {
+    let sla = Sla::from_env()?;
+
+    with_multichain_nodes(MultichainConfig::default(), |ctx| {
+        Box::pin(async move {
+            let state = wait_for::running_mpc(&ctx, Some(0)).await?;
+            wait_for::has_at_least_mine_presignatures(&ctx, 2).await?;
+
+            let account = ctx.nodes.worker().dev_create_account().await?;
+            let signer = InMemorySigner {
+                account_id: account.id().clone(),
+                public_key: account.secret_key().public_key().to_string().parse()?,
+                secret_key: account.secret_key().to_string().parse()?,
+            };
+            let mut mpc_pk_bytes = vec![0x04];
+            mpc_pk_bytes.extend_from_slice(&state.public_key.as_bytes()[1..]);
+
+            let period = Duration::from_secs_f64(1.0 / sla.rps);
+            let start = Instant::now();
+            tracing::info!(rps = sla.rps, requests = sla.requests(), "starting SLA run");
+            let requests = (0..sla.requests()).map(|i| {
+                let signer = &signer;
+                let account = &account;
+                let mpc_pk_bytes = &mpc_pk_bytes;
+                let ctx = &ctx;
+                async move {
+                    tokio::time::sleep_until(start + period * i as u32).await;
+                    let payload: [u8; 32] = rand::thread_rng().gen();
+                    let payload_hashed = web3::signing::keccak256(&payload);
+                    let request = SignRequest {
+                        payload: payload_hashed,
+                        path: "test".to_string(),
+                        key_version: 0,
+                        message: None,
+                        max_wait_blocks: None,
+                    };
+
+                    let submitted = Instant::now();
+                    let status = ctx
+                        .rpc_client
+                        .call(signer, ctx.contract().id(), "sign")
+                        .args_json(serde_json::json!({
+                            "request": request,
+                        }))
+                        .gas(Gas::from_tgas(50))
+                        .deposit(NearToken::from_yoctonear(1))
+                        .transact_async()
+                        .await?;
+                    let outcome = tokio::time::timeout(REQUEST_TIMEOUT, async {
+                        loop {
+                            if let Poll::Ready(outcome) = status.status().await? {
+                                return anyhow::Ok(outcome);
+                            }
+                            tokio::time::sleep(POLL_INTERVAL).await;
+                        }
+                    })
+                    .await??;
+                    let latency = submitted.elapsed();
+
+                    anyhow::ensure!(
+                        !outcome.is_failure(),
+                        "sign request failed: {:?}",
+                        outcome.status()
+                    );
+                    let response: SignatureResponse = outcome.json()?;
+                    let signature = cait_sith::FullSignature::<Secp256k1> {
+                        big_r: response.big_r.affine_point,
+                        s: response.s.scalar,
+                    };
+                    actions::assert_signature(account.id(), mpc_pk_bytes, payload_hashed, &signature)
+                        .await;
+                    anyhow::Ok(latency)
+                }
+            });
+            let results = futures::future::join_all(requests).await;
+
+            let mut latencies = Vec::with_capacity(results.len());
+            let mut failures = 0;
+            for result in results {
+                match result {
+                    Ok(latency) => latencies.push(latency),
+                    Err(err) => {
+                        tracing::error!(?err, "sign request did not produce a signature");
+                        failures += 1;
+                    }
+                }
+            }
+            latencies.sort();
+            anyhow::ensure!(!latencies.is_empty(), "no signature was produced");
+
+            let p50 = percentile(&latencies, 0.5);
+            let p95 = percentile(&latencies, 0.95);
+            tracing::info!(?p50, ?p95, failures, "SLA run finished");
+            if failures > 0 || p50 > sla.p50 || p95 > sla.p95 {
+                println!("signature latency histogram:\n{}", histogram(&latencies));
+                anyhow::bail!(
+                    "signature latency SLA violated: p50={p50:?} (max {:?}), p95={p95:?} (max {:?}), {failures} failed requests",
+                    sla.p50,
+                    sla.p95,
+                );
+            }
+            Ok(())
+        })
+    })
+    .await
+}
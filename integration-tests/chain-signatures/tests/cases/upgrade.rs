@@ -0,0 +1,81 @@
+//! Upgrade of the contract while it is serving sign requests. Requests keep getting submitted
+//! before, during and after the contract is replaced in place, and every one of them has to get
+//! a signature of the same root key. To check the migration from the previous release rather
+//! than an upgrade to the same code, deploy its wasm first with `MPC_TEST_CONTRACT`.
+
+use std::time::Duration;
+
+use integration_tests_chain_signatures::MultichainConfig;
+use test_log::test;
+
+use crate::actions::{self, wait_for};
+use crate::with_multichain_nodes;
+
+/// Sign requests submitted over the course of the test.
+const REQUESTS: usize = 12;
+/// Time between two sign requests, on top of the time it takes to submit one.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+/// Time after the first request at which the upgrade gets proposed.
+const UPGRADE_AFTER: Duration = Duration::from_secs(5);
+
+#[test(tokio::test)]
+async fn test_contract_upgrade_with_live_traffic() -> anyhow::Result<()> {
+    with_multichain_nodes(MultichainConfig::default(), |ctx| {
+        Box::pin(async move {
+            let state = wait_for::running_mpc(&ctx, Some(0)).await?;
+            wait_for::has_at_least_mine_presignatures(&ctx, 2).await?;
+
+            let traffic = async {
+                let mut requests = Vec::with_capacity(REQUESTS);
+                for _ in 0..REQUESTS {
+                    requests.push(actions::request_sign(&ctx).await?);
+                    tokio::time::sleep(REQUEST_INTERVAL).await;
+                }
+                anyhow::Ok(requests)
+            };
+            let upgrade = async {
+                tokio::time::sleep(UPGRADE_AFTER).await;
+                let id = ctx.propose_update_contract_default().await;
+                ctx.vote_update(id).await;
+                tracing::info!(?id, "contract upgraded under traffic");
+            };
+            let (requests, ()) = tokio::join!(traffic, upgrade);
+
+            // Requests are not resubmitted, so that a request dropped by the upgrade fails the
+            // test instead of being papered over by a retry.
+            let mut mpc_pk_bytes = vec![0x04];
+            mpc_pk_bytes.extend_from_slice(&state.public_key.as_bytes()[1..]);
+            let mut dropped = 0;
+            for (i, (_, payload_hash, account, status)) in requests?.into_iter().enumerate() {
+                match wait_for::signature_responded(status).await {
+                    Ok(signature) => {
+                        actions::assert_signature(
+                            account.id(),
+                            &mpc_pk_bytes,
+                            payload_hash,
+                            &signature,
+                        )
+                        .await
+                    }
+                    Err(err) => {
+                        tracing::error!(request = i, ?err, "sign request dropped by the upgrade");
+                        dropped += 1;
+                    }
+                }
+            }
+            assert_eq!(
+                dropped, 0,
+                "{dropped} of {REQUESTS} sign requests were dropped"
+            );
+
+            let new_state = wait_for::running_mpc(&ctx, Some(state.epoch)).await?;
+            assert_eq!(
+                state.public_key, new_state.public_key,
+                "root public key must not change across the upgrade"
+            );
+            // The upgraded contract keeps serving new requests.
+            actions::single_payload_signature_production(&ctx, &new_state).await
+        })
+    })
+    .await
+}
@@ -5,7 +5,9 @@ use crate::actions::wait_for;
 use mpc_contract::update::{ProposeUpdateArgs, UpdateId};
 
 use futures::future::BoxFuture;
+use integration_tests_chain_signatures::attach::{self, PersistedEnv};
 use integration_tests_chain_signatures::containers::DockerClient;
+use integration_tests_chain_signatures::snapshot::{self, Snapshot};
 use integration_tests_chain_signatures::utils::{vote_join, vote_leave};
 use integration_tests_chain_signatures::{run, utils, MultichainConfig, Nodes};
 
@@ -14,6 +16,7 @@ use near_workspaces::{Account, AccountId, Contract};
 
 use integration_tests_chain_signatures::local::NodeConfig;
 use std::collections::HashSet;
+use std::path::Path;
 
 const CURRENT_CONTRACT_DEPLOY_DEPOSIT: NearToken = NearToken::from_millinear(9000);
 const CURRENT_CONTRACT_FILE_PATH: &str =
@@ -31,6 +34,14 @@ impl MultichainTestContext<'_> {
         self.nodes.contract()
     }
 
+    /// Saves the state of the environment to `path`, for other tests to start from it through
+    /// `MPC_TEST_SNAPSHOT`.
+    pub async fn save_snapshot(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        Snapshot::take(&self.nodes, self.cfg.threshold)
+            .await?
+            .write(path)
+    }
+
     pub async fn participant_accounts(&self) -> anyhow::Result<Vec<&Account>> {
         let state = wait_for::running_mpc(self, None).await?;
         let participant_ids = state.participants.keys().collect::<HashSet<_>>();
@@ -53,7 +64,7 @@ impl MultichainTestContext<'_> {
                 node.account
             }
             None => {
-                let account = self.nodes.ctx().worker.dev_create_account().await?;
+                let account = self.nodes.worker().dev_create_account().await?;
                 tracing::info!(node_account_id = %account.id(), "adding new participant");
                 account
             }
@@ -184,17 +195,69 @@ impl MultichainTestContext<'_> {
     }
 }
 
-pub async fn with_multichain_nodes<F>(cfg: MultichainConfig, f: F) -> anyhow::Result<()>
+pub async fn with_multichain_nodes<F>(mut cfg: MultichainConfig, f: F) -> anyhow::Result<()>
 where
     F: for<'a> FnOnce(MultichainTestContext<'a>) -> BoxFuture<'a, anyhow::Result<()>>,
 {
     let docker_client = DockerClient::default();
-    let nodes = run(cfg.clone(), &docker_client).await?;
+    let (nodes, sk_local_path) = match (attach::attach_path(), snapshot::snapshot_path()) {
+        (Some(path), _) => {
+            // The environment keeps running after the test, so its shares are left in place.
+            let env = PersistedEnv::read(path)?;
+            tracing::info!(contract_id = %env.contract_id, "attaching to a running environment");
+            cfg.nodes = env.nodes.len();
+            cfg.threshold = env.threshold;
+            (env.attach().await?, None)
+        }
+        (None, Some(path)) => {
+            let nodes = load_snapshot(path, &mut cfg, &docker_client).await?;
+            let sk_local_path = nodes.ctx().storage_options.sk_share_local_path.clone();
+            (nodes, Some(sk_local_path))
+        }
+        (None, None) => {
+            let nodes = run(cfg.clone(), &docker_client).await?;
+            let sk_local_path = nodes.ctx().storage_options.sk_share_local_path.clone();
+            (nodes, Some(sk_local_path))
+        }
+    };
+    run_with_nodes(nodes, cfg, sk_local_path, f).await
+}
 
+/// Runs `f` against the environment saved to `path` by [`MultichainTestContext::save_snapshot`].
+pub async fn with_snapshot<F>(path: impl AsRef<Path>, f: F) -> anyhow::Result<()>
+where
+    F: for<'a> FnOnce(MultichainTestContext<'a>) -> BoxFuture<'a, anyhow::Result<()>>,
+{
+    let docker_client = DockerClient::default();
+    let mut cfg = MultichainConfig::default();
+    let nodes = load_snapshot(path, &mut cfg, &docker_client).await?;
     let sk_local_path = nodes.ctx().storage_options.sk_share_local_path.clone();
+    run_with_nodes(nodes, cfg, Some(sk_local_path), f).await
+}
 
+async fn load_snapshot<'a>(
+    path: impl AsRef<Path>,
+    cfg: &mut MultichainConfig,
+    docker_client: &'a DockerClient,
+) -> anyhow::Result<Nodes<'a>> {
+    let snapshot = Snapshot::read(path)?;
+    tracing::info!(contract_id = %snapshot.contract.account_id, "starting from a snapshot");
+    cfg.nodes = snapshot.nodes.len();
+    cfg.threshold = snapshot.threshold;
+    snapshot.load(docker_client).await
+}
+
+async fn run_with_nodes<F>(
+    nodes: Nodes<'_>,
+    cfg: MultichainConfig,
+    sk_local_path: Option<Option<String>>,
+    f: F,
+) -> anyhow::Result<()>
+where
+    F: for<'a> FnOnce(MultichainTestContext<'a>) -> BoxFuture<'a, anyhow::Result<()>>,
+{
     let connector = near_jsonrpc_client::JsonRpcClient::new_client();
-    let jsonrpc_client = connector.connect(&nodes.ctx().lake_indexer.rpc_host_address);
+    let jsonrpc_client = connector.connect(nodes.near_rpc());
     let rpc_client = near_fetch::Client::from_client(jsonrpc_client);
     let result = f(MultichainTestContext {
         nodes,
@@ -203,7 +266,9 @@ where
         cfg,
     })
     .await;
-    utils::clear_local_sk_shares(sk_local_path).await?;
+    if let Some(sk_local_path) = sk_local_path {
+        utils::clear_local_sk_shares(sk_local_path).await?;
+    }
 
     result
 }
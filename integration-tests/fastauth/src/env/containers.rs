@@ -8,18 +8,24 @@ use ed25519_dalek::{PublicKey as PublicKeyEd25519, Verifier};
 use futures::{lock::Mutex, StreamExt};
 use hyper::StatusCode;
 use mpc_recovery::firewall::allowed::DelegateActionRelayer;
+use mpc_recovery::gcp::Storage;
+use mpc_recovery::leader_node::{challenge, rate_limit};
 use mpc_recovery::logging;
+use mpc_recovery::relayer::RelayerMode;
 use mpc_recovery::sign_node::oidc::OidcToken;
 use mpc_recovery::{
     msg::{
-        AcceptNodePublicKeysRequest, ClaimOidcRequest, ClaimOidcResponse, MpcPkRequest,
-        MpcPkResponse, NewAccountRequest, NewAccountResponse, SignRequest, SignResponse,
+        AcceptNodePublicKeysRequest, AddIdentityRequest, ClaimOidcRequest, ClaimOidcResponse,
+        DeleteIdentityRequest, DeleteIdentityResponse, IdentitiesResponse, Identity,
+        ListIdentitiesRequest, MpcPkRequest, MpcPkResponse, NewAccountRequest, NewAccountResponse,
+        RemoveIdentityRequest, RemoveIdentityResponse, SignRequest, SignResponse,
         UserCredentialsRequest, UserCredentialsResponse,
     },
     relayer::NearRpcAndRelayerClient,
     transaction::{CreateAccountOptions, LimitedAccessKey},
     utils::{
-        claim_oidc_request_digest, claim_oidc_response_digest, sign_digest, sign_request_digest,
+        claim_oidc_request_digest, claim_oidc_response_digest, delegate_action_request_digest,
+        delete_identity_request_digest, sign_digest, sign_request_digest,
         user_credentials_request_digest,
     },
 };
@@ -38,9 +44,13 @@ use testcontainers::{
     Container, GenericImage, Image, RunnableImage,
 };
 use tokio::io::AsyncWriteExt;
+use tokio::task::JoinHandle;
 use tracing;
 
 use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::env::{Context, LeaderNodeApi, SignerNodeApi};
 use crate::util::{
@@ -49,12 +59,85 @@ use crate::util::{
 
 static NETWORK_MUTEX: Lazy<Mutex<i32>> = Lazy::new(|| Mutex::new(0));
 
+/// Engine that the containers of the tests are run with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ContainerBackend {
+    #[default]
+    Docker,
+    /// Podman, rootless or not. Its API socket has to be running, e.g. through
+    /// `systemctl --user start podman.socket` for rootless Podman.
+    Podman,
+}
+
+impl ContainerBackend {
+    /// The backend picked through the `MPC_TEST_BACKEND` environment variable, Docker if unset.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("MPC_TEST_BACKEND") {
+            Ok(backend) => <Self as clap::ValueEnum>::from_str(&backend, true)
+                .map_err(|err| anyhow!("invalid MPC_TEST_BACKEND: {err}")),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Socket of the API of the engine. A unix socket in `DOCKER_HOST` takes precedence, since
+    /// that is where both engines point their clients to when the socket is not the default one.
+    fn socket(&self) -> String {
+        if let Ok(host) = std::env::var("DOCKER_HOST") {
+            if host.starts_with("unix://") {
+                return host;
+            }
+        }
+        match self {
+            ContainerBackend::Docker => "unix:///var/run/docker.sock".to_string(),
+            ContainerBackend::Podman => {
+                // Rootless Podman serves its API from the runtime directory of the user.
+                if let Ok(runtime_dir) = std::env::var("XDG_RUNTIME_DIR") {
+                    let socket = format!("{runtime_dir}/podman/podman.sock");
+                    if std::path::Path::new(&socket).exists() {
+                        return format!("unix://{socket}");
+                    }
+                }
+                "unix:///run/podman/podman.sock".to_string()
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ContainerBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            ContainerBackend::Docker => "docker",
+            ContainerBackend::Podman => "podman",
+        };
+        write!(f, "{}", str)
+    }
+}
+
 pub struct DockerClient {
     pub docker: Docker,
     pub cli: Cli,
+    pub backend: ContainerBackend,
 }
 
 impl DockerClient {
+    pub fn new(backend: ContainerBackend) -> anyhow::Result<Self> {
+        let socket = backend.socket();
+        tracing::info!(%backend, socket, "connecting to container engine");
+        Ok(Self {
+            docker: Docker::connect_with_local(
+                &socket,
+                // 10 minutes timeout for all requests in case a lot of tests are being ran in parallel.
+                600,
+                bollard::API_DEFAULT_VERSION,
+            )?,
+            cli: match backend {
+                ContainerBackend::Docker => Cli::docker(),
+                ContainerBackend::Podman => Cli::podman(),
+            },
+            backend,
+        })
+    }
+
     pub async fn get_network_ip_address<I: Image>(
         &self,
         container: &Container<'_, I>,
@@ -148,20 +231,34 @@ impl DockerClient {
 
         Ok(())
     }
+
+    /// Logs of the container `id`, or only its last `tail` lines.
+    pub async fn logs(&self, id: &str, tail: Option<usize>) -> anyhow::Result<String> {
+        container_logs(&self.docker, id, tail).await
+    }
+}
+
+async fn container_logs(docker: &Docker, id: &str, tail: Option<usize>) -> anyhow::Result<String> {
+    let mut output = docker.logs::<String>(
+        id,
+        Some(LogsOptions {
+            stdout: true,
+            stderr: true,
+            tail: tail.map_or_else(|| "all".to_string(), |tail| tail.to_string()),
+            ..Default::default()
+        }),
+    );
+    let mut logs = String::new();
+    while let Some(output) = output.next().await {
+        logs.push_str(&output?.to_string());
+    }
+    Ok(logs)
 }
 
 impl Default for DockerClient {
+    /// Client of the engine picked through `MPC_TEST_BACKEND`.
     fn default() -> Self {
-        Self {
-            docker: Docker::connect_with_local(
-                "unix:///var/run/docker.sock",
-                // 10 minutes timeout for all requests in case a lot of tests are being ran in parallel.
-                600,
-                bollard::API_DEFAULT_VERSION,
-            )
-            .unwrap(),
-            cli: Default::default(),
-        }
+        Self::new(ContainerBackend::from_env().unwrap()).unwrap()
     }
 }
 
@@ -177,7 +274,7 @@ impl<'a> Redis<'a> {
 
     pub async fn run(docker_client: &'a DockerClient, network: &str) -> anyhow::Result<Redis<'a>> {
         tracing::info!("Running Redis container...");
-        let image = GenericImage::new("redis", "latest")
+        let image = GenericImage::new("docker.io/library/redis", "latest")
             .with_exposed_port(Self::CONTAINER_PORT)
             .with_wait_for(WaitFor::message_on_stdout("Ready to accept connections"));
         let image: RunnableImage<GenericImage> = image.into();
@@ -264,11 +361,22 @@ impl<'a> Sandbox<'a> {
     }
 }
 
+const RELAYER_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const RELAYER_HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+/// Failed health checks in a row after which a relayer that is still running is restarted.
+const RELAYER_MAX_HEALTH_CHECK_FAILURES: usize = 5;
+/// Time a restarted relayer gets to start listening before it is checked again.
+const RELAYER_RESTART_GRACE_PERIOD: Duration = Duration::from_secs(10);
+/// Lines of the logs of a crashed relayer that get surfaced into the test output.
+const RELAYER_CRASH_LOG_LINES: usize = 100;
+
 pub struct Relayer<'a> {
     pub id: String,
     pub container: Container<'a, GenericImage>,
     pub address: String,
     pub local_address: String,
+    restarts: Arc<AtomicUsize>,
+    supervisor: JoinHandle<()>,
 }
 
 pub struct RelayerConfig {
@@ -291,6 +399,9 @@ pub struct RelayerConfig {
 impl<'a> Relayer<'a> {
     pub const CONTAINER_PORT: u16 = 3000;
     pub const TMP_FOLDER_PATH: &'static str = "./tmp";
+    /// Polled to tell whether the relayer is up. Any response that is not a server error
+    /// counts as healthy, since only whether the server answers at all matters here.
+    pub const HEALTH_PATH: &'static str = "/health";
 
     pub async fn run(
         docker_client: &'a DockerClient,
@@ -369,25 +480,51 @@ impl<'a> Relayer<'a> {
         )
         .with_env_var("RUST_LOG", "DEBUG");
 
+        // The host port is pinned, so that it stays the same when the relayer gets restarted.
+        let host_port = std::net::TcpListener::bind(("127.0.0.1", 0))?
+            .local_addr()?
+            .port();
         let image: RunnableImage<GenericImage> = image.into();
-        let image = image.with_network(network);
+        let image = image
+            .with_network(network)
+            .with_mapped_port((host_port, Self::CONTAINER_PORT));
         let container = docker_client.cli.run(image);
         let ip_address = docker_client
             .get_network_ip_address(&container, network)
             .await?;
-        let host_port = container.get_host_port_ipv4(Self::CONTAINER_PORT);
 
         let full_address = format!("http://{}:{}", ip_address, Self::CONTAINER_PORT);
         tracing::info!("Relayer container is running at {}", full_address);
 
+        let local_address = format!("http://127.0.0.1:{host_port}");
+        let restarts = Arc::new(AtomicUsize::new(0));
+        let supervisor = tokio::spawn(supervise_relayer(
+            docker_client.docker.clone(),
+            relayer_id.to_string(),
+            container.id().to_string(),
+            format!("{local_address}{}", Self::HEALTH_PATH).parse()?,
+            restarts.clone(),
+        ));
+
         Ok(Relayer {
             container,
             address: full_address,
-            local_address: format!("http://127.0.0.1:{host_port}"),
+            local_address,
             id: relayer_id.to_string(),
+            restarts,
+            supervisor,
         })
     }
 
+    /// How many times the relayer got restarted after crashing.
+    pub fn restarts(&self) -> usize {
+        self.restarts.load(Ordering::SeqCst)
+    }
+
+    pub async fn logs(&self, docker_client: &DockerClient) -> anyhow::Result<String> {
+        docker_client.logs(self.container.id(), None).await
+    }
+
     pub fn clean_tmp_files(&self) -> anyhow::Result<(), anyhow::Error> {
         std::fs::remove_dir_all(format!("{}/{}", Self::TMP_FOLDER_PATH, self.id))
             .unwrap_or_else(|_| panic!("Failed to clean tmp files for relayer {}", self.id));
@@ -395,6 +532,76 @@ impl<'a> Relayer<'a> {
     }
 }
 
+/// Polls the health of the relayer until its container is gone, and restarts it when it
+/// crashed or stopped answering. The logs leading up to the crash are surfaced as a warning.
+async fn supervise_relayer(
+    docker: Docker,
+    id: String,
+    container_id: String,
+    health_url: hyper::Uri,
+    restarts: Arc<AtomicUsize>,
+) {
+    let client = hyper::Client::new();
+    let mut failures = 0;
+    loop {
+        tokio::time::sleep(RELAYER_HEALTH_CHECK_INTERVAL).await;
+        let running = match docker.inspect_container(&container_id, None).await {
+            Ok(inspect) => inspect
+                .state
+                .and_then(|state| state.running)
+                .unwrap_or(false),
+            // The container got removed along with the relayer.
+            Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => return,
+            Err(err) => {
+                tracing::debug!(relayer = %id, %err, "failed to inspect the relayer container");
+                continue;
+            }
+        };
+        let healthy = running
+            && matches!(
+                tokio::time::timeout(
+                    RELAYER_HEALTH_CHECK_TIMEOUT,
+                    client.get(health_url.clone())
+                )
+                .await,
+                Ok(Ok(response)) if !response.status().is_server_error()
+            );
+        if healthy {
+            failures = 0;
+            continue;
+        }
+        failures += 1;
+        if running && failures < RELAYER_MAX_HEALTH_CHECK_FAILURES {
+            continue;
+        }
+
+        let logs = container_logs(&docker, &container_id, Some(RELAYER_CRASH_LOG_LINES))
+            .await
+            .unwrap_or_else(|err| format!("failed to fetch the logs: {err}"));
+        tracing::warn!(
+            relayer = %id,
+            running,
+            failures,
+            "relayer is down, restarting it. Its last logs were:\n{logs}"
+        );
+        if let Err(err) = docker.restart_container(&container_id, None).await {
+            tracing::error!(relayer = %id, %err, "failed to restart the relayer");
+            continue;
+        }
+        restarts.fetch_add(1, Ordering::SeqCst);
+        failures = 0;
+        tokio::time::sleep(RELAYER_RESTART_GRACE_PERIOD).await;
+    }
+}
+
+impl Drop for Relayer<'_> {
+    fn drop(&mut self) {
+        self.supervisor.abort();
+    }
+}
+
 pub struct OidcProvider<'a> {
     pub container: Container<'a, GenericImage>,
     pub jwt_pk_url: String,
@@ -530,8 +737,12 @@ impl SignerNode<'_> {
             cipher_key: Some(hex::encode(cipher_key)),
             gcp_project_id: ctx.gcp_project_id.clone(),
             gcp_datastore_url: Some(ctx.datastore.address.clone()),
+            storage: Storage::Gcp,
             jwt_signature_pk_url: ctx.oidc_provider.jwt_pk_url.clone(),
+            oidc_providers: None,
             logging_options: logging::Options::default(),
+            session_options: Default::default(),
+            oidc_cache_options: Default::default(),
         }
         .into_str_args();
 
@@ -667,8 +878,13 @@ impl<'a> LeaderNode<'a> {
             fast_auth_partners_filepath: None,
             gcp_project_id: ctx.gcp_project_id.clone(),
             gcp_datastore_url: Some(ctx.datastore.address.to_string()),
+            storage: Storage::Gcp,
             jwt_signature_pk_url: ctx.oidc_provider.jwt_pk_url.to_string(),
             logging_options: logging::Options::default(),
+            rate_limit_options: rate_limit::Options::default(),
+            challenge_options: challenge::Options::default(),
+            oidc_cache_options: Default::default(),
+            relayer: RelayerMode::Partner,
         }
         .into_str_args();
 
@@ -746,6 +962,34 @@ impl LeaderNodeApi {
         util::post(format!("{}/new_account", self.address), request).await
     }
 
+    pub async fn delete_identity(
+        &self,
+        request: DeleteIdentityRequest,
+    ) -> anyhow::Result<(StatusCode, DeleteIdentityResponse)> {
+        util::post(format!("{}/delete_identity", self.address), request).await
+    }
+
+    pub async fn add_identity(
+        &self,
+        request: AddIdentityRequest,
+    ) -> anyhow::Result<(StatusCode, IdentitiesResponse)> {
+        util::post(format!("{}/add_identity", self.address), request).await
+    }
+
+    pub async fn list_identities(
+        &self,
+        request: ListIdentitiesRequest,
+    ) -> anyhow::Result<(StatusCode, IdentitiesResponse)> {
+        util::post(format!("{}/list_identities", self.address), request).await
+    }
+
+    pub async fn remove_identity(
+        &self,
+        request: RemoveIdentityRequest,
+    ) -> anyhow::Result<(StatusCode, RemoveIdentityResponse)> {
+        util::post(format!("{}/remove_identity", self.address), request).await
+    }
+
     pub async fn new_account_with_helper(
         &self,
         account_id: &AccountId,
@@ -779,6 +1023,7 @@ impl LeaderNodeApi {
             oidc_token: oidc_token.clone(),
             user_credentials_frp_signature: frp_signature,
             frp_public_key: user_pk,
+            challenge: None,
         };
 
         self.new_account(new_account_request).await
@@ -897,6 +1142,105 @@ impl LeaderNodeApi {
         }
     }
 
+    pub async fn delete_identity_with_helper(
+        &self,
+        account_id: &AccountId,
+        oidc_token: &OidcToken,
+        frp_sk: &SecretKey,
+        frp_pk: &PublicKey,
+    ) -> anyhow::Result<(StatusCode, DeleteIdentityResponse)> {
+        let near_account_id = account_id.as_str().parse().unwrap();
+        let delete_identity_request_digest =
+            delete_identity_request_digest(&near_account_id, oidc_token, frp_pk)?;
+        let frp_signature = sign_digest(&delete_identity_request_digest, frp_sk)?;
+
+        let user_credentials_request_digest = user_credentials_request_digest(oidc_token, frp_pk)?;
+        let user_credentials_frp_signature = sign_digest(&user_credentials_request_digest, frp_sk)?;
+
+        self.delete_identity(DeleteIdentityRequest {
+            near_account_id,
+            oidc_token: oidc_token.clone(),
+            frp_signature,
+            user_credentials_frp_signature,
+            frp_public_key: frp_pk.clone(),
+        })
+        .await
+    }
+
+    pub async fn add_identity_with_helper(
+        &self,
+        account_id: &AccountId,
+        oidc_token: &OidcToken,
+        frp_sk: &SecretKey,
+        frp_pk: &PublicKey,
+    ) -> anyhow::Result<(StatusCode, IdentitiesResponse)> {
+        let user_credentials_request_digest = user_credentials_request_digest(oidc_token, frp_pk)?;
+        let user_credentials_frp_signature = sign_digest(&user_credentials_request_digest, frp_sk)?;
+
+        self.add_identity(AddIdentityRequest {
+            near_account_id: account_id.as_str().parse().unwrap(),
+            oidc_token: oidc_token.clone(),
+            user_credentials_frp_signature,
+            frp_public_key: frp_pk.clone(),
+        })
+        .await
+    }
+
+    pub async fn list_identities_with_helper(
+        &self,
+        account_id: &AccountId,
+        oidc_token: &OidcToken,
+        frp_sk: &SecretKey,
+        frp_pk: &PublicKey,
+    ) -> anyhow::Result<(StatusCode, IdentitiesResponse)> {
+        let user_credentials_request_digest = user_credentials_request_digest(oidc_token, frp_pk)?;
+        let user_credentials_frp_signature = sign_digest(&user_credentials_request_digest, frp_sk)?;
+
+        self.list_identities(ListIdentitiesRequest {
+            near_account_id: account_id.as_str().parse().unwrap(),
+            oidc_token: oidc_token.clone(),
+            user_credentials_frp_signature,
+            frp_public_key: frp_pk.clone(),
+        })
+        .await
+    }
+
+    pub async fn remove_identity_with_helper(
+        &self,
+        account_id: &AccountId,
+        identity: &Identity,
+        oidc_token: &OidcToken,
+        frp_sk: &SecretKey,
+        frp_pk: &PublicKey,
+    ) -> anyhow::Result<(StatusCode, RemoveIdentityResponse)> {
+        let near_account_id = account_id.as_str().parse().unwrap();
+        let actions = vec![Action::DeleteKey(DeleteKeyAction {
+            public_key: identity.recovery_public_key.clone(),
+        })
+        .try_into()?];
+        let delegate_action_request_digest = delegate_action_request_digest(
+            &near_account_id,
+            &near_account_id,
+            &actions,
+            oidc_token,
+            frp_pk,
+        )?;
+        let frp_signature = sign_digest(&delegate_action_request_digest, frp_sk)?;
+
+        let user_credentials_request_digest = user_credentials_request_digest(oidc_token, frp_pk)?;
+        let user_credentials_frp_signature = sign_digest(&user_credentials_request_digest, frp_sk)?;
+
+        self.remove_identity(RemoveIdentityRequest {
+            near_account_id,
+            oidc_token: oidc_token.clone(),
+            frp_signature,
+            user_credentials_frp_signature,
+            frp_public_key: frp_pk.clone(),
+            identity: identity.internal_account_id.clone(),
+        })
+        .await
+    }
+
     pub async fn sign_with_helper(
         &self,
         delegate_action: &DelegateAction,
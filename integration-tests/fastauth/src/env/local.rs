@@ -4,8 +4,10 @@ use crate::util;
 use aes_gcm::aead::consts::U32;
 use aes_gcm::aead::generic_array::GenericArray;
 use mpc_recovery::firewall::allowed::DelegateActionRelayer;
+use mpc_recovery::gcp::Storage;
+use mpc_recovery::leader_node::{challenge, rate_limit};
 use mpc_recovery::logging;
-use mpc_recovery::relayer::NearRpcAndRelayerClient;
+use mpc_recovery::relayer::{NearRpcAndRelayerClient, RelayerMode};
 use multi_party_eddsa::protocols::ExpandedKeyPair;
 
 pub struct SignerNode {
@@ -37,8 +39,12 @@ impl SignerNode {
             cipher_key: Some(hex::encode(cipher_key)),
             gcp_project_id: ctx.gcp_project_id.clone(),
             gcp_datastore_url: Some(ctx.datastore.local_address.clone()),
+            storage: Storage::Gcp,
             jwt_signature_pk_url: ctx.oidc_provider.jwt_pk_local_url.clone(),
+            oidc_providers: None,
             logging_options: logging::Options::default(),
+            session_options: Default::default(),
+            oidc_cache_options: Default::default(),
         };
 
         let sign_node_id = format!("sign-{node_id}");
@@ -118,8 +124,13 @@ impl LeaderNode {
             ),
             gcp_project_id: ctx.gcp_project_id.clone(),
             gcp_datastore_url: Some(ctx.datastore.local_address.clone()),
+            storage: Storage::Gcp,
             jwt_signature_pk_url: ctx.oidc_provider.jwt_pk_local_url.clone(),
             logging_options: logging::Options::default(),
+            rate_limit_options: rate_limit::Options::default(),
+            challenge_options: challenge::Options::default(),
+            oidc_cache_options: Default::default(),
+            relayer: RelayerMode::Partner,
         };
 
         let process = mpc::spawn(ctx.release, "leader", cli).await?;
@@ -1,10 +1,25 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use integration_tests_fastauth::env;
-use integration_tests_fastauth::env::containers::DockerClient;
+use integration_tests_fastauth::env::containers::{ContainerBackend, DockerClient};
 use tokio::io::{stdin, AsyncReadExt};
 use tracing_subscriber::EnvFilter;
 
 #[derive(Parser, Debug)]
+struct Args {
+    /// Engine to run the containers with
+    #[arg(
+        long,
+        global = true,
+        env("MPC_TEST_BACKEND"),
+        value_enum,
+        default_value_t
+    )]
+    backend: ContainerBackend,
+    #[command(subcommand)]
+    command: Cli,
+}
+
+#[derive(Subcommand, Debug)]
 enum Cli {
     SetupEnv { nodes: usize },
 }
@@ -15,10 +30,11 @@ async fn main() -> anyhow::Result<()> {
         .with_thread_ids(true)
         .with_env_filter(EnvFilter::from_default_env());
     subscriber.init();
-    match Cli::parse() {
+    let args = Args::parse();
+    match args.command {
         Cli::SetupEnv { nodes } => {
             println!("Setting up an environment with {} nodes...", nodes);
-            let docker_client = DockerClient::default();
+            let docker_client = DockerClient::new(args.backend)?;
             let nodes = env::run(nodes, &docker_client).await?;
             let ctx = nodes.ctx();
 
@@ -77,7 +77,7 @@ pub async fn fetch_recovery_pk(
         .assert_ok()?
     {
         UserCredentialsResponse::Ok { recovery_pk } => recovery_pk,
-        UserCredentialsResponse::Err { msg } => anyhow::bail!("error response: {}", msg),
+        UserCredentialsResponse::Err { msg, .. } => anyhow::bail!("error response: {}", msg),
     };
     Ok(recovery_pk)
 }
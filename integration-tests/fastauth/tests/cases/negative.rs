@@ -1,4 +1,4 @@
-use crate::cases::{fetch_recovery_pk, register_account};
+use crate::cases::{fetch_recovery_pk, new_random_account, register_account};
 use crate::{account, check, key, with_nodes, MpcCheck};
 use anyhow::Context;
 use ed25519_dalek::{PublicKey as PublicKeyEd25519, Signature, Verifier};
@@ -7,6 +7,7 @@ use integration_tests_fastauth::util;
 use mpc_recovery::sign_node::oidc::OidcToken;
 use mpc_recovery::utils::user_credentials_request_digest;
 use mpc_recovery::{
+    error::ErrorCode,
     msg::{ClaimOidcRequest, MpcPkRequest, NewAccountResponse, UserCredentialsResponse},
     utils::{claim_oidc_request_digest, claim_oidc_response_digest, sign_digest},
 };
@@ -76,7 +77,7 @@ async fn whitlisted_actions_test() -> anyhow::Result<()> {
                     &user_public_key,
                 )
                 .await?
-                .assert_bad_request_contains("action can not be performed")?;
+                .assert_bad_request_code(ErrorCode::AccountDeletionUnsupported)?;
         }
 
         // Client should not be able to delete their recovery key
@@ -91,7 +92,7 @@ async fn whitlisted_actions_test() -> anyhow::Result<()> {
             .assert_ok()?
         {
             UserCredentialsResponse::Ok { recovery_pk } => recovery_pk,
-            UserCredentialsResponse::Err { msg } => {
+            UserCredentialsResponse::Err { msg, .. } => {
                 return Err(anyhow::anyhow!("error response: {}", msg))
             }
         };
@@ -106,7 +107,7 @@ async fn whitlisted_actions_test() -> anyhow::Result<()> {
                 &user_public_key,
             )
             .await?
-            .assert_bad_request_contains("recovery key can not be deleted")?;
+            .assert_bad_request_code(ErrorCode::RecoveryKeyDeletion)?;
 
         tokio::time::sleep(Duration::from_millis(2000)).await;
         check::access_key_exists(&ctx, &account_id, &recovery_pk).await?;
@@ -410,7 +411,7 @@ async fn test_invalid_token() -> anyhow::Result<()> {
             .assert_ok()?
         {
             UserCredentialsResponse::Ok { recovery_pk } => recovery_pk,
-            UserCredentialsResponse::Err { msg } => anyhow::bail!("error response: {}", msg),
+            UserCredentialsResponse::Err { msg, .. } => anyhow::bail!("error response: {}", msg),
         };
 
         let new_user_public_key = key::random_pk();
@@ -660,3 +661,50 @@ async fn test_creation_of_two_account_with_the_same_oidc_should_not_be_possible(
     })
     .await
 }
+
+#[test(tokio::test)]
+async fn test_delete_identity_rejections() -> anyhow::Result<()> {
+    with_nodes(3, |ctx| async move {
+        let (account_id, user_secret_key, oidc_token) = new_random_account(&ctx, None).await?;
+        let user_public_key = user_secret_key.public_key();
+        let recovery_pk = fetch_recovery_pk(&ctx, &user_secret_key, &oidc_token).await?;
+
+        // The request has to be signed with the key that claimed the token.
+        ctx.leader_node
+            .delete_identity_with_helper(
+                &account_id,
+                &oidc_token,
+                &key::random_sk(),
+                &user_public_key,
+            )
+            .await?
+            .assert_bad_request_code(ErrorCode::InvalidSignature)?;
+
+        // Deleting the recovery key would leave the account without a full access key.
+        ctx.leader_node
+            .delete_key_with_helper(
+                &account_id,
+                &oidc_token,
+                &user_public_key,
+                &recovery_pk,
+                &user_secret_key,
+                &user_public_key,
+            )
+            .await?
+            .assert_ok()?;
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+        ctx.leader_node
+            .delete_identity_with_helper(
+                &account_id,
+                &oidc_token,
+                &user_secret_key,
+                &user_public_key,
+            )
+            .await?
+            .assert_bad_request_code(ErrorCode::LastFullAccessKey)?;
+        check::access_key_exists(&ctx, &account_id, &recovery_pk).await?;
+
+        Ok(())
+    })
+    .await
+}
@@ -1,10 +1,11 @@
 use crate::cases::{add_pk_and_check_validity, fetch_recovery_pk, new_random_account};
-use crate::{account, key, with_nodes, MpcCheck, TestContext};
+use crate::{account, check, key, with_nodes, MpcCheck, TestContext};
 use futures::stream::FuturesUnordered;
 use hyper::StatusCode;
 use mpc_recovery::{
     gcp::value::{FromValue, IntoValue},
-    sign_node::user_credentials::EncryptedUserCredentials,
+    msg::{DeleteIdentityResponse, IdentitiesResponse, RemoveIdentityResponse},
+    sign_node::{oidc::OidcToken, user_credentials::EncryptedUserCredentials},
     transaction::LimitedAccessKey,
 };
 use near_workspaces::types::AccessKeyPermission;
@@ -50,6 +51,158 @@ async fn test_basic_front_running_protection() -> anyhow::Result<()> {
     .await
 }
 
+#[test(tokio::test)]
+async fn test_delete_identity() -> anyhow::Result<()> {
+    with_nodes(3, |ctx| async move {
+        let (account_id, user_secret_key, oidc_token) = new_random_account(&ctx, None).await?;
+        let user_public_key = user_secret_key.public_key();
+        let recovery_pk = fetch_recovery_pk(&ctx, &user_secret_key, &oidc_token).await?;
+
+        let response = ctx
+            .leader_node
+            .delete_identity_with_helper(&account_id, &oidc_token, &user_secret_key, &user_public_key)
+            .await?
+            .assert_ok()?;
+        assert!(matches!(
+            response,
+            DeleteIdentityResponse::Ok { deleted_key: Some(deleted_key), .. } if deleted_key == recovery_pk
+        ));
+        tokio::time::sleep(std::time::Duration::from_millis(2000)).await;
+        check::access_key_does_not_exists(&ctx, &account_id, &recovery_pk.to_string()).await?;
+        check::access_key_exists(&ctx, &account_id, &user_public_key).await?;
+
+        // The user credentials are gone, so the identity gets a new recovery key that can not
+        // recover the account.
+        let new_recovery_pk = fetch_recovery_pk(&ctx, &user_secret_key, &oidc_token).await?;
+        assert_ne!(new_recovery_pk, recovery_pk);
+        for recovery_pk in [&recovery_pk, &new_recovery_pk] {
+            assert!(ctx
+                .leader_node
+                .add_key_with_helper(
+                    &account_id,
+                    &oidc_token,
+                    &key::random_pk(),
+                    recovery_pk,
+                    &user_secret_key,
+                    &user_public_key,
+                )
+                .await
+                .is_err());
+        }
+
+        // Deleting it again only deletes the new user credentials.
+        let response = ctx
+            .leader_node
+            .delete_identity_with_helper(&account_id, &oidc_token, &user_secret_key, &user_public_key)
+            .await?
+            .assert_ok()?;
+        assert!(matches!(
+            response,
+            DeleteIdentityResponse::Ok {
+                deleted_key: None,
+                ..
+            }
+        ));
+
+        Ok(())
+    })
+    .await
+}
+
+#[test(tokio::test)]
+async fn test_remove_identity() -> anyhow::Result<()> {
+    with_nodes(3, |ctx| async move {
+        let (account_id, user_secret_key, oidc_token) = new_random_account(&ctx, None).await?;
+        let user_public_key = user_secret_key.public_key();
+
+        // Link a second identity to the account.
+        let other_secret_key = key::random_sk();
+        let other_public_key = other_secret_key.public_key();
+        let other_oidc_token = OidcToken::random_valid();
+        ctx.leader_node
+            .claim_oidc_with_helper(&other_oidc_token, &other_public_key, &other_secret_key)
+            .await?;
+        let other_recovery_pk =
+            fetch_recovery_pk(&ctx, &other_secret_key, &other_oidc_token).await?;
+        let recovery_pk = fetch_recovery_pk(&ctx, &user_secret_key, &oidc_token).await?;
+        add_pk_and_check_validity(
+            &ctx,
+            &account_id,
+            &user_secret_key,
+            &oidc_token,
+            &recovery_pk,
+            Some(other_recovery_pk.clone()),
+        )
+        .await?;
+        let IdentitiesResponse::Ok { identities } = ctx
+            .leader_node
+            .add_identity_with_helper(
+                &account_id,
+                &other_oidc_token,
+                &other_secret_key,
+                &other_public_key,
+            )
+            .await?
+            .assert_ok()?
+        else {
+            anyhow::bail!("unexpected response");
+        };
+        assert_eq!(identities.len(), 2);
+        let other_identity = identities
+            .into_iter()
+            .find(|identity| identity.recovery_public_key == other_recovery_pk)
+            .unwrap();
+
+        let response = ctx
+            .leader_node
+            .remove_identity_with_helper(
+                &account_id,
+                &other_identity,
+                &oidc_token,
+                &user_secret_key,
+                &user_public_key,
+            )
+            .await?
+            .assert_ok()?;
+        assert!(matches!(
+            response,
+            RemoveIdentityResponse::Ok { removed } if removed == other_identity
+        ));
+        tokio::time::sleep(std::time::Duration::from_millis(2000)).await;
+        check::access_key_does_not_exists(&ctx, &account_id, &other_recovery_pk.to_string())
+            .await?;
+
+        // Listing with the removed identity neither works nor brings it back.
+        ctx.leader_node
+            .list_identities_with_helper(
+                &account_id,
+                &other_oidc_token,
+                &other_secret_key,
+                &other_public_key,
+            )
+            .await?
+            .assert_unauthorized()?;
+        let IdentitiesResponse::Ok { identities } = ctx
+            .leader_node
+            .list_identities_with_helper(
+                &account_id,
+                &oidc_token,
+                &user_secret_key,
+                &user_public_key,
+            )
+            .await?
+            .assert_ok()?
+        else {
+            anyhow::bail!("unexpected response");
+        };
+        assert_eq!(identities.len(), 1);
+        assert_eq!(identities[0].recovery_public_key, recovery_pk);
+
+        Ok(())
+    })
+    .await
+}
+
 #[test(tokio::test)]
 async fn test_basic_action() -> anyhow::Result<()> {
     with_nodes(3, |ctx| async move { basic_action(&ctx).await }).await
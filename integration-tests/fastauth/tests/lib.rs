@@ -5,9 +5,11 @@ use hyper::StatusCode;
 use integration_tests_fastauth::env;
 use integration_tests_fastauth::env::containers::DockerClient;
 use mpc_recovery::{
+    error::ErrorCode,
     gcp::GcpService,
     msg::{
-        ClaimOidcResponse, MpcPkResponse, NewAccountResponse, SignResponse, UserCredentialsResponse,
+        ClaimOidcResponse, DeleteIdentityResponse, IdentitiesResponse, MpcPkResponse,
+        NewAccountResponse, RemoveIdentityResponse, SignResponse, UserCredentialsResponse,
     },
 };
 use near_workspaces::{network::Sandbox, Worker};
@@ -41,7 +43,7 @@ where
     let docker_client = DockerClient::default();
     let nodes = env::run(nodes, &docker_client).await?;
 
-    f(TestContext {
+    let result = f(TestContext {
         env: nodes.ctx().env.clone(),
         pk_set: nodes.pk_set(),
         leader_node: nodes.leader_api(),
@@ -50,7 +52,19 @@ where
         gcp_project_id: nodes.ctx().gcp_project_id.clone(),
         gcp_datastore_url: nodes.datastore_addr(),
     })
-    .await?;
+    .await;
+    if result.is_err() {
+        // The relayer is the most common reason for a failure, so its logs are always shown.
+        let relayer = &nodes.ctx().relayer_ctx.relayer;
+        match relayer.logs(&docker_client).await {
+            Ok(logs) => tracing::error!(
+                restarts = relayer.restarts(),
+                "test failed, logs of the relayer:\n{logs}"
+            ),
+            Err(err) => tracing::error!(%err, "test failed, but the relayer logs are unavailable"),
+        }
+    }
+    result?;
 
     nodes.ctx().relayer_ctx.relayer.clean_tmp_files()?;
 
@@ -163,6 +177,7 @@ trait MpcCheck {
 
     fn assert_ok(self) -> anyhow::Result<Self::Response>;
     fn assert_bad_request_contains(self, expected: &str) -> anyhow::Result<Self::Response>;
+    fn assert_bad_request_code(self, expected: ErrorCode) -> anyhow::Result<Self::Response>;
     fn assert_unauthorized_contains(self, expected: &str) -> anyhow::Result<Self::Response>;
     fn assert_internal_error_contains(self, expected: &str) -> anyhow::Result<Self::Response>;
     fn assert_dependency_error_contains(self, expected: &str) -> anyhow::Result<Self::Response>;
@@ -189,7 +204,7 @@ trait MpcCheck {
     }
 }
 
-// Presumes that $response::Err has a `msg: String` field.
+// Presumes that $response::Err has `code: ErrorCode` and `msg: String` fields.
 #[macro_export]
 macro_rules! impl_mpc_check {
     ( $response:ident ) => {
@@ -234,6 +249,27 @@ macro_rules! impl_mpc_check {
                 }
             }
 
+            fn assert_bad_request_code(
+                self,
+                expected: ErrorCode,
+            ) -> anyhow::Result<Self::Response> {
+                let status_code = self.0;
+                let response = self.1;
+
+                if status_code == StatusCode::BAD_REQUEST {
+                    let $response::Err { code, .. } = response else {
+                        anyhow::bail!("unexpected Ok with a 400 http code");
+                    };
+                    assert_eq!(code, expected, "unexpected error code in {response:?}");
+
+                    Ok(response)
+                } else {
+                    anyhow::bail!(
+                        "expected 400, but got {status_code} with response: {response:?}"
+                    );
+                }
+            }
+
             fn assert_unauthorized_contains(
                 self,
                 expected: &str,
@@ -304,3 +340,6 @@ impl_mpc_check!(NewAccountResponse);
 impl_mpc_check!(MpcPkResponse);
 impl_mpc_check!(ClaimOidcResponse);
 impl_mpc_check!(UserCredentialsResponse);
+impl_mpc_check!(DeleteIdentityResponse);
+impl_mpc_check!(IdentitiesResponse);
+impl_mpc_check!(RemoveIdentityResponse);
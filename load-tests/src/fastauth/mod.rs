@@ -235,6 +235,7 @@ pub async fn new_account(user: &mut GooseUser) -> TransactionResult {
         oidc_token: session.jwt_token.clone(),
         user_credentials_frp_signature,
         frp_public_key: fa_public_key,
+        challenge: None,
     };
 
     let body_json = serde_json::to_string(&new_account_request).expect("json serialization failed");
@@ -4,7 +4,11 @@ use axum::response::Response;
 use curv::elliptic::curves::{Ed25519, Point};
 use curv::BigInt;
 use near_crypto::PublicKey;
+use near_primitives::errors::{ActionErrorKind, TxExecutionError};
+use near_primitives::types::AccountId;
+use serde::{Deserialize, Serialize};
 
+use crate::primitives::InternalAccountId;
 use crate::relayer::error::RelayerError;
 use crate::sign_node::oidc::OidcDigest;
 
@@ -21,7 +25,59 @@ pub enum MpcError {
     LeaderNodeRejection(#[from] LeaderNodeError),
 }
 
+/// Stable code of a failed request to the leader node, sent along with the error message so that
+/// clients can branch on the failure without parsing the message. Codes are never renamed, new
+/// failure modes get new codes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request body is not valid JSON or has malformed fields, such as a malformed key.
+    MalformedRequest,
+    MalformedDelegateAction,
+    InvalidOidcToken,
+    InvalidSignature,
+    IdentityNotLinked,
+    IdentityNotFound,
+    CannotRemoveOwnIdentity,
+    /// The recovery key to delete is the last full access key of the account.
+    LastFullAccessKey,
+    RecoveryKeyDeletion,
+    AccountDeletionUnsupported,
+    /// The recovery key of the user could not be retrieved from the sign nodes, usually because
+    /// of a wrong signature of the user credentials.
+    RecoveryKeyUnavailable,
+    AccountExists,
+    AccessKeyExists,
+    RateLimited,
+    ChallengeFailed,
+    RelayerFailure,
+    /// The sign nodes rejected the request.
+    SignNodeRejection,
+    /// The sign nodes could not be reached or did not answer in time.
+    SignNodeUnavailable,
+    SigningFailed,
+    InvalidIdempotencyKey,
+    IdempotencyKeyReused,
+    RequestInProgress,
+    /// The asynchronous request polled with `/status/{id}` is unknown or expired.
+    RequestNotFound,
+    /// The asynchronous request was never finished, it has to be sent again.
+    RequestAbandoned,
+    Internal,
+    /// The keys to delete are not the limited access keys of the account, which may have
+    /// changed since they were listed.
+    AccessKeysChanged,
+}
+
 impl MpcError {
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            Self::JsonExtractorRejection(_) => ErrorCode::MalformedRequest,
+            Self::LeaderNodeRejection(error) => error.error_code(),
+            Self::SignNodeRejection(_) => ErrorCode::SignNodeRejection,
+        }
+    }
+
     pub fn status(&self) -> StatusCode {
         match self {
             Self::JsonExtractorRejection(json_rejection) => json_rejection.status(),
@@ -47,7 +103,12 @@ impl MpcError {
 // We implement `IntoResponse` so MpcError can be used as a response
 impl axum::response::IntoResponse for MpcError {
     fn into_response(self) -> Response {
-        (self.status(), axum::Json(self.safe_error_message())).into_response()
+        let body = serde_json::json!({
+            "type": "err",
+            "code": self.error_code(),
+            "msg": self.safe_error_message(),
+        });
+        (self.status(), axum::Json(body)).into_response()
     }
 }
 
@@ -77,6 +138,22 @@ pub enum LeaderNodeError {
     FailedToRetrieveRecoveryPk(anyhow::Error),
     #[error("timeout gathering sign node pks")]
     TimeoutGatheringPublicKeys,
+    #[error("identity {0} can not recover account {1}")]
+    IdentityNotLinked(InternalAccountId, AccountId),
+    #[error("identity {0} is not registered for this account")]
+    IdentityNotFound(InternalAccountId),
+    #[error("the identity used to authorize the request can not be removed")]
+    CannotRemoveOwnIdentity,
+    #[error("recovery key {0} is the last full access key of the account")]
+    LastFullAccessKey(PublicKey),
+    #[error("key {0} is already an access key of the account")]
+    AccessKeyAlreadyExists(PublicKey),
+    #[error("the limited access keys of the account are not the keys to delete")]
+    AccessKeysChanged,
+    #[error("too many requests from {0}, try again later")]
+    RateLimited(InternalAccountId),
+    #[error("account creation challenge failed: {0}")]
+    ChallengeFailed(String),
     #[error("network error: {0}")]
     NetworkRejection(#[from] reqwest::Error),
     #[error(transparent)]
@@ -95,6 +172,14 @@ impl LeaderNodeError {
             LeaderNodeError::MalformedDelegateAction(_) => StatusCode::BAD_REQUEST,
             LeaderNodeError::RelayerError(_) => StatusCode::FAILED_DEPENDENCY,
             LeaderNodeError::TimeoutGatheringPublicKeys => StatusCode::INTERNAL_SERVER_ERROR,
+            LeaderNodeError::IdentityNotLinked(_, _) => StatusCode::UNAUTHORIZED,
+            LeaderNodeError::IdentityNotFound(_) => StatusCode::NOT_FOUND,
+            LeaderNodeError::CannotRemoveOwnIdentity => StatusCode::BAD_REQUEST,
+            LeaderNodeError::LastFullAccessKey(_) => StatusCode::BAD_REQUEST,
+            LeaderNodeError::AccessKeyAlreadyExists(_) => StatusCode::BAD_REQUEST,
+            LeaderNodeError::AccessKeysChanged => StatusCode::CONFLICT,
+            LeaderNodeError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            LeaderNodeError::ChallengeFailed(_) => StatusCode::FORBIDDEN,
             LeaderNodeError::RecoveryKeyCanNotBeDeleted(_) => StatusCode::BAD_REQUEST,
             LeaderNodeError::AccountDeletionUnsupported => StatusCode::BAD_REQUEST,
             LeaderNodeError::FailedToRetrieveRecoveryPk(_) => StatusCode::UNAUTHORIZED,
@@ -104,6 +189,48 @@ impl LeaderNodeError {
             LeaderNodeError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            LeaderNodeError::ClientError(_, _) => ErrorCode::SignNodeRejection,
+            LeaderNodeError::ServerError(_) => ErrorCode::SignNodeUnavailable,
+            LeaderNodeError::DataConversionFailure(_) => ErrorCode::MalformedRequest,
+            LeaderNodeError::AggregateSigningFailed(_) => ErrorCode::SigningFailed,
+            LeaderNodeError::SignatureVerificationFailed(_) => ErrorCode::InvalidSignature,
+            LeaderNodeError::OidcVerificationFailed(_) => ErrorCode::InvalidOidcToken,
+            LeaderNodeError::MalformedDelegateAction(_) => ErrorCode::MalformedDelegateAction,
+            LeaderNodeError::RelayerError(err) if is_account_exists(err) => {
+                ErrorCode::AccountExists
+            }
+            LeaderNodeError::RelayerError(_) => ErrorCode::RelayerFailure,
+            LeaderNodeError::TimeoutGatheringPublicKeys => ErrorCode::SignNodeUnavailable,
+            LeaderNodeError::IdentityNotLinked(_, _) => ErrorCode::IdentityNotLinked,
+            LeaderNodeError::IdentityNotFound(_) => ErrorCode::IdentityNotFound,
+            LeaderNodeError::CannotRemoveOwnIdentity => ErrorCode::CannotRemoveOwnIdentity,
+            LeaderNodeError::LastFullAccessKey(_) => ErrorCode::LastFullAccessKey,
+            LeaderNodeError::AccessKeyAlreadyExists(_) => ErrorCode::AccessKeyExists,
+            LeaderNodeError::AccessKeysChanged => ErrorCode::AccessKeysChanged,
+            LeaderNodeError::RateLimited(_) => ErrorCode::RateLimited,
+            LeaderNodeError::ChallengeFailed(_) => ErrorCode::ChallengeFailed,
+            LeaderNodeError::RecoveryKeyCanNotBeDeleted(_) => ErrorCode::RecoveryKeyDeletion,
+            LeaderNodeError::AccountDeletionUnsupported => ErrorCode::AccountDeletionUnsupported,
+            LeaderNodeError::FailedToRetrieveRecoveryPk(_) => ErrorCode::RecoveryKeyUnavailable,
+            LeaderNodeError::NetworkRejection(_) => ErrorCode::SignNodeUnavailable,
+            LeaderNodeError::Other(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+/// Whether the relayer failed to create an account because it already exists. The relayer only
+/// forwards the message of the failed transaction, so that one has to be matched on.
+fn is_account_exists(err: &RelayerError) -> bool {
+    match err {
+        RelayerError::TxExecutionFailure(TxExecutionError::ActionError(err)) => {
+            matches!(err.kind, ActionErrorKind::AccountAlreadyExists { .. })
+        }
+        RelayerError::RequestFailure(_, msg) => msg.contains("AccountAlreadyExists"),
+        _ => false,
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -120,6 +247,10 @@ pub enum SignNodeError {
     OidcTokenNotClaimed(OidcDigest),
     #[error("aggregate signing failed: {0}")]
     AggregateSigningFailed(#[from] AggregateSigningError),
+    #[error("invalid account recovery: {0}")]
+    InvalidAccountRecovery(anyhow::Error),
+    #[error("invalid delegate action: {0}")]
+    InvalidDelegateAction(anyhow::Error),
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -133,6 +264,8 @@ impl SignNodeError {
             Self::OidcTokenClaimedWithAnotherKey(_) => StatusCode::UNAUTHORIZED,
             Self::OidcTokenNotClaimed(_) => StatusCode::UNAUTHORIZED,
             Self::AggregateSigningFailed(err) => err.code(),
+            Self::InvalidAccountRecovery(_) => StatusCode::BAD_REQUEST,
+            Self::InvalidDelegateAction(_) => StatusCode::BAD_REQUEST,
             Self::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -156,6 +289,8 @@ pub enum AggregateSigningError {
     SignatureVerificationFailed(anyhow::Error),
     #[error("{0}")]
     DataConversionFailure(anyhow::Error),
+    #[error("failed to access signing sessions: {0}")]
+    SessionStoreFailure(anyhow::Error),
 }
 
 impl AggregateSigningError {
@@ -169,6 +304,7 @@ impl AggregateSigningError {
             Self::NodeKeysUnavailable => StatusCode::BAD_REQUEST,
             Self::SignatureVerificationFailed(_) => StatusCode::BAD_REQUEST,
             Self::DataConversionFailure(_) => StatusCode::BAD_REQUEST,
+            Self::SessionStoreFailure(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
@@ -1,11 +1,32 @@
 use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 
+use jsonwebtoken::Algorithm;
+use near_crypto::SecretKey;
+use near_primitives::types::AccountId;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize, Hash, PartialEq, Eq)]
 pub struct OidcProvider {
     pub issuer: String,
     pub audience: String,
+    /// Where the issuer publishes its keys as a JWK set. Tokens of providers without one are
+    /// verified with the Firebase keys, or with the Apple keys for Apple.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwks_url: Option<String>,
+    /// Algorithms the issuer signs its tokens with, only `RS256` if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub algorithms: Vec<Algorithm>,
+}
+
+impl OidcProvider {
+    pub fn allowed_algorithms(&self) -> &[Algorithm] {
+        if self.algorithms.is_empty() {
+            &[Algorithm::RS256]
+        } else {
+            &self.algorithms
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Hash, PartialEq, Eq)]
@@ -14,10 +35,41 @@ pub struct DelegateActionRelayer {
     pub api_key: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, Hash, PartialEq, Eq)]
+/// Account that creates the accounts of the users of a partner, so that every partner pays for
+/// the accounts of its own users.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccountCreator {
+    pub account_id: AccountId,
+    /// Secret keys of the account, loaded from the `mpc-recovery-account-creator-sk-<env>-<account
+    /// id>` secret when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secret_keys: Vec<SecretKey>,
+}
+
+impl std::fmt::Debug for AccountCreator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccountCreator")
+            .field("account_id", &self.account_id)
+            .field("secret_keys", &self.secret_keys.len())
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct FastAuthPartner {
     pub oidc_provider: OidcProvider,
     pub relayer: DelegateActionRelayer,
+    /// Creates the accounts of the users of the partner instead of the account creator of the
+    /// leader node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_creator: Option<AccountCreator>,
+}
+
+// Partners are told apart by their provider, which also keeps the secret keys out of the hash.
+impl Hash for FastAuthPartner {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.oidc_provider.hash(state);
+    }
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -27,10 +79,13 @@ pub struct OidcProviderList {
 
 impl OidcProviderList {
     pub fn contains(&self, issuer: &str, audience: &str) -> bool {
-        self.entries.contains(&OidcProvider {
-            issuer: issuer.into(),
-            audience: audience.into(),
-        })
+        self.find(issuer, audience).is_some()
+    }
+
+    pub fn find(&self, issuer: &str, audience: &str) -> Option<&OidcProvider> {
+        self.entries
+            .iter()
+            .find(|entry| entry.issuer == issuer && entry.audience == audience)
     }
 
     pub fn insert(&mut self, entry: OidcProvider) {
@@ -15,13 +15,88 @@ use google_secretmanager1::oauth2::{
 use google_secretmanager1::SecretManager;
 use hyper::client::HttpConnector;
 use hyper_rustls::HttpsConnector;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+
+/// Where the nodes keep their datastore entities.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Storage {
+    /// GCP Datastore, or its emulator when a datastore URL is given.
+    #[default]
+    Gcp,
+    /// Memory of the node itself, so it can be run without GCP or Docker. Nothing is persisted
+    /// and secrets have to be passed as arguments, so this is only meant for development.
+    Local,
+}
+
+impl Display for Storage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Storage::Gcp => "gcp",
+            Storage::Local => "local",
+        };
+        write!(f, "{}", str)
+    }
+}
 
 #[derive(Clone)]
 pub struct GcpService {
     env: String,
     project_id: String,
-    datastore: Datastore<HttpsConnector<HttpConnector>>,
-    secret_manager: SecretManager<HttpsConnector<HttpConnector>>,
+    datastore: DatastoreClient,
+    secret_manager: Option<SecretManager<HttpsConnector<HttpConnector>>>,
+}
+
+#[derive(Clone)]
+enum DatastoreClient {
+    Gcp(Datastore<HttpsConnector<HttpConnector>>),
+    Local(Arc<Mutex<LocalEntities>>),
+}
+
+/// Entities of the local storage, keyed by their kind and name.
+#[derive(Default)]
+struct LocalEntities(HashMap<(String, String), Entity>);
+
+impl LocalEntities {
+    fn key(entity: &Entity) -> anyhow::Result<(String, String)> {
+        let path_element = entity
+            .key
+            .as_ref()
+            .and_then(|k| k.path.as_ref())
+            .and_then(|p| p.first())
+            .ok_or_else(|| anyhow::anyhow!("entity is missing a key"))?;
+        match (&path_element.kind, &path_element.name) {
+            (Some(kind), Some(name)) => Ok((kind.clone(), name.clone())),
+            _ => anyhow::bail!("entity key is missing a kind or name"),
+        }
+    }
+
+    /// Applies the mutation the same way as Datastore does, i.e. inserts fail for existing
+    /// entities and updates for missing ones.
+    fn apply(&mut self, mutation: Mutation) -> anyhow::Result<()> {
+        if let Some(entity) = mutation.insert {
+            let key = Self::key(&entity)?;
+            if self.0.contains_key(&key) {
+                anyhow::bail!("entity {key:?} already exists");
+            }
+            self.0.insert(key, entity);
+        } else if let Some(entity) = mutation.update {
+            let key = Self::key(&entity)?;
+            if !self.0.contains_key(&key) {
+                anyhow::bail!("entity {key:?} does not exist");
+            }
+            self.0.insert(key, entity);
+        } else if let Some(entity) = mutation.upsert {
+            self.0.insert(Self::key(&entity)?, entity);
+        } else if let Some(key) = mutation.delete {
+            self.0.remove(&Self::key(&Entity {
+                key: Some(key),
+                ..Default::default()
+            })?);
+        }
+        Ok(())
+    }
 }
 
 pub trait KeyKind {
@@ -68,15 +143,42 @@ impl GcpService {
         Ok(Self {
             env,
             project_id,
-            datastore,
-            secret_manager,
+            datastore: DatastoreClient::Gcp(datastore),
+            secret_manager: Some(secret_manager),
         })
     }
 
+    /// Keeps the entities in memory instead of GCP, see [`Storage::Local`].
+    pub fn local(env: String) -> Self {
+        Self {
+            env,
+            project_id: String::new(),
+            datastore: DatastoreClient::Local(Arc::default()),
+            secret_manager: None,
+        }
+    }
+
+    pub async fn init(
+        storage: Storage,
+        env: String,
+        project_id: String,
+        gcp_datastore_url: Option<String>,
+    ) -> anyhow::Result<Self> {
+        match storage {
+            Storage::Gcp => Self::new(env, project_id, gcp_datastore_url).await,
+            Storage::Local => Ok(Self::local(env)),
+        }
+    }
+
     #[tracing::instrument(level = "debug", skip_all, fields(name = name.as_ref()))]
     pub async fn load_secret<T: AsRef<str>>(&self, name: T) -> anyhow::Result<Vec<u8>> {
-        let (_, response) = self
-            .secret_manager
+        let Some(secret_manager) = &self.secret_manager else {
+            anyhow::bail!(
+                "secret {} can not be loaded with local storage, pass it as an argument instead",
+                name.as_ref()
+            );
+        };
+        let (_, response) = secret_manager
             .projects()
             .secrets_versions_access(&format!(
                 "projects/{}/secrets/{}",
@@ -101,6 +203,23 @@ impl GcpService {
         &self,
         name_key: K,
     ) -> anyhow::Result<Option<T>> {
+        let datastore = match &self.datastore {
+            DatastoreClient::Gcp(datastore) => datastore,
+            DatastoreClient::Local(entities) => {
+                let key = (format!("{}-{}", T::kind(), self.env), name_key.to_string());
+                let entity = entities
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .0
+                    .get(&key)
+                    .cloned();
+                return match entity {
+                    Some(entity) => Ok(Some(T::from_value(entity.into_value())?)),
+                    None => Ok(None),
+                };
+            }
+        };
+
         let request = LookupRequest {
             keys: Some(vec![Key {
                 path: Some(vec![PathElement {
@@ -116,8 +235,7 @@ impl GcpService {
             database_id: Some("".to_string()),
         };
         tracing::debug!(?request);
-        let (_, response) = self
-            .datastore
+        let (_, response) = datastore
             .projects()
             .lookup(request, &self.project_id)
             .doit()
@@ -135,85 +253,69 @@ impl GcpService {
 
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn insert<T: IntoValue + KeyKind>(&self, value: T) -> anyhow::Result<()> {
-        let mut entity = Entity::from_value(value.into_value())?;
-        let path_element = entity
-            .key
-            .as_mut()
-            .and_then(|k| k.path.as_mut())
-            .and_then(|p| p.first_mut());
-        if let Some(path_element) = path_element {
-            // We can't create multiple datastore databases in GCP, so we have to suffix
-            // type kinds with env (`dev`, `prod`).
-            path_element.kind = Some(format!("{}-{}", T::kind(), self.env))
-        }
-
-        let request = CommitRequest {
-            database_id: Some("".to_string()),
-            mode: Some(String::from("NON_TRANSACTIONAL")),
-            mutations: Some(vec![Mutation {
-                insert: Some(entity),
-                delete: None,
-                update: None,
-                base_version: None,
-                upsert: None,
-                update_time: None,
-            }]),
-            single_use_transaction: None,
-            transaction: None,
-        };
-        tracing::debug!(?request);
-        let (_, response) = self
-            .datastore
-            .projects()
-            .commit(request, &self.project_id)
-            .doit()
-            .await?;
-        tracing::debug!(?response, "received response");
-
-        Ok(())
+        let entity = self.entity(value)?;
+        self.commit(Mutation {
+            insert: Some(entity),
+            delete: None,
+            update: None,
+            base_version: None,
+            upsert: None,
+            update_time: None,
+        })
+        .await
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn update<T: IntoValue + KeyKind>(&self, value: T) -> anyhow::Result<()> {
-        let mut entity = Entity::from_value(value.into_value())?;
-        let path_element = entity
-            .key
-            .as_mut()
-            .and_then(|k| k.path.as_mut())
-            .and_then(|p| p.first_mut());
-        if let Some(path_element) = path_element {
-            // We can't create multiple datastore databases in GCP, so we have to suffix
-            // type kinds with env (`dev`, `prod`).
-            path_element.kind = Some(format!("{}-{}", T::kind(), self.env))
-        }
+        let entity = self.entity(value)?;
+        self.commit(Mutation {
+            insert: None,
+            delete: None,
+            update: Some(entity),
+            base_version: None,
+            upsert: None,
+            update_time: None,
+        })
+        .await
+    }
 
-        let request = CommitRequest {
-            database_id: Some("".to_string()),
-            mode: Some(String::from("NON_TRANSACTIONAL")),
-            mutations: Some(vec![Mutation {
-                insert: None,
-                delete: None,
-                update: Some(entity),
-                base_version: None,
-                upsert: None,
-                update_time: None,
-            }]),
-            single_use_transaction: None,
-            transaction: None,
-        };
-        tracing::debug!(?request);
-        let (_, response) = self
-            .datastore
-            .projects()
-            .commit(request, &self.project_id)
-            .doit()
-            .await?;
-        tracing::debug!(?response, "received response");
+    pub async fn upsert<T: IntoValue + KeyKind>(&self, value: T) -> anyhow::Result<()> {
+        let entity = self.entity(value)?;
+        self.commit(Mutation {
+            insert: None,
+            delete: None,
+            update: None,
+            base_version: None,
+            upsert: Some(entity),
+            update_time: None,
+        })
+        .await
+    }
 
-        Ok(())
+    /// Deletes the entity of kind `T` named `name_key`, which succeeds if there is none.
+    #[tracing::instrument(level = "debug", skip_all, fields(key = name_key.to_string()))]
+    pub async fn delete<K: ToString, T: KeyKind>(&self, name_key: K) -> anyhow::Result<()> {
+        self.commit(Mutation {
+            insert: None,
+            delete: Some(Key {
+                path: Some(vec![PathElement {
+                    // We can't create multiple datastore databases in GCP, so we have to suffix
+                    // type kinds with env (`dev`, `prod`).
+                    kind: Some(format!("{}-{}", T::kind(), self.env)),
+                    name: Some(name_key.to_string()),
+                    id: None,
+                }]),
+                partition_id: None,
+            }),
+            update: None,
+            base_version: None,
+            upsert: None,
+            update_time: None,
+        })
+        .await
     }
 
-    pub async fn upsert<T: IntoValue + KeyKind>(&self, value: T) -> anyhow::Result<()> {
+    fn entity<T: IntoValue + KeyKind>(&self, value: T) -> anyhow::Result<Entity> {
         let mut entity = Entity::from_value(value.into_value())?;
         let path_element = entity
             .key
@@ -225,25 +327,29 @@ impl GcpService {
             // type kinds with env (`dev`, `prod`).
             path_element.kind = Some(format!("{}-{}", T::kind(), self.env))
         }
+        Ok(entity)
+    }
+
+    async fn commit(&self, mutation: Mutation) -> anyhow::Result<()> {
+        let datastore = match &self.datastore {
+            DatastoreClient::Gcp(datastore) => datastore,
+            DatastoreClient::Local(entities) => {
+                return entities
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .apply(mutation)
+            }
+        };
 
         let request = CommitRequest {
             database_id: Some("".to_string()),
             mode: Some(String::from("NON_TRANSACTIONAL")),
-            mutations: Some(vec![Mutation {
-                insert: None,
-                delete: None,
-                update: None,
-                base_version: None,
-                upsert: Some(entity),
-                update_time: None,
-            }]),
+            mutations: Some(vec![mutation]),
             single_use_transaction: None,
             transaction: None,
         };
-
         tracing::debug!(?request);
-        let (_, response) = self
-            .datastore
+        let (_, response) = datastore
             .projects()
             .commit(request, &self.project_id)
             .doit()
@@ -255,6 +361,22 @@ impl GcpService {
 
     pub async fn fetch_entities<T: KeyKind>(&self) -> anyhow::Result<Vec<EntityResult>> {
         let kind: String = format!("{}-{}", T::kind(), self.env);
+        let datastore = match &self.datastore {
+            DatastoreClient::Gcp(datastore) => datastore,
+            DatastoreClient::Local(entities) => {
+                return Ok(entities
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .0
+                    .iter()
+                    .filter(|((entity_kind, _), _)| *entity_kind == kind)
+                    .map(|(_, entity)| EntityResult {
+                        entity: Some(entity.clone()),
+                        ..Default::default()
+                    })
+                    .collect());
+            }
+        };
         let req = RunQueryRequest {
             database_id: Some("".to_string()),
             partition_id: Default::default(),
@@ -273,8 +395,7 @@ impl GcpService {
             gql_query: None,
         };
 
-        let (_hyper_resp, query_resp) = self
-            .datastore
+        let (_hyper_resp, query_resp) = datastore
             .projects()
             .run_query(req, &self.project_id)
             .doit()
@@ -288,3 +409,61 @@ impl GcpService {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sign_node::user_credentials::EncryptedUserCredentials;
+    use aes_gcm::{Aes256Gcm, KeyInit};
+
+    #[tokio::test]
+    async fn test_local_storage() {
+        let gcp_service = GcpService::local("test".to_string());
+        let cipher = Aes256Gcm::new(&Aes256Gcm::generate_key(&mut aes_gcm::aead::OsRng));
+        let credentials =
+            EncryptedUserCredentials::random(0, "issuer:subject".to_string(), &cipher).unwrap();
+        let name_key = format!("0/{}", credentials.internal_account_id);
+
+        assert!(gcp_service
+            .get::<_, EncryptedUserCredentials>(&name_key)
+            .await
+            .unwrap()
+            .is_none());
+        // Updates fail for missing entities, just like with Datastore.
+        assert!(gcp_service.update(credentials.clone()).await.is_err());
+
+        gcp_service.insert(credentials.clone()).await.unwrap();
+        let stored = gcp_service
+            .get::<_, EncryptedUserCredentials>(&name_key)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.public_key, credentials.public_key);
+        assert_eq!(stored.encrypted_key_pair, credentials.encrypted_key_pair);
+        assert!(gcp_service.insert(credentials.clone()).await.is_err());
+
+        gcp_service.upsert(credentials).await.unwrap();
+        let entities = gcp_service
+            .fetch_entities::<EncryptedUserCredentials>()
+            .await
+            .unwrap();
+        assert_eq!(entities.len(), 1);
+
+        gcp_service
+            .delete::<_, EncryptedUserCredentials>(&name_key)
+            .await
+            .unwrap();
+        assert!(gcp_service
+            .get::<_, EncryptedUserCredentials>(&name_key)
+            .await
+            .unwrap()
+            .is_none());
+        // Deleting a missing entity succeeds, just like with Datastore.
+        gcp_service
+            .delete::<_, EncryptedUserCredentials>(&name_key)
+            .await
+            .unwrap();
+
+        assert!(gcp_service.load_secret("sk-share").await.is_err());
+    }
+}
@@ -0,0 +1,345 @@
+//! Asynchronous mode of the leader node endpoints that wait for the relayer. Clients that send
+//! `Prefer: respond-async` get a request id back right away with `202 Accepted`, and poll
+//! `GET /status/{id}` until it returns the response that the endpoint would have. Requests are
+//! kept in the datastore for [`ASYNC_REQUEST_TTL`] seconds.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::{HeaderMap, StatusCode};
+use axum::{Extension, Json};
+use chrono::Utc;
+use google_datastore1::api::{Key, PathElement};
+use rand::RngCore;
+use serde::Serialize;
+use tracing::Instrument;
+
+use super::idempotency::ErrorResponse;
+use super::LeaderState;
+use crate::error::ErrorCode;
+use crate::gcp::error::ConvertError;
+use crate::gcp::value::{FromValue, IntoValue, Value};
+use crate::gcp::KeyKind;
+use crate::msg::{
+    DelegateActionResponse, NewAccountResponse, RecoverAccountResponse, StatusResponse,
+};
+
+pub const PREFER_HEADER: &str = "Prefer";
+pub const RESPOND_ASYNC: &str = "respond-async";
+
+/// How long in seconds the response to an asynchronous request can be fetched.
+pub const ASYNC_REQUEST_TTL: i64 = 24 * 60 * 60;
+
+/// How long in seconds a request may be in progress before it is considered abandoned, e.g.
+/// because the leader node restarted in the middle of it.
+const IN_PROGRESS_TIMEOUT: i64 = 5 * 60;
+
+/// Responses that can tell the client that its request is being processed.
+pub trait PendingResponse: ErrorResponse {
+    fn pending(request_id: String) -> Self;
+}
+
+impl PendingResponse for NewAccountResponse {
+    fn pending(request_id: String) -> Self {
+        NewAccountResponse::Pending { request_id }
+    }
+}
+
+impl PendingResponse for RecoverAccountResponse {
+    fn pending(request_id: String) -> Self {
+        RecoverAccountResponse::Pending { request_id }
+    }
+}
+
+impl PendingResponse for DelegateActionResponse {
+    fn pending(request_id: String) -> Self {
+        DelegateActionResponse::Pending { request_id }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsyncRequestRecord {
+    pub id: String,
+    /// Endpoint that the request was sent to.
+    pub endpoint: String,
+    /// Status and response of the request, or `None` while it is still in progress.
+    pub response: Option<(u16, String)>,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+impl KeyKind for AsyncRequestRecord {
+    fn kind() -> String {
+        "AsyncRequestRecord".to_string()
+    }
+}
+
+impl IntoValue for AsyncRequestRecord {
+    fn into_value(self) -> Value {
+        let mut properties = HashMap::new();
+        properties.insert("id".to_string(), Value::StringValue(self.id.clone()));
+        properties.insert("endpoint".to_string(), Value::StringValue(self.endpoint));
+        if let Some((status, response)) = self.response {
+            properties.insert("status".to_string(), Value::IntegerValue(status as i64));
+            properties.insert("response".to_string(), Value::StringValue(response));
+        }
+        properties.insert(
+            "created_at".to_string(),
+            Value::IntegerValue(self.created_at),
+        );
+        properties.insert(
+            "expires_at".to_string(),
+            Value::IntegerValue(self.expires_at),
+        );
+        Value::EntityValue {
+            key: Key {
+                path: Some(vec![PathElement {
+                    kind: Some(AsyncRequestRecord::kind()),
+                    name: Some(self.id),
+                    id: None,
+                }]),
+                partition_id: None,
+            },
+            properties,
+        }
+    }
+}
+
+impl FromValue for AsyncRequestRecord {
+    fn from_value(value: Value) -> Result<Self, ConvertError> {
+        match value {
+            Value::EntityValue { mut properties, .. } => {
+                let mut take = |name: &str| {
+                    properties
+                        .remove(name)
+                        .ok_or_else(|| ConvertError::MissingProperty(name.to_string()))
+                };
+                let id = String::from_value(take("id")?)?;
+                let endpoint = String::from_value(take("endpoint")?)?;
+                let created_at = i64::from_value(take("created_at")?)?;
+                let expires_at = i64::from_value(take("expires_at")?)?;
+                let response = match (take("status"), take("response")) {
+                    (Ok(status), Ok(response)) => {
+                        let status = u16::try_from(i64::from_value(status)?)
+                            .map_err(|_| ConvertError::MalformedProperty("status".to_string()))?;
+                        Some((status, String::from_value(response)?))
+                    }
+                    _ => None,
+                };
+
+                Ok(Self {
+                    id,
+                    endpoint,
+                    response,
+                    created_at,
+                    expires_at,
+                })
+            }
+            value => Err(ConvertError::UnexpectedPropertyType {
+                expected: "entity".to_string(),
+                got: format!("{:?}", value),
+            }),
+        }
+    }
+}
+
+impl AsyncRequestRecord {
+    fn is_expired(&self, now: i64) -> bool {
+        now >= self.expires_at
+    }
+
+    fn is_abandoned(&self, now: i64) -> bool {
+        self.response.is_none() && now >= self.created_at + IN_PROGRESS_TIMEOUT
+    }
+}
+
+fn wants_async(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(PREFER_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|preference| preference.trim().eq_ignore_ascii_case(RESPOND_ASYNC))
+}
+
+fn new_request_id() -> String {
+    let mut id = [0; 16];
+    rand::thread_rng().fill_bytes(&mut id);
+    hex::encode(id)
+}
+
+/// Processes the request in the background if the client prefers to respond asynchronously,
+/// answering with the id to poll its status with. Otherwise processes it right away.
+pub async fn respond_async<R, F, Fut>(
+    state: &Arc<LeaderState>,
+    endpoint: &str,
+    headers: &HeaderMap,
+    process: F,
+) -> (StatusCode, Json<R>)
+where
+    R: PendingResponse + Serialize,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = (StatusCode, Json<R>)> + Send + 'static,
+{
+    if !wants_async(headers) {
+        return process().await;
+    }
+
+    let now = Utc::now().timestamp();
+    let mut record = AsyncRequestRecord {
+        id: new_request_id(),
+        endpoint: endpoint.to_string(),
+        response: None,
+        created_at: now,
+        expires_at: now + ASYNC_REQUEST_TTL,
+    };
+    if let Err(err) = state.gcp_service.insert(record.clone()).await {
+        tracing::error!(?err, "failed to store asynchronous request");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(R::err(
+                ErrorCode::Internal,
+                "failed to store asynchronous request".to_string(),
+            )),
+        );
+    }
+
+    let request_id = record.id.clone();
+    let response = process();
+    let state = state.clone();
+    tokio::spawn(
+        async move {
+            let (status, Json(response)) = response.await;
+            record.response = serde_json::to_string(&response)
+                .ok()
+                .map(|response| (status.as_u16(), response));
+            if let Err(err) = state.gcp_service.upsert(record).await {
+                tracing::error!(?err, "failed to store response of asynchronous request");
+            }
+        }
+        .in_current_span(),
+    );
+    tracing::info!(request_id, endpoint, "processing request asynchronously");
+    (StatusCode::ACCEPTED, Json(R::pending(request_id)))
+}
+
+/// Status of an asynchronous request. Once it is done, this is the status and response that the
+/// endpoint would have returned.
+#[tracing::instrument(level = "info", skip_all, fields(env = state.env, request_id))]
+pub(super) async fn status(
+    Extension(state): Extension<Arc<LeaderState>>,
+    Path(request_id): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let now = Utc::now().timestamp();
+    let record = match state
+        .gcp_service
+        .get::<_, AsyncRequestRecord>(&request_id)
+        .await
+    {
+        Ok(record) => record.filter(|record| !record.is_expired(now)),
+        Err(err) => {
+            tracing::error!(?err, "failed to look up asynchronous request");
+            return status_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusResponse::err(ErrorCode::Internal, "failed to look up request".to_string()),
+            );
+        }
+    };
+    let Some(record) = record else {
+        return status_response(
+            StatusCode::NOT_FOUND,
+            StatusResponse::err(
+                ErrorCode::RequestNotFound,
+                "unknown or expired request id".to_string(),
+            ),
+        );
+    };
+    if record.is_abandoned(now) {
+        return status_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusResponse::err(
+                ErrorCode::RequestAbandoned,
+                "request was abandoned, send it again".to_string(),
+            ),
+        );
+    }
+    let Some((status, response)) = record.response else {
+        return status_response(StatusCode::ACCEPTED, StatusResponse::Pending { request_id });
+    };
+    match (
+        StatusCode::from_u16(status),
+        serde_json::from_str::<serde_json::Value>(&response),
+    ) {
+        (Ok(status), Ok(response)) => (status, Json(response)),
+        _ => status_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusResponse::err(
+                ErrorCode::Internal,
+                "stored response of request is malformed".to_string(),
+            ),
+        ),
+    }
+}
+
+fn status_response(
+    status: StatusCode,
+    response: StatusResponse,
+) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        status,
+        Json(serde_json::to_value(response).unwrap_or_default()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(response: Option<(u16, String)>) -> AsyncRequestRecord {
+        AsyncRequestRecord {
+            id: new_request_id(),
+            endpoint: "new_account".to_string(),
+            response,
+            created_at: 1_000,
+            expires_at: 1_000 + ASYNC_REQUEST_TTL,
+        }
+    }
+
+    #[test]
+    fn test_async_request_record_value() {
+        let pending = record(None);
+        assert_eq!(
+            AsyncRequestRecord::from_value(pending.clone().into_value()).unwrap(),
+            pending
+        );
+        let done = record(Some((200, "{}".to_string())));
+        assert_eq!(
+            AsyncRequestRecord::from_value(done.clone().into_value()).unwrap(),
+            done
+        );
+    }
+
+    #[test]
+    fn test_async_request_record_expiry() {
+        let pending = record(None);
+        assert!(!pending.is_abandoned(1_000));
+        assert!(pending.is_abandoned(1_000 + IN_PROGRESS_TIMEOUT));
+
+        let done = record(Some((200, "{}".to_string())));
+        assert!(!done.is_abandoned(1_000 + IN_PROGRESS_TIMEOUT));
+        assert!(!done.is_expired(1_000 + IN_PROGRESS_TIMEOUT));
+        assert!(done.is_expired(done.expires_at));
+    }
+
+    #[test]
+    fn test_wants_async() {
+        let mut headers = HeaderMap::new();
+        assert!(!wants_async(&headers));
+        headers.insert(PREFER_HEADER, "return=minimal".parse().unwrap());
+        assert!(!wants_async(&headers));
+        headers.append(PREFER_HEADER, "wait=10, Respond-Async".parse().unwrap());
+        assert!(wants_async(&headers));
+    }
+}
@@ -0,0 +1,280 @@
+//! Optional challenge that has to be solved before the leader node creates an account, to make
+//! farming accounts through it expensive. It is either a hashcash style proof of work bound to
+//! the new account, or a CAPTCHA token checked with an external verification service.
+
+use std::fmt::Display;
+
+use sha2::{Digest, Sha256};
+
+use crate::error::LeaderNodeError;
+use crate::msg::{ChallengeSolution, NewAccountRequest};
+
+/// Challenge that has to be solved to create an account.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChallengeMode {
+    /// Accounts are created without a challenge.
+    #[default]
+    None,
+    /// Requests have to include a proof of work, see [`proof_of_work_digest`].
+    Pow,
+    /// Requests have to include a CAPTCHA token, which is checked with `--captcha-verify-url`.
+    Captcha,
+}
+
+impl Display for ChallengeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            ChallengeMode::None => "none",
+            ChallengeMode::Pow => "pow",
+            ChallengeMode::Captcha => "captcha",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// Configures the challenge required to create new accounts.
+#[derive(Debug, Clone, clap::Parser)]
+pub struct Options {
+    /// Challenge that new account requests have to solve.
+    #[clap(
+        long,
+        env("MPC_RECOVERY_ACCOUNT_CHALLENGE"),
+        value_enum,
+        default_value = "none"
+    )]
+    pub account_challenge: ChallengeMode,
+
+    /// Leading zero bits that the proof of work digest needs to have.
+    #[clap(long, env("MPC_RECOVERY_POW_DIFFICULTY"), default_value = "20")]
+    pub pow_difficulty: u32,
+
+    /// Endpoint verifying CAPTCHA tokens. It gets a form with the `secret` and the `response`
+    /// token, and replies with a JSON object that has a `success` field.
+    #[clap(long, env("MPC_RECOVERY_CAPTCHA_VERIFY_URL"))]
+    pub captcha_verify_url: Option<String>,
+
+    /// Secret of this deployment at the CAPTCHA verification service.
+    #[clap(long, env("MPC_RECOVERY_CAPTCHA_SECRET"))]
+    pub captcha_secret: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            account_challenge: ChallengeMode::None,
+            pow_difficulty: 20,
+            captcha_verify_url: None,
+            captcha_secret: None,
+        }
+    }
+}
+
+impl Options {
+    pub fn into_str_args(self) -> Vec<String> {
+        let mut buf = vec![
+            "--account-challenge".to_string(),
+            self.account_challenge.to_string(),
+            "--pow-difficulty".to_string(),
+            self.pow_difficulty.to_string(),
+        ];
+        if let Some(captcha_verify_url) = self.captcha_verify_url {
+            buf.push("--captcha-verify-url".to_string());
+            buf.push(captcha_verify_url);
+        }
+        if let Some(captcha_secret) = self.captcha_secret {
+            buf.push("--captcha-secret".to_string());
+            buf.push(captcha_secret);
+        }
+        buf
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CaptchaVerifyResponse {
+    success: bool,
+}
+
+pub enum ChallengeVerifier {
+    None,
+    ProofOfWork { difficulty: u32 },
+    Captcha { verify_url: String, secret: String },
+}
+
+impl ChallengeVerifier {
+    pub fn new(options: &Options) -> anyhow::Result<Self> {
+        Ok(match options.account_challenge {
+            ChallengeMode::None => Self::None,
+            ChallengeMode::Pow => {
+                anyhow::ensure!(
+                    options.pow_difficulty <= 256,
+                    "pow difficulty can be at most 256 bits"
+                );
+                Self::ProofOfWork {
+                    difficulty: options.pow_difficulty,
+                }
+            }
+            ChallengeMode::Captcha => {
+                let (Some(verify_url), Some(secret)) =
+                    (&options.captcha_verify_url, &options.captcha_secret)
+                else {
+                    anyhow::bail!("captcha challenge requires a verify url and a secret");
+                };
+                Self::Captcha {
+                    verify_url: verify_url.clone(),
+                    secret: secret.clone(),
+                }
+            }
+        })
+    }
+
+    /// Checks the challenge solution attached to `request`. Cheap checks should run before
+    /// this one, since verifying a CAPTCHA takes a request to an external service.
+    pub async fn verify(
+        &self,
+        client: &reqwest::Client,
+        request: &NewAccountRequest,
+    ) -> Result<(), LeaderNodeError> {
+        match (self, &request.challenge) {
+            (Self::None, _) => Ok(()),
+            (Self::ProofOfWork { difficulty }, Some(ChallengeSolution::ProofOfWork { nonce })) => {
+                let digest = proof_of_work_digest(request, *nonce);
+                if leading_zero_bits(&digest) >= *difficulty {
+                    Ok(())
+                } else {
+                    Err(LeaderNodeError::ChallengeFailed(format!(
+                        "proof of work does not have {difficulty} leading zero bits"
+                    )))
+                }
+            }
+            (Self::Captcha { verify_url, secret }, Some(ChallengeSolution::Captcha { token })) => {
+                let response: CaptchaVerifyResponse = client
+                    .post(verify_url)
+                    .form(&[("secret", secret.as_str()), ("response", token.as_str())])
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                if response.success {
+                    Ok(())
+                } else {
+                    Err(LeaderNodeError::ChallengeFailed(
+                        "captcha was not solved".to_string(),
+                    ))
+                }
+            }
+            (Self::ProofOfWork { difficulty }, _) => Err(LeaderNodeError::ChallengeFailed(
+                format!("a proof of work with {difficulty} leading zero bits is required"),
+            )),
+            (Self::Captcha { .. }, _) => Err(LeaderNodeError::ChallengeFailed(
+                "a captcha token is required".to_string(),
+            )),
+        }
+    }
+}
+
+/// Digest of the proof of work for `request`, which is the SHA-256 of
+/// `{near_account_id}:{frp_public_key}:{nonce}`. Binding it to the account and the key means a
+/// solution can not be reused for other accounts.
+pub fn proof_of_work_digest(request: &NewAccountRequest, nonce: u64) -> [u8; 32] {
+    Sha256::digest(
+        format!(
+            "{}:{}:{}",
+            request.near_account_id, request.frp_public_key, nonce
+        )
+        .as_bytes(),
+    )
+    .into()
+}
+
+fn leading_zero_bits(digest: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in digest {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sign_node::oidc::OidcToken;
+    use crate::transaction::CreateAccountOptions;
+    use ed25519_dalek::Signature;
+
+    fn request(challenge: Option<ChallengeSolution>) -> NewAccountRequest {
+        NewAccountRequest {
+            near_account_id: "alice.near".parse().unwrap(),
+            create_account_options: CreateAccountOptions {
+                full_access_keys: None,
+                limited_access_keys: None,
+                contract_bytes: None,
+            },
+            oidc_token: OidcToken::new("token"),
+            user_credentials_frp_signature: Signature::from_bytes(&[0; 64]).unwrap(),
+            frp_public_key: "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+                .parse()
+                .unwrap(),
+            challenge,
+        }
+    }
+
+    #[test]
+    fn test_leading_zero_bits() {
+        assert_eq!(leading_zero_bits(&[0xff]), 0);
+        assert_eq!(leading_zero_bits(&[0x00, 0x10]), 11);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+    }
+
+    #[tokio::test]
+    async fn test_proof_of_work() {
+        let verifier = ChallengeVerifier::new(&Options {
+            account_challenge: ChallengeMode::Pow,
+            pow_difficulty: 8,
+            ..Default::default()
+        })
+        .unwrap();
+        let client = reqwest::Client::new();
+
+        let nonce = (0..)
+            .find(|nonce| leading_zero_bits(&proof_of_work_digest(&request(None), *nonce)) >= 8)
+            .unwrap();
+        let solved = request(Some(ChallengeSolution::ProofOfWork { nonce }));
+        assert!(verifier.verify(&client, &solved).await.is_ok());
+
+        // The solution is bound to the account it was computed for.
+        let mut other_account = solved.clone();
+        other_account.near_account_id = "bob.near".parse().unwrap();
+        assert_ne!(
+            proof_of_work_digest(&solved, nonce),
+            proof_of_work_digest(&other_account, nonce)
+        );
+
+        assert!(matches!(
+            verifier.verify(&client, &request(None)).await,
+            Err(LeaderNodeError::ChallengeFailed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_no_challenge() {
+        let verifier = ChallengeVerifier::new(&Options::default()).unwrap();
+        assert!(verifier
+            .verify(&reqwest::Client::new(), &request(None))
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn test_captcha_requires_verify_url() {
+        assert!(ChallengeVerifier::new(&Options {
+            account_challenge: ChallengeMode::Captcha,
+            captcha_secret: Some("secret".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
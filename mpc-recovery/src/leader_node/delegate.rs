@@ -0,0 +1,154 @@
+//! Delegate actions (NEP-366) signed with the recovery key of the identity on behalf of the
+//! account and relayed by the partner, so that accounts without any NEAR balance can still
+//! manage their keys. The user only signs off on the actions, the nonce and expiry of the
+//! delegate action are filled in by the leader.
+
+use std::sync::Arc;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_primitives::delegate_action::{DelegateAction, NonDelegateAction, SignedDelegateAction};
+use near_primitives::transaction::Action;
+
+use super::{identities, LeaderState};
+use crate::error::LeaderNodeError;
+use crate::msg::{
+    DelegateActionNodeRequest, DelegateActionRequest, DelegateActionResponse, Identity,
+    SignNodeRequest,
+};
+use crate::nar;
+use crate::oauth::IdTokenClaims;
+use crate::relayer::RelayerMode;
+use crate::transaction::sign_payload_with_mpc;
+
+pub(super) async fn process_delegate_action(
+    state: Arc<LeaderState>,
+    request: DelegateActionRequest,
+) -> Result<DelegateActionResponse, LeaderNodeError> {
+    // Nested delegate actions fail to deserialize as `NonDelegateAction`
+    let actions = Vec::<NonDelegateAction>::try_from_slice(&request.actions)
+        .map_err(LeaderNodeError::MalformedDelegateAction)?;
+
+    let (oidc_token_claims, identity) = identities::authorize(
+        &state,
+        &request.near_account_id,
+        &request.oidc_token,
+        &request.user_credentials_frp_signature,
+        &request.frp_public_key,
+    )
+    .await?;
+    for action in &actions {
+        match Action::from(action.clone()) {
+            Action::DeleteKey(delete_key)
+                if delete_key.public_key == identity.recovery_public_key =>
+            {
+                return Err(LeaderNodeError::RecoveryKeyCanNotBeDeleted(
+                    delete_key.public_key,
+                ));
+            }
+            Action::DeleteAccount(_) => return Err(LeaderNodeError::AccountDeletionUnsupported),
+            _ => {}
+        }
+    }
+    let signed_delegate_action =
+        relay(&state, &request, &oidc_token_claims, &identity, actions).await?;
+    let serialized = signed_delegate_action
+        .try_to_vec()
+        .map_err(|e| LeaderNodeError::DataConversionFailure(e.into()))?;
+    Ok(DelegateActionResponse::Ok {
+        signed_delegate_action: serialized,
+    })
+}
+
+/// Signs the delegate action of `request` with the recovery key of `identity` and relays it.
+/// `actions` are the deserialized actions of the request.
+pub(super) async fn relay(
+    state: &LeaderState,
+    request: &DelegateActionRequest,
+    oidc_token_claims: &IdTokenClaims,
+    identity: &Identity,
+    actions: Vec<NonDelegateAction>,
+) -> Result<SignedDelegateAction, LeaderNodeError> {
+    let partner = match state.relayer {
+        RelayerMode::Partner => Some(
+            state
+                .partners
+                .find(&oidc_token_claims.iss, &oidc_token_claims.aud)?,
+        ),
+        RelayerMode::None => None,
+    };
+
+    nar::retry(|| async {
+        let (_hash, block_height, nonce) = state
+            .client
+            .access_key(&request.near_account_id, &identity.recovery_public_key)
+            .await?;
+        let delegate_action = DelegateAction {
+            sender_id: request.near_account_id.clone(),
+            receiver_id: request.receiver_id.clone(),
+            actions: actions.clone(),
+            nonce,
+            max_block_height: block_height + 100,
+            public_key: identity.recovery_public_key.clone(),
+        };
+
+        let signature = sign_payload_with_mpc(
+            &state.reqwest_client,
+            &state.sign_nodes,
+            SignNodeRequest::DelegateAction(DelegateActionNodeRequest {
+                oidc_token: request.oidc_token.clone(),
+                delegate_action: delegate_action.clone(),
+                frp_signature: request.frp_signature,
+                frp_public_key: request.frp_public_key.clone(),
+            }),
+        )
+        .await?;
+        let signed_delegate_action = SignedDelegateAction {
+            delegate_action,
+            signature: near_crypto::Signature::ED25519(signature),
+        };
+
+        let result = match &partner {
+            Some(partner) => state
+                .client
+                .send_meta_tx(signed_delegate_action.clone(), partner.relayer.clone())
+                .await
+                .map(|_| ()),
+            // The account creator pays for relaying the delegate action itself.
+            None => state
+                .client
+                .send_tx(
+                    state.account_creators.signer(&oidc_token_claims.aud),
+                    &request.near_account_id,
+                    vec![Action::Delegate(signed_delegate_action.clone())],
+                )
+                .await
+                .map(|_| ()),
+        };
+
+        match result {
+            Ok(()) => {
+                tracing::info!(
+                    near_account_id = request.near_account_id.to_string(),
+                    receiver_id = request.receiver_id.to_string(),
+                    "delegate action relayed"
+                );
+                Ok(signed_delegate_action)
+            }
+            Err(err) => {
+                tracing::error!("relaying delegate action failed: {err}");
+                state
+                    .client
+                    .invalidate_cache_if_acc_creation_failed(
+                        &(
+                            request.near_account_id.clone(),
+                            identity.recovery_public_key.clone(),
+                        ),
+                        &format!("{:?}", err),
+                    )
+                    .await;
+                Err(LeaderNodeError::RelayerError(err))
+            }
+        }
+    })
+    .await
+}
@@ -0,0 +1,192 @@
+//! Deletion of an identity, e.g. for a GDPR erasure request. The recovery key of the identity is
+//! deleted from the account, the identity is unregistered from the account and the sign nodes
+//! delete its user credentials, so that no share of the recovery key is left. Each step is skipped
+//! once done, so a request that failed halfway can be sent again.
+
+use std::sync::Arc;
+
+use near_primitives::delegate_action::SignedDelegateAction;
+use near_primitives::transaction::Action;
+use near_primitives::views::AccessKeyPermissionView;
+
+use super::{identities, LeaderState};
+use crate::error::LeaderNodeError;
+use crate::msg::{
+    DeleteIdentityNodeRequest, DeleteIdentityRequest, DeleteIdentityResponse,
+    DeleteUserCredentialsNodeRequest, Identity, SignNodeRequest,
+};
+use crate::nar;
+use crate::oauth::IdTokenClaims;
+use crate::relayer::error::RelayerError;
+use crate::relayer::RelayerMode;
+use crate::transaction::{
+    call_all_nodes, new_delete_identity_delegate_action, sign_payload_with_mpc,
+};
+use crate::utils::{check_digest_signature, delete_identity_request_digest};
+
+pub(super) async fn process_delete_identity(
+    state: Arc<LeaderState>,
+    request: DeleteIdentityRequest,
+) -> Result<DeleteIdentityResponse, LeaderNodeError> {
+    // The recovery key does not have to be on the account anymore, so that a request that
+    // failed after deleting it can be sent again to delete the rest.
+    let (oidc_token_claims, identity) = identities::verify(
+        &state,
+        &request.oidc_token,
+        &request.user_credentials_frp_signature,
+        &request.frp_public_key,
+    )
+    .await?;
+    let digest = delete_identity_request_digest(
+        &request.near_account_id,
+        &request.oidc_token,
+        &request.frp_public_key,
+    )
+    .map_err(|err| LeaderNodeError::Other(err.into()))?;
+    check_digest_signature(&request.frp_public_key, &request.frp_signature, &digest)
+        .map_err(LeaderNodeError::SignatureVerificationFailed)?;
+
+    let key_deleted = delete_recovery_key(&state, &request, &oidc_token_claims, &identity).await?;
+    identities::unregister(
+        &state,
+        &request.near_account_id,
+        &identity.internal_account_id,
+    )
+    .await?;
+
+    // The user credentials go last, since the recovery key can not be deleted without them.
+    nar::retry(|| async {
+        call_all_nodes::<_, ()>(
+            &state.reqwest_client,
+            &state.sign_nodes,
+            "delete_user_credentials",
+            DeleteUserCredentialsNodeRequest {
+                near_account_id: request.near_account_id.clone(),
+                oidc_token: request.oidc_token.clone(),
+                frp_signature: request.frp_signature,
+                frp_public_key: request.frp_public_key.clone(),
+            },
+        )
+        .await
+    })
+    .await?;
+
+    tracing::info!(
+        near_account_id = request.near_account_id.to_string(),
+        internal_account_id = identity.internal_account_id,
+        key_deleted,
+        "identity deleted"
+    );
+    Ok(DeleteIdentityResponse::Ok {
+        near_account_id: request.near_account_id,
+        deleted_key: key_deleted.then_some(identity.recovery_public_key),
+    })
+}
+
+/// Deletes the recovery key of `identity` from the account with a delegate action signed by that
+/// same key, unless it is not on the account anymore. Returns whether it was deleted.
+async fn delete_recovery_key(
+    state: &LeaderState,
+    request: &DeleteIdentityRequest,
+    oidc_token_claims: &IdTokenClaims,
+    identity: &Identity,
+) -> Result<bool, LeaderNodeError> {
+    let partner = match state.relayer {
+        RelayerMode::Partner => Some(
+            state
+                .partners
+                .find(&oidc_token_claims.iss, &oidc_token_claims.aud)?,
+        ),
+        RelayerMode::None => None,
+    };
+
+    nar::retry(|| async {
+        let access_keys = match state.client.access_keys(&request.near_account_id).await {
+            Ok(access_keys) => access_keys,
+            Err(RelayerError::UnknownAccount(_)) => return Ok(false),
+            Err(err) => return Err(LeaderNodeError::RelayerError(err)),
+        };
+        if !access_keys
+            .iter()
+            .any(|key| key.public_key == identity.recovery_public_key)
+        {
+            return Ok(false);
+        }
+        // Deleting the last full access key would leave nobody in control of the account.
+        if !access_keys.iter().any(|key| {
+            key.public_key != identity.recovery_public_key
+                && matches!(
+                    key.access_key.permission,
+                    AccessKeyPermissionView::FullAccess
+                )
+        }) {
+            return Err(LeaderNodeError::LastFullAccessKey(
+                identity.recovery_public_key.clone(),
+            ));
+        }
+
+        let (_hash, block_height, nonce) = state
+            .client
+            .access_key(&request.near_account_id, &identity.recovery_public_key)
+            .await?;
+        let delegate_action = new_delete_identity_delegate_action(
+            &request.near_account_id,
+            &identity.recovery_public_key,
+            nonce,
+            block_height + 100,
+        )?;
+
+        let signature = sign_payload_with_mpc(
+            &state.reqwest_client,
+            &state.sign_nodes,
+            SignNodeRequest::DeleteIdentity(DeleteIdentityNodeRequest {
+                oidc_token: request.oidc_token.clone(),
+                delegate_action: delegate_action.clone(),
+                frp_signature: request.frp_signature,
+                frp_public_key: request.frp_public_key.clone(),
+            }),
+        )
+        .await?;
+        let signed_delegate_action = SignedDelegateAction {
+            delegate_action,
+            signature: near_crypto::Signature::ED25519(signature),
+        };
+
+        let result = match &partner {
+            Some(partner) => state
+                .client
+                .send_meta_tx(signed_delegate_action, partner.relayer.clone())
+                .await
+                .map(|_| ()),
+            // The account creator pays for relaying the delegate action itself.
+            None => state
+                .client
+                .send_tx(
+                    state.account_creators.signer(&oidc_token_claims.aud),
+                    &request.near_account_id,
+                    vec![Action::Delegate(signed_delegate_action)],
+                )
+                .await
+                .map(|_| ()),
+        };
+
+        match result {
+            Ok(()) => Ok(true),
+            Err(err) => {
+                tracing::error!("recovery key deletion failed: {err}");
+                state
+                    .client
+                    .invalidate_cache_if_acc_creation_failed(
+                        &(
+                            request.near_account_id.clone(),
+                            identity.recovery_public_key.clone(),
+                        ),
+                        &format!("{:?}", err),
+                    )
+                    .await;
+                Err(LeaderNodeError::RelayerError(err))
+            }
+        }
+    })
+    .await
+}
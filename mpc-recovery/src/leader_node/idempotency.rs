@@ -0,0 +1,334 @@
+//! Idempotency keys of the leader node endpoints that submit transactions through the relayer.
+//! Clients that retry a request with the same `Idempotency-Key` header get the response of the
+//! first attempt back instead of creating a duplicate transaction. Seen keys are kept in the
+//! datastore for [`IDEMPOTENCY_KEY_TTL`] seconds.
+
+use std::collections::HashMap;
+use std::future::Future;
+
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use chrono::Utc;
+use google_datastore1::api::{Key, PathElement};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use super::LeaderState;
+use crate::error::ErrorCode;
+use crate::gcp::error::ConvertError;
+use crate::gcp::value::{FromValue, IntoValue, Value};
+use crate::gcp::KeyKind;
+use crate::msg::{DelegateActionResponse, NewAccountResponse, RecoverAccountResponse};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// How long in seconds the response to a request is kept around for retries of it.
+pub const IDEMPOTENCY_KEY_TTL: i64 = 24 * 60 * 60;
+
+/// How long in seconds a request may be in progress before its key is considered abandoned,
+/// e.g. because the leader node restarted in the middle of it, and can be used again.
+const IN_PROGRESS_TIMEOUT: i64 = 5 * 60;
+
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// Responses that can carry the errors of the idempotency checks.
+pub trait ErrorResponse {
+    fn err(code: ErrorCode, msg: String) -> Self;
+}
+
+impl ErrorResponse for NewAccountResponse {
+    fn err(code: ErrorCode, msg: String) -> Self {
+        NewAccountResponse::err(code, msg)
+    }
+}
+
+impl ErrorResponse for RecoverAccountResponse {
+    fn err(code: ErrorCode, msg: String) -> Self {
+        RecoverAccountResponse::err(code, msg)
+    }
+}
+
+impl ErrorResponse for DelegateActionResponse {
+    fn err(code: ErrorCode, msg: String) -> Self {
+        DelegateActionResponse::err(code, msg)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdempotencyRecord {
+    /// The idempotency key scoped to the endpoint it was used with.
+    pub key: String,
+    /// Hash of the request, so that a key can not be reused for a different request.
+    pub request_hash: String,
+    /// Status and response of the request, or `None` while it is still in progress.
+    pub response: Option<(u16, String)>,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+impl KeyKind for IdempotencyRecord {
+    fn kind() -> String {
+        "IdempotencyRecord".to_string()
+    }
+}
+
+impl IntoValue for IdempotencyRecord {
+    fn into_value(self) -> Value {
+        let mut properties = HashMap::new();
+        properties.insert("key".to_string(), Value::StringValue(self.key.clone()));
+        properties.insert(
+            "request_hash".to_string(),
+            Value::StringValue(self.request_hash),
+        );
+        if let Some((status, response)) = self.response {
+            properties.insert("status".to_string(), Value::IntegerValue(status as i64));
+            properties.insert("response".to_string(), Value::StringValue(response));
+        }
+        properties.insert(
+            "created_at".to_string(),
+            Value::IntegerValue(self.created_at),
+        );
+        properties.insert(
+            "expires_at".to_string(),
+            Value::IntegerValue(self.expires_at),
+        );
+        Value::EntityValue {
+            key: Key {
+                path: Some(vec![PathElement {
+                    kind: Some(IdempotencyRecord::kind()),
+                    name: Some(self.key),
+                    id: None,
+                }]),
+                partition_id: None,
+            },
+            properties,
+        }
+    }
+}
+
+impl FromValue for IdempotencyRecord {
+    fn from_value(value: Value) -> Result<Self, ConvertError> {
+        match value {
+            Value::EntityValue { mut properties, .. } => {
+                let mut take = |name: &str| {
+                    properties
+                        .remove(name)
+                        .ok_or_else(|| ConvertError::MissingProperty(name.to_string()))
+                };
+                let key = String::from_value(take("key")?)?;
+                let request_hash = String::from_value(take("request_hash")?)?;
+                let created_at = i64::from_value(take("created_at")?)?;
+                let expires_at = i64::from_value(take("expires_at")?)?;
+                let response = match (take("status"), take("response")) {
+                    (Ok(status), Ok(response)) => {
+                        let status = u16::try_from(i64::from_value(status)?)
+                            .map_err(|_| ConvertError::MalformedProperty("status".to_string()))?;
+                        Some((status, String::from_value(response)?))
+                    }
+                    _ => None,
+                };
+
+                Ok(Self {
+                    key,
+                    request_hash,
+                    response,
+                    created_at,
+                    expires_at,
+                })
+            }
+            value => Err(ConvertError::UnexpectedPropertyType {
+                expected: "entity".to_string(),
+                got: format!("{:?}", value),
+            }),
+        }
+    }
+}
+
+impl IdempotencyRecord {
+    fn is_expired(&self, now: i64) -> bool {
+        now >= self.expires_at
+            || (self.response.is_none() && now >= self.created_at + IN_PROGRESS_TIMEOUT)
+    }
+}
+
+fn request_hash<T: Serialize>(request: &T) -> String {
+    let request = serde_json::to_vec(request).unwrap_or_default();
+    hex::encode(Sha256::digest(&request))
+}
+
+fn reject<R: ErrorResponse>(
+    status: StatusCode,
+    code: ErrorCode,
+    msg: &str,
+) -> (StatusCode, Json<R>) {
+    (status, Json(R::err(code, msg.to_string())))
+}
+
+/// Processes `request` once per `Idempotency-Key`. Requests without the header are always
+/// processed. Retries with the same key get the response of the first attempt, unless it failed
+/// with a server error, in which case the request is processed again.
+pub async fn idempotent<Req, R, F, Fut>(
+    state: &LeaderState,
+    endpoint: &str,
+    headers: &HeaderMap,
+    request: &Req,
+    process: F,
+) -> (StatusCode, Json<R>)
+where
+    Req: Serialize,
+    R: ErrorResponse + Serialize + DeserializeOwned,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = (StatusCode, Json<R>)>,
+{
+    let Some(key) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return process().await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN => key,
+        _ => {
+            return reject(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidIdempotencyKey,
+                "Idempotency-Key must be between 1 and 255 visible ASCII characters",
+            )
+        }
+    };
+    let key = format!("{endpoint}:{key}");
+    let request_hash = request_hash(request);
+    let now = Utc::now().timestamp();
+
+    let existing = match state.gcp_service.get::<_, IdempotencyRecord>(&key).await {
+        Ok(existing) => existing.filter(|record| !record.is_expired(now)),
+        Err(err) => {
+            tracing::error!(%key, ?err, "failed to look up idempotency key");
+            return reject(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
+                "failed to look up idempotency key",
+            );
+        }
+    };
+    if let Some(existing) = existing {
+        if existing.request_hash != request_hash {
+            return reject(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::IdempotencyKeyReused,
+                "Idempotency-Key was already used for a different request",
+            );
+        }
+        let Some((status, response)) = existing.response else {
+            return reject(
+                StatusCode::CONFLICT,
+                ErrorCode::RequestInProgress,
+                "a request with this Idempotency-Key is still in progress",
+            );
+        };
+        tracing::info!(%key, status, "replaying response of idempotent request");
+        return match (
+            StatusCode::from_u16(status),
+            serde_json::from_str::<R>(&response),
+        ) {
+            (Ok(status), Ok(response)) => (status, Json(response)),
+            _ => reject(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
+                "stored response of idempotent request is malformed",
+            ),
+        };
+    }
+
+    // The key is claimed before processing, so that concurrent retries do not get processed
+    // twice. Keys that expired are taken over by overwriting them.
+    let mut record = IdempotencyRecord {
+        key: key.clone(),
+        request_hash,
+        response: None,
+        created_at: now,
+        expires_at: now + IDEMPOTENCY_KEY_TTL,
+    };
+    if let Err(err) = state.gcp_service.insert(record.clone()).await {
+        if let Err(err) = claim_expired(state, &record, now).await {
+            tracing::warn!(%key, ?err, "failed to claim idempotency key");
+            return reject(
+                StatusCode::CONFLICT,
+                ErrorCode::RequestInProgress,
+                "a request with this Idempotency-Key is still in progress",
+            );
+        }
+        tracing::debug!(%key, ?err, "took over expired idempotency key");
+    }
+
+    let (status, Json(response)) = process().await;
+    if status.is_server_error() {
+        // Let retries process the request again, the failure may be transient.
+        record.expires_at = now;
+    } else {
+        record.response = serde_json::to_string(&response)
+            .ok()
+            .map(|response| (status.as_u16(), response));
+    }
+    if let Err(err) = state.gcp_service.upsert(record).await {
+        tracing::error!(%key, ?err, "failed to store response of idempotent request");
+    }
+    (status, Json(response))
+}
+
+/// Overwrites the record of `record.key` if it expired in the meantime.
+async fn claim_expired(
+    state: &LeaderState,
+    record: &IdempotencyRecord,
+    now: i64,
+) -> anyhow::Result<()> {
+    match state
+        .gcp_service
+        .get::<_, IdempotencyRecord>(&record.key)
+        .await?
+    {
+        Some(existing) if !existing.is_expired(now) => {
+            anyhow::bail!("idempotency key is in use")
+        }
+        _ => state.gcp_service.upsert(record.clone()).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(response: Option<(u16, String)>) -> IdempotencyRecord {
+        IdempotencyRecord {
+            key: "new_account:abc".to_string(),
+            request_hash: request_hash(&"request"),
+            response,
+            created_at: 1_000,
+            expires_at: 1_000 + IDEMPOTENCY_KEY_TTL,
+        }
+    }
+
+    #[test]
+    fn test_idempotency_record_value() {
+        let pending = record(None);
+        assert_eq!(
+            IdempotencyRecord::from_value(pending.clone().into_value()).unwrap(),
+            pending
+        );
+        let done = record(Some((200, "{}".to_string())));
+        assert_eq!(
+            IdempotencyRecord::from_value(done.clone().into_value()).unwrap(),
+            done
+        );
+    }
+
+    #[test]
+    fn test_idempotency_record_expiry() {
+        let in_progress_timeout = IN_PROGRESS_TIMEOUT;
+        let pending = record(None);
+        assert!(!pending.is_expired(1_000));
+        assert!(pending.is_expired(1_000 + in_progress_timeout));
+
+        let done = record(Some((200, "{}".to_string())));
+        assert!(!done.is_expired(1_000 + in_progress_timeout));
+        assert!(done.is_expired(done.expires_at));
+    }
+}
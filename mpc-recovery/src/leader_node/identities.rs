@@ -0,0 +1,370 @@
+//! Bookkeeping of the OIDC identities that can recover a NEAR account. An identity can recover
+//! an account when its recovery key is one of the account's access keys, so the chain is the
+//! source of truth for authorization. The records kept here are what allows users to see and
+//! manage all of their identities from any one of them.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use borsh::BorshSerialize;
+use ed25519_dalek::Signature;
+use google_datastore1::api::{Key, PathElement};
+use near_crypto::PublicKey;
+use near_primitives::delegate_action::NonDelegateAction;
+use near_primitives::transaction::{Action, DeleteKeyAction};
+use near_primitives::types::AccountId;
+
+use super::{delegate, LeaderState};
+use crate::error::LeaderNodeError;
+use crate::gcp::error::ConvertError;
+use crate::gcp::value::{FromValue, IntoValue, Value};
+use crate::gcp::KeyKind;
+use crate::key_recovery::get_user_recovery_pk;
+use crate::msg::{
+    AddIdentityRequest, DelegateActionRequest, IdentitiesResponse, Identity, ListIdentitiesRequest,
+    RemoveIdentityRequest, RemoveIdentityResponse,
+};
+use crate::nar;
+use crate::oauth::{verify_oidc_nonce, verify_oidc_token, IdTokenClaims};
+use crate::relayer::error::RelayerError;
+use crate::sign_node::oidc::OidcToken;
+use crate::utils::{check_digest_signature, user_credentials_request_digest};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountIdentities {
+    pub near_account_id: AccountId,
+    pub identities: Vec<Identity>,
+}
+
+impl KeyKind for AccountIdentities {
+    fn kind() -> String {
+        "AccountIdentities".to_string()
+    }
+}
+
+impl IntoValue for AccountIdentities {
+    fn into_value(self) -> Value {
+        let mut properties = HashMap::new();
+        properties.insert(
+            "near_account_id".to_string(),
+            Value::StringValue(self.near_account_id.to_string()),
+        );
+        properties.insert(
+            "identities".to_string(),
+            Value::StringValue(serde_json::to_string(&self.identities).unwrap()),
+        );
+        Value::EntityValue {
+            key: Key {
+                path: Some(vec![PathElement {
+                    kind: Some(AccountIdentities::kind()),
+                    name: Some(self.near_account_id.to_string()),
+                    id: None,
+                }]),
+                partition_id: None,
+            },
+            properties,
+        }
+    }
+}
+
+impl FromValue for AccountIdentities {
+    fn from_value(value: Value) -> Result<Self, ConvertError> {
+        match value {
+            Value::EntityValue { mut properties, .. } => {
+                let (_, near_account_id) = properties
+                    .remove_entry("near_account_id")
+                    .ok_or_else(|| ConvertError::MissingProperty("near_account_id".to_string()))?;
+                let near_account_id = String::from_value(near_account_id)?
+                    .parse()
+                    .map_err(|_| ConvertError::MalformedProperty("near_account_id".to_string()))?;
+
+                let (_, identities) = properties
+                    .remove_entry("identities")
+                    .ok_or_else(|| ConvertError::MissingProperty("identities".to_string()))?;
+                let identities = String::from_value(identities)?;
+                let identities = serde_json::from_str(&identities)
+                    .map_err(|_| ConvertError::MalformedProperty("identities".to_string()))?;
+
+                Ok(Self {
+                    near_account_id,
+                    identities,
+                })
+            }
+            value => Err(ConvertError::UnexpectedPropertyType {
+                expected: "entity".to_string(),
+                got: format!("{:?}", value),
+            }),
+        }
+    }
+}
+
+impl AccountIdentities {
+    /// Adds the identity if it is not there yet, returning whether anything changed.
+    pub fn insert(&mut self, identity: Identity) -> bool {
+        match self
+            .identities
+            .iter_mut()
+            .find(|known| known.internal_account_id == identity.internal_account_id)
+        {
+            Some(known) if *known == identity => false,
+            Some(known) => {
+                *known = identity;
+                true
+            }
+            None => {
+                self.identities.push(identity);
+                true
+            }
+        }
+    }
+
+    pub fn remove(&mut self, internal_account_id: &str) -> Option<Identity> {
+        let index = self
+            .identities
+            .iter()
+            .position(|identity| identity.internal_account_id == internal_account_id)?;
+        Some(self.identities.remove(index))
+    }
+}
+
+async fn load(
+    state: &LeaderState,
+    near_account_id: &AccountId,
+) -> Result<AccountIdentities, LeaderNodeError> {
+    let identities = state
+        .gcp_service
+        .get::<_, AccountIdentities>(near_account_id)
+        .await?;
+    Ok(identities.unwrap_or_else(|| AccountIdentities {
+        near_account_id: near_account_id.clone(),
+        identities: Vec::new(),
+    }))
+}
+
+/// Removes the record of the identity `internal_account_id` of `near_account_id`, along with the
+/// record of the account once it has no identities left.
+pub(super) async fn unregister(
+    state: &LeaderState,
+    near_account_id: &AccountId,
+    internal_account_id: &str,
+) -> Result<(), LeaderNodeError> {
+    let mut identities = load(state, near_account_id).await?;
+    if identities.remove(internal_account_id).is_none() {
+        return Ok(());
+    }
+    if identities.identities.is_empty() {
+        state
+            .gcp_service
+            .delete::<_, AccountIdentities>(near_account_id)
+            .await?;
+    } else {
+        state.gcp_service.upsert(identities).await?;
+    }
+    Ok(())
+}
+
+/// Records `identity` as one of the identities of `near_account_id`. Meant to be called once
+/// the identity's recovery key is known to be on the account.
+pub(super) async fn register(
+    state: &LeaderState,
+    near_account_id: &AccountId,
+    identity: Identity,
+) -> Result<AccountIdentities, LeaderNodeError> {
+    let mut identities = load(state, near_account_id).await?;
+    if identities.insert(identity) {
+        state.gcp_service.upsert(identities.clone()).await?;
+    }
+    Ok(identities)
+}
+
+/// Verifies the OIDC token and checks that the recovery key of its identity is one of the
+/// access keys of `near_account_id`.
+pub(super) async fn authorize(
+    state: &LeaderState,
+    near_account_id: &AccountId,
+    oidc_token: &OidcToken,
+    user_credentials_frp_signature: &Signature,
+    frp_public_key: &PublicKey,
+) -> Result<(IdTokenClaims, Identity), LeaderNodeError> {
+    let (oidc_token_claims, identity) = verify(
+        state,
+        oidc_token,
+        user_credentials_frp_signature,
+        frp_public_key,
+    )
+    .await?;
+    match state
+        .client
+        .access_key(near_account_id, &identity.recovery_public_key)
+        .await
+    {
+        Ok(_) => Ok((oidc_token_claims, identity)),
+        Err(RelayerError::UnknownAccessKey(_)) | Err(RelayerError::UnknownAccount(_)) => {
+            Err(LeaderNodeError::IdentityNotLinked(
+                identity.internal_account_id,
+                near_account_id.clone(),
+            ))
+        }
+        Err(err) => Err(LeaderNodeError::RelayerError(err)),
+    }
+}
+
+/// Verifies the OIDC token and retrieves the recovery key of its identity, regardless of the
+/// accounts that the identity can recover.
+pub(super) async fn verify(
+    state: &LeaderState,
+    oidc_token: &OidcToken,
+    user_credentials_frp_signature: &Signature,
+    frp_public_key: &PublicKey,
+) -> Result<(IdTokenClaims, Identity), LeaderNodeError> {
+    let oidc_providers = state.partners.oidc_providers();
+    let oidc_token_claims = verify_oidc_token(
+        oidc_token,
+        Some(&oidc_providers),
+        &oidc_providers,
+        &state.oidc_cache,
+        &state.jwt_signature_pk_url,
+    )
+    .await
+    .map_err(LeaderNodeError::OidcVerificationFailed)?;
+    verify_oidc_nonce(&oidc_token_claims, frp_public_key)
+        .map_err(LeaderNodeError::OidcVerificationFailed)?;
+    state.rate_limiter.check(&oidc_token_claims)?;
+
+    let digest = user_credentials_request_digest(oidc_token, frp_public_key)?;
+    check_digest_signature(frp_public_key, user_credentials_frp_signature, &digest)
+        .map_err(LeaderNodeError::SignatureVerificationFailed)?;
+
+    let recovery_public_key = nar::retry(|| async {
+        get_user_recovery_pk(
+            &state.reqwest_client,
+            &state.sign_nodes,
+            oidc_token,
+            user_credentials_frp_signature,
+            frp_public_key,
+        )
+        .await
+    })
+    .await
+    .map_err(|err| LeaderNodeError::FailedToRetrieveRecoveryPk(err.into()))?;
+
+    let internal_account_id = oidc_token_claims.get_internal_account_id();
+    Ok((
+        oidc_token_claims,
+        Identity {
+            internal_account_id,
+            recovery_public_key,
+        },
+    ))
+}
+
+pub(super) async fn process_add_identity(
+    state: Arc<LeaderState>,
+    request: AddIdentityRequest,
+) -> Result<IdentitiesResponse, LeaderNodeError> {
+    // The recovery key of the new identity has to be added to the account beforehand, which
+    // can only be done through `/sign` with an identity that can already recover it.
+    let (_, identity) = authorize(
+        &state,
+        &request.near_account_id,
+        &request.oidc_token,
+        &request.user_credentials_frp_signature,
+        &request.frp_public_key,
+    )
+    .await?;
+    let identities = register(&state, &request.near_account_id, identity).await?;
+
+    Ok(IdentitiesResponse::Ok {
+        identities: identities.identities,
+    })
+}
+
+pub(super) async fn process_list_identities(
+    state: Arc<LeaderState>,
+    request: ListIdentitiesRequest,
+) -> Result<IdentitiesResponse, LeaderNodeError> {
+    let (_, identity) = authorize(
+        &state,
+        &request.near_account_id,
+        &request.oidc_token,
+        &request.user_credentials_frp_signature,
+        &request.frp_public_key,
+    )
+    .await?;
+    // Accounts created before identities were tracked have no record yet, so the caller is
+    // listed even when it is not registered. Listing does not register it though, as only
+    // `/add_identity` and `/new_account` do.
+    let mut identities = load(&state, &request.near_account_id).await?;
+    identities.insert(identity);
+
+    Ok(IdentitiesResponse::Ok {
+        identities: identities.identities,
+    })
+}
+
+pub(super) async fn process_remove_identity(
+    state: Arc<LeaderState>,
+    request: RemoveIdentityRequest,
+) -> Result<RemoveIdentityResponse, LeaderNodeError> {
+    let (oidc_token_claims, identity) = authorize(
+        &state,
+        &request.near_account_id,
+        &request.oidc_token,
+        &request.user_credentials_frp_signature,
+        &request.frp_public_key,
+    )
+    .await?;
+    if identity.internal_account_id == request.identity {
+        return Err(LeaderNodeError::CannotRemoveOwnIdentity);
+    }
+
+    let mut identities = load(&state, &request.near_account_id).await?;
+    let removed = identities
+        .remove(&request.identity)
+        .ok_or_else(|| LeaderNodeError::IdentityNotFound(request.identity.clone()))?;
+
+    // The recovery key on the account is what lets the identity in, so it goes before the
+    // record does. A key that is gone already was deleted by a request that failed halfway.
+    match state
+        .client
+        .access_key(&request.near_account_id, &removed.recovery_public_key)
+        .await
+    {
+        Ok(_) => {
+            let actions = vec![
+                NonDelegateAction::try_from(Action::DeleteKey(DeleteKeyAction {
+                    public_key: removed.recovery_public_key.clone(),
+                }))
+                .map_err(|_| {
+                    LeaderNodeError::Other(anyhow::anyhow!("unexpected delegate action"))
+                })?,
+            ];
+            let delegate_request = DelegateActionRequest {
+                near_account_id: request.near_account_id.clone(),
+                receiver_id: request.near_account_id.clone(),
+                actions: actions
+                    .try_to_vec()
+                    .map_err(|e| LeaderNodeError::DataConversionFailure(e.into()))?,
+                oidc_token: request.oidc_token,
+                frp_signature: request.frp_signature,
+                user_credentials_frp_signature: request.user_credentials_frp_signature,
+                frp_public_key: request.frp_public_key,
+            };
+            delegate::relay(
+                &state,
+                &delegate_request,
+                &oidc_token_claims,
+                &identity,
+                actions,
+            )
+            .await?;
+        }
+        Err(RelayerError::UnknownAccessKey(_)) => {}
+        Err(err) => return Err(LeaderNodeError::RelayerError(err)),
+    }
+
+    identities.insert(identity);
+    state.gcp_service.upsert(identities).await?;
+
+    Ok(RemoveIdentityResponse::Ok { removed })
+}
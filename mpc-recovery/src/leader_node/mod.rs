@@ -1,17 +1,21 @@
-use crate::error::{LeaderNodeError, MpcError};
+use crate::error::{ErrorCode, LeaderNodeError, MpcError};
 use crate::firewall::allowed::PartnerList;
+use crate::gcp::GcpService;
 use crate::key_recovery::get_user_recovery_pk;
 use crate::msg::{
-    AcceptNodePublicKeysRequest, ClaimOidcNodeRequest, ClaimOidcRequest, ClaimOidcResponse,
-    MpcPkRequest, MpcPkResponse, NewAccountRequest, NewAccountResponse, SignNodeRequest,
+    AcceptNodePublicKeysRequest, AddIdentityRequest, ClaimOidcNodeRequest, ClaimOidcRequest,
+    ClaimOidcResponse, DelegateActionRequest, DelegateActionResponse, DeleteIdentityRequest,
+    DeleteIdentityResponse, IdentitiesResponse, Identity, ListIdentitiesRequest, MpcPkRequest,
+    MpcPkResponse, NewAccountRequest, NewAccountResponse, RecoverAccountRequest,
+    RecoverAccountResponse, RemoveIdentityRequest, RemoveIdentityResponse, SignNodeRequest,
     SignRequest, SignResponse, UserCredentialsRequest, UserCredentialsResponse,
 };
-use crate::oauth::verify_oidc_token;
+use crate::oauth::{self, verify_oidc_nonce, verify_oidc_token, OidcCache};
 use crate::relayer::msg::CreateAccountAtomicRequest;
-use crate::relayer::NearRpcAndRelayerClient;
+use crate::relayer::{NearRpcAndRelayerClient, RelayerMode};
 use crate::transaction::{
-    get_mpc_signature, new_create_account_delegate_action, sign_payload_with_mpc,
-    to_dalek_combined_public_key,
+    get_mpc_signature, new_create_account_action, new_create_account_delegate_action,
+    sign_payload_with_mpc, to_dalek_combined_public_key,
 };
 use crate::utils::{check_digest_signature, user_credentials_request_digest};
 use crate::{metrics, nar};
@@ -21,23 +25,36 @@ use axum::middleware::{self, Next};
 use axum::response::IntoResponse;
 use axum::routing::get;
 use axum::{
-    http::{Request, StatusCode},
+    http::{HeaderMap, Request, StatusCode},
     routing::post,
     Extension, Json, Router,
 };
 use axum_extra::extract::WithRejection;
 use axum_tracing_opentelemetry::middleware::{OtelAxumLayer, OtelInResponseLayer};
 use borsh::BorshDeserialize;
+use challenge::ChallengeVerifier;
 use curv::elliptic::curves::{Ed25519, Point};
+use near_crypto::InMemorySigner;
 use near_fetch::signer::KeyRotatingSigner;
 use near_primitives::delegate_action::{DelegateAction, NonDelegateAction};
 use near_primitives::transaction::{Action, DeleteAccountAction, DeleteKeyAction};
 use near_primitives::types::AccountId;
 use prometheus::{Encoder, TextEncoder};
+use rate_limit::RateLimiter;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Instant;
 
+mod async_request;
+pub mod challenge;
+mod delegate;
+mod deletion;
+mod idempotency;
+mod identities;
+pub mod rate_limit;
+mod recovery;
+
 pub struct Config {
     pub env: String,
     pub port: u16,
@@ -45,9 +62,33 @@ pub struct Config {
     pub near_rpc: String,
     pub near_root_account: String,
     // TODO: temporary solution
-    pub account_creator_signer: KeyRotatingSigner,
+    pub account_creators: AccountCreators,
     pub partners: PartnerList,
     pub jwt_signature_pk_url: String,
+    pub gcp_service: GcpService,
+    pub rate_limit_options: rate_limit::Options,
+    pub challenge_options: challenge::Options,
+    pub oidc_cache_options: oauth::cache::Options,
+    pub relayer: RelayerMode,
+}
+
+/// Accounts that create the accounts of the users, so that partners with an account creator of
+/// their own pay for the accounts of their users instead of the leader node.
+pub struct AccountCreators {
+    /// Creates the accounts of the users of partners without an account creator of their own.
+    pub default: KeyRotatingSigner,
+    /// Account creators of the partners, keyed by the OIDC audience of the partner.
+    pub partners: HashMap<String, KeyRotatingSigner>,
+}
+
+impl AccountCreators {
+    /// Next signer of the account creator of the partner with the OIDC `audience`.
+    pub fn signer(&self, audience: &str) -> &InMemorySigner {
+        self.partners
+            .get(audience)
+            .unwrap_or(&self.default)
+            .fetch_and_rotate_signer()
+    }
 }
 
 pub async fn run(config: Config) {
@@ -57,24 +98,42 @@ pub async fn run(config: Config) {
         sign_nodes,
         near_rpc,
         near_root_account,
-        account_creator_signer,
+        account_creators,
         partners,
         jwt_signature_pk_url,
+        gcp_service,
+        rate_limit_options,
+        challenge_options,
+        oidc_cache_options,
+        relayer,
     } = config;
     let _span = tracing::debug_span!("run", env, port);
     tracing::debug!(?sign_nodes, "running a leader node");
 
     let client = NearRpcAndRelayerClient::connect(&near_rpc);
+    let challenge_verifier = match ChallengeVerifier::new(&challenge_options) {
+        Ok(challenge_verifier) => challenge_verifier,
+        Err(err) => {
+            tracing::error!("Invalid account challenge options: {err}");
+            return;
+        }
+    };
 
+    let reqwest_client = reqwest::Client::new();
     let state = Arc::new(LeaderState {
         env,
         sign_nodes,
         client,
-        reqwest_client: reqwest::Client::new(),
+        oidc_cache: OidcCache::new(&oidc_cache_options, reqwest_client.clone()),
+        reqwest_client,
         near_root_account: near_root_account.parse().unwrap(),
-        account_creator_signer,
+        account_creators,
         partners,
         jwt_signature_pk_url,
+        gcp_service,
+        rate_limiter: RateLimiter::new(&rate_limit_options),
+        challenge_verifier,
+        relayer,
     });
 
     // Get keys from all sign nodes, and broadcast them out as a set.
@@ -112,6 +171,13 @@ pub async fn run(config: Config) {
         .route("/user_credentials", post(user_credentials))
         .route("/new_account", post(new_account))
         .route("/sign", post(sign))
+        .route("/add_identity", post(add_identity))
+        .route("/list_identities", post(list_identities))
+        .route("/remove_identity", post(remove_identity))
+        .route("/delete_identity", post(delete_identity))
+        .route("/recover_account", post(recover_account))
+        .route("/delegate_action", post(delegate_action))
+        .route("/status/:id", get(async_request::status))
         .route("/metrics", get(metrics))
         .route_layer(middleware::from_fn(track_metrics))
         .layer(Extension(state))
@@ -194,11 +260,16 @@ struct LeaderState {
     sign_nodes: Vec<String>,
     client: NearRpcAndRelayerClient,
     reqwest_client: reqwest::Client,
+    oidc_cache: Arc<OidcCache>,
     near_root_account: AccountId,
     // TODO: temporary solution
-    account_creator_signer: KeyRotatingSigner,
+    account_creators: AccountCreators,
     partners: PartnerList,
     jwt_signature_pk_url: String,
+    gcp_service: GcpService,
+    rate_limiter: RateLimiter,
+    challenge_verifier: ChallengeVerifier,
+    relayer: RelayerMode,
 }
 
 async fn mpc_public_key(
@@ -212,6 +283,7 @@ async fn mpc_public_key(
             return (
                 err.code(),
                 Json(MpcPkResponse::Err {
+                    code: err.error_code(),
                     msg: err.to_string(),
                 }),
             )
@@ -224,6 +296,7 @@ async fn mpc_public_key(
             return (
                 err.code(),
                 Json(MpcPkResponse::Err {
+                    code: ErrorCode::SigningFailed,
                     msg: err.to_string(),
                 }),
             )
@@ -262,7 +335,10 @@ async fn claim_oidc(
         ),
         Err(e) => (
             StatusCode::BAD_REQUEST,
-            Json(ClaimOidcResponse::Err { msg: e.to_string() }),
+            Json(ClaimOidcResponse::Err {
+                code: e.error_code(),
+                msg: e.to_string(),
+            }),
         ),
     }
 }
@@ -287,6 +363,7 @@ async fn user_credentials(
             (
                 err.code(),
                 Json(UserCredentialsResponse::Err {
+                    code: err.error_code(),
                     msg: err.to_string(),
                 }),
             )
@@ -298,14 +375,19 @@ async fn process_user_credentials(
     state: Arc<LeaderState>,
     request: UserCredentialsRequest,
 ) -> Result<UserCredentialsResponse, LeaderNodeError> {
-    verify_oidc_token(
+    let oidc_providers = state.partners.oidc_providers();
+    let oidc_token_claims = verify_oidc_token(
         &request.oidc_token,
-        Some(&state.partners.oidc_providers()),
-        &state.reqwest_client,
+        Some(&oidc_providers),
+        &oidc_providers,
+        &state.oidc_cache,
         &state.jwt_signature_pk_url,
     )
     .await
     .map_err(LeaderNodeError::OidcVerificationFailed)?;
+    verify_oidc_nonce(&oidc_token_claims, &request.frp_public_key)
+        .map_err(LeaderNodeError::OidcVerificationFailed)?;
+    state.rate_limiter.check(&oidc_token_claims)?;
 
     nar::retry(|| async {
         let mpc_user_recovery_pk = get_user_recovery_pk(
@@ -330,14 +412,23 @@ async fn process_new_account(
 ) -> Result<NewAccountResponse, LeaderNodeError> {
     // Create a transaction to create new NEAR account
     let new_user_account_id = request.near_account_id;
+    let oidc_providers = state.partners.oidc_providers();
     let oidc_token_claims = verify_oidc_token(
         &request.oidc_token,
-        Some(&state.partners.oidc_providers()),
-        &state.reqwest_client,
+        Some(&oidc_providers),
+        &oidc_providers,
+        &state.oidc_cache,
         &state.jwt_signature_pk_url,
     )
     .await
     .map_err(LeaderNodeError::OidcVerificationFailed)?;
+    verify_oidc_nonce(&oidc_token_claims, &request.frp_public_key)
+        .map_err(LeaderNodeError::OidcVerificationFailed)?;
+    state.rate_limiter.check(&oidc_token_claims)?;
+    state
+        .challenge_verifier
+        .verify(&state.reqwest_client, &request)
+        .await?;
     let internal_acc_id = oidc_token_claims.get_internal_account_id();
 
     // FIXME: waiting on https://github.com/near/mpc-recovery/issues/193
@@ -368,14 +459,7 @@ async fn process_new_account(
     .await?;
 
     nar::retry(|| async {
-        let account_creator = state.account_creator_signer.fetch_and_rotate_signer();
-
-        // Get nonce and recent block hash
-        let (_hash, block_height, nonce) = state
-            .client
-            .access_key(&account_creator.account_id, &account_creator.public_key)
-            .await
-            .map_err(LeaderNodeError::RelayerError)?;
+        let account_creator = state.account_creators.signer(&oidc_token_claims.aud);
 
         // Add recovery key to create account options
         let mut new_account_options = request.create_account_options.clone();
@@ -384,29 +468,54 @@ async fn process_new_account(
             None => new_account_options.full_access_keys = Some(vec![mpc_user_recovery_pk.clone()]),
         }
 
-        // We create accounts using the local key
-        let signed_delegate_action = new_create_account_delegate_action(
-            account_creator,
-            &new_user_account_id,
-            &new_account_options,
-            &state.near_root_account,
-            nonce,
-            block_height + 100,
-        )
-        .map_err(LeaderNodeError::Other)?;
-
-        // Send delegate action to relayer
-        let request = CreateAccountAtomicRequest {
-            account_id: new_user_account_id.clone(),
-            allowance: 300_000_000_000_000,
-            oauth_token: internal_acc_id.clone(),
-            signed_delegate_action,
-        };
+        let result = match state.relayer {
+            RelayerMode::Partner => {
+                // Get nonce and recent block hash
+                let (_hash, block_height, nonce) = state
+                    .client
+                    .access_key(&account_creator.account_id, &account_creator.public_key)
+                    .await
+                    .map_err(LeaderNodeError::RelayerError)?;
+
+                // We create accounts using the local key
+                let signed_delegate_action = new_create_account_delegate_action(
+                    account_creator,
+                    &new_user_account_id,
+                    &new_account_options,
+                    &state.near_root_account,
+                    nonce,
+                    block_height + 100,
+                )
+                .map_err(LeaderNodeError::Other)?;
+
+                // Send delegate action to relayer
+                let request = CreateAccountAtomicRequest {
+                    account_id: new_user_account_id.clone(),
+                    allowance: 300_000_000_000_000,
+                    oauth_token: internal_acc_id.clone(),
+                    signed_delegate_action,
+                };
 
-        let result = state
-            .client
-            .create_account_atomic(request, &partner.relayer)
-            .await;
+                state
+                    .client
+                    .create_account_atomic(request, &partner.relayer)
+                    .await
+            }
+            // The account creator pays for the transaction itself, so no allowance is
+            // registered for the new account.
+            RelayerMode::None => state
+                .client
+                .send_tx(
+                    account_creator,
+                    &state.near_root_account,
+                    vec![new_create_account_action(
+                        &new_user_account_id,
+                        &new_account_options,
+                    )],
+                )
+                .await
+                .map(|_| ()),
+        };
 
         match result {
             Ok(_) => {
@@ -414,6 +523,16 @@ async fn process_new_account(
                     "account creation succeeded: {new_user_account_id:?}",
                     new_user_account_id = new_user_account_id
                 );
+                let identity = Identity {
+                    internal_account_id: internal_acc_id.clone(),
+                    recovery_public_key: mpc_user_recovery_pk.clone(),
+                };
+                // The account is already created at this point, and identities missing a
+                // record get registered the next time they are used, so don't fail on this.
+                if let Err(err) = identities::register(&state, &new_user_account_id, identity).await
+                {
+                    tracing::error!("failed to register identity of new account: {err}");
+                }
                 Ok(NewAccountResponse::Ok {
                     create_account_options: new_account_options,
                     user_recovery_public_key: mpc_user_recovery_pk.clone(),
@@ -443,6 +562,7 @@ async fn process_new_account(
 #[tracing::instrument(level = "info", skip_all, fields(env = state.env))]
 async fn new_account(
     Extension(state): Extension<Arc<LeaderState>>,
+    headers: HeaderMap,
     WithRejection(Json(request), _): WithRejection<Json<NewAccountRequest>, MpcError>,
 ) -> (StatusCode, Json<NewAccountResponse>) {
     tracing::info!(
@@ -452,16 +572,28 @@ async fn new_account(
         "new_account request"
     );
 
-    match process_new_account(state, request).await {
-        Ok(response) => {
-            tracing::debug!("responding with OK");
-            (StatusCode::OK, Json(response))
-        }
-        Err(err) => {
-            tracing::error!(err = ?err);
-            (err.code(), Json(NewAccountResponse::err(err.to_string())))
+    let process = {
+        let (state, headers) = (state.clone(), headers.clone());
+        move || async move {
+            let process = || async {
+                match process_new_account(state.clone(), request.clone()).await {
+                    Ok(response) => {
+                        tracing::debug!("responding with OK");
+                        (StatusCode::OK, Json(response))
+                    }
+                    Err(err) => {
+                        tracing::error!(err = ?err);
+                        (
+                            err.code(),
+                            Json(NewAccountResponse::err(err.error_code(), err.to_string())),
+                        )
+                    }
+                }
+            };
+            idempotency::idempotent(&state, "new_account", &headers, &request, process).await
         }
-    }
+    };
+    async_request::respond_async(&state, "new_account", &headers, process).await
 }
 
 async fn process_sign(
@@ -473,14 +605,19 @@ async fn process_sign(
         .map_err(LeaderNodeError::MalformedDelegateAction)?;
 
     // Check OIDC token
-    verify_oidc_token(
+    let oidc_providers = state.partners.oidc_providers();
+    let oidc_token_claims = verify_oidc_token(
         &request.oidc_token,
-        Some(&state.partners.oidc_providers()),
-        &state.reqwest_client,
+        Some(&oidc_providers),
+        &oidc_providers,
+        &state.oidc_cache,
         &state.jwt_signature_pk_url,
     )
     .await
     .map_err(LeaderNodeError::OidcVerificationFailed)?;
+    verify_oidc_nonce(&oidc_token_claims, &request.frp_public_key)
+        .map_err(LeaderNodeError::OidcVerificationFailed)?;
+    state.rate_limiter.check(&oidc_token_claims)?;
 
     // Prevent recovery key delition
     let requested_delegate_actions: &Vec<NonDelegateAction> = &delegate_action.actions;
@@ -578,11 +715,193 @@ async fn sign(
         }
         Err(e) => {
             tracing::error!(err = ?e);
-            (e.code(), Json(SignResponse::err(e.to_string())))
+            (
+                e.code(),
+                Json(SignResponse::err(e.error_code(), e.to_string())),
+            )
+        }
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all, fields(env = state.env))]
+async fn add_identity(
+    Extension(state): Extension<Arc<LeaderState>>,
+    WithRejection(Json(request), _): WithRejection<Json<AddIdentityRequest>, MpcError>,
+) -> (StatusCode, Json<IdentitiesResponse>) {
+    tracing::info!(
+        near_account_id = request.near_account_id.to_string(),
+        oidc_token = format!("{:.5}...", request.oidc_token),
+        "add_identity request"
+    );
+
+    match identities::process_add_identity(state, request).await {
+        Ok(response) => {
+            tracing::debug!("responding with OK");
+            (StatusCode::OK, Json(response))
+        }
+        Err(e) => {
+            tracing::error!(err = ?e);
+            (
+                e.code(),
+                Json(IdentitiesResponse::err(e.error_code(), e.to_string())),
+            )
+        }
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all, fields(env = state.env))]
+async fn list_identities(
+    Extension(state): Extension<Arc<LeaderState>>,
+    WithRejection(Json(request), _): WithRejection<Json<ListIdentitiesRequest>, MpcError>,
+) -> (StatusCode, Json<IdentitiesResponse>) {
+    tracing::info!(
+        near_account_id = request.near_account_id.to_string(),
+        oidc_token = format!("{:.5}...", request.oidc_token),
+        "list_identities request"
+    );
+
+    match identities::process_list_identities(state, request).await {
+        Ok(response) => {
+            tracing::debug!("responding with OK");
+            (StatusCode::OK, Json(response))
+        }
+        Err(e) => {
+            tracing::error!(err = ?e);
+            (
+                e.code(),
+                Json(IdentitiesResponse::err(e.error_code(), e.to_string())),
+            )
+        }
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all, fields(env = state.env))]
+async fn remove_identity(
+    Extension(state): Extension<Arc<LeaderState>>,
+    WithRejection(Json(request), _): WithRejection<Json<RemoveIdentityRequest>, MpcError>,
+) -> (StatusCode, Json<RemoveIdentityResponse>) {
+    tracing::info!(
+        near_account_id = request.near_account_id.to_string(),
+        oidc_token = format!("{:.5}...", request.oidc_token),
+        identity = request.identity,
+        "remove_identity request"
+    );
+
+    match identities::process_remove_identity(state, request).await {
+        Ok(response) => {
+            tracing::debug!("responding with OK");
+            (StatusCode::OK, Json(response))
+        }
+        Err(e) => {
+            tracing::error!(err = ?e);
+            (
+                e.code(),
+                Json(RemoveIdentityResponse::err(e.error_code(), e.to_string())),
+            )
+        }
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all, fields(env = state.env))]
+async fn delete_identity(
+    Extension(state): Extension<Arc<LeaderState>>,
+    WithRejection(Json(request), _): WithRejection<Json<DeleteIdentityRequest>, MpcError>,
+) -> (StatusCode, Json<DeleteIdentityResponse>) {
+    tracing::info!(
+        near_account_id = request.near_account_id.to_string(),
+        oidc_token = format!("{:.5}...", request.oidc_token),
+        "delete_identity request"
+    );
+
+    match deletion::process_delete_identity(state, request).await {
+        Ok(response) => {
+            tracing::debug!("responding with OK");
+            (StatusCode::OK, Json(response))
+        }
+        Err(e) => {
+            tracing::error!(err = ?e);
+            (
+                e.code(),
+                Json(DeleteIdentityResponse::err(e.error_code(), e.to_string())),
+            )
         }
     }
 }
 
+#[tracing::instrument(level = "info", skip_all, fields(env = state.env))]
+async fn recover_account(
+    Extension(state): Extension<Arc<LeaderState>>,
+    headers: HeaderMap,
+    WithRejection(Json(request), _): WithRejection<Json<RecoverAccountRequest>, MpcError>,
+) -> (StatusCode, Json<RecoverAccountResponse>) {
+    tracing::info!(
+        near_account_id = request.near_account_id.to_string(),
+        new_public_key = request.new_public_key.to_string(),
+        oidc_token = format!("{:.5}...", request.oidc_token),
+        "recover_account request"
+    );
+
+    let process = {
+        let (state, headers) = (state.clone(), headers.clone());
+        move || async move {
+            let process = || async {
+                match recovery::process_recover_account(state.clone(), request.clone()).await {
+                    Ok(response) => {
+                        tracing::debug!("responding with OK");
+                        (StatusCode::OK, Json(response))
+                    }
+                    Err(e) => {
+                        tracing::error!(err = ?e);
+                        (
+                            e.code(),
+                            Json(RecoverAccountResponse::err(e.error_code(), e.to_string())),
+                        )
+                    }
+                }
+            };
+            idempotency::idempotent(&state, "recover_account", &headers, &request, process).await
+        }
+    };
+    async_request::respond_async(&state, "recover_account", &headers, process).await
+}
+
+#[tracing::instrument(level = "info", skip_all, fields(env = state.env))]
+async fn delegate_action(
+    Extension(state): Extension<Arc<LeaderState>>,
+    headers: HeaderMap,
+    WithRejection(Json(request), _): WithRejection<Json<DelegateActionRequest>, MpcError>,
+) -> (StatusCode, Json<DelegateActionResponse>) {
+    tracing::info!(
+        near_account_id = request.near_account_id.to_string(),
+        receiver_id = request.receiver_id.to_string(),
+        oidc_token = format!("{:.5}...", request.oidc_token),
+        "delegate_action request"
+    );
+
+    let process = {
+        let (state, headers) = (state.clone(), headers.clone());
+        move || async move {
+            let process = || async {
+                match delegate::process_delegate_action(state.clone(), request.clone()).await {
+                    Ok(response) => {
+                        tracing::debug!("responding with OK");
+                        (StatusCode::OK, Json(response))
+                    }
+                    Err(e) => {
+                        tracing::error!(err = ?e);
+                        (
+                            e.code(),
+                            Json(DelegateActionResponse::err(e.error_code(), e.to_string())),
+                        )
+                    }
+                }
+            };
+            idempotency::idempotent(&state, "delegate_action", &headers, &request, process).await
+        }
+    };
+    async_request::respond_async(&state, "delegate_action", &headers, process).await
+}
+
 async fn gather_sign_node_pk_shares(
     state: &LeaderState,
 ) -> Result<Vec<Point<Ed25519>>, LeaderNodeError> {
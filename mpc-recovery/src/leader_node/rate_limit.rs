@@ -0,0 +1,155 @@
+//! Per user rate limits of the leader node endpoints. Every OIDC identity gets a token bucket,
+//! so a single leaked or compromised token can not be used to spam account creations or key
+//! additions, while other users are not affected by it.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::error::LeaderNodeError;
+use crate::oauth::IdTokenClaims;
+
+/// Configures the rate limits of the leader node.
+#[derive(Debug, Clone, clap::Parser)]
+pub struct Options {
+    /// How many requests a single user can make in a burst.
+    #[clap(long, env("MPC_RECOVERY_RATE_LIMIT_BURST"), default_value = "10")]
+    pub rate_limit_burst: u32,
+
+    /// How many requests a single user can make per minute after exhausting the burst. Setting
+    /// it to zero disables rate limiting.
+    #[clap(long, env("MPC_RECOVERY_RATE_LIMIT_PER_MINUTE"), default_value = "10")]
+    pub rate_limit_per_minute: u32,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            rate_limit_burst: 10,
+            rate_limit_per_minute: 10,
+        }
+    }
+}
+
+impl Options {
+    pub fn into_str_args(self) -> Vec<String> {
+        vec![
+            "--rate-limit-burst".to_string(),
+            self.rate_limit_burst.to_string(),
+            "--rate-limit-per-minute".to_string(),
+            self.rate_limit_per_minute.to_string(),
+        ]
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+pub struct RateLimiter {
+    burst: f64,
+    per_second: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Once this many users are tracked, the buckets of users that have not made requests in a
+    /// while get dropped.
+    const MAX_BUCKETS: usize = 100_000;
+
+    pub fn new(options: &Options) -> Self {
+        Self {
+            burst: options.rate_limit_burst as f64,
+            per_second: options.rate_limit_per_minute as f64 / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from the bucket of the identity of `claims`, which has to be verified
+    /// beforehand so that nobody can exhaust the limits of someone else.
+    pub fn check(&self, claims: &IdTokenClaims) -> Result<(), LeaderNodeError> {
+        let identity = claims.get_internal_account_id();
+        if self.try_acquire(&identity, Instant::now()) {
+            Ok(())
+        } else {
+            tracing::warn!(%identity, "rate limit exceeded");
+            Err(LeaderNodeError::RateLimited(identity))
+        }
+    }
+
+    fn try_acquire(&self, key: &str, now: Instant) -> bool {
+        if self.per_second == 0.0 {
+            return true;
+        }
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+        if buckets.len() >= Self::MAX_BUCKETS && !buckets.contains_key(key) {
+            buckets.retain(|_, bucket| self.refill(bucket, now) < self.burst);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated_at: now,
+        });
+        if self.refill(bucket, now) < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    /// Adds the tokens accumulated since the bucket was last updated, returning the new amount.
+    fn refill(&self, bucket: &mut Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * self.per_second).min(self.burst);
+        bucket.updated_at = now;
+        bucket.tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(&Options {
+            rate_limit_burst: 3,
+            rate_limit_per_minute: 6,
+        });
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.try_acquire("alice", now));
+        }
+        assert!(!limiter.try_acquire("alice", now));
+        // Every user has their own bucket.
+        assert!(limiter.try_acquire("bob", now));
+
+        // A token is added every 10 seconds.
+        assert!(!limiter.try_acquire("alice", now + Duration::from_secs(5)));
+        assert!(limiter.try_acquire("alice", now + Duration::from_secs(10)));
+        assert!(!limiter.try_acquire("alice", now + Duration::from_secs(10)));
+
+        // The bucket never holds more than the burst.
+        let later = now + Duration::from_secs(3600);
+        for _ in 0..3 {
+            assert!(limiter.try_acquire("alice", later));
+        }
+        assert!(!limiter.try_acquire("alice", later));
+    }
+
+    #[test]
+    fn test_rate_limiter_disabled() {
+        let limiter = RateLimiter::new(&Options {
+            rate_limit_burst: 0,
+            rate_limit_per_minute: 0,
+        });
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert!(limiter.try_acquire("alice", now));
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Recovery of accounts whose keys got lost. The limited access keys of the account are replaced
+//! with a fresh full access key in a single delegate action signed by the recovery key of the
+//! identity, so either all of the keys are replaced or none is.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use near_crypto::PublicKey;
+use near_primitives::delegate_action::SignedDelegateAction;
+use near_primitives::transaction::Action;
+use near_primitives::views::AccessKeyPermissionView;
+
+use super::{identities, LeaderState};
+use crate::error::LeaderNodeError;
+use crate::msg::{
+    RecoverAccountNodeRequest, RecoverAccountRequest, RecoverAccountResponse, SignNodeRequest,
+};
+use crate::nar;
+use crate::relayer::RelayerMode;
+use crate::transaction::{new_recover_account_delegate_action, sign_payload_with_mpc};
+
+pub(super) async fn process_recover_account(
+    state: Arc<LeaderState>,
+    request: RecoverAccountRequest,
+) -> Result<RecoverAccountResponse, LeaderNodeError> {
+    let (oidc_token_claims, identity) = identities::authorize(
+        &state,
+        &request.near_account_id,
+        &request.oidc_token,
+        &request.user_credentials_frp_signature,
+        &request.frp_public_key,
+    )
+    .await?;
+    let partner = match state.relayer {
+        RelayerMode::Partner => Some(
+            state
+                .partners
+                .find(&oidc_token_claims.iss, &oidc_token_claims.aud)?,
+        ),
+        RelayerMode::None => None,
+    };
+
+    nar::retry(|| async {
+        let access_keys = state.client.access_keys(&request.near_account_id).await?;
+        if access_keys
+            .iter()
+            .any(|key| key.public_key == request.new_public_key)
+        {
+            return Err(LeaderNodeError::AccessKeyAlreadyExists(
+                request.new_public_key.clone(),
+            ));
+        }
+        // The user signed off on the keys to delete, so the request only goes through while
+        // they are exactly the limited access keys of the account.
+        let old_keys: HashSet<PublicKey> = access_keys
+            .into_iter()
+            .filter(|key| {
+                matches!(
+                    key.access_key.permission,
+                    AccessKeyPermissionView::FunctionCall { .. }
+                )
+            })
+            .map(|key| key.public_key)
+            .collect();
+        let keys_to_delete: HashSet<PublicKey> = request.keys_to_delete.iter().cloned().collect();
+        if old_keys != keys_to_delete || keys_to_delete.len() != request.keys_to_delete.len() {
+            return Err(LeaderNodeError::AccessKeysChanged);
+        }
+
+        let (_hash, block_height, nonce) = state
+            .client
+            .access_key(&request.near_account_id, &identity.recovery_public_key)
+            .await?;
+        let delegate_action = new_recover_account_delegate_action(
+            &request.near_account_id,
+            &identity.recovery_public_key,
+            &request.keys_to_delete,
+            &request.new_public_key,
+            nonce,
+            block_height + 100,
+        )?;
+
+        let signature = sign_payload_with_mpc(
+            &state.reqwest_client,
+            &state.sign_nodes,
+            SignNodeRequest::RecoverAccount(RecoverAccountNodeRequest {
+                oidc_token: request.oidc_token.clone(),
+                delegate_action: delegate_action.clone(),
+                new_public_key: request.new_public_key.clone(),
+                keys_to_delete: request.keys_to_delete.clone(),
+                frp_signature: request.frp_signature,
+                frp_public_key: request.frp_public_key.clone(),
+            }),
+        )
+        .await?;
+        let signed_delegate_action = SignedDelegateAction {
+            delegate_action,
+            signature: near_crypto::Signature::ED25519(signature),
+        };
+
+        let result = match &partner {
+            Some(partner) => state
+                .client
+                .send_meta_tx(signed_delegate_action, partner.relayer.clone())
+                .await
+                .map(|_| ()),
+            // The account creator pays for relaying the delegate action itself.
+            None => state
+                .client
+                .send_tx(
+                    state.account_creators.signer(&oidc_token_claims.aud),
+                    &request.near_account_id,
+                    vec![Action::Delegate(signed_delegate_action)],
+                )
+                .await
+                .map(|_| ()),
+        };
+
+        match result {
+            Ok(()) => {
+                tracing::info!(
+                    near_account_id = request.near_account_id.to_string(),
+                    deleted_keys = request.keys_to_delete.len(),
+                    "account recovery succeeded"
+                );
+                Ok(RecoverAccountResponse::Ok {
+                    near_account_id: request.near_account_id.clone(),
+                    new_public_key: request.new_public_key.clone(),
+                    deleted_keys: request.keys_to_delete.clone(),
+                })
+            }
+            Err(err) => {
+                tracing::error!("account recovery failed: {err}");
+                state
+                    .client
+                    .invalidate_cache_if_acc_creation_failed(
+                        &(
+                            request.near_account_id.clone(),
+                            identity.recovery_public_key.clone(),
+                        ),
+                        &format!("{:?}", err),
+                    )
+                    .await;
+                Err(LeaderNodeError::RelayerError(err))
+            }
+        }
+    })
+    .await
+}
@@ -1,12 +1,14 @@
 // TODO: FIXME: Remove this once we have a better way to handle these large errors
 #![allow(clippy::result_large_err)]
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 use aes_gcm::aead::consts::U32;
 use aes_gcm::aead::generic_array::GenericArray;
 use aes_gcm::aead::OsRng;
 use aes_gcm::{Aes256Gcm, KeyInit};
+use anyhow::Context;
 use clap::Parser;
 use curv::elliptic::curves::Ed25519;
 use curv::elliptic::curves::Point;
@@ -18,8 +20,10 @@ use near_crypto::{InMemorySigner, SecretKey};
 use near_fetch::signer::KeyRotatingSigner;
 use near_primitives::types::AccountId;
 
-use crate::firewall::allowed::PartnerList;
-use crate::gcp::GcpService;
+use crate::firewall::allowed::{OidcProviderList, PartnerList};
+use crate::gcp::{GcpService, Storage};
+use crate::leader_node::AccountCreators;
+use crate::relayer::RelayerMode;
 use crate::sign_node::migration;
 
 pub mod error;
@@ -113,12 +117,32 @@ pub enum Cli {
         /// GCP datastore URL
         #[arg(long, env("MPC_RECOVERY_GCP_DATASTORE_URL"))]
         gcp_datastore_url: Option<String>,
+        /// Where to store user data, `local` keeps it in memory to run without GCP
+        #[arg(long, env("MPC_RECOVERY_STORAGE"), value_enum, default_value = "gcp")]
+        storage: Storage,
         /// URL to the public key used to sign JWT tokens
         #[arg(long, env("MPC_RECOVERY_JWT_SIGNATURE_PK_URL"))]
         jwt_signature_pk_url: String,
         /// Enables export of span data using opentelemetry protocol.
         #[clap(flatten)]
         logging_options: logging::Options,
+        /// Per user limits on the requests to the leader node.
+        #[clap(flatten)]
+        rate_limit_options: leader_node::rate_limit::Options,
+        /// Challenge that new accounts have to solve, against automated account creation.
+        #[clap(flatten)]
+        challenge_options: leader_node::challenge::Options,
+        /// Caching of verified OIDC tokens and of the public keys of their providers.
+        #[clap(flatten)]
+        oidc_cache_options: oauth::cache::Options,
+        /// How to submit transactions, `none` sends them with the account creator directly
+        #[arg(
+            long,
+            env("MPC_RECOVERY_RELAYER"),
+            value_enum,
+            default_value = "partner"
+        )]
+        relayer: RelayerMode,
     },
     StartSign {
         /// Environment to run in (`dev` or `prod`)
@@ -142,12 +166,25 @@ pub enum Cli {
         /// GCP datastore URL
         #[arg(long, env("MPC_RECOVERY_GCP_DATASTORE_URL"))]
         gcp_datastore_url: Option<String>,
+        /// Where to store user data, `local` keeps it in memory to run without GCP
+        #[arg(long, env("MPC_RECOVERY_STORAGE"), value_enum, default_value = "gcp")]
+        storage: Storage,
         /// URL to the public key used to sign JWT tokens
         #[arg(long, env("MPC_RECOVERY_JWT_SIGNATURE_PK_URL"))]
         jwt_signature_pk_url: String,
+        /// JSON list of OIDC providers that publish their keys at a JWKS URL, to verify their
+        /// tokens with. Should match the providers of the leader node.
+        #[arg(long, env("MPC_RECOVERY_OIDC_PROVIDERS"))]
+        oidc_providers: Option<String>,
         /// Enables export of span data using opentelemetry protocol.
         #[clap(flatten)]
         logging_options: logging::Options,
+        /// Where to keep signing sessions between the rounds of signing.
+        #[clap(flatten)]
+        session_options: sign_node::session_store::Options,
+        /// Caching of verified OIDC tokens and of the public keys of their providers.
+        #[clap(flatten)]
+        oidc_cache_options: oauth::cache::Options,
     },
     RotateSignNodeCipher {
         /// Environment to run in (`dev` or `prod`)
@@ -203,8 +240,13 @@ pub async fn run(cmd: Cli) -> anyhow::Result<()> {
             fast_auth_partners_filepath: partners_filepath,
             gcp_project_id,
             gcp_datastore_url,
+            storage,
             jwt_signature_pk_url,
             logging_options,
+            rate_limit_options,
+            challenge_options,
+            oidc_cache_options,
+            relayer,
         } => {
             let _subscriber_guard = logging::subscribe_global(
                 EnvFilter::from_default_env(),
@@ -214,14 +256,19 @@ pub async fn run(cmd: Cli) -> anyhow::Result<()> {
             )
             .await;
             let gcp_service =
-                GcpService::new(env.clone(), gcp_project_id, gcp_datastore_url).await?;
-            let account_creator_signer =
-                load_account_creator(&gcp_service, &env, &account_creator_id, account_creator_sk)
-                    .await?;
+                GcpService::init(storage, env.clone(), gcp_project_id, gcp_datastore_url).await?;
             let partners = PartnerList {
                 entries: load_entries(&gcp_service, &env, "leader", partners, partners_filepath)
                     .await?,
             };
+            let account_creators = load_account_creators(
+                &gcp_service,
+                &env,
+                &account_creator_id,
+                account_creator_sk,
+                &partners,
+            )
+            .await?;
 
             let config = LeaderConfig {
                 env,
@@ -229,9 +276,14 @@ pub async fn run(cmd: Cli) -> anyhow::Result<()> {
                 sign_nodes,
                 near_rpc,
                 near_root_account,
-                account_creator_signer,
+                account_creators,
                 partners,
                 jwt_signature_pk_url,
+                gcp_service,
+                rate_limit_options,
+                challenge_options,
+                oidc_cache_options,
+                relayer,
             };
 
             run_leader_node(config).await;
@@ -244,8 +296,12 @@ pub async fn run(cmd: Cli) -> anyhow::Result<()> {
             web_port,
             gcp_project_id,
             gcp_datastore_url,
+            storage,
             jwt_signature_pk_url,
+            oidc_providers,
             logging_options,
+            session_options,
+            oidc_cache_options,
         } => {
             let _subscriber_guard = logging::subscribe_global(
                 EnvFilter::from_default_env(),
@@ -255,7 +311,13 @@ pub async fn run(cmd: Cli) -> anyhow::Result<()> {
             )
             .await;
             let gcp_service =
-                GcpService::new(env.clone(), gcp_project_id, gcp_datastore_url).await?;
+                GcpService::init(storage, env.clone(), gcp_project_id, gcp_datastore_url).await?;
+            let oidc_providers = OidcProviderList {
+                entries: match oidc_providers {
+                    Some(oidc_providers) => serde_json::from_str(&oidc_providers)?,
+                    None => Default::default(),
+                },
+            };
             let cipher_key = load_cipher_key(&gcp_service, &env, node_id, cipher_key).await?;
             let cipher_key = hex::decode(cipher_key)?;
             let cipher_key = GenericArray::<u8, U32>::clone_from_slice(&cipher_key);
@@ -273,6 +335,9 @@ pub async fn run(cmd: Cli) -> anyhow::Result<()> {
                 cipher,
                 port: web_port,
                 jwt_signature_pk_url,
+                oidc_providers,
+                session_options,
+                oidc_cache_options,
             };
             run_sign_node(config).await;
         }
@@ -364,12 +429,12 @@ async fn load_cipher_key(
 
 async fn load_account_creator(
     gcp_service: &GcpService,
-    env: &str,
+    secret_name: &str,
     account_creator_id: &AccountId,
     account_creator_sk: Vec<SecretKey>,
 ) -> anyhow::Result<KeyRotatingSigner> {
     let sks = if account_creator_sk.is_empty() {
-        let name = format!("mpc-recovery-account-creator-sk-{env}/versions/latest");
+        let name = format!("{secret_name}/versions/latest");
         let data = gcp_service.load_secret(name).await?;
         serde_json::from_str(std::str::from_utf8(&data)?)?
     } else {
@@ -381,6 +446,52 @@ async fn load_account_creator(
     })))
 }
 
+/// Loads the account creator of the leader node along with the ones of the partners that pay
+/// for the accounts of their users themselves.
+async fn load_account_creators(
+    gcp_service: &GcpService,
+    env: &str,
+    account_creator_id: &AccountId,
+    account_creator_sk: Vec<SecretKey>,
+    partners: &PartnerList,
+) -> anyhow::Result<AccountCreators> {
+    let default = load_account_creator(
+        gcp_service,
+        &format!("mpc-recovery-account-creator-sk-{env}"),
+        account_creator_id,
+        account_creator_sk,
+    )
+    .await?;
+
+    let mut partner_creators = HashMap::new();
+    for partner in &partners.entries {
+        let Some(account_creator) = &partner.account_creator else {
+            continue;
+        };
+        let audience = &partner.oidc_provider.audience;
+        if partner_creators.contains_key(audience) {
+            anyhow::bail!("more than one account creator for the audience {audience}");
+        }
+        let signer = load_account_creator(
+            gcp_service,
+            &format!(
+                "mpc-recovery-account-creator-sk-{env}-{}",
+                account_creator.account_id
+            ),
+            &account_creator.account_id,
+            account_creator.secret_keys.clone(),
+        )
+        .await
+        .with_context(|| format!("failed to load the account creator of {audience}"))?;
+        partner_creators.insert(audience.clone(), signer);
+    }
+
+    Ok(AccountCreators {
+        default,
+        partners: partner_creators,
+    })
+}
+
 async fn load_entries<T>(
     gcp_service: &GcpService,
     env: &str,
@@ -428,8 +539,13 @@ impl Cli {
                 fast_auth_partners_filepath,
                 gcp_project_id,
                 gcp_datastore_url,
+                storage,
                 jwt_signature_pk_url,
                 logging_options,
+                rate_limit_options,
+                challenge_options,
+                oidc_cache_options,
+                relayer,
             } => {
                 let mut buf = vec![
                     "start-leader".to_string(),
@@ -445,6 +561,8 @@ impl Cli {
                     account_creator_id.to_string(),
                     "--gcp-project-id".to_string(),
                     gcp_project_id,
+                    "--storage".to_string(),
+                    storage.to_string(),
                     "--jwt-signature-pk-url".to_string(),
                     jwt_signature_pk_url,
                 ];
@@ -469,6 +587,11 @@ impl Cli {
                 buf.push("--account-creator-sk".to_string());
                 buf.push(account_creator_sk);
                 buf.extend(logging_options.into_str_args());
+                buf.extend(rate_limit_options.into_str_args());
+                buf.extend(challenge_options.into_str_args());
+                buf.extend(oidc_cache_options.into_str_args());
+                buf.push("--relayer".to_string());
+                buf.push(relayer.to_string());
 
                 buf
             }
@@ -480,8 +603,12 @@ impl Cli {
                 sk_share,
                 gcp_project_id,
                 gcp_datastore_url,
+                storage,
                 jwt_signature_pk_url,
+                oidc_providers,
                 logging_options,
+                session_options,
+                oidc_cache_options,
             } => {
                 let mut buf = vec![
                     "start-sign".to_string(),
@@ -493,6 +620,8 @@ impl Cli {
                     web_port.to_string(),
                     "--gcp-project-id".to_string(),
                     gcp_project_id,
+                    "--storage".to_string(),
+                    storage.to_string(),
                     "--jwt-signature-pk-url".to_string(),
                     jwt_signature_pk_url,
                 ];
@@ -508,7 +637,13 @@ impl Cli {
                     buf.push("--gcp-datastore-url".to_string());
                     buf.push(gcp_datastore_url);
                 }
+                if let Some(oidc_providers) = oidc_providers {
+                    buf.push("--oidc-providers".to_string());
+                    buf.push(oidc_providers);
+                }
                 buf.extend(logging_options.into_str_args());
+                buf.extend(session_options.into_str_args());
+                buf.extend(oidc_cache_options.into_str_args());
 
                 buf
             }
@@ -33,6 +33,14 @@ lazy_static! {
         &["method", "path"]
     )
     .expect("can't create a metric");
+    pub static ref OIDC_CACHE_COUNT: IntCounterVec = register_int_counter_vec!(
+        opts!(
+            "mpc_oidc_cache_count",
+            "Total count of lookups in the OIDC verification caches, by cache and outcome"
+        ),
+        &["cache", "outcome"]
+    )
+    .expect("can't create a metric");
     pub static ref HTTP_PROCESSING_TIME: HistogramVec = register_histogram_vec!(
         "mpc_http_processing_time",
         "Time taken to process HTTP requests in seconds",
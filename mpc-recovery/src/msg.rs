@@ -1,3 +1,5 @@
+use crate::error::ErrorCode;
+use crate::primitives::InternalAccountId;
 use crate::sign_node::oidc::{OidcHash, OidcToken};
 use crate::transaction::CreateAccountOptions;
 use curv::elliptic::curves::{Ed25519, Point};
@@ -16,7 +18,7 @@ pub struct MpcPkRequest {}
 #[serde(rename_all = "snake_case")]
 pub enum MpcPkResponse {
     Ok { mpc_pk: ed25519_dalek::PublicKey },
-    Err { msg: String },
+    Err { code: ErrorCode, msg: String },
 }
 
 impl TryInto<ed25519_dalek::PublicKey> for MpcPkResponse {
@@ -25,7 +27,7 @@ impl TryInto<ed25519_dalek::PublicKey> for MpcPkResponse {
     fn try_into(self) -> Result<ed25519_dalek::PublicKey, Self::Error> {
         match self {
             MpcPkResponse::Ok { mpc_pk } => Ok(mpc_pk),
-            MpcPkResponse::Err { msg } => anyhow::bail!("error response: {}", msg),
+            MpcPkResponse::Err { msg, .. } => anyhow::bail!("error response: {}", msg),
         }
     }
 }
@@ -48,6 +50,7 @@ pub enum ClaimOidcResponse {
         mpc_signature: Signature,
     },
     Err {
+        code: ErrorCode,
         msg: String,
     },
 }
@@ -58,7 +61,7 @@ impl TryInto<Signature> for ClaimOidcResponse {
     fn try_into(self) -> Result<Signature, Self::Error> {
         let mpc_signature = match self {
             ClaimOidcResponse::Ok { mpc_signature } => mpc_signature,
-            ClaimOidcResponse::Err { msg } => anyhow::bail!("error response: {}", msg),
+            ClaimOidcResponse::Err { msg, .. } => anyhow::bail!("error response: {}", msg),
         };
 
         Ok(mpc_signature)
@@ -78,12 +81,12 @@ pub struct UserCredentialsRequest {
 #[serde(rename_all = "snake_case")]
 pub enum UserCredentialsResponse {
     Ok { recovery_pk: near_crypto::PublicKey },
-    Err { msg: String },
+    Err { code: ErrorCode, msg: String },
 }
 
 impl UserCredentialsResponse {
-    pub fn err(msg: String) -> Self {
-        UserCredentialsResponse::Err { msg }
+    pub fn err(code: ErrorCode, msg: String) -> Self {
+        UserCredentialsResponse::Err { code, msg }
     }
 }
 
@@ -95,6 +98,19 @@ pub struct NewAccountRequest {
     #[serde(with = "hex_signature")]
     pub user_credentials_frp_signature: Signature,
     pub frp_public_key: near_crypto::PublicKey,
+    /// Solution of the challenge that the leader node requires for new accounts, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<ChallengeSolution>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum ChallengeSolution {
+    /// Nonce that makes the proof of work digest of the request have enough leading zero bits.
+    ProofOfWork { nonce: u64 },
+    /// Token of a solved CAPTCHA.
+    Captcha { token: String },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -106,14 +122,19 @@ pub enum NewAccountResponse {
         user_recovery_public_key: near_crypto::PublicKey,
         near_account_id: AccountId,
     },
+    /// The request is processed asynchronously, its result can be polled with `/status/{id}`.
+    Pending {
+        request_id: String,
+    },
     Err {
+        code: ErrorCode,
         msg: String,
     },
 }
 
 impl NewAccountResponse {
-    pub fn err(msg: String) -> Self {
-        NewAccountResponse::Err { msg }
+    pub fn err(code: ErrorCode, msg: String) -> Self {
+        NewAccountResponse::Err { code, msg }
     }
 }
 
@@ -139,13 +160,14 @@ pub enum SignResponse {
         signature: Signature,
     },
     Err {
+        code: ErrorCode,
         msg: String,
     },
 }
 
 impl SignResponse {
-    pub fn err(msg: String) -> Self {
-        SignResponse::Err { msg }
+    pub fn err(code: ErrorCode, msg: String) -> Self {
+        SignResponse::Err { code, msg }
     }
 }
 
@@ -154,6 +176,9 @@ impl SignResponse {
 pub enum SignNodeRequest {
     ClaimOidc(ClaimOidcNodeRequest),
     SignShare(SignShareNodeRequest),
+    RecoverAccount(RecoverAccountNodeRequest),
+    DelegateAction(DelegateActionNodeRequest),
+    DeleteIdentity(DeleteIdentityNodeRequest),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -165,6 +190,53 @@ pub struct SignShareNodeRequest {
     pub frp_public_key: near_crypto::PublicKey,
 }
 
+/// Signing of the delegate action built by the leader to recover an account. Unlike
+/// `SignShare`, the user signs off on the account, the new key and the keys to delete instead of
+/// the delegate action itself, since the nonce is only known to the leader.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecoverAccountNodeRequest {
+    pub oidc_token: OidcToken,
+    pub delegate_action: DelegateAction,
+    pub new_public_key: near_crypto::PublicKey,
+    pub keys_to_delete: Vec<near_crypto::PublicKey>,
+    #[serde(with = "hex_signature")]
+    pub frp_signature: Signature,
+    pub frp_public_key: near_crypto::PublicKey,
+}
+
+/// Signing of a delegate action whose nonce and expiry are filled in by the leader. The user
+/// signs off on the sender, receiver and actions of the delegate action only.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DelegateActionNodeRequest {
+    pub oidc_token: OidcToken,
+    pub delegate_action: DelegateAction,
+    #[serde(with = "hex_signature")]
+    pub frp_signature: Signature,
+    pub frp_public_key: near_crypto::PublicKey,
+}
+
+/// Signing of the delegate action built by the leader to delete the recovery key of an identity
+/// from an account. The user signs off on the account only, like for `/delete_identity`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeleteIdentityNodeRequest {
+    pub oidc_token: OidcToken,
+    pub delegate_action: DelegateAction,
+    #[serde(with = "hex_signature")]
+    pub frp_signature: Signature,
+    pub frp_public_key: near_crypto::PublicKey,
+}
+
+/// Deletion of the user credentials of an identity from a sign node, once it is unlinked from
+/// `near_account_id`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeleteUserCredentialsNodeRequest {
+    pub near_account_id: AccountId,
+    pub oidc_token: OidcToken,
+    #[serde(with = "hex_signature")]
+    pub frp_signature: Signature,
+    pub frp_public_key: near_crypto::PublicKey,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ClaimOidcNodeRequest {
     #[serde(with = "hex::serde")]
@@ -181,6 +253,206 @@ pub struct PublicKeyNodeRequest {
     pub frp_public_key: near_crypto::PublicKey,
 }
 
+/// An OIDC identity that can be used to recover a NEAR account.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub internal_account_id: InternalAccountId,
+    pub recovery_public_key: near_crypto::PublicKey,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AddIdentityRequest {
+    pub near_account_id: AccountId,
+    pub oidc_token: OidcToken,
+    #[serde(with = "hex_signature")]
+    pub user_credentials_frp_signature: Signature,
+    pub frp_public_key: near_crypto::PublicKey,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ListIdentitiesRequest {
+    pub near_account_id: AccountId,
+    pub oidc_token: OidcToken,
+    #[serde(with = "hex_signature")]
+    pub user_credentials_frp_signature: Signature,
+    pub frp_public_key: near_crypto::PublicKey,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum IdentitiesResponse {
+    Ok { identities: Vec<Identity> },
+    Err { code: ErrorCode, msg: String },
+}
+
+impl IdentitiesResponse {
+    pub fn err(code: ErrorCode, msg: String) -> Self {
+        IdentitiesResponse::Err { code, msg }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RemoveIdentityRequest {
+    pub near_account_id: AccountId,
+    pub oidc_token: OidcToken,
+    /// Signature of the `delegate_action_request_digest` of the account deleting the recovery
+    /// key of the identity to remove from itself.
+    #[serde(with = "hex_signature")]
+    pub frp_signature: Signature,
+    #[serde(with = "hex_signature")]
+    pub user_credentials_frp_signature: Signature,
+    pub frp_public_key: near_crypto::PublicKey,
+    /// The identity to remove, in the same `iss:sub` format as returned by `/list_identities`.
+    pub identity: InternalAccountId,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum RemoveIdentityResponse {
+    Ok { removed: Identity },
+    Err { code: ErrorCode, msg: String },
+}
+
+impl RemoveIdentityResponse {
+    pub fn err(code: ErrorCode, msg: String) -> Self {
+        RemoveIdentityResponse::Err { code, msg }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeleteIdentityRequest {
+    pub near_account_id: AccountId,
+    pub oidc_token: OidcToken,
+    #[serde(with = "hex_signature")]
+    pub frp_signature: Signature,
+    #[serde(with = "hex_signature")]
+    pub user_credentials_frp_signature: Signature,
+    pub frp_public_key: near_crypto::PublicKey,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteIdentityResponse {
+    Ok {
+        near_account_id: AccountId,
+        /// The recovery key of the identity that got deleted from the account, if it was still
+        /// on the account.
+        deleted_key: Option<near_crypto::PublicKey>,
+    },
+    Err {
+        code: ErrorCode,
+        msg: String,
+    },
+}
+
+impl DeleteIdentityResponse {
+    pub fn err(code: ErrorCode, msg: String) -> Self {
+        DeleteIdentityResponse::Err { code, msg }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecoverAccountRequest {
+    pub near_account_id: AccountId,
+    /// The key that replaces the limited access keys of the account, added as a full access key.
+    pub new_public_key: near_crypto::PublicKey,
+    /// The limited access keys of the account, which get deleted. The request is rejected when
+    /// the account has different limited access keys by the time it is processed.
+    pub keys_to_delete: Vec<near_crypto::PublicKey>,
+    pub oidc_token: OidcToken,
+    #[serde(with = "hex_signature")]
+    pub frp_signature: Signature,
+    #[serde(with = "hex_signature")]
+    pub user_credentials_frp_signature: Signature,
+    pub frp_public_key: near_crypto::PublicKey,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum RecoverAccountResponse {
+    Ok {
+        near_account_id: AccountId,
+        new_public_key: near_crypto::PublicKey,
+        deleted_keys: Vec<near_crypto::PublicKey>,
+    },
+    /// The request is processed asynchronously, its result can be polled with `/status/{id}`.
+    Pending {
+        request_id: String,
+    },
+    Err {
+        code: ErrorCode,
+        msg: String,
+    },
+}
+
+impl RecoverAccountResponse {
+    pub fn err(code: ErrorCode, msg: String) -> Self {
+        RecoverAccountResponse::Err { code, msg }
+    }
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DelegateActionRequest {
+    pub near_account_id: AccountId,
+    pub receiver_id: AccountId,
+    /// Base64 encoded borsh serialization of the actions, which can not be delegate actions.
+    #[serde_as(as = "Base64")]
+    pub actions: Vec<u8>,
+    pub oidc_token: OidcToken,
+    #[serde(with = "hex_signature")]
+    pub frp_signature: Signature,
+    #[serde(with = "hex_signature")]
+    pub user_credentials_frp_signature: Signature,
+    pub frp_public_key: near_crypto::PublicKey,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum DelegateActionResponse {
+    Ok {
+        /// Base64 encoded borsh serialization of the `SignedDelegateAction` that was relayed.
+        #[serde_as(as = "Base64")]
+        signed_delegate_action: Vec<u8>,
+    },
+    /// The request is processed asynchronously, its result can be polled with `/status/{id}`.
+    Pending {
+        request_id: String,
+    },
+    Err {
+        code: ErrorCode,
+        msg: String,
+    },
+}
+
+impl DelegateActionResponse {
+    pub fn err(code: ErrorCode, msg: String) -> Self {
+        DelegateActionResponse::Err { code, msg }
+    }
+}
+
+/// Response of `/status/{id}` while the request is still in progress or can not be found. Once
+/// the request is done, the response of the endpoint it was sent to is returned instead.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum StatusResponse {
+    Pending { request_id: String },
+    Err { code: ErrorCode, msg: String },
+}
+
+impl StatusResponse {
+    pub fn err(code: ErrorCode, msg: String) -> Self {
+        StatusResponse::Err { code, msg }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AcceptNodePublicKeysRequest {
     pub public_keys: Vec<Point<Ed25519>>,
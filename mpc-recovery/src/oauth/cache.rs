@@ -0,0 +1,250 @@
+//! Caches of the OIDC token verification. Verifying a token takes fetching the public keys of
+//! its provider, and every request to the leader gets its token verified again by each of the
+//! sign nodes. Verified tokens are cached by their hash until they expire, and the public keys
+//! of the providers are cached and refreshed in the background, so that requests do not have to
+//! wait on the providers.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+
+use crate::metrics;
+use crate::oauth::IdTokenClaims;
+use crate::sign_node::oidc::{OidcHash, OidcToken};
+
+/// Keys of a provider are not fetched again within this long of the previous fetch, even if a
+/// token is signed with a key that they do not contain.
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Configures the caches of the OIDC token verification.
+#[derive(Debug, Clone, clap::Parser)]
+pub struct Options {
+    /// Seconds between refreshes of the cached public keys of the OIDC providers. Keys are also
+    /// fetched again when a token is signed with a key that is not cached. Setting it to zero
+    /// fetches the keys for every token that is verified.
+    #[clap(
+        long,
+        env("MPC_RECOVERY_OIDC_KEYS_REFRESH_INTERVAL"),
+        default_value = "3600"
+    )]
+    pub oidc_keys_refresh_interval: u64,
+
+    /// Seconds that a verified OIDC token is cached for, which is cut short by the expiry of the
+    /// token. Setting it to zero verifies every token from scratch.
+    #[clap(long, env("MPC_RECOVERY_OIDC_TOKEN_CACHE_TTL"), default_value = "300")]
+    pub oidc_token_cache_ttl: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            oidc_keys_refresh_interval: 3600,
+            oidc_token_cache_ttl: 300,
+        }
+    }
+}
+
+impl Options {
+    pub fn into_str_args(self) -> Vec<String> {
+        vec![
+            "--oidc-keys-refresh-interval".to_string(),
+            self.oidc_keys_refresh_interval.to_string(),
+            "--oidc-token-cache-ttl".to_string(),
+            self.oidc_token_cache_ttl.to_string(),
+        ]
+    }
+}
+
+struct CachedKeys {
+    keys: serde_json::Value,
+    fetched_at: Instant,
+}
+
+struct CachedToken {
+    claims: IdTokenClaims,
+    expires_at: Instant,
+}
+
+pub struct OidcCache {
+    client: reqwest::Client,
+    keys_refresh_interval: Duration,
+    token_ttl: Duration,
+    /// Public keys of the providers, keyed by the URL they are published at.
+    keys: Mutex<HashMap<String, CachedKeys>>,
+    tokens: Mutex<HashMap<OidcHash, CachedToken>>,
+}
+
+impl OidcCache {
+    /// Once this many tokens are cached, the expired ones get dropped.
+    const MAX_TOKENS: usize = 100_000;
+
+    pub fn new(options: &Options, client: reqwest::Client) -> Arc<Self> {
+        let cache = Arc::new(Self {
+            client,
+            keys_refresh_interval: Duration::from_secs(options.oidc_keys_refresh_interval),
+            token_ttl: Duration::from_secs(options.oidc_token_cache_ttl),
+            keys: Mutex::new(HashMap::new()),
+            tokens: Mutex::new(HashMap::new()),
+        });
+        if !cache.keys_refresh_interval.is_zero() {
+            tokio::spawn(refresh_keys(Arc::downgrade(&cache)));
+        }
+        cache
+    }
+
+    /// The claims of `token` if it was verified before and did not expire since.
+    pub fn verified_token(&self, token: &OidcToken) -> Option<IdTokenClaims> {
+        let tokens = self.tokens.lock().unwrap();
+        let cached = tokens
+            .get(&token.digest_hash())
+            .filter(|cached| cached.expires_at > Instant::now());
+        metrics::OIDC_CACHE_COUNT
+            .with_label_values(&["token", if cached.is_some() { "hit" } else { "miss" }])
+            .inc();
+        cached.map(|cached| cached.claims.clone())
+    }
+
+    /// Caches the `claims` of a `token` that was just verified, for no longer than it is valid.
+    pub fn insert_verified_token(&self, token: &OidcToken, claims: &IdTokenClaims) {
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        let valid_for = Duration::from_secs((claims.exp as u64).saturating_sub(now));
+        let ttl = self.token_ttl.min(valid_for);
+        if ttl.is_zero() {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut tokens = self.tokens.lock().unwrap();
+        if tokens.len() >= Self::MAX_TOKENS {
+            tokens.retain(|_, cached| cached.expires_at > now);
+        }
+        tokens.insert(
+            token.digest_hash(),
+            CachedToken {
+                claims: claims.clone(),
+                expires_at: now + ttl,
+            },
+        );
+    }
+
+    /// The public keys published at `url`, fetched unless they are cached. With `refetch` they
+    /// are fetched again anyway, unless that was just done, which is meant for tokens signed with
+    /// a key that the cached ones do not contain.
+    pub async fn keys<T: DeserializeOwned>(&self, url: &str, refetch: bool) -> anyhow::Result<T> {
+        let cached = if self.keys_refresh_interval.is_zero() {
+            None
+        } else {
+            let keys = self.keys.lock().unwrap();
+            keys.get(url)
+                .filter(|cached| !refetch || cached.fetched_at.elapsed() < MIN_REFETCH_INTERVAL)
+                .map(|cached| cached.keys.clone())
+        };
+        metrics::OIDC_CACHE_COUNT
+            .with_label_values(&["keys", if cached.is_some() { "hit" } else { "miss" }])
+            .inc();
+        let keys = match cached {
+            Some(keys) => keys,
+            None => self.fetch_keys(url).await?,
+        };
+        Ok(serde_json::from_value(keys)?)
+    }
+
+    async fn fetch_keys(&self, url: &str) -> anyhow::Result<serde_json::Value> {
+        let keys: serde_json::Value = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if !self.keys_refresh_interval.is_zero() {
+            self.keys.lock().unwrap().insert(
+                url.to_string(),
+                CachedKeys {
+                    keys: keys.clone(),
+                    fetched_at: Instant::now(),
+                },
+            );
+        }
+        Ok(keys)
+    }
+}
+
+/// Refreshes the cached keys of every provider periodically, until the cache is dropped. Keys
+/// that fail to refresh are kept until the next attempt.
+async fn refresh_keys(cache: Weak<OidcCache>) {
+    loop {
+        let Some(interval) = cache.upgrade().map(|cache| cache.keys_refresh_interval) else {
+            return;
+        };
+        tokio::time::sleep(interval).await;
+        let Some(cache) = cache.upgrade() else {
+            return;
+        };
+        let urls = cache
+            .keys
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        for url in urls {
+            if let Err(err) = cache.fetch_keys(&url).await {
+                tracing::warn!(
+                    %url,
+                    "failed to refresh the public keys of an OIDC provider: {err}"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration as ChronoDuration, Utc};
+
+    fn claims(exp: i64) -> IdTokenClaims {
+        IdTokenClaims {
+            iss: "test_issuer".to_string(),
+            sub: "test_subject".to_string(),
+            aud: "test_audience".to_string(),
+            exp: exp as usize,
+            nonce: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verified_tokens_are_cached_until_they_expire() {
+        let cache = OidcCache::new(&Options::default(), reqwest::Client::new());
+        let token = OidcToken::new("token");
+        assert!(cache.verified_token(&token).is_none());
+
+        let valid = claims((Utc::now() + ChronoDuration::hours(1)).timestamp());
+        cache.insert_verified_token(&token, &valid);
+        assert_eq!(cache.verified_token(&token).unwrap().sub, valid.sub);
+        assert!(cache.verified_token(&OidcToken::new("other")).is_none());
+
+        // Tokens that already expired are not cached at all.
+        let expired_token = OidcToken::new("expired");
+        let expired = claims((Utc::now() - ChronoDuration::minutes(1)).timestamp());
+        cache.insert_verified_token(&expired_token, &expired);
+        assert!(cache.verified_token(&expired_token).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_token_cache_disabled() {
+        let options = Options {
+            oidc_token_cache_ttl: 0,
+            ..Default::default()
+        };
+        let cache = OidcCache::new(&options, reqwest::Client::new());
+        let token = OidcToken::new("token");
+        let valid = claims((Utc::now() + ChronoDuration::hours(1)).timestamp());
+        cache.insert_verified_token(&token, &valid);
+        assert!(cache.verified_token(&token).is_none());
+    }
+}
@@ -0,0 +1,598 @@
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey};
+use near_crypto::PublicKey;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub use cache::OidcCache;
+
+use crate::firewall::allowed::{OidcProvider, OidcProviderList};
+use crate::primitives::InternalAccountId;
+use crate::sign_node::oidc::OidcToken;
+
+pub mod cache;
+
+pub const APPLE_ISSUER: &str = "https://appleid.apple.com";
+pub const APPLE_JWKS_URL: &str = "https://appleid.apple.com/auth/keys";
+
+// Specs for ID token verification:
+// Google: https://developers.google.com/identity/openid-connect/openid-connect#validatinganidtoken
+// Firebase: https://firebase.google.com/docs/auth/admin/verify-id-tokens#verify_id_tokens_using_a_third-party_jwt_library
+// Apple: https://developer.apple.com/documentation/sign_in_with_apple/sign_in_with_apple_rest_api/verifying_a_user
+//
+// Tokens of the `jwks_providers` that have a JWKS URL configured are verified with the keys
+// published there, which is how any other OIDC provider can be supported.
+pub async fn verify_oidc_token(
+    token: &OidcToken,
+    oidc_providers: Option<&OidcProviderList>,
+    jwks_providers: &OidcProviderList,
+    cache: &OidcCache,
+    jwt_signature_pk_url: &str,
+) -> anyhow::Result<IdTokenClaims> {
+    // The allowlist may have changed since the token got cached, so it is checked again.
+    if let Some(claims) = cache.verified_token(token) {
+        check_allowed(&claims, oidc_providers)?;
+        return Ok(claims);
+    }
+
+    let claims = verify_uncached_oidc_token(
+        token,
+        oidc_providers,
+        jwks_providers,
+        cache,
+        jwt_signature_pk_url,
+    )
+    .await?;
+    cache.insert_verified_token(token, &claims);
+    Ok(claims)
+}
+
+async fn verify_uncached_oidc_token(
+    token: &OidcToken,
+    oidc_providers: Option<&OidcProviderList>,
+    jwks_providers: &OidcProviderList,
+    cache: &OidcCache,
+    jwt_signature_pk_url: &str,
+) -> anyhow::Result<IdTokenClaims> {
+    let unverified_claims = token.unverified_claims()?;
+    if let Some(provider) = jwks_providers.find(&unverified_claims.iss, &unverified_claims.aud) {
+        if let Some(jwks_url) = &provider.jwks_url {
+            return verify_jwks_token(token, provider, cache, jwks_url).await;
+        }
+    }
+    if unverified_claims.is_apple() {
+        return verify_apple_token(token, oidc_providers, cache, APPLE_JWKS_URL).await;
+    }
+
+    // Firebase publishes its keys by their id, which is all that is needed to see whether they
+    // have to be fetched again, as every key gets tried anyway.
+    let kid = jsonwebtoken::decode_header(token.as_ref())
+        .ok()
+        .and_then(|header| header.kid);
+    let public_keys: HashMap<String, String> = cached_keys(
+        cache,
+        jwt_signature_pk_url,
+        |keys: &HashMap<String, String>| kid.as_ref().map_or(true, |kid| keys.contains_key(kid)),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("failed to get Firebase public key: {e}"))?;
+    tracing::info!("verify_oidc_token firebase public keys: {public_keys:?}");
+
+    let mut last_occured_error =
+        anyhow::anyhow!("Unexpected error. Firebase public keys not found");
+    for public_key in public_keys.into_values() {
+        match validate_jwt(token, public_key.as_bytes(), oidc_providers) {
+            Ok(claims) => {
+                tracing::info!("Access token is valid");
+                return Ok(claims);
+            }
+            Err(e) => {
+                tracing::info!("Access token verification failed: {}", e);
+                last_occured_error = e;
+            }
+        }
+    }
+    Err(last_occured_error)
+}
+
+/// The keys published at `url`, from the cache unless `has_key` does not find the key of the
+/// token in there, in which case the provider may have rotated its keys since they got cached.
+async fn cached_keys<T: DeserializeOwned>(
+    cache: &OidcCache,
+    url: &str,
+    has_key: impl Fn(&T) -> bool,
+) -> anyhow::Result<T> {
+    let keys = cache.keys(url, false).await?;
+    if has_key(&keys) {
+        return Ok(keys);
+    }
+    cache.keys(url, true).await
+}
+
+/// Apple publishes its keys as a JWK set, so unlike Firebase we can pick the key to verify
+/// with by the `kid` in the token header instead of trying all of them.
+async fn verify_apple_token(
+    token: &OidcToken,
+    oidc_providers: Option<&OidcProviderList>,
+    cache: &OidcCache,
+    apple_jwks_url: &str,
+) -> anyhow::Result<IdTokenClaims> {
+    let kid = jsonwebtoken::decode_header(token.as_ref())?
+        .kid
+        .ok_or_else(|| anyhow::anyhow!("Apple ID token is missing the key id"))?;
+    let jwks = cached_keys(cache, apple_jwks_url, |jwks: &AppleJwks| {
+        jwks.keys.iter().any(|jwk| jwk.kid == kid)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("failed to get Apple public keys: {e}"))?;
+    let jwk = jwks
+        .keys
+        .iter()
+        .find(|jwk| jwk.kid == kid)
+        .ok_or_else(|| anyhow::anyhow!("UnknownKeyId: {kid}"))?;
+
+    let decoding_key = DecodingKey::from_rsa_components(&jwk.n, &jwk.e)?;
+    validate_jwt_with_key(token, &decoding_key, oidc_providers, &[Algorithm::RS256])
+}
+
+/// Verifies a token of a provider that publishes its keys as a JWK set, picking the key to
+/// verify with by the `kid` in the token header.
+async fn verify_jwks_token(
+    token: &OidcToken,
+    provider: &OidcProvider,
+    cache: &OidcCache,
+    jwks_url: &str,
+) -> anyhow::Result<IdTokenClaims> {
+    let kid = jsonwebtoken::decode_header(token.as_ref())?
+        .kid
+        .ok_or_else(|| anyhow::anyhow!("ID token is missing the key id"))?;
+    let jwks = cached_keys(cache, jwks_url, |jwks: &JwkSet| jwks.find(&kid).is_some())
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to get public keys of {}: {e}", provider.issuer))?;
+    let jwk = jwks
+        .find(&kid)
+        .ok_or_else(|| anyhow::anyhow!("UnknownKeyId: {kid}"))?;
+
+    let decoding_key = DecodingKey::from_jwk(jwk)?;
+    let mut oidc_providers = OidcProviderList::default();
+    oidc_providers.insert(provider.clone());
+    validate_jwt_with_key(
+        token,
+        &decoding_key,
+        Some(&oidc_providers),
+        provider.allowed_algorithms(),
+    )
+}
+
+/// This function validates JWT (OIDC ID token) by checking the signature received
+/// from the issuer, issuer, audience, and expiration time.
+fn validate_jwt(
+    token: &OidcToken,
+    public_key: &[u8],
+    oidc_providers: Option<&OidcProviderList>,
+) -> anyhow::Result<IdTokenClaims> {
+    tracing::info!(
+        oidc_token = format!("{:.5}...", token),
+        public_key = String::from_utf8(public_key.to_vec()).unwrap_or_default(),
+        "validate_jwt call"
+    );
+
+    let decoding_key = DecodingKey::from_rsa_pem(public_key)?;
+    validate_jwt_with_key(token, &decoding_key, oidc_providers, &[Algorithm::RS256])
+}
+
+fn validate_jwt_with_key(
+    token: &OidcToken,
+    decoding_key: &DecodingKey,
+    oidc_providers: Option<&OidcProviderList>,
+    algorithms: &[Algorithm],
+) -> anyhow::Result<IdTokenClaims> {
+    let (header, claims, _sig) = token.decode(decoding_key)?;
+    check_allowed(&claims, oidc_providers)?;
+
+    tracing::info!(
+        issuer = claims.iss,
+        audience = claims.aud,
+        "validate_jwt call decoded"
+    );
+
+    if !algorithms.contains(&header.alg) {
+        anyhow::bail!("InvalidAlgorithm: {:?}", header.alg);
+    }
+
+    tracing::info!(
+        claims = format!("{:?}", claims),
+        "validate_jwt call successful"
+    );
+
+    Ok(claims)
+}
+
+fn check_allowed(
+    claims: &IdTokenClaims,
+    oidc_providers: Option<&OidcProviderList>,
+) -> anyhow::Result<()> {
+    let IdTokenClaims {
+        iss: issuer,
+        aud: audience,
+        ..
+    } = claims;
+
+    // If no OIDC providers are specified in the allowlist, we allow any issuer and audience.
+    // Should be used in signing nodes only.
+    if let Some(oidc_providers) = oidc_providers {
+        if !oidc_providers.contains(issuer, audience) {
+            anyhow::bail!("UnauthorizedTokenIssuerOrAudience: iss={issuer}, aud={audience}");
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub exp: usize,
+    /// Set by providers that echo back the nonce passed in the authorization request, which
+    /// Apple always does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+}
+
+impl IdTokenClaims {
+    pub fn get_internal_account_id(&self) -> InternalAccountId {
+        format!("{}:{}", self.iss, self.sub)
+    }
+
+    pub fn is_apple(&self) -> bool {
+        self.iss == APPLE_ISSUER
+    }
+}
+
+/// Apple ID tokens have to be requested with a nonce, and FastAuth expects that nonce to be the
+/// hex encoded SHA-256 of the FRP public key. This ties the token to the key that is using it,
+/// so that a leaked token can not be replayed with another key. Tokens from other providers are
+/// not checked.
+pub fn verify_oidc_nonce(claims: &IdTokenClaims, frp_public_key: &PublicKey) -> anyhow::Result<()> {
+    if !claims.is_apple() {
+        return Ok(());
+    }
+
+    let expected = expected_nonce(frp_public_key);
+    match &claims.nonce {
+        Some(nonce) if *nonce == expected => Ok(()),
+        Some(nonce) => anyhow::bail!("InvalidNonce: expected={expected}, got={nonce}"),
+        None => anyhow::bail!("MissingNonce: Apple ID token must contain a nonce"),
+    }
+}
+
+pub fn expected_nonce(frp_public_key: &PublicKey) -> String {
+    let hasher = sha2::Digest::chain(sha2::Sha256::default(), frp_public_key.to_string());
+    hex::encode(sha2::Digest::finalize(hasher))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppleJwk {
+    pub kid: String,
+    pub n: String,
+    pub e: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppleJwks {
+    pub keys: Vec<AppleJwk>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use rand::rngs::OsRng;
+    use rsa::{
+        pkcs1::{EncodeRsaPrivateKey, EncodeRsaPublicKey},
+        RsaPrivateKey, RsaPublicKey,
+    };
+
+    #[tokio::test]
+    async fn test_get_pagoda_firebase_public_key() {
+        let url =
+        "https://www.googleapis.com/robot/v1/metadata/x509/securetoken@system.gserviceaccount.com";
+        let cache = OidcCache::new(&cache::Options::default(), reqwest::Client::new());
+        let pk: HashMap<String, String> = cache.keys(url, false).await.unwrap();
+        assert!(!pk.is_empty());
+    }
+
+    #[test]
+    fn test_validate_jwt() {
+        let (private_key_der, public_key_der): (Vec<u8>, Vec<u8>) = get_rsa_pem_key_pair();
+
+        let my_claims = IdTokenClaims {
+            iss: "test_issuer".to_string(),
+            sub: "test_subject".to_string(),
+            aud: "test_audience".to_string(),
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            nonce: None,
+        };
+        let oidc_providers = allowlist_from_claims(&my_claims);
+
+        let token = match encode(
+            &Header::new(Algorithm::RS256),
+            &my_claims,
+            &EncodingKey::from_rsa_pem(&private_key_der).unwrap(),
+        ) {
+            Ok(t) => OidcToken::new(t.as_str()),
+            Err(e) => panic!("Failed to encode token: {}", e),
+        };
+
+        // Valid token and claims
+        validate_jwt(&token, &public_key_der, Some(&oidc_providers)).unwrap();
+
+        // Invalid public key
+        let (invalid_public_key, _invalid_private_key) = get_rsa_pem_key_pair();
+        match validate_jwt(&token, &invalid_public_key, Some(&oidc_providers)) {
+            Ok(_) => panic!("Token validation should fail"),
+            Err(e) => assert_eq!(e.to_string(), "InvalidSignature"),
+        }
+
+        // Invalid issuer or audience
+        let new_claims = IdTokenClaims {
+            iss: "unauthorized_issuer".to_string(),
+            sub: "unauthorized_subject".to_string(),
+            aud: "unauthorized_audience".to_string(),
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            nonce: None,
+        };
+        let token = match encode(
+            &Header::new(Algorithm::RS256),
+            &new_claims,
+            &EncodingKey::from_rsa_pem(&private_key_der).unwrap(),
+        ) {
+            Ok(t) => OidcToken::new(t.as_str()),
+            Err(e) => panic!("Failed to encode token: {}", e),
+        };
+        match validate_jwt(&token, &public_key_der, Some(&oidc_providers)) {
+            Ok(_) => panic!("Token validation should fail on invalid issuer or audience"),
+            Err(e) => assert_eq!(e.to_string(), "UnauthorizedTokenIssuerOrAudience: iss=unauthorized_issuer, aud=unauthorized_audience", "{:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_validate_jwt_without_oidc() {
+        let (private_key_der, public_key_der): (Vec<u8>, Vec<u8>) = get_rsa_pem_key_pair();
+
+        let my_claims = IdTokenClaims {
+            iss: "test_issuer".to_string(),
+            sub: "test_subject".to_string(),
+            aud: "test_audience".to_string(),
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            nonce: None,
+        };
+
+        let token = match encode(
+            &Header::new(Algorithm::RS256),
+            &my_claims,
+            &EncodingKey::from_rsa_pem(&private_key_der).unwrap(),
+        ) {
+            Ok(t) => OidcToken::new(t.as_str()),
+            Err(e) => panic!("Failed to encode token: {}", e),
+        };
+
+        // Valid token and claims
+        match validate_jwt(&token, &public_key_der, None) {
+            Ok(_) => (),
+            Err(e) => panic!("Token validation should succeed: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_validate_apple_jwt_with_jwk() {
+        use rsa::PublicKeyParts;
+
+        let mut rng = OsRng;
+        let private_key = RsaPrivateKey::new(&mut rng, 2048).unwrap();
+        let public_key = RsaPublicKey::from(&private_key);
+        let jwk = AppleJwk {
+            kid: "test_kid".to_string(),
+            n: b64_url(&public_key.n().to_bytes_be()),
+            e: b64_url(&public_key.e().to_bytes_be()),
+        };
+        let private_key_pem = private_key
+            .to_pkcs1_pem(rsa::pkcs8::LineEnding::LF)
+            .unwrap();
+
+        let frp_public_key: PublicKey = "ed25519:J75xXmF7WUPS3xCm3hy2tgwLCKdYM1iJd4BWF8sWVnae"
+            .parse()
+            .unwrap();
+        let my_claims = IdTokenClaims {
+            iss: APPLE_ISSUER.to_string(),
+            sub: "001234.abcdef.1234".to_string(),
+            aud: "org.near.fastauth".to_string(),
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            nonce: Some(expected_nonce(&frp_public_key)),
+        };
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(jwk.kid.clone());
+        let token = OidcToken::new(
+            &encode(
+                &header,
+                &my_claims,
+                &EncodingKey::from_rsa_pem(private_key_pem.as_bytes()).unwrap(),
+            )
+            .unwrap(),
+        );
+        assert!(token.unverified_claims().unwrap().is_apple());
+
+        let decoding_key = DecodingKey::from_rsa_components(&jwk.n, &jwk.e).unwrap();
+        let oidc_providers = allowlist_from_claims(&my_claims);
+        let claims = validate_jwt_with_key(
+            &token,
+            &decoding_key,
+            Some(&oidc_providers),
+            &[Algorithm::RS256],
+        )
+        .unwrap();
+        verify_oidc_nonce(&claims, &frp_public_key).unwrap();
+
+        // Token meant for a different client id
+        let mut other_audience = OidcProviderList::default();
+        other_audience.insert(OidcProvider {
+            issuer: APPLE_ISSUER.to_string(),
+            audience: "org.near.other".to_string(),
+            jwks_url: None,
+            algorithms: Vec::new(),
+        });
+        assert!(validate_jwt_with_key(
+            &token,
+            &decoding_key,
+            Some(&other_audience),
+            &[Algorithm::RS256]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_validate_jwt_with_jwks() {
+        use rsa::PublicKeyParts;
+
+        let private_key = RsaPrivateKey::new(&mut OsRng, 2048).unwrap();
+        let public_key = RsaPublicKey::from(&private_key);
+        let jwks: JwkSet = serde_json::from_value(serde_json::json!({
+            "keys": [{
+                "kty": "RSA",
+                "kid": "test_kid",
+                "alg": "RS384",
+                "use": "sig",
+                "n": b64_url(&public_key.n().to_bytes_be()),
+                "e": b64_url(&public_key.e().to_bytes_be()),
+            }]
+        }))
+        .unwrap();
+        let private_key_pem = private_key
+            .to_pkcs1_pem(rsa::pkcs8::LineEnding::LF)
+            .unwrap();
+        let provider = OidcProvider {
+            issuer: "https://auth.example.com".to_string(),
+            audience: "fastauth".to_string(),
+            jwks_url: Some("https://auth.example.com/.well-known/jwks.json".to_string()),
+            algorithms: vec![Algorithm::RS384],
+        };
+        let mut oidc_providers = OidcProviderList::default();
+        oidc_providers.insert(provider.clone());
+
+        let claims = IdTokenClaims {
+            iss: provider.issuer.clone(),
+            sub: "test_subject".to_string(),
+            aud: provider.audience.clone(),
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            nonce: None,
+        };
+        let sign = |alg| {
+            let mut header = Header::new(alg);
+            header.kid = Some("test_kid".to_string());
+            OidcToken::new(
+                &encode(
+                    &header,
+                    &claims,
+                    &EncodingKey::from_rsa_pem(private_key_pem.as_bytes()).unwrap(),
+                )
+                .unwrap(),
+            )
+        };
+
+        let decoding_key = DecodingKey::from_jwk(jwks.find("test_kid").unwrap()).unwrap();
+        let token = sign(Algorithm::RS384);
+        validate_jwt_with_key(
+            &token,
+            &decoding_key,
+            Some(&oidc_providers),
+            provider.allowed_algorithms(),
+        )
+        .unwrap();
+
+        // Only the algorithms configured for the provider are accepted.
+        let token = sign(Algorithm::RS256);
+        match validate_jwt_with_key(
+            &token,
+            &decoding_key,
+            Some(&oidc_providers),
+            provider.allowed_algorithms(),
+        ) {
+            Ok(_) => panic!("Token validation should fail on a disallowed algorithm"),
+            Err(e) => assert_eq!(e.to_string(), "InvalidAlgorithm: RS256"),
+        }
+    }
+
+    #[test]
+    fn test_verify_oidc_nonce() {
+        let frp_public_key: PublicKey = "ed25519:J75xXmF7WUPS3xCm3hy2tgwLCKdYM1iJd4BWF8sWVnae"
+            .parse()
+            .unwrap();
+        let other_public_key: PublicKey = "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+            .parse()
+            .unwrap();
+        let mut claims = IdTokenClaims {
+            iss: APPLE_ISSUER.to_string(),
+            sub: "test_subject".to_string(),
+            aud: "test_audience".to_string(),
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            nonce: None,
+        };
+
+        match verify_oidc_nonce(&claims, &frp_public_key) {
+            Ok(_) => panic!("Apple token without a nonce should be rejected"),
+            Err(e) => assert!(e.to_string().starts_with("MissingNonce"), "{:?}", e),
+        }
+
+        claims.nonce = Some(expected_nonce(&other_public_key));
+        match verify_oidc_nonce(&claims, &frp_public_key) {
+            Ok(_) => panic!("Apple token with a nonce for another key should be rejected"),
+            Err(e) => assert!(e.to_string().starts_with("InvalidNonce"), "{:?}", e),
+        }
+
+        claims.nonce = Some(expected_nonce(&frp_public_key));
+        verify_oidc_nonce(&claims, &frp_public_key).unwrap();
+
+        // Nonce is not required for other providers
+        claims.iss = "https://securetoken.google.com/test_audience".to_string();
+        claims.nonce = None;
+        verify_oidc_nonce(&claims, &frp_public_key).unwrap();
+    }
+
+    fn b64_url(bytes: &[u8]) -> String {
+        base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, bytes)
+    }
+
+    pub fn get_rsa_pem_key_pair() -> (Vec<u8>, Vec<u8>) {
+        let mut rng = OsRng;
+        let bits: usize = 2048;
+        let private_key = RsaPrivateKey::new(&mut rng, bits).expect("failed to generate a key");
+        let public_key = RsaPublicKey::from(&private_key);
+
+        let private_key_der = private_key
+            .to_pkcs1_pem(rsa::pkcs8::LineEnding::LF)
+            .expect("Failed to encode private key")
+            .as_bytes()
+            .to_vec();
+        let public_key_der = public_key
+            .to_pkcs1_pem(rsa::pkcs8::LineEnding::LF)
+            .expect("Failed to encode public key")
+            .as_bytes()
+            .to_vec();
+
+        (private_key_der, public_key_der)
+    }
+
+    fn allowlist_from_claims(claims: &IdTokenClaims) -> OidcProviderList {
+        let mut oidc_providers = OidcProviderList::default();
+        oidc_providers.insert(OidcProvider {
+            issuer: claims.iss.clone(),
+            audience: claims.aud.clone(),
+            jwks_url: None,
+            algorithms: Vec::new(),
+        });
+        oidc_providers
+    }
+}
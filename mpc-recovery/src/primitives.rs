@@ -6,6 +6,9 @@ pub enum HashSalt {
     ClaimOidcResponse = 1,
     UserCredentialsRequest = 2,
     SignRequest = 3,
+    RecoverAccountRequest = 4,
+    DelegateActionRequest = 5,
+    DeleteIdentityRequest = 6,
 }
 
 // Mentioned in the readme, here to avoid collisions with legitimate transactions
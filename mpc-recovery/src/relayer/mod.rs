@@ -8,12 +8,38 @@ use self::msg::{
 use crate::firewall::allowed::DelegateActionRelayer;
 use anyhow::Context;
 use hyper::{Body, Client, Method, Request};
-use near_crypto::PublicKey;
+use near_crypto::{InMemorySigner, PublicKey};
 use near_jsonrpc_client::errors::{JsonRpcError, JsonRpcServerError};
-use near_jsonrpc_primitives::types::query::RpcQueryError;
+use near_jsonrpc_client::methods;
+use near_jsonrpc_primitives::types::query::{QueryResponseKind, RpcQueryError};
 use near_primitives::hash::CryptoHash;
-use near_primitives::types::{AccountId, BlockHeight, Nonce};
-use near_primitives::views::FinalExecutionStatus;
+use near_primitives::transaction::Action;
+use near_primitives::types::{AccountId, BlockHeight, Finality, Nonce};
+use near_primitives::views::{
+    AccessKeyInfoView, FinalExecutionOutcomeView, FinalExecutionStatus, QueryRequest,
+};
+use std::fmt::Display;
+
+/// How the leader node gets its transactions on chain.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RelayerMode {
+    /// Through the relayer of the FastAuth partner of the request.
+    #[default]
+    Partner,
+    /// Directly through NEAR RPC, paid by the account creator. New accounts get no relayer
+    /// allowance, so this is meant for local setups without a relayer.
+    None,
+}
+
+impl Display for RelayerMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            RelayerMode::Partner => "partner",
+            RelayerMode::None => "none",
+        };
+        write!(f, "{}", str)
+    }
+}
 
 pub struct NearRpcAndRelayerClient {
     rpc_client: near_fetch::Client,
@@ -54,6 +80,38 @@ impl NearRpcAndRelayerClient {
         Ok((hash, height, nonce))
     }
 
+    /// All access keys of `account_id`, as of the latest final block.
+    pub async fn access_keys(
+        &self,
+        account_id: &AccountId,
+    ) -> Result<Vec<AccessKeyInfoView>, RelayerError> {
+        let response = self
+            .rpc_client
+            .query(methods::query::RpcQueryRequest {
+                block_reference: Finality::Final.into(),
+                request: QueryRequest::ViewAccessKeyList {
+                    account_id: account_id.clone(),
+                },
+            })
+            .await
+            .map_err(|e| match e {
+                JsonRpcError::ServerError(JsonRpcServerError::HandlerError(
+                    RpcQueryError::UnknownAccount {
+                        requested_account_id,
+                        ..
+                    },
+                )) => RelayerError::UnknownAccount(requested_account_id),
+                _ => anyhow::anyhow!(e).into(),
+            })?;
+
+        match response.kind {
+            QueryResponseKind::AccessKeyList(list) => Ok(list.keys),
+            kind => Err(RelayerError::DataConversionFailure(anyhow::anyhow!(
+                "unexpected query response: {kind:?}"
+            ))),
+        }
+    }
+
     #[tracing::instrument(level = "debug", skip_all, fields(account_id = request.account_id.to_string()))]
     pub async fn register_account_and_allowance(
         &self,
@@ -196,6 +254,31 @@ impl NearRpcAndRelayerClient {
         }
     }
 
+    /// Signs and sends a transaction with `signer` directly through RPC, without a relayer.
+    #[tracing::instrument(level = "debug", skip_all, fields(receiver_id = receiver_id.to_string()))]
+    pub async fn send_tx(
+        &self,
+        signer: &InMemorySigner,
+        receiver_id: &AccountId,
+        actions: Vec<Action>,
+    ) -> Result<FinalExecutionOutcomeView, RelayerError> {
+        let outcome = self
+            .rpc_client
+            .send_tx(signer, receiver_id, actions)
+            .await
+            .map_err(|e| RelayerError::NetworkFailure(anyhow::anyhow!(e)))?;
+        match outcome.status {
+            FinalExecutionStatus::NotStarted | FinalExecutionStatus::Started => {
+                Err(RelayerError::TxNotReady)
+            }
+            FinalExecutionStatus::Failure(e) => Err(RelayerError::TxExecutionFailure(e)),
+            FinalExecutionStatus::SuccessValue(_) => {
+                tracing::debug!(tx_hash = %outcome.transaction.hash, "success");
+                Ok(outcome)
+            }
+        }
+    }
+
     pub(crate) async fn invalidate_cache_if_acc_creation_failed(
         &self,
         cache_key: &(AccountId, PublicKey),
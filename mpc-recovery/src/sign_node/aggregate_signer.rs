@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 
 use curv::arithmetic::Converter;
@@ -15,26 +14,21 @@ use rand::Rng;
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
+use super::session_store::SessionStore;
 use crate::error::AggregateSigningError;
 use crate::transaction::{to_dalek_public_key, to_dalek_signature};
 
-pub struct SigningState {
-    committed: RwLock<HashMap<AggrCommitment, Committed>>,
-    revealed: RwLock<HashMap<Reveal, Revealed>>,
-}
+/// Round of the signing sessions kept in the store, by the message that starts the next round.
+const COMMITTED: &str = "committed";
+const REVEALED: &str = "revealed";
 
-impl Default for SigningState {
-    fn default() -> Self {
-        Self::new()
-    }
+pub struct SigningState {
+    sessions: SessionStore,
 }
 
 impl SigningState {
-    pub fn new() -> Self {
-        SigningState {
-            committed: RwLock::new(HashMap::new()),
-            revealed: RwLock::new(HashMap::new()),
-        }
+    pub fn new(sessions: SessionStore) -> Self {
+        SigningState { sessions }
     }
 
     pub async fn get_commitment(
@@ -58,10 +52,9 @@ impl SigningState {
         rng: &mut impl Rng,
     ) -> Result<SignedCommitment, AggregateSigningError> {
         let (commitment, state) = Committed::commit(our_key, node_key, message, rng)?;
-        self.committed
-            .write()
-            .await
-            .insert(commitment.commitment.clone(), state);
+        self.sessions
+            .insert(COMMITTED, &commitment.commitment.session_id(), &state)
+            .await?;
         Ok(commitment)
     }
 
@@ -79,18 +72,19 @@ impl SigningState {
             )
         })?;
         // Don't readd this on failure, this commitment is now burnt
-        let state = self
-            .committed
-            .write()
-            .await
-            .remove(&our_c.commitment)
+        let state: Committed = self
+            .sessions
+            .take(COMMITTED, &our_c.commitment.session_id())
+            .await?
             .ok_or_else(|| {
                 AggregateSigningError::CommitmentNotFound(format!("{:?}", our_c.commitment))
             })?;
 
         let (reveal, state) = state.reveal(node_info, recieved_commitments).await?;
         let reveal = Reveal(reveal);
-        self.revealed.write().await.insert(reveal.clone(), state);
+        self.sessions
+            .insert(REVEALED, &reveal.session_id(), &state)
+            .await?;
         Ok(reveal)
     }
 
@@ -105,11 +99,10 @@ impl SigningState {
         })?;
 
         // Don't readd this on failure, this commitment is now burnt
-        let state = self
-            .revealed
-            .write()
-            .await
-            .remove(our_r)
+        let state: Revealed = self
+            .sessions
+            .take(REVEALED, &our_r.session_id())
+            .await?
             .ok_or_else(|| AggregateSigningError::RevealNotFound(format!("{:?}", our_r)))?;
 
         let signature_parts = signature_parts.into_iter().map(|s| s.0).collect();
@@ -121,7 +114,7 @@ impl SigningState {
 /// This represents the signers view of a single signed transaction
 /// We use an minor extention of aggregate signatures to do this.
 /// This extension creates a "node key" in addition to the signing keys which allows the key to verify that the information they recieves actually comes from a signer
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Committed {
     ephemeral_key: aggsig::EphemeralKey,
     our_signature: aggsig::SignSecondMsg,
@@ -133,15 +126,33 @@ pub struct Committed {
 #[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct AggrCommitment(pub BigInt);
 
+impl AggrCommitment {
+    /// Identifies the signing session started with this commitment.
+    fn session_id(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+}
+
 impl Hash for AggrCommitment {
     fn hash<H: Hasher>(&self, hasher: &mut H) {
-        self.0.to_bytes().hash(hasher);
+        self.session_id().hash(hasher);
     }
 }
 
 #[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct Reveal(pub SignSecondMsg);
 
+impl Reveal {
+    /// Identifies the signing session that revealed this. The point has a fixed size, so the
+    /// blind factor that follows it can not be shifted into it.
+    fn session_id(&self) -> Vec<u8> {
+        let SignSecondMsg { R, blind_factor } = &self.0;
+        let mut id = R.to_bytes(false).to_vec();
+        id.extend(blind_factor.to_bytes());
+        id
+    }
+}
+
 impl Hash for Reveal {
     fn hash<H: Hasher>(&self, hasher: &mut H) {
         // TODO fix collision risk
@@ -193,7 +204,7 @@ impl Committed {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Revealed {
     commitments: Vec<AggrCommitment>,
     signing_public_keys: Vec<Point<Ed25519>>,
@@ -354,6 +365,7 @@ mod tests {
     use crate::transaction::from_dalek_signature;
 
     use super::*;
+    use aes_gcm::{Aes256Gcm, KeyInit};
     use curv::elliptic::curves::{Ed25519, Point};
     use ed25519_dalek::{SignatureError, Verifier};
     use multi_party_eddsa::protocols::ExpandedKeyPair;
@@ -373,6 +385,11 @@ mod tests {
         dalek_pub.verify(msg, &dalek_sig)
     }
 
+    fn signing_state(node_id: usize) -> SigningState {
+        let cipher = Aes256Gcm::new(&Aes256Gcm::generate_key(&mut OsRng));
+        SigningState::new(SessionStore::memory(node_id, cipher))
+    }
+
     fn create_rogue_commit(message: &[u8], commitments: &[SignedCommitment]) -> SignedCommitment {
         let ks = || (ExpandedKeyPair::create(), ExpandedKeyPair::create());
         // Also generate a public key for the rogue key
@@ -424,9 +441,9 @@ mod tests {
         let ni = |n| NodeInfo::new(n, Some(nodes_public_keys.clone()));
 
         // Set up nodes with that config
-        let s1 = SigningState::new();
-        let s2 = SigningState::new();
-        let s3 = SigningState::new();
+        let s1 = signing_state(0);
+        let s2 = signing_state(1);
+        let s3 = signing_state(2);
 
         let message = b"message in a bottle".to_vec();
 
@@ -485,9 +502,9 @@ mod tests {
         let ni = |n| NodeInfo::new(n, Some(nodes_public_keys.clone()));
 
         // Set up nodes with that config
-        let s1 = SigningState::new();
-        let s2 = SigningState::new();
-        let s3 = SigningState::new();
+        let s1 = signing_state(0);
+        let s2 = signing_state(1);
+        let s3 = signing_state(2);
 
         let message = b"message in a bottle".to_vec();
 
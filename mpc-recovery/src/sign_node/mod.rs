@@ -1,14 +1,24 @@
 use self::aggregate_signer::{NodeInfo, Reveal, SignedCommitment, SigningState};
-use self::oidc::OidcDigest;
+use self::oidc::{OidcDigest, OidcToken};
 use self::user_credentials::EncryptedUserCredentials;
 use crate::error::{MpcError, SignNodeError};
+use crate::firewall::allowed::OidcProviderList;
 use crate::gcp::GcpService;
-use crate::msg::{AcceptNodePublicKeysRequest, PublicKeyNodeRequest, SignNodeRequest};
-use crate::oauth::verify_oidc_token;
+use crate::msg::{
+    AcceptNodePublicKeysRequest, DeleteUserCredentialsNodeRequest, PublicKeyNodeRequest,
+    SignNodeRequest,
+};
+use crate::oauth::{self, verify_oidc_token, OidcCache};
 use crate::primitives::InternalAccountId;
 use crate::sign_node::pk_set::SignerNodePkSet;
+use crate::sign_node::session_store::SessionStore;
+use crate::transaction::{
+    check_delegate_action, check_delete_identity_delegate_action,
+    check_recover_account_delegate_action,
+};
 use crate::utils::{
     check_digest_signature, claim_oidc_request_digest, claim_oidc_response_digest,
+    delegate_action_request_digest, delete_identity_request_digest, recover_account_request_digest,
     sign_request_digest, user_credentials_request_digest,
 };
 use crate::NodeId;
@@ -17,10 +27,13 @@ use aes_gcm::Aes256Gcm;
 use axum::routing::get;
 use axum::{http::StatusCode, routing::post, Extension, Json, Router};
 use axum_extra::extract::WithRejection;
+use axum_tracing_opentelemetry::middleware::OtelAxumLayer;
 use borsh::BorshSerialize;
 use curv::elliptic::curves::{Ed25519, Point};
 use multi_party_eddsa::protocols::{self, ExpandedKeyPair};
 
+use near_crypto::PublicKey;
+use near_primitives::delegate_action::DelegateAction;
 use near_primitives::hash::hash;
 use near_primitives::signable_message::{SignableMessage, SignableMessageType};
 
@@ -31,6 +44,7 @@ pub mod aggregate_signer;
 pub mod migration;
 pub mod oidc;
 pub mod pk_set;
+pub mod session_store;
 pub mod user_credentials;
 
 pub struct Config {
@@ -40,6 +54,10 @@ pub struct Config {
     pub cipher: Aes256Gcm,
     pub port: u16,
     pub jwt_signature_pk_url: String,
+    /// Providers whose tokens are verified with the keys at their JWKS URL.
+    pub oidc_providers: OidcProviderList,
+    pub session_options: session_store::Options,
+    pub oidc_cache_options: oauth::cache::Options,
 }
 
 pub async fn run(config: Config) {
@@ -51,8 +69,13 @@ pub async fn run(config: Config) {
         cipher,
         port,
         jwt_signature_pk_url,
+        oidc_providers,
+        session_options,
+        oidc_cache_options,
     } = config;
     let our_index = usize::try_from(our_index).expect("This index is way to big");
+    let sessions = SessionStore::new(&session_options, our_index, cipher.clone())
+        .expect("failed to set up the store of signing sessions");
 
     let pk_set = gcp_service
         .get::<_, SignerNodePkSet>(format!("{}/{}", our_index, pk_set::MAIN_KEY))
@@ -61,12 +84,13 @@ pub async fn run(config: Config) {
 
     let state = Arc::new(SignNodeState {
         gcp_service,
-        reqwest_client: reqwest::Client::new(),
+        oidc_cache: OidcCache::new(&oidc_cache_options, reqwest::Client::new()),
         node_key,
         cipher,
-        signing_state: SigningState::new(),
+        signing_state: SigningState::new(sessions),
         node_info: NodeInfo::new(our_index, pk_set.map(|set| set.public_keys)),
         jwt_signature_pk_url,
+        oidc_providers,
     });
 
     let app = Router::new()
@@ -78,13 +102,18 @@ pub async fn run(config: Config) {
                 StatusCode::OK
             }),
         )
+        .route("/healthz", get(|| async move { StatusCode::OK }))
+        .route("/readyz", get(readyz))
         .route("/commit", post(commit))
         .route("/reveal", post(reveal))
         .route("/signature_share", post(signature_share))
         .route("/public_key", post(public_key))
         .route("/public_key_node", post(public_key_node))
         .route("/accept_pk_set", post(accept_pk_set))
-        .layer(Extension(state));
+        .route("/delete_user_credentials", post(delete_user_credentials))
+        .layer(Extension(state))
+        // Continue the trace of the leader node that sent the request
+        .layer(OtelAxumLayer::default());
 
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     tracing::debug!(?addr, "starting http server");
@@ -96,12 +125,13 @@ pub async fn run(config: Config) {
 
 struct SignNodeState {
     gcp_service: GcpService,
-    reqwest_client: reqwest::Client,
+    oidc_cache: Arc<OidcCache>,
     node_key: ExpandedKeyPair,
     cipher: Aes256Gcm,
     signing_state: SigningState,
     node_info: NodeInfo,
     jwt_signature_pk_url: String,
+    oidc_providers: OidcProviderList,
 }
 
 async fn get_or_generate_user_creds(
@@ -208,20 +238,8 @@ async fn process_commit(
         SignNodeRequest::SignShare(request) => {
             tracing::debug!(?request, "processing sign share request");
 
-            // Check OIDC Token
-            let oidc_token_claims = verify_oidc_token(
-                &request.oidc_token,
-                None,
-                &state.reqwest_client,
-                &state.jwt_signature_pk_url,
-            )
-            .await
-            .map_err(SignNodeError::OidcVerificationFailed)?;
-            tracing::debug!(?oidc_token_claims, "oidc token verified");
-
-            let frp_pk = request.frp_public_key;
-
             // Check request FRP signature
+            let frp_pk = request.frp_public_key;
             let digest =
                 sign_request_digest(&request.delegate_action, &request.oidc_token, &frp_pk)?;
             match check_digest_signature(&frp_pk, &request.frp_signature, &digest) {
@@ -229,72 +247,193 @@ async fn process_commit(
                 Err(e) => return Err(SignNodeError::DigestSignatureVerificationFailed(e)),
             };
 
-            // Check if this OIDC token was claimed
-            let oidc_hash = request.oidc_token.digest_hash();
+            commit_to_delegate_action(
+                &state,
+                &request.oidc_token,
+                frp_pk,
+                &request.delegate_action,
+            )
+            .await
+        }
+        SignNodeRequest::RecoverAccount(request) => {
+            tracing::debug!(?request, "processing recover account request");
 
-            let oidc_digest = OidcDigest {
-                node_id: state.node_info.our_index,
-                digest: oidc_hash,
-                public_key: frp_pk,
+            // Check request FRP signature, which covers the account, its new key and the keys
+            // to delete
+            let frp_pk = request.frp_public_key;
+            let digest = recover_account_request_digest(
+                &request.delegate_action.sender_id,
+                &request.new_public_key,
+                &request.keys_to_delete,
+                &request.oidc_token,
+                &frp_pk,
+            )?;
+            match check_digest_signature(&frp_pk, &request.frp_signature, &digest) {
+                Ok(()) => tracing::debug!("recover account digest signature verified"),
+                Err(e) => return Err(SignNodeError::DigestSignatureVerificationFailed(e)),
             };
 
-            match state
-                .gcp_service
-                .get::<_, OidcDigest>(oidc_digest.to_name())
-                .await
-            {
-                Ok(Some(stored_digest)) => {
-                    if stored_digest == oidc_digest {
-                        tracing::info!(?oidc_digest, "oidc token was claimed with provided pk");
-                    } else {
-                        tracing::error!(?oidc_digest, "oidc token was claimed with another key");
-                        return Err(SignNodeError::OidcTokenClaimedWithAnotherKey(oidc_digest));
-                    }
-                }
-                Ok(None) => {
-                    tracing::info!(?oidc_digest, "oidc token was not claimed");
-                    return Err(SignNodeError::OidcTokenNotClaimed(oidc_digest));
-                }
-                Err(e) => {
-                    tracing::error!(
-                        ?oidc_digest,
-                        "failed to get oidc token digest from the database"
-                    );
-                    return Err(SignNodeError::Other(e));
-                }
+            // The delegate action is built by the leader, so make sure it does nothing more
+            // than what the user signed off on
+            check_recover_account_delegate_action(
+                &request.delegate_action,
+                &request.new_public_key,
+                &request.keys_to_delete,
+            )
+            .map_err(SignNodeError::InvalidAccountRecovery)?;
+
+            commit_to_delegate_action(
+                &state,
+                &request.oidc_token,
+                frp_pk,
+                &request.delegate_action,
+            )
+            .await
+        }
+        SignNodeRequest::DelegateAction(request) => {
+            tracing::debug!(?request, "processing delegate action request");
+
+            // Check request FRP signature, which covers the actions but not the nonce and
+            // expiry of the delegate action that only the leader knows
+            let frp_pk = request.frp_public_key;
+            let delegate_action = &request.delegate_action;
+            let digest = delegate_action_request_digest(
+                &delegate_action.sender_id,
+                &delegate_action.receiver_id,
+                &delegate_action.actions,
+                &request.oidc_token,
+                &frp_pk,
+            )?;
+            match check_digest_signature(&frp_pk, &request.frp_signature, &digest) {
+                Ok(()) => tracing::debug!("delegate action digest signature verified"),
+                Err(e) => return Err(SignNodeError::DigestSignatureVerificationFailed(e)),
             };
 
-            // Get user credentials
-            let internal_account_id = oidc_token_claims.get_internal_account_id();
-            let user_credentials = get_or_generate_user_creds(&state, internal_account_id).await?;
-            tracing::debug!("user credentials retrieved");
+            check_delegate_action(delegate_action).map_err(SignNodeError::InvalidDelegateAction)?;
 
-            // Get commitment
-            let signable_message = SignableMessage::new(
-                &request.delegate_action,
-                SignableMessageType::DelegateAction,
-            );
-            let bytes = match signable_message.try_to_vec() {
-                Ok(bytes) => bytes,
-                Err(e) => return Err(SignNodeError::Other(e.into())),
+            commit_to_delegate_action(&state, &request.oidc_token, frp_pk, delegate_action).await
+        }
+        SignNodeRequest::DeleteIdentity(request) => {
+            tracing::debug!(?request, "processing delete identity request");
+
+            // Check request FRP signature, which covers the account only, since the delegate
+            // action is built by the leader
+            let frp_pk = request.frp_public_key;
+            let digest = delete_identity_request_digest(
+                &request.delegate_action.sender_id,
+                &request.oidc_token,
+                &frp_pk,
+            )?;
+            match check_digest_signature(&frp_pk, &request.frp_signature, &digest) {
+                Ok(()) => tracing::debug!("delete identity digest signature verified"),
+                Err(e) => return Err(SignNodeError::DigestSignatureVerificationFailed(e)),
             };
-            let hash = hash(&bytes).as_bytes().to_vec();
 
-            let response = state
-                .signing_state
-                .get_commitment(
-                    &user_credentials.decrypt_key_pair(&state.cipher)?,
-                    &state.node_key,
-                    hash,
-                )
-                .await
-                .map_err(|e| anyhow::anyhow!(e))?;
-            tracing::info!("returning signed commitment");
-            Ok(response)
+            check_delete_identity_delegate_action(&request.delegate_action)
+                .map_err(SignNodeError::InvalidDelegateAction)?;
+
+            commit_to_delegate_action(
+                &state,
+                &request.oidc_token,
+                frp_pk,
+                &request.delegate_action,
+            )
+            .await
         }
     }
 }
 
+/// Checks that `oidc_token` was claimed with `frp_pk` on this node.
+async fn check_oidc_token_claimed(
+    state: &SignNodeState,
+    oidc_token: &OidcToken,
+    frp_pk: PublicKey,
+) -> Result<(), SignNodeError> {
+    let oidc_hash = oidc_token.digest_hash();
+
+    let oidc_digest = OidcDigest {
+        node_id: state.node_info.our_index,
+        digest: oidc_hash,
+        public_key: frp_pk,
+    };
+
+    match state
+        .gcp_service
+        .get::<_, OidcDigest>(oidc_digest.to_name())
+        .await
+    {
+        Ok(Some(stored_digest)) => {
+            if stored_digest == oidc_digest {
+                tracing::info!(?oidc_digest, "oidc token was claimed with provided pk");
+            } else {
+                tracing::error!(?oidc_digest, "oidc token was claimed with another key");
+                return Err(SignNodeError::OidcTokenClaimedWithAnotherKey(oidc_digest));
+            }
+        }
+        Ok(None) => {
+            tracing::info!(?oidc_digest, "oidc token was not claimed");
+            return Err(SignNodeError::OidcTokenNotClaimed(oidc_digest));
+        }
+        Err(e) => {
+            tracing::error!(
+                ?oidc_digest,
+                "failed to get oidc token digest from the database"
+            );
+            return Err(SignNodeError::Other(e));
+        }
+    };
+    Ok(())
+}
+
+/// Commits to signing `delegate_action` with the user credentials of the claimed `oidc_token`.
+async fn commit_to_delegate_action(
+    state: &SignNodeState,
+    oidc_token: &OidcToken,
+    frp_pk: PublicKey,
+    delegate_action: &DelegateAction,
+) -> Result<SignedCommitment, SignNodeError> {
+    // Check OIDC Token
+    let oidc_token_claims = verify_oidc_token(
+        oidc_token,
+        None,
+        &state.oidc_providers,
+        &state.oidc_cache,
+        &state.jwt_signature_pk_url,
+    )
+    .await
+    .map_err(SignNodeError::OidcVerificationFailed)?;
+    tracing::debug!(?oidc_token_claims, "oidc token verified");
+
+    // Check if this OIDC token was claimed
+    check_oidc_token_claimed(state, oidc_token, frp_pk).await?;
+
+    // Get user credentials
+    let internal_account_id = oidc_token_claims.get_internal_account_id();
+    let user_credentials = get_or_generate_user_creds(state, internal_account_id).await?;
+    tracing::debug!("user credentials retrieved");
+
+    // Get commitment
+    let signable_message =
+        SignableMessage::new(delegate_action, SignableMessageType::DelegateAction);
+    let bytes = match signable_message.try_to_vec() {
+        Ok(bytes) => bytes,
+        Err(e) => return Err(SignNodeError::Other(e.into())),
+    };
+    let hash = hash(&bytes).as_bytes().to_vec();
+
+    let response = state
+        .signing_state
+        .get_commitment(
+            &user_credentials.decrypt_key_pair(&state.cipher)?,
+            &state.node_key,
+            hash,
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    tracing::info!("returning signed commitment");
+    Ok(response)
+}
+
 #[tracing::instrument(level = "debug", skip_all, fields(id = state.node_info.our_index))]
 async fn commit(
     Extension(state): Extension<Arc<SignNodeState>>,
@@ -368,7 +507,8 @@ async fn process_public_key(
     let oidc_token_claims = verify_oidc_token(
         &request.oidc_token,
         None,
-        &state.reqwest_client,
+        &state.oidc_providers,
+        &state.oidc_cache,
         &state.jwt_signature_pk_url,
     )
     .await
@@ -383,39 +523,7 @@ async fn process_public_key(
     };
 
     // Check if this OIDC token was claimed
-    let oidc_hash = request.oidc_token.digest_hash();
-
-    let oidc_digest = OidcDigest {
-        node_id: state.node_info.our_index,
-        digest: oidc_hash,
-        public_key: frp_pk,
-    };
-
-    match state
-        .gcp_service
-        .get::<_, OidcDigest>(oidc_digest.to_name())
-        .await
-    {
-        Ok(Some(stored_digest)) => {
-            if stored_digest == oidc_digest {
-                tracing::info!(?oidc_digest, "oidc token was claimed with provided pk");
-            } else {
-                tracing::error!(?oidc_digest, "oidc token was claimed with another key");
-                return Err(SignNodeError::OidcTokenClaimedWithAnotherKey(oidc_digest));
-            }
-        }
-        Ok(None) => {
-            tracing::info!(?oidc_digest, "oidc token was not claimed");
-            return Err(SignNodeError::OidcTokenNotClaimed(oidc_digest));
-        }
-        Err(e) => {
-            tracing::error!(
-                ?oidc_digest,
-                "failed to get oidc token digest from the database"
-            );
-            return Err(SignNodeError::Other(e));
-        }
-    };
+    check_oidc_token_claimed(&state, &request.oidc_token, frp_pk).await?;
 
     let internal_acc_id = oidc_token_claims.get_internal_account_id();
     match get_or_generate_user_creds(&state, internal_acc_id).await {
@@ -436,6 +544,62 @@ async fn public_key(
     }
 }
 
+/// Deletes the user credentials of the identity of the OIDC token from this node, so that its
+/// share of the recovery key is gone for good. Using the identity again afterwards generates new
+/// credentials, and with them a new recovery key.
+async fn process_delete_user_credentials(
+    state: Arc<SignNodeState>,
+    request: DeleteUserCredentialsNodeRequest,
+) -> Result<(), SignNodeError> {
+    // Check OIDC Token
+    let oidc_token_claims = verify_oidc_token(
+        &request.oidc_token,
+        None,
+        &state.oidc_providers,
+        &state.oidc_cache,
+        &state.jwt_signature_pk_url,
+    )
+    .await
+    .map_err(SignNodeError::OidcVerificationFailed)?;
+
+    let frp_pk = request.frp_public_key;
+    // Check the request signature
+    let digest =
+        delete_identity_request_digest(&request.near_account_id, &request.oidc_token, &frp_pk)?;
+    match check_digest_signature(&frp_pk, &request.frp_signature, &digest) {
+        Ok(()) => tracing::debug!("delete identity digest signature verified"),
+        Err(e) => return Err(SignNodeError::DigestSignatureVerificationFailed(e)),
+    };
+
+    // Check if this OIDC token was claimed
+    check_oidc_token_claimed(&state, &request.oidc_token, frp_pk).await?;
+
+    let internal_account_id = oidc_token_claims.get_internal_account_id();
+    state
+        .gcp_service
+        .delete::<_, EncryptedUserCredentials>(format!(
+            "{}/{}",
+            state.node_info.our_index, internal_account_id
+        ))
+        .await?;
+    tracing::info!(internal_account_id, "deleted user credentials");
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(id = state.node_info.our_index))]
+async fn delete_user_credentials(
+    Extension(state): Extension<Arc<SignNodeState>>,
+    WithRejection(Json(request), _): WithRejection<
+        Json<DeleteUserCredentialsNodeRequest>,
+        MpcError,
+    >,
+) -> (StatusCode, Json<Result<(), String>>) {
+    match process_delete_user_credentials(state, request).await {
+        Ok(()) => (StatusCode::OK, Json(Ok(()))),
+        Err(e) => (e.code(), Json(Err(e.to_string()))),
+    }
+}
+
 #[allow(clippy::type_complexity)]
 #[tracing::instrument(level = "debug", skip_all, fields(id = state.node_info.our_index))]
 async fn public_key_node(
@@ -505,6 +669,18 @@ async fn accept_pk_set(
     }
 }
 
+/// Whether the sign node can take part in signing, so that orchestrators such as Kubernetes do
+/// not route to it before the leader has handed out the public keys of every node.
+#[tracing::instrument(level = "debug", skip_all, fields(id = state.node_info.our_index))]
+async fn readyz(
+    Extension(state): Extension<Arc<SignNodeState>>,
+) -> (StatusCode, Json<Result<(), String>>) {
+    match check_if_ready(&state).await {
+        Ok(()) => (StatusCode::OK, Json(Ok(()))),
+        Err(msg) => (StatusCode::SERVICE_UNAVAILABLE, Json(Err(msg))),
+    }
+}
+
 /// Validate whether the current state of the sign node is useable or not.
 async fn check_if_ready(state: &SignNodeState) -> Result<(), String> {
     let public_keys = state.node_info.nodes_public_keys.read().await;
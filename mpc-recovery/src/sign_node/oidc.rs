@@ -21,7 +21,9 @@ use crate::{
     oauth::IdTokenClaims,
 };
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
+#[derive(
+    Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, BorshSerialize, BorshDeserialize,
+)]
 pub struct OidcHash([u8; 32]);
 
 impl AsRef<[u8]> for OidcHash {
@@ -98,6 +100,16 @@ impl OidcToken {
 
         Ok((header, claims, signature.into()))
     }
+
+    /// Reads the claims without checking the signature. Only to be used for figuring out how
+    /// the token should be verified, never for trusting its contents.
+    pub fn unverified_claims(&self) -> anyhow::Result<IdTokenClaims> {
+        let mut parts = self.as_ref().splitn(3, '.');
+        let (Some(_header), Some(payload)) = (parts.next(), parts.next()) else {
+            anyhow::bail!("could not split into header and payload for OIDC token");
+        };
+        Ok(serde_json::from_slice(&b64_decode(payload)?)?)
+    }
 }
 
 fn b64_decode<T: AsRef<[u8]>>(input: T) -> anyhow::Result<Vec<u8>> {
@@ -115,6 +127,7 @@ fn create_jwt_token(rsa_pem_key: &str) -> String {
         sub: generate_random_string(7),
         aud,
         exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+        nonce: None,
     };
 
     let token = match encode(
@@ -0,0 +1,246 @@
+//! Storage of the signing sessions of the sign node between the commit, reveal and signature
+//! share rounds. A session holds the nonce of the node along with the key pair of the user, so
+//! it is encrypted with the cipher of the node before being stored. Every session is taken out
+//! of the store when the next round uses it, so that a nonce can never be used twice.
+//!
+//! Sessions are kept in memory by default, which ties them to a single process. With Redis they
+//! survive restarts of the node and can be picked up by any of its replicas.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use aes_gcm::aead::{Aead, AeadCore, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use deadpool_redis::redis::{self, AsyncCommands};
+use deadpool_redis::Pool;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::error::AggregateSigningError;
+
+/// Can be bumped to drop the sessions of a previous release with an incompatible format.
+const SESSION_STORE_VERSION: &str = "v1";
+const NONCE_LEN: usize = 12;
+
+/// Configures where the sign node keeps its signing sessions.
+#[derive(Debug, Clone, clap::Parser)]
+pub struct Options {
+    /// Redis to keep signing sessions in, so that they survive restarts of the sign node and can
+    /// be shared by its replicas. Sessions are only kept in memory when not provided.
+    #[clap(long, env("MPC_RECOVERY_SESSION_REDIS_URL"))]
+    pub session_redis_url: Option<String>,
+
+    /// Seconds that a signing session is kept in Redis without being used by the next round.
+    #[clap(long, env("MPC_RECOVERY_SESSION_TTL"), default_value = "600")]
+    pub session_ttl: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            session_redis_url: None,
+            session_ttl: 600,
+        }
+    }
+}
+
+impl Options {
+    pub fn into_str_args(self) -> Vec<String> {
+        let mut args = vec!["--session-ttl".to_string(), self.session_ttl.to_string()];
+        if let Some(session_redis_url) = self.session_redis_url {
+            args.extend(["--session-redis-url".to_string(), session_redis_url]);
+        }
+        args
+    }
+}
+
+enum Backend {
+    /// Sessions of this process only. They are kept until used, like the sessions in Redis
+    /// without their expiry.
+    Memory(Mutex<HashMap<String, Vec<u8>>>),
+    Redis {
+        pool: Pool,
+        ttl: Duration,
+    },
+}
+
+pub struct SessionStore {
+    backend: Backend,
+    cipher: Aes256Gcm,
+    node_id: usize,
+}
+
+impl SessionStore {
+    pub fn memory(node_id: usize, cipher: Aes256Gcm) -> Self {
+        Self {
+            backend: Backend::Memory(Mutex::new(HashMap::new())),
+            cipher,
+            node_id,
+        }
+    }
+
+    pub fn new(options: &Options, node_id: usize, cipher: Aes256Gcm) -> anyhow::Result<Self> {
+        let Some(redis_url) = &options.session_redis_url else {
+            return Ok(Self::memory(node_id, cipher));
+        };
+        let pool = deadpool_redis::Config::from_url(redis_url)
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))?;
+        tracing::info!(
+            ttl = options.session_ttl,
+            "keeping signing sessions in redis"
+        );
+        Ok(Self {
+            backend: Backend::Redis {
+                pool,
+                ttl: Duration::from_secs(options.session_ttl),
+            },
+            cipher,
+            node_id,
+        })
+    }
+
+    /// Stores the `session` of the round `kind`, to be taken out with the same `id`.
+    pub async fn insert<T: Serialize>(
+        &self,
+        kind: &str,
+        id: &[u8],
+        session: &T,
+    ) -> Result<(), AggregateSigningError> {
+        let key = self.key(kind, id);
+        let session = serde_json::to_vec(session)
+            .map_err(|e| AggregateSigningError::DataConversionFailure(e.into()))?;
+        let encrypted = self.encrypt(&key, &session)?;
+        match &self.backend {
+            Backend::Memory(sessions) => {
+                sessions.lock().await.insert(key, encrypted);
+            }
+            Backend::Redis { pool, ttl } => {
+                let mut connection = pool
+                    .get()
+                    .await
+                    .map_err(|e| AggregateSigningError::SessionStoreFailure(e.into()))?;
+                connection
+                    .set_ex::<_, _, ()>(&key, encrypted, ttl.as_secs())
+                    .await
+                    .map_err(|e| AggregateSigningError::SessionStoreFailure(e.into()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the session of the round `kind` with `id` and returns it, if it is still around.
+    pub async fn take<T: DeserializeOwned>(
+        &self,
+        kind: &str,
+        id: &[u8],
+    ) -> Result<Option<T>, AggregateSigningError> {
+        let key = self.key(kind, id);
+        let encrypted = match &self.backend {
+            Backend::Memory(sessions) => sessions.lock().await.remove(&key),
+            Backend::Redis { pool, .. } => {
+                let mut connection = pool
+                    .get()
+                    .await
+                    .map_err(|e| AggregateSigningError::SessionStoreFailure(e.into()))?;
+                // Replicas race for the same session when the leader retries a request, so it
+                // has to be read and removed atomically. Requires Redis 6.2 or later.
+                let encrypted: Option<Vec<u8>> = redis::cmd("GETDEL")
+                    .arg(&key)
+                    .query_async(&mut connection)
+                    .await
+                    .map_err(|e| AggregateSigningError::SessionStoreFailure(e.into()))?;
+                encrypted
+            }
+        };
+        let Some(encrypted) = encrypted else {
+            return Ok(None);
+        };
+        let session = self.decrypt(&key, &encrypted)?;
+        serde_json::from_slice(&session)
+            .map(Some)
+            .map_err(|e| AggregateSigningError::DataConversionFailure(e.into()))
+    }
+
+    fn key(&self, kind: &str, id: &[u8]) -> String {
+        format!(
+            "session:{SESSION_STORE_VERSION}:{}:{kind}:{}",
+            self.node_id,
+            hex::encode(Sha256::digest(id))
+        )
+    }
+
+    /// Encrypts `session` under a fresh nonce, bound to `key` so that sessions can not be
+    /// swapped for one another in the store.
+    fn encrypt(&self, key: &str, session: &[u8]) -> Result<Vec<u8>, AggregateSigningError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: session,
+                    aad: key.as_bytes(),
+                },
+            )
+            .map_err(|e| AggregateSigningError::SessionStoreFailure(anyhow::anyhow!(e)))?;
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend(ciphertext);
+        Ok(encrypted)
+    }
+
+    fn decrypt(&self, key: &str, encrypted: &[u8]) -> Result<Vec<u8>, AggregateSigningError> {
+        if encrypted.len() < NONCE_LEN {
+            return Err(AggregateSigningError::SessionStoreFailure(anyhow::anyhow!(
+                "stored session is too short"
+            )));
+        }
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: key.as_bytes(),
+                },
+            )
+            .map_err(|e| AggregateSigningError::SessionStoreFailure(anyhow::anyhow!(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes_gcm::KeyInit;
+
+    #[tokio::test]
+    async fn test_sessions_are_taken_once() {
+        let cipher = Aes256Gcm::new(&Aes256Gcm::generate_key(&mut OsRng));
+        let store = SessionStore::memory(0, cipher);
+        store
+            .insert("commit", b"id", &"session".to_string())
+            .await
+            .unwrap();
+
+        // Sessions of another round do not collide, even with the same id.
+        assert_eq!(store.take::<String>("reveal", b"id").await.unwrap(), None);
+        assert_eq!(
+            store.take::<String>("commit", b"id").await.unwrap(),
+            Some("session".to_string())
+        );
+        assert_eq!(store.take::<String>("commit", b"id").await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_sessions_are_bound_to_their_key() {
+        let cipher = Aes256Gcm::new(&Aes256Gcm::generate_key(&mut OsRng));
+        let store = SessionStore::memory(0, cipher);
+        let key = store.key("commit", b"id");
+        let encrypted = store.encrypt(&key, b"session").unwrap();
+        assert_eq!(store.decrypt(&key, &encrypted).unwrap(), b"session");
+
+        let other_key = store.key("commit", b"other id");
+        assert!(store.decrypt(&other_key, &encrypted).is_err());
+    }
+}
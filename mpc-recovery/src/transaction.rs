@@ -15,11 +15,14 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use near_crypto::{InMemorySigner, PublicKey};
+use near_primitives::account::{AccessKey, AccessKeyPermission};
 use near_primitives::delegate_action::{DelegateAction, NonDelegateAction, SignedDelegateAction};
 use near_primitives::signable_message::{SignableMessage, SignableMessageType};
-use near_primitives::transaction::{Action, FunctionCallAction};
+use near_primitives::transaction::{Action, AddKeyAction, DeleteKeyAction, FunctionCallAction};
 use near_primitives::types::{AccountId, Nonce};
 
+use std::collections::HashSet;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CreateAccountOptions {
     pub full_access_keys: Option<Vec<PublicKey>>,
@@ -47,16 +50,12 @@ pub struct LimitedAccessKey {
     pub method_names: String,
 }
 
-#[allow(clippy::too_many_arguments)]
-pub fn new_create_account_delegate_action(
-    signer: &InMemorySigner,
+/// Call of `create_account_advanced` on the linkdrop contract of the root account.
+pub fn new_create_account_action(
     new_account_id: &AccountId,
     new_account_options: &CreateAccountOptions,
-    near_root_account: &AccountId,
-    nonce: Nonce,
-    max_block_height: u64,
-) -> anyhow::Result<SignedDelegateAction> {
-    let create_acc_action = Action::FunctionCall(FunctionCallAction {
+) -> Action {
+    Action::FunctionCall(FunctionCallAction {
         method_name: "create_account_advanced".to_string(),
         args: json!({
             "new_account_id": new_account_id,
@@ -66,7 +65,19 @@ pub fn new_create_account_delegate_action(
         .into_bytes(),
         gas: 300_000_000_000_000,
         deposit: 0,
-    });
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn new_create_account_delegate_action(
+    signer: &InMemorySigner,
+    new_account_id: &AccountId,
+    new_account_options: &CreateAccountOptions,
+    near_root_account: &AccountId,
+    nonce: Nonce,
+    max_block_height: u64,
+) -> anyhow::Result<SignedDelegateAction> {
+    let create_acc_action = new_create_account_action(new_account_id, new_account_options);
 
     let delegate_create_acc_action = NonDelegateAction::try_from(create_acc_action)?;
 
@@ -89,6 +100,143 @@ pub fn new_create_account_delegate_action(
     })
 }
 
+/// Delegate action of `near_account_id` to itself that deletes `old_keys` and adds
+/// `new_public_key` as a full access key. Being a single receipt, either all of the keys are
+/// replaced or none is.
+pub fn new_recover_account_delegate_action(
+    near_account_id: &AccountId,
+    recovery_public_key: &PublicKey,
+    old_keys: &[PublicKey],
+    new_public_key: &PublicKey,
+    nonce: Nonce,
+    max_block_height: u64,
+) -> anyhow::Result<DelegateAction> {
+    let add_key = Action::AddKey(AddKeyAction {
+        public_key: new_public_key.clone(),
+        access_key: AccessKey::full_access(),
+    });
+    let delete_keys = old_keys.iter().map(|public_key| {
+        Action::DeleteKey(DeleteKeyAction {
+            public_key: public_key.clone(),
+        })
+    });
+    let actions = std::iter::once(add_key)
+        .chain(delete_keys)
+        .map(NonDelegateAction::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| anyhow::anyhow!("nested delegate actions are not allowed"))?;
+
+    Ok(DelegateAction {
+        sender_id: near_account_id.clone(),
+        receiver_id: near_account_id.clone(),
+        actions,
+        nonce,
+        max_block_height,
+        public_key: recovery_public_key.clone(),
+    })
+}
+
+/// Checks that `delegate_action` does nothing but add `new_public_key` as a full access key of
+/// its sender and delete exactly `keys_to_delete` from it, excluding the recovery key that signs
+/// it.
+pub fn check_recover_account_delegate_action(
+    delegate_action: &DelegateAction,
+    new_public_key: &PublicKey,
+    keys_to_delete: &[PublicKey],
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        delegate_action.receiver_id == delegate_action.sender_id,
+        "keys can only be replaced on the account itself"
+    );
+    let mut added = false;
+    let mut deleted = HashSet::new();
+    for action in &delegate_action.actions {
+        match Action::from(action.clone()) {
+            Action::AddKey(add_key)
+                if !added
+                    && add_key.public_key == *new_public_key
+                    && add_key.access_key.permission == AccessKeyPermission::FullAccess =>
+            {
+                added = true;
+            }
+            Action::DeleteKey(delete_key)
+                if delete_key.public_key != *new_public_key
+                    && delete_key.public_key != delegate_action.public_key
+                    && keys_to_delete.contains(&delete_key.public_key)
+                    && deleted.insert(delete_key.public_key.clone()) => {}
+            action => anyhow::bail!("unexpected action: {action:?}"),
+        }
+    }
+    anyhow::ensure!(added, "{new_public_key} is not added to the account");
+    anyhow::ensure!(
+        deleted.len() == keys_to_delete.len(),
+        "not all of the keys to delete are deleted"
+    );
+    Ok(())
+}
+
+/// Delegate action that deletes the recovery key of an identity from the account, signed by that
+/// same recovery key.
+pub fn new_delete_identity_delegate_action(
+    near_account_id: &AccountId,
+    recovery_public_key: &PublicKey,
+    nonce: Nonce,
+    max_block_height: u64,
+) -> anyhow::Result<DelegateAction> {
+    let delete_key = Action::DeleteKey(DeleteKeyAction {
+        public_key: recovery_public_key.clone(),
+    });
+    Ok(DelegateAction {
+        sender_id: near_account_id.clone(),
+        receiver_id: near_account_id.clone(),
+        actions: vec![NonDelegateAction::try_from(delete_key)
+            .map_err(|_| anyhow::anyhow!("nested delegate actions are not allowed"))?],
+        nonce,
+        max_block_height,
+        public_key: recovery_public_key.clone(),
+    })
+}
+
+/// Checks that `delegate_action` does nothing but delete the recovery key that signs it from its
+/// sender.
+pub fn check_delete_identity_delegate_action(
+    delegate_action: &DelegateAction,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        delegate_action.receiver_id == delegate_action.sender_id,
+        "keys can only be deleted on the account itself"
+    );
+    match delegate_action
+        .actions
+        .iter()
+        .map(|action| Action::from(action.clone()))
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [Action::DeleteKey(delete_key)] if delete_key.public_key == delegate_action.public_key => {
+            Ok(())
+        }
+        actions => anyhow::bail!("unexpected actions: {actions:?}"),
+    }
+}
+
+/// Checks that `delegate_action` neither deletes its sender nor the recovery key that signs it,
+/// which would leave the account without a way to be recovered.
+pub fn check_delegate_action(delegate_action: &DelegateAction) -> anyhow::Result<()> {
+    for action in &delegate_action.actions {
+        match Action::from(action.clone()) {
+            Action::DeleteAccount(_) => anyhow::bail!("account deletion is not allowed"),
+            Action::DeleteKey(delete_key)
+                if delete_key.public_key == delegate_action.public_key =>
+            {
+                anyhow::bail!("recovery key {} can not be deleted", delete_key.public_key)
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
 pub async fn get_mpc_signature(
     client: &reqwest::Client,
     sign_nodes: &[String],
@@ -147,16 +295,19 @@ pub enum NodeCallError {
 }
 
 /// Call every node with an identical payload and send the response
+#[tracing::instrument(level = "debug", skip_all, fields(path = path))]
 pub async fn call_all_nodes<Req: Serialize, Res: DeserializeOwned>(
     client: &reqwest::Client,
     sign_nodes: &[String],
     path: &str,
     request: Req,
 ) -> Result<Vec<Res>, LeaderNodeError> {
+    let trace_context = crate::logging::trace_context_headers();
     let responses = sign_nodes.iter().map(|sign_node| {
         client
             .post(format!("{}/{}", sign_node, path))
             .header("content-type", "application/json")
+            .headers(trace_context.clone())
             .json(&request)
             .send()
             .then(|r| async move {
@@ -215,3 +366,133 @@ pub fn to_dalek_public_key(
         .context("to dalek key conversion failed")
         .map_err(AggregateSigningError::DataConversionFailure)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, SecretKey};
+    use near_primitives::transaction::DeleteAccountAction;
+
+    fn delegate_action(recovery_public_key: &PublicKey, action: Action) -> DelegateAction {
+        let account_id: AccountId = "alice.near".parse().unwrap();
+        DelegateAction {
+            sender_id: account_id.clone(),
+            receiver_id: account_id,
+            actions: vec![NonDelegateAction::try_from(action).unwrap()],
+            nonce: 1,
+            max_block_height: 100,
+            public_key: recovery_public_key.clone(),
+        }
+    }
+
+    #[test]
+    fn test_check_delegate_action() {
+        let recovery_public_key = SecretKey::from_random(KeyType::ED25519).public_key();
+        let other_public_key = SecretKey::from_random(KeyType::ED25519).public_key();
+
+        let rotate_key = delegate_action(
+            &recovery_public_key,
+            Action::DeleteKey(DeleteKeyAction {
+                public_key: other_public_key,
+            }),
+        );
+        assert!(check_delegate_action(&rotate_key).is_ok());
+
+        let delete_recovery_key = delegate_action(
+            &recovery_public_key,
+            Action::DeleteKey(DeleteKeyAction {
+                public_key: recovery_public_key.clone(),
+            }),
+        );
+        assert!(check_delegate_action(&delete_recovery_key).is_err());
+
+        let delete_account = delegate_action(
+            &recovery_public_key,
+            Action::DeleteAccount(DeleteAccountAction {
+                beneficiary_id: "bob.near".parse().unwrap(),
+            }),
+        );
+        assert!(check_delegate_action(&delete_account).is_err());
+    }
+
+    #[test]
+    fn test_check_recover_account_delegate_action() {
+        let account_id: AccountId = "alice.near".parse().unwrap();
+        let recovery_public_key = SecretKey::from_random(KeyType::ED25519).public_key();
+        let new_public_key = SecretKey::from_random(KeyType::ED25519).public_key();
+        let old_keys = (0..2)
+            .map(|_| SecretKey::from_random(KeyType::ED25519).public_key())
+            .collect::<Vec<_>>();
+
+        let recover = new_recover_account_delegate_action(
+            &account_id,
+            &recovery_public_key,
+            &old_keys,
+            &new_public_key,
+            1,
+            100,
+        )
+        .unwrap();
+        assert!(
+            check_recover_account_delegate_action(&recover, &new_public_key, &old_keys).is_ok()
+        );
+
+        // Keys the user did not sign off on can not be deleted, and all of those they did must be.
+        let other_keys = vec![old_keys[0].clone()];
+        assert!(
+            check_recover_account_delegate_action(&recover, &new_public_key, &other_keys).is_err()
+        );
+        let other_keys = vec![
+            old_keys[0].clone(),
+            old_keys[1].clone(),
+            SecretKey::from_random(KeyType::ED25519).public_key(),
+        ];
+        assert!(
+            check_recover_account_delegate_action(&recover, &new_public_key, &other_keys).is_err()
+        );
+
+        let mut delete_twice = recover;
+        delete_twice.actions.push(
+            NonDelegateAction::try_from(Action::DeleteKey(DeleteKeyAction {
+                public_key: old_keys[0].clone(),
+            }))
+            .unwrap(),
+        );
+        assert!(
+            check_recover_account_delegate_action(&delete_twice, &new_public_key, &old_keys)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_check_delete_identity_delegate_action() {
+        let account_id: AccountId = "alice.near".parse().unwrap();
+        let recovery_public_key = SecretKey::from_random(KeyType::ED25519).public_key();
+        let other_public_key = SecretKey::from_random(KeyType::ED25519).public_key();
+
+        let delete_identity =
+            new_delete_identity_delegate_action(&account_id, &recovery_public_key, 1, 100).unwrap();
+        assert!(check_delete_identity_delegate_action(&delete_identity).is_ok());
+
+        let delete_other_key = delegate_action(
+            &recovery_public_key,
+            Action::DeleteKey(DeleteKeyAction {
+                public_key: other_public_key,
+            }),
+        );
+        assert!(check_delete_identity_delegate_action(&delete_other_key).is_err());
+
+        let mut other_receiver = delete_identity.clone();
+        other_receiver.receiver_id = "bob.near".parse().unwrap();
+        assert!(check_delete_identity_delegate_action(&other_receiver).is_err());
+
+        let mut more_actions = delete_identity;
+        more_actions.actions.push(
+            NonDelegateAction::try_from(Action::DeleteAccount(DeleteAccountAction {
+                beneficiary_id: "bob.near".parse().unwrap(),
+            }))
+            .unwrap(),
+        );
+        assert!(check_delete_identity_delegate_action(&more_actions).is_err());
+    }
+}
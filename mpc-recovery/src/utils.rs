@@ -2,7 +2,8 @@ use anyhow::Context;
 use borsh::BorshSerialize;
 use ed25519_dalek::Signature;
 use near_crypto::PublicKey;
-use near_primitives::delegate_action::DelegateAction;
+use near_primitives::delegate_action::{DelegateAction, NonDelegateAction};
+use near_primitives::types::AccountId;
 use sha2::{Digest, Sha256};
 
 use crate::error::SignNodeError;
@@ -50,6 +51,56 @@ pub fn sign_request_digest(
     Ok(hasher.finalize().to_vec())
 }
 
+pub fn recover_account_request_digest(
+    near_account_id: &AccountId,
+    new_public_key: &PublicKey,
+    keys_to_delete: &[PublicKey],
+    oidc_token: &OidcToken,
+    frp_public_key: &PublicKey,
+) -> Result<Vec<u8>, SignNodeError> {
+    let mut hasher = Sha256::default();
+    BorshSerialize::serialize(&HashSalt::RecoverAccountRequest.get_salt(), &mut hasher)
+        .context("Serialization failed")?;
+    BorshSerialize::serialize(near_account_id, &mut hasher).context("Serialization failed")?;
+    BorshSerialize::serialize(new_public_key, &mut hasher).context("Serialization failed")?;
+    BorshSerialize::serialize(keys_to_delete, &mut hasher).context("Serialization failed")?;
+    BorshSerialize::serialize(oidc_token, &mut hasher).context("Serialization failed")?;
+    BorshSerialize::serialize(frp_public_key, &mut hasher).context("Serialization failed")?;
+    Ok(hasher.finalize().to_vec())
+}
+
+pub fn delegate_action_request_digest(
+    near_account_id: &AccountId,
+    receiver_id: &AccountId,
+    actions: &[NonDelegateAction],
+    oidc_token: &OidcToken,
+    frp_public_key: &PublicKey,
+) -> Result<Vec<u8>, SignNodeError> {
+    let mut hasher = Sha256::default();
+    BorshSerialize::serialize(&HashSalt::DelegateActionRequest.get_salt(), &mut hasher)
+        .context("Serialization failed")?;
+    BorshSerialize::serialize(near_account_id, &mut hasher).context("Serialization failed")?;
+    BorshSerialize::serialize(receiver_id, &mut hasher).context("Serialization failed")?;
+    BorshSerialize::serialize(actions, &mut hasher).context("Serialization failed")?;
+    BorshSerialize::serialize(oidc_token, &mut hasher).context("Serialization failed")?;
+    BorshSerialize::serialize(frp_public_key, &mut hasher).context("Serialization failed")?;
+    Ok(hasher.finalize().to_vec())
+}
+
+pub fn delete_identity_request_digest(
+    near_account_id: &AccountId,
+    oidc_token: &OidcToken,
+    frp_public_key: &PublicKey,
+) -> Result<Vec<u8>, SignNodeError> {
+    let mut hasher = Sha256::default();
+    BorshSerialize::serialize(&HashSalt::DeleteIdentityRequest.get_salt(), &mut hasher)
+        .context("Serialization failed")?;
+    BorshSerialize::serialize(near_account_id, &mut hasher).context("Serialization failed")?;
+    BorshSerialize::serialize(oidc_token, &mut hasher).context("Serialization failed")?;
+    BorshSerialize::serialize(frp_public_key, &mut hasher).context("Serialization failed")?;
+    Ok(hasher.finalize().to_vec())
+}
+
 pub fn user_credentials_request_digest(
     oidc_token: &OidcToken,
     frp_public_key: &PublicKey,